@@ -0,0 +1,56 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2024 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Outgoing serialization benchmarks, modeled on high rate linear streaming from a client.
+
+use buttplug::core::message::{
+  serializer::{vec_to_protocol_json, PooledText},
+  ButtplugClientMessageV3,
+  ButtplugMessage,
+  LinearCmdV1,
+  VectorSubcommandV1,
+};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+fn linear_stream_message(id: u32) -> Vec<ButtplugClientMessageV3> {
+  let mut msg = LinearCmdV1::new(
+    0,
+    vec![
+      VectorSubcommandV1::new(0, 10, (id % 100) as f64 / 100.0),
+      VectorSubcommandV1::new(1, 10, (id % 50) as f64 / 50.0),
+    ],
+  );
+  msg.set_id(id);
+  vec![msg.into()]
+}
+
+fn serialize_linear_stream(c: &mut Criterion) {
+  let mut group = c.benchmark_group("linear_stream");
+  let msgs = linear_stream_message(1);
+
+  // What every frame cost before pooling: a fresh string per message, freed after send.
+  group.bench_function("unpooled", |b| {
+    b.iter(|| {
+      let text = serde_json::to_string(black_box(&msgs)).expect("Infallible serialization");
+      drop(black_box(text));
+    })
+  });
+
+  // Pooled path, with the frame buffer being handed back the same way the websocket transports
+  // do once a write finishes.
+  group.bench_function("pooled", |b| {
+    b.iter(|| {
+      let text = vec_to_protocol_json(black_box(&msgs));
+      drop(black_box(PooledText::new(text)));
+    })
+  });
+
+  group.finish();
+}
+
+criterion_group!(benches, serialize_linear_stream);
+criterion_main!(benches);
@@ -8,11 +8,12 @@
 //! Implementation of internal Buttplug Client event loop.
 
 use super::{
-  client_message_sorter::ClientMessageSorter,
-  device::{ButtplugClientDevice, ButtplugClientDeviceEvent},
+  client_message_sorter::{message_device_index, ClientMessageSorter},
+  device::{ButtplugClientDevice, ButtplugClientDeviceEvent, ClientDeviceIdentity},
   ButtplugClientEvent,
   ButtplugClientMessageFuturePair,
   ButtplugClientMessageSender,
+  ButtplugClientResultStateShared,
 };
 use crate::core::{
   connector::{ButtplugConnector, ButtplugConnectorStateShared},
@@ -28,9 +29,12 @@ use crate::core::{
 };
 use dashmap::DashMap;
 use futures::FutureExt;
-use std::sync::{
-  atomic::{AtomicBool, Ordering},
-  Arc,
+use std::{
+  collections::HashSet,
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+  },
 };
 use tokio::sync::{broadcast, mpsc};
 
@@ -47,6 +51,9 @@ pub enum ButtplugClientRequest {
   /// Bundled future should have reply set and waker called when this is
   /// finished.
   Message(ButtplugClientMessageFuturePair),
+  /// Client request to be notified once all pipelined messages sent to a device so far have been
+  /// acknowledged by the server.
+  WaitForPipelinedAcks(u32, ButtplugClientResultStateShared),
 }
 
 /// Event loop for running [ButtplugClient] connections.
@@ -89,6 +96,9 @@ where
   from_connector_receiver: mpsc::Receiver<ButtplugServerMessageV3>,
   /// Map of devices shared between the client and the event loop
   device_map: Arc<DashMap<u32, Arc<ButtplugClientDevice>>>,
+  /// Every device handle the client has handed out, connected or not, so devices that come back
+  /// get their old handle. Outlives the event loop, to carry handles over client reconnects.
+  known_devices: Arc<DashMap<ClientDeviceIdentity, Arc<ButtplugClientDevice>>>,
   /// Sends events to the [ButtplugClient] instance.
   to_client_sender: broadcast::Sender<ButtplugClientEvent>,
   /// Sends events to the client receiver. Stored here so it can be handed to
@@ -97,6 +107,9 @@ where
   /// Receives incoming messages from client instances.
   from_client_receiver: broadcast::Receiver<ButtplugClientRequest>,
   sorter: ClientMessageSorter,
+  /// Indexes of devices the server has removed. Messages still addressed to them are failed here
+  /// instead of being sent on to the server.
+  removed_devices: HashSet<u32>,
 }
 
 impl<ConnectorType> ButtplugClientEventLoop<ConnectorType>
@@ -115,17 +128,20 @@ where
     to_client_sender: broadcast::Sender<ButtplugClientEvent>,
     from_client_sender: Arc<ButtplugClientMessageSender>,
     device_map: Arc<DashMap<u32, Arc<ButtplugClientDevice>>>,
+    known_devices: Arc<DashMap<ClientDeviceIdentity, Arc<ButtplugClientDevice>>>,
   ) -> Self {
     trace!("Creating ButtplugClientEventLoop instance.");
     Self {
       connected_status,
       device_map,
+      known_devices,
       from_client_receiver: from_client_sender.subscribe(),
       from_client_sender,
       to_client_sender,
       from_connector_receiver,
       connector,
       sorter: ClientMessageSorter::default(),
+      removed_devices: HashSet::new(),
     }
   }
 
@@ -133,7 +149,8 @@ where
   ///
   /// Given a [DeviceMessageInfo] from a [DeviceAdded] or [DeviceList] message,
   /// creates a ButtplugClientDevice and adds it the internal device map, then
-  /// returns the instance.
+  /// returns the instance. If the client has a removed handle for the same
+  /// device, that handle is reconnected and returned instead.
   fn create_client_device(&mut self, info: &DeviceMessageInfoV3) -> Arc<ButtplugClientDevice> {
     debug!(
       "Trying to create a client device from DeviceMessageInfo: {:?}",
@@ -147,11 +164,25 @@ where
       }
       // If it doesn't, insert it.
       None => {
-        debug!("Device does not exist, creating new entry.");
-        let device = Arc::new(ButtplugClientDevice::new_from_device_info(
-          info,
-          &self.from_client_sender,
-        ));
+        self.removed_devices.remove(&info.device_index());
+        let identity = ClientDeviceIdentity::new(info);
+        let known_device = self
+          .known_devices
+          .get(&identity)
+          .map(|device| device.value().clone())
+          .filter(|device| device.rebind(info));
+        let device = if let Some(device) = known_device {
+          debug!("Device was seen before, reusing its handle.");
+          device
+        } else {
+          debug!("Device does not exist, creating new entry.");
+          let device = Arc::new(ButtplugClientDevice::new_from_device_info(
+            info,
+            &self.from_client_sender,
+          ));
+          self.known_devices.insert(identity, device.clone());
+          device
+        };
         self.device_map.insert(info.device_index(), device.clone());
         device
       }
@@ -186,6 +217,10 @@ where
       .expect("Checked for device index already."))
     .clone();
     device.set_device_connected(false);
+    // Anything still waiting on the device won't hear back, so fail it now instead of leaving it to
+    // whatever the server does with it.
+    self.removed_devices.insert(device_index);
+    self.sorter.fail_device_futures(device_index);
     device.queue_event(ButtplugClientDeviceEvent::DeviceRemoved);
     // Then remove it from our storage map
     self.device_map.remove(&device_index);
@@ -264,6 +299,12 @@ where
       ButtplugServerMessageV3::Error(e) => {
         self.send_client_event(ButtplugClientEvent::Error(e.into()));
       }
+      ButtplugServerMessageV3::PatternSessionProgress(msg) => {
+        self.send_client_event(ButtplugClientEvent::PatternSessionProgress(msg));
+      }
+      ButtplugServerMessageV3::PatternSessionEnded(msg) => {
+        self.send_client_event(ButtplugClientEvent::PatternSessionEnded(msg));
+      }
       _ => error!("Cannot process message, dropping: {:?}", msg),
     }
   }
@@ -272,12 +313,29 @@ where
   async fn send_message(&mut self, mut msg_fut: ButtplugClientMessageFuturePair) {
     if let Err(e) = &msg_fut.msg.is_valid() {
       error!("Message not valid: {:?} - Error: {}", msg_fut.msg, e);
-      msg_fut
-        .waker
-        .set_reply(Err(ButtplugError::from(e.clone()).into()));
+      let err = ButtplugError::from(e.clone());
+      if let Some(device_index) = msg_fut.pipelined_device {
+        self.sorter.record_pipeline_error(device_index, &err);
+      }
+      msg_fut.waker.set_reply(Err(err.into()));
       return;
     }
 
+    if let Some(device_index) = message_device_index(&msg_fut.msg) {
+      if self.removed_devices.contains(&device_index) {
+        debug!(
+          "Not sending message to removed device {}: {:?}",
+          device_index, msg_fut.msg
+        );
+        let err = ButtplugError::from(ButtplugDeviceError::DeviceDisconnected(device_index));
+        if msg_fut.pipelined_device.is_some() {
+          self.sorter.record_pipeline_error(device_index, &err);
+        }
+        msg_fut.waker.set_reply(Err(err.into()));
+        return;
+      }
+    }
+
     trace!("Sending message to connector: {:?}", msg_fut.msg);
     self.sorter.register_future(&mut msg_fut);
     if self.connector.send(msg_fut.msg).await.is_err() {
@@ -300,6 +358,10 @@ where
         self.send_message(msg_fut).await;
         true
       }
+      ButtplugClientRequest::WaitForPipelinedAcks(device_index, waiter) => {
+        self.sorter.register_pipeline_waiter(device_index, waiter);
+        true
+      }
       ButtplugClientRequest::Disconnect(state) => {
         trace!("Client requested disconnect");
         state.set_reply(self.connector.disconnect().await);
@@ -356,6 +418,7 @@ where
       .iter()
       .for_each(|k| self.disconnect_device(*k));
     self.connected_status.store(false, Ordering::SeqCst);
+    self.sorter.fail_pipeline_waiters();
     self.send_client_event(ButtplugClientEvent::ServerDisconnect);
 
     debug!("Exiting client event loop.");
@@ -11,16 +11,90 @@ use crate::{
   client::{
     ButtplugClientError,
     ButtplugClientMessageFuturePair,
+    ButtplugClientResultStateShared,
     ButtplugServerMessageStateShared,
   },
-  core::message::{ButtplugMessage, ButtplugMessageValidator, ButtplugServerMessageV3},
+  core::{
+    connector::ButtplugConnectorError,
+    errors::{ButtplugDeviceError, ButtplugError},
+    message::{
+      ButtplugClientMessageV3,
+      ButtplugDeviceMessage,
+      ButtplugMessage,
+      ButtplugMessageValidator,
+      ButtplugServerMessageV3,
+    },
+  },
 };
-use dashmap::DashMap;
-use std::sync::{
-  atomic::{AtomicU32, Ordering},
-  Arc,
+use dashmap::{DashMap, DashSet};
+use std::{
+  collections::HashSet,
+  sync::{
+    atomic::{AtomicU32, Ordering},
+    Arc,
+  },
 };
 
+/// Acknowledgement tracking for commands sent to a single device in pipelined mode.
+#[derive(Default)]
+struct PipelinedAcks {
+  /// `id`s of pipelined messages that are still waiting for a response.
+  outstanding: HashSet<u32>,
+  /// First error received since waiters were last resolved.
+  first_error: Option<ButtplugError>,
+  /// Futures to resolve once there are no outstanding messages left.
+  waiters: Vec<ButtplugClientResultStateShared>,
+}
+
+impl PipelinedAcks {
+  fn record_error(&mut self, err: &ButtplugError) {
+    if self.first_error.is_none() {
+      self.first_error = Some(err.clone());
+    }
+  }
+
+  fn maybe_resolve_waiters(&mut self) {
+    if !self.outstanding.is_empty() || self.waiters.is_empty() {
+      return;
+    }
+    let first_error = self.first_error.take();
+    for waiter in self.waiters.drain(..) {
+      waiter.set_reply(match &first_error {
+        Some(err) => Err(err.clone().into()),
+        None => Ok(()),
+      });
+    }
+  }
+}
+
+/// Index of the device a client message is addressed to, if it's a device message.
+pub(super) fn message_device_index(msg: &ButtplugClientMessageV3) -> Option<u32> {
+  match msg {
+    ButtplugClientMessageV3::VibrateCmd(msg) => Some(msg.device_index()),
+    ButtplugClientMessageV3::LinearCmd(msg) => Some(msg.device_index()),
+    ButtplugClientMessageV3::RotateCmd(msg) => Some(msg.device_index()),
+    ButtplugClientMessageV3::RawWriteCmd(msg) => Some(msg.device_index()),
+    ButtplugClientMessageV3::RawReadCmd(msg) => Some(msg.device_index()),
+    ButtplugClientMessageV3::StopDeviceCmd(msg) => Some(msg.device_index()),
+    ButtplugClientMessageV3::RawSubscribeCmd(msg) => Some(msg.device_index()),
+    ButtplugClientMessageV3::RawUnsubscribeCmd(msg) => Some(msg.device_index()),
+    ButtplugClientMessageV3::ScalarCmd(msg) => Some(msg.device_index()),
+    ButtplugClientMessageV3::SensorReadCmd(msg) => Some(msg.device_index()),
+    ButtplugClientMessageV3::SensorSubscribeCmd(msg) => Some(msg.device_index()),
+    ButtplugClientMessageV3::SensorUnsubscribeCmd(msg) => Some(msg.device_index()),
+    ButtplugClientMessageV3::RequestDeviceInfo(msg) => Some(msg.device_index()),
+    ButtplugClientMessageV3::IdentifyDevice(msg) => Some(msg.device_index()),
+    ButtplugClientMessageV3::RequestServerInfo(_)
+    | ButtplugClientMessageV3::Ping(_)
+    | ButtplugClientMessageV3::StartScanning(_)
+    | ButtplugClientMessageV3::StopScanning(_)
+    | ButtplugClientMessageV3::RequestDeviceList(_)
+    | ButtplugClientMessageV3::StopAllDevices(_)
+    | ButtplugClientMessageV3::StartPatternSession(_)
+    | ButtplugClientMessageV3::StopPatternSession(_) => None,
+  }
+}
+
 /// Message sorting and pairing for remote client connectors.
 ///
 /// In order to create reliable connections to remote systems, we need a way to maintain message
@@ -66,6 +140,19 @@ pub struct ClientMessageSorter {
   /// that unsigned 2^32 will be enough (Buttplug isn't THAT chatty), and use it as a monotonically
   /// increasing counter for setting `id`s.
   current_id: Arc<AtomicU32>,
+
+  /// Map of device indexes to the state of commands pipelined to that device.
+  pipelines: DashMap<u32, PipelinedAcks>,
+
+  /// Map of pipelined message `id`s to the index of the device they were sent to.
+  pipelined_ids: DashMap<u32, u32>,
+
+  /// Map of device message `id`s to the index of the device they were sent to.
+  device_ids: DashMap<u32, u32>,
+
+  /// `id`s of messages that were already failed because their device was removed. Responses that
+  /// show up for them afterward are dropped instead of being treated as events.
+  drained_ids: DashSet<u32>,
 }
 
 impl ClientMessageSorter {
@@ -78,9 +165,91 @@ impl ClientMessageSorter {
     trace!("Setting message id to {}", id);
     msg_fut.msg.set_id(id);
     self.future_map.insert(id, msg_fut.waker.clone());
+    if let Some(device_index) = message_device_index(&msg_fut.msg) {
+      self.device_ids.insert(id, device_index);
+    }
+    if let Some(device_index) = msg_fut.pipelined_device {
+      self.pipelined_ids.insert(id, device_index);
+      self
+        .pipelines
+        .entry(device_index)
+        .or_default()
+        .outstanding
+        .insert(id);
+    }
     self.current_id.store(id + 1, Ordering::Relaxed);
   }
 
+  /// Registers a future to be resolved once all pipelined messages currently outstanding for a
+  /// device have received a response.
+  pub fn register_pipeline_waiter(
+    &self,
+    device_index: u32,
+    waiter: ButtplugClientResultStateShared,
+  ) {
+    let mut pipeline = self.pipelines.entry(device_index).or_default();
+    pipeline.waiters.push(waiter);
+    pipeline.maybe_resolve_waiters();
+  }
+
+  /// Records an error for a pipelined message that failed before it could be sent, so it will be
+  /// reported to pipeline waiters.
+  pub fn record_pipeline_error(&self, device_index: u32, err: &ButtplugError) {
+    self
+      .pipelines
+      .entry(device_index)
+      .or_default()
+      .record_error(err);
+  }
+
+  /// Fails all pipeline waiters, for use when the connection goes away and no more responses will
+  /// arrive.
+  pub fn fail_pipeline_waiters(&self) {
+    for mut pipeline in self.pipelines.iter_mut() {
+      for waiter in pipeline.waiters.drain(..) {
+        waiter.set_reply(Err(ButtplugConnectorError::ConnectorNotConnected.into()));
+      }
+    }
+  }
+
+  /// Fails every message still waiting on a response from a device that has been removed, with a
+  /// [ButtplugDeviceError::DeviceDisconnected] error.
+  pub fn fail_device_futures(&self, device_index: u32) {
+    let ids: Vec<u32> = self
+      .device_ids
+      .iter()
+      .filter(|entry| *entry.value() == device_index)
+      .map(|entry| *entry.key())
+      .collect();
+    let err = ButtplugError::from(ButtplugDeviceError::DeviceDisconnected(device_index));
+    for id in ids {
+      self.device_ids.remove(&id);
+      let Some((_, state)) = self.future_map.remove(&id) else {
+        continue;
+      };
+      debug!(
+        "Failing message id {} after device {} was removed.",
+        id, device_index
+      );
+      self.drained_ids.insert(id);
+      self.resolve_pipelined_id(id, Some(&err));
+      state.set_reply(Err(err.clone().into()));
+    }
+  }
+
+  fn resolve_pipelined_id(&self, id: u32, error: Option<&ButtplugError>) {
+    let Some((_, device_index)) = self.pipelined_ids.remove(&id) else {
+      return;
+    };
+    if let Some(mut pipeline) = self.pipelines.get_mut(&device_index) {
+      pipeline.outstanding.remove(&id);
+      if let Some(err) = error {
+        pipeline.record_error(err);
+      }
+      pipeline.maybe_resolve_waiters();
+    }
+  }
+
   /// Given a response message from the server, resolve related future if we have one.
   ///
   /// Returns true if the response message was resolved to a future via matching `id`, otherwise
@@ -89,15 +258,28 @@ impl ClientMessageSorter {
     trace!("{:?}", msg);
     let id = msg.id();
     trace!("Trying to resolve message future for id {}.", id);
+    if self.drained_ids.remove(&id).is_some() {
+      trace!(
+        "Dropping late response for id {}, device already removed.",
+        id
+      );
+      return true;
+    }
     match self.future_map.remove(&id) {
       Some((_, state)) => {
+        self.device_ids.remove(&id);
         trace!("Resolved id {} to a future.", id);
         if let Err(e) = msg.is_valid() {
           error!("Message not valid: {:?} - Error: {}", msg, e);
-          state.set_reply(Err(ButtplugClientError::ButtplugError(e.into())));
+          let err = ButtplugError::from(e);
+          self.resolve_pipelined_id(id, Some(&err));
+          state.set_reply(Err(ButtplugClientError::ButtplugError(err)));
         } else if let ButtplugServerMessageV3::Error(e) = msg {
-          state.set_reply(Err(e.original_error().into()))
+          let err = e.original_error();
+          self.resolve_pipelined_id(id, Some(&err));
+          state.set_reply(Err(err.into()))
         } else {
+          self.resolve_pipelined_id(id, None);
           state.set_reply(Ok(msg.clone()))
         }
         true
@@ -119,6 +301,10 @@ impl Default for ClientMessageSorter {
     Self {
       future_map: DashMap::new(),
       current_id: Arc::new(AtomicU32::new(1)),
+      pipelines: DashMap::new(),
+      pipelined_ids: DashMap::new(),
+      device_ids: DashMap::new(),
+      drained_ids: DashSet::new(),
     }
   }
 }
@@ -9,7 +9,9 @@
 
 use super::{
   create_boxed_future_client_error,
+  linear_oscillation::LinearOscillation,
   ButtplugClientMessageSender,
+  ButtplugClientResult,
   ButtplugClientResultFuture,
 };
 use crate::{
@@ -22,13 +24,17 @@ use crate::{
       ButtplugServerMessageV3,
       ClientDeviceMessageAttributesV3,
       ClientGenericDeviceMessageAttributesV3,
+      DeviceInfoV4,
       DeviceMessageInfoV3,
+      DeviceTransport,
       Endpoint,
+      IdentifyDeviceV4,
       LinearCmdV1,
       RawReadCmdV2,
       RawSubscribeCmdV2,
       RawUnsubscribeCmdV2,
       RawWriteCmdV2,
+      RequestDeviceInfoV4,
       RotateCmdV1,
       RotationSubcommandV1,
       ScalarCmdV3,
@@ -48,10 +54,13 @@ use getset::{CopyGetters, Getters};
 use std::{
   collections::HashMap,
   fmt,
+  ops::RangeInclusive,
   sync::{
-    atomic::{AtomicBool, Ordering},
+    atomic::{AtomicBool, AtomicU32, Ordering},
     Arc,
+    RwLock,
   },
+  time::Duration,
 };
 use tokio::sync::broadcast;
 
@@ -66,6 +75,10 @@ pub enum ButtplugClientDeviceEvent {
   DeviceRemoved,
   /// Client has disconnected from server.
   ClientDisconnect,
+  /// Device has come back after being removed, either because the device reconnected to the server
+  /// or because the client reconnected. The handle can be used again, and may have a new
+  /// [index](ButtplugClientDevice::index).
+  DeviceReconnected,
   /// Message was received from server for that specific device.
   Message(ButtplugServerMessageV3),
 }
@@ -140,6 +153,24 @@ pub enum LinearCommand {
   LinearMap(HashMap<u32, (u32, f64)>),
 }
 
+/// What a [ButtplugClientDevice] is matched on when deciding whether a device the server reports
+/// is one the client has seen before. Servers keep a device's index for as long as they remember
+/// it, so together with the name it picks out the same hardware.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub(super) struct ClientDeviceIdentity {
+  name: String,
+  index: u32,
+}
+
+impl ClientDeviceIdentity {
+  pub(super) fn new(info: &DeviceMessageInfoV3) -> Self {
+    Self {
+      name: info.device_name().clone(),
+      index: info.device_index(),
+    }
+  }
+}
+
 #[derive(Getters, CopyGetters)]
 /// Client-usable representation of device connected to the corresponding
 /// [ButtplugServer][crate::server::ButtplugServer]
@@ -147,6 +178,13 @@ pub enum LinearCommand {
 /// [ButtplugClientDevice] instances are obtained from the
 /// [ButtplugClient][super::ButtplugClient], and allow the user to send commands
 /// to a device connected to the server.
+///
+/// Handles outlive their connection. When a device is removed, its handle is kept around, and if
+/// the same device shows up again (after the device reconnects to the server, or the client
+/// reconnects to a server that still knows the device) the same handle is handed out again with
+/// [DeviceReconnected](ButtplugClientDeviceEvent::DeviceReconnected) sent to its event stream.
+/// Applications can hold on to handles and check [is_connected](Self::is_connected) instead of
+/// swapping in new ones.
 pub struct ButtplugClientDevice {
   /// Name of the device
   #[getset(get = "pub")]
@@ -157,12 +195,14 @@ pub struct ButtplugClientDevice {
   /// Index of the device, matching the index in the
   /// [ButtplugServer][crate::server::ButtplugServer]'s
   /// [DeviceManager][crate::server::device_manager::DeviceManager].
-  #[getset(get_copy = "pub")]
-  index: u32,
+  index: AtomicU32,
   /// Map of messages the device can take, along with the attributes of those
   /// messages.
   #[getset(get = "pub")]
   message_attributes: ClientDeviceMessageAttributesV3,
+  /// Protocol, transport, address and firmware version, once fetched with
+  /// [request_device_info](Self::request_device_info).
+  device_info: Arc<RwLock<Option<DeviceInfoV4>>>,
   /// Sends commands from the [ButtplugClientDevice] instance to the
   /// [ButtplugClient][super::ButtplugClient]'s event loop, which will then send
   /// the message on to the [ButtplugServer][crate::server::ButtplugServer]
@@ -176,6 +216,9 @@ pub struct ButtplugClientDevice {
   /// [ButtplugClientDevice] instance is still connected to the
   /// [ButtplugServer][crate::server::ButtplugServer].
   client_connected: Arc<AtomicBool>,
+  /// True if actuator commands should be sent in pipelined mode. See
+  /// [ButtplugClientDevice::set_pipelined].
+  pipelined: AtomicBool,
 }
 
 impl ButtplugClientDevice {
@@ -210,12 +253,14 @@ impl ButtplugClientDevice {
     Self {
       name: name.to_owned(),
       display_name: display_name.clone(),
-      index,
+      index: AtomicU32::new(index),
       message_attributes: message_attributes.clone(),
+      device_info: Arc::new(RwLock::new(None)),
       event_loop_sender: message_sender.clone(),
       internal_event_sender: event_sender,
       device_connected,
       client_connected,
+      pipelined: AtomicBool::new(false),
     }
   }
 
@@ -232,10 +277,147 @@ impl ButtplugClientDevice {
     )
   }
 
+  /// Point a removed handle at the device it identifies, now that the server has reported it again.
+  /// Returns false, leaving the handle alone, if the handle is still connected or the device no
+  /// longer takes the same messages, in which case the device should get a new handle.
+  pub(super) fn rebind(&self, info: &DeviceMessageInfoV3) -> bool {
+    if self.is_connected() || *info.device_messages() != self.message_attributes {
+      return false;
+    }
+    info!(
+      "Rebinding client device {} to index {}.",
+      self.name,
+      info.device_index()
+    );
+    self.index.store(info.device_index(), Ordering::SeqCst);
+    self.set_client_connected(true);
+    self.set_device_connected(true);
+    self.queue_event(ButtplugClientDeviceEvent::DeviceReconnected);
+    true
+  }
+
+  /// Index of the device, matching the index in the
+  /// [ButtplugServer][crate::server::ButtplugServer]'s device manager. This can change if the
+  /// device is removed and comes back.
+  pub fn index(&self) -> u32 {
+    self.index.load(Ordering::SeqCst)
+  }
+
+  /// Name of the protocol the server uses to talk to the device. None until
+  /// [request_device_info](Self::request_device_info) has succeeded.
+  pub fn protocol(&self) -> Option<String> {
+    self.read_device_info(|info| info.device_protocol().clone())
+  }
+
+  /// How the server is connected to the device. None until
+  /// [request_device_info](Self::request_device_info) has succeeded.
+  pub fn transport(&self) -> Option<DeviceTransport> {
+    self.read_device_info(|info| info.device_transport())
+  }
+
+  /// Hardware address of the device (BLE address, serial port, etc...). None until
+  /// [request_device_info](Self::request_device_info) has succeeded.
+  pub fn address(&self) -> Option<String> {
+    self.read_device_info(|info| info.device_address().clone())
+  }
+
+  /// Firmware version the device reported when it connected. None until
+  /// [request_device_info](Self::request_device_info) has succeeded, or if the device doesn't
+  /// report one.
+  pub fn firmware_version(&self) -> Option<String> {
+    self.read_device_info(|info| info.device_firmware_version().clone())
+  }
+
+  fn read_device_info<T>(&self, field: impl FnOnce(&DeviceInfoV4) -> Option<T>) -> Option<T> {
+    self
+      .device_info
+      .read()
+      .expect("Device info lock should never be poisoned.")
+      .as_ref()
+      .and_then(field)
+  }
+
+  /// Ask the server how it is connected to the device, filling in [protocol](Self::protocol),
+  /// [transport](Self::transport), [address](Self::address) and
+  /// [firmware_version](Self::firmware_version).
+  ///
+  /// Device enumeration messages in spec v3 don't carry this, so it takes a separate request that
+  /// the client doesn't make on its own. Servers that predate the request don't recognize it, and
+  /// may never answer.
+  pub fn request_device_info(&self) -> ButtplugClientResultFuture {
+    let msg = ButtplugClientMessageV3::RequestDeviceInfo(RequestDeviceInfoV4::new(self.index()));
+    let send_fut = self.event_loop_sender.send_message(msg);
+    let device_info = self.device_info.clone();
+    async move {
+      match send_fut.await? {
+        ButtplugServerMessageV3::DeviceInfo(info) => {
+          *device_info
+            .write()
+            .expect("Device info lock should never be poisoned.") = Some(info);
+          Ok(())
+        }
+        ButtplugServerMessageV3::Error(err) => Err(ButtplugError::from(err).into()),
+        msg => Err(
+          ButtplugError::from(ButtplugMessageError::UnexpectedMessageType(format!(
+            "{:?}",
+            msg
+          )))
+          .into(),
+        ),
+      }
+    }
+    .boxed()
+  }
+
+  /// True if the device is connected to the server, and the client is connected to the server.
+  /// Commands sent while this is false fail.
+  pub fn is_connected(&self) -> bool {
+    self.device_connected.load(Ordering::SeqCst) && self.client_connected.load(Ordering::SeqCst)
+  }
+
   pub fn connected(&self) -> bool {
     self.device_connected.load(Ordering::SeqCst)
   }
 
+  /// Returns true if actuator commands are being sent in pipelined mode.
+  pub fn pipelined(&self) -> bool {
+    self.pipelined.load(Ordering::Relaxed)
+  }
+
+  /// Sets whether actuator commands ([vibrate][Self::vibrate], [scalar][Self::scalar],
+  /// [linear][Self::linear], [rotate][Self::rotate], [stop][Self::stop], etc...) are sent in
+  /// pipelined mode.
+  ///
+  /// By default, a command is only sent once the future returned by its method is polled, and
+  /// callers usually wait for the server's response before sending the next one, costing a round
+  /// trip per update. In pipelined mode, commands are sent as soon as the method is called, so
+  /// callers can fire off updates without waiting, while still having the server receive them in
+  /// the order they were issued. The returned futures still resolve on acknowledgement, but can be
+  /// dropped, with [acknowledged][Self::acknowledged] used to check up on everything sent so far.
+  pub fn set_pipelined(&self, pipelined: bool) {
+    self.pipelined.store(pipelined, Ordering::Relaxed);
+  }
+
+  /// Returns a future that resolves once all pipelined commands sent to this device before this
+  /// call have been acknowledged by the server.
+  ///
+  /// If any of those commands failed, the future resolves to the first error received since the
+  /// last time this future resolved.
+  pub fn acknowledged(&self) -> ButtplugClientResultFuture {
+    self.event_loop_sender.wait_for_pipelined_acks(self.index())
+  }
+
+  /// Sends an actuator command, taking pipelined mode into account.
+  fn send_command(&self, msg: ButtplugClientMessageV3) -> ButtplugClientResultFuture {
+    if self.pipelined() {
+      self
+        .event_loop_sender
+        .send_message_pipelined(msg, self.index())
+    } else {
+      self.event_loop_sender.send_message_expect_ok(msg)
+    }
+  }
+
   pub fn event_stream(&self) -> Box<dyn Stream<Item = ButtplugClientDeviceEvent> + Send + Unpin> {
     Box::new(Box::pin(convert_broadcast_receiver_to_stream(
       self.internal_event_sender.subscribe(),
@@ -334,8 +516,8 @@ impl ButtplugClientDevice {
         }
       }
     }
-    let msg = ScalarCmdV3::new(self.index, scalar_vec).into();
-    self.event_loop_sender.send_message_expect_ok(msg)
+    let msg = ScalarCmdV3::new(self.index(), scalar_vec).into();
+    self.send_command(msg)
   }
 
   pub fn vibrate_attributes(&self) -> Vec<ClientGenericDeviceMessageAttributesV3> {
@@ -414,8 +596,8 @@ impl ButtplugClientDevice {
         }
       }
     }
-    let msg = ScalarCmdV3::new(self.index, scalar_vec).into();
-    self.event_loop_sender.send_message_expect_ok(msg)
+    let msg = ScalarCmdV3::new(self.index(), scalar_vec).into();
+    self.send_command(msg)
   }
 
   pub fn linear_attributes(&self) -> Vec<ClientGenericDeviceMessageAttributesV3> {
@@ -472,8 +654,59 @@ impl ButtplugClientDevice {
         }
       }
     }
-    let msg = LinearCmdV1::new(self.index, linear_vec).into();
-    self.event_loop_sender.send_message_expect_ok(msg)
+    let msg = LinearCmdV1::new(self.index(), linear_vec).into();
+    self.send_command(msg)
+  }
+
+  /// Strokes all linear features of the device back and forth across `range` (positions, 0.0-1.0),
+  /// at `speed` full strokes (there and back) per second, until the returned handle is stopped or
+  /// dropped.
+  ///
+  /// Strokes are timed against a fixed clock rather than by sleeping between commands, so latency
+  /// in getting commands to the device doesn't slow the rhythm down. See
+  /// [linear_oscillation](super::linear_oscillation) for details.
+  #[allow(clippy::result_large_err)]
+  pub fn oscillate_linear(
+    &self,
+    range: RangeInclusive<f64>,
+    speed: f64,
+  ) -> ButtplugClientResult<LinearOscillation> {
+    let Some(linear_attrs) = self.message_attributes.linear_cmd() else {
+      return Err(
+        ButtplugError::from(ButtplugDeviceError::MessageNotSupported(
+          ButtplugDeviceMessageType::LinearCmd,
+        ))
+        .into(),
+      );
+    };
+    if !(0.0..=1.0).contains(range.start())
+      || !(0.0..=1.0).contains(range.end())
+      || range.start() >= range.end()
+    {
+      return Err(
+        ButtplugError::from(ButtplugMessageError::InvalidMessageContents(format!(
+          "Oscillation range {:?} must be increasing, and within 0.0-1.0.",
+          range
+        )))
+        .into(),
+      );
+    }
+    if !speed.is_finite() || speed <= 0.0 {
+      return Err(
+        ButtplugError::from(ButtplugMessageError::InvalidMessageContents(format!(
+          "Oscillation speed {} must be a positive number of strokes per second.",
+          speed
+        )))
+        .into(),
+      );
+    }
+    Ok(LinearOscillation::start(
+      self.event_loop_sender.clone(),
+      self.index(),
+      linear_attrs.len() as u32,
+      range,
+      Duration::from_secs_f64(0.5 / speed),
+    ))
   }
 
   pub fn rotate_attributes(&self) -> Vec<ClientGenericDeviceMessageAttributesV3> {
@@ -530,8 +763,8 @@ impl ButtplugClientDevice {
         }
       }
     }
-    let msg = RotateCmdV1::new(self.index, rotate_vec).into();
-    self.event_loop_sender.send_message_expect_ok(msg)
+    let msg = RotateCmdV1::new(self.index(), rotate_vec).into();
+    self.send_command(msg)
   }
 
   pub fn subscribe_sensor(
@@ -545,7 +778,7 @@ impl ButtplugClientDevice {
           .into(),
       );
     }
-    let msg = SensorSubscribeCmdV3::new(self.index, sensor_index, sensor_type).into();
+    let msg = SensorSubscribeCmdV3::new(self.index(), sensor_index, sensor_type).into();
     self.event_loop_sender.send_message_expect_ok(msg)
   }
 
@@ -560,7 +793,7 @@ impl ButtplugClientDevice {
           .into(),
       );
     }
-    let msg = SensorUnsubscribeCmdV3::new(self.index, sensor_index, sensor_type).into();
+    let msg = SensorUnsubscribeCmdV3::new(self.index(), sensor_index, sensor_type).into();
     self.event_loop_sender.send_message_expect_ok(msg)
   }
 
@@ -585,7 +818,7 @@ impl ButtplugClientDevice {
         ButtplugDeviceError::ProtocolSensorNotSupported(*sensor_type).into(),
       );
     }
-    let msg = SensorReadCmdV3::new(self.index, sensor_indexes[0], *sensor_type).into();
+    let msg = SensorReadCmdV3::new(self.index(), sensor_indexes[0], *sensor_type).into();
     let reply = self.event_loop_sender.send_message(msg);
     async move {
       if let ButtplugServerMessageV3::SensorReading(data) = reply.await? {
@@ -647,7 +880,7 @@ impl ButtplugClientDevice {
       );
     }
     let msg = ButtplugClientMessageV3::RawWriteCmd(RawWriteCmdV2::new(
-      self.index,
+      self.index(),
       endpoint,
       data,
       write_with_response,
@@ -667,7 +900,7 @@ impl ButtplugClientDevice {
       );
     }
     let msg = ButtplugClientMessageV3::RawReadCmd(RawReadCmdV2::new(
-      self.index,
+      self.index(),
       endpoint,
       expected_length,
       timeout,
@@ -696,7 +929,7 @@ impl ButtplugClientDevice {
       );
     }
     let msg =
-      ButtplugClientMessageV3::RawSubscribeCmd(RawSubscribeCmdV2::new(self.index, endpoint));
+      ButtplugClientMessageV3::RawSubscribeCmd(RawSubscribeCmdV2::new(self.index(), endpoint));
     self.event_loop_sender.send_message_expect_ok(msg)
   }
 
@@ -707,16 +940,24 @@ impl ButtplugClientDevice {
       );
     }
     let msg =
-      ButtplugClientMessageV3::RawUnsubscribeCmd(RawUnsubscribeCmdV2::new(self.index, endpoint));
+      ButtplugClientMessageV3::RawUnsubscribeCmd(RawUnsubscribeCmdV2::new(self.index(), endpoint));
     self.event_loop_sender.send_message_expect_ok(msg)
   }
 
+  /// Briefly actuate the device (usually a short, low vibration) so the user can tell which
+  /// physical device this is. The device is stopped afterward. Fails for devices with no safe way
+  /// to identify themselves, like e-stim units and strokers, and on servers that predate the
+  /// request.
+  pub fn identify(&self) -> ButtplugClientResultFuture {
+    self
+      .event_loop_sender
+      .send_message_expect_ok(IdentifyDeviceV4::new(self.index()).into())
+  }
+
   /// Commands device to stop all movement.
   pub fn stop(&self) -> ButtplugClientResultFuture {
     // All devices accept StopDeviceCmd
-    self
-      .event_loop_sender
-      .send_message_expect_ok(StopDeviceCmdV0::new(self.index).into())
+    self.send_command(StopDeviceCmdV0::new(self.index()).into())
   }
 
   pub(super) fn set_device_connected(&self, connected: bool) {
@@ -746,7 +987,7 @@ impl Eq for ButtplugClientDevice {
 
 impl PartialEq for ButtplugClientDevice {
   fn eq(&self, other: &Self) -> bool {
-    self.index == other.index
+    self.index() == other.index()
   }
 }
 
@@ -754,7 +995,7 @@ impl fmt::Debug for ButtplugClientDevice {
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
     f.debug_struct("ButtplugClientDevice")
       .field("name", &self.name)
-      .field("index", &self.index)
+      .field("index", &self.index())
       .finish()
   }
 }
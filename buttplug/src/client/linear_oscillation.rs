@@ -0,0 +1,142 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2024 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Stroking linear devices back and forth between two positions.
+//!
+//! Just about every stroker app ends up writing the same loop: send a LinearCmd to one end of the
+//! range, sleep for the stroke duration, send one to the other end, repeat. Sleeping after each
+//! command means every bit of latency (message round trips, a busy runtime) gets added to the
+//! stroke, so the rhythm drifts and the device ends up waiting at each end of the range. Here,
+//! strokes are scheduled against a fixed clock instead, and each command is given whatever time is
+//! left until its stroke should end.
+
+use super::ButtplugClientMessageSender;
+use crate::{
+  core::message::{LinearCmdV1, VectorSubcommandV1},
+  util::{async_manager, sleep},
+};
+use instant::Instant;
+use std::{
+  ops::RangeInclusive,
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+  },
+  time::Duration,
+};
+use tokio_util::sync::CancellationToken;
+
+/// Handle to a running linear oscillation, returned by
+/// [ButtplugClientDevice::oscillate_linear](super::ButtplugClientDevice::oscillate_linear).
+///
+/// The oscillation runs until [stop](Self::stop) is called, the handle is dropped, or a command
+/// fails (usually because the device or client disconnected). Stopping leaves the device wherever
+/// its last stroke took it.
+pub struct LinearOscillation {
+  cancel_token: CancellationToken,
+  running: Arc<AtomicBool>,
+}
+
+impl LinearOscillation {
+  pub(super) fn start(
+    sender: Arc<ButtplugClientMessageSender>,
+    device_index: u32,
+    feature_count: u32,
+    range: RangeInclusive<f64>,
+    stroke_duration: Duration,
+  ) -> Self {
+    let cancel_token = CancellationToken::new();
+    let running = Arc::new(AtomicBool::new(true));
+    let task_token = cancel_token.child_token();
+    let task_running = running.clone();
+    async_manager::spawn(async move {
+      run_oscillation(
+        sender,
+        device_index,
+        feature_count,
+        range,
+        stroke_duration,
+        task_token,
+      )
+      .await;
+      task_running.store(false, Ordering::Relaxed);
+    });
+    Self {
+      cancel_token,
+      running,
+    }
+  }
+
+  /// True until the oscillation has been stopped, or has ended on its own after a failed command.
+  pub fn is_running(&self) -> bool {
+    self.running.load(Ordering::Relaxed) && !self.cancel_token.is_cancelled()
+  }
+
+  /// Stop sending strokes.
+  pub fn stop(&self) {
+    self.cancel_token.cancel();
+  }
+}
+
+impl Drop for LinearOscillation {
+  fn drop(&mut self) {
+    self.cancel_token.cancel();
+  }
+}
+
+async fn run_oscillation(
+  sender: Arc<ButtplugClientMessageSender>,
+  device_index: u32,
+  feature_count: u32,
+  range: RangeInclusive<f64>,
+  stroke_duration: Duration,
+  cancel_token: CancellationToken,
+) {
+  let start = Instant::now();
+  let mut stroke = 0u32;
+  loop {
+    let now = Instant::now();
+    let mut stroke_end = start + stroke_duration * (stroke + 1);
+    // If we've fallen a whole stroke or more behind, skip the strokes we missed instead of rushing
+    // through them, so the device picks the rhythm back up where it should be.
+    if stroke_end <= now {
+      stroke = ((now - start).as_secs_f64() / stroke_duration.as_secs_f64()) as u32;
+      stroke_end = start + stroke_duration * (stroke + 1);
+    }
+    let position = if stroke.is_multiple_of(2) {
+      *range.end()
+    } else {
+      *range.start()
+    };
+    // Moving over the time left in the stroke, instead of the full stroke duration, soaks up
+    // whatever latency the last command picked up.
+    let duration = (stroke_end - now).as_millis() as u32;
+    let msg = LinearCmdV1::new(
+      device_index,
+      (0..feature_count)
+        .map(|index| VectorSubcommandV1::new(index, duration, position))
+        .collect(),
+    );
+    tokio::select! {
+      _ = cancel_token.cancelled() => return,
+      result = sender.send_message_expect_ok(msg.into()) => {
+        if let Err(err) = result {
+          info!(
+            "Linear oscillation for device {} stopping after command failure: {:?}",
+            device_index, err
+          );
+          return;
+        }
+      }
+    }
+    tokio::select! {
+      _ = cancel_token.cancelled() => return,
+      _ = sleep(stroke_end.saturating_duration_since(Instant::now())) => {}
+    }
+    stroke += 1;
+  }
+}
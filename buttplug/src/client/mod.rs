@@ -9,19 +9,24 @@
 pub mod client_event_loop;
 pub mod client_message_sorter;
 pub mod device;
+pub mod linear_oscillation;
 
 use crate::{
   core::{
     connector::{ButtplugConnector, ButtplugConnectorError, ButtplugConnectorFuture},
-    errors::{ButtplugError, ButtplugHandshakeError},
+    errors::{ButtplugError, ButtplugHandshakeError, ButtplugMessageError},
     message::{
       ButtplugClientMessageV3,
       ButtplugServerMessageV3,
+      PatternSessionEndedV4,
+      PatternSessionProgressV4,
       PingV0,
       RequestDeviceListV0,
       RequestServerInfoV1,
+      StartPatternSessionV4,
       StartScanningV0,
       StopAllDevicesV0,
+      StopPatternSessionV4,
       StopScanningV0,
       BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION,
     },
@@ -29,11 +34,13 @@ use crate::{
   util::{
     async_manager,
     future::{ButtplugFuture, ButtplugFutureStateShared},
+    sleep,
     stream::convert_broadcast_receiver_to_stream,
   },
 };
 use client_event_loop::{ButtplugClientEventLoop, ButtplugClientRequest};
 use dashmap::DashMap;
+use device::ClientDeviceIdentity;
 pub use device::{
   ButtplugClientDevice,
   ButtplugClientDeviceEvent,
@@ -46,9 +53,13 @@ use futures::{
   future::{self, BoxFuture, FutureExt},
   Stream,
 };
-use std::sync::{
-  atomic::{AtomicBool, Ordering},
-  Arc,
+pub use linear_oscillation::LinearOscillation;
+use std::{
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+  },
+  time::Duration,
 };
 use thiserror::Error;
 use tokio::sync::{broadcast, mpsc, Mutex};
@@ -62,6 +73,10 @@ use tracing_futures::Instrument;
 type ButtplugClientResult<T = ()> = Result<T, ButtplugClientError>;
 type ButtplugClientResultFuture<T = ()> = BoxFuture<'static, ButtplugClientResult<T>>;
 
+/// How long [ButtplugClient::connect] waits for the connection and handshake to finish, unless
+/// changed with [ButtplugClient::with_handshake_timeout].
+pub const DEFAULT_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
 /// Result type used for passing server responses.
 pub type ButtplugServerMessageResult = ButtplugClientResult<ButtplugServerMessageV3>;
 pub type ButtplugServerMessageResultFuture = ButtplugClientResultFuture<ButtplugServerMessageV3>;
@@ -70,6 +85,8 @@ pub(crate) type ButtplugServerMessageStateShared =
   ButtplugFutureStateShared<ButtplugServerMessageResult>;
 /// Future type that expects server responses.
 pub(crate) type ButtplugServerMessageFuture = ButtplugFuture<ButtplugServerMessageResult>;
+/// Future state type for signaling that a set of pipelined commands has been acknowledged.
+pub(crate) type ButtplugClientResultStateShared = ButtplugFutureStateShared<ButtplugClientResult>;
 
 /// Future state for messages sent from the client that expect a server response.
 ///
@@ -87,11 +104,29 @@ pub(crate) type ButtplugServerMessageFuture = ButtplugFuture<ButtplugServerMessa
 pub struct ButtplugClientMessageFuturePair {
   msg: ButtplugClientMessageV3,
   waker: ButtplugServerMessageStateShared,
+  /// Index of the device this message was pipelined to, if it was sent in pipelined mode.
+  pipelined_device: Option<u32>,
 }
 
 impl ButtplugClientMessageFuturePair {
   pub fn new(msg: ButtplugClientMessageV3, waker: ButtplugServerMessageStateShared) -> Self {
-    Self { msg, waker }
+    Self {
+      msg,
+      waker,
+      pipelined_device: None,
+    }
+  }
+
+  pub fn new_pipelined(
+    msg: ButtplugClientMessageV3,
+    waker: ButtplugServerMessageStateShared,
+    device_index: u32,
+  ) -> Self {
+    Self {
+      msg,
+      waker,
+      pipelined_device: Some(device_index),
+    }
   }
 }
 
@@ -138,6 +173,12 @@ pub enum ButtplugClientEvent {
   /// Emitted when an error that cannot be matched to a request is received from
   /// the server.
   Error(ButtplugError),
+  /// Emitted periodically while a pattern session started with
+  /// [ButtplugClient::start_pattern_session] runs.
+  PatternSessionProgress(PatternSessionProgressV4),
+  /// Emitted once when a pattern session started with [ButtplugClient::start_pattern_session]
+  /// ends.
+  PatternSessionEnded(PatternSessionEndedV4),
 }
 
 impl Unpin for ButtplugClientEvent {
@@ -231,6 +272,48 @@ impl ButtplugClientMessageSender {
     let send_fut = self.send_message(msg);
     async move { send_fut.await.map(|_| ()) }.boxed()
   }
+
+  /// Sends a device command in pipelined mode. Expects to receive an [Ok] type ButtplugMessage back
+  /// from the server.
+  ///
+  /// Unlike the other send methods, the message is handed to the event loop immediately instead of
+  /// when the returned future is first polled, so commands go out in the order this is called and
+  /// callers don't need to wait on one command before sending the next. The acknowledgement is
+  /// tracked by the event loop, see [Self::wait_for_pipelined_acks].
+  pub fn send_message_pipelined(
+    &self,
+    msg: ButtplugClientMessageV3,
+    device_index: u32,
+  ) -> ButtplugClientResultFuture {
+    if !self.connected.load(Ordering::Relaxed) {
+      return future::ready(Err(ButtplugConnectorError::ConnectorNotConnected.into())).boxed();
+    }
+    let fut = ButtplugServerMessageFuture::default();
+    let internal_msg = ButtplugClientRequest::Message(
+      ButtplugClientMessageFuturePair::new_pipelined(msg, fut.get_state_clone(), device_index),
+    );
+    if self.message_sender.send(internal_msg).is_err() {
+      return future::ready(Err(ButtplugConnectorError::ConnectorChannelClosed.into())).boxed();
+    }
+    async move { fut.await.map(|_| ()) }.boxed()
+  }
+
+  /// Returns a future that resolves once every pipelined command sent to a device before this call
+  /// has been acknowledged by the server.
+  ///
+  /// If any of those commands failed, the future resolves to the first error received.
+  pub fn wait_for_pipelined_acks(&self, device_index: u32) -> ButtplugClientResultFuture {
+    if !self.connected.load(Ordering::Relaxed) {
+      return future::ready(Err(ButtplugConnectorError::ConnectorNotConnected.into())).boxed();
+    }
+    let fut = ButtplugFuture::<ButtplugClientResult>::default();
+    let internal_msg =
+      ButtplugClientRequest::WaitForPipelinedAcks(device_index, fut.get_state_clone());
+    if self.message_sender.send(internal_msg).is_err() {
+      return future::ready(Err(ButtplugConnectorError::ConnectorChannelClosed.into())).boxed();
+    }
+    fut.boxed()
+  }
 }
 
 /// Struct used by applications to communicate with a Buttplug Server.
@@ -260,6 +343,9 @@ pub struct ButtplugClient {
   message_sender: Arc<ButtplugClientMessageSender>,
   connected: Arc<AtomicBool>,
   device_map: Arc<DashMap<u32, Arc<ButtplugClientDevice>>>,
+  /// Every device handle handed out, kept so devices that come back get the same handle.
+  known_devices: Arc<DashMap<ClientDeviceIdentity, Arc<ButtplugClientDevice>>>,
+  handshake_timeout: Duration,
 }
 
 impl ButtplugClient {
@@ -277,12 +363,28 @@ impl ButtplugClient {
       )),
       connected,
       device_map: Arc::new(DashMap::new()),
+      known_devices: Arc::new(DashMap::new()),
+      handshake_timeout: DEFAULT_HANDSHAKE_TIMEOUT,
     }
   }
 
+  /// Set how long [connect](Self::connect) waits for the connector to come up and the server to
+  /// answer the handshake before giving up with
+  /// [HandshakeTimeout](ButtplugConnectorError::HandshakeTimeout).
+  pub fn with_handshake_timeout(mut self, timeout: Duration) -> Self {
+    self.handshake_timeout = timeout;
+    self
+  }
+
+  /// Connect to a server through `connector`, and run the protocol handshake.
+  ///
+  /// Failures that usually mean the connector is pointed at the wrong place (timeouts, something
+  /// other than a Buttplug server answering, servers that need authentication, or servers older
+  /// than this client) come back as [ButtplugConnectorError]s with
+  /// [remediation hints](ButtplugConnectorError::remediation_hint).
   pub async fn connect<ConnectorType>(
     &self,
-    mut connector: ConnectorType,
+    connector: ConnectorType,
   ) -> Result<(), ButtplugClientError>
   where
     ConnectorType: ButtplugConnector<ButtplugClientMessageV3, ButtplugServerMessageV3> + 'static,
@@ -293,9 +395,37 @@ impl ButtplugClient {
       ));
     }
 
-    // If connect is being called again, clear out the device map and start over.
+    // If connect is being called again, clear out the device map and start over. Handles from the
+    // last connection stay known, and are reused for devices the server still has.
     self.device_map.clear();
 
+    let timeout = self.handshake_timeout;
+    let result = select! {
+      result = self.connect_and_handshake(connector).fuse() => result,
+      _ = sleep(timeout).fuse() => {
+        error!("Connection to server did not finish within {:?}.", timeout);
+        Err(ButtplugConnectorError::HandshakeTimeout(timeout).into())
+      }
+    };
+    if result.is_err() {
+      // The event loop may already be running with the connector, so shut it down rather than
+      // leave a half connected client behind. If it isn't running, there's nothing to tell.
+      let fut = ButtplugConnectorFuture::default();
+      let _ = self
+        .message_sender
+        .send_message_to_event_loop(ButtplugClientRequest::Disconnect(fut.get_state_clone()))
+        .await;
+    }
+    result
+  }
+
+  async fn connect_and_handshake<ConnectorType>(
+    &self,
+    mut connector: ConnectorType,
+  ) -> Result<(), ButtplugClientError>
+  where
+    ConnectorType: ButtplugConnector<ButtplugClientMessageV3, ButtplugServerMessageV3> + 'static,
+  {
     info!("Connecting to server.");
     let (connector_sender, connector_receiver) = mpsc::channel(256);
     connector.connect(connector_sender).await.map_err(|e| {
@@ -310,6 +440,7 @@ impl ButtplugClient {
       self.event_stream.clone(),
       self.message_sender.clone(),
       self.device_map.clone(),
+      self.known_devices.clone(),
     );
 
     // Start the event loop before we run the handshake.
@@ -336,7 +467,13 @@ impl ButtplugClient {
       .send_message_ignore_connect_status(
         RequestServerInfoV1::new(&self.client_name, BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION).into(),
       )
-      .await?;
+      .await
+      .map_err(|err| match err {
+        ButtplugClientError::ButtplugError(ButtplugError::ButtplugHandshakeError(
+          ButtplugHandshakeError::MessageSpecVersionMismatch(server_version, client_version),
+        )) => ButtplugConnectorError::SpecVersionMismatch(server_version, client_version).into(),
+        err => err,
+      })?;
 
     debug!("Got ServerInfo return.");
     if let ButtplugServerMessageV3::ServerInfo(server_info) = msg {
@@ -362,7 +499,6 @@ impl ButtplugClient {
       }
       Ok(())
     } else {
-      self.disconnect().await?;
       Err(ButtplugClientError::ButtplugError(
         ButtplugHandshakeError::UnexpectedHandshakeMessageReceived(format!("{:?}", msg)).into(),
       ))
@@ -427,6 +563,41 @@ impl ButtplugClient {
       .send_message_expect_ok(StopAllDevicesV0::default().into())
   }
 
+  /// Hands the server a pattern to run on its own clock, returning the session id. Progress and
+  /// the end of the session arrive as [ButtplugClientEvent::PatternSessionProgress] and
+  /// [ButtplugClientEvent::PatternSessionEnded].
+  ///
+  /// Tracks address actuators by the index used for them in ScalarCmd. Devices in the session
+  /// reject other actuator commands until it ends.
+  pub fn start_pattern_session(
+    &self,
+    session: StartPatternSessionV4,
+  ) -> ButtplugClientResultFuture<u32> {
+    let send_fut = self.message_sender.send_message(session.into());
+    async move {
+      match send_fut.await? {
+        ButtplugServerMessageV3::PatternSessionStarted(started) => Ok(started.session_id()),
+        ButtplugServerMessageV3::Error(err) => Err(ButtplugError::from(err).into()),
+        msg => Err(
+          ButtplugError::from(ButtplugMessageError::UnexpectedMessageType(format!(
+            "{:?}",
+            msg
+          )))
+          .into(),
+        ),
+      }
+    }
+    .boxed()
+  }
+
+  /// Stops a pattern session started with [ButtplugClient::start_pattern_session], stopping the
+  /// features it was running.
+  pub fn stop_pattern_session(&self, session_id: u32) -> ButtplugClientResultFuture {
+    self
+      .message_sender
+      .send_message_expect_ok(StopPatternSessionV4::new(session_id).into())
+  }
+
   pub fn event_stream(&self) -> impl Stream<Item = ButtplugClientEvent> {
     let stream = convert_broadcast_receiver_to_stream(self.event_stream.subscribe());
     // We can either Box::pin here or force the user to pin_mut!() on their
@@ -0,0 +1,254 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2024 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Frame compression for remote connectors.
+//!
+//! Meant for slow or metered links, like a phone hotspot. The websocket library we use doesn't
+//! support permessage-deflate, so compression happens a layer up: outgoing text frames big enough
+//! to be worth it are deflated and sent as binary frames, and incoming binary frames are inflated
+//! back to text. Small frames (most commands) go out as plain text, since deflate overhead would
+//! make them bigger. Compressed frames start with a short marker, so a side without compression
+//! turned on can tell what it was sent and close the connection with an error that says so,
+//! instead of failing to parse. Both sides of the connection need compression turned on.
+
+use crate::core::message::serializer::{ButtplugSerializedMessage, ButtplugSerializerError};
+use flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression};
+use std::{
+  io::{Read, Write},
+  sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+  },
+};
+
+/// Text frames shorter than this are sent as is.
+const MIN_COMPRESSED_FRAME_SIZE: usize = 256;
+/// Largest frame we'll inflate, so a malicious peer can't make us allocate without bound.
+const MAX_DECOMPRESSED_FRAME_SIZE: u64 = 16 * 1024 * 1024;
+/// Prefix on every compressed frame.
+const COMPRESSED_FRAME_MARKER: &[u8] = b"BPDF";
+
+/// True if `msg` is a frame compressed by a [FrameCompressor].
+pub(super) fn is_compressed_frame(msg: &ButtplugSerializedMessage) -> bool {
+  matches!(msg, ButtplugSerializedMessage::Binary(data) if data.starts_with(COMPRESSED_FRAME_MARKER))
+}
+
+#[derive(Default)]
+struct CompressionCounters {
+  bytes_sent: AtomicU64,
+  wire_bytes_sent: AtomicU64,
+  bytes_received: AtomicU64,
+  wire_bytes_received: AtomicU64,
+}
+
+/// Running totals for a compressed connection. Clones share the same counters, so a copy taken
+/// before handing the connector over to a client or server keeps updating.
+#[derive(Clone, Default)]
+pub struct ButtplugCompressionStatistics {
+  counters: Arc<CompressionCounters>,
+}
+
+impl ButtplugCompressionStatistics {
+  /// Bytes sent, before compression.
+  pub fn bytes_sent(&self) -> u64 {
+    self.counters.bytes_sent.load(Ordering::Relaxed)
+  }
+
+  /// Bytes sent, as they went out over the transport.
+  pub fn wire_bytes_sent(&self) -> u64 {
+    self.counters.wire_bytes_sent.load(Ordering::Relaxed)
+  }
+
+  /// Bytes received, after decompression.
+  pub fn bytes_received(&self) -> u64 {
+    self.counters.bytes_received.load(Ordering::Relaxed)
+  }
+
+  /// Bytes received, as they came in over the transport.
+  pub fn wire_bytes_received(&self) -> u64 {
+    self.counters.wire_bytes_received.load(Ordering::Relaxed)
+  }
+
+  /// Wire size over original size for everything sent, so lower is better. 1.0 until something
+  /// has been sent.
+  pub fn send_ratio(&self) -> f64 {
+    ratio(self.wire_bytes_sent(), self.bytes_sent())
+  }
+
+  /// Wire size over original size for everything received.
+  pub fn receive_ratio(&self) -> f64 {
+    ratio(self.wire_bytes_received(), self.bytes_received())
+  }
+}
+
+impl std::fmt::Debug for ButtplugCompressionStatistics {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("ButtplugCompressionStatistics")
+      .field("bytes_sent", &self.bytes_sent())
+      .field("wire_bytes_sent", &self.wire_bytes_sent())
+      .field("bytes_received", &self.bytes_received())
+      .field("wire_bytes_received", &self.wire_bytes_received())
+      .finish()
+  }
+}
+
+fn ratio(wire: u64, original: u64) -> f64 {
+  if original == 0 {
+    1.0
+  } else {
+    wire as f64 / original as f64
+  }
+}
+
+fn frame_len(msg: &ButtplugSerializedMessage) -> u64 {
+  match msg {
+    ButtplugSerializedMessage::Text(text) => text.len() as u64,
+    ButtplugSerializedMessage::Binary(data) => data.len() as u64,
+  }
+}
+
+pub(super) struct FrameCompressor {
+  statistics: ButtplugCompressionStatistics,
+}
+
+impl FrameCompressor {
+  pub(super) fn new(statistics: ButtplugCompressionStatistics) -> Self {
+    Self { statistics }
+  }
+
+  pub(super) fn compress(&self, msg: ButtplugSerializedMessage) -> ButtplugSerializedMessage {
+    let original_len = frame_len(&msg);
+    let out = match msg {
+      ButtplugSerializedMessage::Text(text) if text.len() >= MIN_COMPRESSED_FRAME_SIZE => {
+        let mut encoder =
+          DeflateEncoder::new(COMPRESSED_FRAME_MARKER.to_vec(), Compression::default());
+        match encoder
+          .write_all(text.as_bytes())
+          .and_then(|_| encoder.finish())
+        {
+          Ok(compressed) if compressed.len() < text.len() => {
+            ButtplugSerializedMessage::Binary(compressed)
+          }
+          Ok(_) => ButtplugSerializedMessage::Text(text),
+          Err(err) => {
+            warn!("Cannot compress frame, sending uncompressed: {:?}", err);
+            ButtplugSerializedMessage::Text(text)
+          }
+        }
+      }
+      msg => msg,
+    };
+    let counters = &self.statistics.counters;
+    counters
+      .bytes_sent
+      .fetch_add(original_len, Ordering::Relaxed);
+    counters
+      .wire_bytes_sent
+      .fetch_add(frame_len(&out), Ordering::Relaxed);
+    out
+  }
+
+  pub(super) fn decompress(
+    &self,
+    msg: ButtplugSerializedMessage,
+  ) -> Result<ButtplugSerializedMessage, ButtplugSerializerError> {
+    let wire_len = frame_len(&msg);
+    let out = match msg {
+      ButtplugSerializedMessage::Binary(data) => {
+        let Some(compressed) = data.strip_prefix(COMPRESSED_FRAME_MARKER) else {
+          return Err(ButtplugSerializerError::CompressionError(
+            "Binary frame is not a compressed frame".to_owned(),
+          ));
+        };
+        let mut text = String::new();
+        // Read one byte past the limit, so a frame that's too big can be told apart from one
+        // that's exactly at it.
+        DeflateDecoder::new(compressed)
+          .take(MAX_DECOMPRESSED_FRAME_SIZE + 1)
+          .read_to_string(&mut text)
+          .map_err(|err| ButtplugSerializerError::CompressionError(err.to_string()))?;
+        if text.len() as u64 > MAX_DECOMPRESSED_FRAME_SIZE {
+          return Err(ButtplugSerializerError::OversizedFrame(
+            MAX_DECOMPRESSED_FRAME_SIZE,
+          ));
+        }
+        ButtplugSerializedMessage::Text(text)
+      }
+      msg => msg,
+    };
+    let counters = &self.statistics.counters;
+    counters
+      .wire_bytes_received
+      .fetch_add(wire_len, Ordering::Relaxed);
+    counters
+      .bytes_received
+      .fetch_add(frame_len(&out), Ordering::Relaxed);
+    Ok(out)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{
+    is_compressed_frame,
+    ButtplugCompressionStatistics,
+    FrameCompressor,
+    COMPRESSED_FRAME_MARKER,
+    MAX_DECOMPRESSED_FRAME_SIZE,
+  };
+  use crate::core::message::serializer::{ButtplugSerializedMessage, ButtplugSerializerError};
+  use flate2::{write::DeflateEncoder, Compression};
+  use std::io::Write;
+
+  #[test]
+  fn test_frame_compression() {
+    let sender_statistics = ButtplugCompressionStatistics::default();
+    let sender = FrameCompressor::new(sender_statistics.clone());
+    let receiver = FrameCompressor::new(ButtplugCompressionStatistics::default());
+
+    // Small frames aren't worth compressing.
+    let ping = ButtplugSerializedMessage::Text(r#"[{"Ping":{"Id":1}}]"#.to_owned());
+    assert_eq!(sender.compress(ping.clone()), ping);
+
+    // Device lists repeat themselves a lot, so compress well.
+    let device_list = ButtplugSerializedMessage::Text(format!(
+      r#"[{{"DeviceList":{{"Id":1,"Devices":[{}]}}}}]"#,
+      vec![r#"{"DeviceName":"Lovense Hush","DeviceIndex":0}"#; 20].join(",")
+    ));
+    let compressed = sender.compress(device_list.clone());
+    assert!(is_compressed_frame(&compressed));
+    assert_eq!(receiver.decompress(compressed).unwrap(), device_list);
+    assert!(sender_statistics.send_ratio() < 0.5);
+
+    // Binary frames that aren't ours, or are but are corrupt, are rejected.
+    assert!(receiver
+      .decompress(ButtplugSerializedMessage::Binary(vec![0xff, 0xff, 0xff]))
+      .is_err());
+    let mut corrupt = COMPRESSED_FRAME_MARKER.to_vec();
+    corrupt.extend([0xff, 0xff, 0xff]);
+    assert!(receiver
+      .decompress(ButtplugSerializedMessage::Binary(corrupt))
+      .is_err());
+  }
+
+  #[test]
+  fn test_oversized_frame() {
+    let receiver = FrameCompressor::new(ButtplugCompressionStatistics::default());
+    // Deflates down to almost nothing, but inflates to just past the limit.
+    let mut encoder = DeflateEncoder::new(COMPRESSED_FRAME_MARKER.to_vec(), Compression::best());
+    encoder
+      .write_all(&vec![b' '; MAX_DECOMPRESSED_FRAME_SIZE as usize + 1])
+      .unwrap();
+    let bomb = ButtplugSerializedMessage::Binary(encoder.finish().unwrap());
+    assert_eq!(
+      receiver.decompress(bomb),
+      Err(ButtplugSerializerError::OversizedFrame(
+        MAX_DECOMPRESSED_FRAME_SIZE
+      ))
+    );
+  }
+}
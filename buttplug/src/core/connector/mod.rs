@@ -63,15 +63,26 @@
 //! There are slightly more useful situations like device forwarders where this work comes in also,
 //! but that Windows 7/Android example is where the idea originally came from.
 
+#[cfg(feature = "websockets")]
+mod compression;
 #[cfg(all(feature = "server", feature = "client", not(feature = "wasm")))]
 mod in_process_connector;
 pub mod remote_connector;
+mod replay_protection;
+#[cfg(feature = "serialize-json")]
+mod trace;
 pub mod transport;
 
 use crate::{
-  core::message::{serializer::ButtplugSerializedMessage, ButtplugMessage},
+  core::message::{
+    serializer::ButtplugSerializedMessage,
+    ButtplugMessage,
+    ButtplugMessageSpecVersion,
+  },
   util::future::{ButtplugFuture, ButtplugFutureStateShared},
 };
+#[cfg(feature = "websockets")]
+pub use compression::ButtplugCompressionStatistics;
 use displaydoc::Display;
 use futures::future::{self, BoxFuture, FutureExt};
 #[cfg(all(feature = "server", feature = "client", not(feature = "wasm")))]
@@ -84,8 +95,20 @@ pub use remote_connector::{
   ButtplugRemoteConnector,
   ButtplugRemoteServerConnector,
 };
+use std::time::Duration;
 use thiserror::Error;
 use tokio::sync::mpsc::Sender;
+#[cfg(feature = "serialize-json")]
+pub use trace::{
+  ButtplugMessageTrace,
+  ButtplugTraceDirection,
+  ButtplugTraceEntry,
+  ButtplugTraceError,
+  ButtplugTraceMessage,
+  ButtplugTraceRecorder,
+  ButtplugTracingConnector,
+  BUTTPLUG_TRACE_FORMAT_VERSION,
+};
 #[cfg(feature = "websockets")]
 pub use transport::ButtplugWebsocketClientTransport;
 
@@ -114,6 +137,40 @@ pub enum ButtplugConnectorError {
   ConnectorGenericError(String),
   /// Specific error for connector type: {0}.
   TransportSpecificError(transport::ButtplugConnectorTransportSpecificError),
+  /// Connection handshake did not finish within {0:?}.
+  HandshakeTimeout(Duration),
+  /// Remote is not a Buttplug server: {0}
+  NotAButtplugServer(String),
+  /// Server requires authentication: {0}
+  AuthenticationRequired(String),
+  /// Server message spec version ({0}) is older than client version ({1}).
+  SpecVersionMismatch(ButtplugMessageSpecVersion, ButtplugMessageSpecVersion),
+}
+
+impl ButtplugConnectorError {
+  /// Suggestion for how to fix the error, for applications to show to users along with it.
+  pub fn remediation_hint(&self) -> Option<&'static str> {
+    match self {
+      Self::HandshakeTimeout(_) => Some(
+        "Check that the address points at a running Buttplug server (Intiface Central listens on \
+         ws://127.0.0.1:12345 by default), and that no firewall or proxy is holding the \
+         connection open.",
+      ),
+      Self::NotAButtplugServer(_) => Some(
+        "Something other than a Buttplug server is listening at that address. Check the port, \
+         and that the server application is running.",
+      ),
+      Self::AuthenticationRequired(_) => Some(
+        "The server only accepts clients it trusts. Connect with a mutual TLS connector, using \
+         an identity the server has been set up to accept.",
+      ),
+      Self::SpecVersionMismatch(..) => Some(
+        "The server is older than this client. Update the server application, or use a client \
+         built against an older version of the library.",
+      ),
+      _ => None,
+    }
+  }
 }
 
 impl<T> From<ButtplugConnectorError> for BoxFuture<'static, Result<T, ButtplugConnectorError>>
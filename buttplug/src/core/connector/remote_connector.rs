@@ -7,7 +7,10 @@
 
 //! Generic remote transport handling methods and traits
 
+#[cfg(feature = "websockets")]
+use super::compression::{is_compressed_frame, ButtplugCompressionStatistics, FrameCompressor};
 use super::{
+  replay_protection::ReplayProtection,
   transport::{ButtplugConnectorTransport, ButtplugTransportIncomingMessage},
   ButtplugConnector,
   ButtplugConnectorError,
@@ -29,7 +32,7 @@ use crate::{
   util::async_manager,
 };
 use futures::{future::BoxFuture, select, FutureExt};
-use std::marker::PhantomData;
+use std::{marker::PhantomData, time::Duration};
 use tokio::sync::mpsc::{channel, Receiver, Sender};
 
 enum ButtplugRemoteConnectorMessage<T>
@@ -64,6 +67,8 @@ async fn remote_connector_event_loop<
   transport_outgoing_sender: Sender<ButtplugSerializedMessage>,
   // Takes data coming in from the transport.
   mut transport_incoming_recv: Receiver<ButtplugTransportIncomingMessage>,
+  mut replay_protection: Option<ReplayProtection>,
+  #[cfg(feature = "websockets")] compressor: Option<FrameCompressor>,
 ) where
   TransportType: ButtplugConnectorTransport + 'static,
   SerializerType: ButtplugMessageSerializer<Inbound = InboundMessageType, Outbound = OutboundMessageType>
@@ -103,6 +108,49 @@ async fn remote_connector_event_loop<
       StreamValue::Incoming(remote_msg) => {
         match remote_msg {
           ButtplugTransportIncomingMessage::Message(serialized_msg) => {
+            #[cfg(feature = "websockets")]
+            let serialized_msg = match compressor.as_ref() {
+              Some(compressor) => match compressor.decompress(serialized_msg) {
+                Ok(msg) => msg,
+                Err(e) => {
+                  error!("Closing remote Buttplug connection, cannot read frame: {}", e);
+                  if let Err(e) = transport.disconnect().await {
+                    error!("Error disconnecting transport: {:?}", e);
+                  }
+                  break;
+                }
+              },
+              None if is_compressed_frame(&serialized_msg) => {
+                error!(
+                  "Closing remote Buttplug connection, the other side is sending compressed frames \
+                   but compression isn't turned on here. Compression has to be turned on for both \
+                   sides of the connection."
+                );
+                if let Err(e) = transport.disconnect().await {
+                  error!("Error disconnecting transport: {:?}", e);
+                }
+                break;
+              }
+              None => serialized_msg,
+            };
+            let serialized_msg = match replay_protection.as_mut() {
+              Some(protection) => match protection.verify(&serialized_msg) {
+                Ok(msg) => msg,
+                Err(e) => {
+                  // Once a frame fails, sequences on each side no longer line up, and the other
+                  // side may not be who we think it is. Either way, the connection can't be used.
+                  error!(
+                    "Closing remote Buttplug connection, frame was rejected - Message: {:?} - Error: {}",
+                    serialized_msg, e
+                  );
+                  if let Err(e) = transport.disconnect().await {
+                    error!("Error disconnecting transport: {:?}", e);
+                  }
+                  break;
+                }
+              },
+              None => serialized_msg,
+            };
             match serializer.deserialize(&serialized_msg) {
               Ok(array) => {
                 for smsg in array {
@@ -143,7 +191,20 @@ async fn remote_connector_event_loop<
           ButtplugRemoteConnectorMessage::Message(msg) => {
             // Create future sets our message ID, so make sure this
             // happens before we send out the message.
-            let serialized_msg = serializer.serialize(&[msg.clone()]);
+            let mut serialized_msg = serializer.serialize(std::slice::from_ref(msg));
+            if let Some(protection) = replay_protection.as_mut() {
+              serialized_msg = match protection.protect(serialized_msg) {
+                Ok(msg) => msg,
+                Err(e) => {
+                  error!("Cannot add replay protection to outgoing message: {}", e);
+                  continue;
+                }
+              };
+            }
+            #[cfg(feature = "websockets")]
+            if let Some(compressor) = compressor.as_ref() {
+              serialized_msg = compressor.compress(serialized_msg);
+            }
             if transport_outgoing_sender
               .send(serialized_msg)
               .await
@@ -206,6 +267,13 @@ pub struct ButtplugRemoteConnector<
   transport: Option<TransportType>,
   /// Sender for forwarding outgoing messages to the connector event loop.
   event_loop_sender: Option<Sender<ButtplugRemoteConnectorMessage<OutboundMessageType>>>,
+  /// If set, frames are authenticated with the key and wrapped with sequence numbers and
+  /// timestamps. Incoming frames older than the max age, out of sequence, or that fail
+  /// authentication close the connection.
+  replay_protection: Option<(Duration, Vec<u8>)>,
+  /// If set, large frames are compressed, with totals kept here.
+  #[cfg(feature = "websockets")]
+  compression_statistics: Option<ButtplugCompressionStatistics>,
   dummy_serializer: PhantomData<SerializerType>,
 }
 
@@ -222,9 +290,39 @@ where
     Self {
       transport: Some(transport),
       event_loop_sender: None,
+      replay_protection: None,
+      #[cfg(feature = "websockets")]
+      compression_statistics: None,
       dummy_serializer: PhantomData::default(),
     }
   }
+
+  /// Turn on replay protection, for connections where the transport isn't encrypted. Frames get
+  /// sequence numbers, timestamps, and an HMAC keyed with `key`. The first incoming frame that
+  /// fails authentication, is out of sequence, or is more than `max_frame_age` away from the local
+  /// clock closes the connection. The other side of the connection has to turn this on with the
+  /// same key, and the clocks on both machines need to be roughly in sync.
+  pub fn with_replay_protection(mut self, max_frame_age: Duration, key: &[u8]) -> Self {
+    self.replay_protection = Some((max_frame_age, key.to_vec()));
+    self
+  }
+
+  /// Turn on compression, for slow or metered links. Large frames are deflated before going out,
+  /// and compressed frames coming in are inflated. The other side of the connection has to turn
+  /// this on too. If only one side has it on, the other closes the connection with an error as
+  /// soon as it gets a compressed frame.
+  #[cfg(feature = "websockets")]
+  pub fn with_compression(mut self) -> Self {
+    self.compression_statistics = Some(ButtplugCompressionStatistics::default());
+    self
+  }
+
+  /// Byte counts and compression ratios, if compression is on. The returned value keeps updating
+  /// after the connector is handed off, so grab it before connecting.
+  #[cfg(feature = "websockets")]
+  pub fn compression_statistics(&self) -> Option<ButtplugCompressionStatistics> {
+    self.compression_statistics.clone()
+  }
 }
 
 impl<TransportType, SerializerType, OutboundMessageType, InboundMessageType>
@@ -253,6 +351,15 @@ where
         .expect("Already checked that this would be a valid take().");
       let (connector_outgoing_sender, connector_outgoing_receiver) = channel(256);
       self.event_loop_sender = Some(connector_outgoing_sender);
+      let replay_protection = self
+        .replay_protection
+        .as_ref()
+        .map(|(max_frame_age, key)| ReplayProtection::new(*max_frame_age, key));
+      #[cfg(feature = "websockets")]
+      let compressor = self
+        .compression_statistics
+        .clone()
+        .map(FrameCompressor::new);
       async move {
         let (transport_outgoing_sender, transport_outgoing_receiver) = channel(256);
         let (transport_incoming_sender, transport_incoming_receiver) = channel(256);
@@ -275,6 +382,9 @@ where
                 transport,
                 transport_outgoing_sender,
                 transport_incoming_receiver,
+                replay_protection,
+                #[cfg(feature = "websockets")]
+                compressor,
               )
               .await
             });
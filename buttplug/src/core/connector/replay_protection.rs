@@ -0,0 +1,288 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2024 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Authenticated sequence and timestamp framing for remote connectors.
+//!
+//! Without an encrypted transport, anyone sitting between client and server can record a frame
+//! (say, a ScalarCmd at full power) and send it again later, or write one of their own. With
+//! replay protection on, each serialized frame is wrapped as
+//!
+//! ```json
+//! {"Sequence":1,"Timestamp":1700000000000,"Mac":"9f86d0...","Messages":[...]}
+//! ```
+//!
+//! where Sequence counts up from 1 per connection, Timestamp is milliseconds since the unix epoch,
+//! and Mac is a hex encoded HMAC-SHA256 of the other three, keyed with a secret both sides were
+//! given. Incoming frames have to carry a valid Mac and the next sequence number, can't go back in
+//! time, and have to be within the allowed age of the local clock. Without the key, frames can't
+//! be forged or edited, replays within a connection are caught by the sequence, and replays from
+//! older connections by the timestamp. This only detects tampering, it doesn't hide anything, and
+//! both sides have to turn it on with the same key.
+
+use crate::core::message::serializer::{ButtplugSerializedMessage, ButtplugSerializerError};
+use hmac::{Hmac, Mac};
+use instant::SystemTime;
+use serde::Deserialize;
+use serde_json::value::RawValue;
+use sha2::Sha256;
+use std::time::{Duration, UNIX_EPOCH};
+
+type HmacSha256 = Hmac<Sha256>;
+
+#[derive(Deserialize)]
+struct ProtectedFrame<'a> {
+  #[serde(rename = "Sequence")]
+  sequence: u64,
+  #[serde(rename = "Timestamp")]
+  timestamp: u64,
+  #[serde(rename = "Mac")]
+  mac: &'a str,
+  // Kept as the text that was sent, since the Mac covers it byte for byte.
+  #[serde(rename = "Messages", borrow)]
+  messages: &'a RawValue,
+}
+
+pub(super) struct ReplayProtection {
+  max_frame_age: Duration,
+  key: HmacSha256,
+  last_outgoing_sequence: u64,
+  last_incoming_sequence: u64,
+  last_incoming_timestamp: u64,
+}
+
+impl ReplayProtection {
+  pub(super) fn new(max_frame_age: Duration, key: &[u8]) -> Self {
+    Self {
+      max_frame_age,
+      key: HmacSha256::new_from_slice(key).expect("HMAC takes keys of any length."),
+      last_outgoing_sequence: 0,
+      last_incoming_sequence: 0,
+      last_incoming_timestamp: 0,
+    }
+  }
+
+  fn mac(&self, sequence: u64, timestamp: u64, messages: &str) -> HmacSha256 {
+    let mut mac = self.key.clone();
+    mac.update(&sequence.to_be_bytes());
+    mac.update(&timestamp.to_be_bytes());
+    mac.update(messages.as_bytes());
+    mac
+  }
+
+  /// Wrap an outgoing frame with the next sequence number and the current time.
+  pub(super) fn protect(
+    &mut self,
+    msg: ButtplugSerializedMessage,
+  ) -> Result<ButtplugSerializedMessage, ButtplugSerializerError> {
+    self.protect_at(msg, now_millis())
+  }
+
+  fn protect_at(
+    &mut self,
+    msg: ButtplugSerializedMessage,
+    timestamp: u64,
+  ) -> Result<ButtplugSerializedMessage, ButtplugSerializerError> {
+    let ButtplugSerializedMessage::Text(messages) = msg else {
+      return Err(ButtplugSerializerError::BinaryDeserializationError);
+    };
+    self.last_outgoing_sequence += 1;
+    let mac = self
+      .mac(self.last_outgoing_sequence, timestamp, &messages)
+      .finalize()
+      .into_bytes()
+      .iter()
+      .map(|byte| format!("{:02x}", byte))
+      .collect::<String>();
+    Ok(ButtplugSerializedMessage::Text(format!(
+      r#"{{"Sequence":{},"Timestamp":{},"Mac":"{}","Messages":{}}}"#,
+      self.last_outgoing_sequence, timestamp, mac, messages
+    )))
+  }
+
+  /// Check an incoming frame, returning the messages inside it if it's authentic and isn't a
+  /// replay.
+  pub(super) fn verify(
+    &mut self,
+    msg: &ButtplugSerializedMessage,
+  ) -> Result<ButtplugSerializedMessage, ButtplugSerializerError> {
+    self.verify_at(msg, now_millis())
+  }
+
+  fn verify_at(
+    &mut self,
+    msg: &ButtplugSerializedMessage,
+    now: u64,
+  ) -> Result<ButtplugSerializedMessage, ButtplugSerializerError> {
+    let ButtplugSerializedMessage::Text(text) = msg else {
+      return Err(ButtplugSerializerError::BinaryDeserializationError);
+    };
+    let frame: ProtectedFrame = serde_json::from_str(text).map_err(|err| {
+      ButtplugSerializerError::ReplayProtectionError(format!("Frame is not protected: {}", err))
+    })?;
+    // Check the Mac first, so nothing else about the frame is trusted before it's authenticated.
+    let authentic = decode_hex(frame.mac).is_some_and(|mac| {
+      self
+        .mac(frame.sequence, frame.timestamp, frame.messages.get())
+        .verify_slice(&mac)
+        .is_ok()
+    });
+    if !authentic {
+      return Err(ButtplugSerializerError::ReplayProtectionError(
+        "Frame failed authentication, it was altered or the keys on each side don't match"
+          .to_owned(),
+      ));
+    }
+    if frame.sequence != self.last_incoming_sequence + 1 {
+      return Err(ButtplugSerializerError::ReplayProtectionError(format!(
+        "Expected sequence {}, got {}",
+        self.last_incoming_sequence + 1,
+        frame.sequence
+      )));
+    }
+    if frame.timestamp < self.last_incoming_timestamp {
+      return Err(ButtplugSerializerError::ReplayProtectionError(format!(
+        "Timestamp {} is earlier than the previous frame's {}",
+        frame.timestamp, self.last_incoming_timestamp
+      )));
+    }
+    // Allow the same slack in both directions, since the other side's clock may be ahead of ours.
+    if now.abs_diff(frame.timestamp) > self.max_frame_age.as_millis() as u64 {
+      return Err(ButtplugSerializerError::ReplayProtectionError(format!(
+        "Timestamp {} is too far from local time {}",
+        frame.timestamp, now
+      )));
+    }
+    self.last_incoming_sequence = frame.sequence;
+    self.last_incoming_timestamp = frame.timestamp;
+    Ok(ButtplugSerializedMessage::Text(frame.messages.get().to_owned()))
+  }
+}
+
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+  text
+    .as_bytes()
+    .chunks(2)
+    .map(|pair| {
+      let pair = std::str::from_utf8(pair).ok().filter(|pair| pair.len() == 2)?;
+      u8::from_str_radix(pair, 16).ok()
+    })
+    .collect()
+}
+
+fn now_millis() -> u64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|time| time.as_millis() as u64)
+    .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+  use super::ReplayProtection;
+  use crate::core::message::serializer::{ButtplugSerializedMessage, ButtplugSerializerError};
+  use std::time::Duration;
+
+  const KEY: &[u8] = b"test key";
+
+  #[test]
+  fn test_replay_protection() {
+    let mut sender = ReplayProtection::new(Duration::from_secs(30), KEY);
+    let mut receiver = ReplayProtection::new(Duration::from_secs(30), KEY);
+    let now = 1_700_000_000_000;
+    let text = |s: &str| ButtplugSerializedMessage::Text(s.to_owned());
+    let is_rejected = |result: Result<_, _>| {
+      matches!(
+        result,
+        Err(ButtplugSerializerError::ReplayProtectionError(_))
+      )
+    };
+
+    let first = sender
+      .protect_at(text(r#"[{"Ping":{"Id":1}}]"#), now)
+      .unwrap();
+    assert_eq!(
+      receiver.verify_at(&first, now + 10).unwrap(),
+      text(r#"[{"Ping":{"Id":1}}]"#)
+    );
+    // The same frame again is a replay.
+    assert!(is_rejected(receiver.verify_at(&first, now + 20)));
+    // So is one that skips ahead, or doesn't have a sequence at all.
+    sender.protect_at(text("[]"), now).unwrap();
+    let third = sender.protect_at(text("[]"), now).unwrap();
+    assert!(is_rejected(receiver.verify_at(&third, now)));
+    assert!(is_rejected(receiver.verify_at(&text("[]"), now)));
+
+    // A frame from an older connection has a valid sequence, but is too old.
+    let mut old_sender = ReplayProtection::new(Duration::from_secs(30), KEY);
+    let mut new_receiver = ReplayProtection::new(Duration::from_secs(30), KEY);
+    let old = old_sender.protect_at(text("[]"), now).unwrap();
+    assert!(is_rejected(new_receiver.verify_at(&old, now + 60_000)));
+  }
+
+  #[test]
+  fn test_replay_protection_tampering() {
+    let mut sender = ReplayProtection::new(Duration::from_secs(30), KEY);
+    let mut receiver = ReplayProtection::new(Duration::from_secs(30), KEY);
+    let now = 1_700_000_000_000;
+    let text = |s: &str| ButtplugSerializedMessage::Text(s.to_owned());
+    let is_rejected = |result: Result<_, _>| {
+      matches!(
+        result,
+        Err(ButtplugSerializerError::ReplayProtectionError(_))
+      )
+    };
+
+    // Turning the payload up from 0.1 to 1.0 breaks the Mac.
+    let ButtplugSerializedMessage::Text(frame) = sender
+      .protect_at(
+        text(r#"[{"ScalarCmd":{"Id":2,"DeviceIndex":0,"Scalars":[{"Index":0,"Scalar":0.1,"ActuatorType":"Vibrate"}]}}]"#),
+        now,
+      )
+      .unwrap()
+    else {
+      panic!("Protected frames are always text");
+    };
+    let tampered = text(&frame.replace(r#""Scalar":0.1"#, r#""Scalar":1.0"#));
+    assert!(is_rejected(receiver.verify_at(&tampered, now)));
+    // So does bumping the timestamp to get an old frame past the age check.
+    let stale = text(&frame.replace(&now.to_string(), &(now + 60_000).to_string()));
+    assert!(is_rejected(receiver.verify_at(&stale, now + 60_000)));
+    // A rejected frame doesn't move the sequence, so the real one still goes through.
+    assert!(receiver.verify_at(&text(&frame), now).is_ok());
+  }
+
+  #[test]
+  fn test_replay_protection_injection() {
+    let mut receiver = ReplayProtection::new(Duration::from_secs(30), KEY);
+    let now = 1_700_000_000_000;
+    let text = |s: &str| ButtplugSerializedMessage::Text(s.to_owned());
+    let is_rejected = |result: Result<_, _>| {
+      matches!(
+        result,
+        Err(ButtplugSerializerError::ReplayProtectionError(_))
+      )
+    };
+
+    // A well formed frame with the right sequence and time, but a made up Mac.
+    let forged = text(&format!(
+      r#"{{"Sequence":1,"Timestamp":{},"Mac":"{}","Messages":[{{"StopAllDevices":{{"Id":3}}}}]}}"#,
+      now,
+      "00".repeat(32)
+    ));
+    assert!(is_rejected(receiver.verify_at(&forged, now)));
+    // One without a Mac at all.
+    let unsigned = text(&format!(
+      r#"{{"Sequence":1,"Timestamp":{},"Messages":[]}}"#,
+      now
+    ));
+    assert!(is_rejected(receiver.verify_at(&unsigned, now)));
+    // And one signed with a different key.
+    let mut attacker = ReplayProtection::new(Duration::from_secs(30), b"wrong key");
+    let wrong_key = attacker.protect_at(text("[]"), now).unwrap();
+    assert!(is_rejected(receiver.verify_at(&wrong_key, now)));
+  }
+}
@@ -0,0 +1,478 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2024 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Wire traces of client/server traffic, for turning bug reports into tests.
+//!
+//! A [ButtplugTracingConnector] wraps any connector and records the messages going through it into
+//! a [ButtplugMessageTrace]. Traces save to JSON, so users can attach them to bug reports. On the
+//! other end, [ButtplugMessageTrace::replay] plays the client side of a trace into an embedded
+//! server.
+
+use super::{ButtplugConnector, ButtplugConnectorError, ButtplugConnectorResultFuture};
+use crate::{
+  core::message::{
+    serializer::vec_to_protocol_json,
+    ButtplugClientMessageV0,
+    ButtplugClientMessageV1,
+    ButtplugClientMessageV2,
+    ButtplugClientMessageV3,
+    ButtplugClientMessageV4,
+    ButtplugClientMessageVariant,
+    ButtplugMessage,
+    ButtplugServerMessageV0,
+    ButtplugServerMessageV1,
+    ButtplugServerMessageV2,
+    ButtplugServerMessageV3,
+    ButtplugServerMessageV4,
+    ButtplugServerMessageVariant,
+  },
+  util::async_manager,
+};
+use displaydoc::Display;
+use futures::future::BoxFuture;
+use getset::{CopyGetters, Getters};
+use instant::Instant;
+use serde::{Deserialize, Serialize};
+use std::{
+  marker::PhantomData,
+  sync::{Arc, Mutex},
+};
+use thiserror::Error;
+use tokio::sync::mpsc::{channel, Sender};
+
+/// Version of the trace file format. Bumped whenever a change would keep older versions of the
+/// library from reading new traces.
+pub const BUTTPLUG_TRACE_FORMAT_VERSION: u32 = 1;
+
+/// Errors reading or replaying a [ButtplugMessageTrace].
+#[derive(Debug, Error, Display, Clone, PartialEq, Eq)]
+pub enum ButtplugTraceError {
+  /// Cannot read trace: {0}
+  InvalidTrace(String),
+  /// Trace format version {0} is newer than this library supports.
+  UnsupportedFormatVersion(u32),
+  /// Server did not send a {0} event that was in the trace.
+  MissingEvent(String),
+}
+
+/// Which way a traced message was going.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ButtplugTraceDirection {
+  ClientToServer,
+  ServerToClient,
+}
+
+/// A message in a trace.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Getters, CopyGetters)]
+pub struct ButtplugTraceEntry {
+  /// Milliseconds since recording started.
+  #[serde(rename = "Time")]
+  #[getset(get_copy = "pub")]
+  time_ms: u64,
+  #[serde(rename = "Direction")]
+  #[getset(get_copy = "pub")]
+  direction: ButtplugTraceDirection,
+  /// The message in Buttplug JSON protocol format, the same way it would go over a websocket.
+  #[serde(rename = "Message")]
+  #[getset(get = "pub")]
+  message: String,
+}
+
+impl ButtplugTraceEntry {
+  pub fn new(time_ms: u64, direction: ButtplugTraceDirection, message: &str) -> Self {
+    Self {
+      time_ms,
+      direction,
+      message: message.to_owned(),
+    }
+  }
+
+  /// Name of the message type, i.e. "DeviceAdded".
+  fn message_type(&self) -> Option<String> {
+    let json: serde_json::Value = serde_json::from_str(&self.message).ok()?;
+    json.get(0)?.as_object()?.keys().next().cloned()
+  }
+
+  /// Server messages with an ID of 0 are events, anything else is a reply.
+  fn is_event(&self) -> bool {
+    let Ok(json) = serde_json::from_str::<serde_json::Value>(&self.message) else {
+      return false;
+    };
+    json
+      .get(0)
+      .and_then(|msg| msg.as_object())
+      .and_then(|msg| msg.values().next())
+      .and_then(|fields| fields.get("Id"))
+      .and_then(|id| id.as_u64())
+      == Some(0)
+  }
+}
+
+/// Client/server traffic recorded by a [ButtplugTracingConnector].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Getters, CopyGetters)]
+pub struct ButtplugMessageTrace {
+  #[serde(rename = "FormatVersion")]
+  #[getset(get_copy = "pub")]
+  format_version: u32,
+  #[serde(rename = "Entries")]
+  #[getset(get = "pub")]
+  entries: Vec<ButtplugTraceEntry>,
+}
+
+impl Default for ButtplugMessageTrace {
+  fn default() -> Self {
+    Self::new(vec![])
+  }
+}
+
+impl ButtplugMessageTrace {
+  pub fn new(entries: Vec<ButtplugTraceEntry>) -> Self {
+    Self {
+      format_version: BUTTPLUG_TRACE_FORMAT_VERSION,
+      entries,
+    }
+  }
+
+  pub fn to_json(&self) -> String {
+    serde_json::to_string_pretty(self).expect("Traces are only strings and numbers.")
+  }
+
+  pub fn from_json(json: &str) -> Result<Self, ButtplugTraceError> {
+    let trace: Self =
+      serde_json::from_str(json).map_err(|e| ButtplugTraceError::InvalidTrace(e.to_string()))?;
+    if trace.format_version > BUTTPLUG_TRACE_FORMAT_VERSION {
+      return Err(ButtplugTraceError::UnsupportedFormatVersion(
+        trace.format_version,
+      ));
+    }
+    Ok(trace)
+  }
+}
+
+/// Messages that can be written into a trace.
+pub trait ButtplugTraceMessage {
+  /// Which way messages of this type go.
+  fn direction() -> ButtplugTraceDirection;
+  /// The message in Buttplug JSON protocol format.
+  fn trace_json(&self) -> String;
+}
+
+macro_rules! impl_trace_message {
+  ($direction:ident, $($message_type:ty),*) => {
+    $(
+      impl ButtplugTraceMessage for $message_type {
+        fn direction() -> ButtplugTraceDirection {
+          ButtplugTraceDirection::$direction
+        }
+
+        fn trace_json(&self) -> String {
+          vec_to_protocol_json(std::slice::from_ref(self))
+        }
+      }
+    )*
+  };
+}
+
+impl_trace_message!(
+  ClientToServer,
+  ButtplugClientMessageV0,
+  ButtplugClientMessageV1,
+  ButtplugClientMessageV2,
+  ButtplugClientMessageV3,
+  ButtplugClientMessageV4
+);
+impl_trace_message!(
+  ServerToClient,
+  ButtplugServerMessageV0,
+  ButtplugServerMessageV1,
+  ButtplugServerMessageV2,
+  ButtplugServerMessageV3,
+  ButtplugServerMessageV4
+);
+
+impl ButtplugTraceMessage for ButtplugClientMessageVariant {
+  fn direction() -> ButtplugTraceDirection {
+    ButtplugTraceDirection::ClientToServer
+  }
+
+  fn trace_json(&self) -> String {
+    match self {
+      Self::V0(msg) => msg.trace_json(),
+      Self::V1(msg) => msg.trace_json(),
+      Self::V2(msg) => msg.trace_json(),
+      Self::V3(msg) => msg.trace_json(),
+      Self::V4(msg) => msg.trace_json(),
+    }
+  }
+}
+
+impl ButtplugTraceMessage for ButtplugServerMessageVariant {
+  fn direction() -> ButtplugTraceDirection {
+    ButtplugTraceDirection::ServerToClient
+  }
+
+  fn trace_json(&self) -> String {
+    match self {
+      Self::V0(msg) => msg.trace_json(),
+      Self::V1(msg) => msg.trace_json(),
+      Self::V2(msg) => msg.trace_json(),
+      Self::V3(msg) => msg.trace_json(),
+      Self::V4(msg) => msg.trace_json(),
+    }
+  }
+}
+
+/// Collects trace entries. Clones share the same trace.
+#[derive(Clone, Debug)]
+pub struct ButtplugTraceRecorder {
+  started: Instant,
+  entries: Arc<Mutex<Vec<ButtplugTraceEntry>>>,
+}
+
+impl Default for ButtplugTraceRecorder {
+  fn default() -> Self {
+    Self {
+      started: Instant::now(),
+      entries: Arc::new(Mutex::new(vec![])),
+    }
+  }
+}
+
+impl ButtplugTraceRecorder {
+  pub fn record<T>(&self, msg: &T)
+  where
+    T: ButtplugTraceMessage,
+  {
+    self.record_json(T::direction(), &msg.trace_json());
+  }
+
+  fn record_json(&self, direction: ButtplugTraceDirection, message: &str) {
+    let entry = ButtplugTraceEntry::new(
+      self.started.elapsed().as_millis() as u64,
+      direction,
+      message,
+    );
+    self
+      .entries
+      .lock()
+      .expect("Lock is never held across a panic")
+      .push(entry);
+  }
+
+  /// Everything recorded so far.
+  pub fn trace(&self) -> ButtplugMessageTrace {
+    ButtplugMessageTrace::new(
+      self
+        .entries
+        .lock()
+        .expect("Lock is never held across a panic")
+        .clone(),
+    )
+  }
+}
+
+/// Wraps a connector, recording everything sent and received through it. Works on either side of
+/// a connection.
+pub struct ButtplugTracingConnector<ConnectorType, OutboundMessageType, InboundMessageType>
+where
+  ConnectorType: ButtplugConnector<OutboundMessageType, InboundMessageType>,
+  OutboundMessageType: ButtplugMessage + ButtplugTraceMessage + 'static,
+  InboundMessageType: ButtplugMessage + ButtplugTraceMessage + 'static,
+{
+  connector: ConnectorType,
+  recorder: ButtplugTraceRecorder,
+  _phantom: PhantomData<(OutboundMessageType, InboundMessageType)>,
+}
+
+impl<ConnectorType, OutboundMessageType, InboundMessageType>
+  ButtplugTracingConnector<ConnectorType, OutboundMessageType, InboundMessageType>
+where
+  ConnectorType: ButtplugConnector<OutboundMessageType, InboundMessageType>,
+  OutboundMessageType: ButtplugMessage + ButtplugTraceMessage + 'static,
+  InboundMessageType: ButtplugMessage + ButtplugTraceMessage + 'static,
+{
+  pub fn new(connector: ConnectorType) -> Self {
+    Self {
+      connector,
+      recorder: ButtplugTraceRecorder::default(),
+      _phantom: PhantomData,
+    }
+  }
+
+  /// The recorder for this connector. Keeps recording after the connector is handed off, so grab
+  /// it before connecting.
+  pub fn recorder(&self) -> ButtplugTraceRecorder {
+    self.recorder.clone()
+  }
+}
+
+impl<ConnectorType, OutboundMessageType, InboundMessageType>
+  ButtplugConnector<OutboundMessageType, InboundMessageType>
+  for ButtplugTracingConnector<ConnectorType, OutboundMessageType, InboundMessageType>
+where
+  ConnectorType: ButtplugConnector<OutboundMessageType, InboundMessageType>,
+  OutboundMessageType: ButtplugMessage + ButtplugTraceMessage + 'static,
+  InboundMessageType: ButtplugMessage + ButtplugTraceMessage + 'static,
+{
+  fn connect(
+    &mut self,
+    message_receiver: Sender<InboundMessageType>,
+  ) -> BoxFuture<'static, Result<(), ButtplugConnectorError>> {
+    let (inbound_sender, mut inbound_receiver) = channel(256);
+    let recorder = self.recorder.clone();
+    async_manager::spawn(async move {
+      while let Some(msg) = inbound_receiver.recv().await {
+        recorder.record(&msg);
+        if message_receiver.send(msg).await.is_err() {
+          break;
+        }
+      }
+    });
+    self.connector.connect(inbound_sender)
+  }
+
+  fn disconnect(&self) -> ButtplugConnectorResultFuture {
+    self.connector.disconnect()
+  }
+
+  fn send(&self, msg: OutboundMessageType) -> ButtplugConnectorResultFuture {
+    self.recorder.record(&msg);
+    self.connector.send(msg)
+  }
+}
+
+#[cfg(feature = "server")]
+mod replay {
+  use super::*;
+  use crate::{
+    core::message::serializer::{
+      ButtplugMessageSerializer,
+      ButtplugSerializedMessage,
+      ButtplugServerJSONSerializer,
+    },
+    server::ButtplugServerDowngradeWrapper,
+    util::sleep,
+  };
+  use futures::{pin_mut, StreamExt};
+  use std::time::Duration;
+
+  /// How long replay waits for the server to send an event the trace has next.
+  const REPLAY_EVENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+  impl ButtplugMessageTrace {
+    /// Play the client side of the trace into `server`, returning a trace of the server's side of
+    /// the conversation this time around.
+    ///
+    /// Client messages are sent in order, each waiting on the reply to the last. Before sending
+    /// one, we wait for the server to send any events (device added, etc...) the trace had before
+    /// it, so commands don't race the devices they're for. Timing isn't otherwise reproduced.
+    pub async fn replay(
+      &self,
+      server: &ButtplugServerDowngradeWrapper,
+    ) -> Result<ButtplugMessageTrace, ButtplugTraceError> {
+      let serializer = ButtplugServerJSONSerializer::default();
+      let recorder = ButtplugTraceRecorder::default();
+      let events = server.client_version_event_stream();
+      pin_mut!(events);
+      for entry in &self.entries {
+        match entry.direction() {
+          // Replies are recorded as we get them, we only need to wait on events.
+          ButtplugTraceDirection::ServerToClient if entry.is_event() => {
+            let expected = entry.message_type();
+            loop {
+              let event = tokio::select! {
+                event = events.next() => event,
+                _ = sleep(REPLAY_EVENT_TIMEOUT) => None,
+              };
+              let Some(event) = event else {
+                return Err(ButtplugTraceError::MissingEvent(
+                  expected.unwrap_or_default(),
+                ));
+              };
+              let event_json = serialized_text(serializer.serialize(&[event]));
+              recorder.record_json(ButtplugTraceDirection::ServerToClient, &event_json);
+              let event_entry =
+                ButtplugTraceEntry::new(0, ButtplugTraceDirection::ServerToClient, &event_json);
+              if event_entry.message_type() == expected {
+                break;
+              }
+            }
+          }
+          ButtplugTraceDirection::ServerToClient => {}
+          ButtplugTraceDirection::ClientToServer => {
+            let msgs = serializer
+              .deserialize(&ButtplugSerializedMessage::Text(entry.message().clone()))
+              .map_err(|e| ButtplugTraceError::InvalidTrace(e.to_string()))?;
+            recorder.record_json(ButtplugTraceDirection::ClientToServer, entry.message());
+            for msg in msgs {
+              let reply = server.parse_message(msg).await.unwrap_or_else(|err| err);
+              recorder.record_json(
+                ButtplugTraceDirection::ServerToClient,
+                &serialized_text(serializer.serialize(&[reply])),
+              );
+            }
+          }
+        }
+      }
+      Ok(recorder.trace())
+    }
+  }
+
+  fn serialized_text(msg: ButtplugSerializedMessage) -> String {
+    match msg {
+      ButtplugSerializedMessage::Text(text) => text,
+      ButtplugSerializedMessage::Binary(_) => {
+        unreachable!("The JSON serializer only outputs text.")
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn test_trace_json_round_trip() {
+    let trace = ButtplugMessageTrace::new(vec![
+      ButtplugTraceEntry::new(
+        0,
+        ButtplugTraceDirection::ClientToServer,
+        r#"[{"RequestServerInfo":{"Id":1,"ClientName":"Test","MessageVersion":3}}]"#,
+      ),
+      ButtplugTraceEntry::new(
+        12,
+        ButtplugTraceDirection::ServerToClient,
+        r#"[{"ScanningFinished":{"Id":0}}]"#,
+      ),
+    ]);
+    assert_eq!(
+      ButtplugMessageTrace::from_json(&trace.to_json()).expect("Test, assuming infallible."),
+      trace
+    );
+    assert!(!trace.entries()[0].is_event());
+    assert!(trace.entries()[1].is_event());
+    assert_eq!(
+      trace.entries()[1].message_type().as_deref(),
+      Some("ScanningFinished")
+    );
+  }
+
+  #[test]
+  fn test_newer_trace_format_rejected() {
+    let json = format!(
+      r#"{{"FormatVersion":{},"Entries":[]}}"#,
+      BUTTPLUG_TRACE_FORMAT_VERSION + 1
+    );
+    assert_eq!(
+      ButtplugMessageTrace::from_json(&json),
+      Err(ButtplugTraceError::UnsupportedFormatVersion(
+        BUTTPLUG_TRACE_FORMAT_VERSION + 1
+      ))
+    );
+  }
+}
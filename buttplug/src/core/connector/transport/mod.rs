@@ -22,6 +22,9 @@ use thiserror::Error;
 use tokio::sync::mpsc::{Receiver, Sender};
 #[cfg(feature = "websockets")]
 pub use websocket::{
+  generate_mutual_tls_pair,
+  ButtplugMutualTlsConfig,
+  ButtplugTlsIdentity,
   ButtplugWebsocketClientTransport,
   ButtplugWebsocketServerTransport,
   ButtplugWebsocketServerTransportBuilder,
@@ -58,4 +61,7 @@ pub enum ButtplugConnectorTransportSpecificError {
   TungsteniteError(#[from] TungsteniteError),
   #[error("Network error: {0}")]
   GenericNetworkError(String),
+  #[cfg(feature = "websockets")]
+  #[error("TLS error: {0}")]
+  TlsError(String),
 }
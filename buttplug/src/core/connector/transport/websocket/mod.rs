@@ -9,6 +9,10 @@
 
 pub mod websocket_client;
 pub mod websocket_server;
+pub mod websocket_tls;
+
+use crate::core::message::serializer::PooledText;
+use tokio_tungstenite::tungstenite::{Bytes, Message, Utf8Bytes};
 
 pub use tokio_tungstenite::tungstenite::Error as TungsteniteError;
 pub use websocket_client::ButtplugWebsocketClientTransport;
@@ -17,3 +21,11 @@ pub use websocket_server::{
   ButtplugWebsocketServerTransport,
   ButtplugWebsocketServerTransportBuilder,
 };
+pub use websocket_tls::{generate_mutual_tls_pair, ButtplugMutualTlsConfig, ButtplugTlsIdentity};
+
+/// Wrap serialized text in a websocket frame that hands its buffer back to the serializer pool once
+/// the frame has been written out.
+fn pooled_text_frame(text: String) -> Message {
+  let bytes = Bytes::from_owner(PooledText::new(text));
+  Message::Text(Utf8Bytes::try_from(bytes).expect("Buffer came from a String, so is valid UTF-8"))
+}
@@ -7,6 +7,10 @@
 
 //! Handling of websockets using async-tungstenite
 
+use super::{
+  pooled_text_frame,
+  websocket_tls::{tls_error, ButtplugMutualTlsConfig},
+};
 use crate::{
   core::{
     connector::{
@@ -22,9 +26,15 @@ use crate::{
   },
   util::async_manager,
 };
-use futures::{future::BoxFuture, FutureExt, SinkExt, StreamExt};
+use futures::{
+  future::{self, BoxFuture},
+  FutureExt,
+  SinkExt,
+  StreamExt,
+};
 use rustls::{
   client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+  AlertDescription,
   ClientConfig,
   SignatureScheme,
 };
@@ -36,7 +46,7 @@ use tokio::sync::{
 use tokio_tungstenite::{
   connect_async,
   connect_async_tls_with_config,
-  tungstenite::protocol::Message,
+  tungstenite::{error::TlsError, protocol::Message, Error as TungsteniteError},
   Connector,
 };
 use tracing::Instrument;
@@ -55,6 +65,59 @@ pub fn get_rustls_config_dangerous() -> ClientConfig {
 
   config
 }
+
+// Sort out failures that come from connecting to the wrong thing, so users get more to go on than
+// a raw websocket error.
+fn classify_connect_error(err: TungsteniteError) -> ButtplugConnectorError {
+  let tls_alert = match &err {
+    TungsteniteError::Tls(TlsError::Rustls(rustls::Error::AlertReceived(alert))) => Some(*alert),
+    // Alerts that show up after the TLS handshake, like a server rejecting our lack of a client
+    // certificate, come back wrapped in IO errors.
+    TungsteniteError::Io(io_err) => match io_err
+      .get_ref()
+      .and_then(|inner| inner.downcast_ref::<rustls::Error>())
+    {
+      Some(rustls::Error::AlertReceived(alert)) => Some(*alert),
+      _ => None,
+    },
+    _ => None,
+  };
+  if let Some(
+    alert @ (AlertDescription::CertificateRequired
+    | AlertDescription::BadCertificate
+    | AlertDescription::UnknownCA
+    | AlertDescription::AccessDenied),
+  ) = tls_alert
+  {
+    return ButtplugConnectorError::AuthenticationRequired(format!(
+      "Server rejected the connection during TLS setup ({:?})",
+      alert
+    ));
+  }
+  match err {
+    TungsteniteError::Http(response) => {
+      let status = response.status();
+      if status.as_u16() == 401 || status.as_u16() == 403 {
+        ButtplugConnectorError::AuthenticationRequired(format!(
+          "Server refused the websocket upgrade with HTTP {}",
+          status
+        ))
+      } else {
+        ButtplugConnectorError::NotAButtplugServer(format!(
+          "Server answered with HTTP {} instead of a websocket upgrade",
+          status
+        ))
+      }
+    }
+    TungsteniteError::Protocol(protocol_err) => ButtplugConnectorError::NotAButtplugServer(
+      format!("Websocket handshake failed: {}", protocol_err),
+    ),
+    err => ButtplugConnectorError::TransportSpecificError(
+      ButtplugConnectorTransportSpecificError::TungsteniteError(err),
+    ),
+  }
+}
+
 #[derive(Debug)]
 pub struct NoCertificateVerification {}
 impl ServerCertVerifier for NoCertificateVerification {
@@ -116,6 +179,8 @@ pub struct ButtplugWebsocketClientTransport {
   /// If true, bypass certificate verification. Should be true for self-signed
   /// certs.
   bypass_cert_verify: bool,
+  /// If set, present a client certificate and only accept the pinned server certificate.
+  mutual_tls: Option<ButtplugMutualTlsConfig>,
   /// Internally held sender, used for when disconnect is called.
   disconnect_notifier: Arc<Notify>,
 }
@@ -126,6 +191,7 @@ impl ButtplugWebsocketClientTransport {
       should_use_tls,
       address: address.to_owned(),
       bypass_cert_verify,
+      mutual_tls: None,
       disconnect_notifier: Arc::new(Notify::new()),
     }
   }
@@ -149,6 +215,16 @@ impl ButtplugWebsocketClientTransport {
   pub fn new_secure_connector(address: &str, bypass_cert_verify: bool) -> Self {
     ButtplugWebsocketClientTransport::create(address, true, bypass_cert_verify)
   }
+
+  /// Creates a new connector for "wss://" addresses on servers that require mutual TLS
+  ///
+  /// The connector presents the config's identity to the server, and only accepts a server that
+  /// presents the config's peer certificate.
+  pub fn new_mutual_tls_connector(address: &str, config: ButtplugMutualTlsConfig) -> Self {
+    let mut transport = ButtplugWebsocketClientTransport::create(address, true, false);
+    transport.mutual_tls = Some(config);
+    transport
+  }
 }
 
 impl ButtplugConnectorTransport for ButtplugWebsocketClientTransport {
@@ -162,13 +238,24 @@ impl ButtplugConnectorTransport for ButtplugWebsocketClientTransport {
     let address = self.address.clone();
     let should_use_tls = self.should_use_tls;
     let bypass_cert_verify = self.bypass_cert_verify;
+    let client_config = self
+      .mutual_tls
+      .as_ref()
+      .map(ButtplugMutualTlsConfig::client_config);
+    let mutual_tls_config = match client_config {
+      Some(Ok(config)) => Some(config),
+      Some(Err(err)) => return future::ready(Err(tls_error(err))).boxed(),
+      None => None,
+    };
     async move {
       let url = Url::parse(&address).expect("Should be checked before here");
       let stream_result = if should_use_tls {
         // If we're supposed to be a secure connection, generate a TLS connector
         // based on our certificate verfication needs. Otherwise, just pass None in
         // which case we won't wrap.
-        let connector = if bypass_cert_verify {
+        let connector = if let Some(config) = mutual_tls_config {
+          Some(Connector::Rustls(Arc::new(config)))
+        } else if bypass_cert_verify {
           Some(Connector::Rustls(Arc::new(get_rustls_config_dangerous())))
         } else {
           None
@@ -189,7 +276,7 @@ impl ButtplugConnectorTransport for ButtplugWebsocketClientTransport {
                   msg = outgoing_receiver.recv().fuse() => {
                     if let Some(msg) = msg {
                       let out_msg = match msg {
-                        ButtplugSerializedMessage::Text(text) => Message::Text(text.into()),
+                        ButtplugSerializedMessage::Text(text) => pooled_text_frame(text),
                         ButtplugSerializedMessage::Binary(bin) => Message::Binary(bin.into()),
                       };
                       // TODO see what happens when we try to send to a remote that's closed connection.
@@ -291,9 +378,7 @@ impl ButtplugConnectorTransport for ButtplugWebsocketClientTransport {
           );
           Ok(())
         }
-        Err(websocket_error) => Err(ButtplugConnectorError::TransportSpecificError(
-          ButtplugConnectorTransportSpecificError::TungsteniteError(websocket_error),
-        )),
+        Err(websocket_error) => Err(classify_connect_error(websocket_error)),
       }
     }
     .boxed()
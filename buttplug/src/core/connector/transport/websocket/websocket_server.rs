@@ -5,6 +5,10 @@
 // Licensed under the BSD 3-Clause license. See LICENSE file in the project root
 // for full license information.
 
+use super::{
+  pooled_text_frame,
+  websocket_tls::{tls_error, ButtplugMutualTlsConfig},
+};
 use crate::{
   core::{
     connector::{
@@ -20,10 +24,16 @@ use crate::{
   },
   util::async_manager,
 };
-use futures::{future::BoxFuture, FutureExt, SinkExt, StreamExt};
+use futures::{
+  future::{self, BoxFuture},
+  FutureExt,
+  SinkExt,
+  StreamExt,
+};
 use std::{sync::Arc, time::Duration};
 use tokio::{
-  net::{TcpListener, TcpStream},
+  io::{AsyncRead, AsyncWrite},
+  net::TcpListener,
   sync::{
     mpsc::{Receiver, Sender},
     Notify,
@@ -35,8 +45,10 @@ use tokio::{
 pub struct ButtplugWebsocketServerTransportBuilder {
   /// If true, listens all on available interfaces. Otherwise, only listens on 127.0.0.1.
   listen_on_all_interfaces: bool,
-  /// Insecure port for listening for websocket connections.
+  /// Port for listening for websocket connections.
   port: u16,
+  /// If set, connections are wrapped in TLS, and clients have to present the pinned certificate.
+  mutual_tls: Option<ButtplugMutualTlsConfig>,
 }
 
 impl Default for ButtplugWebsocketServerTransportBuilder {
@@ -44,6 +56,7 @@ impl Default for ButtplugWebsocketServerTransportBuilder {
     Self {
       listen_on_all_interfaces: false,
       port: 12345,
+      mutual_tls: None,
     }
   }
 }
@@ -59,21 +72,31 @@ impl ButtplugWebsocketServerTransportBuilder {
     self
   }
 
+  /// Require mutual TLS. The server presents the config's identity, and only accepts clients that
+  /// present its peer certificate.
+  pub fn mutual_tls(&mut self, config: ButtplugMutualTlsConfig) -> &mut Self {
+    self.mutual_tls = Some(config);
+    self
+  }
+
   pub fn finish(&self) -> ButtplugWebsocketServerTransport {
     ButtplugWebsocketServerTransport {
       port: self.port,
       listen_on_all_interfaces: self.listen_on_all_interfaces,
+      mutual_tls: self.mutual_tls.clone(),
       disconnect_notifier: Arc::new(Notify::new()),
     }
   }
 }
 
-async fn run_connection_loop(
-  ws_stream: tokio_tungstenite::WebSocketStream<TcpStream>,
+async fn run_connection_loop<S>(
+  ws_stream: tokio_tungstenite::WebSocketStream<S>,
   mut request_receiver: Receiver<ButtplugSerializedMessage>,
   response_sender: Sender<ButtplugTransportIncomingMessage>,
   disconnect_notifier: Arc<Notify>,
-) {
+) where
+  S: AsyncRead + AsyncWrite + Unpin,
+{
   info!("Starting websocket server connection event loop.");
 
   let (mut websocket_server_sender, mut websocket_server_receiver) = ws_stream.split();
@@ -109,7 +132,7 @@ async fn run_connection_loop(
             ButtplugSerializedMessage::Text(text_msg) => {
               trace!("Sending text message: {}", text_msg);
               if websocket_server_sender
-                .send(tokio_tungstenite::tungstenite::Message::Text(text_msg.into()))
+                .send(pooled_text_frame(text_msg))
                 .await
                 .is_err() {
                 warn!("Cannot send text value to server, considering connection closed.");
@@ -172,8 +195,12 @@ async fn run_connection_loop(
                   pong_count += 1;
                   continue;
                 }
-                tokio_tungstenite::tungstenite::Message::Binary(_) => {
-                  error!("Don't know how to handle binary message types!");
+                tokio_tungstenite::tungstenite::Message::Binary(binary_msg) => {
+                  // Compressed frames, see ButtplugRemoteConnector::with_compression.
+                  if response_sender.send(ButtplugTransportIncomingMessage::Message(ButtplugSerializedMessage::Binary(binary_msg.into()))).await.is_err() {
+                    warn!("Connector that owns transport no longer available, exiting.");
+                    break;
+                  }
                 }
               }
             },
@@ -197,6 +224,7 @@ async fn run_connection_loop(
 pub struct ButtplugWebsocketServerTransport {
   port: u16,
   listen_on_all_interfaces: bool,
+  mutual_tls: Option<ButtplugMutualTlsConfig>,
   disconnect_notifier: Arc<Notify>,
 }
 
@@ -218,6 +246,16 @@ impl ButtplugConnectorTransport for ButtplugWebsocketServerTransport {
     debug!("Websocket: Trying to listen on {}", addr);
     let response_sender_clone = incoming_sender;
     let disconnect_notifier_clone = disconnect_notifier;
+    // Build the TLS config up front, so a bad certificate or key fails the connect right away.
+    let server_config = self
+      .mutual_tls
+      .as_ref()
+      .map(ButtplugMutualTlsConfig::server_config);
+    let tls_acceptor = match server_config {
+      Some(Ok(config)) => Some(tokio_rustls::TlsAcceptor::from(Arc::new(config))),
+      Some(Err(err)) => return future::ready(Err(tls_error(err))).boxed(),
+      None => None,
+    };
     let fut = async move {
       // Create the event loop and TCP listener we'll accept connections on.
       let try_socket = TcpListener::bind(&addr).await;
@@ -230,23 +268,43 @@ impl ButtplugConnectorTransport for ButtplugWebsocketServerTransport {
       debug!("Websocket: Listening on: {}", addr);
       if let Ok((stream, _)) = listener.accept().await {
         info!("Websocket: Got connection");
-        let ws_stream = tokio_tungstenite::accept_async(stream)
-          .await
-          .map_err(|err| {
-            error!("Websocket server accept error: {:?}", err);
-            ButtplugConnectorError::TransportSpecificError(
-              ButtplugConnectorTransportSpecificError::TungsteniteError(err),
-            )
-          })?;
-        async_manager::spawn(async move {
-          run_connection_loop(
-            ws_stream,
-            outgoing_receiver,
-            response_sender_clone,
-            disconnect_notifier_clone,
+        let accept_error = |err| {
+          error!("Websocket server accept error: {:?}", err);
+          ButtplugConnectorError::TransportSpecificError(
+            ButtplugConnectorTransportSpecificError::TungsteniteError(err),
           )
-          .await;
-        });
+        };
+        if let Some(tls_acceptor) = tls_acceptor {
+          let tls_stream = tls_acceptor.accept(stream).await.map_err(|err| {
+            error!("Websocket server TLS handshake error: {:?}", err);
+            tls_error(err)
+          })?;
+          let ws_stream = tokio_tungstenite::accept_async(tls_stream)
+            .await
+            .map_err(accept_error)?;
+          async_manager::spawn(async move {
+            run_connection_loop(
+              ws_stream,
+              outgoing_receiver,
+              response_sender_clone,
+              disconnect_notifier_clone,
+            )
+            .await;
+          });
+        } else {
+          let ws_stream = tokio_tungstenite::accept_async(stream)
+            .await
+            .map_err(accept_error)?;
+          async_manager::spawn(async move {
+            run_connection_loop(
+              ws_stream,
+              outgoing_receiver,
+              response_sender_clone,
+              disconnect_notifier_clone,
+            )
+            .await;
+          });
+        }
         Ok(())
       } else {
         Err(ButtplugConnectorError::ConnectorGenericError(
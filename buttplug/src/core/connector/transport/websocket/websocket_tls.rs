@@ -0,0 +1,272 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2024 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Mutual TLS for websocket transports.
+//!
+//! This is aimed at two machines the user controls talking to each other, not at the public
+//! internet, so there's no certificate authority involved. Each side has its own (usually
+//! self-signed) certificate, and is given the exact certificate to expect from the other side.
+//! Anything else is rejected during the TLS handshake, on both ends. [generate_mutual_tls_pair]
+//! makes a matching set of configurations in one go.
+
+use super::super::ButtplugConnectorTransportSpecificError;
+use crate::core::connector::ButtplugConnectorError;
+use rustls::{
+  client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+  crypto::{ring, verify_tls12_signature, verify_tls13_signature, WebPkiSupportedAlgorithms},
+  pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer, ServerName, UnixTime},
+  server::danger::{ClientCertVerified, ClientCertVerifier},
+  ClientConfig,
+  DigitallySignedStruct,
+  DistinguishedName,
+  ServerConfig,
+  SignatureScheme,
+};
+use std::sync::Arc;
+
+/// A certificate and its private key, both DER encoded. The key must be PKCS#8.
+#[derive(Clone)]
+pub struct ButtplugTlsIdentity {
+  certificate: Vec<u8>,
+  private_key: Vec<u8>,
+}
+
+impl ButtplugTlsIdentity {
+  pub fn new(certificate: &[u8], private_key: &[u8]) -> Self {
+    Self {
+      certificate: certificate.to_vec(),
+      private_key: private_key.to_vec(),
+    }
+  }
+
+  pub fn certificate(&self) -> &[u8] {
+    &self.certificate
+  }
+
+  fn certificate_chain(&self) -> Vec<CertificateDer<'static>> {
+    vec![CertificateDer::from(self.certificate.clone())]
+  }
+
+  fn key(&self) -> PrivateKeyDer<'static> {
+    PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(self.private_key.clone()))
+  }
+}
+
+// Keep keys out of logs.
+impl std::fmt::Debug for ButtplugTlsIdentity {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("ButtplugTlsIdentity")
+      .field("certificate", &format!("{} bytes", self.certificate.len()))
+      .finish()
+  }
+}
+
+/// Our own identity, plus the certificate the other side of the connection has to present.
+#[derive(Clone, Debug)]
+pub struct ButtplugMutualTlsConfig {
+  identity: ButtplugTlsIdentity,
+  peer_certificate: Vec<u8>,
+}
+
+impl ButtplugMutualTlsConfig {
+  pub fn new(identity: ButtplugTlsIdentity, peer_certificate: &[u8]) -> Self {
+    Self {
+      identity,
+      peer_certificate: peer_certificate.to_vec(),
+    }
+  }
+
+  pub fn identity(&self) -> &ButtplugTlsIdentity {
+    &self.identity
+  }
+
+  pub fn peer_certificate(&self) -> &[u8] {
+    &self.peer_certificate
+  }
+
+  pub(super) fn client_config(&self) -> Result<ClientConfig, rustls::Error> {
+    let mut config = ClientConfig::builder()
+      .with_root_certificates(rustls::RootCertStore::empty())
+      .with_client_auth_cert(self.identity.certificate_chain(), self.identity.key())?;
+    config
+      .dangerous()
+      .set_certificate_verifier(Arc::new(PinnedCertificateVerifier::new(
+        &self.peer_certificate,
+      )));
+    Ok(config)
+  }
+
+  pub(super) fn server_config(&self) -> Result<ServerConfig, rustls::Error> {
+    ServerConfig::builder()
+      .with_client_cert_verifier(Arc::new(PinnedCertificateVerifier::new(
+        &self.peer_certificate,
+      )))
+      .with_single_cert(self.identity.certificate_chain(), self.identity.key())
+  }
+}
+
+/// Generate self-signed certificates for a server and a client, and return (server config, client
+/// config) with each pinned to the other. `server_names` are the host names or IP addresses the
+/// client will use to reach the server. They're put in the certificate for the benefit of other
+/// tools, since pinning doesn't look at names.
+#[allow(clippy::result_large_err)]
+pub fn generate_mutual_tls_pair(
+  server_names: &[&str],
+) -> Result<(ButtplugMutualTlsConfig, ButtplugMutualTlsConfig), ButtplugConnectorError> {
+  let generate = |names: Vec<String>| {
+    rcgen::generate_simple_self_signed(names)
+      .map(|certified| {
+        ButtplugTlsIdentity::new(certified.cert.der(), &certified.key_pair.serialize_der())
+      })
+      .map_err(tls_error)
+  };
+  let server = generate(server_names.iter().map(|name| name.to_string()).collect())?;
+  let client = generate(vec!["buttplug-client".to_owned()])?;
+  Ok((
+    ButtplugMutualTlsConfig::new(server.clone(), client.certificate()),
+    ButtplugMutualTlsConfig::new(client, server.certificate()),
+  ))
+}
+
+pub(super) fn tls_error(err: impl std::fmt::Display) -> ButtplugConnectorError {
+  ButtplugConnectorError::TransportSpecificError(ButtplugConnectorTransportSpecificError::TlsError(
+    err.to_string(),
+  ))
+}
+
+/// Accepts exactly one certificate, for either end of the connection. Handshake signatures are
+/// still checked, so the peer has to hold the matching private key.
+#[derive(Debug)]
+struct PinnedCertificateVerifier {
+  certificate: CertificateDer<'static>,
+  algorithms: WebPkiSupportedAlgorithms,
+}
+
+impl PinnedCertificateVerifier {
+  fn new(certificate: &[u8]) -> Self {
+    Self {
+      certificate: CertificateDer::from(certificate.to_vec()),
+      algorithms: ring::default_provider().signature_verification_algorithms,
+    }
+  }
+
+  fn check(&self, end_entity: &CertificateDer<'_>) -> Result<(), rustls::Error> {
+    if *end_entity == self.certificate {
+      Ok(())
+    } else {
+      Err(rustls::Error::InvalidCertificate(
+        rustls::CertificateError::ApplicationVerificationFailure,
+      ))
+    }
+  }
+}
+
+impl ServerCertVerifier for PinnedCertificateVerifier {
+  fn verify_server_cert(
+    &self,
+    end_entity: &CertificateDer<'_>,
+    _intermediates: &[CertificateDer<'_>],
+    _server_name: &ServerName<'_>,
+    _ocsp: &[u8],
+    _now: UnixTime,
+  ) -> Result<ServerCertVerified, rustls::Error> {
+    self
+      .check(end_entity)
+      .map(|_| ServerCertVerified::assertion())
+  }
+
+  fn verify_tls12_signature(
+    &self,
+    message: &[u8],
+    cert: &CertificateDer<'_>,
+    dss: &DigitallySignedStruct,
+  ) -> Result<HandshakeSignatureValid, rustls::Error> {
+    verify_tls12_signature(message, cert, dss, &self.algorithms)
+  }
+
+  fn verify_tls13_signature(
+    &self,
+    message: &[u8],
+    cert: &CertificateDer<'_>,
+    dss: &DigitallySignedStruct,
+  ) -> Result<HandshakeSignatureValid, rustls::Error> {
+    verify_tls13_signature(message, cert, dss, &self.algorithms)
+  }
+
+  fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+    self.algorithms.supported_schemes()
+  }
+}
+
+impl ClientCertVerifier for PinnedCertificateVerifier {
+  fn root_hint_subjects(&self) -> &[DistinguishedName] {
+    &[]
+  }
+
+  fn verify_client_cert(
+    &self,
+    end_entity: &CertificateDer<'_>,
+    _intermediates: &[CertificateDer<'_>],
+    _now: UnixTime,
+  ) -> Result<ClientCertVerified, rustls::Error> {
+    self
+      .check(end_entity)
+      .map(|_| ClientCertVerified::assertion())
+  }
+
+  fn verify_tls12_signature(
+    &self,
+    message: &[u8],
+    cert: &CertificateDer<'_>,
+    dss: &DigitallySignedStruct,
+  ) -> Result<HandshakeSignatureValid, rustls::Error> {
+    verify_tls12_signature(message, cert, dss, &self.algorithms)
+  }
+
+  fn verify_tls13_signature(
+    &self,
+    message: &[u8],
+    cert: &CertificateDer<'_>,
+    dss: &DigitallySignedStruct,
+  ) -> Result<HandshakeSignatureValid, rustls::Error> {
+    verify_tls13_signature(message, cert, dss, &self.algorithms)
+  }
+
+  fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+    self.algorithms.supported_schemes()
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::generate_mutual_tls_pair;
+  use rustls::pki_types::ServerName;
+  use std::sync::Arc;
+  use tokio_rustls::{TlsAcceptor, TlsConnector};
+
+  #[tokio::test]
+  async fn test_mutual_tls_pinning() {
+    let (server, client) = generate_mutual_tls_pair(&["localhost"]).unwrap();
+    let (_, other_client) = generate_mutual_tls_pair(&["localhost"]).unwrap();
+    let handshake = |client_config: rustls::ClientConfig| {
+      let acceptor = TlsAcceptor::from(Arc::new(server.server_config().unwrap()));
+      let connector = TlsConnector::from(Arc::new(client_config));
+      async move {
+        let (client_stream, server_stream) = tokio::io::duplex(16384);
+        let name = ServerName::try_from("localhost").unwrap();
+        let (client_result, server_result) = tokio::join!(
+          connector.connect(name, client_stream),
+          acceptor.accept(server_stream)
+        );
+        client_result.is_ok() && server_result.is_ok()
+      }
+    };
+    assert!(handshake(client.client_config().unwrap()).await);
+    // A client from another pair has the wrong certificate, and trusts the wrong server.
+    assert!(!handshake(other_client.client_config().unwrap()).await);
+  }
+}
@@ -6,6 +6,10 @@
 // for full license information.
 
 //! Buttplug Error Structs/Enums, representing protocol errors.
+//!
+//! The [Display](std::fmt::Display) implementations on these types are English strings meant for
+//! logs. Front-ends that show errors to users should use [ButtplugError::details], which provides
+//! a stable code and named parameters that can be looked up in a localized message catalog.
 
 use super::message::{
   self,
@@ -13,6 +17,7 @@ use super::message::{
   ActuatorType,
   ButtplugDeviceMessageType,
   ButtplugMessageSpecVersion,
+  DeviceAxis,
   Endpoint,
   ErrorCode,
   FeatureType,
@@ -21,16 +26,48 @@ use super::message::{
 #[cfg(feature = "server")]
 use crate::server::device::hardware::communication::HardwareSpecificError;
 use displaydoc::Display;
+#[cfg(feature = "async-core")]
 use futures::future::BoxFuture;
+use getset::{CopyGetters, Getters};
+#[cfg(feature = "serialize-json")]
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use thiserror::Error;
 
 pub type ButtplugResult<T = ()> = Result<T, ButtplugError>;
 
+/// Locale independent representation of an error.
+///
+/// `code` is a stable, dot separated identifier (e.g. `device.not_connected`) that will not change
+/// between library versions, and `params` holds the values that the English
+/// [Display](std::fmt::Display) output would have interpolated, keyed by name.
+#[derive(Debug, Clone, PartialEq, Eq, Getters, CopyGetters)]
+pub struct ButtplugErrorDetails {
+  #[getset(get_copy = "pub")]
+  code: &'static str,
+  #[getset(get = "pub")]
+  params: BTreeMap<&'static str, String>,
+}
+
+impl ButtplugErrorDetails {
+  fn new(code: &'static str, params: Vec<(&'static str, String)>) -> Self {
+    Self {
+      code,
+      params: params.into_iter().collect(),
+    }
+  }
+
+  /// Get a single parameter value by name.
+  pub fn param(&self, name: &str) -> Option<&str> {
+    self.params.get(name).map(|value| value.as_str())
+  }
+}
+
 /// Handshake errors occur while a client is connecting to a server. This
 /// usually involves protocol handshake errors. For connector errors (i.e. when
 /// a remote network connection cannot be established), see
 /// [crate::connector::ButtplugConnectorError].
+#[cfg(feature = "async-core")]
 impl<T> From<ButtplugHandshakeError> for BoxFuture<'static, Result<T, ButtplugError>>
 where
   T: Send + 'static,
@@ -55,8 +92,38 @@ pub enum ButtplugHandshakeError {
   UntypedDeserializedError(String),
 }
 
+impl ButtplugHandshakeError {
+  pub fn details(&self) -> ButtplugErrorDetails {
+    match self {
+      Self::UnexpectedHandshakeMessageReceived(message) => ButtplugErrorDetails::new(
+        "handshake.unexpected_message",
+        vec![("message", message.clone())],
+      ),
+      Self::RequestServerInfoExpected => {
+        ButtplugErrorDetails::new("handshake.request_server_info_expected", vec![])
+      }
+      Self::HandshakeAlreadyHappened => {
+        ButtplugErrorDetails::new("handshake.already_happened", vec![])
+      }
+      Self::MessageSpecVersionMismatch(server_version, client_version) => {
+        ButtplugErrorDetails::new(
+          "handshake.spec_version_mismatch",
+          vec![
+            ("server_version", server_version.to_string()),
+            ("client_version", client_version.to_string()),
+          ],
+        )
+      }
+      Self::UntypedDeserializedError(message) => {
+        ButtplugErrorDetails::new("handshake.untyped", vec![("message", message.clone())])
+      }
+    }
+  }
+}
+
 /// Message errors occur when a message is somehow malformed on creation, or
 /// received unexpectedly by a client or server.
+#[cfg(feature = "async-core")]
 impl<T> From<ButtplugMessageError> for BoxFuture<'static, Result<T, ButtplugError>>
 where
   T: Send + 'static,
@@ -84,13 +151,58 @@ pub enum ButtplugMessageError {
   /// Message serialization error
   #[error(transparent)]
   MessageSerializationError(#[from] ButtplugSerializerError),
+  /// Observer clients cannot send {0} messages
+  ObserverNotAllowed(String),
   /// Untyped Deserialized Error: {0}
   UntypedDeserializedError(String),
 }
 
+impl ButtplugMessageError {
+  pub fn details(&self) -> ButtplugErrorDetails {
+    match self {
+      Self::UnexpectedMessageType(message_type) => ButtplugErrorDetails::new(
+        "message.unexpected_type",
+        vec![("message_type", message_type.clone())],
+      ),
+      Self::VersionError(message_type, from_version, to_version) => ButtplugErrorDetails::new(
+        "message.version_conversion",
+        vec![
+          ("message_type", message_type.clone()),
+          ("from_version", from_version.clone()),
+          ("to_version", to_version.clone()),
+        ],
+      ),
+      Self::MessageConversionError(reason) => {
+        ButtplugErrorDetails::new("message.conversion", vec![("reason", reason.clone())])
+      }
+      Self::InvalidMessageContents(reason) => {
+        ButtplugErrorDetails::new("message.invalid_contents", vec![("reason", reason.clone())])
+      }
+      Self::UnhandledMessage(message_type) => ButtplugErrorDetails::new(
+        "message.unhandled",
+        vec![("message_type", message_type.clone())],
+      ),
+      Self::ValidationError(reason) => {
+        ButtplugErrorDetails::new("message.validation", vec![("reason", reason.clone())])
+      }
+      Self::MessageSerializationError(err) => {
+        ButtplugErrorDetails::new("message.serialization", vec![("reason", err.to_string())])
+      }
+      Self::ObserverNotAllowed(message_type) => ButtplugErrorDetails::new(
+        "message.observer_not_allowed",
+        vec![("message_type", message_type.clone())],
+      ),
+      Self::UntypedDeserializedError(message) => {
+        ButtplugErrorDetails::new("message.untyped", vec![("message", message.clone())])
+      }
+    }
+  }
+}
+
 /// Ping errors occur when a server requires a ping response (set up during
 /// connection handshake), and the client does not return a response in the
 /// alloted timeframe. This also signifies a server disconnect.
+#[cfg(feature = "async-core")]
 impl<T> From<ButtplugPingError> for BoxFuture<'static, Result<T, ButtplugError>>
 where
   T: Send + 'static,
@@ -113,9 +225,23 @@ pub enum ButtplugPingError {
   UntypedDeserializedError(String),
 }
 
+impl ButtplugPingError {
+  pub fn details(&self) -> ButtplugErrorDetails {
+    match self {
+      Self::PingedOut => ButtplugErrorDetails::new("ping.pinged_out", vec![]),
+      Self::PingTimerNotRunning => ButtplugErrorDetails::new("ping.timer_not_running", vec![]),
+      Self::InvalidPingTimeout => ButtplugErrorDetails::new("ping.invalid_timeout", vec![]),
+      Self::UntypedDeserializedError(message) => {
+        ButtplugErrorDetails::new("ping.untyped", vec![("message", message.clone())])
+      }
+    }
+  }
+}
+
 /// Device errors occur during device interactions, including sending
 /// unsupported message commands, addressing the wrong number of device
 /// attributes, etc...
+#[cfg(feature = "async-core")]
 impl<T> From<ButtplugDeviceError> for BoxFuture<'static, Result<T, ButtplugError>>
 where
   T: Send + 'static,
@@ -180,10 +306,204 @@ pub enum ButtplugDeviceError {
   DeviceSensorTypeMismatch(u32, SensorType, FeatureType),
   /// Protocol does not have an implementation available for Sensor Type {0}
   ProtocolSensorNotSupported(SensorType),
+  /// Device {0} is in use by a pattern session
+  DeviceInPatternSession(u32),
+  /// No pattern session with id {0} is running
+  PatternSessionNotFound(u32),
+  /// Device {0} has no {1} axis
+  DeviceAxisNotFound(String, DeviceAxis),
+  /// Device {0} is missing the services it should have, which usually means the OS has a stale copy
+  /// cached from older firmware. Remove and re-pair the device to clear it.
+  DeviceServiceCacheStale(String),
+  /// Device {0} was removed before the command could complete
+  DeviceDisconnected(u32),
+  /// Cannot calibrate sensor at feature index {0}: {1}
+  SensorCalibrationError(u32, String),
+  /// Device {0} is cooling down after using up its energy budget
+  DeviceCoolingDown(u32),
+  /// {0} subcommand {1} has a value of {2}, but values must be between 0.0 and 1.0
+  DeviceSubcommandValueOutOfRange(ButtplugDeviceMessageType, u32, String),
+  /// {0} subcommand {1} addresses feature {2}, but device only has {3} features
+  DeviceSubcommandFeatureIndexError(ButtplugDeviceMessageType, u32, u32, u32),
+  /// {0} subcommand {1} addresses feature {2}, which does not take {0}
+  DeviceSubcommandFeatureMismatch(ButtplugDeviceMessageType, u32, u32),
+}
+
+impl ButtplugDeviceError {
+  pub fn details(&self) -> ButtplugErrorDetails {
+    match self {
+      Self::DeviceNotConnected(device) => {
+        ButtplugErrorDetails::new("device.not_connected", vec![("device", device.clone())])
+      }
+      Self::MessageNotSupported(message_type) => ButtplugErrorDetails::new(
+        "device.message_not_supported",
+        vec![("message_type", message_type.to_string())],
+      ),
+      Self::DeviceFeatureCountMismatch(feature_count, command_count) => ButtplugErrorDetails::new(
+        "device.feature_count_mismatch",
+        vec![
+          ("feature_count", feature_count.to_string()),
+          ("command_count", command_count.to_string()),
+        ],
+      ),
+      Self::DeviceFeatureIndexError(feature_count, index) => ButtplugErrorDetails::new(
+        "device.feature_index",
+        vec![
+          ("feature_count", feature_count.to_string()),
+          ("index", index.to_string()),
+        ],
+      ),
+      Self::DeviceSensorIndexError(sensor_count, index) => ButtplugErrorDetails::new(
+        "device.sensor_index",
+        vec![
+          ("sensor_count", sensor_count.to_string()),
+          ("index", index.to_string()),
+        ],
+      ),
+      Self::DeviceConnectionError(reason) => {
+        ButtplugErrorDetails::new("device.connection", vec![("reason", reason.clone())])
+      }
+      Self::DeviceCommunicationError(reason) => {
+        ButtplugErrorDetails::new("device.communication", vec![("reason", reason.clone())])
+      }
+      Self::InvalidEndpoint(endpoint) => ButtplugErrorDetails::new(
+        "device.invalid_endpoint",
+        vec![("endpoint", endpoint.to_string())],
+      ),
+      Self::UnhandledCommand(command) => ButtplugErrorDetails::new(
+        "device.unhandled_command",
+        vec![("command", command.clone())],
+      ),
+      Self::DeviceSpecificError(err) => ButtplugErrorDetails::new(
+        "device.hardware_specific",
+        vec![("reason", err.to_string())],
+      ),
+      Self::DeviceNotAvailable(index) => {
+        ButtplugErrorDetails::new("device.not_available", vec![("index", index.to_string())])
+      }
+      Self::DeviceScanningAlreadyStarted => {
+        ButtplugErrorDetails::new("device.scanning_already_started", vec![])
+      }
+      Self::DeviceScanningAlreadyStopped => {
+        ButtplugErrorDetails::new("device.scanning_already_stopped", vec![])
+      }
+      Self::DevicePermissionError(reason) => {
+        ButtplugErrorDetails::new("device.permission", vec![("reason", reason.clone())])
+      }
+      Self::ProtocolAttributesNotFound(reason) => ButtplugErrorDetails::new(
+        "device.protocol_attributes_not_found",
+        vec![("reason", reason.clone())],
+      ),
+      Self::ProtocolNotImplemented(protocol) => ButtplugErrorDetails::new(
+        "device.protocol_not_implemented",
+        vec![("protocol", protocol.clone())],
+      ),
+      Self::ProtocolSpecificError(protocol, reason) => ButtplugErrorDetails::new(
+        "device.protocol_specific",
+        vec![("protocol", protocol.clone()), ("reason", reason.clone())],
+      ),
+      Self::ProtocolRequirementError(reason) => ButtplugErrorDetails::new(
+        "device.protocol_requirement",
+        vec![("reason", reason.clone())],
+      ),
+      Self::ProtocolAlreadyAdded(protocol) => ButtplugErrorDetails::new(
+        "device.protocol_already_added",
+        vec![("protocol", protocol.clone())],
+      ),
+      Self::UntypedDeserializedError(message) => {
+        ButtplugErrorDetails::new("device.untyped", vec![("message", message.clone())])
+      }
+      Self::DeviceConfigurationError(reason) => {
+        ButtplugErrorDetails::new("device.configuration", vec![("reason", reason.clone())])
+      }
+      Self::DeviceActuatorTypeMismatch(index, actuator_type, feature_type) => {
+        ButtplugErrorDetails::new(
+          "device.actuator_type_mismatch",
+          vec![
+            ("index", index.clone()),
+            ("actuator_type", actuator_type.to_string()),
+            ("feature_type", feature_type.to_string()),
+          ],
+        )
+      }
+      Self::DeviceSensorTypeMismatch(index, sensor_type, feature_type) => {
+        ButtplugErrorDetails::new(
+          "device.sensor_type_mismatch",
+          vec![
+            ("index", index.to_string()),
+            ("sensor_type", sensor_type.to_string()),
+            ("feature_type", feature_type.to_string()),
+          ],
+        )
+      }
+      Self::ProtocolSensorNotSupported(sensor_type) => ButtplugErrorDetails::new(
+        "device.protocol_sensor_not_supported",
+        vec![("sensor_type", sensor_type.to_string())],
+      ),
+      Self::DeviceInPatternSession(index) => ButtplugErrorDetails::new(
+        "device.in_pattern_session",
+        vec![("index", index.to_string())],
+      ),
+      Self::PatternSessionNotFound(session_id) => ButtplugErrorDetails::new(
+        "device.pattern_session_not_found",
+        vec![("session_id", session_id.to_string())],
+      ),
+      Self::DeviceAxisNotFound(device, axis) => ButtplugErrorDetails::new(
+        "device.axis_not_found",
+        vec![("device", device.clone()), ("axis", axis.to_string())],
+      ),
+      Self::DeviceServiceCacheStale(device) => ButtplugErrorDetails::new(
+        "device.service_cache_stale",
+        vec![("device", device.clone())],
+      ),
+      Self::DeviceDisconnected(index) => {
+        ButtplugErrorDetails::new("device.disconnected", vec![("index", index.to_string())])
+      }
+      Self::SensorCalibrationError(index, reason) => ButtplugErrorDetails::new(
+        "device.sensor_calibration",
+        vec![("index", index.to_string()), ("reason", reason.clone())],
+      ),
+      Self::DeviceCoolingDown(index) => {
+        ButtplugErrorDetails::new("device.cooling_down", vec![("index", index.to_string())])
+      }
+      Self::DeviceSubcommandValueOutOfRange(message_type, subcommand, value) => {
+        ButtplugErrorDetails::new(
+          "device.subcommand_value_out_of_range",
+          vec![
+            ("message_type", message_type.to_string()),
+            ("subcommand", subcommand.to_string()),
+            ("value", value.clone()),
+          ],
+        )
+      }
+      Self::DeviceSubcommandFeatureIndexError(message_type, subcommand, index, feature_count) => {
+        ButtplugErrorDetails::new(
+          "device.subcommand_feature_index",
+          vec![
+            ("message_type", message_type.to_string()),
+            ("subcommand", subcommand.to_string()),
+            ("index", index.to_string()),
+            ("feature_count", feature_count.to_string()),
+          ],
+        )
+      }
+      Self::DeviceSubcommandFeatureMismatch(message_type, subcommand, index) => {
+        ButtplugErrorDetails::new(
+          "device.subcommand_feature_mismatch",
+          vec![
+            ("message_type", message_type.to_string()),
+            ("subcommand", subcommand.to_string()),
+            ("index", index.to_string()),
+          ],
+        )
+      }
+    }
+  }
 }
 
 /// Unknown errors occur in exceptional circumstances where no other error type
 /// will suffice. These are rare and usually fatal (disconnecting) errors.
+#[cfg(feature = "async-core")]
 impl<T> From<ButtplugUnknownError> for BoxFuture<'static, Result<T, ButtplugError>>
 where
   T: Send + 'static,
@@ -206,6 +526,26 @@ pub enum ButtplugUnknownError {
   DeviceManagerNotRunning,
 }
 
+impl ButtplugUnknownError {
+  pub fn details(&self) -> ButtplugErrorDetails {
+    match self {
+      Self::NoDeviceCommManagers => {
+        ButtplugErrorDetails::new("unknown.no_device_comm_managers", vec![])
+      }
+      Self::UnexpectedType(type_name) => ButtplugErrorDetails::new(
+        "unknown.unexpected_type",
+        vec![("type_name", type_name.clone())],
+      ),
+      Self::UntypedDeserializedError(message) => {
+        ButtplugErrorDetails::new("unknown.untyped", vec![("message", message.clone())])
+      }
+      Self::DeviceManagerNotRunning => {
+        ButtplugErrorDetails::new("unknown.device_manager_not_running", vec![])
+      }
+    }
+  }
+}
+
 /// Aggregation enum for protocol error types.
 #[derive(Debug, Error, Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
@@ -222,6 +562,19 @@ pub enum ButtplugError {
   ButtplugUnknownError(#[from] ButtplugUnknownError),
 }
 
+impl ButtplugError {
+  /// Code and parameters for this error, for front-ends that localize error messages.
+  pub fn details(&self) -> ButtplugErrorDetails {
+    match self {
+      Self::ButtplugHandshakeError(err) => err.details(),
+      Self::ButtplugMessageError(err) => err.details(),
+      Self::ButtplugPingError(err) => err.details(),
+      Self::ButtplugDeviceError(err) => err.details(),
+      Self::ButtplugUnknownError(err) => err.details(),
+    }
+  }
+}
+
 impl From<message::ErrorV0> for ButtplugError {
   /// Turns a Buttplug Protocol Error Message [super::messages::Error] into a [ButtplugError] type.
   fn from(error: message::ErrorV0) -> Self {
@@ -244,3 +597,140 @@ impl From<message::ErrorV0> for ButtplugError {
     }
   }
 }
+
+#[cfg(test)]
+mod test {
+  use super::{
+    ButtplugDeviceError,
+    ButtplugError,
+    ButtplugErrorDetails,
+    ButtplugHandshakeError,
+    ButtplugMessageError,
+    ButtplugPingError,
+    ButtplugUnknownError,
+  };
+  use crate::core::message::ButtplugMessageSpecVersion;
+  #[cfg(feature = "serialize-json")]
+  use crate::core::message::ErrorV0;
+
+  // Front-ends key their translations off these, so changing any of them is a breaking change.
+  fn assert_details(details: ButtplugErrorDetails, code: &str, params: &[(&str, &str)]) {
+    assert_eq!(details.code(), code);
+    let actual: Vec<(&str, &str)> = details
+      .params()
+      .iter()
+      .map(|(name, value)| (*name, value.as_str()))
+      .collect();
+    let mut expected = params.to_vec();
+    expected.sort();
+    assert_eq!(actual, expected);
+  }
+
+  #[test]
+  fn test_handshake_error_details() {
+    assert_details(
+      ButtplugHandshakeError::HandshakeAlreadyHappened.details(),
+      "handshake.already_happened",
+      &[],
+    );
+    let server_version = ButtplugMessageSpecVersion::Version2.to_string();
+    let client_version = ButtplugMessageSpecVersion::Version3.to_string();
+    assert_details(
+      ButtplugHandshakeError::MessageSpecVersionMismatch(
+        ButtplugMessageSpecVersion::Version2,
+        ButtplugMessageSpecVersion::Version3,
+      )
+      .details(),
+      "handshake.spec_version_mismatch",
+      &[
+        ("server_version", &server_version),
+        ("client_version", &client_version),
+      ],
+    );
+  }
+
+  #[test]
+  fn test_message_error_details() {
+    assert_details(
+      ButtplugMessageError::UnexpectedMessageType("Ok".to_owned()).details(),
+      "message.unexpected_type",
+      &[("message_type", "Ok")],
+    );
+    assert_details(
+      ButtplugMessageError::VersionError("Ok".to_owned(), "3".to_owned(), "2".to_owned()).details(),
+      "message.version_conversion",
+      &[
+        ("message_type", "Ok"),
+        ("from_version", "3"),
+        ("to_version", "2"),
+      ],
+    );
+  }
+
+  #[test]
+  fn test_ping_error_details() {
+    assert_details(
+      ButtplugPingError::PingedOut.details(),
+      "ping.pinged_out",
+      &[],
+    );
+    assert_details(
+      ButtplugPingError::UntypedDeserializedError("Gone".to_owned()).details(),
+      "ping.untyped",
+      &[("message", "Gone")],
+    );
+  }
+
+  #[test]
+  fn test_device_error_details() {
+    assert_details(
+      ButtplugDeviceError::DeviceNotConnected("Lovense Hush".to_owned()).details(),
+      "device.not_connected",
+      &[("device", "Lovense Hush")],
+    );
+    assert_details(
+      ButtplugDeviceError::DeviceFeatureIndexError(2, 5).details(),
+      "device.feature_index",
+      &[("feature_count", "2"), ("index", "5")],
+    );
+  }
+
+  #[test]
+  fn test_unknown_error_details() {
+    assert_details(
+      ButtplugUnknownError::NoDeviceCommManagers.details(),
+      "unknown.no_device_comm_managers",
+      &[],
+    );
+    assert_details(
+      ButtplugUnknownError::UnexpectedType("Foo".to_owned()).details(),
+      "unknown.unexpected_type",
+      &[("type_name", "Foo")],
+    );
+  }
+
+  #[test]
+  fn test_wrapped_error_details_match_inner() {
+    let inner = ButtplugPingError::PingTimerNotRunning;
+    assert_eq!(
+      ButtplugError::from(inner.clone()).details(),
+      inner.details()
+    );
+  }
+
+  #[cfg(feature = "serialize-json")]
+  #[test]
+  fn test_error_details_survive_error_message() {
+    let err: ButtplugError =
+      ButtplugDeviceError::ProtocolSpecificError("lovense".to_owned(), "Bad reply".to_owned())
+        .into();
+    let mut error_msg = ErrorV0::from(err.clone());
+    // Simulate the message crossing a remote connection, where the original error is lost.
+    error_msg = serde_json::from_str(&serde_json::to_string(&error_msg).unwrap()).unwrap();
+    let details = error_msg.original_error().details();
+    assert_eq!(details, err.details());
+    assert_eq!(details.code(), "device.protocol_specific");
+    assert_eq!(details.param("protocol"), Some("lovense"));
+    assert_eq!(details.param("reason"), Some("Bad reply"));
+  }
+}
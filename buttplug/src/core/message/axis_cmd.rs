@@ -0,0 +1,74 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2024 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+use super::*;
+use getset::{CopyGetters, Getters};
+#[cfg(feature = "serialize-json")]
+use serde::{Deserialize, Serialize};
+
+/// Move one axis of a multi-axis device to a certain position in a certain amount of time
+#[derive(Debug, PartialEq, Clone, CopyGetters)]
+#[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
+#[getset(get_copy = "pub")]
+pub struct AxisSubcommandV4 {
+  #[cfg_attr(feature = "serialize-json", serde(rename = "Axis"))]
+  axis: DeviceAxis,
+  #[cfg_attr(feature = "serialize-json", serde(rename = "Duration"))]
+  duration: u32,
+  #[cfg_attr(feature = "serialize-json", serde(rename = "Position"))]
+  position: f64,
+}
+
+impl AxisSubcommandV4 {
+  pub fn new(axis: DeviceAxis, duration: u32, position: f64) -> Self {
+    Self {
+      axis,
+      duration,
+      position,
+    }
+  }
+}
+
+/// Move axes of a multi-axis device, addressed by name instead of feature index. All axes in the
+/// message are meant to move together.
+#[derive(Debug, ButtplugDeviceMessage, ButtplugMessageFinalizer, PartialEq, Clone, Getters)]
+#[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
+pub struct AxisCmdV4 {
+  #[cfg_attr(feature = "serialize-json", serde(rename = "Id"))]
+  id: u32,
+  #[cfg_attr(feature = "serialize-json", serde(rename = "DeviceIndex"))]
+  device_index: u32,
+  #[cfg_attr(feature = "serialize-json", serde(rename = "Axes"))]
+  #[getset(get = "pub")]
+  axes: Vec<AxisSubcommandV4>,
+}
+
+impl AxisCmdV4 {
+  pub fn new(device_index: u32, axes: Vec<AxisSubcommandV4>) -> Self {
+    Self {
+      id: 1,
+      device_index,
+      axes,
+    }
+  }
+}
+
+impl ButtplugMessageValidator for AxisCmdV4 {
+  fn is_valid(&self) -> Result<(), ButtplugMessageError> {
+    self.is_not_system_id(self.id)?;
+    for axis in &self.axes {
+      self.is_in_command_range(
+        axis.position,
+        format!(
+          "AxisSubcommand position {} for axis {} is invalid, should be between 0.0 and 1.0",
+          axis.position, axis.axis
+        ),
+      )?;
+    }
+    Ok(())
+  }
+}
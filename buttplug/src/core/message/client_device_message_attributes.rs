@@ -10,6 +10,7 @@ use crate::core::{
   message::{ButtplugDeviceMessageType, Endpoint},
 };
 use getset::{Getters, MutGetters, Setters};
+#[cfg(feature = "serialize-json")]
 use serde::{ser::SerializeSeq, Deserialize, Serialize, Serializer};
 use std::ops::RangeInclusive;
 
@@ -20,7 +21,8 @@ use super::{
   FeatureType,
 };
 
-#[derive(Debug, Display, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
 pub enum ActuatorType {
   Unknown,
   Vibrate,
@@ -32,6 +34,8 @@ pub enum ActuatorType {
   // For instances where we specify a position to move to ASAP. Usually servos, probably for the
   // OSR-2/SR-6.
   Position,
+  // Electrical stimulation intensity.
+  Estim,
 }
 
 impl TryFrom<FeatureType> for ActuatorType {
@@ -45,6 +49,7 @@ impl TryFrom<FeatureType> for ActuatorType {
       FeatureType::Constrict => Ok(ActuatorType::Constrict),
       FeatureType::Inflate => Ok(ActuatorType::Inflate),
       FeatureType::Position => Ok(ActuatorType::Position),
+      FeatureType::Estim => Ok(ActuatorType::Estim),
       _ => Err(format!(
         "Feature type {value} not valid for ActuatorType conversion"
       )),
@@ -52,13 +57,16 @@ impl TryFrom<FeatureType> for ActuatorType {
   }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Display)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display)]
+#[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
 pub enum SensorType {
   Unknown,
   Battery,
   RSSI,
   Button,
   Pressure,
+  Velocity,
+  Position,
   // Temperature,
   // Accelerometer,
   // Gyro,
@@ -73,6 +81,8 @@ impl TryFrom<FeatureType> for SensorType {
       FeatureType::RSSI => Ok(SensorType::RSSI),
       FeatureType::Button => Ok(SensorType::Button),
       FeatureType::Pressure => Ok(SensorType::Pressure),
+      FeatureType::Velocity => Ok(SensorType::Velocity),
+      FeatureType::Position => Ok(SensorType::Position),
       _ => Err(format!(
         "Feature type {value} not valid for SensorType conversion"
       )),
@@ -93,61 +103,85 @@ impl TryFrom<FeatureType> for SensorType {
 pub struct ClientDeviceMessageAttributesV3 {
   // Generic commands
   #[getset(get = "pub", get_mut = "pub(super)")]
-  #[serde(rename = "ScalarCmd")]
-  #[serde(skip_serializing_if = "Option::is_none")]
+  #[cfg_attr(feature = "serialize-json", serde(rename = "ScalarCmd"))]
+  #[cfg_attr(
+    feature = "serialize-json",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
   scalar_cmd: Option<Vec<ClientGenericDeviceMessageAttributesV3>>,
   #[getset(get = "pub", get_mut = "pub(super)")]
-  #[serde(rename = "RotateCmd")]
-  #[serde(skip_serializing_if = "Option::is_none")]
+  #[cfg_attr(feature = "serialize-json", serde(rename = "RotateCmd"))]
+  #[cfg_attr(
+    feature = "serialize-json",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
   rotate_cmd: Option<Vec<ClientGenericDeviceMessageAttributesV3>>,
   #[getset(get = "pub", get_mut = "pub(super)")]
-  #[serde(rename = "LinearCmd")]
-  #[serde(skip_serializing_if = "Option::is_none")]
+  #[cfg_attr(feature = "serialize-json", serde(rename = "LinearCmd"))]
+  #[cfg_attr(
+    feature = "serialize-json",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
   linear_cmd: Option<Vec<ClientGenericDeviceMessageAttributesV3>>,
 
   // Sensor Messages
   #[getset(get = "pub")]
-  #[serde(rename = "SensorReadCmd")]
-  #[serde(skip_serializing_if = "Option::is_none")]
+  #[cfg_attr(feature = "serialize-json", serde(rename = "SensorReadCmd"))]
+  #[cfg_attr(
+    feature = "serialize-json",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
   sensor_read_cmd: Option<Vec<SensorDeviceMessageAttributesV3>>,
   #[getset(get = "pub")]
-  #[serde(rename = "SensorSubscribeCmd")]
-  #[serde(skip_serializing_if = "Option::is_none")]
+  #[cfg_attr(feature = "serialize-json", serde(rename = "SensorSubscribeCmd"))]
+  #[cfg_attr(
+    feature = "serialize-json",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
   sensor_subscribe_cmd: Option<Vec<SensorDeviceMessageAttributesV3>>,
 
   // StopDeviceCmd always exists
   #[getset(get = "pub")]
-  #[serde(rename = "StopDeviceCmd")]
-  #[serde(skip_deserializing)]
+  #[cfg_attr(feature = "serialize-json", serde(rename = "StopDeviceCmd"))]
+  #[cfg_attr(feature = "serialize-json", serde(skip_deserializing))]
   stop_device_cmd: NullDeviceMessageAttributesV1,
 
   // Raw commands are only added post-serialization
   #[getset(get = "pub")]
-  #[serde(rename = "RawReadCmd")]
-  #[serde(skip_deserializing)]
-  #[serde(skip_serializing_if = "Option::is_none")]
+  #[cfg_attr(feature = "serialize-json", serde(rename = "RawReadCmd"))]
+  #[cfg_attr(feature = "serialize-json", serde(skip_deserializing))]
+  #[cfg_attr(
+    feature = "serialize-json",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
   raw_read_cmd: Option<RawDeviceMessageAttributesV2>,
   // Raw commands are only added post-serialization
   #[getset(get = "pub")]
-  #[serde(rename = "RawWriteCmd")]
-  #[serde(skip_deserializing)]
-  #[serde(skip_serializing_if = "Option::is_none")]
+  #[cfg_attr(feature = "serialize-json", serde(rename = "RawWriteCmd"))]
+  #[cfg_attr(feature = "serialize-json", serde(skip_deserializing))]
+  #[cfg_attr(
+    feature = "serialize-json",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
   raw_write_cmd: Option<RawDeviceMessageAttributesV2>,
   // Raw commands are only added post-serialization
   #[getset(get = "pub")]
-  #[serde(rename = "RawSubscribeCmd")]
-  #[serde(skip_deserializing)]
-  #[serde(skip_serializing_if = "Option::is_none")]
+  #[cfg_attr(feature = "serialize-json", serde(rename = "RawSubscribeCmd"))]
+  #[cfg_attr(feature = "serialize-json", serde(skip_deserializing))]
+  #[cfg_attr(
+    feature = "serialize-json",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
   raw_subscribe_cmd: Option<RawDeviceMessageAttributesV2>,
 
   // Needed to load from config for fallback, but unused here.
   #[getset(get = "pub")]
-  #[serde(rename = "FleshlightLaunchFW12Cmd")]
-  #[serde(skip_serializing)]
+  #[cfg_attr(feature = "serialize-json", serde(rename = "FleshlightLaunchFW12Cmd"))]
+  #[cfg_attr(feature = "serialize-json", serde(skip_serializing))]
   fleshlight_launch_fw12_cmd: Option<NullDeviceMessageAttributesV1>,
   #[getset(get = "pub")]
-  #[serde(rename = "VorzeA10CycloneCmd")]
-  #[serde(skip_serializing)]
+  #[cfg_attr(feature = "serialize-json", serde(rename = "VorzeA10CycloneCmd"))]
+  #[cfg_attr(feature = "serialize-json", serde(skip_serializing))]
   vorze_a10_cyclone_cmd: Option<NullDeviceMessageAttributesV1>,
 }
 
@@ -231,6 +265,8 @@ impl ClientDeviceMessageAttributesV3 {
       ButtplugDeviceMessageType::SensorUnsubscribeCmd => self.sensor_subscribe_cmd.is_some(),
       ButtplugDeviceMessageType::LinearCmd => self.linear_cmd.is_some(),
       ButtplugDeviceMessageType::RotateCmd => self.rotate_cmd.is_some(),
+      // Axes only exist in v4 feature lists.
+      ButtplugDeviceMessageType::AxisCmd => false,
       ButtplugDeviceMessageType::BatteryLevelCmd => {
         if let Some(sensor_info) = &self.sensor_read_cmd {
           sensor_info
@@ -334,28 +370,31 @@ impl ClientDeviceMessageAttributesV3Builder {
   }
 }
 
-#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
 pub struct NullDeviceMessageAttributesV1 {}
 
+#[cfg(feature = "serialize-json")]
 fn unspecified_feature() -> String {
   "N/A".to_string()
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Getters, Setters)]
+#[derive(Clone, Debug, PartialEq, Eq, Getters, Setters)]
+#[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
 pub struct ClientGenericDeviceMessageAttributesV3 {
   #[getset(get = "pub")]
-  #[serde(rename = "FeatureDescriptor")]
-  #[serde(default = "unspecified_feature")]
+  #[cfg_attr(feature = "serialize-json", serde(rename = "FeatureDescriptor"))]
+  #[cfg_attr(feature = "serialize-json", serde(default = "unspecified_feature"))]
   feature_descriptor: String,
   #[getset(get = "pub")]
-  #[serde(rename = "ActuatorType")]
+  #[cfg_attr(feature = "serialize-json", serde(rename = "ActuatorType"))]
   actuator_type: ActuatorType,
-  #[serde(rename = "StepCount")]
+  #[cfg_attr(feature = "serialize-json", serde(rename = "StepCount"))]
   #[getset(get = "pub")]
   step_count: u32,
   // TODO This needs to actually be part of the device info relayed to the client in spec v4.
   #[getset(get = "pub")]
-  #[serde(skip, default)]
+  #[cfg_attr(feature = "serialize-json", serde(skip, default))]
   index: u32,
 }
 
@@ -398,10 +437,11 @@ impl ClientGenericDeviceMessageAttributesV3 {
   }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Default, Serialize, Deserialize, Getters, Setters)]
+#[derive(Clone, Debug, PartialEq, Eq, Default, Getters, Setters)]
+#[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
 pub struct RawDeviceMessageAttributesV2 {
   #[getset(get = "pub")]
-  #[serde(rename = "Endpoints")]
+  #[cfg_attr(feature = "serialize-json", serde(rename = "Endpoints"))]
   endpoints: Vec<Endpoint>,
 }
 
@@ -413,6 +453,7 @@ impl RawDeviceMessageAttributesV2 {
   }
 }
 
+#[cfg(feature = "serialize-json")]
 fn range_sequence_serialize<S>(
   range_vec: &Vec<RangeInclusive<i32>>,
   serializer: S,
@@ -427,20 +468,24 @@ where
   seq.end()
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Getters, Setters)]
+#[derive(Clone, Debug, PartialEq, Eq, Getters, Setters)]
+#[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
 pub struct SensorDeviceMessageAttributesV3 {
   #[getset(get = "pub")]
-  #[serde(rename = "FeatureDescriptor")]
+  #[cfg_attr(feature = "serialize-json", serde(rename = "FeatureDescriptor"))]
   feature_descriptor: String,
   #[getset(get = "pub")]
-  #[serde(rename = "SensorType")]
+  #[cfg_attr(feature = "serialize-json", serde(rename = "SensorType"))]
   sensor_type: SensorType,
   #[getset(get = "pub")]
-  #[serde(rename = "SensorRange", serialize_with = "range_sequence_serialize")]
+  #[cfg_attr(
+    feature = "serialize-json",
+    serde(rename = "SensorRange", serialize_with = "range_sequence_serialize")
+  )]
   sensor_range: Vec<RangeInclusive<i32>>,
   // TODO This needs to actually be part of the device info relayed to the client in spec v4.
   #[getset(get = "pub")]
-  #[serde(skip, default)]
+  #[cfg_attr(feature = "serialize-json", serde(skip, default))]
   index: u32,
 }
 
@@ -468,63 +513,91 @@ impl SensorDeviceMessageAttributes {
 }
  */
 
-#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Getters, Setters)]
+#[derive(Clone, Debug, PartialEq, Eq, Getters, Setters)]
+#[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
 pub struct ClientDeviceMessageAttributesV2 {
   // Generic commands
   #[getset(get = "pub")]
-  #[serde(rename = "VibrateCmd")]
-  #[serde(skip_serializing_if = "Option::is_none")]
+  #[cfg_attr(feature = "serialize-json", serde(rename = "VibrateCmd"))]
+  #[cfg_attr(
+    feature = "serialize-json",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
   vibrate_cmd: Option<GenericDeviceMessageAttributesV2>,
   #[getset(get = "pub")]
-  #[serde(rename = "RotateCmd")]
-  #[serde(skip_serializing_if = "Option::is_none")]
+  #[cfg_attr(feature = "serialize-json", serde(rename = "RotateCmd"))]
+  #[cfg_attr(
+    feature = "serialize-json",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
   rotate_cmd: Option<GenericDeviceMessageAttributesV2>,
   #[getset(get = "pub")]
-  #[serde(rename = "LinearCmd")]
-  #[serde(skip_serializing_if = "Option::is_none")]
+  #[cfg_attr(feature = "serialize-json", serde(rename = "LinearCmd"))]
+  #[cfg_attr(
+    feature = "serialize-json",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
   linear_cmd: Option<GenericDeviceMessageAttributesV2>,
   #[getset(get = "pub")]
-  #[serde(rename = "BatteryLevelCmd")]
-  #[serde(skip_serializing_if = "Option::is_none")]
+  #[cfg_attr(feature = "serialize-json", serde(rename = "BatteryLevelCmd"))]
+  #[cfg_attr(
+    feature = "serialize-json",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
   battery_level_cmd: Option<NullDeviceMessageAttributesV1>,
 
   // RSSILevel is added post-serialization (only for bluetooth devices)
   #[getset(get = "pub")]
-  #[serde(rename = "RSSILevelCmd")]
-  #[serde(skip_serializing_if = "Option::is_none")]
+  #[cfg_attr(feature = "serialize-json", serde(rename = "RSSILevelCmd"))]
+  #[cfg_attr(
+    feature = "serialize-json",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
   rssi_level_cmd: Option<NullDeviceMessageAttributesV1>,
 
   // StopDeviceCmd always exists
   #[getset(get = "pub")]
-  #[serde(rename = "StopDeviceCmd")]
+  #[cfg_attr(feature = "serialize-json", serde(rename = "StopDeviceCmd"))]
   stop_device_cmd: NullDeviceMessageAttributesV1,
 
   // Raw commands are only added post-serialization
   #[getset(get = "pub")]
-  #[serde(rename = "RawReadCmd")]
-  #[serde(skip_serializing_if = "Option::is_none")]
+  #[cfg_attr(feature = "serialize-json", serde(rename = "RawReadCmd"))]
+  #[cfg_attr(
+    feature = "serialize-json",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
   raw_read_cmd: Option<RawDeviceMessageAttributesV2>,
   #[getset(get = "pub")]
-  #[serde(rename = "RawWriteCmd")]
-  #[serde(skip_serializing_if = "Option::is_none")]
+  #[cfg_attr(feature = "serialize-json", serde(rename = "RawWriteCmd"))]
+  #[cfg_attr(
+    feature = "serialize-json",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
   raw_write_cmd: Option<RawDeviceMessageAttributesV2>,
   #[getset(get = "pub")]
-  #[serde(rename = "RawSubscribeCmd")]
-  #[serde(skip_serializing_if = "Option::is_none")]
+  #[cfg_attr(feature = "serialize-json", serde(rename = "RawSubscribeCmd"))]
+  #[cfg_attr(
+    feature = "serialize-json",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
   raw_subscribe_cmd: Option<RawDeviceMessageAttributesV2>,
   #[getset(get = "pub")]
-  #[serde(rename = "RawUnsubscribeCmd")]
-  #[serde(skip_serializing_if = "Option::is_none")]
+  #[cfg_attr(feature = "serialize-json", serde(rename = "RawUnsubscribeCmd"))]
+  #[cfg_attr(
+    feature = "serialize-json",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
   raw_unsubscribe_cmd: Option<RawDeviceMessageAttributesV2>,
 
   // Needed to load from config for fallback, but unused here.
   #[getset(get = "pub")]
-  #[serde(rename = "FleshlightLaunchFW12Cmd")]
-  #[serde(skip)]
+  #[cfg_attr(feature = "serialize-json", serde(rename = "FleshlightLaunchFW12Cmd"))]
+  #[cfg_attr(feature = "serialize-json", serde(skip))]
   fleshlight_launch_fw12_cmd: Option<NullDeviceMessageAttributesV1>,
   #[getset(get = "pub")]
-  #[serde(rename = "VorzeA10CycloneCmd")]
-  #[serde(skip)]
+  #[cfg_attr(feature = "serialize-json", serde(rename = "VorzeA10CycloneCmd"))]
+  #[cfg_attr(feature = "serialize-json", serde(skip))]
   vorze_a10_cyclone_cmd: Option<NullDeviceMessageAttributesV1>,
 }
 
@@ -583,13 +656,14 @@ impl From<ClientDeviceMessageAttributesV3> for ClientDeviceMessageAttributesV2 {
   }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Getters, Setters)]
+#[derive(Clone, Debug, PartialEq, Eq, Getters, Setters)]
+#[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
 pub struct GenericDeviceMessageAttributesV2 {
   #[getset(get = "pub")]
-  #[serde(rename = "FeatureCount")]
+  #[cfg_attr(feature = "serialize-json", serde(rename = "FeatureCount"))]
   feature_count: u32,
   #[getset(get = "pub")]
-  #[serde(rename = "StepCount")]
+  #[cfg_attr(feature = "serialize-json", serde(rename = "StepCount"))]
   step_count: Vec<u32>,
 }
 
@@ -621,20 +695,30 @@ impl From<Vec<ClientGenericDeviceMessageAttributesV3>> for GenericDeviceMessageA
   }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Getters, Setters)]
+#[derive(Clone, Debug, PartialEq, Eq, Getters, Setters)]
+#[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
 pub struct ClientDeviceMessageAttributesV1 {
   // Generic commands
   #[getset(get = "pub")]
-  #[serde(rename = "VibrateCmd")]
-  #[serde(skip_serializing_if = "Option::is_none")]
+  #[cfg_attr(feature = "serialize-json", serde(rename = "VibrateCmd"))]
+  #[cfg_attr(
+    feature = "serialize-json",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
   vibrate_cmd: Option<GenericDeviceMessageAttributesV1>,
   #[getset(get = "pub")]
-  #[serde(rename = "RotateCmd")]
-  #[serde(skip_serializing_if = "Option::is_none")]
+  #[cfg_attr(feature = "serialize-json", serde(rename = "RotateCmd"))]
+  #[cfg_attr(
+    feature = "serialize-json",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
   rotate_cmd: Option<GenericDeviceMessageAttributesV1>,
   #[getset(get = "pub")]
-  #[serde(rename = "LinearCmd")]
-  #[serde(skip_serializing_if = "Option::is_none")]
+  #[cfg_attr(feature = "serialize-json", serde(rename = "LinearCmd"))]
+  #[cfg_attr(
+    feature = "serialize-json",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
   linear_cmd: Option<GenericDeviceMessageAttributesV1>,
 
   // StopDeviceCmd always exists
@@ -643,13 +727,22 @@ pub struct ClientDeviceMessageAttributesV1 {
 
   // Obsolete commands are only added post-serialization
   #[getset(get = "pub")]
-  #[serde(skip_serializing_if = "Option::is_none")]
+  #[cfg_attr(
+    feature = "serialize-json",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
   single_motor_vibrate_cmd: Option<NullDeviceMessageAttributesV1>,
   #[getset(get = "pub")]
-  #[serde(skip_serializing_if = "Option::is_none")]
+  #[cfg_attr(
+    feature = "serialize-json",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
   fleshlight_launch_fw12_cmd: Option<NullDeviceMessageAttributesV1>,
   #[getset(get = "pub")]
-  #[serde(skip_serializing_if = "Option::is_none")]
+  #[cfg_attr(
+    feature = "serialize-json",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
   vorze_a10_cyclone_cmd: Option<NullDeviceMessageAttributesV1>,
 }
 
@@ -680,9 +773,10 @@ impl From<ClientDeviceMessageAttributesV2> for ClientDeviceMessageAttributesV1 {
   }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Getters, Setters)]
+#[derive(Clone, Debug, PartialEq, Eq, Getters, Setters)]
+#[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
 pub struct GenericDeviceMessageAttributesV1 {
-  #[serde(rename = "FeatureCount")]
+  #[cfg_attr(feature = "serialize-json", serde(rename = "FeatureCount"))]
   feature_count: u32,
 }
 
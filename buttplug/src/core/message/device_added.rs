@@ -8,13 +8,13 @@
 use super::device_message_info::{DeviceMessageInfoV0, DeviceMessageInfoV1, DeviceMessageInfoV2};
 use super::*;
 
-use getset::{CopyGetters, Getters};
+use getset::{CopyGetters, Getters, Setters};
 
 #[cfg(feature = "serialize-json")]
 use serde::{Deserialize, Serialize};
 
 /// Notification that a device has been found and connected to the server.
-#[derive(ButtplugMessage, Clone, Debug, PartialEq, Eq, Getters, CopyGetters)]
+#[derive(ButtplugMessage, Clone, Debug, PartialEq, Eq, Getters, CopyGetters, Setters)]
 #[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
 pub struct DeviceAddedV4 {
   #[cfg_attr(feature = "serialize-json", serde(rename = "Id"))]
@@ -42,9 +42,53 @@ pub struct DeviceAddedV4 {
   )]
   #[getset(get = "pub")]
   device_message_timing_gap: Option<u32>,
+  #[cfg_attr(
+    feature = "serialize-json",
+    serde(
+      rename = "DeviceProtocol",
+      default,
+      skip_serializing_if = "Option::is_none"
+    )
+  )]
+  #[getset(get = "pub", set = "pub")]
+  device_protocol: Option<String>,
+  #[cfg_attr(
+    feature = "serialize-json",
+    serde(
+      rename = "DeviceTransport",
+      default,
+      skip_serializing_if = "Option::is_none"
+    )
+  )]
+  #[getset(get = "pub", set = "pub")]
+  device_transport: Option<DeviceTransport>,
+  #[cfg_attr(
+    feature = "serialize-json",
+    serde(
+      rename = "DeviceAddress",
+      default,
+      skip_serializing_if = "Option::is_none"
+    )
+  )]
+  #[getset(get = "pub", set = "pub")]
+  device_address: Option<String>,
+  #[cfg_attr(
+    feature = "serialize-json",
+    serde(
+      rename = "DeviceFirmwareVersion",
+      default,
+      skip_serializing_if = "Option::is_none"
+    )
+  )]
+  #[getset(get = "pub", set = "pub")]
+  device_firmware_version: Option<String>,
   #[cfg_attr(feature = "serialize-json", serde(rename = "DeviceFeatures"))]
   #[getset(get = "pub")]
   device_features: Vec<DeviceFeature>,
+  /// Device list generation this addition brought the server to. See [DeviceListV4].
+  #[cfg_attr(feature = "serialize-json", serde(rename = "Generation", default))]
+  #[getset(get_copy = "pub", set = "pub")]
+  generation: u64,
 }
 
 impl DeviceAddedV4 {
@@ -61,7 +105,12 @@ impl DeviceAddedV4 {
       device_name: device_name.to_string(),
       device_display_name: device_display_name.clone(),
       device_message_timing_gap: *device_message_timing_gap,
+      device_protocol: None,
+      device_transport: None,
+      device_address: None,
+      device_firmware_version: None,
       device_features: device_features.clone(),
+      generation: 0,
     };
     obj.finalize();
     obj
@@ -94,7 +143,7 @@ impl From<DeviceAddedV4> for DeviceAddedV3 {
 }
 
 /// Notification that a device has been found and connected to the server.
-#[derive(ButtplugMessage, Clone, Debug, PartialEq, Eq, Getters, CopyGetters)]
+#[derive(ButtplugMessage, Clone, Debug, PartialEq, Eq, Getters, CopyGetters, Setters)]
 #[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
 pub struct DeviceAddedV3 {
   #[cfg_attr(feature = "serialize-json", serde(rename = "Id"))]
@@ -10,6 +10,7 @@ use crate::core::{
   message::{ButtplugDeviceMessageType, Endpoint},
 };
 use getset::{Getters, MutGetters, Setters};
+#[cfg(feature = "serialize-json")]
 use serde::{ser::SerializeSeq, Deserialize, Serialize, Serializer};
 use std::{collections::HashSet, ops::RangeInclusive};
 
@@ -20,7 +21,8 @@ use super::{
   SensorType,
 };
 
-#[derive(Debug, Default, Display, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Default, Display, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
 pub enum FeatureType {
   #[default]
   Unknown,
@@ -33,11 +35,14 @@ pub enum FeatureType {
   // For instances where we specify a position to move to ASAP. Usually servos, probably for the
   // OSR-2/SR-6.
   Position,
+  // Electrical stimulation intensity.
+  Estim,
   // Sensor Types
   Battery,
   RSSI,
   Button,
   Pressure,
+  Velocity,
   // Currently unused but possible sensor features:
   // Temperature,
   // Accelerometer,
@@ -57,6 +62,7 @@ impl From<ActuatorType> for FeatureType {
       ActuatorType::Constrict => FeatureType::Constrict,
       ActuatorType::Inflate => FeatureType::Inflate,
       ActuatorType::Position => FeatureType::Position,
+      ActuatorType::Estim => FeatureType::Estim,
     }
   }
 }
@@ -69,10 +75,45 @@ impl From<SensorType> for FeatureType {
       SensorType::RSSI => FeatureType::RSSI,
       SensorType::Button => FeatureType::Button,
       SensorType::Pressure => FeatureType::Pressure,
+      SensorType::Velocity => FeatureType::Velocity,
+      SensorType::Position => FeatureType::Position,
     }
   }
 }
 
+/// Named motion axes for multi-axis devices, like the SR6, that can move along and around more than
+/// one direction. Axes are addressed by name in [AxisCmd](super::AxisCmdV4), so clients don't need
+/// to know which feature index the device gave each one.
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
+pub enum DeviceAxis {
+  /// Up/down travel along the length of the receiver.
+  Stroke,
+  /// Forward/back.
+  Surge,
+  /// Left/right.
+  Sway,
+  /// Rotation around the stroke axis.
+  Twist,
+  /// Tilt to the left or right.
+  Roll,
+  /// Tilt forward or back.
+  Pitch,
+}
+
+/// What happens to an actuator when its device is stopped.
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serialize-json", serde(rename_all = "kebab-case"))]
+pub enum StopBehavior {
+  /// Leave the actuator where it is. Default for linear actuators, which hold a position instead of
+  /// running.
+  Hold,
+  /// Send the actuator back to zero. For speed style actuators this just stops them, which is the
+  /// default, while linear actuators move back to their 0 position.
+  ReturnToZero,
+}
+
 // This will look almost exactly like ServerDeviceFeature. However, it will only contain
 // information we want the client to know, i.e. step counts versus specific step ranges. This is
 // what will be sent to the client as part of DeviceAdded/DeviceList messages. It should not be used
@@ -81,26 +122,31 @@ impl From<SensorType> for FeatureType {
 // For many messages, client and server configurations may be exactly the same. If they are not,
 // then we denote this by prefixing the type with Client/Server. Server attributes will usually be
 // hosted in the server/device/configuration module.
-#[derive(
-  Clone, Debug, Default, PartialEq, Eq, Getters, MutGetters, Setters, Serialize, Deserialize,
-)]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Getters, MutGetters, Setters)]
+#[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
 pub struct DeviceFeature {
   #[getset(get = "pub", get_mut = "pub(super)")]
-  #[serde(default)]
+  #[cfg_attr(feature = "serialize-json", serde(default))]
   description: String,
   #[getset(get = "pub")]
-  #[serde(rename = "feature-type")]
+  #[cfg_attr(feature = "serialize-json", serde(rename = "feature-type"))]
   feature_type: FeatureType,
   #[getset(get = "pub")]
-  #[serde(skip_serializing_if = "Option::is_none")]
-  #[serde(rename = "actuator")]
+  #[cfg_attr(
+    feature = "serialize-json",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  #[cfg_attr(feature = "serialize-json", serde(rename = "actuator"))]
   actuator: Option<DeviceFeatureActuator>,
   #[getset(get = "pub")]
-  #[serde(skip_serializing_if = "Option::is_none")]
-  #[serde(rename = "sensor")]
+  #[cfg_attr(
+    feature = "serialize-json",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  #[cfg_attr(feature = "serialize-json", serde(rename = "sensor"))]
   sensor: Option<DeviceFeatureSensor>,
   #[getset(get = "pub")]
-  #[serde(skip)]
+  #[cfg_attr(feature = "serialize-json", serde(skip))]
   raw: Option<DeviceFeatureRaw>,
 }
 
@@ -124,6 +170,9 @@ impl DeviceFeature {
     if let Some(actuator) = &self.actuator {
       actuator.is_valid()?;
     }
+    if let Some(sensor) = &self.sensor {
+      sensor.is_valid()?;
+    }
     Ok(())
   }
 
@@ -138,6 +187,7 @@ impl DeviceFeature {
   }
 }
 
+#[cfg(feature = "serialize-json")]
 fn range_serialize<S>(range: &RangeInclusive<u32>, serializer: S) -> Result<S::Ok, S::Error>
 where
   S: Serializer,
@@ -148,6 +198,7 @@ where
   seq.end()
 }
 
+#[cfg(feature = "serialize-json")]
 fn range_sequence_serialize<S>(
   range_vec: &Vec<RangeInclusive<i32>>,
   serializer: S,
@@ -162,39 +213,91 @@ where
   seq.end()
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Getters, MutGetters, Setters, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Getters, MutGetters, Setters)]
+#[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
 pub struct DeviceFeatureActuatorSerialized {
   #[getset(get = "pub")]
-  #[serde(rename = "step-range")]
-  #[serde(serialize_with = "range_serialize")]
+  #[cfg_attr(feature = "serialize-json", serde(rename = "step-range"))]
+  #[cfg_attr(feature = "serialize-json", serde(serialize_with = "range_serialize"))]
   step_range: RangeInclusive<u32>,
   // This doesn't exist in base configs, so when we load these from the base config file, we'll just
   // copy the step_range value.
   #[getset(get = "pub")]
-  #[serde(rename = "step-limit")]
-  #[serde(default)]
+  #[cfg_attr(feature = "serialize-json", serde(rename = "step-limit"))]
+  #[cfg_attr(feature = "serialize-json", serde(default))]
   step_limit: Option<RangeInclusive<u32>>,
   #[getset(get = "pub")]
-  #[serde(rename = "messages")]
+  #[cfg_attr(feature = "serialize-json", serde(rename = "messages"))]
   messages: HashSet<ButtplugActuatorFeatureMessageType>,
+  #[getset(get = "pub")]
+  #[cfg_attr(feature = "serialize-json", serde(rename = "response-curve"))]
+  #[cfg_attr(
+    feature = "serialize-json",
+    serde(default, skip_serializing_if = "Option::is_none")
+  )]
+  response_curve: Option<Vec<u32>>,
+  #[getset(get = "pub")]
+  #[cfg_attr(feature = "serialize-json", serde(rename = "stop-behavior"))]
+  #[cfg_attr(
+    feature = "serialize-json",
+    serde(default, skip_serializing_if = "Option::is_none")
+  )]
+  stop_behavior: Option<StopBehavior>,
+  #[getset(get = "pub")]
+  #[cfg_attr(
+    feature = "serialize-json",
+    serde(default, skip_serializing_if = "Option::is_none")
+  )]
+  axis: Option<DeviceAxis>,
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Getters, MutGetters, Setters, Serialize, Deserialize)]
-#[serde(from = "DeviceFeatureActuatorSerialized")]
+#[derive(Clone, Debug, PartialEq, Eq, Getters, MutGetters, Setters)]
+#[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
+#[cfg_attr(
+  feature = "serialize-json",
+  serde(from = "DeviceFeatureActuatorSerialized")
+)]
 pub struct DeviceFeatureActuator {
   #[getset(get = "pub")]
-  #[serde(rename = "step-range")]
-  #[serde(serialize_with = "range_serialize")]
+  #[cfg_attr(feature = "serialize-json", serde(rename = "step-range"))]
+  #[cfg_attr(feature = "serialize-json", serde(serialize_with = "range_serialize"))]
   step_range: RangeInclusive<u32>,
   // This doesn't exist in base configs, so when we load these from the base config file, we'll just
   // copy the step_range value.
   #[getset(get = "pub")]
-  #[serde(rename = "step-limit")]
-  #[serde(serialize_with = "range_serialize")]
+  #[cfg_attr(feature = "serialize-json", serde(rename = "step-limit"))]
+  #[cfg_attr(feature = "serialize-json", serde(serialize_with = "range_serialize"))]
   step_limit: RangeInclusive<u32>,
   #[getset(get = "pub")]
-  #[serde(rename = "messages")]
+  #[cfg_attr(feature = "serialize-json", serde(rename = "messages"))]
   messages: HashSet<ButtplugActuatorFeatureMessageType>,
+  // Output values the protocol should send at evenly spaced points across the step range, for
+  // hardware whose response isn't linear. Only used by protocols that support it (currently
+  // XInput), which interpolate between the points.
+  #[getset(get = "pub", set = "pub")]
+  #[cfg_attr(feature = "serialize-json", serde(rename = "response-curve"))]
+  #[cfg_attr(
+    feature = "serialize-json",
+    serde(default, skip_serializing_if = "Option::is_none")
+  )]
+  response_curve: Option<Vec<u32>>,
+  // Only set when the config overrides the default for the actuator's messages, see
+  // [DeviceFeatureActuator::stop_behavior].
+  #[getset(set = "pub")]
+  #[cfg_attr(feature = "serialize-json", serde(rename = "stop-behavior"))]
+  #[cfg_attr(
+    feature = "serialize-json",
+    serde(default, skip_serializing_if = "Option::is_none")
+  )]
+  stop_behavior: Option<StopBehavior>,
+  // Which axis of a multi-axis device this actuator moves, if any. Required for the actuator to
+  // take AxisCmd.
+  #[getset(get = "pub", set = "pub")]
+  #[cfg_attr(
+    feature = "serialize-json",
+    serde(default, skip_serializing_if = "Option::is_none")
+  )]
+  axis: Option<DeviceAxis>,
 }
 
 impl From<DeviceFeatureActuatorSerialized> for DeviceFeatureActuator {
@@ -203,6 +306,9 @@ impl From<DeviceFeatureActuatorSerialized> for DeviceFeatureActuator {
       step_range: value.step_range.clone(),
       step_limit: value.step_limit.unwrap_or(value.step_range),
       messages: value.messages,
+      response_curve: value.response_curve,
+      stop_behavior: value.stop_behavior,
+      axis: value.axis,
     }
   }
 }
@@ -217,34 +323,76 @@ impl DeviceFeatureActuator {
       step_range: step_range.clone(),
       step_limit: step_limit.clone(),
       messages: messages.clone(),
+      response_curve: None,
+      stop_behavior: None,
+      axis: None,
     }
   }
 
+  /// What a device stop does to this actuator. Unless the config says otherwise, actuators that
+  /// only take position messages ([LinearCmd](ButtplugActuatorFeatureMessageType::LinearCmd) and
+  /// [AxisCmd](ButtplugActuatorFeatureMessageType::AxisCmd)) hold their position, and everything
+  /// else returns to zero.
+  pub fn stop_behavior(&self) -> StopBehavior {
+    self.stop_behavior.unwrap_or_else(|| {
+      if self.messages.iter().all(|msg| {
+        matches!(
+          msg,
+          ButtplugActuatorFeatureMessageType::LinearCmd
+            | ButtplugActuatorFeatureMessageType::AxisCmd
+        )
+      }) {
+        StopBehavior::Hold
+      } else {
+        StopBehavior::ReturnToZero
+      }
+    })
+  }
+
   pub fn is_valid(&self) -> Result<(), ButtplugDeviceError> {
     if self.step_range.is_empty() || self.step_range.start() > self.step_range.end() {
       Err(ButtplugDeviceError::DeviceConfigurationError(format!(
         "Step range out of order, must be start <= x <= end."
       )))
+    } else if self.step_range.start() == self.step_range.end() {
+      Err(ButtplugDeviceError::DeviceConfigurationError(format!(
+        "Step range {:?} has a step count of 0, must cover at least one step.",
+        self.step_range
+      )))
     } else if self.step_limit.is_empty() || self.step_limit.start() > self.step_limit.end() {
       Err(ButtplugDeviceError::DeviceConfigurationError(format!(
         "Step limit out of order, must be start <= x <= end."
       )))
+    } else if self.messages.is_empty() {
+      Err(ButtplugDeviceError::DeviceConfigurationError(
+        "Actuator has no messages, must allow at least one.".to_owned(),
+      ))
+    } else if self
+      .response_curve
+      .as_ref()
+      .is_some_and(|curve| curve.len() < 2)
+    {
+      Err(ButtplugDeviceError::DeviceConfigurationError(
+        "Response curve needs at least two points.".to_owned(),
+      ))
     } else {
       Ok(())
     }
   }
 }
 
-#[derive(
-  Clone, Debug, Default, PartialEq, Eq, Getters, MutGetters, Setters, Serialize, Deserialize,
-)]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Getters, MutGetters, Setters)]
+#[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
 pub struct DeviceFeatureSensor {
   #[getset(get = "pub", get_mut = "pub(super)")]
-  #[serde(rename = "value-range")]
-  #[serde(serialize_with = "range_sequence_serialize")]
+  #[cfg_attr(feature = "serialize-json", serde(rename = "value-range"))]
+  #[cfg_attr(
+    feature = "serialize-json",
+    serde(serialize_with = "range_sequence_serialize")
+  )]
   value_range: Vec<RangeInclusive<i32>>,
   #[getset(get = "pub")]
-  #[serde(rename = "messages")]
+  #[cfg_attr(feature = "serialize-json", serde(rename = "messages"))]
   messages: HashSet<ButtplugSensorFeatureMessageType>,
 }
 
@@ -258,17 +406,27 @@ impl DeviceFeatureSensor {
       messages: messages.clone(),
     }
   }
+
+  pub fn is_valid(&self) -> Result<(), ButtplugDeviceError> {
+    if let Some(range) = self.value_range.iter().find(|range| range.is_empty()) {
+      Err(ButtplugDeviceError::DeviceConfigurationError(format!(
+        "Sensor value range {:?} out of order, must be start <= x <= end.",
+        range
+      )))
+    } else {
+      Ok(())
+    }
+  }
 }
 
-#[derive(
-  Clone, Debug, Default, PartialEq, Eq, Getters, MutGetters, Setters, Serialize, Deserialize,
-)]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Getters, MutGetters, Setters)]
+#[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
 pub struct DeviceFeatureRaw {
   #[getset(get = "pub")]
-  #[serde(rename = "Endpoints")]
+  #[cfg_attr(feature = "serialize-json", serde(rename = "Endpoints"))]
   endpoints: Vec<Endpoint>,
   #[getset(get = "pub")]
-  #[serde(rename = "Messages")]
+  #[cfg_attr(feature = "serialize-json", serde(rename = "Messages"))]
   messages: HashSet<ButtplugDeviceMessageType>,
 }
 
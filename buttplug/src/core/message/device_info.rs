@@ -0,0 +1,85 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2024 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Details about how the server is connected to a device.
+
+use super::*;
+use getset::{CopyGetters, Getters};
+#[cfg(feature = "serialize-json")]
+use serde::{Deserialize, Serialize};
+
+/// Reply to [RequestDeviceInfoV4], carrying the same protocol, transport, address and firmware
+/// version fields [DeviceMessageInfoV4] has. Fields are left out when the server doesn't know them.
+#[derive(
+  Debug, ButtplugDeviceMessage, ButtplugMessageFinalizer, PartialEq, Eq, Clone, Getters, CopyGetters,
+)]
+#[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
+pub struct DeviceInfoV4 {
+  #[cfg_attr(feature = "serialize-json", serde(rename = "Id"))]
+  id: u32,
+  #[cfg_attr(feature = "serialize-json", serde(rename = "DeviceIndex"))]
+  device_index: u32,
+  #[cfg_attr(
+    feature = "serialize-json",
+    serde(
+      rename = "DeviceProtocol",
+      default,
+      skip_serializing_if = "Option::is_none"
+    )
+  )]
+  #[getset(get = "pub")]
+  device_protocol: Option<String>,
+  #[cfg_attr(
+    feature = "serialize-json",
+    serde(
+      rename = "DeviceTransport",
+      default,
+      skip_serializing_if = "Option::is_none"
+    )
+  )]
+  #[getset(get_copy = "pub")]
+  device_transport: Option<DeviceTransport>,
+  #[cfg_attr(
+    feature = "serialize-json",
+    serde(
+      rename = "DeviceAddress",
+      default,
+      skip_serializing_if = "Option::is_none"
+    )
+  )]
+  #[getset(get = "pub")]
+  device_address: Option<String>,
+  #[cfg_attr(
+    feature = "serialize-json",
+    serde(
+      rename = "DeviceFirmwareVersion",
+      default,
+      skip_serializing_if = "Option::is_none"
+    )
+  )]
+  #[getset(get = "pub")]
+  device_firmware_version: Option<String>,
+}
+
+impl ButtplugMessageValidator for DeviceInfoV4 {
+  fn is_valid(&self) -> Result<(), ButtplugMessageError> {
+    self.is_not_system_id(self.id)
+  }
+}
+
+impl From<&DeviceMessageInfoV4> for DeviceInfoV4 {
+  fn from(info: &DeviceMessageInfoV4) -> Self {
+    Self {
+      id: 1,
+      device_index: info.device_index(),
+      device_protocol: info.device_protocol().clone(),
+      device_transport: *info.device_transport(),
+      device_address: info.device_address().clone(),
+      device_firmware_version: info.device_firmware_version().clone(),
+    }
+  }
+}
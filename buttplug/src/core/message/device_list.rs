@@ -8,12 +8,23 @@
 use super::device_message_info::{DeviceMessageInfoV0, DeviceMessageInfoV1, DeviceMessageInfoV2};
 use super::*;
 use device_message_info::DeviceMessageInfoV4;
-use getset::Getters;
+use getset::{CopyGetters, Getters};
 #[cfg(feature = "serialize-json")]
 use serde::{Deserialize, Serialize};
 
 /// List of all devices currently connected to the server.
-#[derive(Default, Clone, Debug, PartialEq, Eq, ButtplugMessage, Getters)]
+///
+/// Every list carries the server's device list generation, which changes whenever a device is
+/// added or removed. Clients that send it back in [RequestDeviceListV4] get an incremental list if
+/// the server can still work out what changed: `devices` holds devices added since then, and
+/// `removed_devices` holds indexes of devices removed since then. Removals should be applied
+/// first, as a device that reconnected will show up in both.
+///
+/// [DeviceAddedV4] and [DeviceRemovedV4] also carry the generation each change brought the server
+/// to, and every change moves the generation up by exactly one. A client that sees a notification
+/// skip a generation has missed one, and can catch up by asking for the changes since the last
+/// generation it saw.
+#[derive(Default, Clone, Debug, PartialEq, Eq, ButtplugMessage, Getters, CopyGetters)]
 #[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
 pub struct DeviceListV4 {
   #[cfg_attr(feature = "serialize-json", serde(rename = "Id"))]
@@ -21,11 +32,56 @@ pub struct DeviceListV4 {
   #[cfg_attr(feature = "serialize-json", serde(rename = "Devices"))]
   #[getset(get = "pub")]
   devices: Vec<DeviceMessageInfoV4>,
+  #[getset(get_copy = "pub")]
+  #[cfg_attr(feature = "serialize-json", serde(rename = "Generation", default))]
+  generation: u64,
+  /// If true, this list only holds changes since the generation the client asked about.
+  #[getset(get_copy = "pub")]
+  #[cfg_attr(
+    feature = "serialize-json",
+    serde(
+      rename = "Incremental",
+      default,
+      skip_serializing_if = "std::ops::Not::not"
+    )
+  )]
+  incremental: bool,
+  #[getset(get = "pub")]
+  #[cfg_attr(
+    feature = "serialize-json",
+    serde(
+      rename = "RemovedDevices",
+      default,
+      skip_serializing_if = "Vec::is_empty"
+    )
+  )]
+  removed_devices: Vec<u32>,
 }
 
 impl DeviceListV4 {
-  pub fn new(devices: Vec<DeviceMessageInfoV4>) -> Self {
-    Self { id: 1, devices }
+  pub fn new(devices: Vec<DeviceMessageInfoV4>, generation: u64) -> Self {
+    Self {
+      id: 1,
+      devices,
+      generation,
+      incremental: false,
+      removed_devices: vec![],
+    }
+  }
+
+  /// Create a list holding only the changes since an earlier generation.
+  pub fn new_incremental(
+    devices: Vec<DeviceMessageInfoV4>,
+    removed_devices: Vec<u32>,
+    generation: u64,
+  ) -> Self {
+    Self {
+      id: 1,
+      devices,
+      generation,
+      incremental: true,
+      removed_devices,
+    }
   }
 }
 
@@ -6,13 +6,43 @@
 // for full license information.
 
 use super::*;
+use std::fmt;
 use device_added::DeviceAddedV4;
-use getset::{CopyGetters, Getters, MutGetters};
+use getset::{CopyGetters, Getters, MutGetters, Setters};
 #[cfg(feature = "serialize-json")]
 use serde::{Deserialize, Serialize};
 
+/// How the server is connected to a device's hardware.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
+pub enum DeviceTransport {
+  BluetoothLE,
+  Serial,
+  #[cfg_attr(feature = "serialize-json", serde(rename = "HID"))]
+  Hid,
+  #[cfg_attr(feature = "serialize-json", serde(rename = "USB"))]
+  Usb,
+  XInput,
+  /// Devices connected over the network, via websockets or remote services like Lovense Connect.
+  Network,
+}
+
+impl fmt::Display for DeviceTransport {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let name = match self {
+      Self::BluetoothLE => "Bluetooth LE",
+      Self::Serial => "Serial",
+      Self::Hid => "HID",
+      Self::Usb => "USB",
+      Self::XInput => "XInput",
+      Self::Network => "Network",
+    };
+    f.write_str(name)
+  }
+}
+
 /// Substructure of device messages, used for attribute information (name, messages supported, etc...)
-#[derive(Clone, Debug, PartialEq, Eq, MutGetters, Getters, CopyGetters)]
+#[derive(Clone, Debug, PartialEq, Eq, MutGetters, Getters, CopyGetters, Setters)]
 #[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
 pub struct DeviceMessageInfoV4 {
   #[cfg_attr(feature = "serialize-json", serde(rename = "DeviceIndex"))]
@@ -36,6 +66,46 @@ pub struct DeviceMessageInfoV4 {
   )]
   #[getset(get = "pub")]
   device_message_timing_gap: Option<u32>,
+  #[cfg_attr(
+    feature = "serialize-json",
+    serde(
+      rename = "DeviceProtocol",
+      default,
+      skip_serializing_if = "Option::is_none"
+    )
+  )]
+  #[getset(get = "pub", set = "pub")]
+  device_protocol: Option<String>,
+  #[cfg_attr(
+    feature = "serialize-json",
+    serde(
+      rename = "DeviceTransport",
+      default,
+      skip_serializing_if = "Option::is_none"
+    )
+  )]
+  #[getset(get = "pub", set = "pub")]
+  device_transport: Option<DeviceTransport>,
+  #[cfg_attr(
+    feature = "serialize-json",
+    serde(
+      rename = "DeviceAddress",
+      default,
+      skip_serializing_if = "Option::is_none"
+    )
+  )]
+  #[getset(get = "pub", set = "pub")]
+  device_address: Option<String>,
+  #[cfg_attr(
+    feature = "serialize-json",
+    serde(
+      rename = "DeviceFirmwareVersion",
+      default,
+      skip_serializing_if = "Option::is_none"
+    )
+  )]
+  #[getset(get = "pub", set = "pub")]
+  device_firmware_version: Option<String>,
   #[cfg_attr(feature = "serialize-json", serde(rename = "DeviceFeatures"))]
   #[getset(get = "pub", get_mut = "pub(super)")]
   device_features: Vec<DeviceFeature>,
@@ -54,6 +124,10 @@ impl DeviceMessageInfoV4 {
       device_name: device_name.to_owned(),
       device_display_name: device_display_name.clone(),
       device_message_timing_gap: *device_message_timing_gap,
+      device_protocol: None,
+      device_transport: None,
+      device_address: None,
+      device_firmware_version: None,
       device_features,
     }
   }
@@ -66,6 +140,10 @@ impl From<DeviceAddedV4> for DeviceMessageInfoV4 {
       device_name: device_added.device_name().clone(),
       device_display_name: device_added.device_display_name().clone(),
       device_message_timing_gap: *device_added.device_message_timing_gap(),
+      device_protocol: device_added.device_protocol().clone(),
+      device_transport: *device_added.device_transport(),
+      device_address: device_added.device_address().clone(),
+      device_firmware_version: device_added.device_firmware_version().clone(),
       device_features: device_added.device_features().clone(),
     }
   }
@@ -75,8 +153,8 @@ impl From<DeviceMessageInfoV4> for DeviceMessageInfoV3 {
   fn from(value: DeviceMessageInfoV4) -> Self {
     DeviceMessageInfoV3::new(
       value.device_index(),
-      &value.device_name(),
-      &value.device_display_name(),
+      value.device_name(),
+      value.device_display_name(),
       &None,
       value.device_features().clone().into(),
     )
@@ -84,7 +162,7 @@ impl From<DeviceMessageInfoV4> for DeviceMessageInfoV3 {
 }
 
 /// Substructure of device messages, used for attribute information (name, messages supported, etc...)
-#[derive(Clone, Debug, PartialEq, Eq, MutGetters, Getters, CopyGetters)]
+#[derive(Clone, Debug, PartialEq, Eq, MutGetters, Getters, CopyGetters, Setters)]
 #[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
 pub struct DeviceMessageInfoV3 {
   #[cfg_attr(feature = "serialize-json", serde(rename = "DeviceIndex"))]
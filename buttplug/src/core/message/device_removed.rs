@@ -12,6 +12,46 @@ use getset::CopyGetters;
 #[cfg(feature = "serialize-json")]
 use serde::{Deserialize, Serialize};
 
+/// Device removal notification, carrying the device list generation the removal brought the
+/// server to. See [DeviceListV4] for how clients can use it to notice missed notifications.
+#[derive(Debug, Default, ButtplugMessage, Clone, PartialEq, Eq, CopyGetters)]
+#[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
+pub struct DeviceRemovedV4 {
+  #[cfg_attr(feature = "serialize-json", serde(rename = "Id"))]
+  id: u32,
+  #[cfg_attr(feature = "serialize-json", serde(rename = "DeviceIndex"))]
+  #[getset(get_copy = "pub")]
+  device_index: u32,
+  #[cfg_attr(feature = "serialize-json", serde(rename = "Generation", default))]
+  #[getset(get_copy = "pub")]
+  generation: u64,
+}
+
+impl DeviceRemovedV4 {
+  pub fn new(device_index: u32, generation: u64) -> Self {
+    Self {
+      id: 0,
+      device_index,
+      generation,
+    }
+  }
+}
+
+impl ButtplugMessageValidator for DeviceRemovedV4 {
+  fn is_valid(&self) -> Result<(), ButtplugMessageError> {
+    self.is_system_id(self.id)
+  }
+}
+
+impl ButtplugMessageFinalizer for DeviceRemovedV4 {
+}
+
+impl From<DeviceRemovedV4> for DeviceRemovedV0 {
+  fn from(value: DeviceRemovedV4) -> Self {
+    Self::new(value.device_index)
+  }
+}
+
 #[derive(Debug, Default, ButtplugMessage, Clone, PartialEq, Eq, CopyGetters)]
 #[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
 pub struct DeviceRemovedV0 {
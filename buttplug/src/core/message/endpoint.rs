@@ -1,3 +1,4 @@
+#[cfg(feature = "serialize-json")]
 use serde::{
   de::{self, Visitor},
   Deserialize,
@@ -5,11 +6,8 @@ use serde::{
   Serialize,
   Serializer,
 };
-use std::{
-  fmt::{self, Debug},
-  str::FromStr,
-  string::ToString,
-};
+#[cfg(feature = "serialize-json")]
+use std::{fmt, str::FromStr};
 
 use core::hash::Hash;
 
@@ -43,6 +41,8 @@ pub enum Endpoint {
   RxBLEBattery,
   /// Receive endpoint for BLE model (usually expected to be BLE standard profile)
   RxBLEModel,
+  /// Receive endpoint for BLE firmware revision (usually expected to be BLE standard profile)
+  RxBLEFirmware,
   /// Receive endpoint for pressure sensors
   RxPressure,
   /// Receive endpoint for touch sensors
@@ -126,6 +126,7 @@ pub enum Endpoint {
 }
 
 // Implement to/from string serialization for Endpoint struct
+#[cfg(feature = "serialize-json")]
 impl Serialize for Endpoint {
   fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
   where
@@ -135,8 +136,10 @@ impl Serialize for Endpoint {
   }
 }
 
+#[cfg(feature = "serialize-json")]
 struct EndpointVisitor;
 
+#[cfg(feature = "serialize-json")]
 impl<'de> Visitor<'de> for EndpointVisitor {
   type Value = Endpoint;
 
@@ -152,6 +155,7 @@ impl<'de> Visitor<'de> for EndpointVisitor {
   }
 }
 
+#[cfg(feature = "serialize-json")]
 impl<'de> Deserialize<'de> for Endpoint {
   fn deserialize<D>(deserializer: D) -> Result<Endpoint, D::Error>
   where
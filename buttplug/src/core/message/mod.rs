@@ -13,29 +13,36 @@
 //! are also enum types that are used to classify messages into categories, for instance, messages
 //! that only should be sent by a client or server.
 
+mod axis_cmd;
 mod battery_level_cmd;
 mod battery_level_reading;
 mod client_device_message_attributes;
 mod device_added;
 mod device_feature;
+mod device_info;
 mod device_list;
 mod device_message_info;
 mod device_removed;
 mod endpoint;
 mod error;
 mod fleshlight_launch_fw12_cmd;
+mod identify_device;
 mod kiiroo_cmd;
 mod linear_cmd;
 mod log;
 mod log_level;
 mod lovense_cmd;
 mod ok;
+mod pattern_session_ended;
+mod pattern_session_progress;
+mod pattern_session_started;
 mod ping;
 mod raw_read_cmd;
 mod raw_reading;
 mod raw_subscribe_cmd;
 mod raw_unsubscribe_cmd;
 mod raw_write_cmd;
+mod request_device_info;
 mod request_device_list;
 mod request_log;
 mod request_server_info;
@@ -44,6 +51,7 @@ mod rssi_level_cmd;
 mod rssi_level_reading;
 mod scalar_cmd;
 mod scanning_finished;
+mod scanning_progress;
 mod sensor_read_cmd;
 mod sensor_reading;
 mod sensor_subscribe_cmd;
@@ -51,15 +59,18 @@ mod sensor_unsubscribe_cmd;
 pub mod serializer;
 mod server_info;
 mod single_motor_vibrate_cmd;
+mod start_pattern_session;
 mod start_scanning;
 mod stop_all_devices;
 mod stop_device_cmd;
+mod stop_pattern_session;
 mod stop_scanning;
 mod test;
 mod vibrate_cmd;
 mod vorze_a10_cyclone_cmd;
 
 pub use self::log::LogV0;
+pub use axis_cmd::{AxisCmdV4, AxisSubcommandV4};
 pub use battery_level_cmd::BatteryLevelCmdV2;
 pub use battery_level_reading::BatteryLevelReadingV2;
 pub use client_device_message_attributes::{
@@ -76,12 +87,15 @@ pub use client_device_message_attributes::{
 };
 pub use device_added::{DeviceAddedV0, DeviceAddedV1, DeviceAddedV2, DeviceAddedV3, DeviceAddedV4};
 pub use device_feature::{
+  DeviceAxis,
   DeviceFeature,
   DeviceFeatureActuator,
   DeviceFeatureRaw,
   DeviceFeatureSensor,
   FeatureType,
+  StopBehavior,
 };
+pub use device_info::DeviceInfoV4;
 pub use device_list::{DeviceListV0, DeviceListV1, DeviceListV2, DeviceListV3, DeviceListV4};
 pub use device_message_info::{
   DeviceMessageInfoV0,
@@ -89,47 +103,58 @@ pub use device_message_info::{
   DeviceMessageInfoV2,
   DeviceMessageInfoV3,
   DeviceMessageInfoV4,
+  DeviceTransport,
 };
-pub use device_removed::DeviceRemovedV0;
+pub use device_removed::{DeviceRemovedV0, DeviceRemovedV4};
 pub use endpoint::Endpoint;
 pub use error::{ErrorCode, ErrorV0};
 pub use fleshlight_launch_fw12_cmd::FleshlightLaunchFW12CmdV0;
+pub use identify_device::IdentifyDeviceV4;
 pub use kiiroo_cmd::KiirooCmdV0;
 pub use linear_cmd::{LinearCmdV1, LinearCmdV4, VectorSubcommandV1, VectorSubcommandV4};
 pub use log_level::LogLevel;
 pub use lovense_cmd::LovenseCmdV0;
 pub use ok::OkV0;
+pub use pattern_session_ended::{PatternSessionEndReason, PatternSessionEndedV4};
+pub use pattern_session_progress::PatternSessionProgressV4;
+pub use pattern_session_started::PatternSessionStartedV4;
 pub use ping::PingV0;
 pub use raw_read_cmd::RawReadCmdV2;
 pub use raw_reading::RawReadingV2;
 pub use raw_subscribe_cmd::RawSubscribeCmdV2;
 pub use raw_unsubscribe_cmd::RawUnsubscribeCmdV2;
 pub use raw_write_cmd::RawWriteCmdV2;
-pub use request_device_list::RequestDeviceListV0;
+pub use request_device_info::RequestDeviceInfoV4;
+pub use request_device_list::{RequestDeviceListV0, RequestDeviceListV4};
 pub use request_log::RequestLogV0;
-pub use request_server_info::RequestServerInfoV1;
+pub use request_server_info::{RequestServerInfoV1, RequestServerInfoV4};
 pub use rotate_cmd::{RotateCmdV1, RotateCmdV4, RotationSubcommandV1, RotationSubcommandV4};
 pub use rssi_level_cmd::RSSILevelCmdV2;
 pub use rssi_level_reading::RSSILevelReadingV2;
 pub use scalar_cmd::{ScalarCmdV3, ScalarCmdV4, ScalarSubcommandV3, ScalarSubcommandV4};
 pub use scanning_finished::ScanningFinishedV0;
+pub use scanning_progress::ScanningProgressV4;
 pub use sensor_read_cmd::{SensorReadCmdV3, SensorReadCmdV4};
 pub use sensor_reading::{SensorReadingV3, SensorReadingV4};
-pub use sensor_subscribe_cmd::{SensorSubscribeCmdV3, SensorSubscribeCmdV4};
+pub use sensor_subscribe_cmd::{SensorDecimation, SensorSubscribeCmdV3, SensorSubscribeCmdV4};
 pub use sensor_unsubscribe_cmd::{SensorUnsubscribeCmdV3, SensorUnsubscribeCmdV4};
 pub use server_info::{ServerInfoV0, ServerInfoV2};
 pub use single_motor_vibrate_cmd::SingleMotorVibrateCmdV0;
+pub use start_pattern_session::{PatternStepV4, PatternTrackV4, StartPatternSessionV4};
 pub use start_scanning::StartScanningV0;
 pub use stop_all_devices::StopAllDevicesV0;
 pub use stop_device_cmd::StopDeviceCmdV0;
+pub use stop_pattern_session::StopPatternSessionV4;
 pub use stop_scanning::StopScanningV0;
 pub use test::TestV0;
 pub use vibrate_cmd::{VibrateCmdV1, VibrateSubcommandV1};
 pub use vorze_a10_cyclone_cmd::VorzeA10CycloneCmdV0;
 
 use crate::core::errors::ButtplugMessageError;
+#[cfg(feature = "serialize-json")]
 use serde::{Deserialize, Serialize};
 #[cfg(feature = "serialize-json")]
+#[cfg(feature = "serialize-json")]
 use serde_repr::{Deserialize_repr, Serialize_repr};
 use std::cmp::Ordering;
 use std::convert::TryFrom;
@@ -248,7 +273,8 @@ pub trait ButtplugDeviceMessage: ButtplugMessage {
 
 /// Used in [MessageAttributes][crate::core::messages::DeviceMessageAttributes] for denoting message
 /// capabilties.
-#[derive(Copy, Debug, Clone, PartialEq, Eq, Hash, Display, Serialize, Deserialize)]
+#[derive(Copy, Debug, Clone, PartialEq, Eq, Hash, Display)]
+#[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
 pub enum ButtplugDeviceMessageType {
   VibrateCmd,
   LinearCmd,
@@ -261,6 +287,7 @@ pub enum ButtplugDeviceMessageType {
   BatteryLevelCmd,
   RSSILevelCmd,
   ScalarCmd,
+  AxisCmd,
   SensorReadCmd,
   SensorSubscribeCmd,
   SensorUnsubscribeCmd,
@@ -287,11 +314,13 @@ impl Ord for ButtplugDeviceMessageType {
   }
 }
 
-#[derive(Copy, Debug, Clone, Hash, Display, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Copy, Debug, Clone, Hash, Display, PartialEq, Eq)]
+#[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
 pub enum ButtplugActuatorFeatureMessageType {
   ScalarCmd,
   RotateCmd,
   LinearCmd,
+  AxisCmd,
 }
 
 impl From<ButtplugActuatorFeatureMessageType> for ButtplugDeviceMessageType {
@@ -300,6 +329,7 @@ impl From<ButtplugActuatorFeatureMessageType> for ButtplugDeviceMessageType {
       ButtplugActuatorFeatureMessageType::LinearCmd => ButtplugDeviceMessageType::LinearCmd,
       ButtplugActuatorFeatureMessageType::RotateCmd => ButtplugDeviceMessageType::RotateCmd,
       ButtplugActuatorFeatureMessageType::ScalarCmd => ButtplugDeviceMessageType::ScalarCmd,
+      ButtplugActuatorFeatureMessageType::AxisCmd => ButtplugDeviceMessageType::AxisCmd,
     }
   }
 }
@@ -312,12 +342,14 @@ impl TryFrom<ButtplugDeviceMessageType> for ButtplugActuatorFeatureMessageType {
       ButtplugDeviceMessageType::LinearCmd => Ok(ButtplugActuatorFeatureMessageType::LinearCmd),
       ButtplugDeviceMessageType::RotateCmd => Ok(ButtplugActuatorFeatureMessageType::RotateCmd),
       ButtplugDeviceMessageType::ScalarCmd => Ok(ButtplugActuatorFeatureMessageType::ScalarCmd),
+      ButtplugDeviceMessageType::AxisCmd => Ok(ButtplugActuatorFeatureMessageType::AxisCmd),
       _ => Err(()),
     }
   }
 }
 
-#[derive(Copy, Debug, Clone, Hash, Display, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Copy, Debug, Clone, Hash, Display, PartialEq, Eq)]
+#[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
 pub enum ButtplugSensorFeatureMessageType {
   SensorReadCmd,
   SensorSubscribeCmd,
@@ -350,7 +382,8 @@ impl TryFrom<ButtplugDeviceMessageType> for ButtplugSensorFeatureMessageType {
   }
 }
 
-#[derive(Copy, Debug, Clone, Hash, Display, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Copy, Debug, Clone, Hash, Display, PartialEq, Eq)]
+#[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
 pub enum ButtplugRawFeatureMessageType {
   RawReadCmd,
   RawWriteCmd,
@@ -538,18 +571,21 @@ pub type ButtplugServerMessageCurrent = ButtplugServerMessageV3;
 #[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
 pub enum ButtplugClientMessageV4 {
   // Handshake messages
-  RequestServerInfo(RequestServerInfoV1),
+  RequestServerInfo(RequestServerInfoV4),
   Ping(PingV0),
   // Device enumeration messages
   StartScanning(StartScanningV0),
   StopScanning(StopScanningV0),
-  RequestDeviceList(RequestDeviceListV0),
+  RequestDeviceList(RequestDeviceListV4),
+  RequestDeviceInfo(RequestDeviceInfoV4),
+  IdentifyDevice(IdentifyDeviceV4),
   // Generic commands
   StopDeviceCmd(StopDeviceCmdV0),
   StopAllDevices(StopAllDevicesV0),
   ScalarCmd(ScalarCmdV4),
   LinearCmd(LinearCmdV4),
   RotateCmd(RotateCmdV4),
+  AxisCmd(AxisCmdV4),
   RawWriteCmd(RawWriteCmdV2),
   RawReadCmd(RawReadCmdV2),
   RawSubscribeCmd(RawSubscribeCmdV2),
@@ -558,6 +594,9 @@ pub enum ButtplugClientMessageV4 {
   SensorReadCmd(SensorReadCmdV4),
   SensorSubscribeCmd(SensorSubscribeCmdV4),
   SensorUnsubscribeCmd(SensorUnsubscribeCmdV4),
+  // Pattern sessions
+  StartPatternSession(StartPatternSessionV4),
+  StopPatternSession(StopPatternSessionV4),
 }
 
 /// Represents all server-to-client messages in v3 of the Buttplug Spec
@@ -574,12 +613,18 @@ pub enum ButtplugServerMessageV4 {
   // Device enumeration messages
   DeviceList(DeviceListV4),
   DeviceAdded(DeviceAddedV4),
-  DeviceRemoved(DeviceRemovedV0),
+  DeviceRemoved(DeviceRemovedV4),
+  DeviceInfo(DeviceInfoV4),
   ScanningFinished(ScanningFinishedV0),
+  ScanningProgress(ScanningProgressV4),
   // Generic commands
   RawReading(RawReadingV2),
   // Sensor commands
   SensorReading(SensorReadingV4),
+  // Pattern sessions
+  PatternSessionStarted(PatternSessionStartedV4),
+  PatternSessionProgress(PatternSessionProgressV4),
+  PatternSessionEnded(PatternSessionEndedV4),
 }
 
 impl ButtplugMessageFinalizer for ButtplugServerMessageV4 {
@@ -626,9 +671,15 @@ pub enum ButtplugClientMessageV3 {
   SensorReadCmd(SensorReadCmdV3),
   SensorSubscribeCmd(SensorSubscribeCmdV3),
   SensorUnsubscribeCmd(SensorUnsubscribeCmdV3),
+  // Spec v4 additions v3 clients can opt in to
+  RequestDeviceInfo(RequestDeviceInfoV4),
+  IdentifyDevice(IdentifyDeviceV4),
+  StartPatternSession(StartPatternSessionV4),
+  StopPatternSession(StopPatternSessionV4),
 }
 
 /// Represents all server-to-client messages in v3 of the Buttplug Spec
+#[allow(clippy::large_enum_variant)]
 #[derive(
   Debug, Clone, PartialEq, ButtplugMessage, ButtplugMessageValidator, FromSpecificButtplugMessage,
 )]
@@ -648,6 +699,11 @@ pub enum ButtplugServerMessageV3 {
   RawReading(RawReadingV2),
   // Sensor commands
   SensorReading(SensorReadingV3),
+  // Spec v4 additions v3 clients can opt in to
+  DeviceInfo(DeviceInfoV4),
+  PatternSessionStarted(PatternSessionStartedV4),
+  PatternSessionProgress(PatternSessionProgressV4),
+  PatternSessionEnded(PatternSessionEndedV4),
 }
 
 impl ButtplugMessageFinalizer for ButtplugServerMessageV3 {
@@ -844,19 +900,28 @@ pub enum ButtplugServerMessageV0 {
   Debug,
   Clone,
   PartialEq,
-  Eq,
   ButtplugMessage,
   ButtplugMessageValidator,
   ButtplugMessageFinalizer,
   FromSpecificButtplugMessage,
 )]
 pub enum ButtplugDeviceManagerMessageUnion {
-  RequestDeviceList(RequestDeviceListV0),
+  RequestDeviceList(RequestDeviceListV4),
+  RequestDeviceInfo(RequestDeviceInfoV4),
+  IdentifyDevice(IdentifyDeviceV4),
+  StartPatternSession(StartPatternSessionV4),
+  StopPatternSession(StopPatternSessionV4),
   StopAllDevices(StopAllDevicesV0),
   StartScanning(StartScanningV0),
   StopScanning(StopScanningV0),
 }
 
+impl From<RequestServerInfoV1> for ButtplugClientMessageV4 {
+  fn from(value: RequestServerInfoV1) -> Self {
+    ButtplugClientMessageV4::RequestServerInfo(value.into())
+  }
+}
+
 impl TryFrom<ButtplugClientMessageV4> for ButtplugDeviceManagerMessageUnion {
   type Error = ();
 
@@ -865,6 +930,18 @@ impl TryFrom<ButtplugClientMessageV4> for ButtplugDeviceManagerMessageUnion {
       ButtplugClientMessageV4::RequestDeviceList(m) => {
         Ok(ButtplugDeviceManagerMessageUnion::RequestDeviceList(m))
       }
+      ButtplugClientMessageV4::RequestDeviceInfo(m) => {
+        Ok(ButtplugDeviceManagerMessageUnion::RequestDeviceInfo(m))
+      }
+      ButtplugClientMessageV4::IdentifyDevice(m) => {
+        Ok(ButtplugDeviceManagerMessageUnion::IdentifyDevice(m))
+      }
+      ButtplugClientMessageV4::StartPatternSession(m) => {
+        Ok(ButtplugDeviceManagerMessageUnion::StartPatternSession(m))
+      }
+      ButtplugClientMessageV4::StopPatternSession(m) => {
+        Ok(ButtplugDeviceManagerMessageUnion::StopPatternSession(m))
+      }
       ButtplugClientMessageV4::StopAllDevices(m) => {
         Ok(ButtplugDeviceManagerMessageUnion::StopAllDevices(m))
       }
@@ -895,6 +972,7 @@ pub enum ButtplugDeviceCommandMessageUnion {
   LinearCmd(LinearCmdV4),
   RotateCmd(RotateCmdV4),
   ScalarCmd(ScalarCmdV4),
+  AxisCmd(AxisCmdV4),
   SensorReadCmd(SensorReadCmdV4),
   SensorSubscribeCmd(SensorSubscribeCmdV4),
   SensorUnsubscribeCmd(SensorUnsubscribeCmdV4),
@@ -915,6 +993,7 @@ impl TryFrom<ButtplugClientMessageV4> for ButtplugDeviceCommandMessageUnion {
       ButtplugClientMessageV4::LinearCmd(m) => Ok(ButtplugDeviceCommandMessageUnion::LinearCmd(m)),
       ButtplugClientMessageV4::RotateCmd(m) => Ok(ButtplugDeviceCommandMessageUnion::RotateCmd(m)),
       ButtplugClientMessageV4::ScalarCmd(m) => Ok(ButtplugDeviceCommandMessageUnion::ScalarCmd(m)),
+      ButtplugClientMessageV4::AxisCmd(m) => Ok(ButtplugDeviceCommandMessageUnion::AxisCmd(m)),
       ButtplugClientMessageV4::SensorReadCmd(m) => {
         Ok(ButtplugDeviceCommandMessageUnion::SensorReadCmd(m))
       }
@@ -0,0 +1,75 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2024 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Notification that a pattern session has ended.
+
+use super::*;
+use getset::{CopyGetters, Getters};
+#[cfg(feature = "serialize-json")]
+use serde::{Deserialize, Serialize};
+
+/// Why a pattern session ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
+pub enum PatternSessionEndReason {
+  /// Every track ran out of steps.
+  Completed,
+  /// The session hit its time limit.
+  TimeLimitReached,
+  /// The session was stopped, or its devices were.
+  Cancelled,
+  /// A device in the session went away or rejected a command.
+  Error,
+}
+
+/// Sent once when a pattern session ends, after its features have been stopped.
+#[derive(
+  Debug, ButtplugMessage, ButtplugMessageFinalizer, Clone, PartialEq, Eq, Getters, CopyGetters,
+)]
+#[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
+pub struct PatternSessionEndedV4 {
+  #[cfg_attr(feature = "serialize-json", serde(rename = "Id"))]
+  id: u32,
+  #[cfg_attr(feature = "serialize-json", serde(rename = "SessionId"))]
+  #[getset(get_copy = "pub")]
+  session_id: u32,
+  #[cfg_attr(feature = "serialize-json", serde(rename = "Reason"))]
+  #[getset(get_copy = "pub")]
+  reason: PatternSessionEndReason,
+  /// What went wrong, if the session ended with [PatternSessionEndReason::Error].
+  #[cfg_attr(
+    feature = "serialize-json",
+    serde(
+      rename = "ErrorMessage",
+      default,
+      skip_serializing_if = "Option::is_none"
+    )
+  )]
+  #[getset(get = "pub")]
+  error_message: Option<String>,
+}
+
+impl PatternSessionEndedV4 {
+  pub fn new(
+    session_id: u32,
+    reason: PatternSessionEndReason,
+    error_message: Option<String>,
+  ) -> Self {
+    Self {
+      id: 0,
+      session_id,
+      reason,
+      error_message,
+    }
+  }
+}
+
+impl ButtplugMessageValidator for PatternSessionEndedV4 {
+  fn is_valid(&self) -> Result<(), ButtplugMessageError> {
+    self.is_system_id(self.id)
+  }
+}
@@ -0,0 +1,44 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2024 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Periodic update on a running pattern session.
+
+use super::*;
+use getset::CopyGetters;
+#[cfg(feature = "serialize-json")]
+use serde::{Deserialize, Serialize};
+
+/// Sent every progress interval while a pattern session runs, with the milliseconds since it
+/// started.
+#[derive(Debug, ButtplugMessage, ButtplugMessageFinalizer, Clone, PartialEq, Eq, CopyGetters)]
+#[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
+pub struct PatternSessionProgressV4 {
+  #[cfg_attr(feature = "serialize-json", serde(rename = "Id"))]
+  id: u32,
+  #[cfg_attr(feature = "serialize-json", serde(rename = "SessionId"))]
+  #[getset(get_copy = "pub")]
+  session_id: u32,
+  #[cfg_attr(feature = "serialize-json", serde(rename = "Elapsed"))]
+  #[getset(get_copy = "pub")]
+  elapsed: u32,
+}
+
+impl PatternSessionProgressV4 {
+  pub fn new(session_id: u32, elapsed: u32) -> Self {
+    Self {
+      id: 0,
+      session_id,
+      elapsed,
+    }
+  }
+}
+
+impl ButtplugMessageValidator for PatternSessionProgressV4 {
+  fn is_valid(&self) -> Result<(), ButtplugMessageError> {
+    self.is_system_id(self.id)
+  }
+}
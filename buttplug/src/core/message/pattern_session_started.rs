@@ -0,0 +1,37 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2024 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Reply to a pattern session request.
+
+use super::*;
+use getset::CopyGetters;
+#[cfg(feature = "serialize-json")]
+use serde::{Deserialize, Serialize};
+
+/// Sent in reply to [StartPatternSessionV4] once the session is running, with the id to stop it
+/// by and to match its progress and end messages against.
+#[derive(Debug, ButtplugMessage, ButtplugMessageFinalizer, Clone, PartialEq, Eq, CopyGetters)]
+#[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
+pub struct PatternSessionStartedV4 {
+  #[cfg_attr(feature = "serialize-json", serde(rename = "Id"))]
+  id: u32,
+  #[cfg_attr(feature = "serialize-json", serde(rename = "SessionId"))]
+  #[getset(get_copy = "pub")]
+  session_id: u32,
+}
+
+impl PatternSessionStartedV4 {
+  pub fn new(session_id: u32) -> Self {
+    Self { id: 1, session_id }
+  }
+}
+
+impl ButtplugMessageValidator for PatternSessionStartedV4 {
+  fn is_valid(&self) -> Result<(), ButtplugMessageError> {
+    self.is_not_system_id(self.id)
+  }
+}
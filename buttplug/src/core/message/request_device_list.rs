@@ -6,6 +6,7 @@
 // for full license information.
 
 use super::*;
+use getset::CopyGetters;
 #[cfg(feature = "serialize-json")]
 use serde::{Deserialize, Serialize};
 
@@ -27,3 +28,53 @@ impl ButtplugMessageValidator for RequestDeviceListV0 {
     self.is_not_system_id(self.id)
   }
 }
+
+/// Device list request that can ask for only the changes since a list the client already has.
+#[derive(Debug, ButtplugMessage, ButtplugMessageFinalizer, Clone, PartialEq, Eq, CopyGetters)]
+#[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
+pub struct RequestDeviceListV4 {
+  #[cfg_attr(feature = "serialize-json", serde(rename = "Id"))]
+  id: u32,
+  /// Generation of the last device list the client received. If set, and the server still knows
+  /// what changed since then, the reply only contains those changes. See [DeviceListV4].
+  #[getset(get_copy = "pub")]
+  #[cfg_attr(
+    feature = "serialize-json",
+    serde(
+      rename = "SinceGeneration",
+      default,
+      skip_serializing_if = "Option::is_none"
+    )
+  )]
+  since_generation: Option<u64>,
+}
+
+impl RequestDeviceListV4 {
+  pub fn new(since_generation: Option<u64>) -> Self {
+    Self {
+      id: 1,
+      since_generation,
+    }
+  }
+}
+
+impl Default for RequestDeviceListV4 {
+  fn default() -> Self {
+    Self::new(None)
+  }
+}
+
+impl ButtplugMessageValidator for RequestDeviceListV4 {
+  fn is_valid(&self) -> Result<(), ButtplugMessageError> {
+    self.is_not_system_id(self.id)
+  }
+}
+
+impl From<RequestDeviceListV0> for RequestDeviceListV4 {
+  fn from(value: RequestDeviceListV0) -> Self {
+    Self {
+      id: value.id,
+      since_generation: None,
+    }
+  }
+}
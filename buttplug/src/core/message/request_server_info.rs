@@ -10,6 +10,7 @@ use getset::{CopyGetters, Getters};
 #[cfg(feature = "serialize-json")]
 use serde::{Deserialize, Serialize};
 
+#[cfg(feature = "serialize-json")]
 fn return_version0() -> ButtplugMessageSpecVersion {
   ButtplugMessageSpecVersion::Version0
 }
@@ -50,9 +51,73 @@ impl ButtplugMessageValidator for RequestServerInfoV1 {
   }
 }
 
+/// Handshake request that can also ask for an observer session.
+#[derive(
+  Debug, ButtplugMessage, ButtplugMessageFinalizer, Clone, PartialEq, Eq, Getters, CopyGetters,
+)]
+#[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
+pub struct RequestServerInfoV4 {
+  #[cfg_attr(feature = "serialize-json", serde(rename = "Id"))]
+  id: u32,
+  #[cfg_attr(feature = "serialize-json", serde(rename = "ClientName"))]
+  #[getset(get = "pub")]
+  client_name: String,
+  #[cfg_attr(
+    feature = "serialize-json",
+    serde(rename = "MessageVersion"),
+    serde(default = "return_version0")
+  )]
+  #[getset(get_copy = "pub")]
+  message_version: ButtplugMessageSpecVersion,
+  /// Observer clients receive server events but can't scan for or command devices. Useful for
+  /// dashboards and stream overlays.
+  #[cfg_attr(
+    feature = "serialize-json",
+    serde(
+      rename = "Observer",
+      default,
+      skip_serializing_if = "std::ops::Not::not"
+    )
+  )]
+  #[getset(get_copy = "pub")]
+  observer: bool,
+}
+
+impl RequestServerInfoV4 {
+  pub fn new(
+    client_name: &str,
+    message_version: ButtplugMessageSpecVersion,
+    observer: bool,
+  ) -> Self {
+    Self {
+      id: 1,
+      client_name: client_name.to_string(),
+      message_version,
+      observer,
+    }
+  }
+}
+
+impl ButtplugMessageValidator for RequestServerInfoV4 {
+  fn is_valid(&self) -> Result<(), ButtplugMessageError> {
+    self.is_not_system_id(self.id)
+  }
+}
+
+impl From<RequestServerInfoV1> for RequestServerInfoV4 {
+  fn from(value: RequestServerInfoV1) -> Self {
+    Self {
+      id: value.id,
+      client_name: value.client_name,
+      message_version: value.message_version,
+      observer: false,
+    }
+  }
+}
+
 #[cfg(test)]
 mod test {
-  use super::{ButtplugMessageSpecVersion, RequestServerInfoV1};
+  use super::{ButtplugMessageSpecVersion, RequestServerInfoV1, RequestServerInfoV4};
 
   #[cfg(feature = "serialize-json")]
   #[test]
@@ -94,4 +159,26 @@ mod test {
       old_msg
     );
   }
+
+  #[cfg(feature = "serialize-json")]
+  #[test]
+  fn test_request_server_info_version4_observer_json_conversion() {
+    let observer_json = r#"
+{
+        "Id": 1,
+        "ClientName": "Test Overlay",
+        "MessageVersion": 4,
+        "Observer": true
+}
+        "#;
+    let observer_msg =
+      serde_json::from_str::<RequestServerInfoV4>(observer_json).expect("Test unwrap");
+    assert!(observer_msg.observer());
+    let msg = serde_json::from_str::<RequestServerInfoV4>(
+      &observer_json.replace(",\n        \"Observer\": true", ""),
+    )
+    .expect("Test unwrap");
+    assert!(!msg.observer());
+    assert!(!serde_json::to_string(&msg).unwrap().contains("Observer"));
+  }
 }
@@ -0,0 +1,49 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2024 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Periodic update on a running scan.
+
+use super::*;
+use getset::{CopyGetters, Getters};
+use std::collections::HashMap;
+#[cfg(feature = "serialize-json")]
+use serde::{Deserialize, Serialize};
+
+/// Sent every scanning progress interval while a scan runs, so clients can show that scanning is
+/// alive. Only exists in spec v4, older clients never see it.
+#[derive(
+  Debug, ButtplugMessage, ButtplugMessageFinalizer, Clone, PartialEq, Eq, Getters, CopyGetters,
+)]
+#[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
+pub struct ScanningProgressV4 {
+  #[cfg_attr(feature = "serialize-json", serde(rename = "Id"))]
+  id: u32,
+  /// Devices found by each communication manager since scanning started, keyed by manager name.
+  #[cfg_attr(feature = "serialize-json", serde(rename = "DevicesFound"))]
+  #[getset(get = "pub")]
+  devices_found: HashMap<String, u32>,
+  /// Milliseconds since scanning started.
+  #[cfg_attr(feature = "serialize-json", serde(rename = "Elapsed"))]
+  #[getset(get_copy = "pub")]
+  elapsed: u32,
+}
+
+impl ScanningProgressV4 {
+  pub fn new(devices_found: HashMap<String, u32>, elapsed: u32) -> Self {
+    Self {
+      id: 0,
+      devices_found,
+      elapsed,
+    }
+  }
+}
+
+impl ButtplugMessageValidator for ScanningProgressV4 {
+  fn is_valid(&self) -> Result<(), ButtplugMessageError> {
+    self.is_system_id(self.id)
+  }
+}
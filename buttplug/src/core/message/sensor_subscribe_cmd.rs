@@ -6,11 +6,24 @@
 // for full license information.
 
 use super::*;
-use getset::Getters;
+use getset::{CopyGetters, Getters};
 #[cfg(feature = "serialize-json")]
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, ButtplugDeviceMessage, ButtplugMessageFinalizer, PartialEq, Eq, Clone, Getters)]
+/// How a rate limited sensor subscription handles readings that arrive faster than its max rate.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
+pub enum SensorDecimation {
+  /// Drop extra readings, sending the newest one once enough time has passed.
+  #[default]
+  Latest,
+  /// Send the average of all readings since the last one sent.
+  Average,
+}
+
+#[derive(
+  Debug, ButtplugDeviceMessage, ButtplugMessageFinalizer, PartialEq, Eq, Clone, Getters, CopyGetters,
+)]
 #[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
 pub struct SensorSubscribeCmdV4 {
   #[cfg_attr(feature = "serialize-json", serde(rename = "Id"))]
@@ -23,6 +36,16 @@ pub struct SensorSubscribeCmdV4 {
   #[getset(get = "pub")]
   #[cfg_attr(feature = "serialize-json", serde(rename = "SensorType"))]
   sensor_type: SensorType,
+  /// Most readings per second the server should send for this subscription. Unlimited if unset.
+  #[getset(get_copy = "pub")]
+  #[cfg_attr(
+    feature = "serialize-json",
+    serde(rename = "MaxRate", default, skip_serializing_if = "Option::is_none")
+  )]
+  max_rate: Option<u32>,
+  #[getset(get_copy = "pub")]
+  #[cfg_attr(feature = "serialize-json", serde(rename = "Decimation", default))]
+  decimation: SensorDecimation,
 }
 
 impl SensorSubscribeCmdV4 {
@@ -32,13 +55,28 @@ impl SensorSubscribeCmdV4 {
       device_index,
       feature_index,
       sensor_type,
+      max_rate: None,
+      decimation: SensorDecimation::default(),
     }
   }
+
+  /// Limit the subscription to `max_rate` readings per second, thinned out using `decimation`.
+  pub fn with_max_rate(mut self, max_rate: u32, decimation: SensorDecimation) -> Self {
+    self.max_rate = Some(max_rate);
+    self.decimation = decimation;
+    self
+  }
 }
 
 impl ButtplugMessageValidator for SensorSubscribeCmdV4 {
   fn is_valid(&self) -> Result<(), ButtplugMessageError> {
-    self.is_not_system_id(self.id)
+    self.is_not_system_id(self.id)?;
+    if self.max_rate == Some(0) {
+      return Err(ButtplugMessageError::InvalidMessageContents(
+        "SensorSubscribeCmd MaxRate must be greater than 0.".to_owned(),
+      ));
+    }
+    Ok(())
   }
 }
 
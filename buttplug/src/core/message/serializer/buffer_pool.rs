@@ -0,0 +1,117 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2024 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Buffer reuse for outgoing serialized messages.
+//!
+//! When streaming commands at high rates (100+ Hz linear updates over websockets, for instance),
+//! allocating a fresh string for every outgoing frame ends up dominating the cost of the send path.
+//! Serializers pull their output buffers from a process-wide pool, and transports that can tell
+//! when they're done with a frame hand the buffer back via [PooledText] instead of dropping it.
+//!
+//! The pool is bounded in both buffer count and buffer size, so a burst of large messages (device
+//! lists, for instance) won't leave memory pinned for the life of the process.
+
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::sync::Mutex;
+
+/// Maximum number of buffers held for reuse.
+const MAX_POOLED_BUFFERS: usize = 64;
+/// Buffers that have grown larger than this are dropped instead of being pooled.
+const MAX_POOLED_BUFFER_CAPACITY: usize = 16 * 1024;
+/// Starting capacity for new buffers, big enough for the usual command or reading message.
+const INITIAL_BUFFER_CAPACITY: usize = 256;
+
+static OUTGOING_BUFFER_POOL: Lazy<Mutex<Vec<String>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+/// Take an empty buffer from the pool, or allocate a new one if the pool is empty.
+fn take_buffer() -> String {
+  OUTGOING_BUFFER_POOL
+    .lock()
+    .expect("Pool lock is never held across a panic")
+    .pop()
+    .unwrap_or_else(|| String::with_capacity(INITIAL_BUFFER_CAPACITY))
+}
+
+/// Return a buffer to the pool for reuse.
+fn return_buffer(mut buffer: String) {
+  if buffer.capacity() > MAX_POOLED_BUFFER_CAPACITY {
+    return;
+  }
+  buffer.clear();
+  let mut pool = OUTGOING_BUFFER_POOL
+    .lock()
+    .expect("Pool lock is never held across a panic");
+  if pool.len() < MAX_POOLED_BUFFERS {
+    pool.push(buffer);
+  }
+}
+
+/// Serialize a value to JSON, writing into a pooled buffer.
+pub(crate) fn to_pooled_json<T>(value: &T) -> String
+where
+  T: Serialize + ?Sized,
+{
+  let mut bytes = take_buffer().into_bytes();
+  serde_json::to_writer(&mut bytes, value).expect("Infallible serialization");
+  String::from_utf8(bytes).expect("serde_json always outputs valid UTF-8")
+}
+
+/// Owner for a serialized frame that returns its buffer to the pool when dropped.
+///
+/// Useful for transports that take ownership of frame data (e.g. as a reference counted byte
+/// buffer) and drop it whenever the write completes.
+#[derive(Debug)]
+pub struct PooledText(Option<String>);
+
+impl PooledText {
+  pub fn new(text: String) -> Self {
+    Self(Some(text))
+  }
+}
+
+impl AsRef<[u8]> for PooledText {
+  fn as_ref(&self) -> &[u8] {
+    self
+      .0
+      .as_ref()
+      .expect("Only taken on drop")
+      .as_bytes()
+  }
+}
+
+impl Drop for PooledText {
+  fn drop(&mut self) {
+    if let Some(text) = self.0.take() {
+      return_buffer(text);
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{to_pooled_json, PooledText, MAX_POOLED_BUFFER_CAPACITY};
+
+  #[test]
+  fn test_pooled_json_matches_serde_json() {
+    let value = vec![("Ok", 1u32), ("Ping", 2)];
+    let text = to_pooled_json(&value);
+    assert_eq!(text, serde_json::to_string(&value).unwrap());
+    drop(PooledText::new(text));
+    // Reused buffers must come back empty.
+    let text = to_pooled_json(&value);
+    assert_eq!(text, serde_json::to_string(&value).unwrap());
+  }
+
+  #[test]
+  fn test_oversized_buffers_are_not_pooled() {
+    let text = to_pooled_json(&"a".repeat(MAX_POOLED_BUFFER_CAPACITY * 2));
+    assert!(text.capacity() > MAX_POOLED_BUFFER_CAPACITY);
+    drop(PooledText::new(text));
+    assert!(to_pooled_json(&0).capacity() <= MAX_POOLED_BUFFER_CAPACITY);
+  }
+}
@@ -5,7 +5,12 @@
 // Licensed under the BSD 3-Clause license. See LICENSE file in the project root
 // for full license information.
 
-use super::{ButtplugMessageSerializer, ButtplugSerializedMessage, ButtplugSerializerError};
+use super::{
+  buffer_pool::to_pooled_json,
+  ButtplugMessageSerializer,
+  ButtplugSerializedMessage,
+  ButtplugSerializerError,
+};
 use crate::core::{
   errors::{ButtplugError, ButtplugHandshakeError, ButtplugMessageError},
   message::{
@@ -72,14 +77,14 @@ pub fn msg_to_protocol_json<T>(msg: T) -> String
 where
   T: ButtplugMessage + Serialize + Deserialize<'static>,
 {
-  serde_json::to_string(&[&msg]).expect("Infallible serialization")
+  to_pooled_json(&[&msg])
 }
 
 pub fn vec_to_protocol_json<T>(msg: &[T]) -> String
 where
   T: ButtplugMessage + Serialize + Deserialize<'static>,
 {
-  serde_json::to_string(msg).expect("Infallible serialization")
+  to_pooled_json(msg)
 }
 
 pub fn deserialize_to_message<T>(
@@ -376,7 +381,11 @@ impl ButtplugMessageSerializer for ButtplugClientJSONSerializer {
 #[cfg(test)]
 mod test {
   use super::*;
-  use crate::core::message::{RequestServerInfoV1, BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION};
+  use crate::core::message::{
+    DeviceAxis,
+    RequestServerInfoV1,
+    BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION,
+  };
 
   #[test]
   fn test_correct_message_version() {
@@ -506,6 +515,38 @@ mod test {
     ));
   }
 
+  #[test]
+  fn test_axis_cmd_message() {
+    let json = r#"[
+        {
+          "RequestServerInfo": {
+              "Id": 1,
+              "ClientName": "Test Client",
+              "MessageVersion": 4
+          }
+        },
+        {
+          "AxisCmd": {
+              "Id": 2,
+              "DeviceIndex": 0,
+              "Axes": [
+                { "Axis": "Stroke", "Duration": 500, "Position": 0.5 },
+                { "Axis": "Roll", "Duration": 500, "Position": 1.0 }
+              ]
+          }
+        }
+    ]"#;
+    let serializer = ButtplugServerJSONSerializer::default();
+    let messages = serializer
+      .deserialize(&ButtplugSerializedMessage::Text(json.to_owned()))
+      .expect("Infallible deserialization");
+    let ButtplugClientMessageVariant::V4(ButtplugClientMessageV4::AxisCmd(msg)) = &messages[1]
+    else {
+      panic!("Should have gotten an AxisCmd, got {:?}", messages[1]);
+    };
+    assert_eq!(msg.axes()[1].axis(), DeviceAxis::Roll);
+  }
+
   #[test]
   fn test_client_incorrect_messages() {
     let incorrect_incoming_messages = vec![
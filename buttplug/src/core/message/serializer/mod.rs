@@ -7,9 +7,13 @@
 
 //! Message de/serialization handling
 
+#[cfg(feature = "serialize-json")]
+mod buffer_pool;
 #[cfg(feature = "serialize-json")]
 mod json_serializer;
 #[cfg(feature = "serialize-json")]
+pub use buffer_pool::PooledText;
+#[cfg(feature = "serialize-json")]
 pub use json_serializer::{
   vec_to_protocol_json,
   ButtplugClientJSONSerializer,
@@ -17,11 +21,13 @@ pub use json_serializer::{
   ButtplugServerJSONSerializer,
 };
 
+#[cfg(feature = "serialize-json")]
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 pub type ButtplugSerializerResult<T> = Result<T, ButtplugSerializerError>;
 
-#[derive(Debug, Error, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
 pub enum ButtplugSerializerError {
   // jsonschema hands back a vector of errors that isn't easy to encase, so we just
   // turn it into a big string and pass that back.
@@ -36,6 +42,15 @@ pub enum ButtplugSerializerError {
   TextDeserializationError,
   #[error("Message version not received, can't figure out which spec version to de/serialize to.")]
   MessageSpecVersionNotReceived,
+  /// Frame failed authentication, sequence or timestamp checks, and may be forged or a replay.
+  #[error("Frame rejected by replay protection: {0}")]
+  ReplayProtectionError(String),
+  /// Compressed frame could not be decompressed.
+  #[error("Cannot decompress frame: {0}")]
+  CompressionError(String),
+  /// Frame is bigger than the given limit in bytes, once decompressed.
+  #[error("Frame is larger than the {0} byte limit")]
+  OversizedFrame(u64),
 }
 
 #[derive(Debug, Display, Clone, PartialEq, Eq)]
@@ -0,0 +1,120 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2024 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Request for the server to run a timed pattern on its own clock.
+
+use super::*;
+use getset::{CopyGetters, Getters};
+#[cfg(feature = "serialize-json")]
+use serde::{Deserialize, Serialize};
+
+/// Hold a scalar actuator at a level for a number of milliseconds.
+#[derive(Debug, PartialEq, Clone, Copy, CopyGetters)]
+#[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
+#[getset(get_copy = "pub")]
+pub struct PatternStepV4 {
+  #[cfg_attr(feature = "serialize-json", serde(rename = "Level"))]
+  level: f64,
+  #[cfg_attr(feature = "serialize-json", serde(rename = "Duration"))]
+  duration: u32,
+}
+
+impl PatternStepV4 {
+  pub fn new(level: f64, duration: u32) -> Self {
+    Self { level, duration }
+  }
+}
+
+/// Steps to run, in order, on one scalar actuator of a device. Spec v3 clients address the
+/// actuator with the index they'd use in a ScalarCmd, spec v4 clients with its feature index.
+#[derive(Debug, PartialEq, Clone, Getters, CopyGetters)]
+#[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
+pub struct PatternTrackV4 {
+  #[cfg_attr(feature = "serialize-json", serde(rename = "DeviceIndex"))]
+  #[getset(get_copy = "pub")]
+  device_index: u32,
+  #[cfg_attr(feature = "serialize-json", serde(rename = "FeatureIndex"))]
+  #[getset(get_copy = "pub")]
+  feature_index: u32,
+  #[cfg_attr(feature = "serialize-json", serde(rename = "Steps"))]
+  #[getset(get = "pub")]
+  steps: Vec<PatternStepV4>,
+}
+
+impl PatternTrackV4 {
+  pub fn new(device_index: u32, feature_index: u32, steps: Vec<PatternStepV4>) -> Self {
+    Self {
+      device_index,
+      feature_index,
+      steps,
+    }
+  }
+}
+
+/// Hands the server a set of tracks to run together, for up to `time_limit` milliseconds. The
+/// server replies with [PatternSessionStartedV4], then sends [PatternSessionProgressV4] updates
+/// and a final [PatternSessionEndedV4].
+#[derive(Debug, ButtplugMessage, ButtplugMessageFinalizer, PartialEq, Clone, Getters, CopyGetters)]
+#[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
+pub struct StartPatternSessionV4 {
+  #[cfg_attr(feature = "serialize-json", serde(rename = "Id"))]
+  id: u32,
+  #[cfg_attr(feature = "serialize-json", serde(rename = "Tracks"))]
+  #[getset(get = "pub")]
+  tracks: Vec<PatternTrackV4>,
+  #[cfg_attr(feature = "serialize-json", serde(rename = "TimeLimit"))]
+  #[getset(get_copy = "pub")]
+  time_limit: u32,
+  /// Start tracks over when they run out of steps, running until the time limit.
+  #[cfg_attr(feature = "serialize-json", serde(rename = "Repeat", default))]
+  #[getset(get_copy = "pub")]
+  repeat: bool,
+  /// Milliseconds between progress updates. Left to the server if not set.
+  #[cfg_attr(
+    feature = "serialize-json",
+    serde(
+      rename = "ProgressInterval",
+      default,
+      skip_serializing_if = "Option::is_none"
+    )
+  )]
+  #[getset(get_copy = "pub")]
+  progress_interval: Option<u32>,
+}
+
+impl StartPatternSessionV4 {
+  pub fn new(
+    tracks: Vec<PatternTrackV4>,
+    time_limit: u32,
+    repeat: bool,
+    progress_interval: Option<u32>,
+  ) -> Self {
+    Self {
+      id: 1,
+      tracks,
+      time_limit,
+      repeat,
+      progress_interval,
+    }
+  }
+}
+
+impl ButtplugMessageValidator for StartPatternSessionV4 {
+  fn is_valid(&self) -> Result<(), ButtplugMessageError> {
+    self.is_not_system_id(self.id)?;
+    for step in self.tracks.iter().flat_map(|track| track.steps.iter()) {
+      self.is_in_command_range(
+        step.level,
+        format!(
+          "Pattern step level {} is invalid, should be between 0.0 and 1.0",
+          step.level
+        ),
+      )?;
+    }
+    Ok(())
+  }
+}
@@ -7,16 +7,20 @@
 
 //! Protocol message and error definitions.
 
+#[cfg(feature = "async-core")]
 pub mod connector;
 pub mod errors;
 pub mod message;
 
 use errors::ButtplugError;
+#[cfg(feature = "async-core")]
 use futures::future::{self, BoxFuture, FutureExt};
 
 pub type ButtplugResult<T = ()> = Result<T, ButtplugError>;
+#[cfg(feature = "async-core")]
 pub type ButtplugResultFuture<T = ()> = BoxFuture<'static, ButtplugResult<T>>;
 
+#[cfg(feature = "async-core")]
 impl<T> From<ButtplugError> for BoxFuture<'static, Result<T, ButtplugError>>
 where
   T: Send + 'static,
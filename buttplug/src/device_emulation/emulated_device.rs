@@ -0,0 +1,366 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2024 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+use crate::{
+  core::{
+    errors::ButtplugDeviceError,
+    message::{
+      AxisSubcommandV4,
+      ButtplugDeviceCommandMessageUnion,
+      DeviceFeature,
+      RotationSubcommandV4,
+      ScalarSubcommandV4,
+      SensorReadingV4,
+      SensorType,
+      VectorSubcommandV4,
+    },
+  },
+  util::async_manager,
+};
+use dashmap::DashSet;
+use futures::{SinkExt, StreamExt};
+use serde::Serialize;
+use std::sync::{
+  atomic::{AtomicBool, Ordering},
+  Arc,
+};
+use tokio::sync::mpsc::{channel, Receiver, Sender};
+use tokio_tungstenite::{tungstenite::Message, MaybeTlsStream, WebSocketStream};
+use tokio_util::sync::CancellationToken;
+
+/// Protocol servers need to use for emulated devices.
+const PASSTHRU_PROTOCOL: &str = "buttplug-passthru";
+/// Device configuration format version that [EmulatedDeviceBuilder::user_config] produces. Servers
+/// only check the major version.
+const DEVICE_CONFIG_MAJOR_VERSION: u32 = 3;
+
+pub type ScalarCallback = Arc<dyn Fn(&ScalarSubcommandV4) + Send + Sync>;
+pub type LinearCallback = Arc<dyn Fn(&VectorSubcommandV4) + Send + Sync>;
+pub type RotateCallback = Arc<dyn Fn(&RotationSubcommandV4) + Send + Sync>;
+pub type AxisCallback = Arc<dyn Fn(&AxisSubcommandV4) + Send + Sync>;
+pub type StopCallback = Arc<dyn Fn() + Send + Sync>;
+
+// Handshake packet the server's websocket device manager expects before anything else.
+#[derive(Serialize)]
+struct EmulatedDeviceInitInfo<'a> {
+  identifier: &'a str,
+  address: &'a str,
+  version: u32,
+}
+
+#[derive(Default, Clone)]
+struct EmulatedDeviceCallbacks {
+  scalar: Option<ScalarCallback>,
+  linear: Option<LinearCallback>,
+  rotate: Option<RotateCallback>,
+  axis: Option<AxisCallback>,
+  stop: Option<StopCallback>,
+}
+
+impl EmulatedDeviceCallbacks {
+  fn dispatch(&self, message: &ButtplugDeviceCommandMessageUnion, sensors: &DashSet<u32>) {
+    match message {
+      ButtplugDeviceCommandMessageUnion::ScalarCmd(msg) => {
+        if let Some(callback) = &self.scalar {
+          for scalar in msg.scalars() {
+            callback(scalar);
+          }
+        }
+      }
+      ButtplugDeviceCommandMessageUnion::LinearCmd(msg) => {
+        if let Some(callback) = &self.linear {
+          for vector in msg.vectors() {
+            callback(vector);
+          }
+        }
+      }
+      ButtplugDeviceCommandMessageUnion::RotateCmd(msg) => {
+        if let Some(callback) = &self.rotate {
+          for rotation in msg.rotations() {
+            callback(rotation);
+          }
+        }
+      }
+      ButtplugDeviceCommandMessageUnion::AxisCmd(msg) => {
+        if let Some(callback) = &self.axis {
+          for axis in msg.axes() {
+            callback(axis);
+          }
+        }
+      }
+      ButtplugDeviceCommandMessageUnion::StopDeviceCmd(_) => {
+        if let Some(callback) = &self.stop {
+          callback();
+        }
+      }
+      ButtplugDeviceCommandMessageUnion::SensorSubscribeCmd(msg) => {
+        sensors.insert(*msg.feature_index());
+      }
+      ButtplugDeviceCommandMessageUnion::SensorUnsubscribeCmd(msg) => {
+        sensors.remove(msg.feature_index());
+      }
+      _ => debug!("Emulated device ignoring unsupported message {:?}", message),
+    }
+  }
+}
+
+/// Builds an [EmulatedDevice], declaring its features and the callbacks that run its actuators.
+///
+/// Callbacks run on the device's connection task, so anything slow should be handed off elsewhere
+/// instead of blocking them. Actuator values arrive as clients sent them, before any step range
+/// conversion, so scalars, speeds and positions are all between 0.0 and 1.0.
+///
+/// Servers repeat the last command they sent every so often as a keepalive, so callbacks should
+/// expect to see the same values more than once.
+#[derive(Clone)]
+pub struct EmulatedDeviceBuilder {
+  identifier: String,
+  address: String,
+  name: String,
+  features: Vec<DeviceFeature>,
+  callbacks: EmulatedDeviceCallbacks,
+}
+
+impl EmulatedDeviceBuilder {
+  /// `identifier` is what the server matches its configuration against, and `address` tells
+  /// devices with the same identifier apart.
+  pub fn new(identifier: &str, address: &str) -> Self {
+    Self {
+      identifier: identifier.to_owned(),
+      address: address.to_owned(),
+      name: identifier.to_owned(),
+      features: vec![],
+      callbacks: EmulatedDeviceCallbacks::default(),
+    }
+  }
+
+  /// Device name shown to clients. Defaults to the identifier.
+  pub fn name(&mut self, name: &str) -> &mut Self {
+    self.name = name.to_owned();
+    self
+  }
+
+  /// Add a feature to the device. Features are indexed in the order they're added.
+  pub fn feature(&mut self, feature: DeviceFeature) -> &mut Self {
+    self.features.push(feature);
+    self
+  }
+
+  pub fn on_scalar<F>(&mut self, callback: F) -> &mut Self
+  where
+    F: Fn(&ScalarSubcommandV4) + Send + Sync + 'static,
+  {
+    self.callbacks.scalar = Some(Arc::new(callback));
+    self
+  }
+
+  pub fn on_linear<F>(&mut self, callback: F) -> &mut Self
+  where
+    F: Fn(&VectorSubcommandV4) + Send + Sync + 'static,
+  {
+    self.callbacks.linear = Some(Arc::new(callback));
+    self
+  }
+
+  pub fn on_rotate<F>(&mut self, callback: F) -> &mut Self
+  where
+    F: Fn(&RotationSubcommandV4) + Send + Sync + 'static,
+  {
+    self.callbacks.rotate = Some(Arc::new(callback));
+    self
+  }
+
+  pub fn on_axis<F>(&mut self, callback: F) -> &mut Self
+  where
+    F: Fn(&AxisSubcommandV4) + Send + Sync + 'static,
+  {
+    self.callbacks.axis = Some(Arc::new(callback));
+    self
+  }
+
+  /// Called when the server stops the device, in place of per-actuator commands.
+  pub fn on_stop<F>(&mut self, callback: F) -> &mut Self
+  where
+    F: Fn() + Send + Sync + 'static,
+  {
+    self.callbacks.stop = Some(Arc::new(callback));
+    self
+  }
+
+  /// User device configuration file, as JSON, that lets a server use this device. The device is
+  /// given `device_index` on the server, so pick one that isn't already taken there.
+  pub fn user_config(&self, device_index: u32) -> String {
+    serde_json::json!({
+      "version": {
+        "major": DEVICE_CONFIG_MAJOR_VERSION,
+        "minor": 0
+      },
+      "user-configs": {
+        "protocols": {
+          PASSTHRU_PROTOCOL: {
+            "communication": [{
+              "websocket": {
+                "name": self.identifier
+              }
+            }]
+          }
+        },
+        "devices": [{
+          "identifier": {
+            "protocol": PASSTHRU_PROTOCOL,
+            "identifier": self.identifier,
+            "address": self.address
+          },
+          "config": {
+            "name": self.name,
+            "features": self.features,
+            "user-config": {
+              "allow": false,
+              "deny": false,
+              "index": device_index
+            }
+          }
+        }]
+      }
+    })
+    .to_string()
+  }
+
+  /// Connect to a server's websocket device manager at `url`, e.g. `ws://127.0.0.1:54817`.
+  pub async fn connect(&self, url: &str) -> Result<EmulatedDevice, ButtplugDeviceError> {
+    let (mut stream, _) = tokio_tungstenite::connect_async(url)
+      .await
+      .map_err(|err| ButtplugDeviceError::DeviceConnectionError(format!("{:?}", err)))?;
+    let init_info = serde_json::to_string(&EmulatedDeviceInitInfo {
+      identifier: &self.identifier,
+      address: &self.address,
+      version: 0,
+    })
+    .expect("Type is always serializable");
+    stream
+      .send(Message::Text(init_info.into()))
+      .await
+      .map_err(|err| ButtplugDeviceError::DeviceConnectionError(format!("{:?}", err)))?;
+    let (outgoing_sender, outgoing_receiver) = channel(256);
+    let connected = Arc::new(AtomicBool::new(true));
+    let subscribed_sensors = Arc::new(DashSet::new());
+    let cancel_token = CancellationToken::new();
+    async_manager::spawn(run_connection_loop(
+      stream,
+      self.callbacks.clone(),
+      outgoing_receiver,
+      subscribed_sensors.clone(),
+      connected.clone(),
+      cancel_token.child_token(),
+    ));
+    Ok(EmulatedDevice {
+      address: self.address.clone(),
+      outgoing_sender,
+      connected,
+      subscribed_sensors,
+      cancel_token,
+    })
+  }
+}
+
+/// The device end of a connection to a Buttplug server, created by [EmulatedDeviceBuilder].
+///
+/// The connection is closed when the device is dropped.
+pub struct EmulatedDevice {
+  address: String,
+  outgoing_sender: Sender<String>,
+  connected: Arc<AtomicBool>,
+  subscribed_sensors: Arc<DashSet<u32>>,
+  cancel_token: CancellationToken,
+}
+
+impl EmulatedDevice {
+  pub fn connected(&self) -> bool {
+    self.connected.load(Ordering::Relaxed)
+  }
+
+  /// True if a client is subscribed to the sensor at `feature_index`. Readings for sensors nobody
+  /// is subscribed to are dropped by the server, so there's no point in taking them.
+  pub fn sensor_subscribed(&self, feature_index: u32) -> bool {
+    self.subscribed_sensors.contains(&feature_index)
+  }
+
+  /// Send a sensor reading for the feature at `feature_index`.
+  pub async fn send_sensor_reading(
+    &self,
+    feature_index: u32,
+    sensor_type: SensorType,
+    data: Vec<i32>,
+  ) -> Result<(), ButtplugDeviceError> {
+    // The server fills in the device index, since we don't know what it is.
+    let reading = SensorReadingV4::new(0, feature_index, sensor_type, data);
+    self
+      .outgoing_sender
+      .send(serde_json::to_string(&reading).expect("Type is always serializable"))
+      .await
+      .map_err(|_| ButtplugDeviceError::DeviceNotConnected(self.address.clone()))
+  }
+
+  pub fn disconnect(&self) {
+    self.cancel_token.cancel();
+  }
+}
+
+impl Drop for EmulatedDevice {
+  fn drop(&mut self) {
+    self.cancel_token.cancel();
+  }
+}
+
+async fn run_connection_loop(
+  stream: WebSocketStream<MaybeTlsStream<tokio::net::TcpStream>>,
+  callbacks: EmulatedDeviceCallbacks,
+  mut outgoing_receiver: Receiver<String>,
+  subscribed_sensors: Arc<DashSet<u32>>,
+  connected: Arc<AtomicBool>,
+  cancel_token: CancellationToken,
+) {
+  let (mut sink, mut source) = stream.split();
+  loop {
+    tokio::select! {
+      _ = cancel_token.cancelled() => break,
+      outgoing = outgoing_receiver.recv() => {
+        let Some(outgoing) = outgoing else {
+          break;
+        };
+        if let Err(err) = sink.send(Message::Text(outgoing.into())).await {
+          error!("Cannot send to server, considering connection closed: {:?}", err);
+          break;
+        }
+      }
+      incoming = source.next() => {
+        let data = match incoming {
+          Some(Ok(Message::Binary(data))) => data.to_vec(),
+          Some(Ok(Message::Text(text))) => text.as_bytes().to_vec(),
+          Some(Ok(Message::Close(_))) | None => {
+            info!("Server closed emulated device connection.");
+            break;
+          }
+          Some(Ok(_)) => continue,
+          Some(Err(err)) => {
+            error!("Error from server, assuming disconnection: {:?}", err);
+            break;
+          }
+        };
+        match serde_json::from_slice::<ButtplugDeviceCommandMessageUnion>(&data) {
+          Ok(message) => callbacks.dispatch(&message, &subscribed_sensors),
+          Err(err) => warn!("Cannot parse message from server: {:?}", err),
+        }
+      }
+    }
+  }
+  connected.store(false, Ordering::Relaxed);
+  subscribed_sensors.clear();
+  if let Err(err) = sink.close().await {
+    debug!("Error closing emulated device connection: {:?}", err);
+  }
+}
@@ -0,0 +1,32 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2024 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Device emulation, for running the device end of a Buttplug connection.
+//!
+//! DIY hardware usually ends up with its own protocol, and then needs a protocol implementation in
+//! the server before anything can talk to it. An [EmulatedDevice] skips that step. It connects to a
+//! server's websocket device manager (the `websocket-server-manager` feature, listening on port
+//! 54817 by default), and talks the `buttplug-passthru` protocol: the server forwards device
+//! command messages as they are, and the emulated device calls back into your code for each
+//! actuator command it receives. Sensor readings go the other way, and are passed on to any clients
+//! subscribed to the sensor.
+//!
+//! Servers only talk to devices they have configurations for, so the features a device declares
+//! also need to be in the server's user device configuration. [EmulatedDeviceBuilder::user_config]
+//! builds that configuration for you.
+
+mod emulated_device;
+
+pub use emulated_device::{
+  AxisCallback,
+  EmulatedDevice,
+  EmulatedDeviceBuilder,
+  LinearCallback,
+  RotateCallback,
+  ScalarCallback,
+  StopCallback,
+};
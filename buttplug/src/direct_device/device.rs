@@ -0,0 +1,189 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2024 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+use crate::{
+  core::{
+    errors::{ButtplugDeviceError, ButtplugError},
+    message::{
+      ActuatorType,
+      ButtplugActuatorFeatureMessageType,
+      ButtplugDeviceCommandMessageUnion,
+      ButtplugDeviceMessageType,
+      ButtplugSensorFeatureMessageType,
+      ButtplugServerMessageV4,
+      DeviceFeature,
+      DeviceMessageInfoV4,
+      FeatureType,
+      LinearCmdV4,
+      RotateCmdV4,
+      RotationSubcommandV4,
+      ScalarCmdV4,
+      ScalarSubcommandV4,
+      SensorReadCmdV4,
+      SensorType,
+      StopDeviceCmdV0,
+      VectorSubcommandV4,
+    },
+    ButtplugResultFuture,
+  },
+  server::device::ServerDeviceManager,
+};
+use futures::{future, FutureExt};
+use std::sync::Arc;
+
+/// Handle for controlling a single device found by a
+/// [DirectDeviceManager](super::DirectDeviceManager).
+///
+/// Commands go through the same checks they would if they came from a client. Once the device
+/// disconnects, they fail with [ButtplugDeviceError::DeviceNotAvailable].
+#[derive(Clone)]
+pub struct DirectDevice {
+  device_manager: Arc<ServerDeviceManager>,
+  info: DeviceMessageInfoV4,
+}
+
+impl DirectDevice {
+  pub(super) fn new(device_manager: Arc<ServerDeviceManager>, info: DeviceMessageInfoV4) -> Self {
+    Self {
+      device_manager,
+      info,
+    }
+  }
+
+  pub fn index(&self) -> u32 {
+    self.info.device_index()
+  }
+
+  pub fn name(&self) -> &str {
+    self.info.device_name()
+  }
+
+  /// Name the user gave the device in their device configuration, if any.
+  pub fn display_name(&self) -> Option<&str> {
+    self.info.device_display_name().as_deref()
+  }
+
+  pub fn features(&self) -> &[DeviceFeature] {
+    self.info.device_features()
+  }
+
+  pub fn connected(&self) -> bool {
+    self.device_manager.devices().contains_key(&self.index())
+  }
+
+  /// Set all vibrators on the device to `level`, between 0.0 and 1.0.
+  pub fn vibrate(&self, level: f64) -> ButtplugResultFuture {
+    self.scalar(ActuatorType::Vibrate, level)
+  }
+
+  /// Set every feature of type `actuator_type` on the device to `level`, between 0.0 and 1.0.
+  pub fn scalar(&self, actuator_type: ActuatorType, level: f64) -> ButtplugResultFuture {
+    let feature_type = FeatureType::from(actuator_type);
+    let scalars: Vec<ScalarSubcommandV4> = self
+      .actuator_indexes(ButtplugActuatorFeatureMessageType::ScalarCmd)
+      .filter(|index| *self.features()[*index as usize].feature_type() == feature_type)
+      .map(|index| ScalarSubcommandV4::new(index, level, actuator_type))
+      .collect();
+    if scalars.is_empty() {
+      return unsupported(ButtplugDeviceMessageType::ScalarCmd);
+    }
+    self.send(ScalarCmdV4::new(self.index(), scalars).into())
+  }
+
+  /// Rotate all rotators on the device at `speed`, between 0.0 and 1.0.
+  pub fn rotate(&self, speed: f64, clockwise: bool) -> ButtplugResultFuture {
+    let rotations: Vec<RotationSubcommandV4> = self
+      .actuator_indexes(ButtplugActuatorFeatureMessageType::RotateCmd)
+      .map(|index| RotationSubcommandV4::new(index, speed, clockwise))
+      .collect();
+    if rotations.is_empty() {
+      return unsupported(ButtplugDeviceMessageType::RotateCmd);
+    }
+    self.send(RotateCmdV4::new(self.index(), rotations).into())
+  }
+
+  /// Move all linear actuators on the device to `position`, between 0.0 and 1.0, over `duration`
+  /// milliseconds.
+  pub fn linear(&self, duration: u32, position: f64) -> ButtplugResultFuture {
+    let vectors: Vec<VectorSubcommandV4> = self
+      .actuator_indexes(ButtplugActuatorFeatureMessageType::LinearCmd)
+      .map(|index| VectorSubcommandV4::new(index, duration, position))
+      .collect();
+    if vectors.is_empty() {
+      return unsupported(ButtplugDeviceMessageType::LinearCmd);
+    }
+    self.send(LinearCmdV4::new(self.index(), vectors).into())
+  }
+
+  pub fn stop(&self) -> ButtplugResultFuture {
+    self.send(StopDeviceCmdV0::new(self.index()).into())
+  }
+
+  /// Battery level, between 0.0 and 1.0.
+  pub fn battery_level(&self) -> ButtplugResultFuture<f64> {
+    let Some(index) = self.features().iter().position(|feature| {
+      *feature.feature_type() == FeatureType::Battery
+        && feature.sensor().as_ref().is_some_and(|sensor| {
+          sensor
+            .messages()
+            .contains(&ButtplugSensorFeatureMessageType::SensorReadCmd)
+        })
+    }) else {
+      return unsupported(ButtplugDeviceMessageType::SensorReadCmd);
+    };
+    let fut = self.device_manager.send_device_command(
+      SensorReadCmdV4::new(self.index(), index as u32, SensorType::Battery).into(),
+    );
+    async move {
+      match fut.await? {
+        ButtplugServerMessageV4::SensorReading(reading) if !reading.data().is_empty() => {
+          Ok(reading.data()[0] as f64 / 100.0)
+        }
+        msg => Err(
+          ButtplugDeviceError::DeviceCommunicationError(format!(
+            "Unexpected reply to battery read: {:?}",
+            msg
+          ))
+          .into(),
+        ),
+      }
+    }
+    .boxed()
+  }
+
+  fn actuator_indexes(
+    &self,
+    message_type: ButtplugActuatorFeatureMessageType,
+  ) -> impl Iterator<Item = u32> + '_ {
+    self
+      .features()
+      .iter()
+      .enumerate()
+      .filter(move |(_, feature)| {
+        feature
+          .actuator()
+          .as_ref()
+          .is_some_and(|actuator| actuator.messages().contains(&message_type))
+      })
+      .map(|(index, _)| index as u32)
+  }
+
+  fn send(&self, msg: ButtplugDeviceCommandMessageUnion) -> ButtplugResultFuture {
+    let fut = self.device_manager.send_device_command(msg);
+    async move { fut.await.map(|_| ()) }.boxed()
+  }
+}
+
+fn unsupported<T>(message_type: ButtplugDeviceMessageType) -> ButtplugResultFuture<T>
+where
+  T: Send + 'static,
+{
+  future::ready(Err(ButtplugError::from(
+    ButtplugDeviceError::MessageNotSupported(message_type),
+  )))
+  .boxed()
+}
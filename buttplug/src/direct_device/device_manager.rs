@@ -0,0 +1,148 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2024 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+use super::DirectDevice;
+use crate::{
+  core::{
+    message::{ButtplugServerMessageV4, DeviceMessageInfoV4},
+    ButtplugResultFuture,
+  },
+  server::{
+    device::{
+      configuration::DeviceConfigurationManager,
+      hardware::communication::HardwareCommunicationManagerBuilder,
+      ServerDeviceManager,
+      ServerDeviceManagerBuilder,
+    },
+    ButtplugServerError,
+    ButtplugServerResultFuture,
+  },
+  util::device_configuration::load_protocol_configs,
+};
+use futures::{FutureExt, Stream, StreamExt};
+use std::sync::Arc;
+
+/// Something that happened to the devices a [DirectDeviceManager] knows about.
+#[derive(Clone)]
+pub enum DirectDeviceEvent {
+  DeviceAdded(DirectDevice),
+  /// Index of the device that went away. Handles to it will fail any commands from here on.
+  DeviceRemoved(u32),
+  ScanningFinished,
+}
+
+/// Configures and creates [DirectDeviceManager] instances.
+pub struct DirectDeviceManagerBuilder {
+  device_manager_builder: ServerDeviceManagerBuilder,
+}
+
+impl Default for DirectDeviceManagerBuilder {
+  /// Builder using the device configuration that ships with the library, with no communication
+  /// managers added yet.
+  fn default() -> Self {
+    let dcm = load_protocol_configs(&None, &None, false)
+      .and_then(|mut builder| builder.finish())
+      .expect("Built in device configuration should always load.");
+    Self::new(dcm)
+  }
+}
+
+impl DirectDeviceManagerBuilder {
+  /// Builder using a device configuration manager set up elsewhere, usually to add a user device
+  /// configuration.
+  pub fn new(device_configuration_manager: DeviceConfigurationManager) -> Self {
+    Self {
+      device_manager_builder: ServerDeviceManagerBuilder::new(device_configuration_manager),
+    }
+  }
+
+  pub fn comm_manager<T>(&mut self, builder: T) -> &mut Self
+  where
+    T: HardwareCommunicationManagerBuilder + 'static,
+  {
+    self.device_manager_builder.comm_manager(builder);
+    self
+  }
+
+  /// Add communication managers for all hardware attached to this machine. See
+  /// [ServerDeviceManagerBuilder::local_comm_managers].
+  pub fn local_comm_managers(&mut self) -> &mut Self {
+    self.device_manager_builder.local_comm_managers();
+    self
+  }
+
+  pub fn finish(&mut self) -> Result<DirectDeviceManager, ButtplugServerError> {
+    Ok(DirectDeviceManager {
+      device_manager: Arc::new(self.device_manager_builder.finish()?),
+    })
+  }
+}
+
+/// Finds devices and hands out [DirectDevice] handles for them.
+///
+/// Dropping the manager stops everything it's running, and disconnects its devices.
+pub struct DirectDeviceManager {
+  device_manager: Arc<ServerDeviceManager>,
+}
+
+impl DirectDeviceManager {
+  pub fn start_scanning(&self) -> ButtplugResultFuture {
+    discard_reply(self.device_manager.start_scanning())
+  }
+
+  pub fn stop_scanning(&self) -> ButtplugResultFuture {
+    discard_reply(self.device_manager.stop_scanning())
+  }
+
+  /// Handles for all currently connected devices, ordered by device index.
+  pub fn devices(&self) -> Vec<DirectDevice> {
+    self
+      .device_manager
+      .device_list()
+      .into_iter()
+      .map(|info| DirectDevice::new(self.device_manager.clone(), info))
+      .collect()
+  }
+
+  /// Handle for the device at `index`, if it's connected.
+  pub fn device(&self, index: u32) -> Option<DirectDevice> {
+    self
+      .devices()
+      .into_iter()
+      .find(|device| device.index() == index)
+  }
+
+  pub fn event_stream(&self) -> impl Stream<Item = DirectDeviceEvent> {
+    let device_manager = self.device_manager.clone();
+    self.device_manager.event_stream().filter_map(move |msg| {
+      let event = match msg {
+        ButtplugServerMessageV4::DeviceAdded(msg) => Some(DirectDeviceEvent::DeviceAdded(
+          DirectDevice::new(device_manager.clone(), DeviceMessageInfoV4::from(msg)),
+        )),
+        ButtplugServerMessageV4::DeviceRemoved(msg) => {
+          Some(DirectDeviceEvent::DeviceRemoved(msg.device_index()))
+        }
+        ButtplugServerMessageV4::ScanningFinished(_) => Some(DirectDeviceEvent::ScanningFinished),
+        _ => None,
+      };
+      futures::future::ready(event)
+    })
+  }
+
+  pub fn stop_all_devices(&self) -> ButtplugResultFuture {
+    discard_reply(self.device_manager.stop_all_devices())
+  }
+
+  /// Stop scanning and all devices, then disconnect them. The manager can't be used afterward.
+  pub fn shutdown(&self) -> ButtplugResultFuture {
+    discard_reply(self.device_manager.shutdown())
+  }
+}
+
+fn discard_reply(fut: ButtplugServerResultFuture) -> ButtplugResultFuture {
+  async move { fut.await.map(|_| ()) }.boxed()
+}
@@ -0,0 +1,45 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2024 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Direct device control, for using devices without a client, a server, or any messages.
+//!
+//! A program that only wants to find a toy and make it vibrate doesn't need a client/server split.
+//! A [DirectDeviceManager] runs the same device manager, communication managers and protocols a
+//! server would, and hands out [DirectDevice] handles with typed methods for each kind of
+//! actuator, so nothing has to deal with message indexes or replies.
+//!
+//! ```no_run
+//! # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! use buttplug::direct_device::{DirectDeviceEvent, DirectDeviceManagerBuilder};
+//! use futures::StreamExt;
+//!
+//! let manager = DirectDeviceManagerBuilder::default()
+//!   .local_comm_managers()
+//!   .finish()?;
+//! let mut events = Box::pin(manager.event_stream());
+//! manager.start_scanning().await?;
+//! while let Some(event) = events.next().await {
+//!   if let DirectDeviceEvent::DeviceAdded(device) = event {
+//!     if device.name().contains("Lovense") {
+//!       device.vibrate(0.5).await?;
+//!       break;
+//!     }
+//!   }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+mod device;
+mod device_manager;
+
+pub use device::DirectDevice;
+pub use device_manager::{
+  DirectDeviceEvent,
+  DirectDeviceManager,
+  DirectDeviceManagerBuilder,
+};
@@ -28,12 +28,15 @@
 //!   - Utilities for all portions of the library that may not be specifically related to sex toy
 //!     functionality. This includes managers for different async runtimes, configuration file
 //!     loading, utilities for streams and futures, etc...
+//!
+//! With the `client` and `server` features off, only Core's message and error types are built. They
+//! don't need an async runtime, and only implement serde traits when `serialize-json` is on.
 
 #[macro_use]
 extern crate buttplug_derive;
 #[macro_use]
 extern crate strum_macros;
-#[cfg(any(feature = "client", feature = "server"))]
+#[cfg(feature = "async-core")]
 #[macro_use]
 extern crate futures;
 #[macro_use]
@@ -42,6 +45,11 @@ extern crate tracing;
 #[cfg(feature = "client")]
 pub mod client;
 pub mod core;
+#[cfg(feature = "device-emulation")]
+pub mod device_emulation;
+#[cfg(feature = "direct-device")]
+pub mod direct_device;
 #[cfg(feature = "server")]
 pub mod server;
+#[cfg(feature = "async-core")]
 pub mod util;
@@ -0,0 +1,346 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2024 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Adaptive write limiting for server devices.
+//!
+//! Some of our communication busses (BLE being the usual suspect) can slow down drastically when a
+//! link gets noisy or a device wanders away from the radio. Without any backpressure, commands
+//! pile up in transport queues and the device ends up running values that are seconds old by the
+//! time they arrive.
+//!
+//! The [AdaptiveWriteLimiter] sits between a [ServerDevice](super::ServerDevice) and its hardware.
+//! It serializes hardware command batches and keeps a running average of how long each batch takes
+//! to complete. Batches that describe the full state of a set of actuators are tagged with a
+//! [CoalesceKey], and a tagged batch replaces any queued (not yet written) batch with the same key.
+//! Once the link is considered congested, the device switches actuator updates over to full
+//! command sets, so intermediate values get dropped and only the newest state goes out when the
+//! link frees up.
+//!
+//! Protocols can also set a minimum interval between batches through their
+//! [ProtocolTimingPolicy](crate::server::device::protocol::ProtocolTimingPolicy). Batches are held
+//! back to that rate, and updates that arrive while one is being held get coalesced the same way
+//! they would on a congested link.
+
+use crate::{
+  core::errors::ButtplugDeviceError,
+  server::device::hardware::HardwareCommand,
+  util::{self, async_manager},
+};
+use futures::future::{BoxFuture, FutureExt};
+use instant::Instant;
+use std::{
+  collections::VecDeque,
+  sync::{Arc, Mutex},
+  time::Duration,
+};
+use tokio::sync::oneshot;
+
+/// Average batch write time above which a device link is considered congested.
+const CONGESTION_THRESHOLD: Duration = Duration::from_millis(100);
+/// Weight of each new latency sample in the running average.
+const LATENCY_SAMPLE_WEIGHT: f64 = 0.25;
+
+type WriteResult = Result<(), ButtplugDeviceError>;
+
+pub(super) type HardwareWriteFn =
+  Arc<dyn Fn(Vec<HardwareCommand>) -> BoxFuture<'static, WriteResult> + Send + Sync>;
+
+/// Identifies command batches that carry the full state for a class of actuators, meaning a newer
+/// batch with the same key makes an older unsent one redundant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum CoalesceKey {
+  Scalar,
+  Rotate,
+}
+
+struct PendingWrite {
+  key: Option<CoalesceKey>,
+  commands: Vec<HardwareCommand>,
+  waiters: Vec<oneshot::Sender<WriteResult>>,
+}
+
+#[derive(Default)]
+struct LimiterState {
+  writing: bool,
+  queue: VecDeque<PendingWrite>,
+  average_latency: Option<Duration>,
+  last_write_start: Option<Instant>,
+}
+
+impl LimiterState {
+  fn is_congested(&self) -> bool {
+    self
+      .average_latency
+      .is_some_and(|latency| latency > CONGESTION_THRESHOLD)
+  }
+
+  fn record_latency(&mut self, sample: Duration) {
+    let was_congested = self.is_congested();
+    self.average_latency = Some(match self.average_latency {
+      Some(average) => {
+        average.mul_f64(1.0 - LATENCY_SAMPLE_WEIGHT) + sample.mul_f64(LATENCY_SAMPLE_WEIGHT)
+      }
+      None => sample,
+    });
+    let congested = self.is_congested();
+    if congested && !was_congested {
+      info!(
+        "Device write latency averaging {:?}, coalescing actuator updates until link recovers.",
+        self.average_latency
+      );
+    } else if was_congested && !congested {
+      info!("Device write latency recovered, no longer coalescing actuator updates.");
+    }
+  }
+}
+
+#[derive(Clone)]
+pub(super) struct AdaptiveWriteLimiter {
+  state: Arc<Mutex<LimiterState>>,
+  write_fn: HardwareWriteFn,
+  min_interval: Option<Duration>,
+}
+
+impl AdaptiveWriteLimiter {
+  pub fn new(min_interval: Option<Duration>, write_fn: HardwareWriteFn) -> Self {
+    Self {
+      state: Arc::new(Mutex::new(LimiterState::default())),
+      write_fn,
+      min_interval,
+    }
+  }
+
+  /// True if recent writes have been completing slower than the link needs to keep up.
+  pub fn is_congested(&self) -> bool {
+    self
+      .state
+      .lock()
+      .expect("Limiter lock is never held across a panic")
+      .is_congested()
+  }
+
+  /// True if the device is rate limited and already has a batch waiting for its turn.
+  pub fn is_throttled(&self) -> bool {
+    self.min_interval.is_some()
+      && !self
+        .state
+        .lock()
+        .expect("Limiter lock is never held across a panic")
+        .queue
+        .is_empty()
+  }
+
+  /// Queue a batch of hardware commands, resolving once the batch (or the batch that superseded it)
+  /// has been written.
+  pub fn write(
+    &self,
+    key: Option<CoalesceKey>,
+    commands: Vec<HardwareCommand>,
+  ) -> BoxFuture<'static, WriteResult> {
+    let (sender, receiver) = oneshot::channel();
+    {
+      let mut state = self
+        .state
+        .lock()
+        .expect("Limiter lock is never held across a panic");
+      // Only look back as far as the last un-keyed batch. Folding into anything before it would
+      // send the new values ahead of writes the caller issued first.
+      let superseded = key.and_then(|key| {
+        state
+          .queue
+          .iter_mut()
+          .rev()
+          .take_while(|pending| pending.key.is_some())
+          .find(|pending| pending.key == Some(key))
+      });
+      if let Some(pending) = superseded {
+        trace!("Coalescing queued {:?} write with newer values.", key);
+        pending.commands = commands;
+        pending.waiters.push(sender);
+      } else {
+        state.queue.push_back(PendingWrite {
+          key,
+          commands,
+          waiters: vec![sender],
+        });
+      }
+      if !state.writing {
+        state.writing = true;
+        async_manager::spawn(run_writes(
+          self.state.clone(),
+          self.write_fn.clone(),
+          self.min_interval,
+        ));
+      }
+    }
+    async move {
+      receiver.await.unwrap_or_else(|_| {
+        Err(ButtplugDeviceError::DeviceNotConnected(
+          "Device write task exited before command was written.".to_owned(),
+        ))
+      })
+    }
+    .boxed()
+  }
+}
+
+async fn run_writes(
+  state: Arc<Mutex<LimiterState>>,
+  write_fn: HardwareWriteFn,
+  min_interval: Option<Duration>,
+) {
+  loop {
+    let last_write_start = {
+      let mut state = state
+        .lock()
+        .expect("Limiter lock is never held across a panic");
+      if state.queue.is_empty() {
+        state.writing = false;
+        return;
+      }
+      state.last_write_start
+    };
+    // Wait out the protocol's minimum interval before taking the next batch off the queue, so
+    // anything that comes in meanwhile can still be coalesced into it.
+    if let (Some(min_interval), Some(last_write_start)) = (min_interval, last_write_start) {
+      let elapsed = last_write_start.elapsed();
+      if elapsed < min_interval {
+        util::sleep(min_interval - elapsed).await;
+      }
+    }
+    let next = state
+      .lock()
+      .expect("Limiter lock is never held across a panic")
+      .queue
+      .pop_front()
+      .expect("Only this task takes batches off the queue");
+    let start = Instant::now();
+    state
+      .lock()
+      .expect("Limiter lock is never held across a panic")
+      .last_write_start = Some(start);
+    let result = write_fn(next.commands).await;
+    state
+      .lock()
+      .expect("Limiter lock is never held across a panic")
+      .record_latency(start.elapsed());
+    for waiter in next.waiters {
+      // If the caller went away, there's no one left to tell.
+      let _ = waiter.send(result.clone());
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{AdaptiveWriteLimiter, CoalesceKey, CONGESTION_THRESHOLD};
+  use crate::{
+    core::message::Endpoint,
+    server::device::hardware::{HardwareCommand, HardwareWriteCmd},
+    util,
+  };
+  use futures::{future::join_all, FutureExt};
+  use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+  };
+
+  fn write_cmd(value: u8) -> Vec<HardwareCommand> {
+    vec![HardwareWriteCmd::new(Endpoint::Tx, vec![value], false).into()]
+  }
+
+  fn test_limiter(
+    delay: Duration,
+    min_interval: Option<Duration>,
+  ) -> (AdaptiveWriteLimiter, Arc<Mutex<Vec<u8>>>) {
+    let written = Arc::new(Mutex::new(vec![]));
+    let written_clone = written.clone();
+    let limiter = AdaptiveWriteLimiter::new(
+      min_interval,
+      Arc::new(move |commands| {
+        let written = written_clone.clone();
+        async move {
+          util::sleep(delay).await;
+          for command in commands {
+            if let HardwareCommand::Write(cmd) = command {
+              written.lock().unwrap().push(cmd.data()[0]);
+            }
+          }
+          Ok(())
+        }
+        .boxed()
+      }),
+    );
+    (limiter, written)
+  }
+
+  #[tokio::test]
+  async fn test_writes_stay_in_order() {
+    let (limiter, written) = test_limiter(Duration::from_millis(5), None);
+    let futs: Vec<_> = (0..5).map(|i| limiter.write(None, write_cmd(i))).collect();
+    for result in join_all(futs).await {
+      assert!(result.is_ok());
+    }
+    assert_eq!(*written.lock().unwrap(), vec![0, 1, 2, 3, 4]);
+    assert!(!limiter.is_congested());
+  }
+
+  #[tokio::test]
+  async fn test_slow_link_coalesces_queued_writes() {
+    let (limiter, written) = test_limiter(CONGESTION_THRESHOLD * 2, None);
+    limiter
+      .write(Some(CoalesceKey::Scalar), write_cmd(0))
+      .await
+      .unwrap();
+    assert!(limiter.is_congested());
+    // Everything queued while the link is busy collapses into the last value.
+    let futs: Vec<_> = (1..5)
+      .map(|i| limiter.write(Some(CoalesceKey::Scalar), write_cmd(i)))
+      .collect();
+    for result in join_all(futs).await {
+      assert!(result.is_ok());
+    }
+    assert_eq!(*written.lock().unwrap(), vec![0, 4]);
+  }
+
+  #[tokio::test]
+  async fn test_coalescing_respects_keys() {
+    let (limiter, written) = test_limiter(Duration::from_millis(20), None);
+    let futs = vec![
+      limiter.write(Some(CoalesceKey::Scalar), write_cmd(0)),
+      limiter.write(Some(CoalesceKey::Scalar), write_cmd(1)),
+      limiter.write(Some(CoalesceKey::Rotate), write_cmd(2)),
+      limiter.write(None, write_cmd(3)),
+      limiter.write(Some(CoalesceKey::Scalar), write_cmd(4)),
+    ];
+    for result in join_all(futs).await {
+      assert!(result.is_ok());
+    }
+    // The last scalar write can't fold into the first, as that would jump it ahead of the un-keyed
+    // write issued before it.
+    assert_eq!(*written.lock().unwrap(), vec![1, 2, 3, 4]);
+  }
+
+  #[tokio::test]
+  async fn test_min_interval_spaces_and_coalesces_writes() {
+    let interval = Duration::from_millis(50);
+    let (limiter, written) = test_limiter(Duration::ZERO, Some(interval));
+    let start = Instant::now();
+    limiter.write(None, write_cmd(0)).await.unwrap();
+    // Updates that come in before the interval is up are held back, and fold into the newest.
+    let futs: Vec<_> = (1..4)
+      .map(|i| limiter.write(Some(CoalesceKey::Scalar), write_cmd(i)))
+      .collect();
+    assert!(limiter.is_throttled());
+    for result in join_all(futs).await {
+      assert!(result.is_ok());
+    }
+    assert_eq!(*written.lock().unwrap(), vec![0, 3]);
+    assert!(start.elapsed() >= interval);
+    assert!(!limiter.is_congested());
+  }
+}
@@ -0,0 +1,278 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2024 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Capability emulation, for driving a device through an actuator type it doesn't have.
+//!
+//! Content is usually written for one class of device: vibration patterns for vibrators, scripts
+//! of positions for strokers. When a user opts a device into a [CapabilityEmulation], the device
+//! gets an extra feature of the type it lacks, listed after its real features, and commands sent
+//! to that feature are translated into commands for the actuators the device does have.
+
+use crate::{
+  core::{
+    errors::{ButtplugDeviceError, ButtplugError},
+    message::{
+      ActuatorType,
+      ButtplugActuatorFeatureMessageType,
+      ButtplugDeviceCommandMessageUnion,
+      ButtplugDeviceMessage,
+      DeviceFeature,
+      DeviceFeatureActuator,
+      FeatureType,
+      LinearCmdV4,
+      ScalarCmdV4,
+      ScalarSubcommandV4,
+      VectorSubcommandV4,
+    },
+  },
+  server::device::configuration::CapabilityEmulation,
+  util::{async_manager, sleep},
+};
+use futures::future::BoxFuture;
+use std::{
+  collections::HashSet,
+  sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+    Mutex,
+  },
+  time::Duration,
+};
+use tokio_util::sync::CancellationToken;
+
+// Step count for emulated features. The translation is continuous, so this only decides how finely
+// clients get to pick values.
+const EMULATED_FEATURE_STEPS: u32 = 100;
+// Stroke durations (one way) for the lowest and highest emulated vibration levels.
+const SLOWEST_STROKE: Duration = Duration::from_millis(1000);
+const FASTEST_STROKE: Duration = Duration::from_millis(200);
+
+/// Sends a linear command to the device's real features.
+pub(super) type LinearCmdSender =
+  Arc<dyn Fn(LinearCmdV4) -> BoxFuture<'static, Result<(), ButtplugError>> + Send + Sync>;
+
+/// Feature to add to a device with `features` for `emulation`, along with the indexes of the real
+/// features it drives. Returns None if the device has nothing to emulate the feature with.
+pub(super) fn emulated_feature(
+  emulation: CapabilityEmulation,
+  features: &[DeviceFeature],
+) -> Option<(DeviceFeature, Vec<u32>)> {
+  let targets: Vec<u32> = features
+    .iter()
+    .enumerate()
+    .filter(|(_, feature)| {
+      let Some(actuator) = feature.actuator() else {
+        return false;
+      };
+      match emulation {
+        CapabilityEmulation::VibrateFromLinear => actuator
+          .messages()
+          .contains(&ButtplugActuatorFeatureMessageType::LinearCmd),
+        CapabilityEmulation::LinearFromVibrate => {
+          *feature.feature_type() == FeatureType::Vibrate
+            && actuator
+              .messages()
+              .contains(&ButtplugActuatorFeatureMessageType::ScalarCmd)
+        }
+      }
+    })
+    .map(|(index, _)| index as u32)
+    .collect();
+  if targets.is_empty() {
+    return None;
+  }
+  let (description, feature_type, message) = match emulation {
+    CapabilityEmulation::VibrateFromLinear => (
+      "Emulated Vibration",
+      FeatureType::Vibrate,
+      ButtplugActuatorFeatureMessageType::ScalarCmd,
+    ),
+    CapabilityEmulation::LinearFromVibrate => (
+      "Emulated Position",
+      FeatureType::Position,
+      ButtplugActuatorFeatureMessageType::LinearCmd,
+    ),
+  };
+  let steps = 0..=EMULATED_FEATURE_STEPS;
+  let feature = DeviceFeature::new(
+    description,
+    feature_type,
+    &Some(DeviceFeatureActuator::new(
+      &steps,
+      &steps,
+      &HashSet::from([message]),
+    )),
+    &None,
+  );
+  Some((feature, targets))
+}
+
+struct Stroking {
+  cancel_token: CancellationToken,
+  // Emulated vibration level, as f64 bits.
+  level: Arc<AtomicU64>,
+}
+
+/// Translates commands for a device's emulated feature into commands for its real features.
+pub(super) struct CapabilityEmulator {
+  emulation: CapabilityEmulation,
+  feature_index: u32,
+  targets: Vec<u32>,
+  send_linear: LinearCmdSender,
+  stroking: Mutex<Option<Stroking>>,
+}
+
+impl CapabilityEmulator {
+  pub fn new(
+    emulation: CapabilityEmulation,
+    feature_index: u32,
+    targets: Vec<u32>,
+    send_linear: LinearCmdSender,
+  ) -> Self {
+    Self {
+      emulation,
+      feature_index,
+      targets,
+      send_linear,
+      stroking: Mutex::new(None),
+    }
+  }
+
+  /// Index of the emulated feature.
+  pub fn feature_index(&self) -> u32 {
+    self.feature_index
+  }
+
+  /// Pull the parts of `message` meant for the emulated feature out and act on them, returning the
+  /// messages the device should run in its place. Messages that don't touch the emulated feature
+  /// come back as they are.
+  pub fn translate(
+    &self,
+    message: ButtplugDeviceCommandMessageUnion,
+  ) -> Result<Vec<ButtplugDeviceCommandMessageUnion>, ButtplugDeviceError> {
+    let messages = match (self.emulation, message) {
+      (
+        CapabilityEmulation::VibrateFromLinear,
+        ButtplugDeviceCommandMessageUnion::ScalarCmd(msg),
+      ) => {
+        let (emulated, real): (Vec<_>, Vec<_>) = msg
+          .scalars()
+          .iter()
+          .cloned()
+          .partition(|scalar| scalar.feature_index() == self.feature_index);
+        // Commands have been validated against the emulated feature, so this is a vibrate level.
+        if let Some(scalar) = emulated.last() {
+          self.set_stroke_level(scalar.scalar());
+        }
+        if real.is_empty() {
+          vec![]
+        } else {
+          vec![ScalarCmdV4::new(msg.device_index(), real).into()]
+        }
+      }
+      (
+        CapabilityEmulation::LinearFromVibrate,
+        ButtplugDeviceCommandMessageUnion::LinearCmd(msg),
+      ) => {
+        let (emulated, real): (Vec<_>, Vec<_>) = msg
+          .vectors()
+          .iter()
+          .cloned()
+          .partition(|vector| vector.feature_index() == self.feature_index);
+        let mut messages = vec![];
+        if !real.is_empty() {
+          messages.push(LinearCmdV4::new(msg.device_index(), real).into());
+        }
+        if let Some(vector) = emulated.last() {
+          let level = vector.position().clamp(0.0, 1.0);
+          let scalars = self
+            .targets
+            .iter()
+            .map(|index| ScalarSubcommandV4::new(*index, level, ActuatorType::Vibrate))
+            .collect();
+          messages.push(ScalarCmdV4::new(msg.device_index(), scalars).into());
+        }
+        messages
+      }
+      (_, message) => vec![message],
+    };
+    Ok(messages)
+  }
+
+  /// Stop whatever the emulated feature has running on its own.
+  pub fn stop(&self) {
+    self.set_stroke_level(0.0);
+  }
+
+  fn set_stroke_level(&self, level: f64) {
+    let mut stroking = self
+      .stroking
+      .lock()
+      .expect("Lock is never held across a panic");
+    if level <= 0.0 {
+      if let Some(stroking) = stroking.take() {
+        stroking.cancel_token.cancel();
+      }
+      return;
+    }
+    let level = level.min(1.0);
+    if let Some(current) = stroking.as_ref() {
+      if !current.cancel_token.is_cancelled() {
+        current.level.store(level.to_bits(), Ordering::Relaxed);
+        return;
+      }
+    }
+    let new_stroking = Stroking {
+      cancel_token: CancellationToken::new(),
+      level: Arc::new(AtomicU64::new(level.to_bits())),
+    };
+    async_manager::spawn(run_strokes(
+      self.send_linear.clone(),
+      self.targets.clone(),
+      new_stroking.level.clone(),
+      new_stroking.cancel_token.clone(),
+    ));
+    *stroking = Some(new_stroking);
+  }
+}
+
+impl Drop for CapabilityEmulator {
+  fn drop(&mut self) {
+    self.stop();
+  }
+}
+
+async fn run_strokes(
+  send_linear: LinearCmdSender,
+  targets: Vec<u32>,
+  level: Arc<AtomicU64>,
+  cancel_token: CancellationToken,
+) {
+  let mut position = 1.0;
+  while !cancel_token.is_cancelled() {
+    let level = f64::from_bits(level.load(Ordering::Relaxed));
+    let stroke = SLOWEST_STROKE.mul_f64(1.0 - level) + FASTEST_STROKE.mul_f64(level);
+    let vectors = targets
+      .iter()
+      .map(|index| VectorSubcommandV4::new(*index, stroke.as_millis() as u32, position))
+      .collect();
+    if let Err(err) = send_linear(LinearCmdV4::new(0, vectors)).await {
+      info!(
+        "Stopping emulated vibration after command failure: {:?}",
+        err
+      );
+      // Let the next level change start over.
+      cancel_token.cancel();
+      return;
+    }
+    tokio::select! {
+      _ = cancel_token.cancelled() => return,
+      _ = sleep(stroke) => {}
+    }
+    position = 1.0 - position;
+  }
+}
@@ -0,0 +1,152 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2024 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Per-device record of recent commands and what the hardware did with them.
+//!
+//! When a device "just stopped", logs are usually off and the session is long gone. Each device
+//! keeps its last few commands and hardware results in memory instead, so they can be pulled out
+//! after the fact with
+//! [device_command_audit](super::ServerDeviceManager::device_command_audit).
+//! Entries outlive disconnection, and a device that reconnects under the same index keeps adding
+//! to the same trail.
+
+use crate::{
+  core::{
+    errors::{ButtplugDeviceError, ButtplugError},
+    message::ButtplugDeviceCommandMessageUnion,
+  },
+  server::device::hardware::HardwareCommand,
+};
+use getset::{CopyGetters, Getters};
+use instant::Instant;
+use std::{collections::VecDeque, sync::Mutex};
+
+/// Something that happened to a device, as recorded in its command audit.
+#[derive(Debug, Clone)]
+pub enum CommandAuditEvent {
+  Connected,
+  Disconnected,
+  /// A command message for the device, recorded once it has finished, along with whether it
+  /// succeeded. Hardware commands it caused are recorded before it.
+  Command {
+    message: ButtplugDeviceCommandMessageUnion,
+    result: Result<(), ButtplugError>,
+  },
+  /// A command sent to the hardware, and whether the hardware took it.
+  Hardware {
+    command: HardwareCommand,
+    result: Result<(), ButtplugDeviceError>,
+  },
+}
+
+#[derive(Debug, Clone, Getters, CopyGetters)]
+pub struct CommandAuditEntry {
+  #[getset(get_copy = "pub")]
+  time: Instant,
+  #[getset(get = "pub")]
+  event: CommandAuditEvent,
+}
+
+/// Fixed size ring buffer of [CommandAuditEntry]s. A capacity of 0 records nothing.
+pub(super) struct CommandAudit {
+  capacity: usize,
+  /// Most recent entries, oldest first.
+  entries: Mutex<VecDeque<CommandAuditEntry>>,
+}
+
+impl CommandAudit {
+  pub(super) fn new(capacity: usize) -> Self {
+    Self {
+      capacity,
+      entries: Mutex::new(VecDeque::with_capacity(capacity)),
+    }
+  }
+
+  pub(super) fn enabled(&self) -> bool {
+    self.capacity > 0
+  }
+
+  pub(super) fn record(&self, event: CommandAuditEvent) {
+    if !self.enabled() {
+      return;
+    }
+    let mut entries = self
+      .entries
+      .lock()
+      .expect("Command audit lock should never be poisoned.");
+    if entries.len() == self.capacity {
+      entries.pop_front();
+    }
+    entries.push_back(CommandAuditEntry {
+      time: Instant::now(),
+      event,
+    });
+  }
+
+  /// Recorded entries, oldest first.
+  pub(super) fn entries(&self) -> Vec<CommandAuditEntry> {
+    self
+      .entries
+      .lock()
+      .expect("Command audit lock should never be poisoned.")
+      .iter()
+      .cloned()
+      .collect()
+  }
+
+  /// Put entries from an earlier audit for the same device ahead of ours, dropping the oldest if
+  /// they don't all fit.
+  pub(super) fn carry_over(&self, previous: &CommandAudit) {
+    let mut entries = self
+      .entries
+      .lock()
+      .expect("Command audit lock should never be poisoned.");
+    let room = self.capacity.saturating_sub(entries.len());
+    for entry in previous.entries().into_iter().rev().take(room) {
+      entries.push_front(entry);
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{CommandAudit, CommandAuditEvent};
+
+  fn is_connected(event: &CommandAuditEvent) -> bool {
+    matches!(event, CommandAuditEvent::Connected)
+  }
+
+  #[test]
+  fn test_command_audit_ring_buffer() {
+    let audit = CommandAudit::new(2);
+    audit.record(CommandAuditEvent::Connected);
+    audit.record(CommandAuditEvent::Disconnected);
+    audit.record(CommandAuditEvent::Connected);
+    let entries = audit.entries();
+    assert_eq!(entries.len(), 2);
+    assert!(!is_connected(entries[0].event()));
+    assert!(is_connected(entries[1].event()));
+
+    let disabled = CommandAudit::new(0);
+    disabled.record(CommandAuditEvent::Connected);
+    assert!(disabled.entries().is_empty());
+  }
+
+  #[test]
+  fn test_command_audit_carry_over() {
+    let previous = CommandAudit::new(3);
+    previous.record(CommandAuditEvent::Connected);
+    previous.record(CommandAuditEvent::Disconnected);
+    let audit = CommandAudit::new(2);
+    audit.record(CommandAuditEvent::Connected);
+    audit.carry_over(&previous);
+    let entries = audit.entries();
+    assert_eq!(entries.len(), 2);
+    assert!(!is_connected(entries[0].event()));
+    assert!(is_connected(entries[1].event()));
+  }
+}
@@ -0,0 +1,413 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2024 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Validation of actuator commands against the features of the device they're sent to.
+//!
+//! Messages from clients are checked on their own when they're deserialized, but commands can also
+//! come from inside the server (device links, pattern sessions, the device manager API) and none
+//! of those checks know what device a command is for. Everything a device is sent goes through
+//! [validate_command] before it reaches the protocol, so protocols can count on every subcommand
+//! addressing a feature that takes the command, with a value between 0.0 and 1.0.
+//!
+//! Values outside that range are rejected by default. Servers talking to apps that would rather
+//! have them saturate can pick [OutOfRangeValuePolicy::Clamp], which is applied by
+//! [apply_value_policy] before validation.
+
+use crate::core::{
+  errors::ButtplugDeviceError,
+  message::{
+    ActuatorType,
+    AxisCmdV4,
+    AxisSubcommandV4,
+    ButtplugActuatorFeatureMessageType,
+    ButtplugDeviceCommandMessageUnion,
+    ButtplugDeviceMessage,
+    ButtplugDeviceMessageType,
+    ButtplugMessage,
+    DeviceFeature,
+    FeatureType,
+    LinearCmdV4,
+    RotateCmdV4,
+    RotationSubcommandV4,
+    ScalarCmdV4,
+    ScalarSubcommandV4,
+    VectorSubcommandV4,
+  },
+};
+
+/// What to do with actuator command values outside of 0.0 to 1.0.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutOfRangeValuePolicy {
+  /// Fail the command with [ButtplugDeviceError::DeviceSubcommandValueOutOfRange].
+  #[default]
+  Reject,
+  /// Move the value to the nearest end of the range and run the command. NaN values have no
+  /// nearest end, so they're still rejected.
+  Clamp,
+}
+
+/// Apply `policy` to the values of an actuator command, returning the command to validate and run.
+pub(super) fn apply_value_policy(
+  message: ButtplugDeviceCommandMessageUnion,
+  policy: OutOfRangeValuePolicy,
+) -> ButtplugDeviceCommandMessageUnion {
+  if policy == OutOfRangeValuePolicy::Reject {
+    return message;
+  }
+  let clamp = |value: f64| value.clamp(0.0, 1.0);
+  match message {
+    ButtplugDeviceCommandMessageUnion::ScalarCmd(msg) => {
+      let scalars = msg
+        .scalars()
+        .iter()
+        .map(|scalar| {
+          ScalarSubcommandV4::new(
+            scalar.feature_index(),
+            clamp(scalar.scalar()),
+            scalar.actuator_type(),
+          )
+        })
+        .collect();
+      let mut clamped = ScalarCmdV4::new(msg.device_index(), scalars);
+      clamped.set_id(msg.id());
+      clamped.into()
+    }
+    ButtplugDeviceCommandMessageUnion::RotateCmd(msg) => {
+      let rotations = msg
+        .rotations()
+        .iter()
+        .map(|rotation| {
+          RotationSubcommandV4::new(
+            rotation.feature_index(),
+            clamp(rotation.speed()),
+            rotation.clockwise(),
+          )
+        })
+        .collect();
+      let mut clamped = RotateCmdV4::new(msg.device_index(), rotations);
+      clamped.set_id(msg.id());
+      clamped.into()
+    }
+    ButtplugDeviceCommandMessageUnion::LinearCmd(msg) => {
+      let vectors = msg
+        .vectors()
+        .iter()
+        .map(|vector| {
+          VectorSubcommandV4::new(
+            vector.feature_index(),
+            vector.duration(),
+            clamp(vector.position()),
+          )
+        })
+        .collect();
+      let mut clamped = LinearCmdV4::new(msg.device_index(), vectors);
+      clamped.set_id(msg.id());
+      clamped.into()
+    }
+    ButtplugDeviceCommandMessageUnion::AxisCmd(msg) => {
+      let axes = msg
+        .axes()
+        .iter()
+        .map(|axis| AxisSubcommandV4::new(axis.axis(), axis.duration(), clamp(axis.position())))
+        .collect();
+      let mut clamped = AxisCmdV4::new(msg.device_index(), axes);
+      clamped.set_id(msg.id());
+      clamped.into()
+    }
+    message => message,
+  }
+}
+
+/// Check every subcommand of `message` against `features`, returning an error naming the first
+/// subcommand that's invalid. Non-actuator messages are left to their own handlers.
+pub(super) fn validate_command(
+  features: &[DeviceFeature],
+  message: &ButtplugDeviceCommandMessageUnion,
+) -> Result<(), ButtplugDeviceError> {
+  match message {
+    ButtplugDeviceCommandMessageUnion::ScalarCmd(msg) => {
+      let subcommands = msg.scalars().iter().map(|scalar| {
+        (
+          Some(scalar.feature_index()),
+          scalar.scalar(),
+          Some(scalar.actuator_type()),
+        )
+      });
+      validate_subcommands(features, ButtplugDeviceMessageType::ScalarCmd, subcommands)
+    }
+    ButtplugDeviceCommandMessageUnion::RotateCmd(msg) => {
+      let subcommands = msg
+        .rotations()
+        .iter()
+        .map(|rotation| (Some(rotation.feature_index()), rotation.speed(), None));
+      validate_subcommands(features, ButtplugDeviceMessageType::RotateCmd, subcommands)
+    }
+    ButtplugDeviceCommandMessageUnion::LinearCmd(msg) => {
+      let subcommands = msg
+        .vectors()
+        .iter()
+        .map(|vector| (Some(vector.feature_index()), vector.position(), None));
+      validate_subcommands(features, ButtplugDeviceMessageType::LinearCmd, subcommands)
+    }
+    // Axis commands address axes rather than features, which the handler looks up itself.
+    ButtplugDeviceCommandMessageUnion::AxisCmd(msg) => {
+      let subcommands = msg.axes().iter().map(|axis| (None, axis.position(), None));
+      validate_subcommands(features, ButtplugDeviceMessageType::AxisCmd, subcommands)
+    }
+    _ => Ok(()),
+  }
+}
+
+fn validate_subcommands(
+  features: &[DeviceFeature],
+  message_type: ButtplugDeviceMessageType,
+  subcommands: impl ExactSizeIterator<Item = (Option<u32>, f64, Option<ActuatorType>)>,
+) -> Result<(), ButtplugDeviceError> {
+  if subcommands.len() == 0 {
+    return Err(ButtplugDeviceError::ProtocolRequirementError(format!(
+      "{} with no subcommands is not valid.",
+      message_type
+    )));
+  }
+  let feature_message_type = match message_type {
+    ButtplugDeviceMessageType::ScalarCmd => ButtplugActuatorFeatureMessageType::ScalarCmd,
+    ButtplugDeviceMessageType::RotateCmd => ButtplugActuatorFeatureMessageType::RotateCmd,
+    ButtplugDeviceMessageType::LinearCmd => ButtplugActuatorFeatureMessageType::LinearCmd,
+    _ => ButtplugActuatorFeatureMessageType::AxisCmd,
+  };
+  for (subcommand, (feature_index, value, actuator_type)) in subcommands.enumerate() {
+    let subcommand = subcommand as u32;
+    // Written so NaN fails the check too.
+    if !(0.0..=1.0).contains(&value) {
+      return Err(ButtplugDeviceError::DeviceSubcommandValueOutOfRange(
+        message_type,
+        subcommand,
+        value.to_string(),
+      ));
+    }
+    let Some(feature_index) = feature_index else {
+      continue;
+    };
+    let Some(feature) = features.get(feature_index as usize) else {
+      return Err(ButtplugDeviceError::DeviceSubcommandFeatureIndexError(
+        message_type,
+        subcommand,
+        feature_index,
+        features.len() as u32,
+      ));
+    };
+    let takes_message = feature
+      .actuator()
+      .as_ref()
+      .is_some_and(|actuator| actuator.messages().contains(&feature_message_type));
+    if !takes_message {
+      return Err(ButtplugDeviceError::DeviceSubcommandFeatureMismatch(
+        message_type,
+        subcommand,
+        feature_index,
+      ));
+    }
+    if let Some(actuator_type) = actuator_type {
+      if *feature.feature_type() != FeatureType::from(actuator_type) {
+        return Err(ButtplugDeviceError::DeviceActuatorTypeMismatch(
+          feature_index.to_string(),
+          actuator_type,
+          *feature.feature_type(),
+        ));
+      }
+    }
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod test {
+  use super::{apply_value_policy, validate_command, OutOfRangeValuePolicy};
+  use crate::core::{
+    errors::ButtplugDeviceError,
+    message::{
+      ActuatorType,
+      AxisCmdV4,
+      AxisSubcommandV4,
+      ButtplugActuatorFeatureMessageType,
+      ButtplugDeviceMessageType,
+      DeviceAxis,
+      DeviceFeature,
+      DeviceFeatureActuator,
+      FeatureType,
+      LinearCmdV4,
+      RotateCmdV4,
+      RotationSubcommandV4,
+      ScalarCmdV4,
+      ScalarSubcommandV4,
+      VectorSubcommandV4,
+    },
+  };
+  use std::collections::HashSet;
+
+  fn feature(
+    feature_type: FeatureType,
+    message_type: ButtplugActuatorFeatureMessageType,
+  ) -> DeviceFeature {
+    DeviceFeature::new(
+      "Test",
+      feature_type,
+      &Some(DeviceFeatureActuator::new(
+        &(0..=20),
+        &(0..=20),
+        &HashSet::from([message_type]),
+      )),
+      &None,
+    )
+  }
+
+  fn features() -> Vec<DeviceFeature> {
+    vec![
+      feature(
+        FeatureType::Vibrate,
+        ButtplugActuatorFeatureMessageType::ScalarCmd,
+      ),
+      feature(
+        FeatureType::Rotate,
+        ButtplugActuatorFeatureMessageType::RotateCmd,
+      ),
+    ]
+  }
+
+  fn scalar(feature_index: u32, value: f64) -> ScalarCmdV4 {
+    ScalarCmdV4::new(
+      0,
+      vec![
+        ScalarSubcommandV4::new(0, 0.5, ActuatorType::Vibrate),
+        ScalarSubcommandV4::new(feature_index, value, ActuatorType::Vibrate),
+      ],
+    )
+  }
+
+  #[test]
+  fn test_validate_scalar_cmd() {
+    let features = features();
+    assert!(validate_command(&features, &scalar(0, 1.0).into()).is_ok());
+    assert_eq!(
+      validate_command(&features, &scalar(0, 1.5).into()),
+      Err(ButtplugDeviceError::DeviceSubcommandValueOutOfRange(
+        ButtplugDeviceMessageType::ScalarCmd,
+        1,
+        "1.5".to_owned()
+      ))
+    );
+    assert!(matches!(
+      validate_command(&features, &scalar(0, f64::NAN).into()),
+      Err(ButtplugDeviceError::DeviceSubcommandValueOutOfRange(
+        _,
+        1,
+        _
+      ))
+    ));
+    // One past the last feature is out of range too.
+    assert_eq!(
+      validate_command(&features, &scalar(2, 0.5).into()),
+      Err(ButtplugDeviceError::DeviceSubcommandFeatureIndexError(
+        ButtplugDeviceMessageType::ScalarCmd,
+        1,
+        2,
+        2
+      ))
+    );
+    assert_eq!(
+      validate_command(&features, &scalar(1, 0.5).into()),
+      Err(ButtplugDeviceError::DeviceSubcommandFeatureMismatch(
+        ButtplugDeviceMessageType::ScalarCmd,
+        1,
+        1
+      ))
+    );
+    assert!(matches!(
+      validate_command(&features, &ScalarCmdV4::new(0, vec![]).into()),
+      Err(ButtplugDeviceError::ProtocolRequirementError(_))
+    ));
+    assert!(matches!(
+      validate_command(
+        &features,
+        &ScalarCmdV4::new(
+          0,
+          vec![ScalarSubcommandV4::new(0, 0.5, ActuatorType::Inflate)]
+        )
+        .into()
+      ),
+      Err(ButtplugDeviceError::DeviceActuatorTypeMismatch(..))
+    ));
+  }
+
+  #[test]
+  fn test_validate_other_actuator_cmds() {
+    let features = features();
+    let rotate = |speed| RotateCmdV4::new(0, vec![RotationSubcommandV4::new(1, speed, true)]);
+    assert!(validate_command(&features, &rotate(0.5).into()).is_ok());
+    assert!(matches!(
+      validate_command(&features, &rotate(-0.5).into()),
+      Err(ButtplugDeviceError::DeviceSubcommandValueOutOfRange(
+        ButtplugDeviceMessageType::RotateCmd,
+        0,
+        _
+      ))
+    ));
+    assert_eq!(
+      validate_command(
+        &features,
+        &LinearCmdV4::new(0, vec![VectorSubcommandV4::new(0, 500, 0.5)]).into()
+      ),
+      Err(ButtplugDeviceError::DeviceSubcommandFeatureMismatch(
+        ButtplugDeviceMessageType::LinearCmd,
+        0,
+        0
+      ))
+    );
+    assert!(matches!(
+      validate_command(
+        &features,
+        &AxisCmdV4::new(0, vec![AxisSubcommandV4::new(DeviceAxis::Stroke, 500, 2.0)]).into()
+      ),
+      Err(ButtplugDeviceError::DeviceSubcommandValueOutOfRange(
+        ButtplugDeviceMessageType::AxisCmd,
+        0,
+        _
+      ))
+    ));
+  }
+
+  #[test]
+  fn test_clamp_value_policy() {
+    let features = features();
+    let rejected = apply_value_policy(scalar(0, 1.5).into(), OutOfRangeValuePolicy::Reject);
+    assert_eq!(rejected, scalar(0, 1.5).into());
+    assert!(validate_command(&features, &rejected).is_err());
+
+    let clamped = apply_value_policy(scalar(0, 1.5).into(), OutOfRangeValuePolicy::Clamp);
+    assert_eq!(clamped, scalar(0, 1.0).into());
+    assert!(validate_command(&features, &clamped).is_ok());
+    let clamped = apply_value_policy(
+      RotateCmdV4::new(0, vec![RotationSubcommandV4::new(1, -0.5, true)]).into(),
+      OutOfRangeValuePolicy::Clamp,
+    );
+    assert_eq!(
+      clamped,
+      RotateCmdV4::new(0, vec![RotationSubcommandV4::new(1, 0.0, true)]).into()
+    );
+
+    // NaN can't be clamped to anything sensible, so it still fails validation.
+    let clamped = apply_value_policy(scalar(0, f64::NAN).into(), OutOfRangeValuePolicy::Clamp);
+    assert!(matches!(
+      validate_command(&features, &clamped),
+      Err(ButtplugDeviceError::DeviceSubcommandValueOutOfRange(
+        _,
+        1,
+        _
+      ))
+    ));
+  }
+}
@@ -1,15 +1,38 @@
 use getset::{CopyGetters, Getters, MutGetters, Setters};
-use serde::{Deserialize, Serialize};
-
-use crate::core::message::{
-  ButtplugActuatorFeatureMessageType,
-  ButtplugDeviceMessageType,
-  ButtplugRawFeatureMessageType,
-  ButtplugSensorFeatureMessageType,
-  DeviceFeature,
-  Endpoint,
+use serde::{ser::SerializeSeq, Deserialize, Serialize, Serializer};
+use std::{collections::HashMap, ops::RangeInclusive};
+
+use crate::core::{
+  errors::ButtplugDeviceError,
+  message::{
+    ButtplugActuatorFeatureMessageType,
+    ButtplugDeviceMessageType,
+    ButtplugRawFeatureMessageType,
+    ButtplugSensorFeatureMessageType,
+    DeviceFeature,
+    Endpoint,
+  },
 };
 
+/// Check each feature in a definition, tagging errors with the index of the feature that failed.
+fn validate_features(features: &[DeviceFeature]) -> Result<(), ButtplugDeviceError> {
+  for (index, feature) in features.iter().enumerate() {
+    if let Err(err) = feature.is_valid() {
+      let reason = match err {
+        ButtplugDeviceError::DeviceConfigurationError(reason) => reason,
+        err => err.to_string(),
+      };
+      return Err(ButtplugDeviceError::DeviceConfigurationError(format!(
+        "Feature {} ({:?}) is invalid: {}",
+        index,
+        feature.feature_type(),
+        reason
+      )));
+    }
+  }
+  Ok(())
+}
+
 #[derive(Debug, Clone, Getters)]
 #[getset(get = "pub")]
 pub struct BaseDeviceDefinition {
@@ -27,9 +50,92 @@ impl BaseDeviceDefinition {
       features: features.into(),
     }
   }
+
+  pub fn is_valid(&self) -> Result<(), ButtplugDeviceError> {
+    validate_features(&self.features)
+  }
+}
+
+/// Actuator types a device can be given on top of its own, emulated by the server using the
+/// actuators it does have. See [UserDeviceCustomization::emulation].
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum CapabilityEmulation {
+  /// Give a linear device a vibration feature. Vibration intensity sets how fast the device strokes
+  /// across its full range, and 0 stops it.
+  VibrateFromLinear,
+  /// Give a vibrating device a linear feature. The position a linear command moves to sets the
+  /// intensity of all of the device's vibrators.
+  LinearFromVibrate,
+}
+
+fn range_sequence_serialize<S>(
+  range_vec: &Vec<RangeInclusive<i32>>,
+  serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+  S: Serializer,
+{
+  let mut seq = serializer.serialize_seq(Some(range_vec.len()))?;
+  for range in range_vec {
+    seq.serialize_element(&vec![*range.start(), *range.end()])?;
+  }
+  seq.end()
 }
 
-#[derive(Serialize, Deserialize, Debug, Getters, CopyGetters, Default, Clone)]
+/// Raw value range a particular sensor was seen to cover while being calibrated, one range per
+/// value in its readings. See [UserDeviceCustomization::sensor_calibrations].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Getters)]
+pub struct SensorCalibration {
+  #[getset(get = "pub")]
+  #[serde(rename = "value-range")]
+  #[serde(serialize_with = "range_sequence_serialize")]
+  value_range: Vec<RangeInclusive<i32>>,
+}
+
+impl SensorCalibration {
+  pub fn new(value_range: &[RangeInclusive<i32>]) -> Self {
+    Self {
+      value_range: value_range.into(),
+    }
+  }
+
+  /// Where each value in a raw reading falls in the calibrated range, from 0.0 at the bottom to
+  /// 1.0 at the top. Values outside the range are clamped to it, and values the calibration has no
+  /// range for are left out.
+  pub fn normalize(&self, data: &[i32]) -> Vec<f64> {
+    data
+      .iter()
+      .zip(self.value_range.iter())
+      .map(|(value, range)| {
+        let width = (*range.end() as f64) - (*range.start() as f64);
+        if width <= 0.0 {
+          return 0.0;
+        }
+        (((*value as f64) - (*range.start() as f64)) / width).clamp(0.0, 1.0)
+      })
+      .collect()
+  }
+
+  /// Normalize a raw reading, then scale it onto `target`, usually the value range the sensor
+  /// declares to clients. Values with no calibrated or target range are passed through untouched.
+  pub fn scale_to(&self, data: &[i32], target: &[RangeInclusive<i32>]) -> Vec<i32> {
+    let normalized = self.normalize(data);
+    data
+      .iter()
+      .enumerate()
+      .map(|(index, value)| {
+        let (Some(normalized), Some(target)) = (normalized.get(index), target.get(index)) else {
+          return *value;
+        };
+        let width = (*target.end() as f64) - (*target.start() as f64);
+        (*target.start() as f64 + normalized * width).round() as i32
+      })
+      .collect()
+  }
+}
+
+#[derive(Serialize, Deserialize, Debug, Getters, CopyGetters, Setters, Default, Clone)]
 pub struct UserDeviceCustomization {
   #[serde(skip_serializing_if = "Option::is_none")]
   #[serde(default)]
@@ -44,6 +150,27 @@ pub struct UserDeviceCustomization {
   deny: bool,
   #[getset(get_copy = "pub")]
   index: u32,
+  /// Extra actuator type to emulate for this device, so content made for other kinds of devices
+  /// can still drive it. Off unless set, and the emulated feature is listed after all of the
+  /// device's real features.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  #[serde(default)]
+  #[getset(get_copy = "pub", set = "pub")]
+  emulation: Option<CapabilityEmulation>,
+  /// Calibrations for the device's sensors, keyed by feature index. Readings from a calibrated
+  /// sensor are scaled so the calibrated range covers the sensor's declared value range.
+  #[serde(skip_serializing_if = "HashMap::is_empty")]
+  #[serde(default)]
+  #[serde(rename = "sensor-calibrations")]
+  #[getset(get = "pub")]
+  sensor_calibrations: HashMap<u32, SensorCalibration>,
+  /// Longest a single linear move sent to the device may take. Longer moves are split into
+  /// segments of at most this long, sent one after the other. Unlimited unless set.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  #[serde(default)]
+  #[serde(rename = "max-linear-duration-ms")]
+  #[getset(get_copy = "pub", set = "pub")]
+  max_linear_duration_ms: Option<u32>,
 }
 
 impl UserDeviceCustomization {
@@ -53,8 +180,23 @@ impl UserDeviceCustomization {
       allow,
       deny,
       index,
+      emulation: None,
+      sensor_calibrations: HashMap::new(),
+      max_linear_duration_ms: None,
     }
   }
+
+  /// Set the calibration for the sensor at `feature_index`, or remove it if `calibration` is None.
+  pub fn set_sensor_calibration(
+    &mut self,
+    feature_index: u32,
+    calibration: Option<SensorCalibration>,
+  ) {
+    match calibration {
+      Some(calibration) => self.sensor_calibrations.insert(feature_index, calibration),
+      None => self.sensor_calibrations.remove(&feature_index),
+    };
+  }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Getters, Setters, MutGetters)]
@@ -94,6 +236,10 @@ impl UserDeviceDefinition {
     }
   }
 
+  pub fn is_valid(&self) -> Result<(), ButtplugDeviceError> {
+    validate_features(&self.features)
+  }
+
   pub fn add_raw_messages(&mut self, endpoints: &[Endpoint]) {
     self
       .features
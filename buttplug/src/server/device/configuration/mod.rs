@@ -105,6 +105,11 @@
 //! Adding protocols to the DCM happens via the add_protocol_factory and remove_protocol_factory
 //! methods.
 //!
+//! Protocols can also be left out at runtime, using
+//! [DeviceConfigurationManagerBuilder::exclude_protocol], or marked experimental with
+//! [DeviceConfigurationManagerBuilder::experimental_protocol] so they're only loaded when
+//! [DeviceConfigurationManagerBuilder::allow_experimental_protocols] is set.
+//!
 //! ### Protocol Device Specifiers
 //!
 //! In order to know if a discovered device can be used by Buttplug, it needs to be checked for
@@ -152,16 +157,85 @@ use crate::{
     ProtocolSpecializer,
   },
 };
-use dashmap::DashMap;
+use dashmap::{DashMap, DashSet};
 use getset::Getters;
 use std::{
-  collections::HashMap,
+  collections::{HashMap, HashSet},
   fmt::{self, Debug},
   sync::{
     atomic::{AtomicBool, Ordering},
     Arc,
   },
 };
+use tokio::sync::Notify;
+
+/// Where a device definition came from, for pointing users at the config entry to fix.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeviceDefinitionSource {
+  Base(BaseDeviceIdentifier),
+  User(UserDeviceIdentifier),
+}
+
+impl fmt::Display for DeviceDefinitionSource {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::Base(ident) => match ident.identifier() {
+        Some(identifier) => write!(
+          f,
+          "base config protocol \"{}\", configuration \"{}\"",
+          ident.protocol(),
+          identifier
+        ),
+        None => write!(f, "base config protocol \"{}\" defaults", ident.protocol()),
+      },
+      Self::User(ident) => write!(
+        f,
+        "user config device \"{}\" (protocol \"{}\", identifier {:?})",
+        ident.address(),
+        ident.protocol(),
+        ident.identifier()
+      ),
+    }
+  }
+}
+
+/// A device definition that was skipped while building a [DeviceConfigurationManager] because it
+/// failed validation.
+#[derive(Debug, Clone, Getters)]
+#[getset(get = "pub")]
+pub struct InvalidDeviceDefinition {
+  source: DeviceDefinitionSource,
+  error: ButtplugDeviceError,
+}
+
+impl fmt::Display for InvalidDeviceDefinition {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}: {}", self.source, self.error)
+  }
+}
+
+/// Protocol implementations index into features based on the base config layout, so user
+/// definitions can add features (for configurable hardware like TCode devices) but can't drop any
+/// the base configuration defines.
+fn check_user_feature_count(
+  base_definitions: &HashMap<BaseDeviceIdentifier, BaseDeviceDefinition>,
+  identifier: &UserDeviceIdentifier,
+  definition: &UserDeviceDefinition,
+) -> Result<(), ButtplugDeviceError> {
+  let base_definition = base_definitions
+    .get(&BaseDeviceIdentifier::from(identifier))
+    .or_else(|| base_definitions.get(&BaseDeviceIdentifier::new(identifier.protocol(), &None)));
+  match base_definition {
+    Some(base) if definition.features().len() < base.features().len() => {
+      Err(ButtplugDeviceError::DeviceConfigurationError(format!(
+        "Definition has {} features, but the base configuration for this device requires {}.",
+        definition.features().len(),
+        base.features().len()
+      )))
+    }
+    _ => Ok(()),
+  }
+}
 
 #[derive(Default, Clone)]
 pub struct DeviceConfigurationManagerBuilder {
@@ -173,6 +247,11 @@ pub struct DeviceConfigurationManagerBuilder {
   user_device_definitions: DashMap<UserDeviceIdentifier, UserDeviceDefinition>,
   /// Map of protocol names to their respective protocol instance factories
   protocols: Vec<(String, Arc<dyn ProtocolIdentifierFactory>)>,
+  /// Protocols that are never loaded, whether they're defaults or added as factories.
+  excluded_protocols: HashSet<String>,
+  /// Protocols that are only loaded if experimental protocols are allowed.
+  experimental_protocols: HashSet<String>,
+  allow_experimental_protocols: bool,
 }
 
 impl DeviceConfigurationManagerBuilder {
@@ -245,6 +324,26 @@ impl DeviceConfigurationManagerBuilder {
     self
   }
 
+  /// Leave a protocol out of the system entirely. Devices that would use it won't be connected to,
+  /// but its code stays in the library.
+  pub fn exclude_protocol(&mut self, protocol_name: &str) -> &mut Self {
+    self.excluded_protocols.insert(protocol_name.to_owned());
+    self
+  }
+
+  /// Mark a protocol as experimental, so it's only loaded if experimental protocols are allowed via
+  /// [Self::allow_experimental_protocols].
+  pub fn experimental_protocol(&mut self, protocol_name: &str) -> &mut Self {
+    self.experimental_protocols.insert(protocol_name.to_owned());
+    self
+  }
+
+  /// Set whether protocols marked with [Self::experimental_protocol] are loaded. Off by default.
+  pub fn allow_experimental_protocols(&mut self, allow: bool) -> &mut Self {
+    self.allow_experimental_protocols = allow;
+    self
+  }
+
   pub fn finish(&mut self) -> Result<DeviceConfigurationManager, ButtplugDeviceError> {
     // Map of protocol names to their respective protocol instance factories
     let mut protocol_map = if !self.skip_default_protocols {
@@ -260,8 +359,24 @@ impl DeviceConfigurationManagerBuilder {
       protocol_map.insert(name.clone(), protocol.clone());
     }
 
+    protocol_map.retain(|name, _| {
+      if self.excluded_protocols.contains(name) {
+        info!("Protocol {} is excluded, not loading.", name);
+        return false;
+      }
+      if self.experimental_protocols.contains(name) && !self.allow_experimental_protocols {
+        info!(
+          "Protocol {} is experimental and experimental protocols are not allowed, not loading.",
+          name
+        );
+        return false;
+      }
+      true
+    });
+
     // Build and validate the protocol attributes tree.
     let mut attribute_tree_map = HashMap::new();
+    let mut invalid_definitions = vec![];
 
     // Add all the defaults first, they won't have parent attributes.
     for (ident, attr) in &self.base_device_definitions {
@@ -274,11 +389,12 @@ impl DeviceConfigurationManagerBuilder {
         );
         continue;
       }
-      for feature in attr.features() {
-        if let Err(e) = feature.is_valid() {
-          error!("Feature {attr:?} for ident {ident:?} is not valid, skipping addition: {e:?}");
-          continue;
-        }
+      if let Err(error) = attr.is_valid() {
+        invalid_definitions.push(InvalidDeviceDefinition {
+          source: DeviceDefinitionSource::Base(ident.clone()),
+          error,
+        });
+        continue;
       }
       attribute_tree_map.insert(ident.clone(), attr.clone());
     }
@@ -296,21 +412,32 @@ impl DeviceConfigurationManagerBuilder {
         );
         continue;
       }
-      for feature in attr.features() {
-        if let Err(e) = feature.is_valid() {
-          error!("Feature {attr:?} for ident {ident:?} is not valid, skipping addition: {e:?}");
-          continue;
-        }
+      if let Err(error) = attr
+        .is_valid()
+        .and_then(|_| check_user_feature_count(&attribute_tree_map, ident, attr))
+      {
+        invalid_definitions.push(InvalidDeviceDefinition {
+          source: DeviceDefinitionSource::User(ident.clone()),
+          error,
+        });
+        continue;
       }
       user_attribute_tree_map.insert(kv.key().clone(), kv.value().clone());
     }
 
+    for invalid in &invalid_definitions {
+      warn!("Skipping invalid device definition from {}", invalid);
+    }
+
     Ok(DeviceConfigurationManager {
       allow_raw_messages: Arc::new(AtomicBool::new(self.allow_raw_messages)),
       base_communication_specifiers: self.communication_specifiers.clone(),
       user_communication_specifiers: self.user_communication_specifiers.clone(),
       base_device_definitions: attribute_tree_map,
       user_device_definitions: user_attribute_tree_map,
+      invalid_definitions,
+      forgotten_addresses: DashSet::new(),
+      user_config_changed: Notify::new(),
       protocol_map,
     })
   }
@@ -346,6 +473,14 @@ pub struct DeviceConfigurationManager {
   /// of session.
   #[getset(get = "pub")]
   user_device_definitions: DashMap<UserDeviceIdentifier, UserDeviceDefinition>,
+  /// Definitions that failed validation and were left out when the manager was built.
+  #[getset(get = "pub")]
+  invalid_definitions: Vec<InvalidDeviceDefinition>,
+  /// Addresses of devices that have been forgotten this session, which won't be connected to again
+  /// until they're remembered.
+  forgotten_addresses: DashSet<String>,
+  /// Woken whenever the user configuration changes, see [Self::user_config_changed].
+  user_config_changed: Notify,
 }
 
 impl Debug for DeviceConfigurationManager {
@@ -370,6 +505,18 @@ impl DeviceConfigurationManager {
     self.allow_raw_messages.store(allow, Ordering::Relaxed)
   }
 
+  /// Wait for the user configuration (user specifiers, device definitions and everything stored in
+  /// them, like indexes, display names and calibrations) to change. Meant for a single watcher that
+  /// keeps a saved copy up to date: changes made while it isn't waiting are coalesced into one
+  /// wakeup, so nothing is missed between saves.
+  pub async fn user_config_changed(&self) {
+    self.user_config_changed.notified().await
+  }
+
+  fn notify_user_config_changed(&self) {
+    self.user_config_changed.notify_one();
+  }
+
   pub fn add_user_communication_specifier(
     &self,
     protocol: &str,
@@ -381,6 +528,7 @@ impl DeviceConfigurationManager {
       .entry(protocol.to_owned())
       .or_default()
       .push(specifier.clone());
+    self.notify_user_config_changed();
     Ok(())
   }
 
@@ -396,6 +544,7 @@ impl DeviceConfigurationManager {
         .filter(|s| *specifier != **s)
         .cloned()
         .collect();
+      self.notify_user_config_changed();
     }
   }
 
@@ -405,20 +554,69 @@ impl DeviceConfigurationManager {
     definition: &UserDeviceDefinition,
   ) -> Result<(), ButtplugDeviceError> {
     if !self.protocol_map.contains_key(identifier.protocol()) {}
+    definition.is_valid()?;
+    check_user_feature_count(&self.base_device_definitions, identifier, definition)?;
+    // Adding a device back by hand is as good as remembering it.
+    self.forgotten_addresses.remove(identifier.address());
     self
       .user_device_definitions
       .entry(identifier.clone())
       .insert(definition.clone());
+    self.notify_user_config_changed();
     Ok(())
   }
 
+  /// Set or clear the stored calibration for the sensor at `feature_index` on a device, so it's
+  /// saved along with the rest of the user configuration. Does nothing for devices that have never
+  /// been seen.
+  pub fn set_user_sensor_calibration(
+    &self,
+    identifier: &UserDeviceIdentifier,
+    feature_index: u32,
+    calibration: Option<SensorCalibration>,
+  ) {
+    if let Some(mut definition) = self.user_device_definitions.get_mut(identifier) {
+      definition
+        .user_config_mut()
+        .set_sensor_calibration(feature_index, calibration);
+      self.notify_user_config_changed();
+    }
+  }
+
   pub fn remove_user_device_definition(&self, identifier: &UserDeviceIdentifier) {
+    if self.user_device_definitions.remove(identifier).is_some() {
+      self.notify_user_config_changed();
+    }
+  }
+
+  /// Remove everything stored for a device (its index, display name, calibrations and so on), and
+  /// stop its address from being connected to again this session. Unlike denying the device, this
+  /// leaves nothing behind in the user configuration once it's saved.
+  pub fn forget_device(&self, identifier: &UserDeviceIdentifier) {
     self.user_device_definitions.remove(identifier);
+    self
+      .forgotten_addresses
+      .insert(identifier.address().clone());
+    self.notify_user_config_changed();
+  }
+
+  /// Allow a forgotten address to be connected to again, where it will show up as a new device.
+  /// Returns false if the address wasn't forgotten.
+  pub fn remember_address(&self, address: &str) -> bool {
+    self.forgotten_addresses.remove(address).is_some()
+  }
+
+  /// True if the address has been forgotten with [Self::forget_device] and not remembered since.
+  pub fn address_forgotten(&self, address: &str) -> bool {
+    self.forgotten_addresses.contains(address)
   }
 
   pub fn address_allowed(&self, address: &str) -> bool {
-    // Make sure the device isn't on the deny list
-    if self
+    // Make sure the device hasn't been forgotten, and isn't on the deny list
+    if self.address_forgotten(address) {
+      info!("Device {} has been forgotten, not connecting.", address);
+      false
+    } else if self
       .user_device_definitions
       .iter()
       .any(|kv| kv.key().address() == address && kv.value().user_config().deny())
@@ -523,7 +721,10 @@ impl DeviceConfigurationManager {
 
     // Loop through both maps, as chaining between DashMap and HashMap gets kinda gross.
     for spec in self.user_communication_specifiers.iter() {
-      update_specializer_map(spec.key(), spec.value());
+      update_specializer_map(
+        spec.key(),
+        &self.with_base_services(spec.key(), spec.value()),
+      );
     }
     for (name, specifiers) in self.base_communication_specifiers.iter() {
       update_specializer_map(name, specifiers);
@@ -531,6 +732,36 @@ impl DeviceConfigurationManager {
     specializers
   }
 
+  /// Fill in services for user BLE specifiers that only list names, from the first BLE specifier
+  /// in the protocol's base config. Lets users point a new advertised name at a protocol that
+  /// already knows the device's layout.
+  fn with_base_services(
+    &self,
+    protocol: &str,
+    specifiers: &[ProtocolCommunicationSpecifier],
+  ) -> Vec<ProtocolCommunicationSpecifier> {
+    let base_btle = self
+      .base_communication_specifiers
+      .get(protocol)
+      .and_then(|base| {
+        base.iter().find_map(|specifier| match specifier {
+          ProtocolCommunicationSpecifier::BluetoothLE(btle) => Some(btle),
+          _ => None,
+        })
+      });
+    specifiers
+      .iter()
+      .map(|specifier| match (specifier, base_btle) {
+        (ProtocolCommunicationSpecifier::BluetoothLE(btle), Some(base_btle)) => {
+          let mut btle = btle.clone();
+          btle.inherit_services(base_btle);
+          ProtocolCommunicationSpecifier::BluetoothLE(btle)
+        }
+        _ => specifier.clone(),
+      })
+      .collect()
+  }
+
   pub fn device_definition(
     &self,
     identifier: &UserDeviceIdentifier,
@@ -568,6 +799,7 @@ impl DeviceConfigurationManager {
       self
         .user_device_definitions
         .insert(identifier.clone(), features.clone());
+      self.notify_user_config_changed();
     }
 
     if self.allow_raw_messages.load(Ordering::Relaxed) {
@@ -656,6 +888,125 @@ mod test {
     ));
     assert!(!config.protocol_specializers(&spec).is_empty());
   }
+
+  #[test]
+  fn test_invalid_definitions_are_reported() {
+    let vibrate_feature = |steps: u32| {
+      DeviceFeature::new(
+        "Vibrator",
+        FeatureType::Vibrate,
+        &Some(DeviceFeatureActuator::new(
+          &RangeInclusive::new(0, steps),
+          &RangeInclusive::new(0, steps),
+          &HashSet::from_iter([ButtplugActuatorFeatureMessageType::ScalarCmd]),
+        )),
+        &None,
+      )
+    };
+    let zero_step_ident = BaseDeviceIdentifier::new("lovense", &Some("P".to_owned()));
+    let mismatched_ident = UserDeviceIdentifier::new("Whatever", "lovense", &None);
+    let dcm = DeviceConfigurationManagerBuilder::default()
+      .protocol_features(
+        &BaseDeviceIdentifier::new("lovense", &None),
+        &BaseDeviceDefinition::new(
+          "Lovense Device",
+          &[vibrate_feature(20), vibrate_feature(20)],
+        ),
+      )
+      .protocol_features(
+        &zero_step_ident,
+        &BaseDeviceDefinition::new("Lovense Edge", &[vibrate_feature(0)]),
+      )
+      .user_protocol_features(
+        &mismatched_ident,
+        &UserDeviceDefinition::new(
+          "Lovense Device",
+          &[vibrate_feature(20)],
+          &UserDeviceCustomization::default(),
+        ),
+      )
+      .finish()
+      .unwrap();
+    let invalid = dcm.invalid_definitions();
+    assert_eq!(invalid.len(), 2);
+    assert_eq!(
+      *invalid[0].source(),
+      DeviceDefinitionSource::Base(zero_step_ident)
+    );
+    assert!(invalid[0].to_string().contains("Feature 0 (Vibrate)"));
+    assert_eq!(
+      *invalid[1].source(),
+      DeviceDefinitionSource::User(mismatched_ident)
+    );
+    assert!(dcm.user_device_definitions().is_empty());
+  }
+
+  #[test]
+  fn test_forget_device() {
+    let dcm = create_unit_test_dcm(false);
+    let ident = UserDeviceIdentifier::new("Whatever", "lovense", &Some("P".to_owned()));
+    assert!(dcm.device_definition(&ident, &[]).is_some());
+    assert!(dcm.user_device_definitions().contains_key(&ident));
+
+    dcm.forget_device(&ident);
+    assert!(dcm.user_device_definitions().is_empty());
+    assert!(!dcm.address_allowed("Whatever"));
+    assert!(dcm.address_allowed("SomethingElse"));
+
+    assert!(dcm.remember_address("Whatever"));
+    assert!(!dcm.remember_address("Whatever"));
+    assert!(dcm.address_allowed("Whatever"));
+  }
+
+  #[test]
+  fn test_excluded_and_experimental_protocols() {
+    let spec = ProtocolCommunicationSpecifier::BluetoothLE(BluetoothLESpecifier::new_from_device(
+      "LVS-Whatever",
+      &HashMap::new(),
+      &[],
+    ));
+    let specifiers = ProtocolCommunicationSpecifier::BluetoothLE(BluetoothLESpecifier::new(
+      HashSet::from(["LVS-*".to_owned()]),
+      vec![],
+      HashSet::new(),
+      HashMap::new(),
+    ));
+    let dcm = |configure: fn(&mut DeviceConfigurationManagerBuilder)| {
+      let mut builder = DeviceConfigurationManagerBuilder::default();
+      builder.communication_specifier("lovense", std::slice::from_ref(&specifiers));
+      configure(&mut builder);
+      builder.finish().unwrap()
+    };
+
+    assert!(!dcm(|_| {}).protocol_specializers(&spec).is_empty());
+    assert!(dcm(|builder| {
+      builder.exclude_protocol("lovense");
+    })
+    .protocol_specializers(&spec)
+    .is_empty());
+    assert!(dcm(|builder| {
+      builder.experimental_protocol("lovense");
+    })
+    .protocol_specializers(&spec)
+    .is_empty());
+    assert!(!dcm(|builder| {
+      builder
+        .experimental_protocol("lovense")
+        .allow_experimental_protocols(true);
+    })
+    .protocol_specializers(&spec)
+    .is_empty());
+    // Exclusion wins over opting in to experimental protocols.
+    assert!(dcm(|builder| {
+      builder
+        .experimental_protocol("lovense")
+        .exclude_protocol("lovense")
+        .allow_experimental_protocols(true);
+    })
+    .protocol_specializers(&spec)
+    .is_empty());
+  }
+
   /*
   #[test]
   fn test_specific_device_config_creation() {
@@ -5,8 +5,8 @@
 // Licensed under the BSD 3-Clause license. See LICENSE file in the project root
 // for full license information.
 
-use crate::core::message::Endpoint;
-use getset::{Getters, MutGetters, Setters};
+use crate::core::message::{DeviceTransport, Endpoint};
+use getset::{CopyGetters, Getters, MutGetters, Setters};
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 use uuid::Uuid;
@@ -83,6 +83,28 @@ impl PartialEq for BluetoothLEManufacturerData {
   }
 }
 
+/// Preferred Bluetooth LE connection parameters, in milliseconds.
+///
+/// These are hints. Most platforms don't let applications pick connection parameters at all, and
+/// the ones that do only offer a few presets, so the values actually used may differ. When used to
+/// report what was negotiated, both values are always filled in.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, CopyGetters)]
+#[getset(get_copy = "pub")]
+pub struct BluetoothLEConnectionParameters {
+  /// Connection interval. Lower values mean lower latency, at the cost of power.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  interval: Option<u32>,
+  /// Supervision timeout, how long the link can go silent before it is considered lost.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  timeout: Option<u32>,
+}
+
+impl BluetoothLEConnectionParameters {
+  pub fn new(interval: Option<u32>, timeout: Option<u32>) -> Self {
+    Self { interval, timeout }
+  }
+}
+
 /// Specifier for Bluetooth LE Devices
 ///
 /// Used by protocols for identifying bluetooth devices via their advertisements, as well as
@@ -101,7 +123,26 @@ pub struct BluetoothLESpecifier {
   /// Services we expect the device may have. More services may be listed in a specifier than any
   /// one device may have, but we expect at least one to be matched by a device in order to consider
   /// the device part of the protocol that has this specifier.
+  ///
+  /// User configs can leave this out to attach new names to a protocol, in which case the
+  /// protocol's own services are used.
+  #[serde(default)]
   services: HashMap<Uuid, HashMap<Endpoint, Uuid>>,
+  /// Connection parameters to ask for once connected, for devices that need low latency.
+  #[serde(
+    default,
+    rename = "connection-parameters",
+    skip_serializing_if = "Option::is_none"
+  )]
+  connection_parameters: Option<BluetoothLEConnectionParameters>,
+  /// How many writes in a row this device may make before other devices on the same adapter get a
+  /// turn. Devices without one get a single write per turn.
+  #[serde(
+    default,
+    rename = "write-weight",
+    skip_serializing_if = "Option::is_none"
+  )]
+  write_weight: Option<u32>,
 }
 
 impl PartialEq for BluetoothLESpecifier {
@@ -165,6 +206,8 @@ impl BluetoothLESpecifier {
       manufacturer_data,
       advertised_services,
       services,
+      connection_parameters: None,
+      write_weight: None,
     }
   }
 
@@ -189,6 +232,15 @@ impl BluetoothLESpecifier {
       manufacturer_data: data_vec,
       advertised_services: service_set,
       services: HashMap::new(),
+      connection_parameters: None,
+      write_weight: None,
+    }
+  }
+
+  /// Take services from `other` if this specifier doesn't list any of its own.
+  pub fn inherit_services(&mut self, other: &BluetoothLESpecifier) {
+    if self.services.is_empty() {
+      self.services = other.services.clone();
     }
   }
 
@@ -204,6 +256,12 @@ impl BluetoothLESpecifier {
       .cloned()
       .collect();
     self.services.extend(other.services);
+    if other.connection_parameters.is_some() {
+      self.connection_parameters = other.connection_parameters;
+    }
+    if other.write_weight.is_some() {
+      self.write_weight = other.write_weight;
+    }
   }
 }
 
@@ -256,6 +314,76 @@ impl PartialEq for XInputSpecifier {
   }
 }
 
+/// Specifier for [Windows.Gaming.Input](crate::server::device::hardware::communication::gaming_input)
+/// gamepads
+///
+/// As with XInput, the communication manager finds gamepads itself, so there is nothing to configure.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct GamingInputSpecifier {
+  // Needed for deserialization but unused.
+  #[allow(dead_code)]
+  exists: bool,
+}
+
+impl Default for GamingInputSpecifier {
+  fn default() -> Self {
+    Self { exists: true }
+  }
+}
+
+impl PartialEq for GamingInputSpecifier {
+  fn eq(&self, _other: &Self) -> bool {
+    true
+  }
+}
+
+/// Specifier for [PlayStation](crate::server::device::hardware::communication::playstation)
+/// controllers
+///
+/// The communication manager knows which controllers it can drive and finds them itself, so as with
+/// XInput, there is nothing to configure.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct PlayStationSpecifier {
+  // Needed for deserialization but unused.
+  #[allow(dead_code)]
+  exists: bool,
+}
+
+impl Default for PlayStationSpecifier {
+  fn default() -> Self {
+    Self { exists: true }
+  }
+}
+
+impl PartialEq for PlayStationSpecifier {
+  fn eq(&self, _other: &Self) -> bool {
+    true
+  }
+}
+
+/// Specifier for [OSC](crate::server::device::hardware::communication::osc) motion trackers
+///
+/// Trackers are discovered by the addresses they send to, so as with XInput, there is nothing to
+/// configure here.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct OscSpecifier {
+  // Needed for deserialization but unused.
+  #[allow(dead_code)]
+  exists: bool,
+}
+
+impl Default for OscSpecifier {
+  fn default() -> Self {
+    Self { exists: true }
+  }
+}
+
+impl PartialEq for OscSpecifier {
+  fn eq(&self, _other: &Self) -> bool {
+    true
+  }
+}
+
 #[derive(
   Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy, Getters, Setters, MutGetters,
 )]
@@ -388,12 +516,35 @@ pub enum ProtocolCommunicationSpecifier {
   Serial(SerialSpecifier),
   #[serde(rename = "xinput")]
   XInput(XInputSpecifier),
+  #[serde(rename = "gaming-input")]
+  GamingInput(GamingInputSpecifier),
+  #[serde(rename = "playstation")]
+  PlayStation(PlayStationSpecifier),
+  #[serde(rename = "osc")]
+  Osc(OscSpecifier),
   #[serde(rename = "lovense-connect-service")]
   LovenseConnectService(LovenseConnectServiceSpecifier),
   #[serde(rename = "websocket")]
   Websocket(WebsocketSpecifier),
 }
 
+impl ProtocolCommunicationSpecifier {
+  /// The transport hardware matching this specifier is connected over.
+  pub fn transport(&self) -> DeviceTransport {
+    match self {
+      Self::BluetoothLE(_) => DeviceTransport::BluetoothLE,
+      Self::HID(_) | Self::PlayStation(_) => DeviceTransport::Hid,
+      Self::USB(_) => DeviceTransport::Usb,
+      Self::Serial(_) => DeviceTransport::Serial,
+      // Same gamepads as XInput, just reached through a newer API.
+      Self::XInput(_) | Self::GamingInput(_) => DeviceTransport::XInput,
+      Self::LovenseConnectService(_) | Self::Websocket(_) | Self::Osc(_) => {
+        DeviceTransport::Network
+      }
+    }
+  }
+}
+
 impl PartialEq for ProtocolCommunicationSpecifier {
   fn eq(&self, other: &ProtocolCommunicationSpecifier) -> bool {
     use ProtocolCommunicationSpecifier::*;
@@ -403,6 +554,9 @@ impl PartialEq for ProtocolCommunicationSpecifier {
       (BluetoothLE(self_spec), BluetoothLE(other_spec)) => self_spec == other_spec,
       (HID(self_spec), HID(other_spec)) => self_spec == other_spec,
       (XInput(self_spec), XInput(other_spec)) => self_spec == other_spec,
+      (GamingInput(self_spec), GamingInput(other_spec)) => self_spec == other_spec,
+      (PlayStation(self_spec), PlayStation(other_spec)) => self_spec == other_spec,
+      (Osc(self_spec), Osc(other_spec)) => self_spec == other_spec,
       (Websocket(self_spec), Websocket(other_spec)) => self_spec == other_spec,
       (LovenseConnectService(self_spec), LovenseConnectService(other_spec)) => {
         self_spec == other_spec
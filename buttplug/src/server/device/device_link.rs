@@ -0,0 +1,299 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2024 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Device links, for driving one device's actuator from another device's sensor inside the server.
+//!
+//! A link watches readings from a sensor feature on a source device, runs each reading through a
+//! [DeviceLinkTransfer], and sends the result to a scalar actuator feature on a target device. This
+//! lets partner setups (a touch sensitive toy driving another toy, for instance) run without a
+//! client relaying every reading.
+
+use crate::{
+  core::{
+    errors::{ButtplugDeviceError, ButtplugError},
+    message::{
+      ActuatorType,
+      ButtplugActuatorFeatureMessageType,
+      ButtplugDeviceMessage,
+      ButtplugSensorFeatureMessageType,
+      ButtplugServerMessageV4,
+      ScalarCmdV4,
+      ScalarSubcommandV4,
+      SensorSubscribeCmdV4,
+      SensorType,
+    },
+  },
+  server::device::{DeviceManagerEvent, ServerDevice},
+  util::async_manager,
+};
+use dashmap::DashMap;
+use getset::{CopyGetters, Getters, Setters};
+use std::{ops::RangeInclusive, sync::Arc};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio_util::sync::CancellationToken;
+
+/// How a source sensor level (normalized to 0.0-1.0 over the sensor's value range) is turned into
+/// a target actuator level.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DeviceLinkTransfer {
+  /// Output follows the input, scaled into `min..=max`. Use a `min` above `max` to invert.
+  Linear { min: f64, max: f64 },
+  /// Output runs at `level` while the input is at or above `threshold`, and is off otherwise.
+  Threshold { threshold: f64, level: f64 },
+}
+
+impl DeviceLinkTransfer {
+  pub fn apply(&self, input: f64) -> f64 {
+    let input = input.clamp(0.0, 1.0);
+    let output = match self {
+      DeviceLinkTransfer::Linear { min, max } => min + (max - min) * input,
+      DeviceLinkTransfer::Threshold { threshold, level } => {
+        if input >= *threshold {
+          *level
+        } else {
+          0.0
+        }
+      }
+    };
+    output.clamp(0.0, 1.0)
+  }
+}
+
+/// A mapping from a sensor feature on one device to a scalar actuator feature on another (or the
+/// same) device.
+#[derive(Debug, Clone, PartialEq, Getters, CopyGetters, Setters)]
+pub struct DeviceLink {
+  #[getset(get_copy = "pub")]
+  source_device_index: u32,
+  #[getset(get_copy = "pub")]
+  source_feature_index: u32,
+  /// Which value of a multi-value sensor reading to use. Defaults to the first.
+  #[getset(get_copy = "pub", set = "pub")]
+  source_value_index: usize,
+  #[getset(get_copy = "pub")]
+  target_device_index: u32,
+  #[getset(get_copy = "pub")]
+  target_feature_index: u32,
+  #[getset(get = "pub")]
+  transfer: DeviceLinkTransfer,
+}
+
+impl DeviceLink {
+  pub fn new(
+    source_device_index: u32,
+    source_feature_index: u32,
+    target_device_index: u32,
+    target_feature_index: u32,
+    transfer: DeviceLinkTransfer,
+  ) -> Self {
+    Self {
+      source_device_index,
+      source_feature_index,
+      source_value_index: 0,
+      target_device_index,
+      target_feature_index,
+      transfer,
+    }
+  }
+}
+
+/// Everything needed to run a link, checked against the devices it connects.
+struct ResolvedDeviceLink {
+  link: DeviceLink,
+  sensor_type: SensorType,
+  value_range: RangeInclusive<i32>,
+  actuator_type: ActuatorType,
+}
+
+impl ResolvedDeviceLink {
+  fn resolve(
+    link: DeviceLink,
+    source: &ServerDevice,
+    target: &ServerDevice,
+  ) -> Result<Self, ButtplugDeviceError> {
+    let source_features = source.definition().features();
+    let source_feature = source_features
+      .get(link.source_feature_index as usize)
+      .ok_or(ButtplugDeviceError::DeviceFeatureIndexError(
+        source_features.len() as u32,
+        link.source_feature_index,
+      ))?;
+    let sensor = source_feature
+      .sensor()
+      .as_ref()
+      .filter(|sensor| {
+        sensor
+          .messages()
+          .contains(&ButtplugSensorFeatureMessageType::SensorSubscribeCmd)
+      })
+      .ok_or_else(|| {
+        ButtplugDeviceError::ProtocolRequirementError(format!(
+          "Feature {} of {} is not a sensor that can be subscribed to.",
+          link.source_feature_index,
+          source.name()
+        ))
+      })?;
+    let sensor_type = SensorType::try_from(*source_feature.feature_type())
+      .map_err(ButtplugDeviceError::ProtocolRequirementError)?;
+    let value_range = sensor
+      .value_range()
+      .get(link.source_value_index)
+      .or_else(|| sensor.value_range().first())
+      .cloned()
+      .unwrap_or(0..=0);
+
+    let target_features = target.definition().features();
+    let target_feature = target_features
+      .get(link.target_feature_index as usize)
+      .ok_or(ButtplugDeviceError::DeviceFeatureIndexError(
+        target_features.len() as u32,
+        link.target_feature_index,
+      ))?;
+    if !target_feature.actuator().as_ref().is_some_and(|actuator| {
+      actuator
+        .messages()
+        .contains(&ButtplugActuatorFeatureMessageType::ScalarCmd)
+    }) {
+      return Err(ButtplugDeviceError::ProtocolRequirementError(format!(
+        "Feature {} of {} is not a scalar actuator.",
+        link.target_feature_index,
+        target.name()
+      )));
+    }
+    let actuator_type = ActuatorType::try_from(*target_feature.feature_type())
+      .map_err(ButtplugDeviceError::ProtocolRequirementError)?;
+
+    Ok(Self {
+      link,
+      sensor_type,
+      value_range,
+      actuator_type,
+    })
+  }
+
+  fn subscribe_command(&self) -> SensorSubscribeCmdV4 {
+    SensorSubscribeCmdV4::new(
+      self.link.source_device_index,
+      self.link.source_feature_index,
+      self.sensor_type,
+    )
+  }
+
+  fn normalize(&self, value: i32) -> f64 {
+    let start = *self.value_range.start() as f64;
+    let span = *self.value_range.end() as f64 - start;
+    if span <= 0.0 {
+      return 0.0;
+    }
+    ((value as f64 - start) / span).clamp(0.0, 1.0)
+  }
+}
+
+fn get_device(devices: &DashMap<u32, Arc<ServerDevice>>, index: u32) -> Option<Arc<ServerDevice>> {
+  devices.get(&index).map(|device| device.value().clone())
+}
+
+/// Check `link` against the current devices, subscribe to its source sensor, and start relaying
+/// readings. The link runs until `token` is cancelled, then stops its target feature.
+pub(super) async fn start_device_link(
+  link: DeviceLink,
+  devices: Arc<DashMap<u32, Arc<ServerDevice>>>,
+  mut events: broadcast::Receiver<DeviceManagerEvent>,
+  token: CancellationToken,
+) -> Result<(), ButtplugError> {
+  let source = get_device(&devices, link.source_device_index).ok_or(
+    ButtplugDeviceError::DeviceNotAvailable(link.source_device_index),
+  )?;
+  let target = get_device(&devices, link.target_device_index).ok_or(
+    ButtplugDeviceError::DeviceNotAvailable(link.target_device_index),
+  )?;
+  let resolved = ResolvedDeviceLink::resolve(link, &source, &target)?;
+  source
+    .parse_message(resolved.subscribe_command().into())
+    .await?;
+
+  async_manager::spawn(async move {
+    let link = &resolved.link;
+    loop {
+      let event = tokio::select! {
+        event = events.recv() => event,
+        _ = token.cancelled() => break,
+      };
+      match event {
+        Ok(DeviceManagerEvent::ServerMessage(ButtplugServerMessageV4::SensorReading(reading)))
+          if reading.device_index() == link.source_device_index
+            && reading.feature_index() == link.source_feature_index =>
+        {
+          let Some(value) = reading.data().get(link.source_value_index) else {
+            continue;
+          };
+          let level = link.transfer.apply(resolved.normalize(*value));
+          let Some(target) = get_device(&devices, link.target_device_index) else {
+            continue;
+          };
+          let command = ScalarCmdV4::new(
+            link.target_device_index,
+            vec![ScalarSubcommandV4::new(
+              link.target_feature_index,
+              level,
+              resolved.actuator_type,
+            )],
+          );
+          if let Err(err) = target.parse_message(command.into()).await {
+            warn!("Device link could not update target device: {:?}", err);
+          }
+        }
+        // A reconnected source device comes back without its sensor subscription.
+        Ok(DeviceManagerEvent::ServerMessage(ButtplugServerMessageV4::DeviceAdded(added)))
+          if added.device_index() == link.source_device_index =>
+        {
+          let Some(source) = get_device(&devices, link.source_device_index) else {
+            continue;
+          };
+          if let Err(err) = source
+            .parse_message(resolved.subscribe_command().into())
+            .await
+          {
+            warn!("Device link could not resubscribe to its sensor: {:?}", err);
+          }
+        }
+        Ok(_) => continue,
+        Err(RecvError::Lagged(skipped)) => {
+          debug!("Device link fell behind, skipped {} events.", skipped);
+        }
+        Err(RecvError::Closed) => return,
+      }
+    }
+    if let Some(target) = get_device(&devices, link.target_device_index) {
+      if let Err(err) = target.stop_features(&[link.target_feature_index]).await {
+        debug!("Could not stop device link target: {:?}", err);
+      }
+    }
+  });
+  Ok(())
+}
+
+#[cfg(test)]
+mod test {
+  use super::DeviceLinkTransfer;
+
+  #[test]
+  fn test_device_link_transfer() {
+    let linear = DeviceLinkTransfer::Linear { min: 0.2, max: 0.6 };
+    assert_eq!(linear.apply(0.0), 0.2);
+    assert_eq!(linear.apply(0.5), 0.4);
+    assert_eq!(linear.apply(2.0), 0.6);
+    let inverted = DeviceLinkTransfer::Linear { min: 1.0, max: 0.0 };
+    assert_eq!(inverted.apply(0.25), 0.75);
+    let threshold = DeviceLinkTransfer::Threshold {
+      threshold: 0.5,
+      level: 0.8,
+    };
+    assert_eq!(threshold.apply(0.49), 0.0);
+    assert_eq!(threshold.apply(0.5), 0.8);
+  }
+}
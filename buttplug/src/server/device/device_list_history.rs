@@ -0,0 +1,144 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2024 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Device list change tracking, for incremental DeviceList replies.
+//!
+//! Generations are 64 bit numbers, with a random per-server epoch in the upper 32 bits and a count
+//! of device additions/removals in the lower 32. The epoch keeps a generation from one server run
+//! from being mistaken for one in another, so a client coming back to a restarted server always
+//! gets a full list.
+
+use std::collections::{HashSet, VecDeque};
+
+/// How many device list changes to keep around. Clients that fall further behind than this get a
+/// full list.
+const DEVICE_LIST_HISTORY_LENGTH: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DeviceListChange {
+  Added(u32),
+  Removed(u32),
+}
+
+/// Changes to the device list since some earlier generation.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(super) struct DeviceListDelta {
+  /// Indexes of devices added since the earlier generation. These may no longer be connected.
+  pub(super) added: HashSet<u32>,
+  /// Indexes of devices removed since the earlier generation.
+  pub(super) removed: Vec<u32>,
+}
+
+pub(super) struct DeviceListHistory {
+  epoch: u32,
+  count: u32,
+  /// Most recent changes, oldest first.
+  changes: VecDeque<DeviceListChange>,
+}
+
+impl Default for DeviceListHistory {
+  fn default() -> Self {
+    Self::new(rand::random())
+  }
+}
+
+impl DeviceListHistory {
+  fn new(epoch: u32) -> Self {
+    Self {
+      epoch,
+      count: 0,
+      changes: VecDeque::new(),
+    }
+  }
+
+  pub(super) fn generation(&self) -> u64 {
+    ((self.epoch as u64) << 32) | self.count as u64
+  }
+
+  /// Record a device addition, returning the generation it brings the list to.
+  pub(super) fn device_added(&mut self, index: u32) -> u64 {
+    self.push(DeviceListChange::Added(index))
+  }
+
+  /// Record a device removal, returning the generation it brings the list to.
+  pub(super) fn device_removed(&mut self, index: u32) -> u64 {
+    self.push(DeviceListChange::Removed(index))
+  }
+
+  fn push(&mut self, change: DeviceListChange) -> u64 {
+    self.count = self.count.wrapping_add(1);
+    if self.changes.len() == DEVICE_LIST_HISTORY_LENGTH {
+      self.changes.pop_front();
+    }
+    self.changes.push_back(change);
+    self.generation()
+  }
+
+  /// Work out what changed since `generation`, or None if that generation is from another server
+  /// run, or old enough that its changes have been dropped.
+  pub(super) fn changes_since(&self, generation: u64) -> Option<DeviceListDelta> {
+    if (generation >> 32) as u32 != self.epoch {
+      return None;
+    }
+    let since = generation as u32;
+    // Number of changes the client is behind. If the count ever wraps, this still comes out right.
+    let behind = self.count.wrapping_sub(since) as usize;
+    if behind > self.changes.len() {
+      return None;
+    }
+    let mut delta = DeviceListDelta::default();
+    for change in self.changes.iter().skip(self.changes.len() - behind) {
+      match *change {
+        DeviceListChange::Added(index) => {
+          delta.added.insert(index);
+        }
+        DeviceListChange::Removed(index) => {
+          delta.added.remove(&index);
+          if !delta.removed.contains(&index) {
+            delta.removed.push(index);
+          }
+        }
+      }
+    }
+    Some(delta)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{DeviceListHistory, DEVICE_LIST_HISTORY_LENGTH};
+  use std::collections::HashSet;
+
+  #[test]
+  fn test_device_list_history() {
+    let mut history = DeviceListHistory::new(5);
+    let start = history.generation();
+    assert_eq!(history.device_added(0), start + 1);
+    assert_eq!(history.device_added(1), start + 2);
+    let after_add = history.generation();
+    history.device_removed(0);
+    history.device_added(0);
+    history.device_removed(1);
+
+    let delta = history.changes_since(start).unwrap();
+    assert_eq!(delta.added, HashSet::from([0]));
+    assert_eq!(delta.removed, vec![0, 1]);
+    let delta = history.changes_since(after_add).unwrap();
+    assert_eq!(delta.added, HashSet::from([0]));
+    assert_eq!(delta.removed, vec![0, 1]);
+    let delta = history.changes_since(history.generation()).unwrap();
+    assert!(delta.added.is_empty() && delta.removed.is_empty());
+
+    // Generations from another epoch, from the future, or too far back can't be diffed.
+    assert!(DeviceListHistory::new(6).changes_since(start).is_none());
+    assert!(history.changes_since(history.generation() + 1).is_none());
+    for _ in 0..DEVICE_LIST_HISTORY_LENGTH {
+      history.device_added(2);
+    }
+    assert!(history.changes_since(after_add).is_none());
+  }
+}
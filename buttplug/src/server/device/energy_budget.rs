@@ -0,0 +1,388 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2024 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Energy budgets, a safety limit on how long a device can be run hard.
+//!
+//! Some devices get hot, drain batteries or just wear people out when left at high intensity for a
+//! long time, and the app driving them may not be paying attention. With an [EnergyBudgetPolicy]
+//! set on the device manager, each device keeps a running total of the time it spends at or above
+//! the policy's intensity threshold. Once that passes the budget, the device is either stopped and
+//! kept stopped for a cooldown, or has its levels capped for a while. Either way the total starts
+//! over once the throttling ends, and the device manager sends [EnergyBudgetEvent]s as throttling
+//! engages and releases.
+
+use super::{
+  event_bus::{DeviceManagerEvent, DeviceManagerEventBus},
+  FeatureIntensity,
+  ServerDevice,
+};
+use crate::{
+  core::{
+    errors::{ButtplugDeviceError, ButtplugError},
+    message::{
+      ButtplugActuatorFeatureMessageType,
+      ButtplugDeviceCommandMessageUnion,
+      ButtplugDeviceMessage,
+      ButtplugMessage,
+      RotateCmdV4,
+      RotationSubcommandV4,
+      ScalarCmdV4,
+      ScalarSubcommandV4,
+      StopDeviceCmdV0,
+    },
+  },
+  util::{self, async_manager},
+};
+use futures::StreamExt;
+use getset::CopyGetters;
+use instant::Instant;
+use std::{
+  sync::{Arc, Mutex, Weak},
+  time::Duration,
+};
+use tokio_util::sync::CancellationToken;
+
+/// What happens to a device once it has used up its energy budget.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EnergyBudgetAction {
+  /// Stop the device, and refuse actuator commands for the given time.
+  Cooldown(Duration),
+  /// Cap scalar and rotation levels at `max_intensity` (0.0-1.0) for `duration`. Levels above the
+  /// cap when this engages are brought down to it.
+  Reduce {
+    max_intensity: f64,
+    duration: Duration,
+  },
+}
+
+impl EnergyBudgetAction {
+  /// How long the throttling lasts.
+  pub fn duration(&self) -> Duration {
+    match self {
+      Self::Cooldown(duration) => *duration,
+      Self::Reduce { duration, .. } => *duration,
+    }
+  }
+}
+
+/// Limits on how long devices can run at high intensity. See the [module docs](self) for how these
+/// are applied.
+#[derive(Debug, Clone, Copy, PartialEq, CopyGetters)]
+#[getset(get_copy = "pub")]
+pub struct EnergyBudgetPolicy {
+  /// Intensity (0.0-1.0) at or above which any of a device's scalar or rotation features counts as
+  /// running the device hard.
+  intensity_threshold: f64,
+  /// Total time a device can spend at or above the threshold before it's throttled.
+  budget: Duration,
+  /// What to do once the budget is used up.
+  action: EnergyBudgetAction,
+}
+
+impl EnergyBudgetPolicy {
+  pub fn new(intensity_threshold: f64, budget: Duration, action: EnergyBudgetAction) -> Self {
+    Self {
+      intensity_threshold,
+      budget,
+      action,
+    }
+  }
+
+  fn is_high(&self, intensities: &[FeatureIntensity]) -> bool {
+    intensities
+      .iter()
+      .any(|feature| feature.intensity() >= self.intensity_threshold)
+  }
+}
+
+/// Throttling changes for a device, sent through the device manager's energy budget event stream.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EnergyBudgetEvent {
+  /// The device used up its budget, and `action` is now in effect.
+  Engaged {
+    device_index: u32,
+    action: EnergyBudgetAction,
+  },
+  /// Throttling ended, and the device's budget has started over.
+  Released { device_index: u32 },
+}
+
+impl EnergyBudgetEvent {
+  pub fn device_index(&self) -> u32 {
+    match self {
+      Self::Engaged { device_index, .. } => *device_index,
+      Self::Released { device_index } => *device_index,
+    }
+  }
+}
+
+/// Throttling currently in effect on a device, shared between the device (which applies it to
+/// incoming commands) and the task tracking its budget.
+#[derive(Debug, Clone, Default)]
+pub(super) struct EnergyThrottle {
+  action: Arc<Mutex<Option<EnergyBudgetAction>>>,
+}
+
+impl EnergyThrottle {
+  fn set(&self, action: Option<EnergyBudgetAction>) {
+    *self
+      .action
+      .lock()
+      .expect("Energy throttle lock should never be poisoned.") = action;
+  }
+
+  /// Apply any throttling in effect to a command for the device, refusing it during a cooldown or
+  /// capping its levels during a reduction. Stops and non-actuator commands always go through.
+  pub(super) fn apply(
+    &self,
+    msg: ButtplugDeviceCommandMessageUnion,
+  ) -> Result<ButtplugDeviceCommandMessageUnion, ButtplugError> {
+    let action = *self
+      .action
+      .lock()
+      .expect("Energy throttle lock should never be poisoned.");
+    match (action, msg) {
+      (
+        Some(EnergyBudgetAction::Cooldown(_)),
+        msg @ (ButtplugDeviceCommandMessageUnion::ScalarCmd(_)
+        | ButtplugDeviceCommandMessageUnion::RotateCmd(_)
+        | ButtplugDeviceCommandMessageUnion::LinearCmd(_)
+        | ButtplugDeviceCommandMessageUnion::AxisCmd(_)),
+      ) => Err(ButtplugDeviceError::DeviceCoolingDown(msg.device_index()).into()),
+      (
+        Some(EnergyBudgetAction::Reduce { max_intensity, .. }),
+        ButtplugDeviceCommandMessageUnion::ScalarCmd(msg),
+      ) => {
+        let scalars = msg
+          .scalars()
+          .iter()
+          .map(|scalar| {
+            ScalarSubcommandV4::new(
+              scalar.feature_index(),
+              scalar.scalar().min(max_intensity),
+              scalar.actuator_type(),
+            )
+          })
+          .collect();
+        let mut capped = ScalarCmdV4::new(msg.device_index(), scalars);
+        capped.set_id(msg.id());
+        Ok(capped.into())
+      }
+      (
+        Some(EnergyBudgetAction::Reduce { max_intensity, .. }),
+        ButtplugDeviceCommandMessageUnion::RotateCmd(msg),
+      ) => {
+        let rotations = msg
+          .rotations()
+          .iter()
+          .map(|rotation| {
+            RotationSubcommandV4::new(
+              rotation.feature_index(),
+              rotation.speed().min(max_intensity),
+              rotation.clockwise(),
+            )
+          })
+          .collect();
+        let mut capped = RotateCmdV4::new(msg.device_index(), rotations);
+        capped.set_id(msg.id());
+        Ok(capped.into())
+      }
+      (_, msg) => Ok(msg),
+    }
+  }
+}
+
+/// Bring a device in line with a throttling action that just engaged.
+async fn enforce(device: &ServerDevice, action: EnergyBudgetAction) -> Result<(), ButtplugError> {
+  let EnergyBudgetAction::Reduce { max_intensity, .. } = action else {
+    device.parse_message(StopDeviceCmdV0::new(0).into()).await?;
+    return Ok(());
+  };
+  let mut scalars = vec![];
+  let mut rotations = vec![];
+  for feature in device.actuator_intensities() {
+    if feature.intensity() <= max_intensity {
+      continue;
+    }
+    let scalar = device.definition().features()[feature.feature_index() as usize]
+      .actuator()
+      .as_ref()
+      .is_some_and(|actuator| {
+        actuator
+          .messages()
+          .contains(&ButtplugActuatorFeatureMessageType::ScalarCmd)
+      });
+    if scalar {
+      scalars.push(ScalarSubcommandV4::new(
+        feature.feature_index(),
+        max_intensity,
+        feature.actuator_type(),
+      ));
+    } else {
+      rotations.push(RotationSubcommandV4::new(
+        feature.feature_index(),
+        max_intensity,
+        feature.clockwise(),
+      ));
+    }
+  }
+  if !scalars.is_empty() {
+    device
+      .parse_message(ScalarCmdV4::new(0, scalars).into())
+      .await?;
+  }
+  if !rotations.is_empty() {
+    device
+      .parse_message(RotateCmdV4::new(0, rotations).into())
+      .await?;
+  }
+  Ok(())
+}
+
+/// Track how long `device` spends at high intensity, throttling it once it goes over the budget in
+/// `policy`. Stops when the device goes away or `cancellation_token` is cancelled.
+pub(super) fn start_energy_budget(
+  device_index: u32,
+  device: &Arc<ServerDevice>,
+  policy: EnergyBudgetPolicy,
+  event_bus: DeviceManagerEventBus,
+  cancellation_token: CancellationToken,
+) {
+  let commands = device
+    .actuator_command_stream()
+    .take_until(cancellation_token.cancelled_owned());
+  let throttle = device.energy_throttle().clone();
+  // Holding the device would keep its command stream open after it disconnects.
+  let device: Weak<ServerDevice> = Arc::downgrade(device);
+  async_manager::spawn(async move {
+    futures::pin_mut!(commands);
+    // Time spent at high intensity before the current stretch, and when the current stretch began.
+    let mut used = Duration::ZERO;
+    let mut high_since: Option<Instant> = None;
+    let mut throttled_until: Option<Instant> = None;
+    loop {
+      let wait = match (throttled_until, high_since) {
+        (Some(until), _) => Some(until.saturating_duration_since(Instant::now())),
+        (None, Some(since)) => Some(policy.budget.saturating_sub(used + since.elapsed())),
+        (None, None) => None,
+      };
+      tokio::select! {
+        command = commands.next() => {
+          if command.is_none() {
+            break;
+          }
+          // Whatever happens while throttled, including our own stop or cap, doesn't count.
+          if throttled_until.is_some() {
+            continue;
+          }
+          let Some(device) = device.upgrade() else {
+            break;
+          };
+          let high = policy.is_high(&device.actuator_intensities());
+          match (high, high_since) {
+            (true, None) => high_since = Some(Instant::now()),
+            (false, Some(since)) => {
+              used += since.elapsed();
+              high_since = None;
+            }
+            _ => {}
+          }
+          continue;
+        }
+        _ = util::sleep(wait.unwrap_or_default()), if wait.is_some() => {}
+      }
+      let Some(device) = device.upgrade() else {
+        break;
+      };
+      used = Duration::ZERO;
+      if throttled_until.take().is_some() {
+        throttle.set(None);
+        high_since = policy
+          .is_high(&device.actuator_intensities())
+          .then(Instant::now);
+        info!("Energy budget throttling released for {}.", device.name());
+        event_bus.publish(DeviceManagerEvent::EnergyBudget(
+          EnergyBudgetEvent::Released { device_index },
+        ));
+      } else {
+        high_since = None;
+        throttled_until = Some(Instant::now() + policy.action.duration());
+        throttle.set(Some(policy.action));
+        info!(
+          "{} used up its energy budget, engaging {:?}.",
+          device.name(),
+          policy.action
+        );
+        if let Err(err) = enforce(&device, policy.action).await {
+          warn!(
+            "Could not throttle {} after it used up its energy budget: {:?}",
+            device.name(),
+            err
+          );
+        }
+        event_bus.publish(DeviceManagerEvent::EnergyBudget(
+          EnergyBudgetEvent::Engaged {
+            device_index,
+            action: policy.action,
+          },
+        ));
+      }
+    }
+  });
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::core::message::{ActuatorType, LinearCmdV4, VectorSubcommandV4};
+
+  fn vibrate(level: f64) -> ButtplugDeviceCommandMessageUnion {
+    ScalarCmdV4::new(
+      3,
+      vec![ScalarSubcommandV4::new(0, level, ActuatorType::Vibrate)],
+    )
+    .into()
+  }
+
+  #[test]
+  fn test_no_throttle_passes_commands() {
+    let throttle = EnergyThrottle::default();
+    assert_eq!(throttle.apply(vibrate(1.0)).unwrap(), vibrate(1.0));
+  }
+
+  #[test]
+  fn test_cooldown_refuses_actuator_commands() {
+    let throttle = EnergyThrottle::default();
+    throttle.set(Some(EnergyBudgetAction::Cooldown(Duration::from_secs(1))));
+    assert_eq!(
+      throttle.apply(vibrate(0.1)),
+      Err(ButtplugDeviceError::DeviceCoolingDown(3).into())
+    );
+    assert!(throttle
+      .apply(LinearCmdV4::new(3, vec![VectorSubcommandV4::new(0, 100, 0.5)]).into())
+      .is_err());
+    let stop: ButtplugDeviceCommandMessageUnion = StopDeviceCmdV0::new(3).into();
+    assert_eq!(throttle.apply(stop.clone()).unwrap(), stop);
+  }
+
+  #[test]
+  fn test_reduce_caps_levels() {
+    let throttle = EnergyThrottle::default();
+    throttle.set(Some(EnergyBudgetAction::Reduce {
+      max_intensity: 0.5,
+      duration: Duration::from_secs(1),
+    }));
+    assert_eq!(throttle.apply(vibrate(0.8)).unwrap(), vibrate(0.5));
+    assert_eq!(throttle.apply(vibrate(0.2)).unwrap(), vibrate(0.2));
+    let rotate: ButtplugDeviceCommandMessageUnion =
+      RotateCmdV4::new(3, vec![RotationSubcommandV4::new(0, 1.0, true)]).into();
+    assert_eq!(
+      throttle.apply(rotate).unwrap(),
+      RotateCmdV4::new(3, vec![RotationSubcommandV4::new(0, 0.5, true)]).into()
+    );
+  }
+}
@@ -0,0 +1,241 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2024 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Event bus for everything the device manager reports.
+//!
+//! The device manager, its event loop, pattern sessions and device links all publish to a single
+//! broadcast channel, and anything that wants to watch them subscribes with a
+//! [DeviceManagerEventFilter] to pick the events it cares about. Adding a new observer (or a new
+//! kind of event) then only touches the publisher and the subscriber, rather than every module the
+//! event passes through on the way.
+
+use super::{
+  hardware::communication::HardwareCommunicationManagerStatus,
+  EnergyBudgetEvent,
+  IntensityMeterReading,
+  ScanningProgress,
+};
+use crate::{
+  core::message::{
+    ButtplugDeviceCommandMessageUnion,
+    ButtplugDeviceMessage,
+    ButtplugServerMessageV4,
+  },
+  server::device::PatternSessionEvent,
+};
+use async_stream::stream;
+use futures::Stream;
+use std::collections::HashSet;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+/// Number of events a subscriber can fall behind by before it starts missing them.
+const EVENT_BUS_CAPACITY: usize = 1024;
+
+/// Something that happened in the device manager.
+#[derive(Debug, Clone)]
+pub enum DeviceManagerEvent {
+  /// A message for clients: device additions and removals, scanning finishing, and sensor and raw
+  /// readings.
+  ServerMessage(ButtplugServerMessageV4),
+  /// Periodic update while scanning, see [ScanningProgress].
+  ScanningProgress(ScanningProgress),
+  /// An actuator command was accepted by a device, with its device index filled in.
+  ActuatorCommand(ButtplugDeviceCommandMessageUnion),
+  /// A communication manager's hardware became available or unavailable.
+  CommManagerStatusChanged {
+    manager: &'static str,
+    status: HardwareCommunicationManagerStatus,
+  },
+  /// A communication manager hit a problem it recovered from, like a toy failing to connect.
+  CommManagerError {
+    manager: &'static str,
+    reason: String,
+  },
+  /// Progress or end of a pattern session.
+  PatternSession(PatternSessionEvent),
+  /// A device's actuator levels changed. Only sent if metering is turned on with
+  /// [intensity_metering_interval](super::ServerDeviceManagerBuilder::intensity_metering_interval).
+  IntensityMeter(IntensityMeterReading),
+  /// Energy budget throttling engaged or released on a device. Only sent if a policy is set with
+  /// [energy_budget](super::ServerDeviceManagerBuilder::energy_budget).
+  EnergyBudget(EnergyBudgetEvent),
+}
+
+/// Kinds of [DeviceManagerEvent], for filtering subscriptions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DeviceManagerEventKind {
+  ServerMessage,
+  ScanningProgress,
+  ActuatorCommand,
+  CommManagerStatusChanged,
+  CommManagerError,
+  PatternSession,
+  IntensityMeter,
+  EnergyBudget,
+}
+
+impl DeviceManagerEvent {
+  pub fn kind(&self) -> DeviceManagerEventKind {
+    match self {
+      Self::ServerMessage(_) => DeviceManagerEventKind::ServerMessage,
+      Self::ScanningProgress(_) => DeviceManagerEventKind::ScanningProgress,
+      Self::ActuatorCommand(_) => DeviceManagerEventKind::ActuatorCommand,
+      Self::CommManagerStatusChanged { .. } => DeviceManagerEventKind::CommManagerStatusChanged,
+      Self::CommManagerError { .. } => DeviceManagerEventKind::CommManagerError,
+      Self::PatternSession(_) => DeviceManagerEventKind::PatternSession,
+      Self::IntensityMeter(_) => DeviceManagerEventKind::IntensityMeter,
+      Self::EnergyBudget(_) => DeviceManagerEventKind::EnergyBudget,
+    }
+  }
+
+  /// Index of the device the event is about, if it's about a single device.
+  pub fn device_index(&self) -> Option<u32> {
+    match self {
+      Self::ServerMessage(ButtplugServerMessageV4::DeviceAdded(msg)) => Some(msg.device_index()),
+      Self::ServerMessage(ButtplugServerMessageV4::DeviceRemoved(msg)) => Some(msg.device_index()),
+      Self::ServerMessage(ButtplugServerMessageV4::RawReading(msg)) => Some(msg.device_index()),
+      Self::ServerMessage(ButtplugServerMessageV4::SensorReading(msg)) => Some(msg.device_index()),
+      Self::ActuatorCommand(msg) => Some(msg.device_index()),
+      Self::IntensityMeter(reading) => Some(reading.device_index()),
+      Self::EnergyBudget(event) => Some(event.device_index()),
+      _ => None,
+    }
+  }
+}
+
+/// Picks which events a subscription gets. The default filter lets everything through.
+#[derive(Debug, Clone, Default)]
+pub struct DeviceManagerEventFilter {
+  kinds: HashSet<DeviceManagerEventKind>,
+  device_indexes: HashSet<u32>,
+}
+
+impl DeviceManagerEventFilter {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Only pass events of this kind. Can be called more than once to pass several kinds.
+  pub fn kind(mut self, kind: DeviceManagerEventKind) -> Self {
+    self.kinds.insert(kind);
+    self
+  }
+
+  /// Only pass events about this device. Can be called more than once to pass several devices.
+  /// Events that aren't about a single device, like ScanningFinished, are filtered out once any
+  /// device index is set.
+  pub fn device_index(mut self, device_index: u32) -> Self {
+    self.device_indexes.insert(device_index);
+    self
+  }
+
+  pub fn matches(&self, event: &DeviceManagerEvent) -> bool {
+    (self.kinds.is_empty() || self.kinds.contains(&event.kind()))
+      && (self.device_indexes.is_empty()
+        || event
+          .device_index()
+          .is_some_and(|index| self.device_indexes.contains(&index)))
+  }
+}
+
+/// Handle to the device manager's event bus, from
+/// [ServerDeviceManager::event_bus](super::ServerDeviceManager). Cheap to clone.
+#[derive(Debug, Clone)]
+pub struct DeviceManagerEventBus {
+  sender: broadcast::Sender<DeviceManagerEvent>,
+}
+
+impl Default for DeviceManagerEventBus {
+  fn default() -> Self {
+    Self {
+      sender: broadcast::channel(EVENT_BUS_CAPACITY).0,
+    }
+  }
+}
+
+impl DeviceManagerEventBus {
+  /// Send an event to every current subscriber. Returns false if there were none, which is usually
+  /// fine.
+  pub(crate) fn publish(&self, event: DeviceManagerEvent) -> bool {
+    self.sender.send(event).is_ok()
+  }
+
+  /// Raw receiver for everything on the bus, for loops that need to select on it directly.
+  pub(super) fn receiver(&self) -> broadcast::Receiver<DeviceManagerEvent> {
+    self.sender.subscribe()
+  }
+
+  /// Stream of events that pass `filter`. A subscriber that falls too far behind skips the events
+  /// it missed rather than being dropped. The stream ends when the device manager shuts down.
+  pub fn subscribe(
+    &self,
+    filter: DeviceManagerEventFilter,
+  ) -> impl Stream<Item = DeviceManagerEvent> {
+    self.subscribe_map(move |event| filter.matches(&event).then_some(event))
+  }
+
+  /// Like [Self::subscribe], but mapping events to a single type, for streams of one kind of event.
+  pub(super) fn subscribe_map<T, F>(&self, map: F) -> impl Stream<Item = T>
+  where
+    F: Fn(DeviceManagerEvent) -> Option<T>,
+  {
+    let mut receiver = self.sender.subscribe();
+    stream! {
+      loop {
+        match receiver.recv().await {
+          Ok(event) => {
+            if let Some(item) = map(event) {
+              yield item;
+            }
+          }
+          Err(RecvError::Lagged(skipped)) => {
+            warn!("Device manager event subscriber fell behind, skipped {} events.", skipped);
+          }
+          Err(RecvError::Closed) => break,
+        }
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{
+    DeviceManagerEvent,
+    DeviceManagerEventBus,
+    DeviceManagerEventFilter,
+    DeviceManagerEventKind,
+  };
+  use crate::core::message::{DeviceRemovedV4, ScanningFinishedV0};
+  use futures::{pin_mut, StreamExt};
+
+  #[tokio::test]
+  async fn test_event_bus_filtering() {
+    let bus = DeviceManagerEventBus::default();
+    let removals = bus.subscribe(
+      DeviceManagerEventFilter::new()
+        .kind(DeviceManagerEventKind::ServerMessage)
+        .device_index(1),
+    );
+    let everything = bus.subscribe(DeviceManagerEventFilter::default());
+    pin_mut!(removals, everything);
+
+    for event in [
+      DeviceManagerEvent::ServerMessage(DeviceRemovedV4::new(0, 1).into()),
+      DeviceManagerEvent::ServerMessage(ScanningFinishedV0::default().into()),
+      DeviceManagerEvent::ServerMessage(DeviceRemovedV4::new(1, 2).into()),
+    ] {
+      assert!(bus.publish(event));
+    }
+    drop(bus);
+
+    let removals: Vec<_> = removals.collect().await;
+    assert_eq!(removals.len(), 1);
+    assert_eq!(removals[0].device_index(), Some(1));
+    assert_eq!(everything.count().await, 3);
+  }
+}
@@ -0,0 +1,109 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2024 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+use crate::{core::message::Endpoint, server::device::protocol::ProtocolCommandConcurrency};
+use dashmap::DashMap;
+use std::sync::{Arc, RwLock};
+use tokio::sync::{Mutex, OwnedMutexGuard};
+
+/// Keeps hardware access for a device within what its protocol can handle, see
+/// [ProtocolCommandConcurrency].
+#[derive(Default)]
+pub(super) struct HardwareCommandGate {
+  concurrency: RwLock<ProtocolCommandConcurrency>,
+  device_lock: Arc<Mutex<()>>,
+  endpoint_locks: DashMap<Endpoint, Arc<Mutex<()>>>,
+}
+
+impl HardwareCommandGate {
+  pub(super) fn set_concurrency(&self, concurrency: ProtocolCommandConcurrency) {
+    *self
+      .concurrency
+      .write()
+      .expect("Gate lock is never held across a panic") = concurrency;
+  }
+
+  /// Wait for a turn to use `endpoint`. The turn lasts until the guard is dropped. Tokio mutexes
+  /// are fair, so turns are handed out in the order callers started waiting.
+  pub(super) async fn acquire(&self, endpoint: Endpoint) -> OwnedMutexGuard<()> {
+    let lock = match *self
+      .concurrency
+      .read()
+      .expect("Gate lock is never held across a panic")
+    {
+      ProtocolCommandConcurrency::Serialized => self.device_lock.clone(),
+      ProtocolCommandConcurrency::PerEndpoint => {
+        self.endpoint_locks.entry(endpoint).or_default().clone()
+      }
+    };
+    lock.lock_owned().await
+  }
+
+  /// Wait for a turn that covers every endpoint in `endpoints`, for writes that have to go out back
+  /// to back. Endpoints are always taken in the same order, so two of these waiting on overlapping
+  /// endpoints can't each end up holding what the other needs.
+  pub(super) async fn acquire_all(&self, endpoints: &[Endpoint]) -> Vec<OwnedMutexGuard<()>> {
+    let mut endpoints = endpoints.to_vec();
+    endpoints.sort_by_key(|endpoint| *endpoint as u8);
+    endpoints.dedup();
+    let serialized = matches!(
+      *self
+        .concurrency
+        .read()
+        .expect("Gate lock is never held across a panic"),
+      ProtocolCommandConcurrency::Serialized
+    );
+    if serialized {
+      return vec![self.device_lock.clone().lock_owned().await];
+    }
+    let mut turns = Vec::with_capacity(endpoints.len());
+    for endpoint in endpoints {
+      turns.push(self.acquire(endpoint).await);
+    }
+    turns
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::HardwareCommandGate;
+  use crate::{core::message::Endpoint, server::device::protocol::ProtocolCommandConcurrency};
+  use futures::FutureExt;
+
+  #[tokio::test]
+  async fn test_command_gate_concurrency() {
+    let gate = HardwareCommandGate::default();
+    let tx = gate.acquire(Endpoint::Tx).await;
+    // Other endpoints are free, the one in use isn't.
+    assert!(gate.acquire(Endpoint::Rx).now_or_never().is_some());
+    assert!(gate.acquire(Endpoint::Tx).now_or_never().is_none());
+    drop(tx);
+
+    gate.set_concurrency(ProtocolCommandConcurrency::Serialized);
+    let tx = gate.acquire(Endpoint::Tx).await;
+    assert!(gate.acquire(Endpoint::Rx).now_or_never().is_none());
+    drop(tx);
+    assert!(gate.acquire(Endpoint::Rx).now_or_never().is_some());
+  }
+
+  #[tokio::test]
+  async fn test_command_gate_acquire_all() {
+    let gate = HardwareCommandGate::default();
+    let turns = gate
+      .acquire_all(&[Endpoint::TxMode, Endpoint::Tx, Endpoint::Tx])
+      .await;
+    assert_eq!(turns.len(), 2);
+    assert!(gate.acquire(Endpoint::Tx).now_or_never().is_none());
+    assert!(gate.acquire(Endpoint::TxMode).now_or_never().is_none());
+    assert!(gate.acquire(Endpoint::Rx).now_or_never().is_some());
+    drop(turns);
+    assert!(gate
+      .acquire_all(&[Endpoint::Tx, Endpoint::TxMode])
+      .now_or_never()
+      .is_some());
+  }
+}
@@ -5,10 +5,17 @@
 // Licensed under the BSD 3-Clause license. See LICENSE file in the project root
 // for full license information.
 
-use super::btleplug_hardware::BtleplugHardwareConnector;
-use crate::server::device::hardware::communication::HardwareCommunicationManagerEvent;
+use super::{
+  btleplug_comm_manager::BTLEPLUG_COMM_MANAGER_NAME,
+  btleplug_hardware::BtleplugHardwareConnector,
+  btleplug_write_scheduler::BtleplugWriteScheduler,
+};
+use crate::server::device::hardware::communication::{
+  HardwareCommunicationManagerEvent,
+  HardwareCommunicationManagerStatus,
+};
 use btleplug::{
-  api::{Central, CentralEvent, Manager as _, Peripheral, ScanFilter},
+  api::{Central, CentralEvent, CentralState, Manager as _, Peripheral, ScanFilter},
   platform::{Adapter, Manager, PeripheralId},
 };
 use futures::{future::FutureExt, StreamExt};
@@ -43,6 +50,7 @@ pub struct BtleplugAdapterTask {
   event_sender: Sender<HardwareCommunicationManagerEvent>,
   command_receiver: Receiver<BtleplugAdapterCommand>,
   adapter_connected: Arc<AtomicBool>,
+  adapter_powered: Arc<AtomicBool>,
   requires_keepalive: bool,
 }
 
@@ -51,20 +59,59 @@ impl BtleplugAdapterTask {
     event_sender: Sender<HardwareCommunicationManagerEvent>,
     command_receiver: Receiver<BtleplugAdapterCommand>,
     adapter_connected: Arc<AtomicBool>,
+    adapter_powered: Arc<AtomicBool>,
     requires_keepalive: bool,
   ) -> Self {
     Self {
       event_sender,
       command_receiver,
       adapter_connected,
+      adapter_powered,
       requires_keepalive,
     }
   }
 
+  async fn send_status(&self, status: HardwareCommunicationManagerStatus) {
+    if self
+      .event_sender
+      .send(HardwareCommunicationManagerEvent::StatusChanged {
+        name: BTLEPLUG_COMM_MANAGER_NAME,
+        status,
+      })
+      .await
+      .is_err()
+    {
+      error!("Device manager receiver dropped, cannot send adapter status message.");
+    }
+  }
+
+  /// Record a new adapter power state, returning true if the adapter just turned back on.
+  async fn update_adapter_state(&self, state: CentralState) -> bool {
+    // Not every platform can tell us the adapter state. If we don't know, assume it's on rather
+    // than blocking scanning.
+    let powered = state != CentralState::PoweredOff;
+    if self.adapter_powered.swap(powered, Ordering::SeqCst) == powered {
+      return false;
+    }
+    if powered {
+      info!("Bluetooth adapter powered on.");
+      self
+        .send_status(HardwareCommunicationManagerStatus::Available)
+        .await;
+    } else {
+      warn!("Bluetooth adapter powered off. Bluetooth devices cannot be found or used until it is turned back on.");
+      self
+        .send_status(HardwareCommunicationManagerStatus::PoweredOff)
+        .await;
+    }
+    powered
+  }
+
   async fn maybe_add_peripheral(
     &self,
     peripheral_id: &PeripheralId,
     adapter: &Adapter,
+    write_scheduler: &BtleplugWriteScheduler,
     tried_addresses: &mut Vec<PeripheralInfo>,
   ) {
     let peripheral = if let Ok(peripheral) = adapter.peripheral(peripheral_id).await {
@@ -118,6 +165,7 @@ impl BtleplugAdapterTask {
         &properties.services,
         peripheral.clone(),
         adapter.clone(),
+        write_scheduler.clone(),
         self.requires_keepalive,
       ));
       if self
@@ -164,6 +212,12 @@ impl BtleplugAdapterTask {
         Ok(adapters) => {
           if let Some(adapter) = adapters.into_iter().next() {
             info!("Bluetooth LE adapter found.");
+            if !adapter_found {
+              self.adapter_connected.store(true, Ordering::SeqCst);
+              self
+                .send_status(HardwareCommunicationManagerStatus::Available)
+                .await;
+            }
             // Bluetooth dongle identification for Windows
             #[cfg(target_os = "windows")]
             {
@@ -204,6 +258,9 @@ impl BtleplugAdapterTask {
             if adapter_found {
               self.adapter_connected.store(false, Ordering::SeqCst);
               warn!("Bluetooth LE adapter not found, will not be using bluetooth scanning until found. Buttplug will continue polling for the adapter, but no more warning messages will be posted.");
+              self
+                .send_status(HardwareCommunicationManagerStatus::Unavailable)
+                .await;
             }
             continue;
           }
@@ -212,6 +269,9 @@ impl BtleplugAdapterTask {
           if adapter_found {
             self.adapter_connected.store(false, Ordering::SeqCst);
             error!("Error retreiving BTLE adapters: {:?}", e);
+            self
+              .send_status(HardwareCommunicationManagerStatus::Unavailable)
+              .await;
           }
           continue;
         }
@@ -225,6 +285,19 @@ impl BtleplugAdapterTask {
       .expect("Should always be able to retreive stream.");
 
     let mut tried_addresses = vec![];
+    // Every device found on this adapter shares its airtime, so their writes all go through one
+    // scheduler.
+    let write_scheduler = BtleplugWriteScheduler::new();
+    // Whether scanning should be running. Tracked separately from the adapter's own state so we
+    // can pick scanning back up if the adapter is turned off and on again.
+    let mut scanning_requested = false;
+
+    match adapter.adapter_state().await {
+      Ok(state) => {
+        self.update_adapter_state(state).await;
+      }
+      Err(err) => debug!("Cannot retrieve bluetooth adapter state: {:?}", err),
+    }
 
     loop {
       let event_fut = events.next();
@@ -234,12 +307,21 @@ impl BtleplugAdapterTask {
             if let Some(event) = event {
               match event {
                 CentralEvent::DeviceDiscovered(peripheral_id) | CentralEvent::DeviceUpdated(peripheral_id) => {
-                  self.maybe_add_peripheral(&peripheral_id, &adapter, &mut tried_addresses).await;
+                  self.maybe_add_peripheral(&peripheral_id, &adapter, &write_scheduler, &mut tried_addresses).await;
                 }
                 CentralEvent::DeviceDisconnected(peripheral_id) => {
                   debug!("BTLEPlug Device disconnected: {:?}", peripheral_id);
                   tried_addresses.retain(|info| info.peripheral_id != peripheral_id);
                 }
+                CentralEvent::StateUpdate(state) => {
+                  if self.update_adapter_state(state).await && scanning_requested {
+                    info!("Bluetooth adapter is back, resuming scanning.");
+                    tried_addresses.clear();
+                    if let Err(err) = adapter.start_scan(ScanFilter::default()).await {
+                      error!("Start scanning request failed: {}", err);
+                    }
+                  }
+                }
                 event => {
                   trace!("Unhandled btleplug central event: {:?}", event)
                 }
@@ -253,12 +335,16 @@ impl BtleplugAdapterTask {
           if let Some(cmd) = command {
             match cmd {
               BtleplugAdapterCommand::StartScanning => {
+                scanning_requested = true;
                 tried_addresses.clear();
-                if let Err(err) = adapter.start_scan(ScanFilter::default()).await {
+                if !self.adapter_powered.load(Ordering::SeqCst) {
+                  warn!("Bluetooth adapter is powered off, scanning will start once it is turned back on.");
+                } else if let Err(err) = adapter.start_scan(ScanFilter::default()).await {
                   error!("Start scanning request failed: {}", err);
                 }
               }
               BtleplugAdapterCommand::StopScanning => {
+                scanning_requested = false;
                 if let Err(err) = adapter.stop_scan().await {
                   error!("Stop scanning request failed: {}", err);
                 }
@@ -9,11 +9,12 @@ use super::btleplug_adapter_task::{BtleplugAdapterCommand, BtleplugAdapterTask};
 use crate::{
   core::{errors::ButtplugDeviceError, ButtplugResultFuture},
   server::device::hardware::communication::{
+    spawn_manager_task,
     HardwareCommunicationManager,
     HardwareCommunicationManagerBuilder,
     HardwareCommunicationManagerEvent,
+    HardwareCommunicationManagerStatus,
   },
-  util::async_manager,
 };
 use futures::future::FutureExt;
 use std::sync::{
@@ -21,6 +22,9 @@ use std::sync::{
   Arc,
 };
 use tokio::sync::mpsc::{channel, Sender};
+use tokio_util::sync::CancellationToken;
+
+pub(super) const BTLEPLUG_COMM_MANAGER_NAME: &str = "BtlePlugCommunicationManager";
 
 #[derive(Default, Clone)]
 pub struct BtlePlugCommunicationManagerBuilder {
@@ -38,10 +42,12 @@ impl HardwareCommunicationManagerBuilder for BtlePlugCommunicationManagerBuilder
   fn finish(
     &mut self,
     sender: Sender<HardwareCommunicationManagerEvent>,
+    cancellation_token: CancellationToken,
   ) -> Box<dyn HardwareCommunicationManager> {
     Box::new(BtlePlugCommunicationManager::new(
       sender,
       self.require_keepalive,
+      cancellation_token,
     ))
   }
 }
@@ -50,36 +56,45 @@ pub struct BtlePlugCommunicationManager {
   adapter_event_sender: Sender<BtleplugAdapterCommand>,
   scanning_status: Arc<AtomicBool>,
   adapter_connected: Arc<AtomicBool>,
+  adapter_powered: Arc<AtomicBool>,
 }
 
 impl BtlePlugCommunicationManager {
   pub fn new(
     event_sender: Sender<HardwareCommunicationManagerEvent>,
     require_keepalive: bool,
+    cancellation_token: CancellationToken,
   ) -> Self {
     let (sender, receiver) = channel(256);
     let adapter_connected = Arc::new(AtomicBool::new(false));
     let adapter_connected_clone = adapter_connected.clone();
-    async_manager::spawn(async move {
+    let adapter_powered = Arc::new(AtomicBool::new(true));
+    let adapter_powered_clone = adapter_powered.clone();
+    spawn_manager_task(event_sender.clone(), async move {
       let mut task = BtleplugAdapterTask::new(
         event_sender,
         receiver,
         adapter_connected_clone,
+        adapter_powered_clone,
         require_keepalive,
       );
-      task.run().await;
+      tokio::select! {
+        _ = task.run() => {}
+        _ = cancellation_token.cancelled() => debug!("Btleplug adapter task cancelled."),
+      }
     });
     Self {
       adapter_event_sender: sender,
       scanning_status: Arc::new(AtomicBool::new(false)),
       adapter_connected,
+      adapter_powered,
     }
   }
 }
 
 impl HardwareCommunicationManager for BtlePlugCommunicationManager {
   fn name(&self) -> &'static str {
-    "BtlePlugCommunicationManager"
+    BTLEPLUG_COMM_MANAGER_NAME
   }
 
   fn start_scanning(&mut self) -> ButtplugResultFuture {
@@ -137,7 +152,17 @@ impl HardwareCommunicationManager for BtlePlugCommunicationManager {
   }
 
   fn can_scan(&self) -> bool {
-    self.adapter_connected.load(Ordering::SeqCst)
+    self.status() == HardwareCommunicationManagerStatus::Available
+  }
+
+  fn status(&self) -> HardwareCommunicationManagerStatus {
+    if !self.adapter_connected.load(Ordering::SeqCst) {
+      HardwareCommunicationManagerStatus::Unavailable
+    } else if !self.adapter_powered.load(Ordering::SeqCst) {
+      HardwareCommunicationManagerStatus::PoweredOff
+    } else {
+      HardwareCommunicationManagerStatus::Available
+    }
   }
 }
 /*
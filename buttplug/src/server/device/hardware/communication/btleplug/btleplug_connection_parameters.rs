@@ -0,0 +1,120 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2024 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Connection parameter requests for BLE devices.
+//!
+//! btleplug doesn't expose connection parameters, so this goes around it to the platform APIs where
+//! there are any. Windows 11 and later let applications pick between a few presets. Everywhere else
+//! the OS (or the device) decides, and all we can do is note that the hints went unused.
+
+use crate::server::device::configuration::BluetoothLEConnectionParameters;
+use btleplug::api::Peripheral;
+use std::any::Any;
+
+/// Platform objects that have to stay alive for a connection parameter request to stay in effect.
+pub(super) type ConnectionParameterGuard = Box<dyn Any + Send + Sync>;
+
+/// Ask the platform for connection parameters close to `hints`. Returns the negotiated parameters
+/// if the platform reports them, and a guard to hold for as long as the device is connected.
+/// Failures are logged and otherwise ignored, leaving the OS defaults in place.
+#[cfg(target_os = "windows")]
+pub(super) async fn request_connection_parameters<T: Peripheral>(
+  device: &T,
+  hints: &BluetoothLEConnectionParameters,
+) -> (
+  Option<BluetoothLEConnectionParameters>,
+  Option<ConnectionParameterGuard>,
+) {
+  match windows_connection_parameters::request(device.address().into(), hints).await {
+    Ok((negotiated, guard)) => {
+      info!(
+        "Requested connection parameters {:?} for device {:?}, negotiated {:?}",
+        hints,
+        device.id(),
+        negotiated
+      );
+      (Some(negotiated), Some(guard))
+    }
+    Err(err) => {
+      warn!(
+        "Cannot request connection parameters for device {:?}, using OS defaults: {:?}",
+        device.id(),
+        err
+      );
+      (None, None)
+    }
+  }
+}
+
+#[cfg(not(target_os = "windows"))]
+pub(super) async fn request_connection_parameters<T: Peripheral>(
+  device: &T,
+  hints: &BluetoothLEConnectionParameters,
+) -> (
+  Option<BluetoothLEConnectionParameters>,
+  Option<ConnectionParameterGuard>,
+) {
+  info!(
+    "Connection parameters {:?} configured for device {:?}, but this platform doesn't allow \
+     requesting them. Using OS defaults.",
+    hints,
+    device.id()
+  );
+  (None, None)
+}
+
+#[cfg(target_os = "windows")]
+mod windows_connection_parameters {
+  use super::ConnectionParameterGuard;
+  use crate::server::device::configuration::BluetoothLEConnectionParameters;
+  use windows::Devices::Bluetooth::{
+    BluetoothLEDevice,
+    BluetoothLEPreferredConnectionParameters,
+    BluetoothLEPreferredConnectionParametersRequestStatus,
+  };
+
+  // Windows' throughput preset uses the shortest intervals it allows, and the power preset uses
+  // long ones. Hints in between get the balanced preset.
+  const THROUGHPUT_OPTIMIZED_MAX_INTERVAL: u32 = 15;
+  const POWER_OPTIMIZED_MIN_INTERVAL: u32 = 100;
+
+  pub(super) async fn request(
+    address: u64,
+    hints: &BluetoothLEConnectionParameters,
+  ) -> windows::core::Result<(BluetoothLEConnectionParameters, ConnectionParameterGuard)> {
+    let device = BluetoothLEDevice::FromBluetoothAddressAsync(address)?.await?;
+    // Only the interval can be steered, there's no way to ask for a supervision timeout.
+    let preferred = match hints.interval() {
+      Some(interval) if interval <= THROUGHPUT_OPTIMIZED_MAX_INTERVAL => {
+        BluetoothLEPreferredConnectionParameters::ThroughputOptimized()?
+      }
+      Some(interval) if interval >= POWER_OPTIMIZED_MIN_INTERVAL => {
+        BluetoothLEPreferredConnectionParameters::PowerOptimized()?
+      }
+      _ => BluetoothLEPreferredConnectionParameters::Balanced()?,
+    };
+    let request = device.RequestPreferredConnectionParameters(&preferred)?;
+    let status = request.Status()?;
+    if status != BluetoothLEPreferredConnectionParametersRequestStatus::Success {
+      warn!(
+        "Windows did not accept connection parameter request for {:#x}: {:?}",
+        address, status
+      );
+    }
+    // Renegotiation happens in the background, so this may still show the old values if the
+    // device is slow to agree.
+    let current = device.GetConnectionParameters()?;
+    // Intervals come back in 1.25ms units, timeouts in 10ms units.
+    let negotiated = BluetoothLEConnectionParameters::new(
+      Some(current.ConnectionInterval()? as u32 * 5 / 4),
+      Some(current.LinkTimeout()? as u32 * 10),
+    );
+    // The request is dropped (and its preference forgotten) once both it and the device handle
+    // are released.
+    Ok((negotiated, Box::new((device, request))))
+  }
+}
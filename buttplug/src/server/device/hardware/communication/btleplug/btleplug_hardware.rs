@@ -5,6 +5,10 @@
 // Licensed under the BSD 3-Clause license. See LICENSE file in the project root
 // for full license information.
 
+use super::{
+  btleplug_connection_parameters::{request_connection_parameters, ConnectionParameterGuard},
+  btleplug_write_scheduler::{BtleplugWriteQueue, BtleplugWriteScheduler, DEFAULT_WRITE_WEIGHT},
+};
 use crate::{
   core::{errors::ButtplugDeviceError, message::Endpoint},
   server::device::hardware::communication::HardwareSpecificError,
@@ -28,7 +32,7 @@ use crate::{
 use async_trait::async_trait;
 use btleplug::api::CharPropFlags;
 use btleplug::{
-  api::{Central, CentralEvent, Characteristic, Peripheral, ValueNotification, WriteType},
+  api::{Central, CentralEvent, Characteristic, Peripheral, Service, ValueNotification, WriteType},
   platform::Adapter,
 };
 use dashmap::DashSet;
@@ -38,7 +42,7 @@ use futures::{
   StreamExt,
 };
 use std::{
-  collections::HashMap,
+  collections::{BTreeSet, HashMap},
   fmt::{self, Debug},
   pin::Pin,
   sync::Arc,
@@ -55,16 +59,18 @@ pub(super) struct BtleplugHardwareConnector<T: Peripheral + 'static> {
   services: Vec<Uuid>,
   device: T,
   adapter: Adapter,
+  write_scheduler: BtleplugWriteScheduler,
   requires_keepalive: bool,
 }
 
 impl<T: Peripheral> BtleplugHardwareConnector<T> {
-  pub fn new(
+  pub(super) fn new(
     name: &str,
     manufacturer_data: &HashMap<u16, Vec<u8>>,
     services: &[Uuid],
     device: T,
     adapter: Adapter,
+    write_scheduler: BtleplugWriteScheduler,
     requires_keepalive: bool,
   ) -> Self {
     Self {
@@ -73,6 +79,7 @@ impl<T: Peripheral> BtleplugHardwareConnector<T> {
       services: services.to_vec(),
       device,
       adapter,
+      write_scheduler,
       requires_keepalive,
     }
   }
@@ -122,6 +129,7 @@ impl<T: Peripheral> HardwareConnector for BtleplugHardwareConnector<T> {
       &self.name,
       self.device.clone(),
       self.adapter.clone(),
+      self.write_scheduler.clone(),
       self.requires_keepalive,
     )))
   }
@@ -131,60 +139,104 @@ pub struct BtleplugHardwareSpecializer<T: Peripheral + 'static> {
   name: String,
   device: T,
   adapter: Adapter,
+  write_scheduler: BtleplugWriteScheduler,
   requires_keepalive: bool,
 }
 
 impl<T: Peripheral> BtleplugHardwareSpecializer<T> {
-  pub(super) fn new(name: &str, device: T, adapter: Adapter, requires_keepalive: bool) -> Self {
+  pub(super) fn new(
+    name: &str,
+    device: T,
+    adapter: Adapter,
+    write_scheduler: BtleplugWriteScheduler,
+    requires_keepalive: bool,
+  ) -> Self {
     Self {
       name: name.to_owned(),
       device,
       adapter,
+      write_scheduler,
       requires_keepalive,
     }
   }
 }
 
+impl<T: Peripheral> BtleplugHardwareSpecializer<T> {
+  /// Drop the connection and discover services again from scratch.
+  async fn rediscover_services(&self) -> Result<(), ButtplugDeviceError> {
+    let btleplug_error = |err: btleplug::Error| {
+      ButtplugDeviceError::DeviceSpecificError(HardwareSpecificError::BtleplugError(format!(
+        "{:?}",
+        err
+      )))
+    };
+    self.device.disconnect().await.map_err(btleplug_error)?;
+    self.device.connect().await.map_err(btleplug_error)?;
+    self
+      .device
+      .discover_services()
+      .await
+      .map_err(btleplug_error)
+  }
+}
+
+/// Device characteristics mapped to the endpoints a protocol expects.
+struct EndpointMap {
+  endpoints: HashMap<Endpoint, Characteristic>,
+  uuid_map: HashMap<Uuid, Endpoint>,
+  /// True if no expected service was found, or a found service lacked expected characteristics.
+  missing_characteristics: bool,
+}
+
+impl EndpointMap {
+  fn new(services: &BTreeSet<Service>, btle: &BluetoothLESpecifier) -> Self {
+    let mut endpoints = HashMap::new();
+    let mut uuid_map = HashMap::new();
+    let mut missing_characteristics = false;
+    for (proto_uuid, proto_service) in btle.services() {
+      for service in services {
+        if service.uuid != *proto_uuid {
+          continue;
+        }
+
+        debug!("Found required service {} {:?}", service.uuid, service);
+        for (chr_name, chr_uuid) in proto_service.iter() {
+          if let Some(chr) = service.characteristics.iter().find(|c| c.uuid == *chr_uuid) {
+            debug!(
+              "Found characteristic {} for endpoint {}",
+              chr.uuid, *chr_name
+            );
+            endpoints.insert(*chr_name, chr.clone());
+            uuid_map.insert(*chr_uuid, *chr_name);
+          } else {
+            error!(
+              "Characteristic {} ({}) not found, may cause issues in connection.",
+              chr_name, chr_uuid
+            );
+            missing_characteristics = true;
+          }
+        }
+      }
+    }
+    Self {
+      missing_characteristics: missing_characteristics || endpoints.is_empty(),
+      endpoints,
+      uuid_map,
+    }
+  }
+}
+
 #[async_trait]
 impl<T: Peripheral> HardwareSpecializer for BtleplugHardwareSpecializer<T> {
   async fn specialize(
     &mut self,
     specifiers: &[ProtocolCommunicationSpecifier],
   ) -> Result<Hardware, ButtplugDeviceError> {
-    // Map UUIDs to endpoints
-    let mut uuid_map = HashMap::<Uuid, Endpoint>::new();
-    let mut endpoints = HashMap::<Endpoint, Characteristic>::new();
     let address = self.device.id();
-
-    if let Some(ProtocolCommunicationSpecifier::BluetoothLE(btle)) = specifiers
+    let Some(ProtocolCommunicationSpecifier::BluetoothLE(btle)) = specifiers
       .iter()
       .find(|x| matches!(x, ProtocolCommunicationSpecifier::BluetoothLE(_)))
-    {
-      for (proto_uuid, proto_service) in btle.services() {
-        for service in self.device.services() {
-          if service.uuid != *proto_uuid {
-            continue;
-          }
-
-          debug!("Found required service {} {:?}", service.uuid, service);
-          for (chr_name, chr_uuid) in proto_service.iter() {
-            if let Some(chr) = service.characteristics.iter().find(|c| c.uuid == *chr_uuid) {
-              debug!(
-                "Found characteristic {} for endpoint {}",
-                chr.uuid, *chr_name
-              );
-              endpoints.insert(*chr_name, chr.clone());
-              uuid_map.insert(*chr_uuid, *chr_name);
-            } else {
-              error!(
-                "Characteristic {} ({}) not found, may cause issues in connection.",
-                chr_name, chr_uuid
-              );
-            }
-          }
-        }
-      }
-    } else {
+    else {
       error!(
         "Can't find btle protocol specifier mapping for device {} {:?}",
         self.name, address
@@ -193,13 +245,60 @@ impl<T: Peripheral> HardwareSpecializer for BtleplugHardwareSpecializer<T> {
         "Can't find btle protocol specifier mapping for device {} {:?}",
         self.name, address
       )));
+    };
+    let connection_parameter_hints = *btle.connection_parameters();
+    let write_queue = self
+      .write_scheduler
+      .device_queue(btle.write_weight().unwrap_or(DEFAULT_WRITE_WEIGHT));
+
+    let mut endpoint_map = EndpointMap::new(&self.device.services(), btle);
+    // Windows caches GATT tables across connections, and after a firmware update the cached table
+    // may not match what the device has anymore. btleplug always asks Windows for uncached
+    // services, but only for services it hasn't seen yet, so reconnect to make it start over.
+    if cfg!(target_os = "windows") && endpoint_map.missing_characteristics {
+      warn!(
+        "Device {} {:?} is missing expected characteristics, possibly due to a stale Windows \
+         Bluetooth cache. Re-enumerating services.",
+        self.name, address
+      );
+      self.rediscover_services().await?;
+      endpoint_map = EndpointMap::new(&self.device.services(), btle);
+      if endpoint_map.endpoints.is_empty() {
+        error!(
+          "Device {} {:?} still has no usable characteristics, it may need to be re-paired.",
+          self.name, address
+        );
+        return Err(ButtplugDeviceError::DeviceServiceCacheStale(
+          self.name.clone(),
+        ));
+      }
+      if endpoint_map.missing_characteristics {
+        error!(
+          "Device {} {:?} is still missing characteristics. If it doesn't work, try removing and \
+           re-pairing it.",
+          self.name, address
+        );
+      }
     }
+    let EndpointMap {
+      endpoints,
+      uuid_map,
+      ..
+    } = endpoint_map;
+
     let notification_stream = self
       .device
       .notifications()
       .await
       .expect("Should always be able to get notifications");
 
+    let (negotiated_connection_parameters, connection_parameter_guard) =
+      if let Some(hints) = connection_parameter_hints {
+        request_connection_parameters(&self.device, &hints).await
+      } else {
+        (None, None)
+      };
+
     let device_internal_impl = BtlePlugHardware::new(
       self.device.clone(),
       &self.name,
@@ -211,6 +310,8 @@ impl<T: Peripheral> HardwareSpecializer for BtleplugHardwareSpecializer<T> {
       notification_stream,
       endpoints.clone(),
       uuid_map,
+      write_queue,
+      connection_parameter_guard,
     );
     let mut hardware = Hardware::new(
       &self.name,
@@ -223,6 +324,9 @@ impl<T: Peripheral> HardwareSpecializer for BtleplugHardwareSpecializer<T> {
     if self.requires_keepalive {
       hardware.set_requires_keepalive();
     }
+    if let Some(connection_parameters) = negotiated_connection_parameters {
+      hardware.set_connection_parameters(connection_parameters);
+    }
     Ok(hardware)
   }
 }
@@ -232,16 +336,22 @@ pub struct BtlePlugHardware<T: Peripheral + 'static> {
   event_stream: broadcast::Sender<HardwareEvent>,
   endpoints: HashMap<Endpoint, Characteristic>,
   subscribed_endpoints: Arc<DashSet<Endpoint>>,
+  write_queue: BtleplugWriteQueue,
+  // Keeps any connection parameter request alive for as long as we're connected.
+  _connection_parameter_guard: Option<ConnectionParameterGuard>,
 }
 
 impl<T: Peripheral + 'static> BtlePlugHardware<T> {
-  pub fn new(
+  #[allow(clippy::too_many_arguments)]
+  pub(super) fn new(
     device: T,
     name: &str,
     mut adapter_event_stream: Pin<Box<dyn Stream<Item = CentralEvent> + Send>>,
     mut notification_stream: Pin<Box<dyn Stream<Item = ValueNotification> + Send>>,
     endpoints: HashMap<Endpoint, Characteristic>,
     uuid_map: HashMap<Uuid, Endpoint>,
+    write_queue: BtleplugWriteQueue,
+    connection_parameter_guard: Option<ConnectionParameterGuard>,
   ) -> Self {
     let (event_stream, _) = broadcast::channel(256);
     let event_stream_clone = event_stream.clone();
@@ -318,6 +428,8 @@ impl<T: Peripheral + 'static> BtlePlugHardware<T> {
       endpoints,
       event_stream,
       subscribed_endpoints: Arc::new(DashSet::new()),
+      write_queue,
+      _connection_parameter_guard: connection_parameter_guard,
     }
   }
 }
@@ -336,6 +448,11 @@ impl<T: Peripheral + 'static> HardwareInternal for BtlePlugHardware<T> {
     .boxed()
   }
 
+  fn is_connected(&self) -> BoxFuture<'static, bool> {
+    let device = self.device.clone();
+    async move { device.is_connected().await.unwrap_or(false) }.boxed()
+  }
+
   fn write_value(
     &self,
     msg: &HardwareWriteCmd,
@@ -384,8 +501,18 @@ impl<T: Peripheral + 'static> HardwareInternal for BtlePlugHardware<T> {
     }
 
     let data = msg.data.clone();
+    // Wait for this device's turn on the adapter, so it can't starve other devices of airtime.
+    let write = self.write_queue.schedule(async move {
+      let result = device.write(&characteristic, &data, write_type).await;
+      (result, characteristic, data)
+    });
     async move {
-      match device.write(&characteristic, &data, write_type).await {
+      let Some((result, characteristic, data)) = write.await else {
+        return Err(ButtplugDeviceError::DeviceConnectionError(
+          "Bluetooth write scheduler has shut down.".to_owned(),
+        ));
+      };
+      match result {
         Ok(()) => {
           trace!(
             "Sent write: {:?}, {:?} to {:?}",
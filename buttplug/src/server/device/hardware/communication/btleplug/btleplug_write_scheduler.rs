@@ -0,0 +1,215 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2024 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Fair scheduling of writes to devices that share a Bluetooth adapter.
+//!
+//! Every device on an adapter shares its airtime, so a device that streams commands as fast as it
+//! can will crowd out everything else. Instead of handing writes straight to btleplug, each device
+//! queues them with the adapter's scheduler, which runs them one at a time and takes turns between
+//! devices. A device's weight is how many writes it may make in a row before the next device with
+//! queued writes gets a turn.
+
+use crate::util::async_manager;
+use futures::future::{BoxFuture, Future, FutureExt};
+use std::{
+  collections::{HashMap, VecDeque},
+  sync::{
+    atomic::{AtomicU32, Ordering},
+    Arc,
+  },
+};
+use tokio::sync::{mpsc, oneshot};
+
+/// Weight used for devices that don't configure one.
+pub(super) const DEFAULT_WRITE_WEIGHT: u32 = 1;
+
+type WriteJob = BoxFuture<'static, ()>;
+
+struct QueuedWrite {
+  device_id: u32,
+  weight: u32,
+  job: WriteJob,
+}
+
+/// Scheduler for all writes going out over one adapter. Cloning it gives another handle to the
+/// same scheduler.
+#[derive(Clone)]
+pub(super) struct BtleplugWriteScheduler {
+  sender: mpsc::UnboundedSender<QueuedWrite>,
+  next_device_id: Arc<AtomicU32>,
+}
+
+impl BtleplugWriteScheduler {
+  /// Create a scheduler and spawn the task that runs its writes. The task exits once the scheduler
+  /// and all of its queues have been dropped.
+  pub(super) fn new() -> Self {
+    let (sender, receiver) = mpsc::unbounded_channel();
+    async_manager::spawn(async move {
+      WriteSchedulerTask::default().run(receiver).await;
+    });
+    Self {
+      sender,
+      next_device_id: Default::default(),
+    }
+  }
+
+  /// Register a device with the scheduler. A weight of 0 is treated as 1.
+  pub(super) fn device_queue(&self, weight: u32) -> BtleplugWriteQueue {
+    BtleplugWriteQueue {
+      device_id: self.next_device_id.fetch_add(1, Ordering::Relaxed),
+      weight: weight.max(1),
+      sender: self.sender.clone(),
+    }
+  }
+}
+
+/// A single device's place in its adapter's write scheduler.
+#[derive(Clone)]
+pub(super) struct BtleplugWriteQueue {
+  device_id: u32,
+  weight: u32,
+  sender: mpsc::UnboundedSender<QueuedWrite>,
+}
+
+impl BtleplugWriteQueue {
+  /// Queue `write` to run on the device's next turn. Resolves to the write's output once it has
+  /// run, or None if the scheduler went away first.
+  pub(super) fn schedule<T: Send + 'static>(
+    &self,
+    write: impl Future<Output = T> + Send + 'static,
+  ) -> impl Future<Output = Option<T>> {
+    let (result_sender, result_receiver) = oneshot::channel();
+    let job = async move {
+      let _ = result_sender.send(write.await);
+    }
+    .boxed();
+    let queued = self.sender.send(QueuedWrite {
+      device_id: self.device_id,
+      weight: self.weight,
+      job,
+    });
+    async move {
+      if queued.is_err() {
+        return None;
+      }
+      result_receiver.await.ok()
+    }
+  }
+}
+
+#[derive(Default)]
+struct WriteSchedulerTask {
+  /// Pending writes for each device that has any.
+  queues: HashMap<u32, (u32, VecDeque<WriteJob>)>,
+  /// Devices with pending writes, in the order they get their turns. The front device is the one
+  /// whose turn it is.
+  turns: VecDeque<u32>,
+  /// Writes the device at the front of `turns` has made this turn.
+  writes_this_turn: u32,
+}
+
+impl WriteSchedulerTask {
+  fn enqueue(&mut self, write: QueuedWrite) {
+    let (weight, queue) = self
+      .queues
+      .entry(write.device_id)
+      .or_insert_with(|| (write.weight, VecDeque::new()));
+    *weight = write.weight;
+    if queue.is_empty() {
+      self.turns.push_back(write.device_id);
+    }
+    queue.push_back(write.job);
+  }
+
+  fn next_job(&mut self) -> Option<WriteJob> {
+    let device_id = *self.turns.front()?;
+    let (weight, queue) = self
+      .queues
+      .get_mut(&device_id)
+      .expect("Devices only take turns while they have a queue");
+    let job = queue
+      .pop_front()
+      .expect("Devices only take turns while they have writes queued");
+    self.writes_this_turn += 1;
+    if queue.is_empty() {
+      self.queues.remove(&device_id);
+      self.turns.pop_front();
+      self.writes_this_turn = 0;
+    } else if self.writes_this_turn >= *weight {
+      self.turns.rotate_left(1);
+      self.writes_this_turn = 0;
+    }
+    Some(job)
+  }
+
+  async fn run(mut self, mut receiver: mpsc::UnboundedReceiver<QueuedWrite>) {
+    loop {
+      // Pick up everything that was queued while the last write ran, so turns are decided over
+      // all of it.
+      while let Ok(write) = receiver.try_recv() {
+        self.enqueue(write);
+      }
+      if let Some(job) = self.next_job() {
+        job.await;
+        continue;
+      }
+      match receiver.recv().await {
+        Some(write) => self.enqueue(write),
+        None => break,
+      }
+    }
+    debug!("Exiting btleplug write scheduler task.");
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use std::sync::Mutex;
+
+  #[tokio::test]
+  async fn test_write_scheduler_takes_turns_by_weight() {
+    let scheduler = BtleplugWriteScheduler::new();
+    let chatty = scheduler.device_queue(2);
+    let quiet = scheduler.device_queue(DEFAULT_WRITE_WEIGHT);
+    let order = Arc::new(Mutex::new(vec![]));
+    // Hold the scheduler up with a write that waits on us, so everything else is queued before
+    // turns are decided.
+    let (release_sender, release_receiver) = oneshot::channel::<()>();
+    let blocker = quiet.schedule(async move {
+      let _ = release_receiver.await;
+    });
+    let record = |name: String| {
+      let order = order.clone();
+      async move { order.lock().unwrap().push(name) }
+    };
+    let mut writes = vec![];
+    for i in 0..6 {
+      writes.push(chatty.schedule(record(format!("chatty{}", i))));
+    }
+    for i in 0..2 {
+      writes.push(quiet.schedule(record(format!("quiet{}", i))));
+    }
+    tokio::task::yield_now().await;
+    release_sender.send(()).unwrap();
+    blocker.await.unwrap();
+    for write in writes {
+      write.await.unwrap();
+    }
+    assert_eq!(
+      *order.lock().unwrap(),
+      vec!["chatty0", "chatty1", "quiet0", "chatty2", "chatty3", "quiet1", "chatty4", "chatty5"]
+    );
+  }
+
+  #[tokio::test]
+  async fn test_write_scheduler_returns_write_output() {
+    let scheduler = BtleplugWriteScheduler::new();
+    let queue = scheduler.device_queue(0);
+    assert_eq!(queue.schedule(async { 5 }).await, Some(5));
+  }
+}
@@ -8,4 +8,6 @@
 pub mod btleplug_comm_manager;
 pub use btleplug_comm_manager::BtlePlugCommunicationManagerBuilder;
 mod btleplug_adapter_task;
+mod btleplug_connection_parameters;
 pub mod btleplug_hardware;
+mod btleplug_write_scheduler;
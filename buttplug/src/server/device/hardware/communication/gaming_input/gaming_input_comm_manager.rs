@@ -0,0 +1,137 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2024 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+use super::gaming_input_hardware::GamingInputHardwareConnector;
+use crate::{
+  core::errors::ButtplugDeviceError,
+  server::device::hardware::communication::{
+    HardwareCommunicationManager,
+    HardwareCommunicationManagerBuilder,
+    HardwareCommunicationManagerEvent,
+    HardwareSpecificError,
+    TimedRetryCommunicationManager,
+    TimedRetryCommunicationManagerImpl,
+  },
+};
+use async_trait::async_trait;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+use windows::Gaming::Input::{Gamepad, RawGameController};
+
+const MICROSOFT_VENDOR_ID: u16 = 0x045e;
+/// Xbox 360 pads (wired, and the wireless receivers) enumerate as gamepads too, but have no
+/// trigger motors.
+const XBOX_360_PRODUCT_IDS: [u16; 5] = [0x028e, 0x028f, 0x0291, 0x02a1, 0x0719];
+
+// Hardware names double as device config identifiers, which is how pads without trigger motors
+// end up with only two vibrators.
+const IMPULSE_TRIGGER_GAMEPAD_NAME: &str = "Gaming Input Gamepad";
+const RUMBLE_GAMEPAD_NAME: &str = "Gaming Input Rumble Gamepad";
+
+/// Windows.Gaming.Input has no way to ask a gamepad whether it has impulse triggers, so go by
+/// hardware IDs. Every Xbox One/Series pad Microsoft makes has them.
+fn has_impulse_triggers(raw_controller: &RawGameController) -> bool {
+  matches!(
+    (raw_controller.HardwareVendorId(), raw_controller.HardwareProductId()),
+    (Ok(MICROSOFT_VENDOR_ID), Ok(product_id)) if !XBOX_360_PRODUCT_IDS.contains(&product_id)
+  )
+}
+
+#[derive(Default, Clone)]
+pub struct GamingInputCommunicationManagerBuilder {}
+
+impl HardwareCommunicationManagerBuilder for GamingInputCommunicationManagerBuilder {
+  fn finish(
+    &mut self,
+    sender: mpsc::Sender<HardwareCommunicationManagerEvent>,
+    cancellation_token: CancellationToken,
+  ) -> Box<dyn HardwareCommunicationManager> {
+    Box::new(TimedRetryCommunicationManager::new(
+      GamingInputCommunicationManager::new(sender.clone()),
+      sender,
+      cancellation_token,
+    ))
+  }
+}
+
+pub struct GamingInputCommunicationManager {
+  sender: mpsc::Sender<HardwareCommunicationManagerEvent>,
+}
+
+impl GamingInputCommunicationManager {
+  fn new(sender: mpsc::Sender<HardwareCommunicationManagerEvent>) -> Self {
+    Self { sender }
+  }
+}
+
+#[async_trait]
+impl TimedRetryCommunicationManagerImpl for GamingInputCommunicationManager {
+  fn name(&self) -> &'static str {
+    "GamingInputCommunicationManager"
+  }
+
+  async fn scan(&self) -> Result<(), ButtplugDeviceError> {
+    trace!("Gaming Input manager scanning for devices");
+    // Work from a snapshot, as the live list can change while we go through it.
+    let gamepads: Vec<Gamepad> = Gamepad::Gamepads()
+      .map_err(|e| {
+        ButtplugDeviceError::from(HardwareSpecificError::GamingInputError(format!("{:?}", e)))
+      })?
+      .into_iter()
+      .collect();
+    for gamepad in gamepads {
+      // Unlike XInput slots, the non-roamable ID sticks with a pad on this machine across
+      // reconnects and reboots, so user configs for it keep applying.
+      let raw_controller = match RawGameController::FromGameController(&gamepad) {
+        Ok(raw_controller) => raw_controller,
+        Err(e) => {
+          warn!(
+            "Cannot get Gaming Input controller info, skipping gamepad: {:?}",
+            e
+          );
+          continue;
+        }
+      };
+      let address = match raw_controller.NonRoamableId() {
+        Ok(id) => format!("gaming-input-{}", id),
+        Err(e) => {
+          warn!(
+            "Cannot get Gaming Input controller ID, skipping gamepad: {:?}",
+            e
+          );
+          continue;
+        }
+      };
+      let name = if has_impulse_triggers(&raw_controller) {
+        IMPULSE_TRIGGER_GAMEPAD_NAME
+      } else {
+        RUMBLE_GAMEPAD_NAME
+      };
+      debug!("Gaming Input manager found device {}", address);
+      let creator = Box::new(GamingInputHardwareConnector::new(gamepad, name, &address));
+      if self
+        .sender
+        .send(HardwareCommunicationManagerEvent::DeviceFound {
+          name: creator.name(),
+          address: creator.address(),
+          creator,
+        })
+        .await
+        .is_err()
+      {
+        error!("Error sending device found message from Gaming Input.");
+        break;
+      }
+    }
+    Ok(())
+  }
+
+  // Windows.Gaming.Input is always there on windows, which is the only place this builds.
+  fn can_scan(&self) -> bool {
+    true
+  }
+}
@@ -0,0 +1,208 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2024 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+use crate::{
+  core::{errors::ButtplugDeviceError, message::Endpoint},
+  server::device::hardware::communication::HardwareSpecificError,
+  server::device::{
+    configuration::{GamingInputSpecifier, ProtocolCommunicationSpecifier},
+    hardware::{
+      GenericHardwareSpecializer,
+      Hardware,
+      HardwareConnector,
+      HardwareEvent,
+      HardwareInternal,
+      HardwareReadCmd,
+      HardwareReading,
+      HardwareSpecializer,
+      HardwareSubscribeCmd,
+      HardwareUnsubscribeCmd,
+      HardwareWriteCmd,
+    },
+  },
+};
+use async_trait::async_trait;
+use byteorder::{LittleEndian, ReadBytesExt};
+use futures::future::{self, BoxFuture, FutureExt};
+use std::{
+  fmt::{self, Debug},
+  io::Cursor,
+};
+use tokio::sync::broadcast;
+use windows::{
+  Foundation::{EventHandler, EventRegistrationToken},
+  Gaming::Input::{Gamepad, GamepadVibration},
+};
+
+fn gaming_input_error(error: windows::core::Error) -> ButtplugDeviceError {
+  ButtplugDeviceError::from(HardwareSpecificError::GamingInputError(format!(
+    "{:?}",
+    error
+  )))
+}
+
+pub struct GamingInputHardwareConnector {
+  gamepad: Gamepad,
+  name: String,
+  address: String,
+}
+
+impl GamingInputHardwareConnector {
+  pub fn new(gamepad: Gamepad, name: &str, address: &str) -> Self {
+    Self {
+      gamepad,
+      name: name.to_owned(),
+      address: address.to_owned(),
+    }
+  }
+
+  pub fn name(&self) -> String {
+    self.name.clone()
+  }
+
+  pub fn address(&self) -> String {
+    self.address.clone()
+  }
+}
+
+impl Debug for GamingInputHardwareConnector {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("GamingInputHardwareConnector")
+      .field("name", &self.name)
+      .field("address", &self.address)
+      .finish()
+  }
+}
+
+#[async_trait]
+impl HardwareConnector for GamingInputHardwareConnector {
+  fn specifier(&self) -> ProtocolCommunicationSpecifier {
+    ProtocolCommunicationSpecifier::GamingInput(GamingInputSpecifier::default())
+  }
+
+  async fn connect(&mut self) -> Result<Box<dyn HardwareSpecializer>, ButtplugDeviceError> {
+    debug!("Emitting a new Gaming Input gamepad impl.");
+    let address = self.address();
+    let hardware_internal = GamingInputHardware::new(self.gamepad.clone(), &address)?;
+    let hardware = Hardware::new(
+      &self.name(),
+      &address,
+      &[Endpoint::Tx],
+      Box::new(hardware_internal),
+    );
+    Ok(Box::new(GenericHardwareSpecializer::new(hardware)))
+  }
+}
+
+pub struct GamingInputHardware {
+  gamepad: Gamepad,
+  event_sender: broadcast::Sender<HardwareEvent>,
+  removed_token: EventRegistrationToken,
+}
+
+impl GamingInputHardware {
+  fn new(gamepad: Gamepad, address: &str) -> Result<Self, ButtplugDeviceError> {
+    let (event_sender, _) = broadcast::channel(256);
+    // Windows tells us about gamepads going away, so there's no need to poll like XInput does.
+    let sender = event_sender.clone();
+    let watched = gamepad.clone();
+    let address = address.to_owned();
+    let removed_token = Gamepad::GamepadRemoved(&EventHandler::<Gamepad>::new(
+      move |_, removed: &Option<Gamepad>| {
+        if removed.as_ref() == Some(&watched) {
+          // No one may be listening yet, in which case there's no one to tell.
+          let _ = sender.send(HardwareEvent::Disconnected(address.clone()));
+        }
+        Ok(())
+      },
+    ))
+    .map_err(gaming_input_error)?;
+    Ok(Self {
+      gamepad,
+      event_sender,
+      removed_token,
+    })
+  }
+}
+
+impl HardwareInternal for GamingInputHardware {
+  fn event_stream(&self) -> broadcast::Receiver<HardwareEvent> {
+    self.event_sender.subscribe()
+  }
+
+  fn disconnect(&self) -> BoxFuture<'static, Result<(), ButtplugDeviceError>> {
+    future::ready(Ok(())).boxed()
+  }
+
+  fn read_value(
+    &self,
+    _msg: &HardwareReadCmd,
+  ) -> BoxFuture<'static, Result<HardwareReading, ButtplugDeviceError>> {
+    future::ready(Err(ButtplugDeviceError::UnhandledCommand(
+      "Gaming Input hardware does not support read".to_owned(),
+    )))
+    .boxed()
+  }
+
+  fn write_value(
+    &self,
+    msg: &HardwareWriteCmd,
+  ) -> BoxFuture<'static, Result<(), ButtplugDeviceError>> {
+    let gamepad = self.gamepad.clone();
+    let data = msg.data.clone();
+    async move {
+      // Speeds come packed as little endian u16s, in the order GamepadVibration lists the motors,
+      // and Windows.Gaming.Input wants them as 0.0-1.0. Pads without trigger motors are only sent
+      // the first two, so the triggers stay off.
+      let mut cursor = Cursor::new(data);
+      let mut next_speed = || {
+        cursor
+          .read_u16::<LittleEndian>()
+          .map_or(0.0, |speed| speed as f64 / u16::MAX as f64)
+      };
+      let vibration = GamepadVibration {
+        LeftMotor: next_speed(),
+        RightMotor: next_speed(),
+        LeftTrigger: next_speed(),
+        RightTrigger: next_speed(),
+      };
+      gamepad.SetVibration(vibration).map_err(gaming_input_error)
+    }
+    .boxed()
+  }
+
+  fn subscribe(
+    &self,
+    _msg: &HardwareSubscribeCmd,
+  ) -> BoxFuture<'static, Result<(), ButtplugDeviceError>> {
+    future::ready(Err(ButtplugDeviceError::UnhandledCommand(
+      "Gaming Input hardware does not support subscribe".to_owned(),
+    )))
+    .boxed()
+  }
+
+  fn unsubscribe(
+    &self,
+    _msg: &HardwareUnsubscribeCmd,
+  ) -> BoxFuture<'static, Result<(), ButtplugDeviceError>> {
+    future::ready(Err(ButtplugDeviceError::UnhandledCommand(
+      "Gaming Input hardware does not support unsubscribe".to_owned(),
+    )))
+    .boxed()
+  }
+}
+
+impl Drop for GamingInputHardware {
+  fn drop(&mut self) {
+    if let Err(e) = Gamepad::RemoveGamepadRemoved(self.removed_token) {
+      warn!(
+        "Cannot stop watching for Gaming Input gamepad removal: {:?}",
+        e
+      );
+    }
+  }
+}
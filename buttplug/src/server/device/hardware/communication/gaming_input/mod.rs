@@ -0,0 +1,24 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2024 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Gamepads through Windows.Gaming.Input.
+//!
+//! XInput only knows about the two main rumble motors, so on Xbox One/Series controllers the motors
+//! in the triggers go unused. Windows.Gaming.Input can drive all four, so gamepads found here get a
+//! vibrator feature per motor, or just the two main ones on pads without trigger motors.
+//!
+//! XInput also stops at four controllers and addresses them by slot, which changes as pads come and
+//! go. Windows.Gaming.Input has no limit on controller count, and pads are addressed by their
+//! non-roamable ID, which stays the same for a pad on a given machine.
+
+mod gaming_input_comm_manager;
+mod gaming_input_hardware;
+
+pub use gaming_input_comm_manager::{
+  GamingInputCommunicationManager,
+  GamingInputCommunicationManagerBuilder,
+};
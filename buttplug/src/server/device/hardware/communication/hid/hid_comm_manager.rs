@@ -12,6 +12,7 @@ use async_trait::async_trait;
 use hidapi::HidApi;
 use std::sync::Arc;
 use tokio::sync::mpsc::Sender;
+use tokio_util::sync::CancellationToken;
 
 use super::hid_device_impl::HidHardwareConnector;
 
@@ -22,9 +23,12 @@ impl HardwareCommunicationManagerBuilder for HidCommunicationManagerBuilder {
   fn finish(
     &mut self,
     sender: Sender<HardwareCommunicationManagerEvent>,
+    cancellation_token: CancellationToken,
   ) -> Box<dyn HardwareCommunicationManager> {
     Box::new(TimedRetryCommunicationManager::new(
-      HidCommunicationManager::new(sender),
+      HidCommunicationManager::new(sender.clone()),
+      sender,
+      cancellation_token,
     ))
   }
 }
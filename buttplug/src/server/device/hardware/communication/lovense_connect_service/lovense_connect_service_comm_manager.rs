@@ -24,6 +24,7 @@ use serde_aux::prelude::*;
 use std::{collections::HashMap, time::Duration};
 use tokio::sync::mpsc;
 use tokio::sync::mpsc::Sender;
+use tokio_util::sync::CancellationToken;
 
 #[derive(Deserialize, Debug, Clone)]
 pub(super) struct LovenseServiceToyInfo {
@@ -117,9 +118,12 @@ impl HardwareCommunicationManagerBuilder for LovenseConnectServiceCommunicationM
   fn finish(
     &mut self,
     sender: Sender<HardwareCommunicationManagerEvent>,
+    cancellation_token: CancellationToken,
   ) -> Box<dyn HardwareCommunicationManager> {
     Box::new(TimedRetryCommunicationManager::new(
-      LovenseConnectServiceCommunicationManager::new(sender),
+      LovenseConnectServiceCommunicationManager::new(sender.clone()),
+      sender,
+      cancellation_token,
     ))
   }
 }
@@ -128,14 +128,22 @@ impl LovenseDongleHardware {
     let device_event_sender_clone = device_event_sender.clone();
     async_manager::spawn(async move {
       while let Some(msg) = device_incoming.recv().await {
+        if let Some(rssi) = msg.data.as_ref().and_then(|data| data.rssi) {
+          trace!(
+            "Lovense dongle toy {} signal strength: {}",
+            address_clone,
+            rssi
+          );
+          // Nobody listening isn't a problem, signal strength is only for whoever cares to watch.
+          let _ = device_event_sender_clone.send(HardwareEvent::Rssi(address_clone.clone(), rssi));
+        }
         if msg.func != LovenseDongleMessageFunc::ToyData {
           continue;
         }
-        let data_str = msg
-          .data
-          .expect("USB format shouldn't change")
-          .data
-          .expect("USB format shouldn't change");
+        // Messages that only carry signal strength don't have anything for the protocol.
+        let Some(data_str) = msg.data.and_then(|data| data.data) else {
+          continue;
+        };
         if device_event_sender_clone
           .send(HardwareEvent::Notification(
             address_clone.clone(),
@@ -0,0 +1,117 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2024 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Notifications for serial ports showing up after startup, so dongles can be plugged in without
+//! restarting the server. Linux gets these from udev. Everywhere else, we compare the port list
+//! every few seconds.
+
+use serialport::available_ports;
+use std::{collections::HashSet, thread, time::Duration};
+use tokio::sync::mpsc::{channel, error::TrySendError, Receiver, Sender};
+use tokio_util::sync::CancellationToken;
+
+const PORT_POLL_INTERVAL: Duration = Duration::from_secs(2);
+/// The udev socket is nonblocking and queues events for us, so this only sets how quickly we get
+/// around to looking at them.
+#[cfg(target_os = "linux")]
+const UDEV_CHECK_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Start watching for serial ports being plugged in. The returned receiver gets a message every
+/// time new ports show up, and closes once `token` is cancelled. Messages don't say which port
+/// appeared, listeners are expected to enumerate ports again.
+pub fn watch_serial_ports(token: CancellationToken) -> Receiver<()> {
+  // A single pending notification covers any number of ports, so there's no need to queue more.
+  let (sender, receiver) = channel(1);
+  thread::Builder::new()
+    .name("Serial Port Hotplug Thread".to_string())
+    .spawn(move || {
+      #[cfg(target_os = "linux")]
+      match udev_watch(&sender, &token) {
+        Ok(()) => return,
+        Err(e) => warn!(
+          "Cannot watch udev for serial ports, falling back to polling: {}",
+          e
+        ),
+      }
+      poll_watch(&sender, &token);
+    })
+    .expect("Thread should always create");
+  receiver
+}
+
+/// Let the listener know ports have changed. Returns false if the listener has gone away.
+fn notify(sender: &Sender<()>) -> bool {
+  !matches!(sender.try_send(()), Err(TrySendError::Closed(_)))
+}
+
+#[cfg(target_os = "linux")]
+fn udev_watch(sender: &Sender<()>, token: &CancellationToken) -> Result<(), libudev::Error> {
+  let context = libudev::Context::new()?;
+  let mut monitor = libudev::Monitor::new(&context)?;
+  monitor.match_subsystem("tty")?;
+  let mut socket = monitor.listen()?;
+  debug!("Watching udev for serial ports.");
+  while !token.is_cancelled() {
+    let mut added = false;
+    while let Some(event) = socket.receive_event() {
+      if event.event_type() == libudev::EventType::Add {
+        debug!("Serial port added: {:?}", event.devnode());
+        added = true;
+      }
+    }
+    if added && !notify(sender) {
+      break;
+    }
+    thread::sleep(UDEV_CHECK_INTERVAL);
+  }
+  Ok(())
+}
+
+fn poll_watch(sender: &Sender<()>, token: &CancellationToken) {
+  debug!("Polling for serial ports every {:?}.", PORT_POLL_INTERVAL);
+  let mut known_ports = port_names();
+  while !token.is_cancelled() {
+    thread::sleep(PORT_POLL_INTERVAL);
+    if has_new_ports(&mut known_ports, port_names()) && !notify(sender) {
+      break;
+    }
+  }
+}
+
+fn port_names() -> HashSet<String> {
+  available_ports()
+    .map(|ports| ports.into_iter().map(|port| port.port_name).collect())
+    .unwrap_or_default()
+}
+
+/// Update our known ports, returning whether any of the current ones are new. Ports that went away
+/// are forgotten, so plugging the same dongle back in counts as new.
+fn has_new_ports(known_ports: &mut HashSet<String>, current_ports: HashSet<String>) -> bool {
+  let added = !current_ports.is_subset(known_ports);
+  *known_ports = current_ports;
+  added
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn ports(names: &[&str]) -> HashSet<String> {
+    names.iter().map(|name| name.to_string()).collect()
+  }
+
+  #[test]
+  fn test_has_new_ports() {
+    let mut known = ports(&["COM1"]);
+    assert!(!has_new_ports(&mut known, ports(&["COM1"])));
+    assert!(has_new_ports(&mut known, ports(&["COM1", "COM3"])));
+    assert!(!has_new_ports(&mut known, ports(&["COM3"])));
+    // Unplugging and replugging a port shows up as new again.
+    assert!(has_new_ports(&mut known, ports(&["COM1", "COM3"])));
+    assert!(!has_new_ports(&mut known, ports(&[])));
+  }
+}
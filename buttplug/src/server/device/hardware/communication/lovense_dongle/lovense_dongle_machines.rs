@@ -0,0 +1,478 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2024 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+use super::{
+  lovense_dongle_messages::{
+    LovenseDeviceCommand,
+    LovenseDongleIncomingMessage,
+    OutgoingLovenseData,
+  },
+  lovense_dongle_state_machine::create_lovense_dongle_machine,
+};
+use crate::{
+  server::device::hardware::communication::{
+    spawn_manager_task,
+    HardwareCommunicationManagerEvent,
+  },
+  util::sleep,
+};
+use futures::{pin_mut, Future};
+use std::{
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+    Mutex,
+  },
+  time::Duration,
+};
+use tokio::{
+  sync::mpsc::{channel, Receiver, Sender},
+  time::timeout,
+};
+use tokio_util::sync::CancellationToken;
+use tracing_futures::Instrument;
+
+// Gives the old connection's threads time to let go of the port before we try to open it again.
+const DONGLE_REOPEN_DELAY: Duration = Duration::from_secs(1);
+const DONGLE_REOPEN_ATTEMPTS: usize = 5;
+/// How long a dongle's state machine gets to stop toys and close its connection on shutdown.
+const DONGLE_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Channels for a freshly opened dongle connection, along with the token that tears it down.
+pub type DongleConnection = (
+  Sender<OutgoingLovenseData>,
+  Receiver<LovenseDongleIncomingMessage>,
+  CancellationToken,
+);
+
+#[derive(Debug)]
+struct DongleMachine {
+  name: String,
+  command_sender: Sender<LovenseDeviceCommand>,
+  is_scanning: Arc<AtomicBool>,
+}
+
+/// State machines for every dongle a comm manager has found.
+///
+/// Each dongle gets its own state machine and channel hub, so toys on one dongle never wait on
+/// traffic for another. Device events from all of the machines are merged back into the comm
+/// manager's event stream, with scanning only reported finished once every dongle is done.
+#[derive(Clone, Debug)]
+pub struct LovenseDongleMachines {
+  event_sender: Sender<HardwareCommunicationManagerEvent>,
+  machines: Arc<Mutex<Vec<DongleMachine>>>,
+  // Scan requests that come in before we have any dongles, so the first one can pick them up.
+  wants_scan: Arc<AtomicBool>,
+  cancellation_token: CancellationToken,
+}
+
+impl LovenseDongleMachines {
+  pub fn new(
+    event_sender: Sender<HardwareCommunicationManagerEvent>,
+    cancellation_token: CancellationToken,
+  ) -> Self {
+    Self {
+      event_sender,
+      machines: Arc::new(Mutex::new(vec![])),
+      wants_scan: Arc::new(AtomicBool::new(false)),
+      cancellation_token,
+    }
+  }
+
+  /// Start a state machine for a newly found dongle. `name` is only used for logging.
+  pub async fn add_dongle(
+    &self,
+    name: &str,
+    dongle_outgoing: Sender<OutgoingLovenseData>,
+    dongle_incoming: Receiver<LovenseDongleIncomingMessage>,
+  ) {
+    info!("Starting state machine for Lovense dongle {}", name);
+    let (command_sender, command_receiver) = channel(256);
+    let (machine_event_sender, mut machine_event_receiver) = channel(256);
+    let is_scanning = Arc::new(AtomicBool::new(false));
+    let mut machine = create_lovense_dongle_machine(
+      name,
+      machine_event_sender,
+      command_receiver,
+      is_scanning.clone(),
+    );
+    let machine_token = self.cancellation_token.child_token();
+    let shutdown_sender = command_sender.clone();
+    spawn_manager_task(
+      self.event_sender.clone(),
+      async move {
+        let run = async move {
+          while let Some(next) = machine.transition().await {
+            machine = next;
+          }
+        };
+        pin_mut!(run);
+        tokio::select! {
+          _ = &mut run => return,
+          _ = machine_token.cancelled() => {}
+        }
+        // Toys keep doing whatever they were last told until someone says otherwise, so give the
+        // machine a chance to stop them before the connection goes away.
+        if shutdown_sender
+          .try_send(LovenseDeviceCommand::Shutdown)
+          .is_err()
+          || timeout(DONGLE_SHUTDOWN_TIMEOUT, run).await.is_err()
+        {
+          warn!("Lovense dongle state machine did not shut down cleanly.");
+        }
+      }
+      .instrument(tracing::info_span!(
+        "Lovense Dongle State Machine",
+        dongle = name
+      )),
+    );
+
+    let machines = self.machines.clone();
+    let event_sender = self.event_sender.clone();
+    spawn_manager_task(self.event_sender.clone(), async move {
+      while let Some(event) = machine_event_receiver.recv().await {
+        if matches!(event, HardwareCommunicationManagerEvent::ScanningFinished)
+          && any_scanning(&machines)
+        {
+          debug!("Lovense dongle finished scanning, waiting on other dongles.");
+          continue;
+        }
+        if event_sender.send(event).await.is_err() {
+          debug!("Device manager disappeared, stopping Lovense dongle event forwarding.");
+          break;
+        }
+      }
+    });
+
+    self
+      .machines
+      .lock()
+      .expect("Lock is never held across a panic")
+      .push(DongleMachine {
+        name: name.to_owned(),
+        command_sender: command_sender.clone(),
+        is_scanning,
+      });
+    let should_scan = self.wants_scan.load(Ordering::SeqCst);
+    if command_sender
+      .send(LovenseDeviceCommand::DongleFound(
+        dongle_outgoing,
+        dongle_incoming,
+      ))
+      .await
+      .is_err()
+    {
+      warn!("Lovense dongle state machine for {} already exited.", name);
+      return;
+    }
+    if should_scan {
+      // The machine holds on to this until it's done checking for already connected toys.
+      let _ = command_sender
+        .send(LovenseDeviceCommand::StartScanning)
+        .await;
+    }
+  }
+
+  /// Watch a dongle's connection for resets, reopening it with `reopen` and handing the new
+  /// channels to the dongle's state machine. Connections are reset by the comm manager's write
+  /// thread when the state machine asks for it, usually because the dongle stopped responding.
+  pub fn supervise_dongle<F, Fut>(
+    &self,
+    name: &str,
+    connection_token: CancellationToken,
+    mut reopen: F,
+  ) where
+    F: FnMut() -> Fut + Send + 'static,
+    Fut: Future<Output = Option<DongleConnection>> + Send,
+  {
+    let machines = self.clone();
+    let name = name.to_owned();
+    spawn_manager_task(self.event_sender.clone(), async move {
+      let mut connection_token = connection_token;
+      'reset: loop {
+        tokio::select! {
+          _ = connection_token.cancelled() => {}
+          _ = machines.cancellation_token.cancelled() => {
+            // The state machine closes the connection itself once it's stopped all the toys, but
+            // make sure the threads go away even if it can't.
+            let _ = timeout(DONGLE_SHUTDOWN_TIMEOUT, connection_token.cancelled()).await;
+            connection_token.cancel();
+            return;
+          }
+        }
+        if machines.cancellation_token.is_cancelled() {
+          return;
+        }
+        info!("Lovense dongle {} connection was reset, reopening.", name);
+        for _ in 0..DONGLE_REOPEN_ATTEMPTS {
+          sleep(DONGLE_REOPEN_DELAY).await;
+          if machines.cancellation_token.is_cancelled() {
+            return;
+          }
+          if let Some((dongle_outgoing, dongle_incoming, token)) = reopen().await {
+            connection_token = token;
+            machines
+              .reconnect_dongle(&name, dongle_outgoing, dongle_incoming)
+              .await;
+            continue 'reset;
+          }
+        }
+        error!(
+          "Cannot reopen Lovense dongle {} after {} attempts, giving up.",
+          name, DONGLE_REOPEN_ATTEMPTS
+        );
+        return;
+      }
+    });
+  }
+
+  /// Hand a dongle's new channels to its state machine, starting one up if this is a dongle we
+  /// haven't seen before.
+  pub async fn reconnect_dongle(
+    &self,
+    name: &str,
+    dongle_outgoing: Sender<OutgoingLovenseData>,
+    dongle_incoming: Receiver<LovenseDongleIncomingMessage>,
+  ) {
+    let command_sender = self
+      .machines
+      .lock()
+      .expect("Lock is never held across a panic")
+      .iter()
+      .find(|machine| machine.name == name)
+      .map(|machine| machine.command_sender.clone());
+    match command_sender {
+      Some(command_sender) => {
+        if command_sender
+          .send(LovenseDeviceCommand::DongleFound(
+            dongle_outgoing,
+            dongle_incoming,
+          ))
+          .await
+          .is_err()
+        {
+          warn!("Lovense dongle state machine for {} has exited.", name);
+        }
+      }
+      None => {
+        self
+          .add_dongle(name, dongle_outgoing, dongle_incoming)
+          .await
+      }
+    }
+  }
+
+  pub fn dongle_count(&self) -> usize {
+    self
+      .machines
+      .lock()
+      .expect("Lock is never held across a panic")
+      .len()
+  }
+
+  pub fn is_scanning(&self) -> bool {
+    any_scanning(&self.machines)
+      || (self.dongle_count() == 0 && self.wants_scan.load(Ordering::SeqCst))
+  }
+
+  pub async fn start_scanning(&self) {
+    self.wants_scan.store(true, Ordering::SeqCst);
+    self.send_scan_command(true).await;
+  }
+
+  pub async fn stop_scanning(&self) {
+    self.wants_scan.store(false, Ordering::SeqCst);
+    if self.dongle_count() == 0 {
+      // Nothing to stop, but act like we at least tried.
+      if self
+        .event_sender
+        .send(HardwareCommunicationManagerEvent::ScanningFinished)
+        .await
+        .is_err()
+      {
+        warn!("Dongle message sent without owner being alive, assuming shutdown.");
+      }
+      return;
+    }
+    self.send_scan_command(false).await;
+  }
+
+  async fn send_scan_command(&self, should_scan: bool) {
+    let senders: Vec<(String, Sender<LovenseDeviceCommand>)> = self
+      .machines
+      .lock()
+      .expect("Lock is never held across a panic")
+      .iter()
+      .map(|machine| (machine.name.clone(), machine.command_sender.clone()))
+      .collect();
+    for (name, sender) in senders {
+      let command = if should_scan {
+        LovenseDeviceCommand::StartScanning
+      } else {
+        LovenseDeviceCommand::StopScanning
+      };
+      if sender.send(command).await.is_err() {
+        warn!("Lovense dongle state machine for {} has exited.", name);
+      }
+    }
+  }
+}
+
+fn any_scanning(machines: &Mutex<Vec<DongleMachine>>) -> bool {
+  machines
+    .lock()
+    .expect("Lock is never held across a panic")
+    .iter()
+    .any(|machine| machine.is_scanning.load(Ordering::SeqCst))
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::server::device::hardware::communication::lovense_dongle::lovense_dongle_messages::LovenseDongleMessageFunc;
+  use std::time::Duration;
+  use tokio::time::{sleep, timeout};
+
+  struct TestDongle {
+    outgoing: Receiver<OutgoingLovenseData>,
+    incoming: Sender<LovenseDongleIncomingMessage>,
+  }
+
+  impl TestDongle {
+    async fn add(machines: &LovenseDongleMachines, name: &str) -> Self {
+      let (outgoing_sender, outgoing) = channel(256);
+      let (incoming, incoming_receiver) = channel(256);
+      machines
+        .add_dongle(name, outgoing_sender, incoming_receiver)
+        .await;
+      let mut dongle = Self { outgoing, incoming };
+      assert_eq!(dongle.next_func().await, LovenseDongleMessageFunc::Version);
+      dongle
+        .reply(r#"{"type":"usb","func":"version","data":{"data":"1.0.0"}}"#)
+        .await;
+      assert_eq!(dongle.next_func().await, LovenseDongleMessageFunc::Statuss);
+      dongle
+    }
+
+    async fn next_func(&mut self) -> LovenseDongleMessageFunc {
+      match self.outgoing.recv().await {
+        Some(OutgoingLovenseData::Message(msg)) => msg.func,
+        msg => panic!("Unexpected dongle output {:?}", msg),
+      }
+    }
+
+    async fn reply(&self, json: &str) {
+      self
+        .incoming
+        .send(serde_json::from_str(json).unwrap())
+        .await
+        .unwrap();
+    }
+  }
+
+  #[tokio::test]
+  async fn test_multiple_dongles_merge_events() {
+    let (event_sender, mut event_receiver) = channel(256);
+    let machines = LovenseDongleMachines::new(event_sender, CancellationToken::new());
+    let mut first = TestDongle::add(&machines, "first").await;
+    let mut second = TestDongle::add(&machines, "second").await;
+    assert_eq!(machines.dongle_count(), 2);
+
+    // Firmware versions are reported per dongle.
+    let mut hardware_names = vec![];
+    for _ in 0..2 {
+      match event_receiver.recv().await {
+        Some(HardwareCommunicationManagerEvent::FirmwareVersion { hardware, version }) => {
+          assert_eq!(version, "1.0.0");
+          hardware_names.push(hardware);
+        }
+        event => panic!("Expected a firmware version, got {:?}", event),
+      }
+    }
+    hardware_names.sort();
+    assert_eq!(hardware_names, vec!["first", "second"]);
+
+    // Toys on either dongle show up through the same event stream.
+    first
+      .reply(r#"{"type":"toy","func":"status","data":{"id":"AAAAAA","status":202}}"#)
+      .await;
+    second
+      .reply(r#"{"type":"toy","func":"status","data":{"id":"BBBBBB","status":202}}"#)
+      .await;
+    let mut addresses = vec![];
+    for _ in 0..2 {
+      match event_receiver.recv().await {
+        Some(HardwareCommunicationManagerEvent::DeviceFound { address, .. }) => {
+          addresses.push(address)
+        }
+        event => panic!("Expected a found device, got {:?}", event),
+      }
+    }
+    addresses.sort();
+    assert_eq!(addresses, vec!["AAAAAA", "BBBBBB"]);
+
+    // One dongle giving up on a scan doesn't end the scan while the other is still going.
+    sleep(Duration::from_millis(300)).await;
+    machines.start_scanning().await;
+    assert_eq!(first.next_func().await, LovenseDongleMessageFunc::Search);
+    assert_eq!(second.next_func().await, LovenseDongleMessageFunc::Search);
+    assert!(machines.is_scanning());
+    first
+      .reply(r#"{"type":"toy","func":"search","result":599}"#)
+      .await;
+    assert!(matches!(
+      event_receiver.recv().await,
+      Some(HardwareCommunicationManagerEvent::Error { .. })
+    ));
+    assert!(timeout(Duration::from_millis(200), event_receiver.recv())
+      .await
+      .is_err());
+    assert!(machines.is_scanning());
+  }
+
+  #[tokio::test]
+  async fn test_comm_managers_run_dongles_independently() {
+    // HID and serial dongles live on different comm managers, each with its own set of machines
+    // but reporting to the same device manager.
+    let (event_sender, mut event_receiver) = channel(256);
+    let hid_token = CancellationToken::new();
+    let hid_machines = LovenseDongleMachines::new(event_sender.clone(), hid_token.clone());
+    let serial_machines = LovenseDongleMachines::new(event_sender, CancellationToken::new());
+    let mut hid = TestDongle::add(&hid_machines, "/dev/hidraw0").await;
+    let serial = TestDongle::add(&serial_machines, "/dev/ttyUSB0").await;
+    for _ in 0..2 {
+      assert!(matches!(
+        event_receiver.recv().await,
+        Some(HardwareCommunicationManagerEvent::FirmwareVersion { .. })
+      ));
+    }
+
+    hid
+      .reply(r#"{"type":"toy","func":"status","data":{"id":"AAAAAA","status":202}}"#)
+      .await;
+    assert!(matches!(
+      event_receiver.recv().await,
+      Some(HardwareCommunicationManagerEvent::DeviceFound { address, .. }) if address == "AAAAAA"
+    ));
+
+    // Shutting down one comm manager stops its toys, and leaves the other's dongles running.
+    hid_token.cancel();
+    assert_eq!(hid.next_func().await, LovenseDongleMessageFunc::Command);
+    assert_eq!(hid.next_func().await, LovenseDongleMessageFunc::Command);
+    assert!(matches!(
+      hid.outgoing.recv().await,
+      Some(OutgoingLovenseData::Close)
+    ));
+    serial
+      .reply(r#"{"type":"toy","func":"status","data":{"id":"BBBBBB","status":202}}"#)
+      .await;
+    assert!(matches!(
+      event_receiver.recv().await,
+      Some(HardwareCommunicationManagerEvent::DeviceFound { address, .. }) if address == "BBBBBB"
+    ));
+  }
+}
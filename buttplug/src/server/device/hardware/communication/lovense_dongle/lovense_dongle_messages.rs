@@ -6,13 +6,16 @@
 // for full license information.
 
 use serde::{Deserialize, Serialize};
-use serde_repr::*;
 use tokio::sync::mpsc::{Receiver, Sender};
 
 #[derive(Debug)]
 pub enum OutgoingLovenseData {
   Raw(String),
   Message(LovenseDongleOutgoingMessage),
+  /// Ask whoever owns the dongle connection to close it and open it back up.
+  Reset,
+  /// Ask whoever owns the dongle connection to close it for good. Sent last thing on shutdown.
+  Close,
 }
 
 #[derive(Debug)]
@@ -23,22 +26,81 @@ pub enum LovenseDeviceCommand {
   ),
   StartScanning,
   StopScanning,
+  /// Stop any connected toys and close the dongle connection, then exit the state machine.
+  Shutdown,
 }
 
-#[repr(u16)]
-#[derive(Serialize_repr, Deserialize_repr, Clone, Copy, Debug, PartialEq, Eq)]
+/// Codes the dongle sends in the `result` field of replies and the `status` field of toy status
+/// updates. Codes we don't know about parse as [LovenseDongleResultCode::Unknown] rather than
+/// failing the whole message, so the state machine still sees them.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(from = "u16", into = "u16")]
 pub enum LovenseDongleResultCode {
-  DongleInitialized = 100,
-  CommandSuccess = 200,
-  DeviceConnectInProgress = 201,
-  DeviceConnectSuccess = 202,
-  SearchStarted = 205,
-  SearchStopped = 206,
-  MalformedMessage = 400,
-  DeviceConnectionFailed = 402,
-  DeviceDisconnected = 403,
-  DeviceNotFound = 404,
-  DongleScanningInterruption = 501,
+  /// Dongle finished starting up.
+  DongleInitialized,
+  /// Last command was accepted.
+  CommandSuccess,
+  /// Dongle is connecting to the toy it found.
+  DeviceConnectInProgress,
+  /// Dongle is connected to a toy.
+  DeviceConnectSuccess,
+  SearchStarted,
+  SearchStopped,
+  /// Dongle couldn't parse the last command.
+  MalformedMessage,
+  /// Dongle found a toy but couldn't connect to it.
+  DeviceConnectionFailed,
+  DeviceDisconnected,
+  /// Toy the dongle was connecting to went away.
+  DeviceNotFound,
+  /// Search was cut short by the dongle, usually because it was busy with something else.
+  DongleScanningInterruption,
+  Unknown(u16),
+}
+
+impl LovenseDongleResultCode {
+  /// Whether the code reports a failure. Like HTTP, the dongle uses 4xx and 5xx for errors.
+  pub fn is_error(&self) -> bool {
+    u16::from(*self) >= 400
+  }
+}
+
+impl From<u16> for LovenseDongleResultCode {
+  fn from(code: u16) -> Self {
+    match code {
+      100 => Self::DongleInitialized,
+      200 => Self::CommandSuccess,
+      201 => Self::DeviceConnectInProgress,
+      202 => Self::DeviceConnectSuccess,
+      205 => Self::SearchStarted,
+      206 => Self::SearchStopped,
+      400 => Self::MalformedMessage,
+      402 => Self::DeviceConnectionFailed,
+      403 => Self::DeviceDisconnected,
+      404 => Self::DeviceNotFound,
+      501 => Self::DongleScanningInterruption,
+      code => Self::Unknown(code),
+    }
+  }
+}
+
+impl From<LovenseDongleResultCode> for u16 {
+  fn from(code: LovenseDongleResultCode) -> Self {
+    match code {
+      LovenseDongleResultCode::DongleInitialized => 100,
+      LovenseDongleResultCode::CommandSuccess => 200,
+      LovenseDongleResultCode::DeviceConnectInProgress => 201,
+      LovenseDongleResultCode::DeviceConnectSuccess => 202,
+      LovenseDongleResultCode::SearchStarted => 205,
+      LovenseDongleResultCode::SearchStopped => 206,
+      LovenseDongleResultCode::MalformedMessage => 400,
+      LovenseDongleResultCode::DeviceConnectionFailed => 402,
+      LovenseDongleResultCode::DeviceDisconnected => 403,
+      LovenseDongleResultCode::DeviceNotFound => 404,
+      LovenseDongleResultCode::DongleScanningInterruption => 501,
+      LovenseDongleResultCode::Unknown(code) => code,
+    }
+  }
 }
 
 #[derive(Serialize, Deserialize, Clone, Copy, Debug)]
@@ -72,6 +134,41 @@ pub enum LovenseDongleMessageFunc {
   Error,
   #[serde(rename = "statuss")]
   Statuss,
+  #[serde(rename = "version")]
+  Version,
+}
+
+/// Firmware version a dongle reports, as `major.minor.patch`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct LovenseDongleFirmwareVersion {
+  pub major: u32,
+  pub minor: u32,
+  pub patch: u32,
+}
+
+impl LovenseDongleFirmwareVersion {
+  pub const fn new(major: u32, minor: u32, patch: u32) -> Self {
+    Self {
+      major,
+      minor,
+      patch,
+    }
+  }
+
+  /// Parse the version string from a dongle reply. Missing minor or patch numbers count as 0.
+  pub fn parse(version: &str) -> Option<Self> {
+    let mut parts = version.trim().trim_start_matches(['v', 'V']).split('.');
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next().map_or(Some(0), |part| part.parse().ok())?;
+    let patch = parts.next().map_or(Some(0), |part| part.parse().ok())?;
+    Some(Self::new(major, minor, patch))
+  }
+}
+
+impl std::fmt::Display for LovenseDongleFirmwareVersion {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+  }
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -95,6 +192,9 @@ pub struct LovenseDongleIncomingData {
   pub data: Option<String>,
   #[serde(skip_serializing_if = "Option::is_none")]
   pub status: Option<LovenseDongleResultCode>,
+  /// Strength of the toy's link to the dongle, in dBm. Only sent by some firmware.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub rssi: Option<i32>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -8,16 +8,39 @@
 use super::{lovense_dongle_hardware::*, lovense_dongle_messages::*};
 use crate::server::device::hardware::communication::HardwareCommunicationManagerEvent;
 use async_trait::async_trait;
-use futures::{select, FutureExt};
-use std::sync::{
-  atomic::{AtomicBool, Ordering},
-  Arc,
+use futures::{future, select, FutureExt};
+use std::{
+  collections::HashMap,
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+  },
+  time::Duration,
 };
 use tokio::{
   sync::mpsc::{channel, Receiver, Sender},
-  time::sleep,
+  time::{sleep, sleep_until, Instant},
 };
 
+// The dongle stops answering if Search and StopSearch are sent too close together, so every scan
+// toggle waits at least this long after the previous one.
+const SCAN_TOGGLE_SPACING: Duration = Duration::from_millis(500);
+// How long to wait for the dongle to tell us its firmware version. Older firmware doesn't answer at
+// all.
+const FIRMWARE_VERSION_TIMEOUT: Duration = Duration::from_millis(250);
+// Dongles that were just plugged in can miss the version query we send while checking for
+// connected toys, so idle asks again once.
+const FIRMWARE_VERSION_QUERIES: u32 = 2;
+// First firmware that can push toy status changes to us instead of waiting to be asked.
+const EAGER_STATUS_MIN_FIRMWARE: LovenseDongleFirmwareVersion =
+  LovenseDongleFirmwareVersion::new(1, 4, 0);
+// The dongle answers everything we send it other than status and version queries. If it hasn't
+// said anything this long after we sent something, it's locked up.
+const DONGLE_WATCHDOG_TIMEOUT: Duration = Duration::from_secs(5);
+// Sent to every connected toy on shutdown. We don't know what kind of toy is on the other end of
+// the dongle, and toys ignore commands for motors they don't have.
+const TOY_STOP_COMMANDS: [&str; 2] = ["Vibrate:0;", "Rotate:0;"];
+
 // I found this hot dog on the ground at
 // https://news.ycombinator.com/item?id=22752907 and dusted it off. It still
 // tastes fine.
@@ -32,19 +55,29 @@ enum IncomingMessage {
   Dongle(LovenseDongleIncomingMessage),
   Device(OutgoingLovenseData),
   Disconnect,
+  Unresponsive,
 }
 
 #[derive(Debug)]
 struct ChannelHub {
+  // Tells this dongle apart from others on the same comm manager, usually its port name.
+  dongle_name: String,
   comm_manager_incoming: Receiver<LovenseDeviceCommand>,
   dongle_outgoing: Sender<OutgoingLovenseData>,
   dongle_incoming: Receiver<LovenseDongleIncomingMessage>,
   event_outgoing: Sender<HardwareCommunicationManagerEvent>,
   is_scanning: Arc<AtomicBool>,
+  last_scan_toggle: Option<Instant>,
+  firmware_version: Option<LovenseDongleFirmwareVersion>,
+  firmware_version_queries: u32,
+  response_deadline: Option<Instant>,
+  // Set when a shutdown request gets folded into a scan request, so the next wait picks it up.
+  shutdown_requested: bool,
 }
 
 impl ChannelHub {
   pub fn new(
+    dongle_name: String,
     comm_manager_incoming: Receiver<LovenseDeviceCommand>,
     dongle_outgoing: Sender<OutgoingLovenseData>,
     dongle_incoming: Receiver<LovenseDongleIncomingMessage>,
@@ -52,26 +85,64 @@ impl ChannelHub {
     is_scanning: Arc<AtomicBool>,
   ) -> Self {
     Self {
+      dongle_name,
       comm_manager_incoming,
       dongle_outgoing,
       dongle_incoming,
       event_outgoing,
       is_scanning,
+      last_scan_toggle: None,
+      firmware_version: None,
+      firmware_version_queries: 0,
+      response_deadline: None,
+      shutdown_requested: false,
     }
   }
 
   pub fn create_new_wait_for_dongle_state(self) -> Option<Box<dyn LovenseDongleState>> {
     self.is_scanning.store(false, Ordering::SeqCst);
+    if self.shutdown_requested {
+      return None;
+    }
+    Some(Box::new(LovenseDongleWaitForDongle::new(
+      self.dongle_name,
+      self.comm_manager_incoming,
+      self.event_outgoing,
+      self.is_scanning,
+      false,
+    )))
+  }
+
+  /// Ask whoever owns the dongle connection to close and reopen it, then wait for it to come back.
+  /// Scanning picks up where it left off once it does.
+  pub async fn reset_connection(self) -> Option<Box<dyn LovenseDongleState>> {
+    if self.shutdown_requested {
+      return self.shutdown(vec![]).await;
+    }
+    let should_scan = self.is_scanning();
+    if self
+      .dongle_outgoing
+      .send(OutgoingLovenseData::Reset)
+      .await
+      .is_err()
+    {
+      warn!("Dongle connection already gone, waiting for it to come back.");
+    }
     Some(Box::new(LovenseDongleWaitForDongle::new(
+      self.dongle_name,
       self.comm_manager_incoming,
       self.event_outgoing,
       self.is_scanning,
+      should_scan,
     )))
   }
 
   pub async fn wait_for_dongle_input(&mut self) -> IncomingMessage {
     match self.dongle_incoming.recv().await {
-      Some(msg) => IncomingMessage::Dongle(msg),
+      Some(msg) => {
+        self.response_deadline = None;
+        IncomingMessage::Dongle(msg)
+      }
       None => {
         info!("Disconnect in dongle channel, assuming shutdown or disconnect, exiting loop");
         IncomingMessage::Disconnect
@@ -80,7 +151,11 @@ impl ChannelHub {
   }
 
   pub async fn wait_for_input(&mut self) -> IncomingMessage {
-    select! {
+    if self.shutdown_requested {
+      return IncomingMessage::CommMgr(LovenseDeviceCommand::Shutdown);
+    }
+    let deadline = self.response_deadline;
+    let msg = select! {
       comm_res = self.comm_manager_incoming.recv().fuse() => {
         match comm_res {
           Some(msg) => IncomingMessage::CommMgr(msg),
@@ -99,15 +174,22 @@ impl ChannelHub {
           }
         }
       }
-    }
+      _ = watchdog(deadline).fuse() => IncomingMessage::Unresponsive,
+    };
+    self.note_response(&msg);
+    msg
   }
 
   pub async fn wait_for_device_input(
     &mut self,
     device_incoming: &mut Receiver<OutgoingLovenseData>,
   ) -> IncomingMessage {
+    if self.shutdown_requested {
+      return IncomingMessage::CommMgr(LovenseDeviceCommand::Shutdown);
+    }
+    let deadline = self.response_deadline;
     pin_mut!(device_incoming);
-    select! {
+    let msg = select! {
       comm_res = self.comm_manager_incoming.recv().fuse() => {
         match comm_res {
           Some(msg) => IncomingMessage::CommMgr(msg),
@@ -135,13 +217,63 @@ impl ChannelHub {
           }
         }
       }
+      _ = watchdog(deadline).fuse() => IncomingMessage::Unresponsive,
+    };
+    self.note_response(&msg);
+    msg
+  }
+
+  fn note_response(&mut self, msg: &IncomingMessage) {
+    if matches!(msg, IncomingMessage::Dongle(_)) {
+      self.response_deadline = None;
     }
   }
 
-  pub async fn send_output(&self, msg: OutgoingLovenseData) {
+  /// Send data to the dongle. Returns false if the dongle connection has gone away, in which case
+  /// the current state should hand off to [Self::connection_lost].
+  #[must_use]
+  pub async fn send_output(&mut self, msg: OutgoingLovenseData) -> bool {
+    let expects_response = match &msg {
+      OutgoingLovenseData::Message(msg) => !matches!(
+        msg.func,
+        LovenseDongleMessageFunc::Statuss | LovenseDongleMessageFunc::Version
+      ),
+      _ => false,
+    };
+    if expects_response && self.response_deadline.is_none() {
+      self.response_deadline = Some(Instant::now() + DONGLE_WATCHDOG_TIMEOUT);
+    }
     if self.dongle_outgoing.send(msg).await.is_err() {
-      warn!("Dongle message sent without owner being alive, assuming shutdown.");
+      warn!(
+        "Lovense dongle {} connection closed while sending.",
+        self.dongle_name
+      );
+      return false;
     }
+    true
+  }
+
+  /// The dongle connection went away underneath us, usually because the dongle was unplugged or
+  /// the comm manager is shutting down. Let users know, then wait for the connection to come back,
+  /// picking scanning back up if we were doing it.
+  pub async fn connection_lost(self) -> Option<Box<dyn LovenseDongleState>> {
+    if self.shutdown_requested {
+      return None;
+    }
+    self
+      .send_error(format!(
+        "Lost connection to Lovense dongle {}",
+        self.dongle_name
+      ))
+      .await;
+    let should_scan = self.is_scanning();
+    Some(Box::new(LovenseDongleWaitForDongle::new(
+      self.dongle_name,
+      self.comm_manager_incoming,
+      self.event_outgoing,
+      self.is_scanning,
+      should_scan,
+    )))
   }
 
   pub async fn send_event(&self, msg: HardwareCommunicationManagerEvent) {
@@ -153,20 +285,211 @@ impl ChannelHub {
     }
   }
 
+  /// Report a dongle problem to the device manager, which passes it on to users.
+  pub async fn send_error(&self, reason: String) {
+    error!("Lovense dongle error: {}", reason);
+    self
+      .send_event(HardwareCommunicationManagerEvent::Error { reason })
+      .await;
+  }
+
+  /// Give up on a scan, letting the device manager know it's over.
+  pub async fn abandon_scanning(&self) {
+    self.set_scanning_status(false);
+    self
+      .send_event(HardwareCommunicationManagerEvent::ScanningFinished)
+      .await;
+  }
+
   pub fn set_scanning_status(&self, is_scanning: bool) {
     self.is_scanning.store(is_scanning, Ordering::SeqCst);
   }
+
+  pub fn is_scanning(&self) -> bool {
+    self.is_scanning.load(Ordering::SeqCst)
+  }
+
+  /// Waits until the dongle can take another scan toggle, folding any scan requests that come in
+  /// from the comm manager meanwhile into the one we're about to act on. Returns whether we should
+  /// end up scanning, or None if the comm manager went away.
+  pub async fn settle_scan_request(&mut self, mut should_scan: bool) -> Option<bool> {
+    if let Some(last_toggle) = self.last_scan_toggle {
+      let deadline = last_toggle + SCAN_TOGGLE_SPACING;
+      loop {
+        select! {
+          comm_res = self.comm_manager_incoming.recv().fuse() => match comm_res {
+            Some(msg) => should_scan = self.fold_scan_request(should_scan, msg),
+            None => return None,
+          },
+          _ = sleep_until(deadline).fuse() => break,
+        }
+      }
+    }
+    while let Ok(msg) = self.comm_manager_incoming.try_recv() {
+      should_scan = self.fold_scan_request(should_scan, msg);
+    }
+    Some(should_scan)
+  }
+
+  fn fold_scan_request(&mut self, should_scan: bool, msg: LovenseDeviceCommand) -> bool {
+    match msg {
+      LovenseDeviceCommand::StartScanning => true,
+      LovenseDeviceCommand::StopScanning => false,
+      LovenseDeviceCommand::Shutdown => {
+        self.shutdown_requested = true;
+        false
+      }
+      msg => {
+        warn!(
+          "Unhandled comm manager message to lovense dongle: {:?}",
+          msg
+        );
+        should_scan
+      }
+    }
+  }
+
+  /// Stop any search and every toy in `toys`, then close the dongle connection for good. Always
+  /// ends the state machine.
+  pub async fn shutdown(mut self, toys: Vec<String>) -> Option<Box<dyn LovenseDongleState>> {
+    info!("Shutting down Lovense dongle {}", self.dongle_name);
+    self.shutdown_requested = true;
+    if self.is_scanning() {
+      self.set_scanning_status(false);
+      let stop_msg = LovenseDongleOutgoingMessage {
+        message_type: LovenseDongleMessageType::Usb,
+        func: LovenseDongleMessageFunc::StopSearch,
+        eager: None,
+        id: None,
+        command: None,
+      };
+      if !self
+        .send_output(OutgoingLovenseData::Message(stop_msg))
+        .await
+      {
+        return None;
+      }
+    }
+    for id in toys {
+      for command in TOY_STOP_COMMANDS {
+        let stop_msg = LovenseDongleOutgoingMessage {
+          message_type: LovenseDongleMessageType::Toy,
+          func: LovenseDongleMessageFunc::Command,
+          eager: None,
+          id: Some(id.clone()),
+          command: Some(command.to_owned()),
+        };
+        if !self
+          .send_output(OutgoingLovenseData::Message(stop_msg))
+          .await
+        {
+          return None;
+        }
+      }
+    }
+    if self
+      .dongle_outgoing
+      .send(OutgoingLovenseData::Close)
+      .await
+      .is_err()
+    {
+      debug!("Lovense dongle connection already closed.");
+    }
+    None
+  }
+
+  pub fn mark_scan_toggle(&mut self) {
+    self.last_scan_toggle = Some(Instant::now());
+  }
+
+  /// Ask the dongle for its firmware version. Whichever state gets the reply passes it on to
+  /// [Self::record_firmware_version]. Returns false if the dongle connection is gone.
+  #[must_use]
+  pub async fn request_firmware_version(&mut self) -> bool {
+    self.firmware_version_queries += 1;
+    let version_msg = LovenseDongleOutgoingMessage {
+      func: LovenseDongleMessageFunc::Version,
+      message_type: LovenseDongleMessageType::Usb,
+      id: None,
+      command: None,
+      eager: None,
+    };
+    self
+      .send_output(OutgoingLovenseData::Message(version_msg))
+      .await
+  }
+
+  /// Whether we still need to ask this connection for its firmware version.
+  pub fn should_query_firmware_version(&self) -> bool {
+    self.firmware_version.is_none() && self.firmware_version_queries < FIRMWARE_VERSION_QUERIES
+  }
+
+  pub fn firmware_version(&self) -> Option<LovenseDongleFirmwareVersion> {
+    self.firmware_version
+  }
+
+  /// Store the firmware version from a dongle version reply, and let the comm manager know about
+  /// it so users can see whether their dongle needs an update.
+  pub async fn record_firmware_version(&mut self, msg: &LovenseDongleIncomingMessage) {
+    let Some(version) = msg
+      .data
+      .as_ref()
+      .and_then(|data| data.data.as_deref())
+      .and_then(LovenseDongleFirmwareVersion::parse)
+    else {
+      warn!(
+        "Cannot parse Lovense dongle firmware version from {:?}",
+        msg
+      );
+      return;
+    };
+    info!("Lovense dongle firmware version {}", version);
+    self.firmware_version = Some(version);
+    self
+      .send_event(HardwareCommunicationManagerEvent::FirmwareVersion {
+        hardware: self.dongle_name.clone(),
+        version: version.to_string(),
+      })
+      .await;
+  }
+
+  /// Whether the dongle can push toy status changes as they happen, so we don't miss connects and
+  /// disconnects while waiting on other traffic.
+  pub fn supports_eager_status(&self) -> bool {
+    self
+      .firmware_version
+      .is_some_and(|version| version >= EAGER_STATUS_MIN_FIRMWARE)
+  }
+}
+
+fn dongle_error(msg: &LovenseDongleIncomingMessage) -> String {
+  format!(
+    "Lovense dongle reported an error: {}",
+    msg.message.as_deref().unwrap_or("no details given")
+  )
+}
+
+/// Resolves once the dongle has gone too long without answering, or never if we're not waiting on
+/// it.
+async fn watchdog(deadline: Option<Instant>) {
+  match deadline {
+    Some(deadline) => sleep_until(deadline).await,
+    None => future::pending().await,
+  }
 }
 
 pub fn create_lovense_dongle_machine(
+  dongle_name: &str,
   event_outgoing: Sender<HardwareCommunicationManagerEvent>,
   comm_incoming_receiver: Receiver<LovenseDeviceCommand>,
   is_scanning: Arc<AtomicBool>,
 ) -> Box<dyn LovenseDongleState> {
   Box::new(LovenseDongleWaitForDongle::new(
+    dongle_name.to_owned(),
     comm_incoming_receiver,
     event_outgoing,
     is_scanning,
+    false,
   ))
 }
 
@@ -203,21 +526,27 @@ macro_rules! device_state_definition {
 
 #[derive(Debug)]
 struct LovenseDongleWaitForDongle {
+  dongle_name: String,
   comm_receiver: Receiver<LovenseDeviceCommand>,
   event_sender: Sender<HardwareCommunicationManagerEvent>,
   is_scanning: Arc<AtomicBool>,
+  should_scan: bool,
 }
 
 impl LovenseDongleWaitForDongle {
   pub fn new(
+    dongle_name: String,
     comm_receiver: Receiver<LovenseDeviceCommand>,
     event_sender: Sender<HardwareCommunicationManagerEvent>,
     is_scanning: Arc<AtomicBool>,
+    should_scan: bool,
   ) -> Self {
     Self {
+      dongle_name,
       comm_receiver,
       event_sender,
       is_scanning,
+      should_scan,
     }
   }
 }
@@ -226,11 +555,12 @@ impl LovenseDongleWaitForDongle {
 impl LovenseDongleState for LovenseDongleWaitForDongle {
   async fn transition(mut self: Box<Self>) -> Option<Box<dyn LovenseDongleState>> {
     info!("Running wait for dongle step");
-    let mut should_scan = false;
+    let mut should_scan = self.should_scan;
     while let Some(msg) = self.comm_receiver.recv().await {
       match msg {
         LovenseDeviceCommand::DongleFound(sender, receiver) => {
           let hub = ChannelHub::new(
+            self.dongle_name,
             self.comm_receiver,
             sender,
             receiver,
@@ -261,6 +591,10 @@ impl LovenseDongleState for LovenseDongleWaitForDongle {
             warn!("Dongle message sent without owner being alive, assuming shutdown.");
           }
         }
+        LovenseDeviceCommand::Shutdown => {
+          info!("Lovense dongle shut down while waiting for dongle.");
+          return None;
+        }
       }
     }
     info!("Lovense dongle receiver dropped, exiting state machine.");
@@ -283,49 +617,76 @@ impl LovenseCheckForAlreadyConnectedDevice {
 #[async_trait]
 impl LovenseDongleState for LovenseCheckForAlreadyConnectedDevice {
   async fn transition(mut self: Box<Self>) -> Option<Box<dyn LovenseDongleState>> {
+    info!("Lovense dongle checking firmware version");
+    if !self.hub.request_firmware_version().await {
+      return self.hub.connection_lost().await;
+    }
+    select! {
+      incoming_msg = self.hub.wait_for_dongle_input().fuse() => match incoming_msg {
+        IncomingMessage::Dongle(msg) if msg.func == LovenseDongleMessageFunc::Version => {
+          self.hub.record_firmware_version(&msg).await
+        }
+        msg => warn!("Cannot handle incoming message {:?}", msg),
+      },
+      _ = sleep(FIRMWARE_VERSION_TIMEOUT).fuse() => {},
+    };
+    if self.hub.firmware_version().is_none() {
+      info!("Lovense dongle did not report a firmware version, assuming older firmware.");
+    }
+
     info!("Lovense dongle checking for already connected devices");
-    // Check to see if any toy is already connected.
+    // Check to see if any toy is already connected. If the firmware can do it, also ask the dongle
+    // to keep sending toy status as it changes, which is how the idle and device loop states find
+    // out about toys coming and going.
     let autoconnect_msg = LovenseDongleOutgoingMessage {
       func: LovenseDongleMessageFunc::Statuss,
       message_type: LovenseDongleMessageType::Toy,
       id: None,
       command: None,
-      eager: None,
+      eager: self.hub.supports_eager_status().then_some(1),
     };
-    self
+    if !self
       .hub
       .send_output(OutgoingLovenseData::Message(autoconnect_msg))
-      .await;
+      .await
+    {
+      return self.hub.connection_lost().await;
+    }
     // This sleep is REQUIRED. If we send something too soon after this, the
     // dongle locks up. The query for already connected devices just returns
-    // nothing if there's no device currently connected, so all we can do is wait.
-    let mut id = None;
-    let fut = self.hub.wait_for_dongle_input();
-    select! {
-      incoming_msg = fut.fuse() => {
-        match incoming_msg {
-          IncomingMessage::Dongle(device_msg) =>
-            match device_msg.func {
-              LovenseDongleMessageFunc::IncomingStatus => {
-                if let Some(incoming_data) = device_msg.data {
-                  if Some(LovenseDongleResultCode::DeviceConnectSuccess) == incoming_data.status {
-                    info!("Lovense dongle already connected to a device, registering in system.");
-                    id = incoming_data.id;
+    // nothing if there's no device currently connected, so all we can do is wait. The dongle sends
+    // one status per connected toy, so collect everything that shows up in the window.
+    let mut ids: Vec<String> = vec![];
+    let deadline = Instant::now() + Duration::from_millis(250);
+    loop {
+      select! {
+        incoming_msg = self.hub.wait_for_dongle_input().fuse() => {
+          match incoming_msg {
+            IncomingMessage::Dongle(device_msg) =>
+              match device_msg.func {
+                LovenseDongleMessageFunc::IncomingStatus => {
+                  if let Some(incoming_data) = device_msg.data {
+                    if Some(LovenseDongleResultCode::DeviceConnectSuccess) == incoming_data.status {
+                      if let Some(id) = incoming_data.id.filter(|id| !ids.contains(id)) {
+                        info!("Lovense dongle already connected to toy {}, registering in system.", id);
+                        ids.push(id);
+                      }
+                    }
                   }
                 }
+                func => warn!("Cannot handle dongle function {:?}", func),
               }
-              func => warn!("Cannot handle dongle function {:?}", func),
-            }
+            // Let the next state deal with the dongle going away.
+            IncomingMessage::Disconnect => break,
             _ => warn!("Cannot handle incoming message {:?}", incoming_msg),
-        }
-      },
-      _ = sleep(std::time::Duration::from_millis(250)).fuse() => {
-        // noop, just fall thru.
+          }
+        },
+        _ = sleep_until(deadline).fuse() => break,
       }
     }
-    if let Some(id) = id {
+    if !ids.is_empty() {
       info!("Lovense dongle found already connected devices");
-      return Some(Box::new(LovenseDongleDeviceLoop::new(self.hub, id)));
+      return Some(Box::new(LovenseDongleDeviceLoop::new(self.hub, ids)));
     }
     if self.should_scan {
       info!("No devices connected to lovense dongle, scanning.");
@@ -336,11 +697,32 @@ impl LovenseDongleState for LovenseCheckForAlreadyConnectedDevice {
   }
 }
 
+state_definition!(LovenseDongleWatchdogReset);
+
+#[async_trait]
+impl LovenseDongleState for LovenseDongleWatchdogReset {
+  async fn transition(mut self: Box<Self>) -> Option<Box<dyn LovenseDongleState>> {
+    // Any toys we had go away with the connection, and get found again when we check for already
+    // connected toys after it comes back.
+    self
+      .hub
+      .send_error(format!(
+        "Lovense dongle has not responded in {:?}, resetting its connection.",
+        DONGLE_WATCHDOG_TIMEOUT
+      ))
+      .await;
+    self.hub.reset_connection().await
+  }
+}
+
 state_definition!(LovenseDongleIdle);
 #[async_trait]
 impl LovenseDongleState for LovenseDongleIdle {
   async fn transition(mut self: Box<Self>) -> Option<Box<dyn LovenseDongleState>> {
     info!("Running idle step");
+    if self.hub.should_query_firmware_version() && !self.hub.request_firmware_version().await {
+      return self.hub.connection_lost().await;
+    }
 
     loop {
       match self.hub.wait_for_input().await {
@@ -353,11 +735,17 @@ impl LovenseDongleState for LovenseDongleIdle {
                     info!("Lovense dongle already connected to a device, registering in system.");
                     return Some(Box::new(LovenseDongleDeviceLoop::new(
                       self.hub,
-                      incoming_data
+                      vec![incoming_data
                         .id
-                        .expect("Dongle protocol shouldn't change, message always has ID."),
+                        .expect("Dongle protocol shouldn't change, message always has ID.")],
                     )));
                   }
+                  status if status.is_error() => {
+                    self
+                      .hub
+                      .send_error(format!("Lovense dongle toy status error: {:?}", status))
+                      .await
+                  }
                   _ => warn!(
                     "LovenseDongleIdle State cannot handle dongle status {:?}",
                     status
@@ -370,6 +758,12 @@ impl LovenseDongleState for LovenseDongleIdle {
             if let Some(result) = device_msg.result {
               match result {
                 LovenseDongleResultCode::SearchStopped => debug!("Lovense dongle search stopped."),
+                result if result.is_error() => {
+                  self
+                    .hub
+                    .send_error(format!("Lovense dongle search failed: {:?}", result))
+                    .await
+                }
                 _ => warn!(
                   "LovenseDongleIdle State cannot handle search result {:?}",
                   result
@@ -383,6 +777,12 @@ impl LovenseDongleState for LovenseDongleIdle {
                 LovenseDongleResultCode::CommandSuccess => {
                   debug!("Lovense dongle search stop command successful.")
                 }
+                result if result.is_error() => {
+                  self
+                    .hub
+                    .send_error(format!("Lovense dongle search stop failed: {:?}", result))
+                    .await
+                }
                 _ => warn!(
                   "LovenseDongleIdle State cannot handle stop search result {:?}",
                   result
@@ -390,6 +790,8 @@ impl LovenseDongleState for LovenseDongleIdle {
               }
             }
           }
+          LovenseDongleMessageFunc::Version => self.hub.record_firmware_version(&device_msg).await,
+          LovenseDongleMessageFunc::Error => self.hub.send_error(dongle_error(&device_msg)).await,
           _ => error!(
             "LovenseDongleIdle State cannot handle dongle function {:?}",
             device_msg
@@ -402,6 +804,7 @@ impl LovenseDongleState for LovenseDongleIdle {
           LovenseDeviceCommand::StopScanning => {
             return Some(Box::new(LovenseDongleStopScanning::new(self.hub)));
           }
+          LovenseDeviceCommand::Shutdown => return self.hub.shutdown(vec![]).await,
           _ => {
             warn!(
               "Unhandled comm manager message to lovense dongle: {:?}",
@@ -409,6 +812,9 @@ impl LovenseDongleState for LovenseDongleIdle {
             );
           }
         },
+        IncomingMessage::Unresponsive => {
+          return Some(Box::new(LovenseDongleWatchdogReset::new(self.hub)));
+        }
         IncomingMessage::Disconnect => {
           info!("Channel disconnect of some kind, returning to 'wait for dongle' state.");
           return self.hub.create_new_wait_for_dongle_state();
@@ -428,6 +834,23 @@ impl LovenseDongleState for LovenseDongleStartScanning {
   async fn transition(mut self: Box<Self>) -> Option<Box<dyn LovenseDongleState>> {
     debug!("starting scan for devices");
 
+    match self.hub.settle_scan_request(true).await {
+      Some(true) => {}
+      Some(false) => {
+        debug!("Scan was stopped before it reached the dongle, staying idle.");
+        self.hub.set_scanning_status(false);
+        self
+          .hub
+          .send_event(HardwareCommunicationManagerEvent::ScanningFinished)
+          .await;
+        return Some(Box::new(LovenseDongleIdle::new(self.hub)));
+      }
+      None => {
+        info!("Channel disconnect of some kind, returning to 'wait for dongle' state.");
+        return self.hub.create_new_wait_for_dongle_state();
+      }
+    }
+
     let scan_msg = LovenseDongleOutgoingMessage {
       message_type: LovenseDongleMessageType::Toy,
       func: LovenseDongleMessageFunc::Search,
@@ -436,10 +859,14 @@ impl LovenseDongleState for LovenseDongleStartScanning {
       command: None,
     };
     self.hub.set_scanning_status(true);
-    self
+    if !self
       .hub
       .send_output(OutgoingLovenseData::Message(scan_msg))
-      .await;
+      .await
+    {
+      return self.hub.connection_lost().await;
+    }
+    self.hub.mark_scan_toggle();
     Some(Box::new(LovenseDongleScanning::new(self.hub)))
   }
 }
@@ -457,6 +884,8 @@ impl LovenseDongleState for LovenseDongleScanning {
           LovenseDeviceCommand::StopScanning => {
             return Some(Box::new(LovenseDongleStopScanning::new(self.hub)));
           }
+          LovenseDeviceCommand::StartScanning => debug!("Lovense dongle already scanning."),
+          LovenseDeviceCommand::Shutdown => return self.hub.shutdown(vec![]).await,
           msg => error!("Not handling comm input: {:?}", msg),
         },
         IncomingMessage::Dongle(device_msg) => {
@@ -469,11 +898,24 @@ impl LovenseDongleState for LovenseDongleScanning {
                       info!("Lovense dongle already connected to a device, registering in system.");
                       return Some(Box::new(LovenseDongleDeviceLoop::new(
                         self.hub,
-                        incoming_data
+                        vec![incoming_data
                           .id
-                          .expect("Dongle protocol shouldn't change, message always has ID."),
+                          .expect("Dongle protocol shouldn't change, message always has ID.")],
                       )));
                     }
+                    LovenseDongleResultCode::DeviceConnectInProgress => {
+                      debug!("Lovense dongle connecting to device.")
+                    }
+                    LovenseDongleResultCode::DeviceConnectionFailed
+                    | LovenseDongleResultCode::DeviceNotFound => {
+                      self
+                        .hub
+                        .send_error(format!(
+                          "Lovense dongle could not connect to toy ({:?}), still scanning.",
+                          status
+                        ))
+                        .await;
+                    }
                     _ => {
                       warn!(
                         "LovenseDongleScanning state cannot handle dongle status {:?}",
@@ -496,8 +938,20 @@ impl LovenseDongleState for LovenseDongleScanning {
                     );
                     return Some(Box::new(LovenseDongleStartScanning::new(self.hub)));
                   }
+                  LovenseDongleResultCode::DongleScanningInterruption => {
+                    warn!("Lovense dongle search was interrupted, restarting.");
+                    return Some(Box::new(LovenseDongleStartScanning::new(self.hub)));
+                  }
+                  result if result.is_error() => {
+                    self
+                      .hub
+                      .send_error(format!("Lovense dongle search failed: {:?}", result))
+                      .await;
+                    self.hub.abandon_scanning().await;
+                    return Some(Box::new(LovenseDongleIdle::new(self.hub)));
+                  }
                   _ => warn!(
-                    "LovenseDongleIdle State cannot handle search result {:?}",
+                    "LovenseDongleScanning State cannot handle search result {:?}",
                     result
                   ),
                 }
@@ -516,12 +970,18 @@ impl LovenseDongleState for LovenseDongleScanning {
                 return Some(Box::new(LovenseDongleIdle::new(self.hub)));
               }
             }
+            LovenseDongleMessageFunc::Error => {
+              self.hub.send_error(dongle_error(&device_msg)).await;
+            }
             _ => warn!(
               "LovenseDongleScanning state cannot handle dongle function {:?}",
               device_msg
             ),
           }
         }
+        IncomingMessage::Unresponsive => {
+          return Some(Box::new(LovenseDongleWatchdogReset::new(self.hub)));
+        }
         IncomingMessage::Disconnect => {
           info!("Channel disconnect of some kind, returning to 'wait for dongle' state.");
           self.hub.set_scanning_status(false);
@@ -542,6 +1002,21 @@ state_definition!(LovenseDongleStopScanning);
 impl LovenseDongleState for LovenseDongleStopScanning {
   async fn transition(mut self: Box<Self>) -> Option<Box<dyn LovenseDongleState>> {
     info!("stopping search");
+    match self.hub.settle_scan_request(false).await {
+      Some(false) => {}
+      Some(true) if self.hub.is_scanning() => {
+        debug!("Scan stop was superseded by a new scan request, still scanning.");
+        return Some(Box::new(LovenseDongleScanning::new(self.hub)));
+      }
+      Some(true) => {
+        debug!("Scan stop was superseded by a new scan request, starting scan.");
+        return Some(Box::new(LovenseDongleStartScanning::new(self.hub)));
+      }
+      None => {
+        info!("Channel disconnect of some kind, returning to 'wait for dongle' state.");
+        return self.hub.create_new_wait_for_dongle_state();
+      }
+    }
     let scan_msg = LovenseDongleOutgoingMessage {
       message_type: LovenseDongleMessageType::Usb,
       func: LovenseDongleMessageFunc::StopSearch,
@@ -549,10 +1024,14 @@ impl LovenseDongleState for LovenseDongleStopScanning {
       id: None,
       command: None,
     };
-    self
+    if !self
       .hub
       .send_output(OutgoingLovenseData::Message(scan_msg))
-      .await;
+      .await
+    {
+      return self.hub.connection_lost().await;
+    }
+    self.hub.mark_scan_toggle();
     self.hub.set_scanning_status(false);
     self
       .hub
@@ -568,6 +1047,12 @@ device_state_definition!(LovenseDongleStopScanningAndConnect);
 impl LovenseDongleState for LovenseDongleStopScanningAndConnect {
   async fn transition(mut self: Box<Self>) -> Option<Box<dyn LovenseDongleState>> {
     info!("stopping search and connecting to device");
+    // We're stopping either way, and a new scan request will be answered by the device loop, so
+    // only the spacing matters here.
+    if self.hub.settle_scan_request(false).await.is_none() {
+      info!("Channel disconnect of some kind, returning to 'wait for dongle' state.");
+      return self.hub.create_new_wait_for_dongle_state();
+    }
     let scan_msg = LovenseDongleOutgoingMessage {
       message_type: LovenseDongleMessageType::Usb,
       func: LovenseDongleMessageFunc::StopSearch,
@@ -575,10 +1060,14 @@ impl LovenseDongleState for LovenseDongleStopScanningAndConnect {
       id: None,
       command: None,
     };
-    self
+    if !self
       .hub
       .send_output(OutgoingLovenseData::Message(scan_msg))
-      .await;
+      .await
+    {
+      return self.hub.connection_lost().await;
+    }
+    self.hub.mark_scan_toggle();
     loop {
       let msg = self.hub.wait_for_input().await;
       match msg {
@@ -591,23 +1080,54 @@ impl LovenseDongleState for LovenseDongleStopScanningAndConnect {
               }
             }
           }
-          LovenseDongleMessageFunc::StopSearch => {
-            if let Some(result) = device_msg.result {
-              if result == LovenseDongleResultCode::CommandSuccess {
-                // Just log and continue here.
-                debug!("Lovense dongle stop search command succeeded.");
-              }
+          LovenseDongleMessageFunc::StopSearch => match device_msg.result {
+            Some(LovenseDongleResultCode::CommandSuccess) => {
+              // Just log and continue here.
+              debug!("Lovense dongle stop search command succeeded.");
+            }
+            Some(result) if result.is_error() => {
+              // We won't be hearing about the search stopping, so don't wait on it.
+              self
+                .hub
+                .send_error(format!("Lovense dongle search stop failed: {:?}", result))
+                .await;
+              self.hub.set_scanning_status(false);
+              break;
             }
+            _ => {}
+          },
+          LovenseDongleMessageFunc::IncomingStatus => {
+            let status = device_msg.data.and_then(|data| data.status);
+            if let Some(status) = status.filter(|status| status.is_error()) {
+              self
+                .hub
+                .send_error(format!(
+                  "Lovense dongle could not connect to toy: {:?}",
+                  status
+                ))
+                .await;
+              self.hub.abandon_scanning().await;
+              return Some(Box::new(LovenseDongleIdle::new(self.hub)));
+            }
+            debug!("Lovense dongle toy status while connecting: {:?}", status);
           }
+          LovenseDongleMessageFunc::Error => self.hub.send_error(dongle_error(&device_msg)).await,
           _ => warn!(
             "LovenseDongleStopScanningAndConnect cannot handle dongle function {:?}",
             device_msg
           ),
         },
+        IncomingMessage::Unresponsive => {
+          return Some(Box::new(LovenseDongleWatchdogReset::new(self.hub)));
+        }
         IncomingMessage::Disconnect => {
           info!("Channel disconnect of some kind, returning to 'wait for dongle' state.");
           return self.hub.create_new_wait_for_dongle_state();
         }
+        // The toy may already be connecting, so stop it too.
+        IncomingMessage::CommMgr(LovenseDeviceCommand::Shutdown) => {
+          return self.hub.shutdown(vec![self.device_id.clone()]).await;
+        }
         _ => warn!("Cannot handle dongle function {:?}", msg),
       }
     }
@@ -617,31 +1137,169 @@ impl LovenseDongleState for LovenseDongleStopScanningAndConnect {
       .await;
     Some(Box::new(LovenseDongleDeviceLoop::new(
       self.hub,
-      self.device_id.clone(),
+      vec![self.device_id.clone()],
     )))
   }
 }
 
-device_state_definition!(LovenseDongleDeviceLoop);
+#[derive(Debug)]
+struct LovenseDongleDeviceLoop {
+  hub: ChannelHub,
+  new_device_ids: Vec<String>,
+}
 
-#[async_trait]
-impl LovenseDongleState for LovenseDongleDeviceLoop {
-  async fn transition(mut self: Box<Self>) -> Option<Box<dyn LovenseDongleState>> {
-    info!("Running Lovense Dongle Device Event Loop");
-    let (device_write_sender, mut device_write_receiver) = channel(256);
+impl LovenseDongleDeviceLoop {
+  pub fn new(hub: ChannelHub, new_device_ids: Vec<String>) -> Self {
+    Self {
+      hub,
+      new_device_ids,
+    }
+  }
+
+  /// Set up the read channel for a toy the dongle has connected to, and let the device manager
+  /// know about it. All toys share a write channel, since the toy ID goes out with every command.
+  async fn add_toy(
+    &self,
+    id: &str,
+    device_write_sender: &Sender<OutgoingLovenseData>,
+    toys: &mut HashMap<String, Sender<LovenseDongleIncomingMessage>>,
+  ) {
     let (device_read_sender, device_read_receiver) = channel(256);
+    toys.insert(id.to_owned(), device_read_sender);
     self
       .hub
       .send_event(HardwareCommunicationManagerEvent::DeviceFound {
         name: "Lovense Dongle Device".to_owned(),
-        address: self.device_id.clone(),
+        address: id.to_owned(),
         creator: Box::new(LovenseDongleHardwareConnector::new(
-          &self.device_id,
-          device_write_sender,
+          id,
+          device_write_sender.clone(),
           device_read_receiver,
         )),
       })
       .await;
+  }
+
+  /// Start or stop searching for more toys alongside the connected ones. Callers should settle the
+  /// request with the hub first, so the dongle isn't toggled too quickly. Returns false if the
+  /// dongle connection is gone.
+  #[must_use]
+  async fn set_search(&mut self, should_scan: bool) -> bool {
+    if should_scan == self.hub.is_scanning() {
+      if should_scan {
+        debug!("Lovense dongle already scanning.");
+      } else {
+        // Nothing to stop, but clients still expect to hear that scanning is over.
+        self
+          .hub
+          .send_event(HardwareCommunicationManagerEvent::ScanningFinished)
+          .await;
+      }
+      return true;
+    }
+    let scan_msg = if should_scan {
+      LovenseDongleOutgoingMessage {
+        message_type: LovenseDongleMessageType::Toy,
+        func: LovenseDongleMessageFunc::Search,
+        eager: None,
+        id: None,
+        command: None,
+      }
+    } else {
+      LovenseDongleOutgoingMessage {
+        message_type: LovenseDongleMessageType::Usb,
+        func: LovenseDongleMessageFunc::StopSearch,
+        eager: None,
+        id: None,
+        command: None,
+      }
+    };
+    if !self
+      .hub
+      .send_output(OutgoingLovenseData::Message(scan_msg))
+      .await
+    {
+      return false;
+    }
+    self.hub.mark_scan_toggle();
+    self.hub.set_scanning_status(should_scan);
+    if !should_scan {
+      self
+        .hub
+        .send_event(HardwareCommunicationManagerEvent::ScanningFinished)
+        .await;
+    }
+    true
+  }
+
+  /// Where to go once the last toy is gone. If we were searching for more toys, keep at it.
+  fn without_toys(self) -> Option<Box<dyn LovenseDongleState>> {
+    if self.hub.is_scanning() {
+      Some(Box::new(LovenseDongleScanning::new(self.hub)))
+    } else {
+      Some(Box::new(LovenseDongleIdle::new(self.hub)))
+    }
+  }
+}
+
+/// Figure out which toy a dongle message is about. Toy data carries the ID in its data block, while
+/// some firmware puts it on the message itself. If neither is there and we only have one toy, it's
+/// safe to assume the message is for that one.
+fn target_toy(
+  msg: &LovenseDongleIncomingMessage,
+  toys: &HashMap<String, Sender<LovenseDongleIncomingMessage>>,
+) -> Option<String> {
+  msg
+    .data
+    .as_ref()
+    .and_then(|data| data.id.clone())
+    .or_else(|| msg.id.clone())
+    .or_else(|| {
+      if toys.len() == 1 {
+        toys.keys().next().cloned()
+      } else {
+        None
+      }
+    })
+}
+
+/// Pass a dongle message on to the toy it's for. Returns false if that was the last toy and its
+/// owner has gone away.
+async fn forward_to_toy(
+  msg: LovenseDongleIncomingMessage,
+  target: Option<String>,
+  toys: &mut HashMap<String, Sender<LovenseDongleIncomingMessage>>,
+) -> bool {
+  match target.and_then(|id| toys.get(&id).map(|sender| (id, sender.clone()))) {
+    Some((id, sender)) => {
+      if sender.send(msg).await.is_err() {
+        warn!(
+          "Lovense dongle toy {} no longer has an owner, dropping it.",
+          id
+        );
+        toys.remove(&id);
+        return !toys.is_empty();
+      }
+    }
+    None => warn!(
+      "Lovense dongle message for unknown toy, ignoring: {:?}",
+      msg
+    ),
+  }
+  true
+}
+
+#[async_trait]
+impl LovenseDongleState for LovenseDongleDeviceLoop {
+  async fn transition(mut self: Box<Self>) -> Option<Box<dyn LovenseDongleState>> {
+    info!("Running Lovense Dongle Device Event Loop");
+    // We hold on to the write sender so we can hand it to toys that show up later, which means the
+    // write receiver never closes on its own.
+    let (device_write_sender, mut device_write_receiver) = channel(256);
+    let mut toys = HashMap::new();
+    for id in std::mem::take(&mut self.new_device_ids) {
+      self.add_toy(&id, &device_write_sender, &mut toys).await;
+    }
     loop {
       let msg = self
         .hub
@@ -649,45 +1307,171 @@ impl LovenseDongleState for LovenseDongleDeviceLoop {
         .await;
       match msg {
         IncomingMessage::Device(device_msg) => {
-          self.hub.send_output(device_msg).await;
+          if !self.hub.send_output(device_msg).await {
+            return self.hub.connection_lost().await;
+          }
         }
         IncomingMessage::Dongle(dongle_msg) => {
+          let target = target_toy(&dongle_msg, &toys);
           match dongle_msg.func {
             LovenseDongleMessageFunc::IncomingStatus => {
-              if let Some(data) = dongle_msg.data {
-                if data.status == Some(LovenseDongleResultCode::DeviceDisconnected) {
-                  // Device disconnected, emit and return to idle.
-                  return Some(Box::new(LovenseDongleIdle::new(self.hub)));
+              match dongle_msg.data.as_ref().and_then(|data| data.status) {
+                Some(LovenseDongleResultCode::DeviceConnectSuccess) => match target {
+                  Some(id) if !toys.contains_key(&id) => {
+                    info!(
+                      "Lovense dongle connected to toy {}, registering in system.",
+                      id
+                    );
+                    self.add_toy(&id, &device_write_sender, &mut toys).await;
+                  }
+                  id => debug!("Lovense dongle toy {:?} already connected.", id),
+                },
+                Some(
+                  status @ (LovenseDongleResultCode::DeviceDisconnected
+                  | LovenseDongleResultCode::DeviceConnectionFailed
+                  | LovenseDongleResultCode::DeviceNotFound),
+                ) => {
+                  if status != LovenseDongleResultCode::DeviceDisconnected {
+                    self
+                      .hub
+                      .send_error(format!("Lovense dongle lost toy connection: {:?}", status))
+                      .await;
+                  }
+                  // Dropping our end of the read channel lets the device know it's gone. If we
+                  // can't tell which toy went away, we can't trust any of them to still be there.
+                  match target {
+                    Some(id) => {
+                      info!("Lovense dongle toy {} disconnected.", id);
+                      toys.remove(&id);
+                    }
+                    None => {
+                      warn!(
+                        "Lovense dongle reported a disconnect without a toy ID, dropping all toys."
+                      );
+                      toys.clear();
+                    }
+                  }
+                  if toys.is_empty() {
+                    return self.without_toys();
+                  }
+                }
+                // Status updates can carry the toy's signal strength, which its device wants.
+                status
+                  if dongle_msg
+                    .data
+                    .as_ref()
+                    .is_some_and(|data| data.rssi.is_some()) =>
+                {
+                  debug!("Lovense dongle toy status: {:?}", status);
+                  if !forward_to_toy(dongle_msg, target, &mut toys).await {
+                    return self.without_toys();
+                  }
+                }
+                status => debug!("Lovense dongle toy status: {:?}", status),
+              }
+            }
+            LovenseDongleMessageFunc::Search => match dongle_msg.result {
+              Some(LovenseDongleResultCode::SearchStarted) => {
+                debug!("Lovense dongle search started.")
+              }
+              Some(
+                LovenseDongleResultCode::SearchStopped
+                | LovenseDongleResultCode::DongleScanningInterruption,
+              ) if self.hub.is_scanning() => {
+                debug!("Lovense dongle stopped scanning before stop was requested, restarting.");
+                match self.hub.settle_scan_request(true).await {
+                  Some(should_scan) => {
+                    self.hub.set_scanning_status(false);
+                    if !self.set_search(should_scan).await {
+                      return self.hub.connection_lost().await;
+                    }
+                  }
+                  None => {
+                    info!("Channel disconnect of some kind, returning to 'wait for dongle' state.");
+                    return self.hub.create_new_wait_for_dongle_state();
+                  }
+                }
+              }
+              Some(result) if result.is_error() => {
+                self
+                  .hub
+                  .send_error(format!("Lovense dongle search failed: {:?}", result))
+                  .await;
+                if self.hub.is_scanning() {
+                  self.hub.abandon_scanning().await;
                 }
               }
+              result => debug!("Lovense dongle search result: {:?}", result),
+            },
+            LovenseDongleMessageFunc::StopSearch => match dongle_msg.result {
+              Some(result) if result.is_error() => {
+                self
+                  .hub
+                  .send_error(format!("Lovense dongle search stop failed: {:?}", result))
+                  .await
+              }
+              result => debug!("Lovense dongle search stop result: {:?}", result),
+            },
+            LovenseDongleMessageFunc::ToyData
+              if self.hub.is_scanning()
+                && target.as_ref().is_some_and(|id| !toys.contains_key(id)) =>
+            {
+              // Same as finding a toy while scanning without any connected: stop the search so the
+              // dongle connects to it.
+              let id = target.expect("Checked above");
+              info!(
+                "Lovense dongle found toy {} while scanning, connecting.",
+                id
+              );
+              if self.hub.settle_scan_request(false).await.is_none() {
+                info!("Channel disconnect of some kind, returning to 'wait for dongle' state.");
+                return self.hub.create_new_wait_for_dongle_state();
+              }
+              if !self.set_search(false).await {
+                return self.hub.connection_lost().await;
+              }
+              self.add_toy(&id, &device_write_sender, &mut toys).await;
             }
+            LovenseDongleMessageFunc::Error => self.hub.send_error(dongle_error(&dongle_msg)).await,
             _ => {
-              if device_read_sender.send(dongle_msg).await.is_err() {
-                warn!("Dongle message sent without owner being alive, assuming shutdown.");
+              if !forward_to_toy(dongle_msg, target, &mut toys).await {
+                return self.without_toys();
               }
             }
           }
         }
-        IncomingMessage::CommMgr(comm_msg) => match comm_msg {
-          LovenseDeviceCommand::StartScanning => {
-            self.hub.set_scanning_status(false);
-            self
-              .hub
-              .send_event(HardwareCommunicationManagerEvent::ScanningFinished)
-              .await;
-          }
-          LovenseDeviceCommand::StopScanning => {
-            self.hub.set_scanning_status(false);
-            self
-              .hub
-              .send_event(HardwareCommunicationManagerEvent::ScanningFinished)
-              .await;
+        IncomingMessage::CommMgr(comm_msg) => {
+          let should_scan = match comm_msg {
+            LovenseDeviceCommand::StartScanning => true,
+            LovenseDeviceCommand::StopScanning => false,
+            LovenseDeviceCommand::Shutdown => {
+              return self.hub.shutdown(toys.into_keys().collect()).await;
+            }
+            _ => {
+              warn!(
+                "Cannot handle communication manager function {:?}",
+                comm_msg
+              );
+              continue;
+            }
+          };
+          // Toy commands wait while we settle, but the dongle stops answering if we toggle scanning
+          // too quickly, which would be worse.
+          match self.hub.settle_scan_request(should_scan).await {
+            Some(should_scan) => {
+              if !self.set_search(should_scan).await {
+                return self.hub.connection_lost().await;
+              }
+            }
+            None => {
+              info!("Channel disconnect of some kind, returning to 'wait for dongle' state.");
+              return self.hub.create_new_wait_for_dongle_state();
+            }
           }
-          _ => warn!(
-            "Cannot handle communication manager function {:?}",
-            comm_msg
-          ),
-        },
+        }
+        IncomingMessage::Unresponsive => {
+          return Some(Box::new(LovenseDongleWatchdogReset::new(self.hub)));
+        }
         IncomingMessage::Disconnect => {
           info!("Channel disconnect of some kind, returning to 'wait for dongle' state.");
           return self.hub.create_new_wait_for_dongle_state();
@@ -696,3 +1480,366 @@ impl LovenseDongleState for LovenseDongleDeviceLoop {
     }
   }
 }
+
+#[cfg(test)]
+mod test {
+  use super::{super::lovense_dongle_test_harness::LovenseDongleTestHarness, *};
+  use crate::{
+    core::message::Endpoint,
+    server::device::hardware::{HardwareEvent, HardwareWriteCmd},
+  };
+  use tokio::time::timeout;
+
+  // Answers the version query with firmware too old for eager status, like most dongles out
+  // there, so idle doesn't ask again.
+  async fn start() -> LovenseDongleTestHarness {
+    LovenseDongleTestHarness::start("test dongle", Some("1.0.0")).await
+  }
+
+  #[tokio::test]
+  async fn test_scan_toggles_are_spaced_and_collapsed() {
+    let mut dongle = start().await;
+    // Let the machine get to idle after the already connected check times out.
+    sleep(Duration::from_millis(300)).await;
+
+    dongle
+      .send_command(LovenseDeviceCommand::StartScanning)
+      .await;
+    assert_eq!(dongle.next_func().await, LovenseDongleMessageFunc::Search);
+    let search_sent = Instant::now();
+
+    // A burst of toggles ends up as a single stop, sent no sooner than the dongle can take it.
+    dongle
+      .send_command(LovenseDeviceCommand::StopScanning)
+      .await;
+    dongle
+      .send_command(LovenseDeviceCommand::StartScanning)
+      .await;
+    dongle
+      .send_command(LovenseDeviceCommand::StopScanning)
+      .await;
+    assert_eq!(
+      dongle.next_func().await,
+      LovenseDongleMessageFunc::StopSearch
+    );
+    assert!(search_sent.elapsed() >= SCAN_TOGGLE_SPACING - Duration::from_millis(10));
+    dongle.expect_scanning_finished().await;
+    dongle.expect_quiet(SCAN_TOGGLE_SPACING * 2).await;
+
+    // A stop that supersedes a start within the spacing never reaches the dongle, but clients
+    // still hear that scanning finished.
+    dongle
+      .send_command(LovenseDeviceCommand::StartScanning)
+      .await;
+    dongle
+      .send_command(LovenseDeviceCommand::StopScanning)
+      .await;
+    dongle.expect_scanning_finished().await;
+    dongle.expect_quiet(SCAN_TOGGLE_SPACING * 2).await;
+  }
+
+  #[tokio::test]
+  async fn test_lost_connection_waits_for_dongle() {
+    let mut dongle = start().await;
+    // Dongle unplugged, so writes start failing.
+    dongle.close_dongle_output();
+    dongle
+      .send_command(LovenseDeviceCommand::StartScanning)
+      .await;
+    dongle.expect_error().await;
+
+    // Once the connection is back, the machine starts over and picks the scan back up.
+    dongle.attach_dongle().await;
+    assert_eq!(dongle.next_func().await, LovenseDongleMessageFunc::Version);
+    assert_eq!(dongle.next_func().await, LovenseDongleMessageFunc::Statuss);
+    assert_eq!(dongle.next_func().await, LovenseDongleMessageFunc::Search);
+  }
+
+  #[tokio::test]
+  async fn test_watchdog_resets_unresponsive_dongle() {
+    let mut dongle = start().await;
+    sleep(Duration::from_millis(300)).await;
+
+    // The dongle takes the search and then goes quiet.
+    dongle
+      .send_command(LovenseDeviceCommand::StartScanning)
+      .await;
+    assert_eq!(dongle.next_func().await, LovenseDongleMessageFunc::Search);
+    timeout(DONGLE_WATCHDOG_TIMEOUT * 2, dongle.expect_error())
+      .await
+      .expect("Watchdog should fire");
+    assert!(matches!(
+      dongle.next_output().await,
+      Some(OutgoingLovenseData::Reset)
+    ));
+
+    // Once the connection comes back, we pick up scanning where we left off.
+    dongle.attach_dongle().await;
+    assert_eq!(dongle.next_func().await, LovenseDongleMessageFunc::Version);
+    assert_eq!(dongle.next_func().await, LovenseDongleMessageFunc::Statuss);
+    assert_eq!(dongle.next_func().await, LovenseDongleMessageFunc::Search);
+  }
+
+  #[tokio::test]
+  async fn test_dongle_errors_end_scanning() {
+    let mut dongle = start().await;
+    sleep(Duration::from_millis(300)).await;
+
+    // Search failing with a code we don't know about still gets reported.
+    dongle
+      .send_command(LovenseDeviceCommand::StartScanning)
+      .await;
+    assert_eq!(dongle.next_func().await, LovenseDongleMessageFunc::Search);
+    dongle
+      .reply_json(r#"{"type":"toy","func":"search","result":599}"#)
+      .await;
+    dongle.expect_error().await;
+    dongle.expect_scanning_finished().await;
+
+    // As does a toy that's found but won't connect.
+    dongle
+      .send_command(LovenseDeviceCommand::StartScanning)
+      .await;
+    assert_eq!(dongle.next_func().await, LovenseDongleMessageFunc::Search);
+    dongle
+      .reply_json(r#"{"type":"toy","func":"toyData","data":{"id":"ABCDEF"}}"#)
+      .await;
+    assert_eq!(
+      dongle.next_func().await,
+      LovenseDongleMessageFunc::StopSearch
+    );
+    dongle
+      .reply_json(r#"{"type":"toy","func":"status","data":{"id":"ABCDEF","status":402}}"#)
+      .await;
+    dongle.expect_error().await;
+    dongle.expect_scanning_finished().await;
+  }
+
+  #[tokio::test]
+  async fn test_scanning_connects_found_toy() {
+    let mut dongle = start().await;
+    sleep(Duration::from_millis(300)).await;
+
+    dongle
+      .send_command(LovenseDeviceCommand::StartScanning)
+      .await;
+    assert_eq!(dongle.next_func().await, LovenseDongleMessageFunc::Search);
+    dongle
+      .reply_json(r#"{"type":"toy","func":"search","result":205}"#)
+      .await;
+
+    // Finding a toy stops the search, and once the dongle confirms it has stopped the toy is
+    // brought into the system.
+    dongle
+      .reply_json(r#"{"type":"toy","func":"toyData","data":{"id":"ABCDEF"}}"#)
+      .await;
+    assert_eq!(
+      dongle.next_func().await,
+      LovenseDongleMessageFunc::StopSearch
+    );
+    dongle
+      .reply_json(r#"{"type":"usb","func":"stopSearch","result":200}"#)
+      .await;
+    dongle
+      .reply_json(r#"{"type":"toy","func":"search","result":206}"#)
+      .await;
+    dongle.expect_scanning_finished().await;
+    let toy = dongle.expect_toy().await;
+    assert_eq!(toy.address(), "ABCDEF");
+  }
+
+  #[tokio::test]
+  async fn test_device_loop_tracks_multiple_toys() {
+    let mut dongle = start().await;
+    dongle
+      .reply_json(r#"{"type":"toy","func":"status","data":{"id":"AAAAAA","status":202}}"#)
+      .await;
+    dongle
+      .reply_json(r#"{"type":"toy","func":"status","data":{"id":"BBBBBB","status":202}}"#)
+      .await;
+    let toy_a = dongle.expect_toy().await;
+    let toy_b = dongle.expect_toy().await;
+    assert_eq!(toy_a.address(), "AAAAAA");
+    assert_eq!(toy_b.address(), "BBBBBB");
+    let mut toy_a_events = toy_a.event_stream();
+    let mut toy_b_events = toy_b.event_stream();
+
+    // Writes go out tagged with the toy they came from.
+    toy_b
+      .write_value(&HardwareWriteCmd::new(
+        Endpoint::Tx,
+        b"Vibrate:5;".to_vec(),
+        false,
+      ))
+      .await
+      .unwrap();
+    match dongle.next_output().await {
+      Some(OutgoingLovenseData::Message(msg)) => assert_eq!(msg.id.as_deref(), Some("BBBBBB")),
+      msg => panic!("Unexpected dongle output {:?}", msg),
+    }
+
+    // Toy data only reaches the toy it belongs to.
+    dongle
+      .reply_json(r#"{"type":"toy","func":"toyData","data":{"id":"BBBBBB","data":"Battery:80;"}}"#)
+      .await;
+    assert!(matches!(
+      toy_b_events.recv().await,
+      Ok(HardwareEvent::Notification(address, Endpoint::Rx, _)) if address == "BBBBBB"
+    ));
+    assert!(toy_a_events.try_recv().is_err());
+
+    // Losing one toy leaves the other connected.
+    dongle
+      .reply_json(r#"{"type":"toy","func":"status","data":{"id":"AAAAAA","status":403}}"#)
+      .await;
+    assert!(matches!(
+      toy_a_events.recv().await,
+      Ok(HardwareEvent::Disconnected(address)) if address == "AAAAAA"
+    ));
+    dongle
+      .reply_json(r#"{"type":"toy","func":"toyData","data":{"id":"BBBBBB","data":"Battery:79;"}}"#)
+      .await;
+    assert!(matches!(
+      toy_b_events.recv().await,
+      Ok(HardwareEvent::Notification(address, Endpoint::Rx, _)) if address == "BBBBBB"
+    ));
+
+    // A toy connecting while others are up gets announced too.
+    dongle
+      .reply_json(r#"{"type":"toy","func":"status","data":{"id":"CCCCCC","status":202}}"#)
+      .await;
+    let toy_c = dongle.expect_toy().await;
+    assert_eq!(toy_c.address(), "CCCCCC");
+  }
+
+  #[tokio::test]
+  async fn test_device_loop_reports_toy_rssi() {
+    let mut dongle = start().await;
+    dongle
+      .reply_json(r#"{"type":"toy","func":"status","data":{"id":"AAAAAA","status":202}}"#)
+      .await;
+    let toy = dongle.expect_toy().await;
+    let mut toy_events = toy.event_stream();
+
+    // Signal strength can come in on its own status update...
+    dongle
+      .reply_json(r#"{"type":"toy","func":"status","data":{"id":"AAAAAA","rssi":-70}}"#)
+      .await;
+    assert!(matches!(
+      toy_events.recv().await,
+      Ok(HardwareEvent::Rssi(address, -70)) if address == "AAAAAA"
+    ));
+
+    // ...or alongside toy data, which still gets through.
+    dongle
+      .reply_json(
+        r#"{"type":"toy","func":"toyData","data":{"id":"AAAAAA","data":"Battery:80;","rssi":-85}}"#,
+      )
+      .await;
+    assert!(matches!(
+      toy_events.recv().await,
+      Ok(HardwareEvent::Rssi(address, -85)) if address == "AAAAAA"
+    ));
+    assert!(matches!(
+      toy_events.recv().await,
+      Ok(HardwareEvent::Notification(address, Endpoint::Rx, _)) if address == "AAAAAA"
+    ));
+  }
+
+  #[tokio::test]
+  async fn test_shutdown_stops_toys_and_closes_connection() {
+    let mut dongle = start().await;
+    dongle
+      .reply_json(r#"{"type":"toy","func":"status","data":{"id":"AAAAAA","status":202}}"#)
+      .await;
+    let _toy = dongle.expect_toy().await;
+
+    dongle.send_command(LovenseDeviceCommand::Shutdown).await;
+    for command in TOY_STOP_COMMANDS {
+      let msg = dongle.next_message().await;
+      assert_eq!(msg.func, LovenseDongleMessageFunc::Command);
+      assert_eq!(msg.id.as_deref(), Some("AAAAAA"));
+      assert_eq!(msg.command.as_deref(), Some(command));
+    }
+    assert!(matches!(
+      dongle.next_output().await,
+      Some(OutgoingLovenseData::Close)
+    ));
+    // The machine has exited, so nothing is left listening for commands.
+    assert!(dongle.next_output().await.is_none());
+  }
+
+  #[tokio::test]
+  async fn test_scanning_while_toy_connected() {
+    let mut dongle = start().await;
+    dongle
+      .reply_json(r#"{"type":"toy","func":"status","data":{"id":"AAAAAA","status":202}}"#)
+      .await;
+    let toy_a = dongle.expect_toy().await;
+    let mut toy_a_events = toy_a.event_stream();
+
+    // Scanning doesn't cost us the toy we already have.
+    dongle
+      .send_command(LovenseDeviceCommand::StartScanning)
+      .await;
+    assert_eq!(dongle.next_func().await, LovenseDongleMessageFunc::Search);
+    dongle
+      .reply_json(r#"{"type":"toy","func":"search","result":205}"#)
+      .await;
+    dongle
+      .reply_json(r#"{"type":"toy","func":"toyData","data":{"id":"AAAAAA","data":"Battery:80;"}}"#)
+      .await;
+    assert!(matches!(
+      toy_a_events.recv().await,
+      Ok(HardwareEvent::Notification(address, Endpoint::Rx, _)) if address == "AAAAAA"
+    ));
+
+    // Finding a new toy stops the search and brings it in next to the first one.
+    dongle
+      .reply_json(r#"{"type":"toy","func":"toyData","data":{"id":"BBBBBB"}}"#)
+      .await;
+    assert_eq!(
+      dongle.next_func().await,
+      LovenseDongleMessageFunc::StopSearch
+    );
+    dongle.expect_scanning_finished().await;
+    let toy_b = dongle.expect_toy().await;
+    assert_eq!(toy_b.address(), "BBBBBB");
+    dongle
+      .reply_json(r#"{"type":"toy","func":"search","result":206}"#)
+      .await;
+    dongle.expect_quiet(SCAN_TOGGLE_SPACING * 2).await;
+
+    // Stopping a scan that isn't running just reports it finished.
+    dongle
+      .send_command(LovenseDeviceCommand::StopScanning)
+      .await;
+    dongle.expect_scanning_finished().await;
+    dongle.expect_quiet(SCAN_TOGGLE_SPACING * 2).await;
+  }
+
+  #[tokio::test]
+  async fn test_eager_status_follows_firmware_version() {
+    async fn status_eager(version: Option<&str>) -> Option<u32> {
+      let mut dongle = LovenseDongleTestHarness::new("test dongle");
+      dongle.attach_dongle().await;
+      dongle.handshake(version).await.eager
+    }
+    assert_eq!(status_eager(None).await, None);
+    assert_eq!(status_eager(Some("1.2.9")).await, None);
+    assert_eq!(status_eager(Some("1.4")).await, Some(1));
+    assert_eq!(status_eager(Some("2.0.1")).await, Some(1));
+  }
+
+  #[tokio::test]
+  async fn test_idle_asks_for_missing_firmware_version() {
+    let mut dongle = LovenseDongleTestHarness::start("test dongle", None).await;
+    // Once the already connected check is done, idle asks again.
+    assert_eq!(dongle.next_func().await, LovenseDongleMessageFunc::Version);
+    dongle
+      .reply_json(r#"{"type":"usb","func":"version","data":{"data":"1.5.2"}}"#)
+      .await;
+    assert_eq!(dongle.expect_firmware_version().await, "1.5.2");
+    dongle.expect_quiet(SCAN_TOGGLE_SPACING * 2).await;
+  }
+}
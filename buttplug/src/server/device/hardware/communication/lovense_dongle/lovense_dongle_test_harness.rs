@@ -0,0 +1,217 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2024 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Scripted stand-in for a Lovense dongle, for testing the dongle state machine without hardware.
+//!
+//! The harness runs a real state machine, but hands it channels the test holds the other ends of.
+//! Tests play the dongle by replying with [LovenseDongleIncomingMessage]s and checking what the
+//! machine writes back, and play the communication manager by sending [LovenseDeviceCommand]s and
+//! checking the [HardwareCommunicationManagerEvent]s that come out. Helpers panic when the machine
+//! does something other than what's expected, so they can be used as assertions.
+
+use super::{
+  lovense_dongle_messages::*,
+  lovense_dongle_state_machine::create_lovense_dongle_machine,
+};
+use crate::{
+  server::device::hardware::{communication::HardwareCommunicationManagerEvent, Hardware},
+  util::async_manager,
+};
+use std::{
+  sync::{atomic::AtomicBool, Arc},
+  time::Duration,
+};
+use tokio::{
+  sync::mpsc::{channel, Receiver, Sender},
+  time::timeout,
+};
+
+/// A Lovense dongle state machine wired up to channels a test can drive.
+pub struct LovenseDongleTestHarness {
+  name: String,
+  command_sender: Sender<LovenseDeviceCommand>,
+  event_receiver: Receiver<HardwareCommunicationManagerEvent>,
+  dongle_output: Receiver<OutgoingLovenseData>,
+  dongle_input: Sender<LovenseDongleIncomingMessage>,
+}
+
+impl LovenseDongleTestHarness {
+  /// Start a state machine for a dongle called `name`. It waits for a dongle until
+  /// [attach_dongle](Self::attach_dongle) is called.
+  pub fn new(name: &str) -> Self {
+    let (event_sender, event_receiver) = channel(256);
+    let (command_sender, command_receiver) = channel(256);
+    let mut machine = create_lovense_dongle_machine(
+      name,
+      event_sender,
+      command_receiver,
+      Arc::new(AtomicBool::new(false)),
+    );
+    async_manager::spawn(async move {
+      while let Some(next) = machine.transition().await {
+        machine = next;
+      }
+    });
+    // Nothing is attached yet, so start out with channels that go nowhere.
+    let (dongle_input, _) = channel(1);
+    let (_, dongle_output) = channel(1);
+    Self {
+      name: name.to_owned(),
+      command_sender,
+      event_receiver,
+      dongle_output,
+      dongle_input,
+    }
+  }
+
+  /// Start a state machine and take its dongle through setup, answering the firmware version query
+  /// with `firmware_version` (or leaving it unanswered if None). The machine is left checking for
+  /// already connected toys, with nothing answering.
+  pub async fn start(name: &str, firmware_version: Option<&str>) -> Self {
+    let mut harness = Self::new(name);
+    harness.attach_dongle().await;
+    harness.handshake(firmware_version).await;
+    harness
+  }
+
+  /// Hand the machine a fresh dongle connection, as if one was plugged in (or reopened).
+  pub async fn attach_dongle(&mut self) {
+    let (output_sender, dongle_output) = channel(256);
+    let (dongle_input, input_receiver) = channel(256);
+    self.dongle_output = dongle_output;
+    self.dongle_input = dongle_input;
+    self
+      .send_command(LovenseDeviceCommand::DongleFound(
+        output_sender,
+        input_receiver,
+      ))
+      .await;
+  }
+
+  /// Play the dongle's side of setup: the firmware version query, answered with `firmware_version`
+  /// if there is one, then the check for already connected toys. Returns the status query, which
+  /// is left unanswered.
+  pub async fn handshake(
+    &mut self,
+    firmware_version: Option<&str>,
+  ) -> LovenseDongleOutgoingMessage {
+    assert_eq!(self.next_func().await, LovenseDongleMessageFunc::Version);
+    if let Some(version) = firmware_version {
+      self
+        .reply_json(&format!(
+          r#"{{"type":"usb","func":"version","data":{{"data":"{}"}}}}"#,
+          version
+        ))
+        .await;
+      self.expect_firmware_version().await;
+    }
+    let status_msg = self.next_message().await;
+    assert_eq!(status_msg.func, LovenseDongleMessageFunc::Statuss);
+    status_msg
+  }
+
+  /// Stop reading what the machine writes, so its writes fail as if the dongle was unplugged.
+  pub fn close_dongle_output(&mut self) {
+    self.dongle_output.close();
+  }
+
+  /// Send a command to the machine, as the communication manager would.
+  pub async fn send_command(&self, command: LovenseDeviceCommand) {
+    self
+      .command_sender
+      .send(command)
+      .await
+      .expect("State machine should be running.");
+  }
+
+  /// Send a message to the machine, as the dongle would.
+  pub async fn reply(&self, msg: LovenseDongleIncomingMessage) {
+    self
+      .dongle_input
+      .send(msg)
+      .await
+      .expect("State machine should be reading from the dongle.");
+  }
+
+  /// Send a message to the machine as the dongle would, in the JSON the dongle sends.
+  pub async fn reply_json(&self, json: &str) {
+    self
+      .reply(serde_json::from_str(json).expect("Test messages should be valid dongle JSON."))
+      .await;
+  }
+
+  /// Next thing the machine wrote to the dongle, or None once the machine has let go of it.
+  pub async fn next_output(&mut self) -> Option<OutgoingLovenseData> {
+    self.dongle_output.recv().await
+  }
+
+  /// Next message the machine wrote to the dongle.
+  pub async fn next_message(&mut self) -> LovenseDongleOutgoingMessage {
+    match self.next_output().await {
+      Some(OutgoingLovenseData::Message(msg)) => msg,
+      output => panic!("Expected a dongle message, got {:?}", output),
+    }
+  }
+
+  /// Function of the next message the machine wrote to the dongle.
+  pub async fn next_func(&mut self) -> LovenseDongleMessageFunc {
+    self.next_message().await.func
+  }
+
+  /// Check that the machine doesn't write anything to the dongle for `wait`.
+  pub async fn expect_quiet(&mut self, wait: Duration) {
+    if let Ok(output) = timeout(wait, self.dongle_output.recv()).await {
+      panic!("Expected the dongle to be left alone, got {:?}", output);
+    }
+  }
+
+  /// Next event the machine sent to the communication manager.
+  pub async fn next_event(&mut self) -> Option<HardwareCommunicationManagerEvent> {
+    self.event_receiver.recv().await
+  }
+
+  /// Wait for the machine to report that scanning has finished.
+  pub async fn expect_scanning_finished(&mut self) {
+    match self.next_event().await {
+      Some(HardwareCommunicationManagerEvent::ScanningFinished) => {}
+      event => panic!("Expected scanning to finish, got {:?}", event),
+    }
+  }
+
+  /// Wait for the machine to report an error.
+  pub async fn expect_error(&mut self) {
+    match self.next_event().await {
+      Some(HardwareCommunicationManagerEvent::Error { .. }) => {}
+      event => panic!("Expected an error, got {:?}", event),
+    }
+  }
+
+  /// Wait for the dongle's firmware version to be reported, and return it.
+  pub async fn expect_firmware_version(&mut self) -> String {
+    match self.next_event().await {
+      Some(HardwareCommunicationManagerEvent::FirmwareVersion { hardware, version }) => {
+        assert_eq!(hardware, self.name);
+        version
+      }
+      event => panic!("Expected a firmware version, got {:?}", event),
+    }
+  }
+
+  /// Wait for the machine to report a toy, and connect to it.
+  pub async fn expect_toy(&mut self) -> Hardware {
+    match self.next_event().await {
+      Some(HardwareCommunicationManagerEvent::DeviceFound { mut creator, .. }) => creator
+        .connect()
+        .await
+        .expect("Dongle toys should always connect.")
+        .specialize(&[])
+        .await
+        .expect("Dongle toys should always specialize."),
+      event => panic!("Expected a found toy, got {:?}", event),
+    }
+  }
+}
@@ -6,26 +6,23 @@
 // for full license information.
 
 use super::{
-  lovense_dongle_messages::{
-    LovenseDeviceCommand,
-    LovenseDongleIncomingMessage,
-    OutgoingLovenseData,
-  },
-  lovense_dongle_state_machine::create_lovense_dongle_machine,
+  lovense_dongle_machines::{DongleConnection, LovenseDongleMachines},
+  lovense_dongle_messages::{LovenseDongleIncomingMessage, OutgoingLovenseData},
 };
 use crate::{
   core::{errors::ButtplugDeviceError, ButtplugResultFuture},
   server::device::hardware::communication::{
+    spawn_manager_task,
     HardwareCommunicationManager,
     HardwareCommunicationManagerBuilder,
     HardwareCommunicationManagerEvent,
   },
-  util::async_manager,
 };
 use futures::FutureExt;
 use hidapi::{HidApi, HidDevice};
 use serde_json::Deserializer;
 use std::{
+  ffi::CString,
   sync::{
     atomic::{AtomicBool, Ordering},
     Arc,
@@ -86,6 +83,16 @@ fn hid_write_thread(
       OutgoingLovenseData::Message(m) => {
         port_write(serde_json::to_string(&m).expect("This will always serialize."));
       }
+      OutgoingLovenseData::Reset => {
+        info!("Resetting HID dongle connection.");
+        token.cancel();
+        break;
+      }
+      OutgoingLovenseData::Close => {
+        info!("Closing HID dongle connection.");
+        token.cancel();
+        break;
+      }
     }
   }
   trace!("Leaving HID dongle write thread");
@@ -102,7 +109,7 @@ fn hid_read_thread(
     .expect("Should alwasy succeed.");
   let mut data: String = String::default();
   let mut buf = [0u8; 1024];
-  while !token.is_cancelled() {
+  'read: while !token.is_cancelled() {
     match dongle.read_timeout(&mut buf, 100) {
       Ok(len) => {
         if len == 0 {
@@ -127,11 +134,13 @@ fn hid_read_thread(
               Ok(m) => {
                 trace!("Read message: {:?}", m);
                 if let Err(err) = sender_clone.blocking_send(m) {
-                  // Error, assume we'll be cancelled by disconnect.
+                  // The state machine has let go of this connection, so there's no one left to
+                  // read for.
                   error!(
                     "Error sending message, assuming device disconnect: {:?}",
                     err
                   );
+                  break 'read;
                 }
               }
               Err(_e) => {
@@ -157,6 +166,43 @@ fn hid_read_thread(
   trace!("Leaving HID dongle read thread");
 }
 
+/// Open a HID dongle and start up its reader and writer threads. Cancelling `token` shuts both
+/// threads down, and the writer cancels it itself if the state machine asks for a reset.
+fn open_hid_dongle(
+  api: &HidApi,
+  path: &CString,
+  token: CancellationToken,
+) -> Option<(DongleConnection, Vec<thread::JoinHandle<()>>)> {
+  // We can't clone HIDDevices, so instead we just open 2 instances of the same one to pass to
+  // the different threads. Ugh.
+  let (dongle1, dongle2) = match (api.open_path(path), api.open_path(path)) {
+    (Ok(dongle1), Ok(dongle2)) => (dongle1, dongle2),
+    _ => return None,
+  };
+  let (writer_sender, writer_receiver) = channel(256);
+  let (reader_sender, reader_receiver) = channel(256);
+  let read_token = token.clone();
+  let write_token = token.clone();
+
+  let read_thread = thread::Builder::new()
+    .name("Lovense Dongle HID Reader Thread".to_string())
+    .spawn(move || {
+      hid_read_thread(dongle1, reader_sender, read_token);
+    })
+    .expect("Thread should always spawn");
+
+  let write_thread = thread::Builder::new()
+    .name("Lovense Dongle HID Writer Thread".to_string())
+    .spawn(move || {
+      hid_write_thread(dongle2, writer_receiver, write_token);
+    })
+    .expect("Thread should always spawn");
+  Some((
+    (writer_sender, reader_receiver, token),
+    vec![read_thread, write_thread],
+  ))
+}
+
 #[derive(Default, Clone)]
 pub struct LovenseHIDDongleCommunicationManagerBuilder {}
 
@@ -164,50 +210,47 @@ impl HardwareCommunicationManagerBuilder for LovenseHIDDongleCommunicationManage
   fn finish(
     &mut self,
     sender: Sender<HardwareCommunicationManagerEvent>,
+    cancellation_token: CancellationToken,
   ) -> Box<dyn HardwareCommunicationManager> {
-    Box::new(LovenseHIDDongleCommunicationManager::new(sender))
+    Box::new(LovenseHIDDongleCommunicationManager::new(
+      sender,
+      cancellation_token,
+    ))
   }
 }
 
 pub struct LovenseHIDDongleCommunicationManager {
-  machine_sender: Sender<LovenseDeviceCommand>,
-  read_thread: Arc<Mutex<Option<thread::JoinHandle<()>>>>,
-  write_thread: Arc<Mutex<Option<thread::JoinHandle<()>>>>,
-  is_scanning: Arc<AtomicBool>,
+  machines: LovenseDongleMachines,
+  threads: Arc<Mutex<Vec<thread::JoinHandle<()>>>>,
   thread_cancellation_token: CancellationToken,
   dongle_available: Arc<AtomicBool>,
 }
 
 impl LovenseHIDDongleCommunicationManager {
-  fn new(event_sender: Sender<HardwareCommunicationManagerEvent>) -> Self {
+  fn new(
+    event_sender: Sender<HardwareCommunicationManagerEvent>,
+    cancellation_token: CancellationToken,
+  ) -> Self {
     trace!("Lovense dongle HID Manager created");
-    let (machine_sender, machine_receiver) = channel(256);
     let dongle_available = Arc::new(AtomicBool::new(false));
+    let thread_cancellation_token = cancellation_token.child_token();
     let mgr = Self {
-      machine_sender,
-      read_thread: Arc::new(Mutex::new(None)),
-      write_thread: Arc::new(Mutex::new(None)),
-      is_scanning: Arc::new(AtomicBool::new(false)),
-      thread_cancellation_token: CancellationToken::new(),
+      machines: LovenseDongleMachines::new(
+        event_sender.clone(),
+        thread_cancellation_token.child_token(),
+      ),
+      threads: Arc::new(Mutex::new(vec![])),
+      thread_cancellation_token,
       dongle_available,
     };
     let dongle_fut = mgr.find_dongle();
-    async_manager::spawn(
+    spawn_manager_task(
+      event_sender.clone(),
       async move {
         let _ = dongle_fut.await;
       }
       .instrument(tracing::info_span!("Lovense HID Dongle Finder Task")),
     );
-    let mut machine =
-      create_lovense_dongle_machine(event_sender, machine_receiver, mgr.is_scanning.clone());
-    async_manager::spawn(
-      async move {
-        while let Some(next) = machine.transition().await {
-          machine = next;
-        }
-      }
-      .instrument(tracing::info_span!("Lovense HID Dongle State Machine")),
-    );
     mgr
   }
 
@@ -216,70 +259,65 @@ impl LovenseHIDDongleCommunicationManager {
     // have one, skip on to scanning. If we can't find one, send message to log
     // and stop scanning.
 
-    let machine_sender_clone = self.machine_sender.clone();
-    let held_read_thread = self.read_thread.clone();
-    let held_write_thread = self.write_thread.clone();
-    let read_token = self.thread_cancellation_token.child_token();
-    let write_token = self.thread_cancellation_token.child_token();
+    let machines = self.machines.clone();
+    let held_threads = self.threads.clone();
     let dongle_available = self.dongle_available.clone();
     async move {
-      let (writer_sender, writer_receiver) = channel(256);
-      let (reader_sender, reader_receiver) = channel(256);
       let api = HidApi::new().map_err(|_| {
         // This may happen if we create a new server in the same process?
         error!("Failed to create HIDAPI instance. Was one already created?");
         ButtplugDeviceError::DeviceConnectionError("Cannot create HIDAPI.".to_owned())
       })?;
 
-      // We can't clone HIDDevices, so instead we just open 2 instances of the same one to pass to
-      // the different threads. Ugh.
-      let dongle1 = api.open(0x1915, 0x520a).map_err(|_| {
-        warn!("Cannot find lovense HID dongle.");
-        ButtplugDeviceError::DeviceConnectionError("Cannot find lovense HID Dongle.".to_owned())
-      })?;
-      let dongle2 = api.open(0x1915, 0x520a).map_err(|_| {
+      // Users with a lot of toys may have more than one dongle plugged in, so set up every one we
+      // can see.
+      let dongle_paths: Vec<CString> = api
+        .device_list()
+        .filter(|info| info.vendor_id() == 0x1915 && info.product_id() == 0x520a)
+        .map(|info| info.path().to_owned())
+        .collect();
+      for path in dongle_paths {
+        let name = path.to_string_lossy().into_owned();
+        // Connections outlive the manager's token, so the state machine can stop toys on shutdown
+        // before closing them. The dongle's supervisor makes sure they do get closed.
+        let Some(((writer_sender, reader_receiver, connection_token), threads)) =
+          open_hid_dongle(&api, &path, CancellationToken::new())
+        else {
+          warn!("Cannot open lovense HID dongle at {}.", name);
+          continue;
+        };
+        held_threads.lock().await.extend(threads);
+        dongle_available.store(true, Ordering::SeqCst);
+        info!("Found Lovense HID Dongle at {}", name);
+        machines
+          .add_dongle(&name, writer_sender, reader_receiver)
+          .await;
+        let held_threads = held_threads.clone();
+        machines.supervise_dongle(&name, connection_token, move || {
+          let path = path.clone();
+          let held_threads = held_threads.clone();
+          async move {
+            // HIDAPI only lets us have one instance around at a time, so make a fresh one for the
+            // reopen and let it go once the threads have their devices.
+            let api = HidApi::new().ok()?;
+            let (connection, threads) = open_hid_dongle(&api, &path, CancellationToken::new())?;
+            held_threads.lock().await.extend(threads);
+            Some(connection)
+          }
+        });
+      }
+      if machines.dongle_count() == 0 {
         warn!("Cannot find lovense HID dongle.");
-        ButtplugDeviceError::DeviceConnectionError("Cannot find lovense HID Dongle.".to_owned())
-      })?;
-
-      dongle_available.store(true, Ordering::SeqCst);
-
-      let read_thread = thread::Builder::new()
-        .name("Lovense Dongle HID Reader Thread".to_string())
-        .spawn(move || {
-          hid_read_thread(dongle1, reader_sender, read_token);
-        })
-        .expect("Thread should always spawn");
-
-      let write_thread = thread::Builder::new()
-        .name("Lovense Dongle HID Writer Thread".to_string())
-        .spawn(move || {
-          hid_write_thread(dongle2, writer_receiver, write_token);
-        })
-        .expect("Thread should always spawn");
-
-      *(held_read_thread.lock().await) = Some(read_thread);
-      *(held_write_thread.lock().await) = Some(write_thread);
-      if machine_sender_clone
-        .send(LovenseDeviceCommand::DongleFound(
-          writer_sender,
-          reader_receiver,
-        ))
-        .await
-        .is_err() {
-          warn!("We've already spun up the state machine, this receiver should exist, but if we're shutting down this will throw.");
-        }
-      info!("Found Lovense HID Dongle");
+        return Err(
+          ButtplugDeviceError::DeviceConnectionError("Cannot find lovense HID Dongle.".to_owned())
+            .into(),
+        );
+      }
       Ok(())
     }
     .boxed()
   }
-
-  pub fn scanning_status(&self) -> Arc<AtomicBool> {
-    self.is_scanning.clone()
-  }
 }
-
 impl HardwareCommunicationManager for LovenseHIDDongleCommunicationManager {
   fn name(&self) -> &'static str {
     "LovenseHIDDongleCommunicationManager"
@@ -287,32 +325,25 @@ impl HardwareCommunicationManager for LovenseHIDDongleCommunicationManager {
 
   fn start_scanning(&mut self) -> ButtplugResultFuture {
     debug!("Lovense Dongle Manager scanning for devices");
-    let sender = self.machine_sender.clone();
-    self.is_scanning.store(true, Ordering::SeqCst);
+    let machines = self.machines.clone();
     async move {
-      sender
-        .send(LovenseDeviceCommand::StartScanning)
-        .await
-        .expect("Machine always exists as long as this object does.");
+      machines.start_scanning().await;
       Ok(())
     }
     .boxed()
   }
 
   fn stop_scanning(&mut self) -> ButtplugResultFuture {
-    let sender = self.machine_sender.clone();
+    let machines = self.machines.clone();
     async move {
-      sender
-        .send(LovenseDeviceCommand::StopScanning)
-        .await
-        .expect("Machine always exists as long as this object does.");
+      machines.stop_scanning().await;
       Ok(())
     }
     .boxed()
   }
 
   fn scanning_status(&self) -> bool {
-    self.is_scanning.load(Ordering::SeqCst)
+    self.machines.is_scanning()
   }
 
   fn can_scan(&self) -> bool {
@@ -6,26 +6,29 @@
 // for full license information.
 
 use super::{
-  lovense_dongle_messages::{
-    LovenseDeviceCommand,
-    LovenseDongleIncomingMessage,
-    OutgoingLovenseData,
-  },
-  lovense_dongle_state_machine::create_lovense_dongle_machine,
+  lovense_dongle_hotplug::watch_serial_ports,
+  lovense_dongle_machines::{DongleConnection, LovenseDongleMachines},
+  lovense_dongle_messages::{LovenseDongleIncomingMessage, OutgoingLovenseData},
 };
 use crate::{
   core::ButtplugResultFuture,
   server::device::hardware::communication::{
+    serial_diagnostics::{categorize_serial_error, port_description},
+    spawn_manager_task,
     HardwareCommunicationManager,
     HardwareCommunicationManagerBuilder,
     HardwareCommunicationManagerEvent,
+    HardwareCommunicationManagerStatus,
+    HardwarePortDiagnostic,
+    HardwarePortIssue,
   },
-  util::async_manager,
+  util::{async_manager, sleep},
 };
 use futures::FutureExt;
 use serde_json::Deserializer;
-use serialport::{available_ports, SerialPort, SerialPortType};
+use serialport::{available_ports, SerialPort, SerialPortInfo, SerialPortType};
 use std::{
+  collections::HashMap,
   io::ErrorKind,
   sync::{
     atomic::{AtomicBool, Ordering},
@@ -38,12 +41,42 @@ use tokio::{
   runtime,
   sync::{
     mpsc::{channel, Receiver, Sender},
+    watch,
     Mutex,
   },
 };
 use tokio_util::sync::CancellationToken;
 use tracing_futures::Instrument;
 
+const LOVENSE_SERIAL_DONGLE_COMM_MANAGER_NAME: &str = "LovenseSerialDongleCommunicationManager";
+/// How long the dongle has to answer a scan request before we report it as unresponsive.
+const DONGLE_RESPONSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+fn is_lovense_dongle(port: &SerialPortInfo) -> bool {
+  // Hardcode the dongle VID/PID for now. We can't really do protocol detection here because this
+  // is a comm bus to us, not a device.
+  match &port.port_type {
+    SerialPortType::UsbPort(usb_info) => usb_info.vid == 0x1a86 && usb_info.pid == 0x7523,
+    _ => false,
+  }
+}
+
+async fn send_status(
+  event_sender: &Sender<HardwareCommunicationManagerEvent>,
+  status: HardwareCommunicationManagerStatus,
+) {
+  if event_sender
+    .send(HardwareCommunicationManagerEvent::StatusChanged {
+      name: LOVENSE_SERIAL_DONGLE_COMM_MANAGER_NAME,
+      status,
+    })
+    .await
+    .is_err()
+  {
+    debug!("Device manager disappeared, dropping dongle status update.");
+  }
+}
+
 fn serial_write_thread(
   mut port: Box<dyn SerialPort>,
   mut receiver: Receiver<OutgoingLovenseData>,
@@ -81,6 +114,16 @@ fn serial_write_thread(
           serde_json::to_string(&m).expect("We create these packets so they'll always serialize."),
         );
       }
+      OutgoingLovenseData::Reset => {
+        info!("Resetting lovense dongle connection.");
+        token.cancel();
+        break;
+      }
+      OutgoingLovenseData::Close => {
+        info!("Closing lovense dongle connection.");
+        token.cancel();
+        break;
+      }
     }
   }
   debug!("Exiting lovense dongle write thread.");
@@ -89,14 +132,18 @@ fn serial_write_thread(
 fn serial_read_thread(
   mut port: Box<dyn SerialPort>,
   sender: Sender<LovenseDongleIncomingMessage>,
+  responded: watch::Sender<bool>,
   token: CancellationToken,
 ) {
   let mut data: String = String::default();
-  while !token.is_cancelled() {
+  'read: while !token.is_cancelled() {
     let mut buf: [u8; 1024] = [0; 1024];
     match port.read(&mut buf) {
       Ok(len) => {
         debug!("Got {} serial bytes", len);
+        if len > 0 && !*responded.borrow() {
+          responded.send_replace(true);
+        }
         data += std::str::from_utf8(&buf[0..len])
           .expect("We should always get valid data from the port.");
         if data.contains('\n') {
@@ -108,12 +155,10 @@ fn serial_read_thread(
             match msg {
               Ok(m) => {
                 debug!("Read message: {:?}", m);
-                async_manager::block_on(async {
-                  sender_clone
-                    .send(m)
-                    .await
-                    .expect("Thread shouldn't be running if we don't have a listener.")
-                });
+                if async_manager::block_on(sender_clone.send(m)).is_err() {
+                  debug!("Lovense dongle state machine let go of this connection.");
+                  break 'read;
+                }
               }
               Err(e) => {
                 error!("Error reading: {:?}", e);
@@ -136,6 +181,9 @@ fn serial_read_thread(
           continue;
         }
         error!("{:?}", e);
+        // Usually means the dongle was unplugged. Take the writer down with us so the connection
+        // gets reopened once the dongle is back.
+        token.cancel();
         break;
       }
     }
@@ -143,6 +191,44 @@ fn serial_read_thread(
   debug!("Exiting lovense dongle read thread.");
 }
 
+/// Open a dongle's serial port and start up its reader and writer threads. Cancelling `token` shuts
+/// both threads down, and the writer cancels it itself if the state machine asks for a reset.
+fn open_serial_dongle(
+  port_name: &str,
+  responded: watch::Sender<bool>,
+  token: CancellationToken,
+) -> Result<(DongleConnection, Vec<thread::JoinHandle<()>>), serialport::Error> {
+  let dongle_port_handle = serialport::new(port_name, 115200)
+    .timeout(Duration::from_millis(500))
+    .open()?;
+  let (writer_sender, writer_receiver) = channel(256);
+  let (reader_sender, reader_receiver) = channel(256);
+  let read_port = (*dongle_port_handle)
+    .try_clone()
+    .expect("USB port should always clone.");
+  let read_token = token.clone();
+  let read_thread = thread::Builder::new()
+    .name("Serial Reader Thread".to_string())
+    .spawn(move || {
+      serial_read_thread(read_port, reader_sender, responded, read_token);
+    })
+    .expect("Thread should always create");
+  let write_port = (*dongle_port_handle)
+    .try_clone()
+    .expect("USB port should always clone.");
+  let write_token = token.clone();
+  let write_thread = thread::Builder::new()
+    .name("Serial Writer Thread".to_string())
+    .spawn(move || {
+      serial_write_thread(write_port, writer_receiver, write_token);
+    })
+    .expect("Thread should always create");
+  Ok((
+    (writer_sender, reader_receiver, token),
+    vec![read_thread, write_thread],
+  ))
+}
+
 #[derive(Default, Clone)]
 pub struct LovenseSerialDongleCommunicationManagerBuilder {}
 
@@ -150,173 +236,269 @@ impl HardwareCommunicationManagerBuilder for LovenseSerialDongleCommunicationMan
   fn finish(
     &mut self,
     sender: Sender<HardwareCommunicationManagerEvent>,
+    cancellation_token: CancellationToken,
   ) -> Box<dyn HardwareCommunicationManager> {
-    Box::new(LovenseSerialDongleCommunicationManager::new(sender))
+    Box::new(LovenseSerialDongleCommunicationManager::new(
+      sender,
+      cancellation_token,
+    ))
+  }
+}
+
+/// Everything we need to bring up dongles, shared between the startup search, hotplug
+/// notifications, and reopening connections that were reset.
+#[derive(Clone)]
+struct SerialDongleConnector {
+  machines: LovenseDongleMachines,
+  threads: Arc<Mutex<Vec<thread::JoinHandle<()>>>>,
+  // Live connection tokens by port name, so we never open the same dongle twice.
+  connections: Arc<Mutex<HashMap<String, CancellationToken>>>,
+  dongle_available: Arc<AtomicBool>,
+  dongle_port: Arc<Mutex<Option<HardwarePortDiagnostic>>>,
+  responded_sender: watch::Sender<bool>,
+  event_sender: Sender<HardwareCommunicationManagerEvent>,
+}
+
+impl SerialDongleConnector {
+  /// Open a dongle's port, unless we already have a live connection to it.
+  async fn open_port(
+    &self,
+    port_name: &str,
+  ) -> Result<Option<DongleConnection>, serialport::Error> {
+    let mut connections = self.connections.lock().await;
+    if connections
+      .get(port_name)
+      .is_some_and(|token| !token.is_cancelled())
+    {
+      return Ok(None);
+    }
+    // Connections outlive the manager's token, so the state machine can stop toys on shutdown
+    // before closing them. The dongle's supervisor makes sure they do get closed.
+    let (connection, threads) = open_serial_dongle(
+      port_name,
+      self.responded_sender.clone(),
+      CancellationToken::new(),
+    )?;
+    connections.insert(port_name.to_owned(), connection.2.clone());
+    self.threads.lock().await.extend(threads);
+    Ok(Some(connection))
+  }
+
+  /// Connect to every dongle we can see that we aren't already connected to. If we can't find one,
+  /// report why through our status.
+  async fn find_dongles(&self) {
+    // TODO Does this block? Should it run in one of our threads?
+    let ports = match available_ports() {
+      Ok(ports) => ports,
+      Err(e) => {
+        info!("Cannot enumerate serial ports: {}", e);
+        if !self.dongle_available.load(Ordering::SeqCst) {
+          send_status(
+            &self.event_sender,
+            HardwareCommunicationManagerStatus::Unavailable,
+          )
+          .await;
+        }
+        return;
+      }
+    };
+    debug!("Got {} serial ports back", ports.len());
+    let mut diagnostics = vec![];
+    for p in ports.iter().filter(|p| is_lovense_dongle(p)) {
+      // Users with a lot of toys may have more than one dongle, so keep going after this one.
+      let (writer_sender, reader_receiver, connection_token) =
+        match self.open_port(&p.port_name).await {
+          Ok(Some(connection)) => connection,
+          Ok(None) => continue,
+          Err(e) => {
+            let issue = categorize_serial_error(&e);
+            error!("Cannot open Lovense dongle at {}: {}", p.port_name, issue);
+            diagnostics.push(HardwarePortDiagnostic::new(
+              &p.port_name,
+              &port_description(p),
+              Some(issue),
+            ));
+            continue;
+          }
+        };
+      info!("Connected to Lovense dongle at {}", p.port_name);
+      // Unresponsive dongle reporting points at the first dongle we found.
+      self.dongle_port.lock().await.get_or_insert_with(|| {
+        HardwarePortDiagnostic::new(&p.port_name, &port_description(p), None)
+      });
+      if !self.dongle_available.swap(true, Ordering::SeqCst) {
+        send_status(
+          &self.event_sender,
+          HardwareCommunicationManagerStatus::Available,
+        )
+        .await;
+      }
+      // If this dongle was plugged back in, its state machine is still around waiting for it.
+      self
+        .machines
+        .reconnect_dongle(&p.port_name, writer_sender, reader_receiver)
+        .await;
+      self.supervise(&p.port_name, connection_token);
+    }
+    if self.machines.dongle_count() > 0 {
+      return;
+    }
+    if diagnostics.is_empty() {
+      warn!("Cannot find Lovense Serial dongle.");
+    } else {
+      send_status(
+        &self.event_sender,
+        HardwareCommunicationManagerStatus::Inaccessible(diagnostics),
+      )
+      .await;
+    }
+  }
+
+  fn supervise(&self, port_name: &str, connection_token: CancellationToken) {
+    let connector = self.clone();
+    let reopen_port_name = port_name.to_owned();
+    self
+      .machines
+      .supervise_dongle(port_name, connection_token, move || {
+        let connector = connector.clone();
+        let port_name = reopen_port_name.clone();
+        async move {
+          match connector.open_port(&port_name).await {
+            Ok(connection) => connection,
+            Err(e) => {
+              warn!("Cannot reopen Lovense dongle at {}: {}", port_name, e);
+              None
+            }
+          }
+        }
+      });
   }
 }
 
 pub struct LovenseSerialDongleCommunicationManager {
-  machine_sender: Sender<LovenseDeviceCommand>,
-  //port: Arc<Mutex<Option<Box<dyn SerialPort>>>>,
-  read_thread: Arc<Mutex<Option<thread::JoinHandle<()>>>>,
-  write_thread: Arc<Mutex<Option<thread::JoinHandle<()>>>>,
-  is_scanning: Arc<AtomicBool>,
+  connector: SerialDongleConnector,
   thread_cancellation_token: CancellationToken,
-  dongle_available: Arc<AtomicBool>,
+  dongle_responded: watch::Receiver<bool>,
 }
 
 impl LovenseSerialDongleCommunicationManager {
-  fn new(event_sender: Sender<HardwareCommunicationManagerEvent>) -> Self {
+  fn new(
+    event_sender: Sender<HardwareCommunicationManagerEvent>,
+    cancellation_token: CancellationToken,
+  ) -> Self {
     trace!("Lovense dongle serial port created");
-    let (machine_sender, machine_receiver) = channel(256);
-    let dongle_available = Arc::new(AtomicBool::new(false));
-    let mgr = Self {
-      machine_sender,
-      read_thread: Arc::new(Mutex::new(None)),
-      write_thread: Arc::new(Mutex::new(None)),
-      is_scanning: Arc::new(AtomicBool::new(false)),
-      thread_cancellation_token: CancellationToken::new(),
-      dongle_available,
+    let (responded_sender, dongle_responded) = watch::channel(false);
+    let thread_cancellation_token = cancellation_token.child_token();
+    let connector = SerialDongleConnector {
+      machines: LovenseDongleMachines::new(
+        event_sender.clone(),
+        thread_cancellation_token.child_token(),
+      ),
+      threads: Arc::new(Mutex::new(vec![])),
+      connections: Arc::new(Mutex::new(HashMap::new())),
+      dongle_available: Arc::new(AtomicBool::new(false)),
+      dongle_port: Arc::new(Mutex::new(None)),
+      responded_sender,
+      event_sender: event_sender.clone(),
     };
-    let dongle_fut = mgr.find_dongle();
-    // TODO If we don't find a dongle before scanning, what happens?
-    async_manager::spawn(async move {
-      if let Err(err) = dongle_fut.await {
-        error!("Error finding serial dongle: {:?}", err);
-      }
-    });
-    let mut machine =
-      create_lovense_dongle_machine(event_sender, machine_receiver, mgr.is_scanning.clone());
-    async_manager::spawn(
+    // Start listening before the first search, so we can't miss a dongle plugged in between.
+    let mut hotplug = watch_serial_ports(thread_cancellation_token.child_token());
+    let finder = connector.clone();
+    spawn_manager_task(
+      event_sender,
       async move {
-        while let Some(next) = machine.transition().await {
-          machine = next;
+        finder.find_dongles().await;
+        while hotplug.recv().await.is_some() {
+          debug!("Serial ports changed, looking for new Lovense dongles.");
+          finder.find_dongles().await;
         }
       }
-      .instrument(tracing::info_span!(
-        parent: tracing::Span::current(),
-        "Lovense Serial Dongle State Machine"
-      )),
+      .instrument(tracing::info_span!("Lovense Serial Dongle Finder")),
     );
-    mgr
+    Self {
+      connector,
+      thread_cancellation_token,
+      dongle_responded,
+    }
   }
 
-  fn find_dongle(&self) -> ButtplugResultFuture {
-    // First off, see if we can actually find a Lovense dongle. If we already
-    // have one, skip on to scanning. If we can't find one, send message to log
-    // and stop scanning.
-
-    let machine_sender_clone = self.machine_sender.clone();
-    let held_read_thread = self.read_thread.clone();
-    let held_write_thread = self.write_thread.clone();
+  /// Make sure the dongle answers once we've asked it to do something, reporting it as
+  /// unresponsive if it doesn't. Dongles with old or corrupt firmware will accept a connection
+  /// but never talk back.
+  fn check_dongle_response(&self) {
+    if !self.connector.dongle_available.load(Ordering::SeqCst) || *self.dongle_responded.borrow() {
+      return;
+    }
+    let mut responded = self.dongle_responded.clone();
+    let dongle_port = self.connector.dongle_port.clone();
+    let event_sender = self.connector.event_sender.clone();
     let token = self.thread_cancellation_token.child_token();
-    let dongle_available = self.dongle_available.clone();
-    async move {
-      // TODO Does this block? Should it run in one of our threads?
-      let found_dongle = false;
-      match available_ports() {
-        Ok(ports) => {
-          debug!("Got {} serial ports back", ports.len());
-          for p in ports {
-            if let SerialPortType::UsbPort(usb_info) = p.port_type {
-              // Hardcode the dongle VID/PID for now. We can't really do protocol
-              // detection here because this is a comm bus to us, not a device.
-              if usb_info.vid == 0x1a86 && usb_info.pid == 0x7523 {
-                // We've found a dongle.
-                info!("Found lovense dongle, connecting");
-                let serial_port =
-                  serialport::new(&p.port_name, 115200).timeout(Duration::from_millis(500));
-                match serial_port.open() {
-                  Ok(dongle_port) => {
-                    let read_token = token.child_token();
-                    let write_token = token.child_token();
-                    let (writer_sender, writer_receiver) = channel(256);
-                    let (reader_sender, reader_receiver) = channel(256);
-                    let read_port = (*dongle_port)
-                      .try_clone()
-                      .expect("USB port should always clone.");
-                    let read_thread = thread::Builder::new()
-                      .name("Serial Reader Thread".to_string())
-                      .spawn(move || {
-                        serial_read_thread(read_port, reader_sender, read_token);
-                      })
-                      .expect("Thread should always create");
-                    let write_port = (*dongle_port)
-                      .try_clone()
-                      .expect("USB port should always clone.");
-                    let write_thread = thread::Builder::new()
-                      .name("Serial Writer Thread".to_string())
-                      .spawn(move || {
-                        serial_write_thread(write_port, writer_receiver, write_token);
-                      })
-                      .expect("Thread should always create");
-                    *(held_read_thread.lock().await) = Some(read_thread);
-                    *(held_write_thread.lock().await) = Some(write_thread);
-                    dongle_available.store(true, Ordering::SeqCst);
-                    machine_sender_clone
-                      .send(LovenseDeviceCommand::DongleFound(
-                        writer_sender,
-                        reader_receiver,
-                      ))
-                      .await
-                      .expect("Machine exists if we got here.");
-                  }
-                  Err(e) => error!("{:?}", e),
-                };
-              }
-            }
-          }
-        }
-        Err(_) => {
-          info!("No serial ports found");
-        }
+    async_manager::spawn(async move {
+      tokio::select! {
+        _ = responded.wait_for(|responded| *responded) => return,
+        _ = sleep(DONGLE_RESPONSE_TIMEOUT) => {}
+        _ = token.cancelled() => return,
       }
-      if !found_dongle {
-        warn!("Cannot find Lovense Serial dongle.");
+      let Some(port) = dongle_port.lock().await.clone() else {
+        return;
+      };
+      warn!(
+        "Lovense dongle at {} has not responded in {:?}.",
+        port.port_name(),
+        DONGLE_RESPONSE_TIMEOUT
+      );
+      send_status(
+        &event_sender,
+        HardwareCommunicationManagerStatus::Inaccessible(vec![HardwarePortDiagnostic::new(
+          port.port_name(),
+          port.description(),
+          Some(HardwarePortIssue::NoResponse),
+        )]),
+      )
+      .await;
+      // If it does wake up eventually, let everyone know it's usable again.
+      let woke_up = tokio::select! {
+        result = responded.wait_for(|responded| *responded) => result.is_ok(),
+        _ = token.cancelled() => false,
+      };
+      if woke_up {
+        send_status(&event_sender, HardwareCommunicationManagerStatus::Available).await;
       }
-      Ok(())
-    }
-    .instrument(tracing::info_span!("Lovense Serial Dongle Finder"))
-    .boxed()
+    });
   }
 }
 
 impl HardwareCommunicationManager for LovenseSerialDongleCommunicationManager {
   fn name(&self) -> &'static str {
-    "LovenseSerialDongleCommunicationManager"
+    LOVENSE_SERIAL_DONGLE_COMM_MANAGER_NAME
   }
 
   fn start_scanning(&mut self) -> ButtplugResultFuture {
     debug!("Lovense Dongle Manager scanning for devices.");
-    let sender = self.machine_sender.clone();
+    self.check_dongle_response();
+    let machines = self.connector.machines.clone();
     async move {
-      sender
-        .send(LovenseDeviceCommand::StartScanning)
-        .await
-        .expect("If we're getting scan requests, we should a task to throw it at.");
+      machines.start_scanning().await;
       Ok(())
     }
     .boxed()
   }
 
   fn stop_scanning(&mut self) -> ButtplugResultFuture {
-    let sender = self.machine_sender.clone();
+    let machines = self.connector.machines.clone();
     async move {
-      sender
-        .send(LovenseDeviceCommand::StopScanning)
-        .await
-        .expect("If we're getting scan requests, we should a task to throw it at.");
+      machines.stop_scanning().await;
       Ok(())
     }
     .boxed()
   }
 
   fn scanning_status(&self) -> bool {
-    self.is_scanning.load(Ordering::SeqCst)
+    self.connector.machines.is_scanning()
   }
 
   fn can_scan(&self) -> bool {
-    self.dongle_available.load(Ordering::SeqCst)
+    self.connector.dongle_available.load(Ordering::SeqCst)
   }
 }
 
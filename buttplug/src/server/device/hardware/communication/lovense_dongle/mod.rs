@@ -6,12 +6,18 @@
 // for full license information.
 
 pub mod lovense_dongle_hardware;
-mod lovense_dongle_messages;
+mod lovense_dongle_hotplug;
+mod lovense_dongle_machines;
+pub mod lovense_dongle_messages;
 mod lovense_dongle_state_machine;
+#[cfg(any(test, feature = "lovense-dongle-test-harness"))]
+pub mod lovense_dongle_test_harness;
 pub mod lovense_hid_dongle_comm_manager;
 pub mod lovense_serial_dongle_comm_manager;
 
 pub use lovense_dongle_hardware::{LovenseDongleHardware, LovenseDongleHardwareConnector};
+#[cfg(any(test, feature = "lovense-dongle-test-harness"))]
+pub use lovense_dongle_test_harness::LovenseDongleTestHarness;
 pub use lovense_hid_dongle_comm_manager::{
   LovenseHIDDongleCommunicationManager,
   LovenseHIDDongleCommunicationManagerBuilder,
@@ -10,6 +10,8 @@
 pub mod lovense_connect_service;
 #[cfg(feature = "websocket-server-manager")]
 pub mod websocket_server;
+#[cfg(feature = "osc-manager")]
+pub mod osc;
 
 // BTLEPlug works on anything not WASM
 #[cfg(all(
@@ -36,27 +38,44 @@ pub mod lovense_dongle;
 ))]
 pub mod serialport;
 
+// Shared diagnostics for the managers that talk over serial ports
+#[cfg(all(
+  any(feature = "serial-manager", feature = "lovense-dongle-manager"),
+  any(target_os = "windows", target_os = "macos", target_os = "linux")
+))]
+pub mod serial_diagnostics;
+
 #[cfg(all(
   feature = "hid-manager",
   any(target_os = "windows", target_os = "macos", target_os = "linux")
 ))]
 pub mod hid;
+#[cfg(all(
+  feature = "playstation-manager",
+  any(target_os = "windows", target_os = "macos", target_os = "linux")
+))]
+pub mod playstation;
 
 // XInput is windows only
 #[cfg(all(feature = "xinput-manager", target_os = "windows"))]
 pub mod xinput;
 
+// As is Windows.Gaming.Input
+#[cfg(all(feature = "gaming-input-manager", target_os = "windows"))]
+pub mod gaming_input;
+
 use crate::{
   core::{errors::ButtplugDeviceError, ButtplugResultFuture},
   server::device::hardware::HardwareConnector,
   util::{async_manager, sleep},
 };
 use async_trait::async_trait;
-use futures::future::{self, FutureExt};
+use futures::future::{self, Future, FutureExt};
+use getset::Getters;
 use serde::{Deserialize, Serialize};
-use std::{sync::Arc, time::Duration};
+use std::{fmt, sync::Arc, time::Duration};
 use thiserror::Error;
-use tokio::sync::mpsc::Sender;
+use tokio::sync::{mpsc::Sender, Notify};
 use tokio_util::sync::CancellationToken;
 
 #[derive(Debug)]
@@ -69,12 +88,132 @@ pub enum HardwareCommunicationManagerEvent {
     creator: Box<dyn HardwareConnector>,
   },
   ScanningFinished,
+  /// The availability of the hardware a manager scans with has changed.
+  StatusChanged {
+    name: &'static str,
+    status: HardwareCommunicationManagerStatus,
+  },
+  /// One of the manager's tasks panicked, so the manager can't be trusted to work anymore. The
+  /// device manager will rebuild or disable it. Usually sent via [spawn_manager_task].
+  Failed {
+    reason: String,
+  },
+  /// Something went wrong that the manager recovered from, but users should hear about, like a
+  /// toy failing to connect.
+  Error {
+    reason: String,
+  },
+  /// A piece of hardware the manager uses, like a dongle, reported its firmware version.
+  /// `hardware` tells apart multiple pieces of hardware on the same manager, e.g. by port name.
+  FirmwareVersion {
+    hardware: String,
+    version: String,
+  },
+}
+
+/// Availability of the hardware (radio, dongle, etc...) a communication manager uses to find and
+/// talk to devices.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HardwareCommunicationManagerStatus {
+  /// Hardware is present and usable.
+  Available,
+  /// Hardware is present but switched off, like a Bluetooth radio the user has toggled off.
+  PoweredOff,
+  /// Hardware could not be found or accessed.
+  Unavailable,
+  /// Hardware was found, but couldn't be used. Carries what was found and why it failed, so
+  /// applications can tell users how to fix it.
+  Inaccessible(Vec<HardwarePortDiagnostic>),
+  /// The manager itself failed, and was disabled after running out of restarts. Carries the reason
+  /// for the last failure.
+  Failed(String),
+}
+
+/// Reasons a port (serial port, dongle, etc...) found by a communication manager couldn't be used.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HardwarePortIssue {
+  /// The operating system refused access to the port.
+  PermissionDenied,
+  /// Something else already has the port open.
+  PortBusy,
+  /// The port opened, but the hardware on the other end never answered.
+  NoResponse,
+  /// Any other failure, with the error reported by the OS.
+  Other(String),
+}
+
+impl fmt::Display for HardwarePortIssue {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::PermissionDenied => write!(
+        f,
+        "Permission denied. On Linux, add your user to the group that owns the port (usually \
+         dialout or uucp), then log out and back in."
+      ),
+      Self::PortBusy => write!(
+        f,
+        "Port is in use. Close any other application that may be using the device."
+      ),
+      Self::NoResponse => write!(
+        f,
+        "Port opened but the hardware did not respond. Try unplugging and replugging it."
+      ),
+      Self::Other(err) => write!(f, "{}", err),
+    }
+  }
+}
+
+/// Result of checking a single port during enumeration.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Getters)]
+#[getset(get = "pub")]
+pub struct HardwarePortDiagnostic {
+  /// OS name for the port (COM3, /dev/ttyUSB0, etc...)
+  port_name: String,
+  /// Short description of what's on the port, if known.
+  description: String,
+  /// Why the port can't be used, or None if it opened fine.
+  issue: Option<HardwarePortIssue>,
+}
+
+impl HardwarePortDiagnostic {
+  pub fn new(port_name: &str, description: &str, issue: Option<HardwarePortIssue>) -> Self {
+    Self {
+      port_name: port_name.to_owned(),
+      description: description.to_owned(),
+      issue,
+    }
+  }
+}
+
+/// Firmware version reported by a piece of hardware a communication manager uses, so users can tell
+/// whether it needs an update when devices fail to pair.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Getters)]
+#[getset(get = "pub")]
+pub struct HardwareFirmwareVersion {
+  /// Which piece of hardware reported, e.g. the port a dongle is plugged into.
+  hardware: String,
+  /// Firmware version, as reported by the hardware.
+  version: String,
+}
+
+impl HardwareFirmwareVersion {
+  pub fn new(hardware: &str, version: &str) -> Self {
+    Self {
+      hardware: hardware.to_owned(),
+      version: version.to_owned(),
+    }
+  }
 }
 
 pub trait HardwareCommunicationManagerBuilder: Send {
+  /// Build the manager. `cancellation_token` is cancelled when the device manager shuts down (or
+  /// replaces the manager after it fails), and anything the manager spawns (scanning loops,
+  /// hardware tasks, etc...) should stop when it is, usually by holding a child token. May be
+  /// called again to rebuild the manager after a failure.
   fn finish(
     &mut self,
     sender: Sender<HardwareCommunicationManagerEvent>,
+    cancellation_token: CancellationToken,
   ) -> Box<dyn HardwareCommunicationManager>;
 }
 
@@ -86,15 +225,46 @@ pub trait HardwareCommunicationManager: Send + Sync {
     false
   }
   fn can_scan(&self) -> bool;
+  /// Current availability of the manager's hardware. Managers that can tell more than whether they
+  /// can scan should override this, and send a [HardwareCommunicationManagerEvent::StatusChanged]
+  /// event whenever it changes.
+  fn status(&self) -> HardwareCommunicationManagerStatus {
+    if self.can_scan() {
+      HardwareCommunicationManagerStatus::Available
+    } else {
+      HardwareCommunicationManagerStatus::Unavailable
+    }
+  }
   // Events happen via channel senders passed to the comm manager.
 }
 
+/// Spawn a task for a comm manager, reporting a panic in it to the device manager as a
+/// [HardwareCommunicationManagerEvent::Failed] event instead of letting it silently take the
+/// manager down.
+pub fn spawn_manager_task<Fut>(sender: Sender<HardwareCommunicationManagerEvent>, future: Fut)
+where
+  Fut: Future<Output = ()> + Send + 'static,
+{
+  async_manager::spawn_catching_panics(future, move |reason| {
+    error!("Communication manager task panicked: {}", reason);
+    if sender
+      .try_send(HardwareCommunicationManagerEvent::Failed { reason })
+      .is_err()
+    {
+      error!("Cannot report communication manager failure, device manager is gone or backed up.");
+    }
+  });
+}
+
 #[derive(Error, Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub enum HardwareSpecificError {
   // XInput library doesn't derive error on its error enum. :(
   #[cfg(all(feature = "xinput-manager", target_os = "windows"))]
   #[error("XInput usage error: {0}")]
   XInputError(String),
+  #[cfg(all(feature = "gaming-input-manager", target_os = "windows"))]
+  #[error("Windows.Gaming.Input error: {0}")]
+  GamingInputError(String),
   // Btleplug library uses Failure, not Error, on its error enum. :(
   #[cfg(all(
     feature = "btleplug-manager",
@@ -123,18 +293,33 @@ pub trait TimedRetryCommunicationManagerImpl: Sync + Send {
   fn rescan_wait_duration(&self) -> Duration {
     Duration::from_secs(1)
   }
+  /// Notified when there's reason to think something new showed up, like an OS hotplug event, so
+  /// the next scan doesn't have to wait out [rescan_wait_duration](Self::rescan_wait_duration).
+  fn rescan_notifier(&self) -> Option<Arc<Notify>> {
+    None
+  }
   async fn scan(&self) -> Result<(), ButtplugDeviceError>;
 }
 
 pub struct TimedRetryCommunicationManager<T: TimedRetryCommunicationManagerImpl + 'static> {
   comm_manager: Arc<T>,
+  /// Used to report a panicking scan loop.
+  event_sender: Sender<HardwareCommunicationManagerEvent>,
+  /// Token from the device manager. Each scan runs on a child of it.
+  parent_token: CancellationToken,
   cancellation_token: Option<CancellationToken>,
 }
 
 impl<T: TimedRetryCommunicationManagerImpl> TimedRetryCommunicationManager<T> {
-  pub fn new(comm_manager: T) -> Self {
+  pub fn new(
+    comm_manager: T,
+    event_sender: Sender<HardwareCommunicationManagerEvent>,
+    parent_token: CancellationToken,
+  ) -> Self {
     Self {
       comm_manager: Arc::new(comm_manager),
+      event_sender,
+      parent_token,
       cancellation_token: None,
     }
   }
@@ -152,19 +337,28 @@ impl<T: TimedRetryCommunicationManagerImpl> HardwareCommunicationManager
       return future::ready(Ok(())).boxed();
     }
     let comm_manager = self.comm_manager.clone();
-    let token = CancellationToken::new();
+    let token = self.parent_token.child_token();
     let child_token = token.child_token();
     self.cancellation_token = Some(token);
     let duration = self.comm_manager.rescan_wait_duration();
+    let rescan_notifier = self.comm_manager.rescan_notifier();
+    let event_sender = self.event_sender.clone();
     async move {
-      async_manager::spawn(async move {
+      spawn_manager_task(event_sender, async move {
         loop {
           if let Err(err) = comm_manager.scan().await {
             error!("Timed Device Communication Manager Failure: {}", err);
             break;
           }
+          let rescan_requested = async {
+            match &rescan_notifier {
+              Some(notifier) => notifier.notified().await,
+              None => future::pending().await,
+            }
+          };
           tokio::select! {
             _ = sleep(duration) => continue,
+            _ = rescan_requested => continue,
             _ = child_token.cancelled() => break,
           }
         }
@@ -0,0 +1,23 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2024 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! OSC input for VR controller and tracker motion.
+//!
+//! Listens for [OSC](https://opensourcecontrol.org) messages over UDP and turns each tracker that
+//! sends to `<prefix><tracker id>` (`/buttplug/tracker/<tracker id>` by default) into a device with
+//! a single velocity sensor. Messages carry either a single speed or the x/y/z components of a
+//! velocity vector, in meters per second. Combined with
+//! [DeviceLink](crate::server::device::DeviceLink), this allows motion to drive toys without
+//! each game mod implementing its own mapping.
+//!
+//! OSC has no notion of connections, so trackers stay around until the comm manager is shut down.
+
+mod osc_comm_manager;
+mod osc_hardware;
+mod osc_message;
+
+pub use osc_comm_manager::{OscCommunicationManager, OscCommunicationManagerBuilder};
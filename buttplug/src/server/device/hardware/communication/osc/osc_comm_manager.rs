@@ -0,0 +1,194 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2024 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+use super::{osc_hardware::OscHardwareConnector, osc_message::parse_osc_packet};
+use crate::{
+  core::ButtplugResultFuture,
+  server::device::hardware::communication::{
+    spawn_manager_task,
+    HardwareCommunicationManager,
+    HardwareCommunicationManagerBuilder,
+    HardwareCommunicationManagerEvent,
+  },
+};
+use futures::FutureExt;
+use std::collections::HashMap;
+use tokio::{
+  net::UdpSocket,
+  sync::{broadcast, mpsc::Sender},
+};
+use tokio_util::sync::CancellationToken;
+
+// Largest payload a UDP datagram can carry.
+const MAX_PACKET_SIZE: usize = 65507;
+
+#[derive(Clone)]
+pub struct OscCommunicationManagerBuilder {
+  listen_on_all_interfaces: bool,
+  server_port: u16,
+  address_prefix: String,
+}
+
+impl Default for OscCommunicationManagerBuilder {
+  fn default() -> Self {
+    Self {
+      listen_on_all_interfaces: false,
+      server_port: 54818,
+      address_prefix: "/buttplug/tracker/".to_owned(),
+    }
+  }
+}
+
+impl OscCommunicationManagerBuilder {
+  pub fn listen_on_all_interfaces(mut self, should_listen: bool) -> Self {
+    self.listen_on_all_interfaces = should_listen;
+    self
+  }
+
+  pub fn server_port(mut self, port: u16) -> Self {
+    self.server_port = port;
+    self
+  }
+
+  /// OSC address prefix trackers send to. Anything after the prefix is used as the tracker id.
+  pub fn address_prefix(mut self, prefix: &str) -> Self {
+    self.address_prefix = prefix.to_owned();
+    self
+  }
+}
+
+impl HardwareCommunicationManagerBuilder for OscCommunicationManagerBuilder {
+  fn finish(
+    &mut self,
+    sender: Sender<HardwareCommunicationManagerEvent>,
+    cancellation_token: CancellationToken,
+  ) -> Box<dyn HardwareCommunicationManager> {
+    Box::new(OscCommunicationManager::new(
+      sender,
+      self.server_port,
+      self.listen_on_all_interfaces,
+      self.address_prefix.clone(),
+      cancellation_token,
+    ))
+  }
+}
+
+pub struct OscCommunicationManager {
+  server_cancellation_token: CancellationToken,
+}
+
+impl OscCommunicationManager {
+  fn new(
+    sender: Sender<HardwareCommunicationManagerEvent>,
+    port: u16,
+    listen_on_all_interfaces: bool,
+    address_prefix: String,
+    cancellation_token: CancellationToken,
+  ) -> Self {
+    let server_cancellation_token = cancellation_token.child_token();
+    let child_token = server_cancellation_token.child_token();
+    spawn_manager_task(sender.clone(), async move {
+      let base_addr = if listen_on_all_interfaces {
+        "0.0.0.0"
+      } else {
+        "127.0.0.1"
+      };
+      let addr = format!("{}:{}", base_addr, port);
+      let socket = match UdpSocket::bind(&addr).await {
+        Ok(socket) => socket,
+        Err(err) => {
+          error!("Cannot bind OSC comm manager to {}: {:?}.", addr, err);
+          return;
+        }
+      };
+      debug!("OSC comm manager listening on: {}", addr);
+      let mut trackers: HashMap<String, broadcast::Sender<f32>> = HashMap::new();
+      let mut buf = vec![0u8; MAX_PACKET_SIZE];
+      loop {
+        let received = tokio::select! {
+          received = socket.recv_from(&mut buf) => received,
+          _ = child_token.cancelled() => {
+            info!("Task token cancelled, assuming OSC comm manager shutdown.");
+            break;
+          }
+        };
+        let length = match received {
+          Ok((length, _)) => length,
+          Err(err) => {
+            // On some platforms, ICMP errors from earlier sends show up here. They don't affect
+            // receiving, so just keep going.
+            debug!("Error receiving OSC packet: {:?}", err);
+            continue;
+          }
+        };
+        for message in parse_osc_packet(&buf[..length]) {
+          let Some(tracker_id) = message.address().strip_prefix(&address_prefix) else {
+            continue;
+          };
+          if tracker_id.is_empty() || message.args().is_empty() {
+            continue;
+          }
+          // A single argument is a speed, more are treated as velocity vector components.
+          let velocity = message
+            .args()
+            .iter()
+            .map(|component| component * component)
+            .sum::<f64>()
+            .sqrt() as f32;
+          if !trackers.contains_key(tracker_id) {
+            let (velocity_sender, _) = broadcast::channel(256);
+            let address = format!("osc:{}", tracker_id);
+            info!("Found new OSC tracker {}", tracker_id);
+            if sender
+              .send(HardwareCommunicationManagerEvent::DeviceFound {
+                name: format!("OSC Tracker {}", tracker_id),
+                address: address.clone(),
+                creator: Box::new(OscHardwareConnector::new(&address, velocity_sender.clone())),
+              })
+              .await
+              .is_err()
+            {
+              error!("Device manager disappeared, exiting.");
+              return;
+            }
+            trackers.insert(tracker_id.to_owned(), velocity_sender);
+          }
+          // If the tracker isn't subscribed, no one cares about the reading.
+          let _ = trackers[tracker_id].send(velocity);
+        }
+      }
+    });
+    Self {
+      server_cancellation_token,
+    }
+  }
+}
+
+impl HardwareCommunicationManager for OscCommunicationManager {
+  fn name(&self) -> &'static str {
+    "OscCommunicationManager"
+  }
+
+  // Trackers show up as soon as they send something, whether or not we're scanning.
+  fn start_scanning(&mut self) -> ButtplugResultFuture {
+    async move { Ok(()) }.boxed()
+  }
+
+  fn stop_scanning(&mut self) -> ButtplugResultFuture {
+    async move { Ok(()) }.boxed()
+  }
+
+  fn can_scan(&self) -> bool {
+    true
+  }
+}
+
+impl Drop for OscCommunicationManager {
+  fn drop(&mut self) {
+    self.server_cancellation_token.cancel();
+  }
+}
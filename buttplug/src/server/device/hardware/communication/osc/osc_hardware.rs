@@ -0,0 +1,200 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2024 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+use crate::{
+  core::{errors::ButtplugDeviceError, message::Endpoint},
+  server::device::{
+    configuration::{OscSpecifier, ProtocolCommunicationSpecifier},
+    hardware::{
+      GenericHardwareSpecializer,
+      Hardware,
+      HardwareConnector,
+      HardwareEvent,
+      HardwareInternal,
+      HardwareReadCmd,
+      HardwareReading,
+      HardwareSpecializer,
+      HardwareSubscribeCmd,
+      HardwareUnsubscribeCmd,
+      HardwareWriteCmd,
+    },
+  },
+  util::async_manager,
+};
+use async_trait::async_trait;
+use futures::{
+  future::{self, BoxFuture},
+  FutureExt,
+};
+use std::sync::{
+  atomic::{AtomicBool, Ordering},
+  Arc,
+};
+use tokio::sync::{broadcast, Mutex};
+use tokio_util::sync::CancellationToken;
+
+#[derive(Debug)]
+pub struct OscHardwareConnector {
+  address: String,
+  velocity_sender: broadcast::Sender<f32>,
+}
+
+impl OscHardwareConnector {
+  pub fn new(address: &str, velocity_sender: broadcast::Sender<f32>) -> Self {
+    Self {
+      address: address.to_owned(),
+      velocity_sender,
+    }
+  }
+}
+
+#[async_trait]
+impl HardwareConnector for OscHardwareConnector {
+  fn specifier(&self) -> ProtocolCommunicationSpecifier {
+    ProtocolCommunicationSpecifier::Osc(OscSpecifier::default())
+  }
+
+  async fn connect(&mut self) -> Result<Box<dyn HardwareSpecializer>, ButtplugDeviceError> {
+    let hardware_internal = OscHardware::new(&self.address, self.velocity_sender.clone());
+    let hardware = Hardware::new(
+      "OSC Tracker",
+      &self.address,
+      &[Endpoint::Rx],
+      Box::new(hardware_internal),
+    );
+    Ok(Box::new(GenericHardwareSpecializer::new(hardware)))
+  }
+}
+
+/// A tracker fed by the OSC comm manager. While subscribed, each velocity reading (in meters per
+/// second) is sent out as a notification on [Endpoint::Rx], as the big endian bytes of an f32.
+pub struct OscHardware {
+  address: String,
+  connected: Arc<AtomicBool>,
+  subscribe_token: Arc<Mutex<Option<CancellationToken>>>,
+  velocity_sender: broadcast::Sender<f32>,
+  device_event_sender: broadcast::Sender<HardwareEvent>,
+}
+
+impl OscHardware {
+  fn new(address: &str, velocity_sender: broadcast::Sender<f32>) -> Self {
+    let (device_event_sender, _) = broadcast::channel(256);
+    Self {
+      address: address.to_owned(),
+      connected: Arc::new(AtomicBool::new(true)),
+      subscribe_token: Arc::new(Mutex::new(None)),
+      velocity_sender,
+      device_event_sender,
+    }
+  }
+}
+
+impl HardwareInternal for OscHardware {
+  fn event_stream(&self) -> broadcast::Receiver<HardwareEvent> {
+    self.device_event_sender.subscribe()
+  }
+
+  fn disconnect(&self) -> BoxFuture<'static, Result<(), ButtplugDeviceError>> {
+    let connected = self.connected.clone();
+    let subscribe_token = self.subscribe_token.clone();
+    async move {
+      connected.store(false, Ordering::SeqCst);
+      if let Some(token) = subscribe_token.lock().await.take() {
+        token.cancel();
+      }
+      Ok(())
+    }
+    .boxed()
+  }
+
+  fn is_connected(&self) -> BoxFuture<'static, bool> {
+    future::ready(self.connected.load(Ordering::SeqCst)).boxed()
+  }
+
+  fn read_value(
+    &self,
+    _msg: &HardwareReadCmd,
+  ) -> BoxFuture<'static, Result<HardwareReading, ButtplugDeviceError>> {
+    future::ready(Err(ButtplugDeviceError::UnhandledCommand(
+      "OSC Hardware does not support read".to_owned(),
+    )))
+    .boxed()
+  }
+
+  fn write_value(
+    &self,
+    _msg: &HardwareWriteCmd,
+  ) -> BoxFuture<'static, Result<(), ButtplugDeviceError>> {
+    future::ready(Err(ButtplugDeviceError::UnhandledCommand(
+      "OSC Hardware does not support write".to_owned(),
+    )))
+    .boxed()
+  }
+
+  fn subscribe(
+    &self,
+    msg: &HardwareSubscribeCmd,
+  ) -> BoxFuture<'static, Result<(), ButtplugDeviceError>> {
+    if msg.endpoint != Endpoint::Rx {
+      return future::ready(Err(ButtplugDeviceError::InvalidEndpoint(msg.endpoint))).boxed();
+    }
+    let mut velocity_receiver = self.velocity_sender.subscribe();
+    let event_sender = self.device_event_sender.clone();
+    let address = self.address.clone();
+    let subscribe_token = self.subscribe_token.clone();
+    async move {
+      let mut current_token = subscribe_token.lock().await;
+      if current_token.is_some() {
+        return Ok(());
+      }
+      let token = CancellationToken::new();
+      *current_token = Some(token.clone());
+      async_manager::spawn(async move {
+        loop {
+          let velocity = tokio::select! {
+            velocity = velocity_receiver.recv() => velocity,
+            _ = token.cancelled() => break,
+          };
+          match velocity {
+            Ok(velocity) => {
+              // We don't care if no one is listening.
+              let _ = event_sender.send(HardwareEvent::Notification(
+                address.clone(),
+                Endpoint::Rx,
+                velocity.to_be_bytes().to_vec(),
+              ));
+            }
+            // Readings are only useful when fresh, so skipping old ones is fine.
+            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => break,
+          }
+        }
+        debug!("Exiting OSC tracker listener task for {}", address);
+      });
+      Ok(())
+    }
+    .boxed()
+  }
+
+  fn unsubscribe(
+    &self,
+    _msg: &HardwareUnsubscribeCmd,
+  ) -> BoxFuture<'static, Result<(), ButtplugDeviceError>> {
+    let subscribe_token = self.subscribe_token.clone();
+    async move {
+      if let Some(token) = subscribe_token.lock().await.take() {
+        token.cancel();
+        Ok(())
+      } else {
+        Err(ButtplugDeviceError::DeviceCommunicationError(
+          "Device not subscribed.".to_owned(),
+        ))
+      }
+    }
+    .boxed()
+  }
+}
@@ -0,0 +1,137 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2024 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Just enough of OSC 1.0 to pull numeric arguments out of messages and bundles.
+
+use getset::Getters;
+
+const BUNDLE_TAG: &[u8] = b"#bundle\0";
+// Bundle tag plus the 8 byte time tag, which we ignore since readings are used as they arrive.
+const BUNDLE_HEADER_LENGTH: usize = 16;
+
+#[derive(Debug, Clone, PartialEq, Getters)]
+#[getset(get = "pub(super)")]
+pub(super) struct OscMessage {
+  address: String,
+  /// Numeric arguments, in order. Non-numeric arguments are skipped.
+  args: Vec<f64>,
+}
+
+/// Parse a UDP packet into the messages it contains. Malformed messages are dropped.
+pub(super) fn parse_osc_packet(data: &[u8]) -> Vec<OscMessage> {
+  let mut messages = vec![];
+  parse_osc_element(data, &mut messages);
+  messages
+}
+
+fn parse_osc_element(data: &[u8], messages: &mut Vec<OscMessage>) {
+  if !data.starts_with(BUNDLE_TAG) {
+    if let Some(message) = parse_osc_message(data) {
+      messages.push(message);
+    }
+    return;
+  }
+  let mut pos = BUNDLE_HEADER_LENGTH;
+  while let Some(size) = read_u32(data, &mut pos) {
+    let Some(element) = data.get(pos..pos + size as usize) else {
+      return;
+    };
+    parse_osc_element(element, messages);
+    pos += size as usize;
+  }
+}
+
+fn parse_osc_message(data: &[u8]) -> Option<OscMessage> {
+  let mut pos = 0;
+  let address = read_string(data, &mut pos)?;
+  if !address.starts_with('/') {
+    return None;
+  }
+  // Type tags are optional in OSC 1.0, but everything that sends numbers includes them.
+  let type_tags = read_string(data, &mut pos)?;
+  let mut args = vec![];
+  for tag in type_tags.strip_prefix(',')?.chars() {
+    match tag {
+      'f' => args.push(f32::from_bits(read_u32(data, &mut pos)?) as f64),
+      'i' => args.push(read_u32(data, &mut pos)? as i32 as f64),
+      'd' => args.push(f64::from_bits(read_u64(data, &mut pos)?)),
+      'h' => args.push(read_u64(data, &mut pos)? as i64 as f64),
+      's' | 'S' => {
+        read_string(data, &mut pos)?;
+      }
+      // Boolean, nil and impulse tags carry no data.
+      'T' | 'F' | 'N' | 'I' => {}
+      _ => {
+        debug!(
+          "Unhandled OSC type tag {}, dropping message to {}.",
+          tag, address
+        );
+        return None;
+      }
+    }
+  }
+  Some(OscMessage { address, args })
+}
+
+fn read_string(data: &[u8], pos: &mut usize) -> Option<String> {
+  let remaining = data.get(*pos..)?;
+  let length = remaining.iter().position(|b| *b == 0)?;
+  let string = std::str::from_utf8(&remaining[..length]).ok()?.to_owned();
+  // Strings are null terminated, then padded out to a multiple of 4 bytes.
+  *pos += (length + 4) & !3;
+  Some(string)
+}
+
+fn read_u32(data: &[u8], pos: &mut usize) -> Option<u32> {
+  let bytes = data.get(*pos..*pos + 4)?;
+  *pos += 4;
+  Some(u32::from_be_bytes(bytes.try_into().ok()?))
+}
+
+fn read_u64(data: &[u8], pos: &mut usize) -> Option<u64> {
+  let bytes = data.get(*pos..*pos + 8)?;
+  *pos += 8;
+  Some(u64::from_be_bytes(bytes.try_into().ok()?))
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn padded(string: &str) -> Vec<u8> {
+    let mut bytes = string.as_bytes().to_vec();
+    bytes.resize((bytes.len() + 4) & !3, 0);
+    bytes
+  }
+
+  #[test]
+  fn test_parse_osc_message_and_bundle() {
+    let mut message = padded("/buttplug/tracker/left");
+    message.extend(padded(",fis"));
+    message.extend(1.5f32.to_be_bytes());
+    message.extend((-2i32).to_be_bytes());
+    message.extend(padded("hand"));
+    let parsed = parse_osc_packet(&message);
+    assert_eq!(parsed.len(), 1);
+    assert_eq!(parsed[0].address(), "/buttplug/tracker/left");
+    assert_eq!(parsed[0].args(), &vec![1.5, -2.0]);
+
+    let mut bundle = BUNDLE_TAG.to_vec();
+    bundle.extend([0, 0, 0, 0, 0, 0, 0, 1]);
+    for _ in 0..2 {
+      bundle.extend((message.len() as u32).to_be_bytes());
+      bundle.extend(&message);
+    }
+    assert_eq!(parse_osc_packet(&bundle).len(), 2);
+
+    // Truncated arguments and unknown tags drop the message.
+    assert!(parse_osc_packet(&message[..message.len() - 12]).is_empty());
+    let mut unknown = padded("/a");
+    unknown.extend(padded(",b"));
+    assert!(parse_osc_packet(&unknown).is_empty());
+  }
+}
@@ -0,0 +1,21 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2024 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! PlayStation controllers (DualShock 4 and DualSense) over HID.
+//!
+//! Gives platforms without XInput a gamepad option, as hidapi is available everywhere we run. Both
+//! controllers get their two rumble motors, and the DualSense also gets resistance on each
+//! trigger. Controllers work over USB or bluetooth, which take differently framed output reports,
+//! so the hardware here takes care of building reports and the protocol only deals in levels.
+
+mod playstation_comm_manager;
+mod playstation_hardware;
+
+pub use playstation_comm_manager::{
+  PlayStationCommunicationManager,
+  PlayStationCommunicationManagerBuilder,
+};
@@ -0,0 +1,143 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2024 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+use super::playstation_hardware::{
+  PlayStationControllerModel,
+  PlayStationHardwareConnector,
+  SONY_VENDOR_ID,
+};
+use crate::{
+  core::errors::ButtplugDeviceError,
+  server::device::hardware::communication::{
+    HardwareCommunicationManager,
+    HardwareCommunicationManagerBuilder,
+    HardwareCommunicationManagerEvent,
+    TimedRetryCommunicationManager,
+    TimedRetryCommunicationManagerImpl,
+  },
+};
+use async_trait::async_trait;
+use hidapi::HidApi;
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+#[derive(Default, Clone)]
+pub struct PlayStationCommunicationManagerBuilder {}
+
+impl HardwareCommunicationManagerBuilder for PlayStationCommunicationManagerBuilder {
+  fn finish(
+    &mut self,
+    sender: mpsc::Sender<HardwareCommunicationManagerEvent>,
+    cancellation_token: CancellationToken,
+  ) -> Box<dyn HardwareCommunicationManager> {
+    Box::new(TimedRetryCommunicationManager::new(
+      PlayStationCommunicationManager::new(sender.clone()),
+      sender,
+      cancellation_token,
+    ))
+  }
+}
+
+pub struct PlayStationCommunicationManager {
+  sender: mpsc::Sender<HardwareCommunicationManagerEvent>,
+  /// None if hidapi couldn't start, in which case there's nothing to scan with.
+  hidapi: Option<Arc<Mutex<HidApi>>>,
+}
+
+impl PlayStationCommunicationManager {
+  fn new(sender: mpsc::Sender<HardwareCommunicationManagerEvent>) -> Self {
+    let hidapi = match HidApi::new() {
+      Ok(api) => Some(Arc::new(Mutex::new(api))),
+      Err(e) => {
+        warn!(
+          "Cannot start hidapi, PlayStation controllers unavailable: {:?}",
+          e
+        );
+        None
+      }
+    };
+    Self { sender, hidapi }
+  }
+}
+
+#[async_trait]
+impl TimedRetryCommunicationManagerImpl for PlayStationCommunicationManager {
+  fn name(&self) -> &'static str {
+    "PlayStationCommunicationManager"
+  }
+
+  async fn scan(&self) -> Result<(), ButtplugDeviceError> {
+    let Some(hidapi) = &self.hidapi else {
+      return Ok(());
+    };
+    trace!("PlayStation manager scanning for devices");
+    let found: Vec<_> = {
+      let mut api = hidapi
+        .lock()
+        .expect("HID API lock should never be poisoned.");
+      // Only look at Sony hardware, there's no need to go through everything else on the system.
+      if let Err(e) = api
+        .reset_devices()
+        .and_then(|_| api.add_devices(SONY_VENDOR_ID, 0))
+      {
+        warn!("Cannot list PlayStation controllers: {:?}", e);
+        return Ok(());
+      }
+      let mut found = vec![];
+      for device_info in api.device_list() {
+        let Some(model) = PlayStationControllerModel::from_product_id(device_info.product_id())
+        else {
+          continue;
+        };
+        // Bluetooth pads report their MAC address as their serial number, which follows them
+        // between ports and reboots. USB pads may not have one, so fall back to the device path.
+        let id = match device_info.serial_number() {
+          Some(serial) if !serial.is_empty() => serial.to_owned(),
+          _ => device_info.path().to_string_lossy().into_owned(),
+        };
+        let address = format!("playstation-{}", id);
+        // Some platforms list a controller once per HID collection.
+        if found
+          .iter()
+          .any(|(_, found_address, _)| *found_address == address)
+        {
+          continue;
+        }
+        found.push((device_info.clone(), address, model));
+      }
+      found
+    };
+    for (device_info, address, model) in found {
+      debug!("PlayStation manager found {} {}", model.name(), address);
+      let creator = Box::new(PlayStationHardwareConnector::new(
+        hidapi.clone(),
+        &device_info,
+        model,
+        &address,
+      ));
+      if self
+        .sender
+        .send(HardwareCommunicationManagerEvent::DeviceFound {
+          name: model.name().to_owned(),
+          address,
+          creator,
+        })
+        .await
+        .is_err()
+      {
+        error!("Error sending device found message from PlayStation manager.");
+        break;
+      }
+    }
+    Ok(())
+  }
+
+  fn can_scan(&self) -> bool {
+    self.hidapi.is_some()
+  }
+}
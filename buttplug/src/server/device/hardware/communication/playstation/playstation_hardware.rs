@@ -0,0 +1,432 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2024 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+use crate::{
+  core::{errors::ButtplugDeviceError, message::Endpoint},
+  server::device::{
+    configuration::{PlayStationSpecifier, ProtocolCommunicationSpecifier},
+    hardware::{
+      GenericHardwareSpecializer,
+      Hardware,
+      HardwareConnector,
+      HardwareEvent,
+      HardwareInternal,
+      HardwareReadCmd,
+      HardwareReading,
+      HardwareSpecializer,
+      HardwareSubscribeCmd,
+      HardwareUnsubscribeCmd,
+      HardwareWriteCmd,
+    },
+  },
+};
+use async_trait::async_trait;
+use futures::future::{self, BoxFuture, FutureExt};
+use hidapi::{BusType, DeviceInfo, HidApi, HidDevice};
+use std::{
+  fmt::{self, Debug},
+  sync::{
+    atomic::{AtomicU8, Ordering},
+    Arc,
+    Mutex,
+  },
+};
+use tokio::sync::broadcast;
+use tokio_util::sync::CancellationToken;
+
+pub const SONY_VENDOR_ID: u16 = 0x054c;
+
+/// Controllers we know how to drive. Each takes output reports in its own layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlayStationControllerModel {
+  DualShock4,
+  /// Includes the DualSense Edge, which takes the same reports.
+  DualSense,
+}
+
+impl PlayStationControllerModel {
+  pub fn from_product_id(product_id: u16) -> Option<Self> {
+    match product_id {
+      // Original and second revision pads, and the wireless adapter.
+      0x05c4 | 0x09cc | 0x0ba0 => Some(Self::DualShock4),
+      0x0ce6 | 0x0df2 => Some(Self::DualSense),
+      _ => None,
+    }
+  }
+
+  /// Hardware name, which is also the identifier the device configuration matches on.
+  pub fn name(&self) -> &'static str {
+    match self {
+      Self::DualShock4 => "DualShock 4",
+      Self::DualSense => "DualSense",
+    }
+  }
+}
+
+const BLUETOOTH_REPORT_LENGTH: usize = 78;
+
+const DUALSHOCK4_USB_REPORT_ID: u8 = 0x05;
+const DUALSHOCK4_USB_REPORT_LENGTH: usize = 32;
+const DUALSHOCK4_BLUETOOTH_REPORT_ID: u8 = 0x11;
+// Send the report to the HID side of the controller (rather than audio), and check its CRC.
+const DUALSHOCK4_BLUETOOTH_HW_CONTROL: u8 = 0xc0;
+const DUALSHOCK4_VALID_FLAG_MOTOR: u8 = 0x01;
+
+const DUALSENSE_USB_REPORT_ID: u8 = 0x02;
+const DUALSENSE_USB_REPORT_LENGTH: usize = 48;
+const DUALSENSE_BLUETOOTH_REPORT_ID: u8 = 0x31;
+const DUALSENSE_BLUETOOTH_TAG: u8 = 0x10;
+const DUALSENSE_VALID_FLAG_COMPATIBLE_VIBRATION: u8 = 0x01;
+const DUALSENSE_VALID_FLAG_HAPTICS_SELECT: u8 = 0x02;
+const DUALSENSE_VALID_FLAG_RIGHT_TRIGGER: u8 = 0x04;
+const DUALSENSE_VALID_FLAG_LEFT_TRIGGER: u8 = 0x08;
+const DUALSENSE_TRIGGER_EFFECT_OFF: u8 = 0x05;
+const DUALSENSE_TRIGGER_EFFECT_CONTINUOUS_RESISTANCE: u8 = 0x01;
+const DUALSENSE_TRIGGER_EFFECT_LENGTH: usize = 11;
+
+/// CRC32 the controllers expect at the end of bluetooth reports, which also covers the 0xa2
+/// transaction header bluetooth HID puts in front of output reports.
+fn bluetooth_crc32(report: &[u8]) -> u32 {
+  let mut crc = 0xffffffffu32;
+  for byte in [0xa2].iter().chain(report) {
+    crc ^= *byte as u32;
+    for _ in 0..8 {
+      crc = if crc & 1 == 1 {
+        (crc >> 1) ^ 0xedb88320
+      } else {
+        crc >> 1
+      };
+    }
+  }
+  !crc
+}
+
+fn seal_bluetooth_report(report: &mut [u8]) {
+  let crc_start = report.len() - 4;
+  let crc = bluetooth_crc32(&report[..crc_start]);
+  report[crc_start..].copy_from_slice(&crc.to_le_bytes());
+}
+
+fn dualsense_trigger_effect(resistance: u8) -> [u8; DUALSENSE_TRIGGER_EFFECT_LENGTH] {
+  let mut effect = [0u8; DUALSENSE_TRIGGER_EFFECT_LENGTH];
+  if resistance == 0 {
+    effect[0] = DUALSENSE_TRIGGER_EFFECT_OFF;
+  } else {
+    // Resist over the whole pull, starting from the top.
+    effect[0] = DUALSENSE_TRIGGER_EFFECT_CONTINUOUS_RESISTANCE;
+    effect[2] = resistance;
+  }
+  effect
+}
+
+/// Build the output report for a write from the protocol, which is one byte per actuator: left
+/// motor, right motor, then (on the DualSense) left and right trigger resistance. Anything not
+/// given is off.
+fn output_report(
+  model: PlayStationControllerModel,
+  bluetooth: bool,
+  sequence: u8,
+  levels: &[u8],
+) -> Vec<u8> {
+  let level = |index: usize| levels.get(index).copied().unwrap_or(0);
+  match model {
+    PlayStationControllerModel::DualShock4 => {
+      let (mut report, common) = if bluetooth {
+        let mut report = vec![0u8; BLUETOOTH_REPORT_LENGTH];
+        report[0] = DUALSHOCK4_BLUETOOTH_REPORT_ID;
+        report[1] = DUALSHOCK4_BLUETOOTH_HW_CONTROL;
+        (report, 3)
+      } else {
+        let mut report = vec![0u8; DUALSHOCK4_USB_REPORT_LENGTH];
+        report[0] = DUALSHOCK4_USB_REPORT_ID;
+        (report, 1)
+      };
+      report[common] = DUALSHOCK4_VALID_FLAG_MOTOR;
+      // The right motor is the small, high frequency one, and comes first.
+      report[common + 3] = level(1);
+      report[common + 4] = level(0);
+      if bluetooth {
+        seal_bluetooth_report(&mut report);
+      }
+      report
+    }
+    PlayStationControllerModel::DualSense => {
+      let (mut report, common) = if bluetooth {
+        let mut report = vec![0u8; BLUETOOTH_REPORT_LENGTH];
+        report[0] = DUALSENSE_BLUETOOTH_REPORT_ID;
+        report[1] = sequence << 4;
+        report[2] = DUALSENSE_BLUETOOTH_TAG;
+        (report, 3)
+      } else {
+        let mut report = vec![0u8; DUALSENSE_USB_REPORT_LENGTH];
+        report[0] = DUALSENSE_USB_REPORT_ID;
+        (report, 1)
+      };
+      report[common] = DUALSENSE_VALID_FLAG_COMPATIBLE_VIBRATION
+        | DUALSENSE_VALID_FLAG_HAPTICS_SELECT
+        | DUALSENSE_VALID_FLAG_RIGHT_TRIGGER
+        | DUALSENSE_VALID_FLAG_LEFT_TRIGGER;
+      report[common + 2] = level(1);
+      report[common + 3] = level(0);
+      let right_trigger = common + 10;
+      let left_trigger = right_trigger + DUALSENSE_TRIGGER_EFFECT_LENGTH;
+      report[right_trigger..left_trigger].copy_from_slice(&dualsense_trigger_effect(level(3)));
+      report[left_trigger..left_trigger + DUALSENSE_TRIGGER_EFFECT_LENGTH]
+        .copy_from_slice(&dualsense_trigger_effect(level(2)));
+      if bluetooth {
+        seal_bluetooth_report(&mut report);
+      }
+      report
+    }
+  }
+}
+
+pub struct PlayStationHardwareConnector {
+  hidapi: Arc<Mutex<HidApi>>,
+  device_info: DeviceInfo,
+  model: PlayStationControllerModel,
+  address: String,
+}
+
+impl PlayStationHardwareConnector {
+  pub fn new(
+    hidapi: Arc<Mutex<HidApi>>,
+    device_info: &DeviceInfo,
+    model: PlayStationControllerModel,
+    address: &str,
+  ) -> Self {
+    Self {
+      hidapi,
+      device_info: device_info.clone(),
+      model,
+      address: address.to_owned(),
+    }
+  }
+}
+
+impl Debug for PlayStationHardwareConnector {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("PlayStationHardwareConnector")
+      .field("model", &self.model)
+      .field("address", &self.address)
+      .field("bus", &self.device_info.bus_type())
+      .finish()
+  }
+}
+
+#[async_trait]
+impl HardwareConnector for PlayStationHardwareConnector {
+  fn specifier(&self) -> ProtocolCommunicationSpecifier {
+    ProtocolCommunicationSpecifier::PlayStation(PlayStationSpecifier::default())
+  }
+
+  async fn connect(&mut self) -> Result<Box<dyn HardwareSpecializer>, ButtplugDeviceError> {
+    // One handle to write with, and one for a thread that reads so we hear about disconnects, as
+    // hidapi devices can't be shared between threads.
+    let (writer, reader) = {
+      let api = self
+        .hidapi
+        .lock()
+        .expect("HID API lock should never be poisoned.");
+      let open = || {
+        api.open_path(self.device_info.path()).map_err(|e| {
+          ButtplugDeviceError::DeviceConnectionError(format!(
+            "Cannot open {} controller: {:?}",
+            self.model.name(),
+            e
+          ))
+        })
+      };
+      (open()?, open()?)
+    };
+    let bluetooth = matches!(self.device_info.bus_type(), BusType::Bluetooth);
+    let hardware_internal =
+      PlayStationHardware::new(writer, reader, self.model, bluetooth, &self.address);
+    let hardware = Hardware::new(
+      self.model.name(),
+      &self.address,
+      &[Endpoint::Tx],
+      Box::new(hardware_internal),
+    );
+    Ok(Box::new(GenericHardwareSpecializer::new(hardware)))
+  }
+}
+
+pub struct PlayStationHardware {
+  device: Arc<Mutex<HidDevice>>,
+  model: PlayStationControllerModel,
+  bluetooth: bool,
+  /// Bluetooth DualSense reports carry a 4 bit sequence number.
+  sequence: AtomicU8,
+  event_sender: broadcast::Sender<HardwareEvent>,
+  reader_token: CancellationToken,
+}
+
+impl PlayStationHardware {
+  fn new(
+    device: HidDevice,
+    reader: HidDevice,
+    model: PlayStationControllerModel,
+    bluetooth: bool,
+    address: &str,
+  ) -> Self {
+    let (event_sender, _) = broadcast::channel(256);
+    let reader_token = CancellationToken::new();
+    {
+      let sender = event_sender.clone();
+      let token = reader_token.clone();
+      let address = address.to_owned();
+      std::thread::spawn(move || {
+        // Input reports are no use to us, but reads start failing as soon as the controller goes
+        // away, which is sooner than waiting for a write to fail.
+        let mut buf = [0u8; 128];
+        while !token.is_cancelled() {
+          if let Err(e) = reader.read_timeout(&mut buf, 100) {
+            info!("PlayStation controller {} went away: {:?}", address, e);
+            // No one may be listening yet, in which case there's no one to tell.
+            let _ = sender.send(HardwareEvent::Disconnected(address.clone()));
+            break;
+          }
+        }
+      });
+    }
+    Self {
+      device: Arc::new(Mutex::new(device)),
+      model,
+      bluetooth,
+      sequence: AtomicU8::new(0),
+      event_sender,
+      reader_token,
+    }
+  }
+}
+
+impl HardwareInternal for PlayStationHardware {
+  fn event_stream(&self) -> broadcast::Receiver<HardwareEvent> {
+    self.event_sender.subscribe()
+  }
+
+  fn disconnect(&self) -> BoxFuture<'static, Result<(), ButtplugDeviceError>> {
+    self.reader_token.cancel();
+    future::ready(Ok(())).boxed()
+  }
+
+  fn read_value(
+    &self,
+    _msg: &HardwareReadCmd,
+  ) -> BoxFuture<'static, Result<HardwareReading, ButtplugDeviceError>> {
+    future::ready(Err(ButtplugDeviceError::UnhandledCommand(
+      "PlayStation controller hardware does not support read".to_owned(),
+    )))
+    .boxed()
+  }
+
+  fn write_value(
+    &self,
+    msg: &HardwareWriteCmd,
+  ) -> BoxFuture<'static, Result<(), ButtplugDeviceError>> {
+    let sequence = self.sequence.fetch_add(1, Ordering::Relaxed) & 0x0f;
+    let report = output_report(self.model, self.bluetooth, sequence, &msg.data);
+    let result = self
+      .device
+      .lock()
+      .expect("HID device lock should never be poisoned.")
+      .write(&report)
+      .map(|_| ())
+      .map_err(|e| {
+        ButtplugDeviceError::DeviceCommunicationError(format!(
+          "Cannot write to PlayStation controller: {:?}",
+          e
+        ))
+      });
+    future::ready(result).boxed()
+  }
+
+  fn subscribe(
+    &self,
+    _msg: &HardwareSubscribeCmd,
+  ) -> BoxFuture<'static, Result<(), ButtplugDeviceError>> {
+    future::ready(Err(ButtplugDeviceError::UnhandledCommand(
+      "PlayStation controller hardware does not support subscribe".to_owned(),
+    )))
+    .boxed()
+  }
+
+  fn unsubscribe(
+    &self,
+    _msg: &HardwareUnsubscribeCmd,
+  ) -> BoxFuture<'static, Result<(), ButtplugDeviceError>> {
+    future::ready(Err(ButtplugDeviceError::UnhandledCommand(
+      "PlayStation controller hardware does not support unsubscribe".to_owned(),
+    )))
+    .boxed()
+  }
+}
+
+impl Drop for PlayStationHardware {
+  fn drop(&mut self) {
+    self.reader_token.cancel();
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{bluetooth_crc32, output_report, PlayStationControllerModel};
+
+  #[test]
+  fn test_dualshock4_reports() {
+    let usb = output_report(
+      PlayStationControllerModel::DualShock4,
+      false,
+      0,
+      &[200, 100],
+    );
+    assert_eq!(usb.len(), 32);
+    assert_eq!(&usb[..6], &[0x05, 0x01, 0x00, 0x00, 100, 200]);
+    assert!(usb[6..].iter().all(|byte| *byte == 0));
+
+    let bluetooth = output_report(PlayStationControllerModel::DualShock4, true, 0, &[200, 100]);
+    assert_eq!(bluetooth.len(), 78);
+    assert_eq!(
+      &bluetooth[..8],
+      &[0x11, 0xc0, 0x00, 0x01, 0x00, 0x00, 100, 200]
+    );
+    assert_eq!(
+      bluetooth[74..],
+      bluetooth_crc32(&bluetooth[..74]).to_le_bytes()
+    );
+  }
+
+  #[test]
+  fn test_dualsense_reports() {
+    let usb = output_report(
+      PlayStationControllerModel::DualSense,
+      false,
+      0,
+      &[200, 100, 0, 50],
+    );
+    assert_eq!(usb.len(), 48);
+    assert_eq!(&usb[..5], &[0x02, 0x0f, 0x00, 100, 200]);
+    // Right trigger effect comes first.
+    assert_eq!(&usb[11..14], &[0x01, 0x00, 50]);
+    assert_eq!(usb[22], 0x05);
+
+    let bluetooth = output_report(PlayStationControllerModel::DualSense, true, 3, &[200, 100]);
+    assert_eq!(bluetooth.len(), 78);
+    assert_eq!(&bluetooth[..7], &[0x31, 0x30, 0x10, 0x0f, 0x00, 100, 200]);
+    assert_eq!(
+      bluetooth[74..],
+      bluetooth_crc32(&bluetooth[..74]).to_le_bytes()
+    );
+  }
+
+  #[test]
+  fn test_bluetooth_crc32() {
+    // CRC32 of 0xa2 followed by "123456789".
+    assert_eq!(bluetooth_crc32(b"123456789"), 0x63da9f12);
+  }
+}
@@ -0,0 +1,103 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2024 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Serial port enumeration diagnostics.
+//!
+//! When a serial port or dongle can't be opened, the error coming back from the OS is rarely
+//! something a user can act on. On Linux it's almost always a missing `dialout`/`uucp` group
+//! membership, and everywhere else it's usually another app holding the port. These helpers sort
+//! open failures into [HardwarePortIssue] categories so managers can report them through
+//! [HardwareCommunicationManagerStatus::Inaccessible](super::HardwareCommunicationManagerStatus).
+
+use super::{HardwarePortDiagnostic, HardwarePortIssue};
+use serialport::{available_ports, ErrorKind, SerialPortInfo, SerialPortType};
+use std::{io, time::Duration};
+
+/// Sort a serial port open error into an actionable category.
+pub fn categorize_serial_error(err: &serialport::Error) -> HardwarePortIssue {
+  match err.kind() {
+    ErrorKind::Io(io::ErrorKind::PermissionDenied) => HardwarePortIssue::PermissionDenied,
+    // serialport reports ports locked by other processes (EBUSY/flock on posix, access denied on
+    // windows) as NoDevice.
+    ErrorKind::NoDevice => HardwarePortIssue::PortBusy,
+    _ => HardwarePortIssue::Other(err.to_string()),
+  }
+}
+
+/// Human readable description of the hardware behind a port.
+pub fn port_description(info: &SerialPortInfo) -> String {
+  match &info.port_type {
+    SerialPortType::UsbPort(usb) => format!(
+      "USB {:04x}:{:04x}{}",
+      usb.vid,
+      usb.pid,
+      usb
+        .product
+        .as_ref()
+        .map(|product| format!(" ({})", product))
+        .unwrap_or_default()
+    ),
+    SerialPortType::BluetoothPort => "Bluetooth".to_owned(),
+    SerialPortType::PciPort => "PCI".to_owned(),
+    SerialPortType::Unknown => "Unknown".to_owned(),
+  }
+}
+
+/// Enumerate serial ports, and try opening each one `filter` accepts, reporting whether it opened
+/// and why not if it didn't.
+///
+/// This blocks while ports are opened, and opening a port may reset whatever is attached to it, so
+/// it should only be run when trying to figure out why something isn't working. Ports this process
+/// already has open will be reported as busy.
+pub fn diagnose_serial_ports<F>(filter: F) -> Result<Vec<HardwarePortDiagnostic>, serialport::Error>
+where
+  F: Fn(&SerialPortInfo) -> bool,
+{
+  let diagnostics = available_ports()?
+    .iter()
+    .filter(|info| filter(info))
+    .map(|info| {
+      let issue = serialport::new(&info.port_name, 9600)
+        .timeout(Duration::from_millis(100))
+        .open()
+        .err()
+        .map(|err| categorize_serial_error(&err));
+      HardwarePortDiagnostic::new(&info.port_name, &port_description(info), issue)
+    })
+    .collect();
+  Ok(diagnostics)
+}
+
+#[cfg(test)]
+mod test {
+  use super::categorize_serial_error;
+  use crate::server::device::hardware::communication::HardwarePortIssue;
+  use serialport::{Error, ErrorKind};
+  use std::io;
+
+  #[test]
+  fn test_serial_error_categories() {
+    assert_eq!(
+      categorize_serial_error(&Error::new(
+        ErrorKind::Io(io::ErrorKind::PermissionDenied),
+        "Permission denied"
+      )),
+      HardwarePortIssue::PermissionDenied
+    );
+    assert_eq!(
+      categorize_serial_error(&Error::new(
+        ErrorKind::NoDevice,
+        "Device or resource busy"
+      )),
+      HardwarePortIssue::PortBusy
+    );
+    assert_eq!(
+      categorize_serial_error(&Error::new(ErrorKind::InvalidInput, "Bad baud rate")),
+      HardwarePortIssue::Other("Bad baud rate".to_owned())
+    );
+  }
+}
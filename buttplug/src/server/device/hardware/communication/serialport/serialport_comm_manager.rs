@@ -5,7 +5,10 @@
 // Licensed under the BSD 3-Clause license. See LICENSE file in the project root
 // for full license information.
 
-use std::time::Duration;
+use std::{
+  sync::atomic::{AtomicBool, Ordering},
+  time::Duration,
+};
 
 use super::SerialPortHardwareConnector;
 use crate::{
@@ -14,6 +17,7 @@ use crate::{
     HardwareCommunicationManager,
     HardwareCommunicationManagerBuilder,
     HardwareCommunicationManagerEvent,
+    HardwareCommunicationManagerStatus,
     TimedRetryCommunicationManager,
     TimedRetryCommunicationManagerImpl,
   },
@@ -21,6 +25,7 @@ use crate::{
 use async_trait::async_trait;
 use serialport::available_ports;
 use tokio::sync::mpsc::Sender;
+use tokio_util::sync::CancellationToken;
 
 #[derive(Default, Clone)]
 pub struct SerialPortCommunicationManagerBuilder {}
@@ -29,28 +34,60 @@ impl HardwareCommunicationManagerBuilder for SerialPortCommunicationManagerBuild
   fn finish(
     &mut self,
     sender: Sender<HardwareCommunicationManagerEvent>,
+    cancellation_token: CancellationToken,
   ) -> Box<dyn HardwareCommunicationManager> {
     Box::new(TimedRetryCommunicationManager::new(
-      SerialPortCommunicationManager::new(sender),
+      SerialPortCommunicationManager::new(sender.clone()),
+      sender,
+      cancellation_token,
     ))
   }
 }
 
+const SERIAL_PORT_COMM_MANAGER_NAME: &str = "SerialPortCommunicationManager";
+
 pub struct SerialPortCommunicationManager {
   sender: Sender<HardwareCommunicationManagerEvent>,
+  // Tracks whether the last port enumeration succeeded, so status is only sent on changes.
+  enumeration_available: AtomicBool,
 }
 
 impl SerialPortCommunicationManager {
   fn new(sender: Sender<HardwareCommunicationManagerEvent>) -> Self {
     trace!("Serial port created.");
-    Self { sender }
+    Self {
+      sender,
+      enumeration_available: AtomicBool::new(true),
+    }
+  }
+
+  async fn update_status(&self, available: bool) {
+    if self.enumeration_available.swap(available, Ordering::SeqCst) == available {
+      return;
+    }
+    let status = if available {
+      HardwareCommunicationManagerStatus::Available
+    } else {
+      HardwareCommunicationManagerStatus::Unavailable
+    };
+    if self
+      .sender
+      .send(HardwareCommunicationManagerEvent::StatusChanged {
+        name: SERIAL_PORT_COMM_MANAGER_NAME,
+        status,
+      })
+      .await
+      .is_err()
+    {
+      debug!("Device manager disappeared, dropping serial status update.");
+    }
   }
 }
 
 #[async_trait]
 impl TimedRetryCommunicationManagerImpl for SerialPortCommunicationManager {
   fn name(&self) -> &'static str {
-    "SerialPortCommunicationManager"
+    SERIAL_PORT_COMM_MANAGER_NAME
   }
 
   fn rescan_wait_duration(&self) -> Duration {
@@ -62,6 +99,7 @@ impl TimedRetryCommunicationManagerImpl for SerialPortCommunicationManager {
     match available_ports() {
       Ok(ports) => {
         debug!("Got {} serial ports back", ports.len());
+        self.update_status(true).await;
         for p in ports {
           trace!(
             "Sending serial port {:?} for possible device connection.",
@@ -82,8 +120,13 @@ impl TimedRetryCommunicationManagerImpl for SerialPortCommunicationManager {
           }
         }
       }
-      Err(_) => {
-        trace!("No serial ports found");
+      Err(e) => {
+        // Enumeration failing outright usually means we can't see the serial subsystem at all
+        // (sandboxing, missing udev, etc...) rather than there being no ports.
+        if self.enumeration_available.load(Ordering::SeqCst) {
+          warn!("Cannot enumerate serial ports: {}", e);
+        }
+        self.update_status(false).await;
       }
     }
     Ok(())
@@ -7,7 +7,10 @@
 
 use crate::{
   core::{errors::ButtplugDeviceError, message::Endpoint},
-  server::device::hardware::communication::HardwareSpecificError,
+  server::device::hardware::communication::{
+    serial_diagnostics::categorize_serial_error,
+    HardwareSpecificError,
+  },
   server::device::{
     configuration::{ProtocolCommunicationSpecifier, SerialSpecifier},
     hardware::{
@@ -226,7 +229,11 @@ impl SerialPortHardware {
       .await
       .expect("This will always be a Some value, we're just blocking for bringup")
       .map_err(|e| {
-        ButtplugDeviceError::DeviceSpecificError(HardwareSpecificError::SerialError(e.to_string()))
+        ButtplugDeviceError::DeviceSpecificError(HardwareSpecificError::SerialError(format!(
+          "Cannot open {}: {}",
+          port_info.port_name,
+          categorize_serial_error(&e)
+        )))
       })?;
     debug!("Serial port received from thread.");
     let (writer_sender, writer_receiver) = mpsc::channel(256);
@@ -9,11 +9,11 @@ use super::websocket_server_hardware::WebsocketServerHardwareConnector;
 use crate::{
   core::ButtplugResultFuture,
   server::device::hardware::communication::{
+    spawn_manager_task,
     HardwareCommunicationManager,
     HardwareCommunicationManagerBuilder,
     HardwareCommunicationManagerEvent,
   },
-  util::async_manager,
 };
 use futures::{FutureExt, StreamExt};
 use getset::{CopyGetters, Getters};
@@ -63,11 +63,13 @@ impl HardwareCommunicationManagerBuilder for WebsocketServerDeviceCommunicationM
   fn finish(
     &mut self,
     sender: Sender<HardwareCommunicationManagerEvent>,
+    cancellation_token: CancellationToken,
   ) -> Box<dyn HardwareCommunicationManager> {
     Box::new(WebsocketServerDeviceCommunicationManager::new(
       sender,
       self.server_port,
       self.listen_on_all_interfaces,
+      cancellation_token,
     ))
   }
 }
@@ -81,11 +83,12 @@ impl WebsocketServerDeviceCommunicationManager {
     sender: Sender<HardwareCommunicationManagerEvent>,
     port: u16,
     listen_on_all_interfaces: bool,
+    cancellation_token: CancellationToken,
   ) -> Self {
     trace!("Websocket server port created.");
-    let server_cancellation_token = CancellationToken::new();
+    let server_cancellation_token = cancellation_token.child_token();
     let child_token = server_cancellation_token.child_token();
-    async_manager::spawn(async move {
+    spawn_manager_task(sender.clone(), async move {
       let base_addr = if listen_on_all_interfaces {
         "0.0.0.0"
       } else {
@@ -5,8 +5,10 @@
 // Licensed under the BSD 3-Clause license. See LICENSE file in the project root
 // for full license information.
 
+mod xinput_connectivity_tracker;
 mod xinput_device_comm_manager;
 mod xinput_hardware;
+mod xinput_hotplug;
 
 pub use xinput_device_comm_manager::{
   XInputDeviceCommunicationManager,
@@ -0,0 +1,330 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2024 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+use super::{
+  xinput_device_comm_manager::XInputControllerIndex,
+  xinput_hardware::create_address,
+  xinput_hotplug::XInputHotplugEvent,
+};
+use crate::{server::device::hardware::HardwareEvent, util::async_manager};
+use futures::future;
+use std::{collections::HashMap, time::Duration};
+use tokio::sync::{
+  broadcast::{self, error::RecvError},
+  mpsc,
+  oneshot,
+};
+use tokio_util::sync::CancellationToken;
+
+pub(super) const DEFAULT_CONNECTIVITY_CHECK_INTERVAL: Duration = Duration::from_millis(500);
+// With hotplug notifications, polling only catches anything they missed, so it never needs to
+// happen more often than this.
+const HOTPLUG_FALLBACK_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Debug)]
+enum TrackerCommand {
+  Add(XInputControllerIndex, broadcast::Sender<HardwareEvent>),
+  Remove(XInputControllerIndex, broadcast::Sender<HardwareEvent>),
+  Query(XInputControllerIndex, oneshot::Sender<bool>),
+}
+
+/// Watches connected XInput gamepads and lets their hardware know when they go away.
+///
+/// All gamepads are checked from a single task that owns the tracked set, so adding the same
+/// controller twice can't end up with two loops racing to report its disconnect. Checks run as
+/// soon as the OS reports a gamepad going away, with polling as a fallback for when it doesn't.
+#[derive(Clone, Debug)]
+pub struct XInputConnectivityTracker {
+  command_sender: mpsc::Sender<TrackerCommand>,
+}
+
+impl XInputConnectivityTracker {
+  /// Create a tracker, which checks gamepads whenever `hotplug` reports a change, or polls them
+  /// every `check_interval` if there are no hotplug notifications to go by.
+  pub fn new(
+    check_interval: Duration,
+    hotplug: Option<broadcast::Receiver<XInputHotplugEvent>>,
+    cancellation_token: CancellationToken,
+  ) -> Self {
+    let handle = rusty_xinput::XInputHandle::load_default()
+      .expect("Always loads in windows, this shouldn't run elsewhere.");
+    Self::new_with_probe(
+      move |index| handle.get_state(index as u32).is_ok(),
+      check_interval,
+      hotplug,
+      cancellation_token,
+    )
+  }
+
+  fn new_with_probe(
+    probe: impl Fn(XInputControllerIndex) -> bool + Send + 'static,
+    interval: Duration,
+    hotplug: Option<broadcast::Receiver<XInputHotplugEvent>>,
+    cancellation_token: CancellationToken,
+  ) -> Self {
+    let (command_sender, command_receiver) = mpsc::channel(256);
+    async_manager::spawn(async move {
+      run_tracker(
+        probe,
+        interval,
+        hotplug,
+        command_receiver,
+        cancellation_token,
+      )
+      .await;
+    });
+    Self { command_sender }
+  }
+
+  /// Start tracking a gamepad. If the gamepad was already being tracked for other hardware, that
+  /// hardware is told it disconnected, since we must have missed the controller dropping out
+  /// between checks.
+  pub async fn add(&self, index: XInputControllerIndex, sender: broadcast::Sender<HardwareEvent>) {
+    if self
+      .command_sender
+      .send(TrackerCommand::Add(index, sender))
+      .await
+      .is_err()
+    {
+      warn!(
+        "XInput connectivity tracker has shut down, cannot track gamepad {}.",
+        index
+      );
+    }
+  }
+
+  /// Stop tracking a gamepad, as long as it's still being tracked for the hardware that owns
+  /// `sender`.
+  pub async fn remove(
+    &self,
+    index: XInputControllerIndex,
+    sender: broadcast::Sender<HardwareEvent>,
+  ) {
+    // If the tracker is already gone, there's nothing left to remove from.
+    let _ = self
+      .command_sender
+      .send(TrackerCommand::Remove(index, sender))
+      .await;
+  }
+
+  /// Whether a gamepad is currently being tracked as connected.
+  pub async fn is_tracked(&self, index: XInputControllerIndex) -> bool {
+    let (sender, receiver) = oneshot::channel();
+    if self
+      .command_sender
+      .send(TrackerCommand::Query(index, sender))
+      .await
+      .is_err()
+    {
+      return false;
+    }
+    receiver.await.unwrap_or(false)
+  }
+}
+
+async fn run_tracker(
+  probe: impl Fn(XInputControllerIndex) -> bool,
+  interval: Duration,
+  mut hotplug: Option<broadcast::Receiver<XInputHotplugEvent>>,
+  mut command_receiver: mpsc::Receiver<TrackerCommand>,
+  cancellation_token: CancellationToken,
+) {
+  let mut tracked: HashMap<u8, (XInputControllerIndex, broadcast::Sender<HardwareEvent>)> =
+    HashMap::new();
+  let mut check_interval = tokio::time::interval(if hotplug.is_some() {
+    interval.max(HOTPLUG_FALLBACK_CHECK_INTERVAL)
+  } else {
+    interval
+  });
+  loop {
+    let hotplug_event = async {
+      match &mut hotplug {
+        Some(receiver) => receiver.recv().await,
+        None => future::pending().await,
+      }
+    };
+    tokio::select! {
+      _ = cancellation_token.cancelled() => return,
+      command = command_receiver.recv() => match command {
+        Some(TrackerCommand::Add(index, sender)) => {
+          if let Some((_, old_sender)) = tracked.insert(index as u8, (index, sender.clone())) {
+            if !old_sender.same_channel(&sender) {
+              info!("XInput gamepad {} reconnected before we saw it leave.", index);
+              // If this fails, the old hardware is already gone, which is what we wanted anyways.
+              let _ = old_sender.send(HardwareEvent::Disconnected(create_address(index)));
+            }
+          }
+        }
+        Some(TrackerCommand::Remove(index, sender)) => {
+          if tracked
+            .get(&(index as u8))
+            .is_some_and(|(_, tracked_sender)| tracked_sender.same_channel(&sender))
+          {
+            tracked.remove(&(index as u8));
+          }
+        }
+        Some(TrackerCommand::Query(index, reply)) => {
+          let _ = reply.send(tracked.contains_key(&(index as u8)));
+        }
+        None => return,
+      },
+      event = hotplug_event => match event {
+        // Arrivals are picked up by scanning. A missed removal might have been any gamepad, so
+        // lagging behind is handled like one.
+        Ok(XInputHotplugEvent::Removed) | Err(RecvError::Lagged(_)) => {
+          check_tracked(&probe, &mut tracked);
+        }
+        Ok(XInputHotplugEvent::Arrived) => {}
+        Err(RecvError::Closed) => {
+          debug!("XInput hotplug notifications stopped, polling gamepads instead.");
+          hotplug = None;
+          check_interval = tokio::time::interval(interval);
+        }
+      },
+      _ = check_interval.tick() => check_tracked(&probe, &mut tracked),
+    }
+  }
+}
+
+fn check_tracked(
+  probe: &impl Fn(XInputControllerIndex) -> bool,
+  tracked: &mut HashMap<u8, (XInputControllerIndex, broadcast::Sender<HardwareEvent>)>,
+) {
+  // If we can't get state, assume we have disconnected.
+  tracked.retain(|_, (index, sender)| {
+    if probe(*index) {
+      return true;
+    }
+    info!("XInput gamepad {} has disconnected.", index);
+    // If this fails, we don't care because the hardware is gone anyways.
+    let _ = sender.send(HardwareEvent::Disconnected(create_address(*index)));
+    false
+  });
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+  };
+
+  const TEST_INTERVAL: Duration = Duration::from_millis(10);
+
+  fn tracker_with_gamepad() -> (XInputConnectivityTracker, Arc<AtomicBool>) {
+    let connected = Arc::new(AtomicBool::new(true));
+    let probe_connected = connected.clone();
+    let tracker = XInputConnectivityTracker::new_with_probe(
+      move |_| probe_connected.load(Ordering::SeqCst),
+      TEST_INTERVAL,
+      None,
+      CancellationToken::new(),
+    );
+    (tracker, connected)
+  }
+
+  fn disconnect_count(receiver: &mut broadcast::Receiver<HardwareEvent>) -> usize {
+    let mut count = 0;
+    while let Ok(event) = receiver.try_recv() {
+      assert!(matches!(event, HardwareEvent::Disconnected(_)));
+      count += 1;
+    }
+    count
+  }
+
+  #[tokio::test]
+  async fn test_duplicate_add_reports_disconnect_once() {
+    let (tracker, connected) = tracker_with_gamepad();
+    let (sender, mut receiver) = broadcast::channel(256);
+    let index = XInputControllerIndex::XInputController1;
+    tracker.add(index, sender.clone()).await;
+    tracker.add(index, sender.clone()).await;
+    assert!(tracker.is_tracked(index).await);
+
+    connected.store(false, Ordering::SeqCst);
+    tokio::time::sleep(TEST_INTERVAL * 5).await;
+    assert_eq!(disconnect_count(&mut receiver), 1);
+    assert!(!tracker.is_tracked(index).await);
+  }
+
+  #[tokio::test]
+  async fn test_rapid_reconnect() {
+    let (tracker, connected) = tracker_with_gamepad();
+    let index = XInputControllerIndex::XInputController2;
+    let (first_sender, mut first_receiver) = broadcast::channel(256);
+    let (second_sender, mut second_receiver) = broadcast::channel(256);
+    let (third_sender, mut third_receiver) = broadcast::channel(256);
+
+    // Disconnect seen by the poll, then a reconnect.
+    tracker.add(index, first_sender.clone()).await;
+    connected.store(false, Ordering::SeqCst);
+    tokio::time::sleep(TEST_INTERVAL * 5).await;
+    connected.store(true, Ordering::SeqCst);
+    tracker.add(index, second_sender.clone()).await;
+    assert!(tracker.is_tracked(index).await);
+    assert_eq!(disconnect_count(&mut first_receiver), 1);
+
+    // Disconnect and reconnect between polls still disconnects the old hardware.
+    tracker.add(index, third_sender.clone()).await;
+    assert!(tracker.is_tracked(index).await);
+    assert_eq!(disconnect_count(&mut second_receiver), 1);
+
+    // Old hardware going away late doesn't stop tracking for the new one.
+    tracker.remove(index, second_sender).await;
+    assert!(tracker.is_tracked(index).await);
+    connected.store(false, Ordering::SeqCst);
+    tokio::time::sleep(TEST_INTERVAL * 5).await;
+    assert_eq!(disconnect_count(&mut third_receiver), 1);
+    assert_eq!(disconnect_count(&mut first_receiver), 0);
+    assert_eq!(disconnect_count(&mut second_receiver), 0);
+  }
+
+  #[tokio::test]
+  async fn test_remove_stops_tracking() {
+    let (tracker, connected) = tracker_with_gamepad();
+    let (sender, mut receiver) = broadcast::channel(256);
+    let index = XInputControllerIndex::XInputController3;
+    tracker.add(index, sender.clone()).await;
+    tracker.remove(index, sender).await;
+    assert!(!tracker.is_tracked(index).await);
+    connected.store(false, Ordering::SeqCst);
+    tokio::time::sleep(TEST_INTERVAL * 5).await;
+    assert_eq!(disconnect_count(&mut receiver), 0);
+  }
+
+  #[tokio::test]
+  async fn test_hotplug_removal_checks_immediately() {
+    let connected = Arc::new(AtomicBool::new(true));
+    let probe_connected = connected.clone();
+    let (hotplug_sender, hotplug_receiver) = broadcast::channel(16);
+    // Polling alone would take far longer than the test waits.
+    let tracker = XInputConnectivityTracker::new_with_probe(
+      move |_| probe_connected.load(Ordering::SeqCst),
+      Duration::from_secs(60),
+      Some(hotplug_receiver),
+      CancellationToken::new(),
+    );
+    let (sender, mut receiver) = broadcast::channel(256);
+    let index = XInputControllerIndex::XInputController4;
+    tracker.add(index, sender).await;
+
+    connected.store(false, Ordering::SeqCst);
+    hotplug_sender
+      .send(XInputHotplugEvent::Arrived)
+      .expect("Test, assuming infallible.");
+    tokio::time::sleep(TEST_INTERVAL * 5).await;
+    assert!(tracker.is_tracked(index).await);
+
+    hotplug_sender
+      .send(XInputHotplugEvent::Removed)
+      .expect("Test, assuming infallible.");
+    tokio::time::sleep(TEST_INTERVAL * 5).await;
+    assert_eq!(disconnect_count(&mut receiver), 1);
+    assert!(!tracker.is_tracked(index).await);
+  }
+}
@@ -5,7 +5,11 @@
 // Licensed under the BSD 3-Clause license. See LICENSE file in the project root
 // for full license information.
 
-use super::xinput_hardware::XInputHardwareConnector;
+use super::{
+  xinput_connectivity_tracker::{XInputConnectivityTracker, DEFAULT_CONNECTIVITY_CHECK_INTERVAL},
+  xinput_hardware::XInputHardwareConnector,
+  xinput_hotplug::{XInputHotplugEvent, XInputHotplugListener},
+};
 use crate::{
   core::errors::ButtplugDeviceError,
   server::device::hardware::communication::{
@@ -15,11 +19,13 @@ use crate::{
     TimedRetryCommunicationManager,
     TimedRetryCommunicationManagerImpl,
   },
+  util::async_manager,
 };
 use async_trait::async_trait;
 use rusty_xinput::XInputHandle;
-use std::string::ToString;
-use tokio::sync::mpsc;
+use std::{string::ToString, sync::Arc, time::Duration};
+use tokio::sync::{broadcast::error::RecvError, mpsc, Notify};
+use tokio_util::sync::CancellationToken;
 
 // 1-index this because we use it elsewhere for showing which controller is which.
 #[derive(Debug, Display, Clone, Copy)]
@@ -31,16 +37,55 @@ pub enum XInputControllerIndex {
   XInputController4 = 3,
 }
 
-#[derive(Default, Clone)]
-pub struct XInputDeviceCommunicationManagerBuilder {}
+const DEFAULT_SCAN_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Clone)]
+pub struct XInputDeviceCommunicationManagerBuilder {
+  scan_interval: Duration,
+  connectivity_check_interval: Duration,
+}
+
+impl Default for XInputDeviceCommunicationManagerBuilder {
+  fn default() -> Self {
+    Self {
+      scan_interval: DEFAULT_SCAN_INTERVAL,
+      connectivity_check_interval: DEFAULT_CONNECTIVITY_CHECK_INTERVAL,
+    }
+  }
+}
+
+impl XInputDeviceCommunicationManagerBuilder {
+  /// How long to wait between scans for new gamepads. Gamepads the OS reports as plugged in are
+  /// scanned for right away either way.
+  pub fn scan_interval(mut self, interval: Duration) -> Self {
+    self.scan_interval = interval;
+    self
+  }
+
+  /// How often to check that connected gamepads are still there, if the OS doesn't report them
+  /// going away. With OS reports, this is only a fallback, and never runs more than every few
+  /// seconds.
+  pub fn connectivity_check_interval(mut self, interval: Duration) -> Self {
+    self.connectivity_check_interval = interval;
+    self
+  }
+}
 
 impl HardwareCommunicationManagerBuilder for XInputDeviceCommunicationManagerBuilder {
   fn finish(
     &mut self,
     sender: mpsc::Sender<HardwareCommunicationManagerEvent>,
+    cancellation_token: CancellationToken,
   ) -> Box<dyn HardwareCommunicationManager> {
     Box::new(TimedRetryCommunicationManager::new(
-      XInputDeviceCommunicationManager::new(sender),
+      XInputDeviceCommunicationManager::new(
+        sender.clone(),
+        self.scan_interval,
+        self.connectivity_check_interval,
+        cancellation_token.clone(),
+      ),
+      sender,
+      cancellation_token,
     ))
   }
 }
@@ -48,14 +93,56 @@ impl HardwareCommunicationManagerBuilder for XInputDeviceCommunicationManagerBui
 pub struct XInputDeviceCommunicationManager {
   sender: mpsc::Sender<HardwareCommunicationManagerEvent>,
   handle: XInputHandle,
+  tracker: XInputConnectivityTracker,
+  scan_interval: Duration,
+  // Kept around so notifications keep coming for as long as the manager exists.
+  _hotplug: Option<XInputHotplugListener>,
+  rescan_notifier: Option<Arc<Notify>>,
 }
 
 impl XInputDeviceCommunicationManager {
-  fn new(sender: mpsc::Sender<HardwareCommunicationManagerEvent>) -> Self {
+  fn new(
+    sender: mpsc::Sender<HardwareCommunicationManagerEvent>,
+    scan_interval: Duration,
+    connectivity_check_interval: Duration,
+    cancellation_token: CancellationToken,
+  ) -> Self {
+    let hotplug = XInputHotplugListener::new();
+    let rescan_notifier = hotplug.as_ref().map(|listener| {
+      let notifier = Arc::new(Notify::new());
+      let mut receiver = listener.subscribe();
+      let arrival_notifier = notifier.clone();
+      let token = cancellation_token.clone();
+      async_manager::spawn(async move {
+        loop {
+          let event = tokio::select! {
+            event = receiver.recv() => event,
+            _ = token.cancelled() => break,
+          };
+          match event {
+            // A missed notification might have been an arrival.
+            Ok(XInputHotplugEvent::Arrived) | Err(RecvError::Lagged(_)) => {
+              arrival_notifier.notify_one()
+            }
+            Ok(XInputHotplugEvent::Removed) => {}
+            Err(RecvError::Closed) => break,
+          }
+        }
+      });
+      notifier
+    });
     Self {
       sender,
+      tracker: XInputConnectivityTracker::new(
+        connectivity_check_interval,
+        hotplug.as_ref().map(|listener| listener.subscribe()),
+        cancellation_token,
+      ),
       handle: rusty_xinput::XInputHandle::load_default()
         .expect("Always loads in windows, this shouldn't run elsewhere."),
+      scan_interval,
+      _hotplug: hotplug,
+      rescan_notifier,
     }
   }
 }
@@ -78,7 +165,7 @@ impl TimedRetryCommunicationManagerImpl for XInputDeviceCommunicationManager {
         Ok(_) => {
           let index = *i as u32;
           debug!("XInput manager found device {}", index);
-          let device_creator = Box::new(XInputHardwareConnector::new(*i));
+          let device_creator = Box::new(XInputHardwareConnector::new(*i, self.tracker.clone()));
 
           if self
             .sender
@@ -106,4 +193,12 @@ impl TimedRetryCommunicationManagerImpl for XInputDeviceCommunicationManager {
   fn can_scan(&self) -> bool {
     true
   }
+
+  fn rescan_wait_duration(&self) -> Duration {
+    self.scan_interval
+  }
+
+  fn rescan_notifier(&self) -> Option<Arc<Notify>> {
+    self.rescan_notifier.clone()
+  }
 }
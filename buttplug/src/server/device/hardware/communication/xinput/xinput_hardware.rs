@@ -5,7 +5,10 @@
 // Licensed under the BSD 3-Clause license. See LICENSE file in the project root
 // for full license information.
 
-use super::xinput_device_comm_manager::XInputControllerIndex;
+use super::{
+  xinput_connectivity_tracker::XInputConnectivityTracker,
+  xinput_device_comm_manager::XInputControllerIndex,
+};
 use crate::{
   core::{errors::ButtplugDeviceError, message::Endpoint},
   server::device::hardware::communication::HardwareSpecificError,
@@ -28,50 +31,34 @@ use crate::{
   util::async_manager,
 };
 use async_trait::async_trait;
-use byteorder::{LittleEndian, ReadBytesExt};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 use futures::future::{self, BoxFuture, FutureExt};
-use rusty_xinput::{XInputHandle, XInputUsageError};
+use rusty_xinput::{XInputHandle, XInputState, XInputUsageError};
 use std::{
   fmt::{self, Debug},
   io::Cursor,
+  sync::{Arc, Mutex},
   time::Duration,
 };
 use tokio::sync::broadcast;
 use tokio_util::sync::CancellationToken;
 
+/// How often gamepad input is checked while it's subscribed to. Pads report at 125hz over USB.
+const INPUT_POLL_INTERVAL: Duration = Duration::from_millis(8);
+
 pub(super) fn create_address(index: XInputControllerIndex) -> String {
   index.to_string()
 }
 
-async fn check_gamepad_connectivity(
-  index: XInputControllerIndex,
-  sender: broadcast::Sender<HardwareEvent>,
-  cancellation_token: CancellationToken,
-) {
-  let handle = rusty_xinput::XInputHandle::load_default()
-    .expect("Always loads in windows, this shouldn't run elsewhere.");
-  loop {
-    // If we can't get state, assume we have disconnected.
-    if handle.get_state(index as u32).is_err() {
-      info!("XInput gamepad {} has disconnected.", index);
-      // If this fails, we don't care because we're exiting anyways.
-      let _ = sender.send(HardwareEvent::Disconnected(create_address(index)));
-      return;
-    }
-    tokio::select! {
-      _ = cancellation_token.cancelled() => return,
-      _ = tokio::time::sleep(Duration::from_millis(500)) => continue
-    }
-  }
-}
-
 pub struct XInputHardwareConnector {
   index: XInputControllerIndex,
+  /// Comm manager connectivity tracker, which watches for the hardware disconnecting.
+  tracker: XInputConnectivityTracker,
 }
 
 impl XInputHardwareConnector {
-  pub fn new(index: XInputControllerIndex) -> Self {
-    Self { index }
+  pub fn new(index: XInputControllerIndex, tracker: XInputConnectivityTracker) -> Self {
+    Self { index, tracker }
   }
 }
 
@@ -91,7 +78,11 @@ impl HardwareConnector for XInputHardwareConnector {
 
   async fn connect(&mut self) -> Result<Box<dyn HardwareSpecializer>, ButtplugDeviceError> {
     debug!("Emitting a new xbox device impl.");
-    let hardware_internal = XInputHardware::new(self.index);
+    let hardware_internal = XInputHardware::new(self.index, self.tracker.clone());
+    self
+      .tracker
+      .add(self.index, hardware_internal.event_sender.clone())
+      .await;
     let hardware = Hardware::new(
       &self.index.to_string(),
       &create_address(self.index),
@@ -107,23 +98,88 @@ pub struct XInputHardware {
   handle: XInputHandle,
   index: XInputControllerIndex,
   event_sender: broadcast::Sender<HardwareEvent>,
-  cancellation_token: CancellationToken,
+  tracker: XInputConnectivityTracker,
+  /// Stops input polling, while input is subscribed to.
+  input_polling: Arc<Mutex<Option<CancellationToken>>>,
+}
+
+/// Pack gamepad input the way the protocol expects it: the button bits, left and right trigger, then
+/// left and right stick X and Y, as XINPUT_GAMEPAD lays them out.
+fn pack_input_state(state: &XInputState) -> Vec<u8> {
+  let mut data = vec![];
+  let (left_x, left_y) = state.left_stick_raw();
+  let (right_x, right_y) = state.right_stick_raw();
+  data
+    .write_u16::<LittleEndian>(state.raw.Gamepad.wButtons)
+    .expect("Writing to a vec is infallible");
+  data.push(state.left_trigger());
+  data.push(state.right_trigger());
+  for axis in [left_x, left_y, right_x, right_y] {
+    data
+      .write_i16::<LittleEndian>(axis)
+      .expect("Writing to a vec is infallible");
+  }
+  data
 }
 
 impl XInputHardware {
-  pub fn new(index: XInputControllerIndex) -> Self {
+  pub fn new(index: XInputControllerIndex, tracker: XInputConnectivityTracker) -> Self {
     let (device_event_sender, _) = broadcast::channel(256);
-    let token = CancellationToken::new();
-    let child = token.child_token();
-    let sender = device_event_sender.clone();
-    async_manager::spawn(async move {
-      check_gamepad_connectivity(index, sender, child).await;
-    });
     Self {
       handle: rusty_xinput::XInputHandle::load_default().expect("The DLL should load as long as we're on windows, and we don't get here if we're not on windows."),
       index,
       event_sender: device_event_sender,
-      cancellation_token: token,
+      tracker,
+      input_polling: Arc::new(Mutex::new(None)),
+    }
+  }
+
+  /// XInput has no input events, so poll the pad and send on every state that's new.
+  fn start_input_polling(&self) {
+    let mut input_polling = self.input_polling.lock().expect("Lock is never poisoned");
+    if input_polling.is_some() {
+      return;
+    }
+    let token = CancellationToken::new();
+    *input_polling = Some(token.clone());
+    let handle = self.handle.clone();
+    let index = self.index;
+    let sender = self.event_sender.clone();
+    async_manager::spawn(async move {
+      let mut poll_interval = tokio::time::interval(INPUT_POLL_INTERVAL);
+      let mut last_packet = None;
+      loop {
+        tokio::select! {
+          _ = token.cancelled() => break,
+          _ = poll_interval.tick() => {}
+        }
+        // The connectivity tracker takes care of noticing when the pad is gone.
+        let Ok(state) = handle.get_state(index as u32) else {
+          continue;
+        };
+        if last_packet == Some(state.raw.dwPacketNumber) {
+          continue;
+        }
+        last_packet = Some(state.raw.dwPacketNumber);
+        // If no one is listening, the device is going away anyways.
+        let _ = sender.send(HardwareEvent::Notification(
+          create_address(index),
+          Endpoint::Rx,
+          pack_input_state(&state),
+        ));
+      }
+      debug!("Stopped polling XInput gamepad {} for input.", index);
+    });
+  }
+
+  fn stop_input_polling(&self) {
+    if let Some(token) = self
+      .input_polling
+      .lock()
+      .expect("Lock is never poisoned")
+      .take()
+    {
+      token.cancel();
     }
   }
 }
@@ -149,9 +205,11 @@ impl HardwareInternal for XInputHardware {
         .map_err(|e| {
           ButtplugDeviceError::from(HardwareSpecificError::XInputError(format!("{:?}", e)))
         })?;
+      // The protocol needs the type to tell wired pads, whose level means nothing, from ones
+      // running on batteries.
       Ok(HardwareReading::new(
         Endpoint::Rx,
-        &[battery.battery_level.0],
+        &[battery.battery_type.0, battery.battery_level.0],
       ))
     }
     .boxed()
@@ -183,27 +241,35 @@ impl HardwareInternal for XInputHardware {
 
   fn subscribe(
     &self,
-    _msg: &HardwareSubscribeCmd,
+    msg: &HardwareSubscribeCmd,
   ) -> BoxFuture<'static, Result<(), ButtplugDeviceError>> {
-    future::ready(Err(ButtplugDeviceError::UnhandledCommand(
-      "XInput hardware does not support subscribe".to_owned(),
-    )))
-    .boxed()
+    if msg.endpoint != Endpoint::Rx {
+      return future::ready(Err(ButtplugDeviceError::InvalidEndpoint(msg.endpoint))).boxed();
+    }
+    self.start_input_polling();
+    future::ready(Ok(())).boxed()
   }
 
   fn unsubscribe(
     &self,
-    _msg: &HardwareUnsubscribeCmd,
+    msg: &HardwareUnsubscribeCmd,
   ) -> BoxFuture<'static, Result<(), ButtplugDeviceError>> {
-    future::ready(Err(ButtplugDeviceError::UnhandledCommand(
-      "XInput hardware does not support unsubscribe".to_owned(),
-    )))
-    .boxed()
+    if msg.endpoint != Endpoint::Rx {
+      return future::ready(Err(ButtplugDeviceError::InvalidEndpoint(msg.endpoint))).boxed();
+    }
+    self.stop_input_polling();
+    future::ready(Ok(())).boxed()
   }
 }
 
 impl Drop for XInputHardware {
   fn drop(&mut self) {
-    self.cancellation_token.cancel();
+    self.stop_input_polling();
+    let tracker = self.tracker.clone();
+    let index = self.index;
+    let sender = self.event_sender.clone();
+    async_manager::spawn(async move {
+      tracker.remove(index, sender).await;
+    });
   }
 }
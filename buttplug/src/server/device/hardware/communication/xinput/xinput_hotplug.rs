@@ -0,0 +1,127 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2024 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! OS notifications for XInput gamepads coming and going.
+//!
+//! XInput itself can only be polled, but the configuration manager can tell us whenever a device
+//! interface gamepads show up through is added or removed. Notifications don't say which XInput
+//! slot changed, so they're only a hint to go check with XInput right away.
+
+use std::ffi::c_void;
+use tokio::sync::broadcast;
+use windows::{
+  core::GUID,
+  Win32::Devices::DeviceAndDriverInstallation::{
+    CM_Register_Notification,
+    CM_Unregister_Notification,
+    CM_NOTIFY_ACTION,
+    CM_NOTIFY_ACTION_DEVICEINTERFACEARRIVAL,
+    CM_NOTIFY_ACTION_DEVICEINTERFACEREMOVAL,
+    CM_NOTIFY_EVENT_DATA,
+    CM_NOTIFY_FILTER,
+    CM_NOTIFY_FILTER_0,
+    CM_NOTIFY_FILTER_0_2,
+    CM_NOTIFY_FILTER_TYPE_DEVICEINTERFACE,
+    CR_SUCCESS,
+    HCMNOTIFICATION,
+  },
+};
+
+// Wired and wireless adapter gamepads show up as XUSB interfaces, bluetooth ones as HID interfaces.
+const XUSB_INTERFACE_CLASS: GUID = GUID::from_u128(0xec87f1e3_c13b_4100_b5f7_8b84d54260cb);
+const HID_INTERFACE_CLASS: GUID = GUID::from_u128(0x4d1e55b2_f16f_11cf_88cb_001111000030);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum XInputHotplugEvent {
+  Arrived,
+  Removed,
+}
+
+/// Registration for device interface notifications, which go out over a broadcast channel until
+/// this is dropped.
+pub struct XInputHotplugListener {
+  registrations: Vec<HCMNOTIFICATION>,
+  // Boxed so the callbacks can hold on to its address.
+  sender: Box<broadcast::Sender<XInputHotplugEvent>>,
+}
+
+impl XInputHotplugListener {
+  /// Register for notifications, or return None if the OS won't give us any, in which case
+  /// gamepads can only be found by polling.
+  pub fn new() -> Option<Self> {
+    let mut listener = Self {
+      registrations: vec![],
+      sender: Box::new(broadcast::channel(16).0),
+    };
+    for class_guid in [XUSB_INTERFACE_CLASS, HID_INTERFACE_CLASS] {
+      let filter = CM_NOTIFY_FILTER {
+        cbSize: std::mem::size_of::<CM_NOTIFY_FILTER>() as u32,
+        FilterType: CM_NOTIFY_FILTER_TYPE_DEVICEINTERFACE,
+        u: CM_NOTIFY_FILTER_0 {
+          DeviceInterface: CM_NOTIFY_FILTER_0_2 {
+            ClassGuid: class_guid,
+          },
+        },
+        ..Default::default()
+      };
+      let mut registration = HCMNOTIFICATION::default();
+      let context = listener.sender.as_ref() as *const broadcast::Sender<XInputHotplugEvent>;
+      // Safety: the sender outlives the registration, as it's only dropped after unregistering.
+      let result = unsafe {
+        CM_Register_Notification(
+          &filter,
+          Some(context as *const c_void),
+          Some(notification_callback),
+          &mut registration,
+        )
+      };
+      if result != CR_SUCCESS {
+        warn!(
+          "Cannot register for XInput hotplug notifications ({:?}), falling back to polling.",
+          result
+        );
+        // Dropping the listener unregisters whatever did go through.
+        return None;
+      }
+      listener.registrations.push(registration);
+    }
+    Some(listener)
+  }
+
+  pub fn subscribe(&self) -> broadcast::Receiver<XInputHotplugEvent> {
+    self.sender.subscribe()
+  }
+}
+
+impl Drop for XInputHotplugListener {
+  fn drop(&mut self) {
+    // Unregistering waits for running callbacks, so nothing can touch the sender after this.
+    for registration in self.registrations.drain(..) {
+      unsafe {
+        let _ = CM_Unregister_Notification(registration);
+      }
+    }
+  }
+}
+
+unsafe extern "system" fn notification_callback(
+  _notification: HCMNOTIFICATION,
+  context: *const c_void,
+  action: CM_NOTIFY_ACTION,
+  _event_data: *const CM_NOTIFY_EVENT_DATA,
+  _event_data_size: u32,
+) -> u32 {
+  let event = match action {
+    CM_NOTIFY_ACTION_DEVICEINTERFACEARRIVAL => XInputHotplugEvent::Arrived,
+    CM_NOTIFY_ACTION_DEVICEINTERFACEREMOVAL => XInputHotplugEvent::Removed,
+    _ => return 0,
+  };
+  let sender = &*(context as *const broadcast::Sender<XInputHotplugEvent>);
+  // Nobody listening just means nothing is scanning or tracked right now.
+  let _ = sender.send(event);
+  0
+}
@@ -1,6 +1,15 @@
+mod command_gate;
 pub mod communication;
 
-use std::{fmt::Debug, sync::Arc, time::Duration};
+use std::{
+  fmt::Debug,
+  sync::{
+    atomic::{AtomicBool, AtomicU64, Ordering},
+    Arc,
+    Mutex,
+  },
+  time::Duration,
+};
 
 use crate::{
   core::{
@@ -14,10 +23,15 @@ use crate::{
       RawWriteCmdV2,
     },
   },
-  server::device::configuration::ProtocolCommunicationSpecifier,
+  server::device::{
+    configuration::{BluetoothLEConnectionParameters, ProtocolCommunicationSpecifier},
+    protocol::{ProtocolCommandConcurrency, ProtocolWriteRetryPolicy},
+  },
+  util::sleep,
 };
 use async_trait::async_trait;
-use futures::future::BoxFuture;
+use command_gate::HardwareCommandGate;
+use futures::future::{self, BoxFuture};
 use futures_util::FutureExt;
 use getset::{CopyGetters, Getters};
 use instant::Instant;
@@ -77,6 +91,16 @@ pub struct HardwareWriteCmd {
   /// Only used with Bluetooth LE writing. If true, use WriteWithResponse commands when sending data to device.
   #[getset(get_copy = "pub")]
   write_with_response: bool,
+  /// Milliseconds to wait after getting the endpoint before writing, for packets that have to land
+  /// a set time after the one before them.
+  #[getset(get_copy = "pub")]
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  send_after_ms: Option<u32>,
+  /// Milliseconds to keep the endpoint after writing, for devices that need time to settle before
+  /// they'll take another packet.
+  #[getset(get_copy = "pub")]
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  post_write_delay_ms: Option<u32>,
 }
 
 impl HardwareWriteCmd {
@@ -86,17 +110,29 @@ impl HardwareWriteCmd {
       endpoint,
       data,
       write_with_response,
+      send_after_ms: None,
+      post_write_delay_ms: None,
     }
   }
+
+  pub fn with_send_after(mut self, send_after_ms: u32) -> Self {
+    self.send_after_ms = Some(send_after_ms);
+    self
+  }
+
+  pub fn with_post_write_delay(mut self, post_write_delay_ms: u32) -> Self {
+    self.post_write_delay_ms = Some(post_write_delay_ms);
+    self
+  }
 }
 
 impl From<RawWriteCmdV2> for HardwareWriteCmd {
   fn from(msg: RawWriteCmdV2) -> Self {
-    Self {
-      endpoint: msg.endpoint(),
-      data: msg.data().clone(),
-      write_with_response: msg.write_with_response(),
-    }
+    Self::new(
+      msg.endpoint(),
+      msg.data().clone(),
+      msg.write_with_response(),
+    )
   }
 }
 
@@ -160,9 +196,11 @@ impl From<RawUnsubscribeCmdV2> for HardwareUnsubscribeCmd {
 
 /// Enumeration of all possible commands that can be sent to a
 /// [Hardware](crate::device::Hardware).
-#[derive(PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
 pub enum HardwareCommand {
   Write(HardwareWriteCmd),
+  /// Writes that go out in order with nothing else in between, see [Hardware::write_transaction].
+  WriteTransaction(Vec<HardwareWriteCmd>),
   // Read not included here because it needs to be called directly so the response can be handled.
   Subscribe(HardwareSubscribeCmd),
   Unsubscribe(HardwareUnsubscribeCmd),
@@ -233,6 +271,8 @@ pub enum HardwareEvent {
   Notification(String, Endpoint, Vec<u8>),
   /// Device disconnected
   Disconnected(String),
+  /// Device reported the strength of its radio link, in dBm
+  Rssi(String, i32),
 }
 
 /// Hardware implementation and communication portion of a
@@ -249,10 +289,22 @@ pub struct Hardware {
   /// Communication endpoints
   endpoints: Vec<Endpoint>,
   /// Internal implementation details
-  internal_impl: Box<dyn HardwareInternal>,
+  internal_impl: Arc<dyn HardwareInternal>,
+  /// Limits how much access to the hardware can overlap, as set by the protocol.
+  command_gate: Arc<HardwareCommandGate>,
+  /// How failed writes are retried, as set by the protocol.
+  write_retry_policy: Arc<Mutex<ProtocolWriteRetryPolicy>>,
+  /// Number of times a failed write has been retried, for diagnosing flaky connections.
+  write_retries: Arc<AtomicU64>,
+  /// When set, writes are logged and dropped instead of being sent to the hardware.
+  dry_run: Arc<AtomicBool>,
   /// Requires a keepalive signal to be sent by the Server Device class
   #[getset(get_copy = "pub")]
   requires_keepalive: bool,
+  /// Connection parameters negotiated with the device, if the transport has them and the platform
+  /// reports them.
+  #[getset(get_copy = "pub")]
+  connection_parameters: Option<BluetoothLEConnectionParameters>,
   last_write_time: Arc<RwLock<Instant>>,
 }
 
@@ -267,8 +319,13 @@ impl Hardware {
       name: name.to_owned(),
       address: address.to_owned(),
       endpoints: endpoints.into(),
-      internal_impl,
+      internal_impl: internal_impl.into(),
+      command_gate: Arc::new(HardwareCommandGate::default()),
+      write_retry_policy: Arc::new(Mutex::new(ProtocolWriteRetryPolicy::default())),
+      write_retries: Arc::new(AtomicU64::new(0)),
+      dry_run: Arc::new(AtomicBool::new(false)),
       requires_keepalive: false,
+      connection_parameters: None,
       last_write_time: Arc::new(RwLock::new(Instant::now())),
     }
   }
@@ -281,6 +338,44 @@ impl Hardware {
     self.requires_keepalive = true;
   }
 
+  /// Set how much access to the hardware can overlap. Until this is called, accesses on the same
+  /// endpoint run one at a time.
+  pub fn set_command_concurrency(&self, concurrency: ProtocolCommandConcurrency) {
+    self.command_gate.set_concurrency(concurrency);
+  }
+
+  /// Set how failed writes are retried. Until this is called, they aren't.
+  pub fn set_write_retry_policy(&self, policy: ProtocolWriteRetryPolicy) {
+    *self
+      .write_retry_policy
+      .lock()
+      .expect("Write retry policy lock should never be poisoned.") = policy;
+  }
+
+  /// Number of times writes to this hardware have been retried since it connected.
+  pub fn write_retry_count(&self) -> u64 {
+    self.write_retries.load(Ordering::Relaxed)
+  }
+
+  /// Turn dry run mode on or off. While on, writes go through everything up to the point of
+  /// being sent, then are logged and reported as successful without reaching the hardware.
+  /// Subscriptions and reads are unaffected.
+  pub fn set_dry_run(&self, dry_run: bool) {
+    self.dry_run.store(dry_run, Ordering::Relaxed);
+  }
+
+  /// Whether writes are currently being held back from the hardware.
+  pub fn dry_run(&self) -> bool {
+    self.dry_run.load(Ordering::Relaxed)
+  }
+
+  pub fn set_connection_parameters(
+    &mut self,
+    connection_parameters: BluetoothLEConnectionParameters,
+  ) {
+    self.connection_parameters = Some(connection_parameters);
+  }
+
   /// Returns the device name
   pub fn name(&self) -> &str {
     &self.name
@@ -309,12 +404,18 @@ impl Hardware {
     self.internal_impl.disconnect()
   }
 
+  /// Check whether the connection to the device is still alive.
+  pub fn is_connected(&self) -> BoxFuture<'static, bool> {
+    self.internal_impl.is_connected()
+  }
+
   pub fn parse_message(
     &self,
     command: &HardwareCommand,
   ) -> BoxFuture<'static, Result<(), ButtplugDeviceError>> {
     match command {
       HardwareCommand::Write(cmd) => self.write_value(cmd),
+      HardwareCommand::WriteTransaction(cmds) => self.write_transaction(cmds),
       HardwareCommand::Subscribe(cmd) => self.subscribe(cmd),
       HardwareCommand::Unsubscribe(cmd) => self.unsubscribe(cmd),
     }
@@ -325,25 +426,110 @@ impl Hardware {
     &self,
     msg: &HardwareReadCmd,
   ) -> BoxFuture<'static, Result<HardwareReading, ButtplugDeviceError>> {
-    self.internal_impl.read_value(msg)
+    let internal_impl = self.internal_impl.clone();
+    let gate = self.command_gate.clone();
+    let msg = *msg;
+    async move {
+      let _turn = gate.acquire(msg.endpoint()).await;
+      internal_impl.read_value(&msg).await
+    }
+    .boxed()
   }
 
-  /// Write a value to the device
+  /// Write a value to the device, waiting out any delays the command asks for. Delays and retries
+  /// hold the endpoint, so nothing else gets written to it in the meantime.
   pub fn write_value(
     &self,
     msg: &HardwareWriteCmd,
   ) -> BoxFuture<'static, Result<(), ButtplugDeviceError>> {
-    let write_fut = self.internal_impl.write_value(msg);
-    if self.requires_keepalive {
-      let last_write_time = self.last_write_time.clone();
-      async move {
+    let gate = self.command_gate.clone();
+    let endpoint = msg.endpoint();
+    let write = self.ungated_write(msg);
+    async move {
+      let _turn = gate.acquire(endpoint).await;
+      write.await
+    }
+    .boxed()
+  }
+
+  /// Write a set of values to the device in order, for protocols that have to update more than one
+  /// endpoint per command. Every endpoint involved is held until the last write is done, so writes
+  /// from other commands can't land in between. Stops at the first write that fails.
+  pub fn write_transaction(
+    &self,
+    msgs: &[HardwareWriteCmd],
+  ) -> BoxFuture<'static, Result<(), ButtplugDeviceError>> {
+    let gate = self.command_gate.clone();
+    let endpoints: Vec<Endpoint> = msgs.iter().map(|msg| msg.endpoint()).collect();
+    let writes: Vec<_> = msgs.iter().map(|msg| self.ungated_write(msg)).collect();
+    async move {
+      let _turns = gate.acquire_all(&endpoints).await;
+      for write in writes {
+        write.await?;
+      }
+      Ok(())
+    }
+    .boxed()
+  }
+
+  /// Does the actual work of a write. Callers need to hold the endpoint while this runs.
+  fn ungated_write(
+    &self,
+    msg: &HardwareWriteCmd,
+  ) -> BoxFuture<'static, Result<(), ButtplugDeviceError>> {
+    let internal_impl = self.internal_impl.clone();
+    let msg = msg.clone();
+    let retry_policy = *self
+      .write_retry_policy
+      .lock()
+      .expect("Write retry policy lock should never be poisoned.");
+    let write_retries = self.write_retries.clone();
+    let name = self.name.clone();
+    let dry_run = self.dry_run();
+    let last_write_time = self
+      .requires_keepalive
+      .then(|| self.last_write_time.clone());
+    async move {
+      if let Some(send_after_ms) = msg.send_after_ms() {
+        sleep(Duration::from_millis(send_after_ms.into())).await;
+      }
+      if let Some(last_write_time) = last_write_time {
         *last_write_time.write().await = Instant::now();
-        write_fut.await
       }
-      .boxed()
-    } else {
-      write_fut
+      let mut retry = 0;
+      loop {
+        if dry_run {
+          info!("Dry run, not writing {:?} to {}", msg, name);
+          break;
+        }
+        match internal_impl.write_value(&msg).await {
+          Ok(()) => break,
+          // Only failures from the transport itself are worth another go. Anything else (bad
+          // endpoints, disconnected devices) will fail the same way again.
+          Err(
+            err @ (ButtplugDeviceError::DeviceCommunicationError(_)
+            | ButtplugDeviceError::DeviceSpecificError(_)),
+          ) if retry < retry_policy.max_retries() => {
+            retry += 1;
+            write_retries.fetch_add(1, Ordering::Relaxed);
+            debug!(
+              "Write to {} failed, retrying ({} of {}): {:?}",
+              name,
+              retry,
+              retry_policy.max_retries(),
+              err
+            );
+            sleep(retry_policy.backoff(retry)).await;
+          }
+          Err(err) => return Err(err),
+        }
+      }
+      if let Some(post_write_delay_ms) = msg.post_write_delay_ms() {
+        sleep(Duration::from_millis(post_write_delay_ms.into())).await;
+      }
+      Ok(())
     }
+    .boxed()
   }
 
   /// Subscribe to a device endpoint, if it exists
@@ -351,7 +537,14 @@ impl Hardware {
     &self,
     msg: &HardwareSubscribeCmd,
   ) -> BoxFuture<'static, Result<(), ButtplugDeviceError>> {
-    self.internal_impl.subscribe(msg)
+    let internal_impl = self.internal_impl.clone();
+    let gate = self.command_gate.clone();
+    let msg = *msg;
+    async move {
+      let _turn = gate.acquire(msg.endpoint()).await;
+      internal_impl.subscribe(&msg).await
+    }
+    .boxed()
   }
 
   /// Unsubscribe from a device endpoint, if it exists
@@ -359,7 +552,14 @@ impl Hardware {
     &self,
     msg: &HardwareUnsubscribeCmd,
   ) -> BoxFuture<'static, Result<(), ButtplugDeviceError>> {
-    self.internal_impl.unsubscribe(msg)
+    let internal_impl = self.internal_impl.clone();
+    let gate = self.command_gate.clone();
+    let msg = *msg;
+    async move {
+      let _turn = gate.acquire(msg.endpoint()).await;
+      internal_impl.unsubscribe(&msg).await
+    }
+    .boxed()
   }
 }
 
@@ -372,6 +572,12 @@ impl Hardware {
 pub trait HardwareInternal: Sync + Send {
   /// Disconnect from the device (if it is connected)
   fn disconnect(&self) -> BoxFuture<'static, Result<(), ButtplugDeviceError>>;
+  /// Check whether the connection to the device is still alive. Used to weed out connections that
+  /// died without the bus telling us, like after the host system sleeps. Busses that always report
+  /// disconnections can rely on the default, which assumes the device is connected.
+  fn is_connected(&self) -> BoxFuture<'static, bool> {
+    future::ready(true).boxed()
+  }
   /// Returns a receiver for any events the device may emit.
   fn event_stream(&self) -> broadcast::Receiver<HardwareEvent>;
   /// Read a value from the device
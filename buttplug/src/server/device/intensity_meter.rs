@@ -0,0 +1,130 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2024 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Intensity metering, reporting what each device's actuators are actually set to.
+//!
+//! Clients send 0.0-1.0 values, but what reaches a device has been through step range conversion,
+//! clamping and rounding, and may have been changed since by something else entirely (a pattern
+//! session, a device link, another client). UIs that want to show real output would have to redo
+//! all of that themselves, so instead the device manager can send a throttled reading of every
+//! device's actuator levels whenever they change.
+
+use super::{
+  event_bus::{DeviceManagerEvent, DeviceManagerEventBus},
+  ServerDevice,
+};
+use crate::{
+  core::message::ActuatorType,
+  util::{self, async_manager},
+};
+use futures::StreamExt;
+use getset::{CopyGetters, Getters};
+use instant::Instant;
+use std::{
+  sync::{Arc, Weak},
+  time::Duration,
+};
+use tokio_util::sync::CancellationToken;
+
+/// Level an actuator feature was last set to.
+#[derive(Debug, Clone, PartialEq, Getters, CopyGetters)]
+pub struct FeatureIntensity {
+  #[getset(get_copy = "pub")]
+  feature_index: u32,
+  #[getset(get_copy = "pub")]
+  actuator_type: ActuatorType,
+  /// Step value sent to the device.
+  #[getset(get_copy = "pub")]
+  step: u32,
+  /// Step value as a fraction of the top of the feature's step range, from 0.0 to 1.0.
+  #[getset(get_copy = "pub")]
+  intensity: f64,
+  /// Direction, for rotating features. Always false for everything else.
+  #[getset(get_copy = "pub")]
+  clockwise: bool,
+}
+
+impl FeatureIntensity {
+  pub(crate) fn new(
+    feature_index: u32,
+    actuator_type: ActuatorType,
+    step: u32,
+    max_step: u32,
+    clockwise: bool,
+  ) -> Self {
+    let intensity = if max_step == 0 {
+      0.0
+    } else {
+      (step as f64 / max_step as f64).min(1.0)
+    };
+    Self {
+      feature_index,
+      actuator_type,
+      step,
+      intensity,
+      clockwise,
+    }
+  }
+}
+
+/// Actuator levels of a device, sent after they change. Only scalar and rotation features are
+/// covered, as linear movements are one-off strokes rather than levels.
+#[derive(Debug, Clone, PartialEq, Getters, CopyGetters)]
+pub struct IntensityMeterReading {
+  #[getset(get_copy = "pub")]
+  device_index: u32,
+  #[getset(get = "pub")]
+  features: Vec<FeatureIntensity>,
+}
+
+/// Publish readings for `device` whenever its actuators change, no more than once per `interval`.
+/// Changes that come in faster are folded into the next reading. Stops when the device goes away
+/// or `cancellation_token` is cancelled.
+pub(super) fn start_intensity_meter(
+  device_index: u32,
+  device: &Arc<ServerDevice>,
+  interval: Duration,
+  event_bus: DeviceManagerEventBus,
+  cancellation_token: CancellationToken,
+) {
+  let commands = device
+    .actuator_command_stream()
+    .take_until(cancellation_token.cancelled_owned());
+  // Holding the device would keep its command stream open after it disconnects.
+  let device: Weak<ServerDevice> = Arc::downgrade(device);
+  async_manager::spawn(async move {
+    futures::pin_mut!(commands);
+    let mut last_sent: Option<Instant> = None;
+    let mut pending = false;
+    loop {
+      let wait = last_sent
+        .map(|sent| interval.saturating_sub(sent.elapsed()))
+        .unwrap_or_default();
+      tokio::select! {
+        command = commands.next() => {
+          if command.is_none() {
+            break;
+          }
+          pending = true;
+        }
+        _ = util::sleep(wait), if pending => {}
+      }
+      if !pending || last_sent.is_some_and(|sent| sent.elapsed() < interval) {
+        continue;
+      }
+      let Some(device) = device.upgrade() else {
+        break;
+      };
+      event_bus.publish(DeviceManagerEvent::IntensityMeter(IntensityMeterReading {
+        device_index,
+        features: device.actuator_intensities(),
+      }));
+      pending = false;
+      last_sent = Some(Instant::now());
+    }
+  });
+}
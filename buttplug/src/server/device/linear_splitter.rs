@@ -0,0 +1,233 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2024 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Splitting of long linear moves into shorter ones.
+//!
+//! Some stroker firmwares misbehave when told to take minutes to reach a position. When a user
+//! sets a maximum move duration for a device, any longer move is cut into segments no longer than
+//! that, each going part of the way, and sent one after the other as the previous one finishes.
+//! Clients still see one command, and any newer command for the same feature drops whatever is
+//! left of the old one.
+
+use super::capability_emulator::LinearCmdSender;
+use crate::{
+  core::message::{ButtplugDeviceMessage, LinearCmdV4, VectorSubcommandV4},
+  util::{async_manager, sleep},
+};
+use dashmap::DashMap;
+use std::{sync::Arc, time::Duration};
+use tokio_util::sync::CancellationToken;
+
+/// Splits linear moves longer than a maximum duration into chained segments.
+pub(super) struct LinearCmdSplitter {
+  max_duration_ms: u32,
+  send_linear: LinearCmdSender,
+  /// Position each feature was last sent to.
+  positions: Arc<DashMap<u32, f64>>,
+  /// Segments still waiting to go out, per feature.
+  chains: DashMap<u32, CancellationToken>,
+}
+
+/// The part of a split command that goes out after its first segments, see
+/// [LinearCmdSplitter::split].
+pub(super) struct RemainingSegments {
+  device_index: u32,
+  send_linear: LinearCmdSender,
+  positions: Arc<DashMap<u32, f64>>,
+  /// First segment, the segments after it, and the token that drops them, per feature.
+  moves: Vec<(VectorSubcommandV4, Vec<VectorSubcommandV4>, CancellationToken)>,
+}
+
+impl RemainingSegments {
+  /// Start sending the rest of each move. Only call this once the first segments have gone out,
+  /// so a command the device rejected doesn't keep going in the background.
+  pub fn start(self) {
+    for (first, rest, chain) in self.moves {
+      // A newer command or a stop came in while the first segment was going out.
+      if chain.is_cancelled() {
+        continue;
+      }
+      self.positions.insert(first.feature_index(), first.position());
+      if !rest.is_empty() {
+        async_manager::spawn(run_segments(
+          self.send_linear.clone(),
+          self.device_index,
+          first.duration(),
+          rest,
+          self.positions.clone(),
+          chain,
+        ));
+      }
+    }
+  }
+}
+
+impl LinearCmdSplitter {
+  pub fn new(max_duration_ms: u32, send_linear: LinearCmdSender) -> Self {
+    Self {
+      max_duration_ms: max_duration_ms.max(1),
+      send_linear,
+      positions: Arc::new(DashMap::new()),
+      chains: DashMap::new(),
+    }
+  }
+
+  /// Return the part of `msg` to send right away, with every move cut down to its first segment,
+  /// along with the rest of each move. Whatever is left of older moves on the same features is
+  /// dropped.
+  pub fn split(&self, msg: &LinearCmdV4) -> (LinearCmdV4, RemainingSegments) {
+    let mut first_segments = vec![];
+    let mut moves = vec![];
+    for vector in msg.vectors() {
+      let feature_index = vector.feature_index();
+      let chain = CancellationToken::new();
+      if let Some(old_chain) = self.chains.insert(feature_index, chain.clone()) {
+        old_chain.cancel();
+      }
+      let start = self.positions.get(&feature_index).map(|position| *position);
+      let mut segments = split_vector(start, vector, self.max_duration_ms).into_iter();
+      let first = segments
+        .next()
+        .expect("Splitting always returns at least one segment");
+      first_segments.push(first.clone());
+      moves.push((first, segments.collect(), chain));
+    }
+    (
+      LinearCmdV4::new(msg.device_index(), first_segments),
+      RemainingSegments {
+        device_index: msg.device_index(),
+        send_linear: self.send_linear.clone(),
+        positions: self.positions.clone(),
+        moves,
+      },
+    )
+  }
+
+  /// Drop every move still in progress.
+  pub fn stop(&self) {
+    self.chains.retain(|_, chain| {
+      chain.cancel();
+      false
+    });
+  }
+}
+
+impl Drop for LinearCmdSplitter {
+  fn drop(&mut self) {
+    self.stop();
+  }
+}
+
+/// Cut a move into segments of at most `max_duration_ms`, evenly spaced between `start` and the
+/// move's target. If where the feature currently is isn't known, there's nothing to space the
+/// positions along, so the move is only split on time: every segment heads for the target, and the
+/// move still takes as long as asked.
+fn split_vector(
+  start: Option<f64>,
+  vector: &VectorSubcommandV4,
+  max_duration_ms: u32,
+) -> Vec<VectorSubcommandV4> {
+  let duration = vector.duration();
+  if duration <= max_duration_ms {
+    return vec![vector.clone()];
+  }
+  let count = duration.div_ceil(max_duration_ms);
+  // Work out where each segment ends, so rounding never adds up to more or less than the original
+  // duration.
+  let end_time = |segment: u32| (duration as u64 * segment as u64 / count as u64) as u32;
+  (1..=count)
+    .map(|segment| {
+      let position = match start {
+        Some(start) if segment != count => {
+          start + (vector.position() - start) * segment as f64 / count as f64
+        }
+        _ => vector.position(),
+      };
+      VectorSubcommandV4::new(
+        vector.feature_index(),
+        end_time(segment) - end_time(segment - 1),
+        position,
+      )
+    })
+    .collect()
+}
+
+async fn run_segments(
+  send_linear: LinearCmdSender,
+  device_index: u32,
+  mut wait_ms: u32,
+  segments: Vec<VectorSubcommandV4>,
+  positions: Arc<DashMap<u32, f64>>,
+  chain: CancellationToken,
+) {
+  for segment in segments {
+    tokio::select! {
+      _ = chain.cancelled() => return,
+      _ = sleep(Duration::from_millis(wait_ms.into())) => {}
+    }
+    let feature_index = segment.feature_index();
+    let position = segment.position();
+    wait_ms = segment.duration();
+    if let Err(err) = send_linear(LinearCmdV4::new(device_index, vec![segment])).await {
+      info!(
+        "Dropping the rest of a split linear move after command failure: {:?}",
+        err
+      );
+      return;
+    }
+    if chain.is_cancelled() {
+      return;
+    }
+    positions.insert(feature_index, position);
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::split_vector;
+  use crate::core::message::VectorSubcommandV4;
+
+  #[test]
+  fn test_split_vector() {
+    let vector = VectorSubcommandV4::new(0, 2500, 1.0);
+    // Short enough moves are left alone.
+    assert_eq!(split_vector(Some(0.0), &vector, 5000), vec![vector.clone()]);
+
+    let segments = split_vector(Some(0.0), &vector, 1000);
+    assert_eq!(
+      segments
+        .iter()
+        .map(|segment| segment.duration())
+        .collect::<Vec<_>>(),
+      vec![833, 833, 834]
+    );
+    let positions: Vec<_> = segments.iter().map(|segment| segment.position()).collect();
+    assert!((positions[0] - 1.0 / 3.0).abs() < f64::EPSILON);
+    assert!((positions[1] - 2.0 / 3.0).abs() < f64::EPSILON);
+    assert_eq!(positions[2], 1.0);
+
+    // Moving down works the same way.
+    let segments = split_vector(Some(1.0), &VectorSubcommandV4::new(0, 2000, 0.0), 1000);
+    assert_eq!(
+      segments,
+      vec![
+        VectorSubcommandV4::new(0, 1000, 0.5),
+        VectorSubcommandV4::new(0, 1000, 0.0)
+      ]
+    );
+
+    // Without a known start, the move is split on time alone, and still takes as long.
+    assert_eq!(
+      split_vector(None, &vector, 1000),
+      vec![
+        VectorSubcommandV4::new(0, 833, 1.0),
+        VectorSubcommandV4::new(0, 833, 1.0),
+        VectorSubcommandV4::new(0, 834, 1.0)
+      ]
+    );
+  }
+}
@@ -95,12 +95,47 @@
 //!
 //!
 
+mod adaptive_write_limiter;
+mod capability_emulator;
+mod command_audit;
+mod command_validation;
 pub mod configuration;
+mod device_link;
+mod device_list_history;
+mod energy_budget;
+mod event_bus;
 pub mod hardware;
+mod intensity_meter;
+mod linear_splitter;
+mod pattern_session;
 pub mod protocol;
+mod sensor_calibration;
+mod sensor_rate_limiter;
 pub mod server_device;
 mod server_device_manager;
 mod server_device_manager_event_loop;
 
+pub use command_audit::{CommandAuditEntry, CommandAuditEvent};
+pub use command_validation::OutOfRangeValuePolicy;
+pub use device_link::{DeviceLink, DeviceLinkTransfer};
+pub use energy_budget::{EnergyBudgetAction, EnergyBudgetEvent, EnergyBudgetPolicy};
+pub use event_bus::{
+  DeviceManagerEvent,
+  DeviceManagerEventBus,
+  DeviceManagerEventFilter,
+  DeviceManagerEventKind,
+};
+pub use intensity_meter::{FeatureIntensity, IntensityMeterReading};
+pub use pattern_session::{
+  PatternSession,
+  PatternSessionEnd,
+  PatternSessionEvent,
+  PatternStep,
+  PatternTrack,
+};
 pub use server_device::{ServerDevice, ServerDeviceEvent};
-pub use server_device_manager::{ServerDeviceManager, ServerDeviceManagerBuilder};
+pub use server_device_manager::{
+  ScanningProgress,
+  ServerDeviceManager,
+  ServerDeviceManagerBuilder,
+};
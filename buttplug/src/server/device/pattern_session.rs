@@ -0,0 +1,458 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2024 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Pattern sessions, for running a whole timed pattern inside the server.
+//!
+//! Clients usually drive patterns by sending a new command at every step, which means GC pauses or
+//! network hiccups on the client side show up as stutters on the device. With a session, the
+//! client hands over every step up front, and the server runs them against its own clock for up to
+//! the session's time limit. Devices in a session are reserved for it: other actuator commands for
+//! them are rejected until the session ends, though a StopDeviceCmd or StopAllDevices will still
+//! stop them, ending the session.
+
+use crate::{
+  core::{
+    errors::{ButtplugDeviceError, ButtplugError, ButtplugMessageError},
+    message::{
+      ActuatorType,
+      ButtplugActuatorFeatureMessageType,
+      ButtplugServerMessageV4,
+      PatternSessionEndReason,
+      PatternSessionEndedV4,
+      PatternSessionProgressV4,
+      ScalarCmdV4,
+      ScalarSubcommandV4,
+      StartPatternSessionV4,
+    },
+  },
+  server::device::{DeviceManagerEvent, DeviceManagerEventBus, ServerDevice},
+  util::{async_manager, sleep},
+};
+use dashmap::DashMap;
+use getset::{CopyGetters, Getters, Setters};
+use instant::Instant;
+use std::{
+  collections::{BTreeMap, HashSet},
+  sync::Arc,
+  time::Duration,
+};
+use tokio_util::sync::CancellationToken;
+
+/// Default time between [PatternSessionEvent::Progress] updates.
+const DEFAULT_PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Hold a scalar actuator at `level` (0.0-1.0) for `duration`.
+#[derive(Debug, Clone, Copy, PartialEq, CopyGetters)]
+#[getset(get_copy = "pub")]
+pub struct PatternStep {
+  level: f64,
+  duration: Duration,
+}
+
+impl PatternStep {
+  pub fn new(level: f64, duration: Duration) -> Self {
+    Self { level, duration }
+  }
+}
+
+/// Steps to run, in order, on one scalar actuator feature of a device.
+#[derive(Debug, Clone, PartialEq, Getters, CopyGetters)]
+pub struct PatternTrack {
+  #[getset(get_copy = "pub")]
+  device_index: u32,
+  #[getset(get_copy = "pub")]
+  feature_index: u32,
+  #[getset(get = "pub")]
+  steps: Vec<PatternStep>,
+}
+
+impl PatternTrack {
+  pub fn new(device_index: u32, feature_index: u32, steps: Vec<PatternStep>) -> Self {
+    Self {
+      device_index,
+      feature_index,
+      steps,
+    }
+  }
+
+  fn length(&self) -> Duration {
+    self.steps.iter().map(|step| step.duration).sum()
+  }
+}
+
+/// A set of tracks for the server to run together, starting at the same time.
+#[derive(Debug, Clone, PartialEq, Getters, CopyGetters, Setters)]
+pub struct PatternSession {
+  #[getset(get = "pub")]
+  tracks: Vec<PatternTrack>,
+  /// The session ends once this much time has passed, even if tracks still have steps left.
+  #[getset(get_copy = "pub")]
+  time_limit: Duration,
+  /// If true, tracks start over when they run out of steps, so the session runs until its time
+  /// limit. Off by default, in which case each track stops its feature when it's done, and the
+  /// session ends when every track is done.
+  #[getset(get_copy = "pub", set = "pub")]
+  repeat: bool,
+  /// How often to send [PatternSessionEvent::Progress] while the session runs. Defaults to once a
+  /// second.
+  #[getset(get_copy = "pub", set = "pub")]
+  progress_interval: Duration,
+}
+
+impl PatternSession {
+  pub fn new(tracks: Vec<PatternTrack>, time_limit: Duration) -> Self {
+    Self {
+      tracks,
+      time_limit,
+      repeat: false,
+      progress_interval: DEFAULT_PROGRESS_INTERVAL,
+    }
+  }
+
+  /// True if any track in the session runs on the device at `device_index`.
+  pub fn uses_device(&self, device_index: u32) -> bool {
+    self
+      .tracks
+      .iter()
+      .any(|track| track.device_index == device_index)
+  }
+
+  fn validate(&self) -> Result<(), ButtplugMessageError> {
+    let invalid = |reason: &str| {
+      ButtplugMessageError::InvalidMessageContents(format!("Invalid pattern session: {}", reason))
+    };
+    if self.tracks.is_empty() {
+      return Err(invalid("Needs at least one track."));
+    }
+    if self.time_limit.is_zero() || self.progress_interval.is_zero() {
+      return Err(invalid("Time limit and progress interval can't be zero."));
+    }
+    let mut features = HashSet::new();
+    for track in &self.tracks {
+      if !features.insert((track.device_index, track.feature_index)) {
+        return Err(invalid("More than one track for the same feature."));
+      }
+      if track.length().is_zero() {
+        return Err(invalid("Tracks must have a non-zero length."));
+      }
+      if track
+        .steps
+        .iter()
+        .any(|step| !(0.0..=1.0).contains(&step.level))
+      {
+        return Err(invalid("Step levels must be between 0.0 and 1.0."));
+      }
+    }
+    Ok(())
+  }
+}
+
+impl From<&StartPatternSessionV4> for PatternSession {
+  fn from(msg: &StartPatternSessionV4) -> Self {
+    let tracks = msg
+      .tracks()
+      .iter()
+      .map(|track| {
+        PatternTrack::new(
+          track.device_index(),
+          track.feature_index(),
+          track
+            .steps()
+            .iter()
+            .map(|step| {
+              PatternStep::new(
+                step.level(),
+                Duration::from_millis(step.duration() as u64),
+              )
+            })
+            .collect(),
+        )
+      })
+      .collect();
+    let mut session = Self::new(tracks, Duration::from_millis(msg.time_limit() as u64));
+    session.repeat = msg.repeat();
+    if let Some(interval) = msg.progress_interval() {
+      session.progress_interval = Duration::from_millis(interval as u64);
+    }
+    session
+  }
+}
+
+/// Why a pattern session ended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PatternSessionEnd {
+  /// Every track ran out of steps.
+  Completed,
+  /// The session hit its time limit.
+  TimeLimitReached,
+  /// The session was cancelled, or its devices were stopped.
+  Cancelled,
+  /// A device in the session went away or rejected a command.
+  Error(ButtplugError),
+}
+
+/// Updates about running pattern sessions, sent through the device manager's pattern session
+/// event stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PatternSessionEvent {
+  /// Sent every progress interval while a session runs.
+  Progress { session_id: u32, elapsed: Duration },
+  /// Sent once when a session ends, after its features have been stopped.
+  Ended {
+    session_id: u32,
+    reason: PatternSessionEnd,
+  },
+}
+
+impl From<PatternSessionEvent> for ButtplugServerMessageV4 {
+  fn from(event: PatternSessionEvent) -> Self {
+    match event {
+      PatternSessionEvent::Progress {
+        session_id,
+        elapsed,
+      } => PatternSessionProgressV4::new(session_id, elapsed.as_millis() as u32).into(),
+      PatternSessionEvent::Ended { session_id, reason } => {
+        let (reason, error_message) = match reason {
+          PatternSessionEnd::Completed => (PatternSessionEndReason::Completed, None),
+          PatternSessionEnd::TimeLimitReached => (PatternSessionEndReason::TimeLimitReached, None),
+          PatternSessionEnd::Cancelled => (PatternSessionEndReason::Cancelled, None),
+          PatternSessionEnd::Error(err) => (PatternSessionEndReason::Error, Some(err.to_string())),
+        };
+        PatternSessionEndedV4::new(session_id, reason, error_message).into()
+      }
+    }
+  }
+}
+
+/// Position of a track in its steps.
+struct TrackCursor {
+  actuator_type: ActuatorType,
+  /// Offset from the session start at which the next change is due, or None if the track is done.
+  next_change: Option<Duration>,
+  /// Step to start at the next change. Once past the last step, the change stops the feature.
+  next_step: usize,
+}
+
+/// Check `session` against the current devices, and work out the actuator type for each track.
+fn resolve_actuator_types(
+  session: &PatternSession,
+  devices: &DashMap<u32, Arc<ServerDevice>>,
+) -> Result<Vec<ActuatorType>, ButtplugError> {
+  session.validate()?;
+  let mut actuator_types = vec![];
+  for track in &session.tracks {
+    let device = devices
+      .get(&track.device_index)
+      .ok_or(ButtplugDeviceError::DeviceNotAvailable(track.device_index))?;
+    let features = device.definition().features();
+    let feature = features.get(track.feature_index as usize).ok_or(
+      ButtplugDeviceError::DeviceFeatureIndexError(features.len() as u32, track.feature_index),
+    )?;
+    if !feature.actuator().as_ref().is_some_and(|actuator| {
+      actuator
+        .messages()
+        .contains(&ButtplugActuatorFeatureMessageType::ScalarCmd)
+    }) {
+      return Err(
+        ButtplugDeviceError::ProtocolRequirementError(format!(
+          "Feature {} of {} is not a scalar actuator.",
+          track.feature_index,
+          device.name()
+        ))
+        .into(),
+      );
+    }
+    actuator_types.push(
+      ActuatorType::try_from(*feature.feature_type())
+        .map_err(ButtplugDeviceError::ProtocolRequirementError)?,
+    );
+  }
+  Ok(actuator_types)
+}
+
+/// Check `session` against the current devices and start running it. The session runs until it
+/// completes, hits its time limit, or `token` is cancelled. It then stops every feature it used,
+/// removes itself from `sessions`, and sends [PatternSessionEvent::Ended].
+pub(super) fn start_pattern_session(
+  session_id: u32,
+  session: PatternSession,
+  devices: Arc<DashMap<u32, Arc<ServerDevice>>>,
+  sessions: Arc<DashMap<u32, (PatternSession, CancellationToken)>>,
+  events: DeviceManagerEventBus,
+  token: CancellationToken,
+) -> Result<(), ButtplugError> {
+  let actuator_types = resolve_actuator_types(&session, &devices)?;
+  let cursors = actuator_types
+    .into_iter()
+    .map(|actuator_type| TrackCursor {
+      actuator_type,
+      next_change: Some(Duration::ZERO),
+      next_step: 0,
+    })
+    .collect();
+  async_manager::spawn(async move {
+    let reason =
+      run_pattern_session(session_id, &session, cursors, &devices, &events, &token).await;
+    // Group features by device, so each device gets a single stop.
+    let mut features: BTreeMap<u32, Vec<u32>> = BTreeMap::new();
+    for track in &session.tracks {
+      features
+        .entry(track.device_index)
+        .or_default()
+        .push(track.feature_index);
+    }
+    for (device_index, feature_indexes) in features {
+      let Some(device) = devices
+        .get(&device_index)
+        .map(|device| device.value().clone())
+      else {
+        continue;
+      };
+      if let Err(err) = device.stop_features(&feature_indexes).await {
+        debug!("Could not stop pattern session device: {:?}", err);
+      }
+    }
+    sessions.remove(&session_id);
+    // No one listening is fine.
+    events.publish(DeviceManagerEvent::PatternSession(
+      PatternSessionEvent::Ended { session_id, reason },
+    ));
+  });
+  Ok(())
+}
+
+async fn run_pattern_session(
+  session_id: u32,
+  session: &PatternSession,
+  mut cursors: Vec<TrackCursor>,
+  devices: &DashMap<u32, Arc<ServerDevice>>,
+  events: &DeviceManagerEventBus,
+  token: &CancellationToken,
+) -> PatternSessionEnd {
+  // Every change is scheduled against the session start, so time spent sending commands doesn't
+  // pile up into drift.
+  let start = Instant::now();
+  let mut next_progress = session.progress_interval;
+  loop {
+    let Some(next_change) = cursors.iter().filter_map(|cursor| cursor.next_change).min() else {
+      return PatternSessionEnd::Completed;
+    };
+    let target = next_change.min(session.time_limit);
+    loop {
+      let wake = target.min(next_progress);
+      tokio::select! {
+        _ = token.cancelled() => return PatternSessionEnd::Cancelled,
+        _ = sleep(wake.saturating_sub(start.elapsed())) => {}
+      }
+      if wake == next_progress {
+        events.publish(DeviceManagerEvent::PatternSession(
+          PatternSessionEvent::Progress {
+            session_id,
+            elapsed: start.elapsed(),
+          },
+        ));
+        next_progress += session.progress_interval;
+      }
+      if wake == target {
+        break;
+      }
+    }
+    if next_change > session.time_limit {
+      return PatternSessionEnd::TimeLimitReached;
+    }
+
+    // Work out every change due now, grouped by device.
+    let mut commands: BTreeMap<u32, Vec<ScalarSubcommandV4>> = BTreeMap::new();
+    for (track, cursor) in session.tracks.iter().zip(cursors.iter_mut()) {
+      if cursor.next_change != Some(next_change) {
+        continue;
+      }
+      let level = match track.steps.get(cursor.next_step) {
+        Some(step) => {
+          cursor.next_step += 1;
+          cursor.next_change = Some(next_change + step.duration);
+          step.level
+        }
+        None if session.repeat => {
+          let step = track.steps[0];
+          cursor.next_step = 1;
+          cursor.next_change = Some(next_change + step.duration);
+          step.level
+        }
+        None => {
+          cursor.next_change = None;
+          0.0
+        }
+      };
+      commands
+        .entry(track.device_index)
+        .or_default()
+        .push(ScalarSubcommandV4::new(
+          track.feature_index,
+          level,
+          cursor.actuator_type,
+        ));
+    }
+    for (device_index, subcommands) in commands {
+      let Some(device) = devices
+        .get(&device_index)
+        .map(|device| device.value().clone())
+      else {
+        return PatternSessionEnd::Error(
+          ButtplugDeviceError::DeviceNotAvailable(device_index).into(),
+        );
+      };
+      if let Err(err) = device
+        .parse_message(ScalarCmdV4::new(device_index, subcommands).into())
+        .await
+      {
+        return PatternSessionEnd::Error(err);
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{PatternSession, PatternStep, PatternTrack};
+  use std::time::Duration;
+
+  #[test]
+  fn test_pattern_session_validation() {
+    let step = PatternStep::new(0.5, Duration::from_millis(100));
+    let session = |tracks| PatternSession::new(tracks, Duration::from_secs(1));
+    assert!(session(vec![PatternTrack::new(0, 0, vec![step])])
+      .validate()
+      .is_ok());
+    assert!(session(vec![]).validate().is_err());
+    assert!(session(vec![
+      PatternTrack::new(0, 0, vec![step]),
+      PatternTrack::new(0, 0, vec![step])
+    ])
+    .validate()
+    .is_err());
+    assert!(session(vec![PatternTrack::new(
+      0,
+      0,
+      vec![PatternStep::new(0.5, Duration::ZERO)]
+    )])
+    .validate()
+    .is_err());
+    assert!(session(vec![PatternTrack::new(
+      0,
+      0,
+      vec![PatternStep::new(1.5, Duration::from_secs(1))]
+    )])
+    .validate()
+    .is_err());
+    assert!(
+      PatternSession::new(vec![PatternTrack::new(0, 0, vec![step])], Duration::ZERO)
+        .validate()
+        .is_err()
+    );
+  }
+}
@@ -5,50 +5,84 @@
 // Licensed under the BSD 3-Clause license. See LICENSE file in the project root
 // for full license information.
 
-use crate::core::{
-  errors::{ButtplugDeviceError, ButtplugError},
-  message::{
-    ActuatorType,
-    ButtplugActuatorFeatureMessageType,
-    ButtplugDeviceCommandMessageUnion,
-    DeviceFeature,
-    DeviceFeatureActuator,
-    RotateCmdV4,
-    RotationSubcommandV4,
-    ScalarCmdV4,
-    ScalarSubcommandV4,
+use crate::{
+  core::{
+    errors::{ButtplugDeviceError, ButtplugError},
+    message::{
+      ActuatorType,
+      ButtplugActuatorFeatureMessageType,
+      ButtplugDeviceCommandMessageUnion,
+      DeviceFeature,
+      DeviceFeatureActuator,
+      LinearCmdV4,
+      RotateCmdV4,
+      RotationSubcommandV4,
+      ScalarCmdV4,
+      ScalarSubcommandV4,
+      StopBehavior,
+      VectorSubcommandV4,
+    },
   },
+  server::device::FeatureIntensity,
 };
 use ahash::{HashMap, HashMapExt};
 use getset::Getters;
 use std::{
   collections::HashSet,
-  sync::atomic::{AtomicBool, AtomicU32, Ordering::Relaxed},
+  sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering::Relaxed},
 };
 
+// How long linear actuators that return to zero on stop take to get there. Slow enough that
+// stopping mid-stroke doesn't slam the actuator back.
+const LINEAR_STOP_DURATION_MS: u32 = 1000;
+
+// The message used to stop a feature, the feature's actuator type, and what stopping should do.
+type FeatureStop = (
+  ButtplugActuatorFeatureMessageType,
+  ActuatorType,
+  StopBehavior,
+);
+
+// A feature's actuator type, along with its step value and (for rotation) direction.
+type FeatureValue = (ActuatorType, (u32, bool));
+
 // As of the last rewrite of the command manager, we're currently only tracking values of scalar and
 // rotation commands. We can just use the rotation (AtomicU32, AtomicBool) pair for storage, and
 // ignore the direction bool for Scalars.
+//
+// Each status is one row of the feature table, and carries the index of the device feature it
+// tracks, since sensors and raw endpoints don't get a row and table positions drift from feature
+// indexes as soon as a device has either.
 #[derive(Getters)]
 #[getset(get = "pub")]
 struct FeatureStatus {
+  feature_index: u32,
   actuator_type: ActuatorType,
   actuator: DeviceFeatureActuator,
   sent: AtomicBool,
   value: (AtomicU32, AtomicBool),
+  // Last value requested by the client, as f64 bits. Kept alongside the step value so the request
+  // can be replayed against a new command manager without rounding drift.
+  requested: AtomicU64,
 }
 
 impl FeatureStatus {
-  pub fn new(actuator_type: &ActuatorType, actuator: &DeviceFeatureActuator) -> Self {
+  pub fn new(
+    feature_index: u32,
+    actuator_type: &ActuatorType,
+    actuator: &DeviceFeatureActuator,
+  ) -> Self {
     Self {
+      feature_index,
       actuator_type: *actuator_type,
       actuator: actuator.clone(),
       sent: AtomicBool::new(false),
       value: (AtomicU32::new(0), AtomicBool::new(false)),
+      requested: AtomicU64::new(0f64.to_bits()),
     }
   }
 
-  pub fn current(&self) -> (ActuatorType, (u32, bool)) {
+  pub fn current(&self) -> FeatureValue {
     (
       self.actuator_type,
       (self.value.0.load(Relaxed), self.value.1.load(Relaxed)),
@@ -59,6 +93,10 @@ impl FeatureStatus {
     self.actuator.messages()
   }
 
+  pub fn requested_value(&self) -> f64 {
+    f64::from_bits(self.requested.load(Relaxed))
+  }
+
   pub fn update(&self, value: &(f64, bool)) -> Option<(u32, bool)> {
     let mut result = None;
     let range_start = *self.actuator.step_range().start();
@@ -85,6 +123,7 @@ impl FeatureStatus {
     let current = self.value.0.load(Relaxed);
     let clockwise = self.value.1.load(Relaxed);
     let sent = self.sent.load(Relaxed);
+    self.requested.store(value.0.to_bits(), Relaxed);
     if !sent || scalar != current || clockwise != value.1 {
       self.value.0.store(scalar, Relaxed);
       self.value.1.store(value.1, Relaxed);
@@ -106,88 +145,120 @@ impl FeatureStatus {
 // horrible day some sex toy decides to use floats in its protocol), so we can just use atomics and
 // call it done.
 pub struct ActuatorCommandManager {
+  // Actuator features, in feature index order.
   feature_status: Vec<FeatureStatus>,
   stop_commands: Vec<ButtplugDeviceCommandMessageUnion>,
+  // Feature index to how that feature is stopped. Features that return to zero are sent a zero
+  // value (zero speed for rotation, position 0 for linear) through the same message they're
+  // normally controlled with.
+  stoppable_features: HashMap<u32, FeatureStop>,
 }
 
 impl ActuatorCommandManager {
   pub fn new(features: &Vec<DeviceFeature>) -> Self {
-    let mut stop_commands = vec![];
-
     let mut statuses = vec![];
-    let mut scalar_subcommands = vec![];
-    let mut rotate_subcommands = vec![];
+    let mut stoppable_features = HashMap::new();
     for (index, feature) in features.iter().enumerate() {
       if let Some(actuator) = feature.actuator() {
         let actuator_type: ActuatorType = feature.feature_type().clone().try_into().unwrap();
-        statuses.push(FeatureStatus::new(&actuator_type, actuator));
-        if actuator
-          .messages()
-          .contains(&crate::core::message::ButtplugActuatorFeatureMessageType::RotateCmd)
-        {
-          rotate_subcommands.push(RotationSubcommandV4::new(index as u32, 0.0, false));
-        } else if actuator
-          .messages()
-          .contains(&crate::core::message::ButtplugActuatorFeatureMessageType::ScalarCmd)
-        {
-          scalar_subcommands.push(ScalarSubcommandV4::new(index as u32, 0.0, actuator_type));
+        statuses.push(FeatureStatus::new(index as u32, &actuator_type, actuator));
+        let stop_message = [
+          ButtplugActuatorFeatureMessageType::RotateCmd,
+          ButtplugActuatorFeatureMessageType::ScalarCmd,
+          ButtplugActuatorFeatureMessageType::LinearCmd,
+        ]
+        .into_iter()
+        .find(|msg_type| actuator.messages().contains(msg_type));
+        if let Some(stop_message) = stop_message {
+          stoppable_features.insert(
+            index as u32,
+            (stop_message, actuator_type, actuator.stop_behavior()),
+          );
         }
       }
     }
-    if !scalar_subcommands.is_empty() {
-      stop_commands.push(ScalarCmdV4::new(0, scalar_subcommands).into());
-    }
-    if !rotate_subcommands.is_empty() {
-      stop_commands.push(RotateCmdV4::new(0, rotate_subcommands).into());
-    }
+    let mut stoppable_indexes: Vec<u32> = stoppable_features.keys().copied().collect();
+    stoppable_indexes.sort_unstable();
+    let stop_commands = stop_commands_for(&stoppable_features, &stoppable_indexes)
+      .expect("Only stoppable features were requested");
 
     Self {
       feature_status: statuses,
       stop_commands,
+      stoppable_features,
     }
   }
 
+  fn feature(&self, feature_index: u32) -> Option<&FeatureStatus> {
+    self
+      .feature_status
+      .binary_search_by_key(&feature_index, |status| *status.feature_index())
+      .ok()
+      .map(|position| &self.feature_status[position])
+  }
+
+  /// Features that take `msg_type`, in the order protocols expect their values.
+  fn features_for(
+    &self,
+    msg_type: ButtplugActuatorFeatureMessageType,
+  ) -> impl Iterator<Item = &FeatureStatus> {
+    self
+      .feature_status
+      .iter()
+      .filter(move |status| status.messages().contains(&msg_type))
+  }
+
+  /// Apply `commands` to the feature table, and return one slot per feature that takes `msg_type`.
+  /// A slot holds the feature's actuator type and new value if the value changed (or if
+  /// `match_all` is set), and None otherwise.
   fn update(
     &self,
     msg_type: ButtplugActuatorFeatureMessageType,
-    commands: &Vec<(u32, ActuatorType, (f64, bool))>,
+    commands: &[(u32, (f64, bool))],
     match_all: bool,
-  ) -> Result<Vec<(u32, ActuatorType, (u32, bool))>, ButtplugError> {
-    // Convert from the generic 0.0-1.0 range to the StepCount attribute given by the device config.
-
-    // If we've already sent commands before, we should check against our old values. Otherwise, we
-    // should always send whatever command we're going to send.
-    let mut result: Vec<(u32, ActuatorType, (u32, bool))> = vec![];
-
-    for command in commands {
-      if command.0 >= self.feature_status.len().try_into().unwrap() {
+  ) -> Result<Vec<Option<FeatureValue>>, ButtplugError> {
+    for (feature_index, _) in commands {
+      let Some(status) = self.feature(*feature_index) else {
+        return Err(
+          ButtplugDeviceError::ProtocolRequirementError(format!(
+            "Command requests feature index {}, which is not an actuator.",
+            feature_index,
+          ))
+          .into(),
+        );
+      };
+      if !status.messages().contains(&msg_type) {
         return Err(
           ButtplugDeviceError::ProtocolRequirementError(format!(
-            "Command requests feature index {}, which does not exist.",
-            command.0,
+            "Feature {} ({}) does not take {:?}.",
+            feature_index,
+            status.actuator_type(),
+            msg_type
           ))
           .into(),
         );
       }
     }
 
-    for (index, cmd) in self.feature_status.iter().enumerate() {
-      let u32_index: u32 = index.try_into().unwrap();
-      if let Some((_, cmd_actuator, cmd_value)) = commands.iter().find(|x| x.0 == u32_index) {
-        // By this point, we should have already checked whether the feature takes the message type.
-        if let Some(updated_value) = self.feature_status[index].update(cmd_value) {
-          result.push((u32_index, *cmd_actuator, updated_value));
-        } else if match_all {
-          result.push((u32_index, *cmd.actuator_type(), cmd.current().1));
-        }
-      } else if match_all {
-        if cmd.messages().contains(&msg_type) {
-          result.push((u32_index, *cmd.actuator_type(), cmd.current().1));
-        }
-      }
-    }
-    // Return the command vector for the protocol to turn into proprietary commands
-    Ok(result)
+    // Convert from the generic 0.0-1.0 range to the step range given by the device config. If we've
+    // already sent commands before, we only report values that changed, unless the protocol needs
+    // every value each time.
+    Ok(
+      self
+        .features_for(msg_type)
+        .map(|status| {
+          let updated = commands
+            .iter()
+            .find(|(feature_index, _)| feature_index == status.feature_index())
+            .and_then(|(_, value)| status.update(value));
+          match updated {
+            Some(value) => Some((*status.actuator_type(), value)),
+            None if match_all => Some(status.current()),
+            None => None,
+          }
+        })
+        .collect(),
+    )
   }
 
   pub fn update_scalar(
@@ -206,33 +277,22 @@ impl ActuatorCommandManager {
       );
     }
 
-    let mut idxs = HashMap::new();
-    for (i, x) in self.feature_status.iter().enumerate() {
-      if x
-        .messages()
-        .contains(&ButtplugActuatorFeatureMessageType::ScalarCmd)
-      {
-        idxs.insert(i, idxs.len());
-      }
-    }
-
-    let mut final_result: Vec<Option<(ActuatorType, u32)>> = vec![None; idxs.len()];
-
-    let mut commands: Vec<(u32, ActuatorType, (f64, bool))> = vec![];
-    msg
+    let commands: Vec<(u32, (f64, bool))> = msg
       .scalars()
       .iter()
-      .for_each(|x| commands.push((x.feature_index(), x.actuator_type(), (x.scalar(), false))));
-    let mut result = self.update(
-      ButtplugActuatorFeatureMessageType::ScalarCmd,
-      &commands,
-      match_all,
-    )?;
-    result.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
-    result.iter().for_each(|(index, actuator, value)| {
-      final_result[idxs[&(*index as usize)]] = Some((*actuator, value.0))
-    });
-    Ok(final_result)
+      .map(|x| (x.feature_index(), (x.scalar(), false)))
+      .collect();
+    Ok(
+      self
+        .update(
+          ButtplugActuatorFeatureMessageType::ScalarCmd,
+          &commands,
+          match_all,
+        )?
+        .into_iter()
+        .map(|slot| slot.map(|(actuator_type, (step, _))| (actuator_type, step)))
+        .collect(),
+    )
   }
 
   pub fn update_rotation(
@@ -251,41 +311,359 @@ impl ActuatorCommandManager {
       );
     }
 
-    let mut final_result: Vec<Option<(u32, bool)>> = vec![
-      None;
-      self
-        .feature_status
-        .iter()
-        .filter(|x| x
-          .messages()
-          .contains(&ButtplugActuatorFeatureMessageType::RotateCmd))
-        .count()
-    ];
-
-    let mut commands: Vec<(u32, ActuatorType, (f64, bool))> = vec![];
-    msg.rotations().iter().for_each(|x| {
-      commands.push((
-        x.feature_index(),
-        ActuatorType::Rotate,
-        (x.speed(), x.clockwise()),
-      ))
-    });
-    let mut result = self.update(
-      ButtplugActuatorFeatureMessageType::RotateCmd,
-      &commands,
-      match_all,
-    )?;
-    result.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
-    result
+    let commands: Vec<(u32, (f64, bool))> = msg
+      .rotations()
       .iter()
-      .enumerate()
-      .for_each(|(array_index, (_, _, value))| final_result[array_index] = Some(*value));
-    Ok(final_result)
+      .map(|x| (x.feature_index(), (x.speed(), x.clockwise())))
+      .collect();
+    Ok(
+      self
+        .update(
+          ButtplugActuatorFeatureMessageType::RotateCmd,
+          &commands,
+          match_all,
+        )?
+        .into_iter()
+        .map(|slot| slot.map(|(_, value)| value))
+        .collect(),
+    )
   }
 
   pub fn stop_commands(&self) -> Vec<ButtplugDeviceCommandMessageUnion> {
     self.stop_commands.clone()
   }
+
+  /// Build the commands needed to stop only the given features, leaving all other features running.
+  ///
+  /// Features whose stop behavior is [StopBehavior::Hold] are left alone. Asking for features that
+  /// aren't actuators is an error.
+  pub fn stop_feature_commands(
+    &self,
+    feature_indexes: &[u32],
+  ) -> Result<Vec<ButtplugDeviceCommandMessageUnion>, ButtplugDeviceError> {
+    stop_commands_for(&self.stoppable_features, feature_indexes)
+  }
+
+  /// Step values last sent to each scalar and rotation feature. Features that have never been
+  /// commanded are at 0.
+  pub fn intensities(&self) -> Vec<FeatureIntensity> {
+    self
+      .feature_status
+      .iter()
+      .filter(|status| {
+        status
+          .messages()
+          .contains(&ButtplugActuatorFeatureMessageType::ScalarCmd)
+          || status
+            .messages()
+            .contains(&ButtplugActuatorFeatureMessageType::RotateCmd)
+      })
+      .map(|status| {
+        let (actuator_type, (step, clockwise)) = status.current();
+        FeatureIntensity::new(
+          *status.feature_index(),
+          actuator_type,
+          step,
+          *status.actuator().step_range().end(),
+          clockwise,
+        )
+      })
+      .collect()
+  }
+
+  /// Build the commands needed to bring a new instance of the device (after a reconnect, for
+  /// instance) back to the values last sent through this manager. Features that are stopped, or
+  /// that have never been commanded, are left out, since a newly connected device is already idle.
+  pub fn replay_commands(&self) -> Vec<ButtplugDeviceCommandMessageUnion> {
+    let mut scalar_subcommands = vec![];
+    let mut rotate_subcommands = vec![];
+    for status in &self.feature_status {
+      let (actuator_type, (step, clockwise)) = status.current();
+      if !status.sent().load(Relaxed) || step == 0 {
+        continue;
+      }
+      if status
+        .messages()
+        .contains(&ButtplugActuatorFeatureMessageType::RotateCmd)
+      {
+        rotate_subcommands.push(RotationSubcommandV4::new(
+          *status.feature_index(),
+          status.requested_value(),
+          clockwise,
+        ));
+      } else if status
+        .messages()
+        .contains(&ButtplugActuatorFeatureMessageType::ScalarCmd)
+      {
+        scalar_subcommands.push(ScalarSubcommandV4::new(
+          *status.feature_index(),
+          status.requested_value(),
+          actuator_type,
+        ));
+      }
+    }
+    let mut commands = vec![];
+    if !scalar_subcommands.is_empty() {
+      commands.push(ScalarCmdV4::new(0, scalar_subcommands).into());
+    }
+    if !rotate_subcommands.is_empty() {
+      commands.push(RotateCmdV4::new(0, rotate_subcommands).into());
+    }
+    commands
+  }
+}
+
+fn stop_commands_for(
+  stoppable_features: &HashMap<u32, FeatureStop>,
+  feature_indexes: &[u32],
+) -> Result<Vec<ButtplugDeviceCommandMessageUnion>, ButtplugDeviceError> {
+  let mut scalar_subcommands = vec![];
+  let mut rotate_subcommands = vec![];
+  let mut linear_subcommands = vec![];
+  for index in feature_indexes {
+    match stoppable_features.get(index) {
+      Some((_, _, StopBehavior::Hold)) => {}
+      Some((ButtplugActuatorFeatureMessageType::RotateCmd, _, _)) => {
+        rotate_subcommands.push(RotationSubcommandV4::new(*index, 0.0, false))
+      }
+      Some((ButtplugActuatorFeatureMessageType::LinearCmd, _, _)) => linear_subcommands.push(
+        VectorSubcommandV4::new(*index, LINEAR_STOP_DURATION_MS, 0.0),
+      ),
+      Some((_, actuator_type, _)) => {
+        scalar_subcommands.push(ScalarSubcommandV4::new(*index, 0.0, *actuator_type))
+      }
+      None => {
+        return Err(ButtplugDeviceError::ProtocolRequirementError(format!(
+          "Feature {} has no actuator that can be stopped.",
+          index
+        )))
+      }
+    }
+  }
+  let mut commands = vec![];
+  if !scalar_subcommands.is_empty() {
+    commands.push(ScalarCmdV4::new(0, scalar_subcommands).into());
+  }
+  if !rotate_subcommands.is_empty() {
+    commands.push(RotateCmdV4::new(0, rotate_subcommands).into());
+  }
+  if !linear_subcommands.is_empty() {
+    commands.push(LinearCmdV4::new(0, linear_subcommands).into());
+  }
+  Ok(commands)
+}
+
+#[cfg(test)]
+mod test {
+  use super::ActuatorCommandManager;
+  use crate::core::message::{
+    ActuatorType,
+    ButtplugActuatorFeatureMessageType,
+    ButtplugSensorFeatureMessageType,
+    DeviceFeature,
+    DeviceFeatureActuator,
+    DeviceFeatureSensor,
+    FeatureType,
+    LinearCmdV4,
+    RotateCmdV4,
+    RotationSubcommandV4,
+    ScalarCmdV4,
+    ScalarSubcommandV4,
+    StopBehavior,
+    VectorSubcommandV4,
+  };
+  use std::collections::HashSet;
+
+  fn actuator(
+    feature_type: FeatureType,
+    steps: u32,
+    message: ButtplugActuatorFeatureMessageType,
+  ) -> DeviceFeature {
+    DeviceFeature::new(
+      "Test",
+      feature_type,
+      &Some(DeviceFeatureActuator::new(
+        &(0..=steps),
+        &(0..=steps),
+        &HashSet::from([message]),
+      )),
+      &None,
+    )
+  }
+
+  // Battery, Vibrate, Constrict, Oscillate, Rotate, Rotate
+  fn mixed_features() -> Vec<DeviceFeature> {
+    vec![
+      DeviceFeature::new(
+        "Battery",
+        FeatureType::Battery,
+        &None,
+        &Some(DeviceFeatureSensor::new(
+          &vec![0..=100],
+          &HashSet::from([ButtplugSensorFeatureMessageType::SensorReadCmd]),
+        )),
+      ),
+      actuator(
+        FeatureType::Vibrate,
+        20,
+        ButtplugActuatorFeatureMessageType::ScalarCmd,
+      ),
+      actuator(
+        FeatureType::Constrict,
+        10,
+        ButtplugActuatorFeatureMessageType::ScalarCmd,
+      ),
+      actuator(
+        FeatureType::Oscillate,
+        100,
+        ButtplugActuatorFeatureMessageType::ScalarCmd,
+      ),
+      actuator(
+        FeatureType::Rotate,
+        20,
+        ButtplugActuatorFeatureMessageType::RotateCmd,
+      ),
+      actuator(
+        FeatureType::Rotate,
+        20,
+        ButtplugActuatorFeatureMessageType::RotateCmd,
+      ),
+    ]
+  }
+
+  #[test]
+  fn test_mixed_scalar_deltas() {
+    let mgr = ActuatorCommandManager::new(&mixed_features());
+    let scalar = |subcommands: Vec<(u32, f64, ActuatorType)>| {
+      ScalarCmdV4::new(
+        0,
+        subcommands
+          .into_iter()
+          .map(|(index, value, actuator_type)| ScalarSubcommandV4::new(index, value, actuator_type))
+          .collect(),
+      )
+    };
+
+    assert_eq!(
+      mgr
+        .update_scalar(
+          &scalar(vec![
+            (1, 0.5, ActuatorType::Vibrate),
+            (3, 0.5, ActuatorType::Oscillate)
+          ]),
+          false
+        )
+        .unwrap(),
+      vec![
+        Some((ActuatorType::Vibrate, 10)),
+        None,
+        Some((ActuatorType::Oscillate, 50))
+      ]
+    );
+    // Only the constriction changes, so only it is reported, in its own slot.
+    assert_eq!(
+      mgr
+        .update_scalar(
+          &scalar(vec![
+            (1, 0.5, ActuatorType::Vibrate),
+            (2, 0.3, ActuatorType::Constrict)
+          ]),
+          false
+        )
+        .unwrap(),
+      vec![None, Some((ActuatorType::Constrict, 3)), None]
+    );
+    assert_eq!(
+      mgr
+        .update_scalar(&scalar(vec![(2, 0.3, ActuatorType::Constrict)]), true)
+        .unwrap(),
+      vec![
+        Some((ActuatorType::Vibrate, 10)),
+        Some((ActuatorType::Constrict, 3)),
+        Some((ActuatorType::Oscillate, 50))
+      ]
+    );
+
+    // The battery isn't an actuator, and the rotators don't take ScalarCmd.
+    assert!(mgr
+      .update_scalar(&scalar(vec![(0, 0.5, ActuatorType::Vibrate)]), false)
+      .is_err());
+    assert!(mgr
+      .update_scalar(&scalar(vec![(4, 0.5, ActuatorType::Rotate)]), false)
+      .is_err());
+  }
+
+  #[test]
+  fn test_mixed_rotation_slots() {
+    let mgr = ActuatorCommandManager::new(&mixed_features());
+    let rotate = |index: u32, speed: f64, clockwise: bool| {
+      RotateCmdV4::new(0, vec![RotationSubcommandV4::new(index, speed, clockwise)])
+    };
+
+    // Commanding only the second rotator fills the second slot.
+    assert_eq!(
+      mgr.update_rotation(&rotate(5, 0.5, true), false).unwrap(),
+      vec![None, Some((10, true))]
+    );
+    assert_eq!(
+      mgr.update_rotation(&rotate(4, 0.25, false), true).unwrap(),
+      vec![Some((5, false)), Some((10, true))]
+    );
+    assert_eq!(
+      mgr.update_rotation(&rotate(5, 0.5, true), false).unwrap(),
+      vec![None, None]
+    );
+    assert!(mgr.update_rotation(&rotate(1, 0.5, true), false).is_err());
+  }
+
+  #[test]
+  fn test_stop_behaviors() {
+    let mut features = mixed_features();
+    // Held constriction, a linear actuator on the default (hold), and one that returns to zero.
+    let mut held_constrict = features[2].actuator().clone().unwrap();
+    held_constrict.set_stop_behavior(Some(StopBehavior::Hold));
+    features[2] = DeviceFeature::new("Test", FeatureType::Constrict, &Some(held_constrict), &None);
+    features.push(actuator(
+      FeatureType::Position,
+      100,
+      ButtplugActuatorFeatureMessageType::LinearCmd,
+    ));
+    let mut returning_linear = features[6].actuator().clone().unwrap();
+    returning_linear.set_stop_behavior(Some(StopBehavior::ReturnToZero));
+    features.push(DeviceFeature::new(
+      "Test",
+      FeatureType::Position,
+      &Some(returning_linear),
+      &None,
+    ));
+    let mgr = ActuatorCommandManager::new(&features);
+
+    assert_eq!(
+      mgr.stop_commands(),
+      vec![
+        ScalarCmdV4::new(
+          0,
+          vec![
+            ScalarSubcommandV4::new(1, 0.0, ActuatorType::Vibrate),
+            ScalarSubcommandV4::new(3, 0.0, ActuatorType::Oscillate),
+          ]
+        )
+        .into(),
+        RotateCmdV4::new(
+          0,
+          vec![
+            RotationSubcommandV4::new(4, 0.0, false),
+            RotationSubcommandV4::new(5, 0.0, false),
+          ]
+        )
+        .into(),
+        LinearCmdV4::new(0, vec![VectorSubcommandV4::new(7, 1000, 0.0)]).into(),
+      ]
+    );
+    // Held features are skipped when stopped on their own too.
+    assert_eq!(mgr.stop_feature_commands(&[2, 6]).unwrap(), vec![]);
+    assert!(mgr.stop_feature_commands(&[0]).is_err());
+  }
 }
 /*
 #[cfg(test)]
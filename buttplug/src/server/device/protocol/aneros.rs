@@ -23,6 +23,10 @@ impl ProtocolHandler for Aneros {
     super::ProtocolKeepaliveStrategy::RepeatLastPacketStrategy
   }
 
+  fn write_retry_policy(&self) -> super::ProtocolWriteRetryPolicy {
+    super::ProtocolWriteRetryPolicy::new(2, 20)
+  }
+
   fn handle_scalar_vibrate_cmd(
     &self,
     index: u32,
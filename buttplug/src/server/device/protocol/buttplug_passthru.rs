@@ -8,18 +8,114 @@
 use crate::{
   core::{
     errors::ButtplugDeviceError,
-    message::{ButtplugDeviceCommandMessageUnion, Endpoint},
+    message::{
+      ButtplugDeviceCommandMessageUnion,
+      ButtplugDeviceMessage,
+      ButtplugServerDeviceMessage,
+      Endpoint,
+      SensorReadingV4,
+    },
   },
   server::device::{
-    hardware::{HardwareCommand, HardwareWriteCmd},
-    protocol::{generic_protocol_setup, ProtocolHandler},
+    configuration::{UserDeviceDefinition, UserDeviceIdentifier},
+    hardware::{Hardware, HardwareCommand, HardwareEvent, HardwareSubscribeCmd, HardwareWriteCmd},
+    protocol::{
+      generic_protocol_initializer_setup,
+      ProtocolCommunicationSpecifier,
+      ProtocolHandler,
+      ProtocolIdentifier,
+      ProtocolInitializer,
+    },
   },
+  util::{async_manager, stream::convert_broadcast_receiver_to_stream},
 };
+use async_trait::async_trait;
+use dashmap::DashSet;
+use futures::StreamExt;
+use std::{
+  pin::Pin,
+  sync::{
+    atomic::{AtomicU32, Ordering},
+    Arc,
+  },
+};
+use tokio::sync::broadcast;
 
-generic_protocol_setup!(ButtplugPassthru, "buttplug-passthru");
+generic_protocol_initializer_setup!(ButtplugPassthru, "buttplug-passthru");
 
 #[derive(Default)]
-struct ButtplugPassthru {}
+pub struct ButtplugPassthruInitializer {}
+
+#[async_trait]
+impl ProtocolInitializer for ButtplugPassthruInitializer {
+  async fn initialize(
+    &mut self,
+    hardware: Arc<Hardware>,
+    _: &UserDeviceDefinition,
+  ) -> Result<Arc<dyn ProtocolHandler>, ButtplugDeviceError> {
+    let handler = ButtplugPassthru::default();
+    // Devices on the other end push sensor readings back to us as serialized SensorReading
+    // messages, if they have anything to send back at all.
+    if hardware.endpoints().contains(&Endpoint::Rx) {
+      hardware
+        .subscribe(&HardwareSubscribeCmd::new(Endpoint::Rx))
+        .await?;
+      let mut hardware_stream = hardware.event_stream();
+      let sender = handler.event_stream.clone();
+      let subscribed_sensors = handler.subscribed_sensors.clone();
+      let device_index = handler.device_index.clone();
+      async_manager::spawn(async move {
+        while let Ok(event) = hardware_stream.recv().await {
+          let HardwareEvent::Notification(_, _, data) = event else {
+            continue;
+          };
+          let reading = match serde_json::from_slice::<SensorReadingV4>(&data) {
+            Ok(reading) => reading,
+            Err(err) => {
+              warn!(
+                "Cannot parse sensor reading from passthru device: {:?}",
+                err
+              );
+              continue;
+            }
+          };
+          // Readings are only forwarded for sensors a client has subscribed to, and the device
+          // doesn't know its index on this server, so fill that in here.
+          if !subscribed_sensors.contains(&reading.feature_index()) {
+            continue;
+          }
+          let reading = SensorReadingV4::new(
+            device_index.load(Ordering::Relaxed),
+            reading.feature_index(),
+            reading.sensor_type(),
+            reading.data().clone(),
+          );
+          // No receivers just means nothing is listening to the device yet.
+          let _ = sender.send(reading.into());
+        }
+      });
+    }
+    Ok(Arc::new(handler))
+  }
+}
+
+pub struct ButtplugPassthru {
+  // Set of sensors we've subscribed to for updates.
+  subscribed_sensors: Arc<DashSet<u32>>,
+  device_index: Arc<AtomicU32>,
+  event_stream: broadcast::Sender<ButtplugServerDeviceMessage>,
+}
+
+impl Default for ButtplugPassthru {
+  fn default() -> Self {
+    let (sender, _) = broadcast::channel(256);
+    Self {
+      subscribed_sensors: Arc::new(DashSet::new()),
+      device_index: Arc::new(AtomicU32::new(0)),
+      event_stream: sender,
+    }
+  }
+}
 
 impl ProtocolHandler for ButtplugPassthru {
   fn keepalive_strategy(&self) -> super::ProtocolKeepaliveStrategy {
@@ -34,6 +130,18 @@ impl ProtocolHandler for ButtplugPassthru {
     &self,
     command_message: &ButtplugDeviceCommandMessageUnion,
   ) -> Result<Vec<HardwareCommand>, ButtplugDeviceError> {
+    match command_message {
+      ButtplugDeviceCommandMessageUnion::SensorSubscribeCmd(msg) => {
+        self
+          .device_index
+          .store(msg.device_index(), Ordering::Relaxed);
+        self.subscribed_sensors.insert(*msg.feature_index());
+      }
+      ButtplugDeviceCommandMessageUnion::SensorUnsubscribeCmd(msg) => {
+        self.subscribed_sensors.remove(msg.feature_index());
+      }
+      _ => {}
+    }
     Ok(vec![HardwareWriteCmd::new(
       Endpoint::Tx,
       serde_json::to_string(&command_message)
@@ -44,4 +152,10 @@ impl ProtocolHandler for ButtplugPassthru {
     )
     .into()])
   }
+
+  fn event_stream(
+    &self,
+  ) -> Pin<Box<dyn futures::Stream<Item = ButtplugServerDeviceMessage> + Send>> {
+    convert_broadcast_receiver_to_stream(self.event_stream.subscribe()).boxed()
+  }
 }
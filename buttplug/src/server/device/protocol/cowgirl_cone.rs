@@ -17,10 +17,9 @@ use crate::{
       ProtocolInitializer,
     },
   },
-  util::sleep,
 };
 use async_trait::async_trait;
-use std::{sync::Arc, time::Duration};
+use std::sync::Arc;
 
 generic_protocol_initializer_setup!(CowgirlCone, "cowgirl-cone");
 
@@ -35,13 +34,11 @@ impl ProtocolInitializer for CowgirlConeInitializer {
     _: &UserDeviceDefinition,
   ) -> Result<Arc<dyn ProtocolHandler>, ButtplugDeviceError> {
     hardware
-      .write_value(&HardwareWriteCmd::new(
-        Endpoint::Tx,
-        vec![0xaa, 0x56, 0x00, 0x00],
-        false,
-      ))
+      .write_value(
+        &HardwareWriteCmd::new(Endpoint::Tx, vec![0xaa, 0x56, 0x00, 0x00], false)
+          .with_post_write_delay(3000),
+      )
       .await?;
-    sleep(Duration::from_millis(3000)).await;
     Ok(Arc::new(CowgirlCone::default()))
   }
 }
@@ -65,6 +65,7 @@ impl ProtocolHandler for Cupido {
             );
             Ok(battery_reading.into())
           }
+          HardwareEvent::Rssi(..) => continue,
           HardwareEvent::Disconnected(_) => Err(ButtplugDeviceError::ProtocolSpecificError(
             "Cupido".to_owned(),
             "Cupido Device disconnected while getting Battery info.".to_owned(),
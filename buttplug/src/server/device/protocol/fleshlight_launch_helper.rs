@@ -56,3 +56,27 @@ pub fn calculate_duration(mut distance: f64, mut speed: f64) -> u32 {
   let mil = (speed / 250f64).powf(-0.95);
   (mil / (90f64 / (distance * 100f64))) as u32
 }
+
+/// Speed response of a stroker that, like the Launch, is sent a target position and a speed
+/// setting rather than a duration. A full stroke at speed setting `s` (0.0-1.0) takes
+/// `fastest_stroke_ms * s.powf(-1.0 / exponent)`, so the curve is fitted per device from its
+/// fastest full stroke and how quickly it slows down at lower settings.
+#[derive(Debug, Clone, Copy)]
+pub struct StrokerSpeedCurve {
+  pub fastest_stroke_ms: f64,
+  pub exponent: f64,
+}
+
+impl StrokerSpeedCurve {
+  /// Speed setting (0.0-1.0) that covers `distance` (0.0-1.0 of a full stroke) in `duration`
+  /// milliseconds, clamped to what the device can do. Moves of no distance get full speed.
+  pub fn speed(&self, distance: f64, duration: u32) -> f64 {
+    if distance <= 0f64 {
+      return 1f64;
+    }
+    let full_stroke_ms = duration as f64 / distance.min(1f64);
+    (self.fastest_stroke_ms / full_stroke_ms)
+      .powf(self.exponent)
+      .clamp(0f64, 1f64)
+  }
+}
@@ -19,6 +19,11 @@ generic_protocol_setup!(FleshyThrust, "fleshy-thrust");
 pub struct FleshyThrust {}
 
 impl ProtocolHandler for FleshyThrust {
+  fn identify_strategy(&self) -> super::ProtocolIdentifyStrategy {
+    // Position only device, a stroke isn't a safe way to identify it.
+    super::ProtocolIdentifyStrategy::Unsupported
+  }
+
   fn handle_linear_cmd(
     &self,
     message: crate::core::message::LinearCmdV4,
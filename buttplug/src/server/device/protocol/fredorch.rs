@@ -182,6 +182,11 @@ pub struct Fredorch {
 }
 
 impl ProtocolHandler for Fredorch {
+  fn identify_strategy(&self) -> super::ProtocolIdentifyStrategy {
+    // Position only device, a stroke isn't a safe way to identify it.
+    super::ProtocolIdentifyStrategy::Unsupported
+  }
+
   fn handle_linear_cmd(
     &self,
     message: message::LinearCmdV4,
@@ -266,6 +266,7 @@ impl ProtocolHandler for Galaku {
             );
             Ok(battery_reading.into())
           }
+          HardwareEvent::Rssi(..) => continue,
           HardwareEvent::Disconnected(_) => Err(ButtplugDeviceError::ProtocolSpecificError(
             "Galaku".to_owned(),
             "Galaku Device disconnected while getting Battery info.".to_owned(),
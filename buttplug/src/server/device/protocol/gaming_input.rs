@@ -0,0 +1,146 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2024 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Gamepads through Windows.Gaming.Input, which unlike XInput can drive the impulse trigger motors
+//! of Xbox One/Series style pads as well as the two main ones. Pads without trigger motors only get
+//! the main two.
+
+use super::xinput::XInputMotor;
+use crate::{
+  core::{
+    errors::ButtplugDeviceError,
+    message::{ActuatorType, Endpoint},
+  },
+  server::device::{
+    configuration::{ProtocolCommunicationSpecifier, UserDeviceDefinition, UserDeviceIdentifier},
+    hardware::{Hardware, HardwareCommand, HardwareWriteCmd},
+    protocol::{
+      generic_protocol_initializer_setup,
+      ProtocolHandler,
+      ProtocolIdentifier,
+      ProtocolInitializer,
+      ProtocolTimingPolicy,
+    },
+  },
+};
+use async_trait::async_trait;
+use byteorder::{LittleEndian, WriteBytesExt};
+use std::sync::Arc;
+
+/// Left motor, right motor, left trigger, right trigger, in the order the hardware takes them.
+const GAMING_INPUT_MOTOR_COUNT: usize = 4;
+/// Just the left and right motors, for pads without trigger motors.
+const GAMING_INPUT_RUMBLE_MOTOR_COUNT: usize = 2;
+
+generic_protocol_initializer_setup!(GamingInput, "gaming-input");
+
+#[derive(Default)]
+pub struct GamingInputInitializer {}
+
+#[async_trait]
+impl ProtocolInitializer for GamingInputInitializer {
+  async fn initialize(
+    &mut self,
+    _: Arc<Hardware>,
+    device_definition: &UserDeviceDefinition,
+  ) -> Result<Arc<dyn ProtocolHandler>, ButtplugDeviceError> {
+    let motors: Vec<XInputMotor> = device_definition
+      .features()
+      .iter()
+      .filter_map(|feature| feature.actuator().as_ref())
+      .map(XInputMotor::new)
+      .collect();
+    if ![GAMING_INPUT_MOTOR_COUNT, GAMING_INPUT_RUMBLE_MOTOR_COUNT].contains(&motors.len()) {
+      return Err(ButtplugDeviceError::DeviceFeatureCountMismatch(
+        GAMING_INPUT_MOTOR_COUNT as u32,
+        motors.len() as u32,
+      ));
+    }
+    Ok(Arc::new(GamingInput { motors }))
+  }
+}
+
+pub struct GamingInput {
+  motors: Vec<XInputMotor>,
+}
+
+impl ProtocolHandler for GamingInput {
+  fn needs_full_command_set(&self) -> bool {
+    true
+  }
+
+  fn timing_policy(&self) -> ProtocolTimingPolicy {
+    // Same controllers as XInput, so the same update rate applies.
+    ProtocolTimingPolicy::new(10)
+  }
+
+  fn handle_scalar_cmd(
+    &self,
+    cmds: &[Option<(ActuatorType, u32)>],
+  ) -> Result<Vec<HardwareCommand>, ButtplugDeviceError> {
+    // As with XInput, we always send every motor's speed, packed as little endian u16s in feature
+    // order. Pads without trigger motors just get fewer speeds.
+    let mut cmd = vec![];
+    for (motor, command) in self.motors.iter().zip(cmds) {
+      let step = command
+        .expect("GCM uses match_all, we'll always get a value for every motor")
+        .1;
+      if cmd.write_u16::<LittleEndian>(motor.speed(step)).is_err() {
+        return Err(ButtplugDeviceError::ProtocolSpecificError(
+          "GamingInput".to_owned(),
+          "Cannot convert Gaming Input value for processing".to_owned(),
+        ));
+      }
+    }
+    Ok(vec![HardwareWriteCmd::new(Endpoint::Tx, cmd, false).into()])
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{GamingInput, XInputMotor};
+  use crate::{
+    core::message::{
+      ActuatorType,
+      ButtplugActuatorFeatureMessageType,
+      DeviceFeatureActuator,
+      Endpoint,
+    },
+    server::device::{
+      hardware::{HardwareCommand, HardwareWriteCmd},
+      protocol::ProtocolHandler,
+    },
+  };
+  use std::collections::HashSet;
+
+  #[test]
+  fn test_gaming_input_motor_order() {
+    let actuator = DeviceFeatureActuator::new(
+      &(0..=65535),
+      &(0..=65535),
+      &HashSet::from([ButtplugActuatorFeatureMessageType::ScalarCmd]),
+    );
+    let gaming_input = GamingInput {
+      motors: (0..4).map(|_| XInputMotor::new(&actuator)).collect(),
+    };
+    let commands = gaming_input
+      .handle_scalar_cmd(&[
+        Some((ActuatorType::Vibrate, 0x1234)),
+        Some((ActuatorType::Vibrate, 0x5678)),
+        Some((ActuatorType::Vibrate, 0x9abc)),
+        Some((ActuatorType::Vibrate, 0xdef0)),
+      ])
+      .unwrap();
+    let expected: HardwareCommand = HardwareWriteCmd::new(
+      Endpoint::Tx,
+      vec![0x34, 0x12, 0x78, 0x56, 0xbc, 0x9a, 0xf0, 0xde],
+      false,
+    )
+    .into();
+    assert_eq!(commands, vec![expected]);
+  }
+}
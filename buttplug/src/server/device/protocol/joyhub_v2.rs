@@ -5,38 +5,29 @@
 // Licensed under the BSD 3-Clause license. See LICENSE file in the project root
 // for full license information.
 
-use crate::server::device::configuration::ProtocolCommunicationSpecifier;
 use crate::{
   core::{
     errors::ButtplugDeviceError,
     message::{ActuatorType, Endpoint},
   },
-  generic_protocol_initializer_setup,
+  generic_protocol_setup,
   server::device::{
-    configuration::{UserDeviceDefinition, UserDeviceIdentifier},
-    hardware::{Hardware, HardwareCommand, HardwareWriteCmd},
-    protocol::{ProtocolHandler, ProtocolIdentifier, ProtocolInitializer},
+    hardware::{HardwareCommand, HardwareWriteCmd},
+    protocol::ProtocolHandler,
   },
-  util::{async_manager, sleep},
 };
-use async_trait::async_trait;
-use std::sync::{Arc, RwLock};
-use std::time::Duration;
-
-generic_protocol_initializer_setup!(JoyHubV2, "joyhub-v2");
-
-async fn delayed_constrict_handler(device: Arc<Hardware>, scalar: u8) {
-  sleep(Duration::from_millis(50)).await;
-  let res = device
-    .write_value(&HardwareWriteCmd::new(
-      Endpoint::Tx,
-      vec![0xa0, 0x0d, 0x00, 0x00, scalar, 0xff],
-      false,
-    ))
-    .await;
-  if res.is_err() {
-    error!("Delayed JoyHub Constrict command error: {:?}", res.err());
-  }
+use std::{iter, sync::RwLock};
+
+generic_protocol_setup!(JoyHubV2, "joyhub-v2");
+
+// Sent after a vibration update, which the device drops if the two arrive too close together.
+fn delayed_constrict_cmd(scalar: u8) -> HardwareWriteCmd {
+  HardwareWriteCmd::new(
+    Endpoint::Tx,
+    vec![0xa0, 0x0d, 0x00, 0x00, scalar, 0xff],
+    false,
+  )
+  .with_send_after(50)
 }
 
 fn vibes_changed(
@@ -87,31 +78,10 @@ fn scalar_changed(
 }
 
 #[derive(Default)]
-pub struct JoyHubV2Initializer {}
-
-#[async_trait]
-impl ProtocolInitializer for JoyHubV2Initializer {
-  async fn initialize(
-    &mut self,
-    hardware: Arc<Hardware>,
-    _: &UserDeviceDefinition,
-  ) -> Result<Arc<dyn ProtocolHandler>, ButtplugDeviceError> {
-    Ok(Arc::new(JoyHubV2::new(hardware)))
-  }
-}
-
 pub struct JoyHubV2 {
-  device: Arc<Hardware>,
   last_cmds: RwLock<Vec<Option<(ActuatorType, u32)>>>,
 }
 
-impl JoyHubV2 {
-  fn new(device: Arc<Hardware>) -> Self {
-    let last_cmds = RwLock::new(vec![]);
-    Self { device, last_cmds }
-  }
-}
-
 impl ProtocolHandler for JoyHubV2 {
   fn keepalive_strategy(&self) -> super::ProtocolKeepaliveStrategy {
     super::ProtocolKeepaliveStrategy::RepeatLastPacketStrategy
@@ -126,6 +96,7 @@ impl ProtocolHandler for JoyHubV2 {
     commands: &[Option<(ActuatorType, u32)>],
   ) -> Result<Vec<HardwareCommand>, ButtplugDeviceError> {
     let cmd1 = commands[0];
+    let mut delayed_constrict = None;
     let mut cmd2 = if commands.len() > 1 {
       commands[1]
     } else {
@@ -143,8 +114,7 @@ impl ProtocolHandler for JoyHubV2 {
         if !scalar_changed(&self.last_cmds, commands, 1usize) {
           // no-op
         } else if vibes_changed(&self.last_cmds, commands, vec![1usize]) {
-          let dev = self.device.clone();
-          async_manager::spawn(async move { delayed_constrict_handler(dev, cmd.1 as u8).await });
+          delayed_constrict = Some(delayed_constrict_cmd(cmd.1 as u8));
         } else {
           let mut command_writer = self.last_cmds.write().expect("Locks should work");
           *command_writer = commands.to_vec();
@@ -165,8 +135,7 @@ impl ProtocolHandler for JoyHubV2 {
         if !scalar_changed(&self.last_cmds, commands, 2usize) {
           // no-op
         } else if vibes_changed(&self.last_cmds, commands, vec![2usize]) {
-          let dev = self.device.clone();
-          async_manager::spawn(async move { delayed_constrict_handler(dev, cmd.1 as u8).await });
+          delayed_constrict = Some(delayed_constrict_cmd(cmd.1 as u8));
         } else {
           let mut command_writer = self.last_cmds.write().expect("Locks should work");
           *command_writer = commands.to_vec();
@@ -183,7 +152,7 @@ impl ProtocolHandler for JoyHubV2 {
 
     let mut command_writer = self.last_cmds.write().expect("Locks should work");
     *command_writer = commands.to_vec();
-    Ok(vec![HardwareWriteCmd::new(
+    let command = HardwareWriteCmd::new(
       Endpoint::Tx,
       vec![
         0xa0,
@@ -195,7 +164,12 @@ impl ProtocolHandler for JoyHubV2 {
         0xaa,
       ],
       false,
+    );
+    Ok(
+      iter::once(command)
+        .chain(delayed_constrict)
+        .map(|cmd| cmd.into())
+        .collect(),
     )
-    .into()])
   }
 }
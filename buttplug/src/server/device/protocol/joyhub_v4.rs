@@ -10,40 +10,31 @@ use crate::{
     errors::ButtplugDeviceError,
     message::{ActuatorType, Endpoint},
   },
-  generic_protocol_initializer_setup,
+  generic_protocol_setup,
   server::device::{
-    configuration::UserDeviceIdentifier,
-    configuration::{ProtocolCommunicationSpecifier, UserDeviceDefinition},
-    hardware::{Hardware, HardwareCommand, HardwareWriteCmd},
-    protocol::{ProtocolHandler, ProtocolIdentifier, ProtocolInitializer},
+    hardware::{HardwareCommand, HardwareWriteCmd},
+    protocol::ProtocolHandler,
   },
-  util::{async_manager, sleep},
 };
-use async_trait::async_trait;
-use std::sync::{Arc, RwLock};
-use std::time::Duration;
-
-generic_protocol_initializer_setup!(JoyHubV4, "joyhub-v4");
-
-async fn delayed_constrict_handler(device: Arc<Hardware>, scalar: u8) {
-  sleep(Duration::from_millis(25)).await;
-  let res = device
-    .write_value(&HardwareWriteCmd::new(
-      Endpoint::Tx,
-      vec![
-        0xa0,
-        0x07,
-        if scalar == 0 { 0x00 } else { 0x01 },
-        0x00,
-        scalar,
-        0xff,
-      ],
-      false,
-    ))
-    .await;
-  if res.is_err() {
-    error!("Delayed JoyHub Constrict command error: {:?}", res.err());
-  }
+use std::{iter, sync::RwLock};
+
+generic_protocol_setup!(JoyHubV4, "joyhub-v4");
+
+// Sent after a vibration update, which the device drops if the two arrive too close together.
+fn delayed_constrict_cmd(scalar: u8) -> HardwareWriteCmd {
+  HardwareWriteCmd::new(
+    Endpoint::Tx,
+    vec![
+      0xa0,
+      0x07,
+      if scalar == 0 { 0x00 } else { 0x01 },
+      0x00,
+      scalar,
+      0xff,
+    ],
+    false,
+  )
+  .with_send_after(25)
 }
 fn vibes_changed(
   old_commands_lock: &RwLock<Vec<Option<(ActuatorType, u32)>>>,
@@ -93,31 +84,10 @@ fn scalar_changed(
 }
 
 #[derive(Default)]
-pub struct JoyHubV4Initializer {}
-
-#[async_trait]
-impl ProtocolInitializer for JoyHubV4Initializer {
-  async fn initialize(
-    &mut self,
-    hardware: Arc<Hardware>,
-    _: &UserDeviceDefinition,
-  ) -> Result<Arc<dyn ProtocolHandler>, ButtplugDeviceError> {
-    Ok(Arc::new(JoyHubV4::new(hardware)))
-  }
-}
-
 pub struct JoyHubV4 {
-  device: Arc<Hardware>,
   last_cmds: RwLock<Vec<Option<(ActuatorType, u32)>>>,
 }
 
-impl JoyHubV4 {
-  fn new(device: Arc<Hardware>) -> Self {
-    let last_cmds = RwLock::new(vec![]);
-    Self { device, last_cmds }
-  }
-}
-
 impl ProtocolHandler for JoyHubV4 {
   fn keepalive_strategy(&self) -> super::ProtocolKeepaliveStrategy {
     super::ProtocolKeepaliveStrategy::RepeatLastPacketStrategy
@@ -132,6 +102,7 @@ impl ProtocolHandler for JoyHubV4 {
     commands: &[Option<(ActuatorType, u32)>],
   ) -> Result<Vec<HardwareCommand>, ButtplugDeviceError> {
     let cmd1 = commands[0];
+    let mut delayed_constrict = None;
     let cmd2 = if commands.len() > 1 {
       commands[1]
     } else {
@@ -149,8 +120,7 @@ impl ProtocolHandler for JoyHubV4 {
         if !scalar_changed(&self.last_cmds, commands, 2usize) {
           // no-op
         } else if vibes_changed(&self.last_cmds, commands, vec![2usize]) {
-          let dev = self.device.clone();
-          async_manager::spawn(async move { delayed_constrict_handler(dev, cmd.1 as u8).await });
+          delayed_constrict = Some(delayed_constrict_cmd(cmd.1 as u8));
         } else {
           let mut command_writer = self.last_cmds.write().expect("Locks should work");
           *command_writer = commands.to_vec();
@@ -175,7 +145,7 @@ impl ProtocolHandler for JoyHubV4 {
     let mut command_writer = self.last_cmds.write().expect("Locks should work");
     *command_writer = commands.to_vec();
 
-    Ok(vec![HardwareWriteCmd::new(
+    let command = HardwareWriteCmd::new(
       Endpoint::Tx,
       vec![
         0xa0,
@@ -187,7 +157,12 @@ impl ProtocolHandler for JoyHubV4 {
         0xaa,
       ],
       false,
+    );
+    Ok(
+      iter::once(command)
+        .chain(delayed_constrict)
+        .map(|cmd| cmd.into())
+        .collect(),
     )
-    .into()])
   }
 }
@@ -10,40 +10,31 @@ use crate::{
     errors::ButtplugDeviceError,
     message::{ActuatorType, Endpoint},
   },
-  generic_protocol_initializer_setup,
+  generic_protocol_setup,
   server::device::{
-    configuration::UserDeviceIdentifier,
-    configuration::{ProtocolCommunicationSpecifier, UserDeviceDefinition},
-    hardware::{Hardware, HardwareCommand, HardwareWriteCmd},
-    protocol::{ProtocolHandler, ProtocolIdentifier, ProtocolInitializer},
+    hardware::{HardwareCommand, HardwareWriteCmd},
+    protocol::ProtocolHandler,
   },
-  util::{async_manager, sleep},
 };
-use async_trait::async_trait;
-use std::sync::{Arc, RwLock};
-use std::time::Duration;
-
-generic_protocol_initializer_setup!(JoyHubV5, "joyhub-v5");
-
-async fn delayed_constrict_handler(device: Arc<Hardware>, scalar: u8) {
-  sleep(Duration::from_millis(25)).await;
-  let res = device
-    .write_value(&HardwareWriteCmd::new(
-      Endpoint::Tx,
-      vec![
-        0xa0,
-        0x07,
-        if scalar == 0 { 0x00 } else { 0x01 },
-        0x00,
-        scalar,
-        0xff,
-      ],
-      false,
-    ))
-    .await;
-  if res.is_err() {
-    error!("Delayed JoyHub Constrict command error: {:?}", res.err());
-  }
+use std::{iter, sync::RwLock};
+
+generic_protocol_setup!(JoyHubV5, "joyhub-v5");
+
+// Sent after a vibration update, which the device drops if the two arrive too close together.
+fn delayed_constrict_cmd(scalar: u8) -> HardwareWriteCmd {
+  HardwareWriteCmd::new(
+    Endpoint::Tx,
+    vec![
+      0xa0,
+      0x07,
+      if scalar == 0 { 0x00 } else { 0x01 },
+      0x00,
+      scalar,
+      0xff,
+    ],
+    false,
+  )
+  .with_send_after(25)
 }
 
 fn vibes_changed(
@@ -94,31 +85,10 @@ fn scalar_changed(
 }
 
 #[derive(Default)]
-pub struct JoyHubV5Initializer {}
-
-#[async_trait]
-impl ProtocolInitializer for JoyHubV5Initializer {
-  async fn initialize(
-    &mut self,
-    hardware: Arc<Hardware>,
-    _: &UserDeviceDefinition,
-  ) -> Result<Arc<dyn ProtocolHandler>, ButtplugDeviceError> {
-    Ok(Arc::new(JoyHubV5::new(hardware)))
-  }
-}
-
 pub struct JoyHubV5 {
-  device: Arc<Hardware>,
   last_cmds: RwLock<Vec<Option<(ActuatorType, u32)>>>,
 }
 
-impl JoyHubV5 {
-  fn new(device: Arc<Hardware>) -> Self {
-    let last_cmds = RwLock::new(vec![]);
-    Self { device, last_cmds }
-  }
-}
-
 impl ProtocolHandler for JoyHubV5 {
   fn keepalive_strategy(&self) -> super::ProtocolKeepaliveStrategy {
     super::ProtocolKeepaliveStrategy::RepeatLastPacketStrategy
@@ -133,6 +103,7 @@ impl ProtocolHandler for JoyHubV5 {
     commands: &[Option<(ActuatorType, u32)>],
   ) -> Result<Vec<HardwareCommand>, ButtplugDeviceError> {
     let cmd1 = commands[0];
+    let mut delayed_constrict = None;
     let mut cmd2 = if commands.len() > 1 {
       commands[1]
     } else {
@@ -145,8 +116,7 @@ impl ProtocolHandler for JoyHubV5 {
         if !scalar_changed(&self.last_cmds, commands, 1usize) {
           // no-op
         } else if vibes_changed(&self.last_cmds, commands, vec![1usize]) {
-          let dev = self.device.clone();
-          async_manager::spawn(async move { delayed_constrict_handler(dev, cmd.1 as u8).await });
+          delayed_constrict = Some(delayed_constrict_cmd(cmd.1 as u8));
         } else {
           let mut command_writer = self.last_cmds.write().expect("Locks should work");
           *command_writer = commands.to_vec();
@@ -171,7 +141,7 @@ impl ProtocolHandler for JoyHubV5 {
     let mut command_writer = self.last_cmds.write().expect("Locks should work");
     *command_writer = commands.to_vec();
 
-    Ok(vec![HardwareWriteCmd::new(
+    let command = HardwareWriteCmd::new(
       Endpoint::Tx,
       vec![
         0xa0,
@@ -183,7 +153,12 @@ impl ProtocolHandler for JoyHubV5 {
         0xaa,
       ],
       false,
+    );
+    Ok(
+      iter::once(command)
+        .chain(delayed_constrict)
+        .map(|cmd| cmd.into())
+        .collect(),
     )
-    .into()])
   }
 }
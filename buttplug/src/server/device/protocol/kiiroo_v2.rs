@@ -12,7 +12,7 @@ use crate::{
   },
   server::device::{
     configuration::{ProtocolCommunicationSpecifier, UserDeviceDefinition, UserDeviceIdentifier},
-    hardware::{Hardware, HardwareCommand, HardwareWriteCmd},
+    hardware::{Hardware, HardwareCommand, HardwareReadCmd, HardwareWriteCmd},
     protocol::{
       fleshlight_launch_helper::calculate_speed,
       generic_protocol_initializer_setup,
@@ -40,22 +40,59 @@ impl ProtocolInitializer for KiirooV2Initializer {
     hardware: Arc<Hardware>,
     _: &UserDeviceDefinition,
   ) -> Result<Arc<dyn ProtocolHandler>, ButtplugDeviceError> {
+    // Only some firmware exposes the BLE device info service, and not knowing the version
+    // shouldn't stop us from using the device.
+    let firmware_version = if hardware.endpoints().contains(&Endpoint::RxBLEFirmware) {
+      match hardware
+        .read_value(&HardwareReadCmd::new(Endpoint::RxBLEFirmware, 32, 500))
+        .await
+      {
+        Ok(reading) => kiiroo_firmware_version(reading.data()),
+        Err(err) => {
+          warn!("Cannot read Kiiroo v2 firmware version: {:?}", err);
+          None
+        }
+      }
+    } else {
+      None
+    };
     let msg = HardwareWriteCmd::new(Endpoint::Firmware, vec![0x0u8], true);
     hardware.write_value(&msg).await?;
-    Ok(Arc::new(KiirooV2::default()))
+    Ok(Arc::new(KiirooV2 {
+      firmware_version,
+      ..Default::default()
+    }))
   }
 }
 
+/// Firmware revision strings can come back padded with nulls or whitespace.
+fn kiiroo_firmware_version(data: &[u8]) -> Option<String> {
+  let version = std::str::from_utf8(data)
+    .ok()?
+    .trim_matches(|c: char| c == '\0' || c.is_whitespace());
+  (!version.is_empty()).then(|| version.to_owned())
+}
+
 #[derive(Default)]
 pub struct KiirooV2 {
   previous_position: Arc<AtomicU8>,
+  firmware_version: Option<String>,
 }
 
 impl ProtocolHandler for KiirooV2 {
+  fn identify_strategy(&self) -> super::ProtocolIdentifyStrategy {
+    // Position only device, a stroke isn't a safe way to identify it.
+    super::ProtocolIdentifyStrategy::Unsupported
+  }
+
   fn keepalive_strategy(&self) -> super::ProtocolKeepaliveStrategy {
     super::ProtocolKeepaliveStrategy::RepeatLastPacketStrategy
   }
 
+  fn firmware_version(&self) -> Option<String> {
+    self.firmware_version.clone()
+  }
+
   fn handle_linear_cmd(
     &self,
     message: message::LinearCmdV4,
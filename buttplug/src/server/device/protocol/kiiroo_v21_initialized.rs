@@ -19,6 +19,7 @@ use crate::{
       ProtocolHandler,
       ProtocolIdentifier,
       ProtocolInitializer,
+      ProtocolWriteFailureStrategy,
     },
   },
 };
@@ -65,10 +66,22 @@ pub struct KiirooV21Initialized {
 }
 
 impl ProtocolHandler for KiirooV21Initialized {
+  fn identify_strategy(&self) -> super::ProtocolIdentifyStrategy {
+    // Position only device, a stroke isn't a safe way to identify it.
+    super::ProtocolIdentifyStrategy::Unsupported
+  }
+
   fn keepalive_strategy(&self) -> super::ProtocolKeepaliveStrategy {
     super::ProtocolKeepaliveStrategy::RepeatLastPacketStrategy
   }
 
+  fn on_write_failure(&self) -> ProtocolWriteFailureStrategy {
+    // We can't tell whether the last position made it to the device, but the init sequence ends
+    // with a move to 0, so we know where it'll be after reinitializing.
+    self.previous_position.store(0, Ordering::SeqCst);
+    ProtocolWriteFailureStrategy::Reinitialize
+  }
+
   fn handle_scalar_vibrate_cmd(
     &self,
     _index: u32,
@@ -8,18 +8,54 @@
 use crate::{
   core::{
     errors::ButtplugDeviceError,
-    message::{ActuatorType, Endpoint},
+    message::{
+      self,
+      ActuatorType,
+      ButtplugDeviceMessage,
+      ButtplugServerDeviceMessage,
+      Endpoint,
+      SensorReadingV4,
+    },
   },
   server::device::{
-    hardware::{HardwareCommand, HardwareWriteCmd},
+    hardware::{
+      Hardware,
+      HardwareCommand,
+      HardwareEvent,
+      HardwareSubscribeCmd,
+      HardwareUnsubscribeCmd,
+      HardwareWriteCmd,
+    },
     protocol::{generic_protocol_setup, ProtocolHandler},
   },
+  util::{async_manager, stream::convert_broadcast_receiver_to_stream},
 };
+use dashmap::DashSet;
+use futures::{
+  future::{self, BoxFuture},
+  FutureExt,
+  StreamExt,
+};
+use std::{pin::Pin, sync::Arc};
+use tokio::sync::broadcast;
 
 generic_protocol_setup!(KiirooV2Vibrator, "kiiroo-v2-vibrator");
 
-#[derive(Default)]
-pub struct KiirooV2Vibrator {}
+pub struct KiirooV2Vibrator {
+  // Set of sensors we've subscribed to for updates.
+  subscribed_sensors: Arc<DashSet<u32>>,
+  event_stream: broadcast::Sender<ButtplugServerDeviceMessage>,
+}
+
+impl Default for KiirooV2Vibrator {
+  fn default() -> Self {
+    let (sender, _) = broadcast::channel(256);
+    Self {
+      subscribed_sensors: Arc::new(DashSet::new()),
+      event_stream: sender,
+    }
+  }
+}
 
 impl ProtocolHandler for KiirooV2Vibrator {
   fn keepalive_strategy(&self) -> super::ProtocolKeepaliveStrategy {
@@ -53,4 +89,96 @@ impl ProtocolHandler for KiirooV2Vibrator {
     )
     .into()])
   }
+
+  fn event_stream(
+    &self,
+  ) -> Pin<Box<dyn futures::Stream<Item = ButtplugServerDeviceMessage> + Send>> {
+    convert_broadcast_receiver_to_stream(self.event_stream.subscribe()).boxed()
+  }
+
+  fn handle_sensor_subscribe_cmd(
+    &self,
+    device: Arc<Hardware>,
+    message: &message::SensorSubscribeCmdV4,
+  ) -> BoxFuture<'_, Result<(), ButtplugDeviceError>> {
+    if self.subscribed_sensors.contains(message.feature_index()) {
+      return future::ready(Ok(())).boxed();
+    }
+    let message = message.clone();
+    let sensors = self.subscribed_sensors.clone();
+    // The Pearl 2 sends capacitive touchpad data on the RxTouch endpoint. The layout of the
+    // notifications isn't documented, so each byte is passed along as its own reading value and
+    // left for the application to interpret.
+    async move {
+      // If we have no sensors we're currently subscribed to, we'll need to bring up our BLE
+      // characteristic subscription.
+      if sensors.is_empty() {
+        device
+          .subscribe(&HardwareSubscribeCmd::new(Endpoint::RxTouch))
+          .await?;
+        let sender = self.event_stream.clone();
+        let mut hardware_stream = device.event_stream();
+        let stream_sensors = sensors.clone();
+        let device_index = message.device_index();
+        let sensor_type = *message.sensor_type();
+        // If we subscribe successfully, we need to set up our event handler.
+        async_manager::spawn(async move {
+          while let Ok(info) = hardware_stream.recv().await {
+            // If we have no receivers, quit.
+            if sender.receiver_count() == 0 || stream_sensors.is_empty() {
+              return;
+            }
+            if let HardwareEvent::Notification(_, Endpoint::RxTouch, data) = info {
+              let touch_data: Vec<i32> = data.iter().map(|x| *x as i32).collect();
+              for sensor_index in stream_sensors.iter() {
+                if sender
+                  .send(
+                    SensorReadingV4::new(
+                      device_index,
+                      *sensor_index,
+                      sensor_type,
+                      touch_data.clone(),
+                    )
+                    .into(),
+                  )
+                  .is_err()
+                {
+                  debug!(
+                    "Hardware device listener for Kiiroo V2 Vibrator shut down, exiting task."
+                  );
+                  return;
+                }
+              }
+            }
+          }
+        });
+      }
+      sensors.insert(*message.feature_index());
+      Ok(())
+    }
+    .boxed()
+  }
+
+  fn handle_sensor_unsubscribe_cmd(
+    &self,
+    device: Arc<Hardware>,
+    message: &message::SensorUnsubscribeCmdV4,
+  ) -> BoxFuture<'_, Result<(), ButtplugDeviceError>> {
+    if !self.subscribed_sensors.contains(message.feature_index()) {
+      return future::ready(Ok(())).boxed();
+    }
+    let message = message.clone();
+    let sensors = self.subscribed_sensors.clone();
+    async move {
+      // Once nothing is subscribed, we can drop our BLE characteristic subscription.
+      sensors.remove(message.feature_index());
+      if sensors.is_empty() {
+        device
+          .unsubscribe(&HardwareUnsubscribeCmd::new(Endpoint::RxTouch))
+          .await?;
+      }
+      Ok(())
+    }
+    .boxed()
+  }
 }
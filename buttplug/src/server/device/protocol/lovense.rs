@@ -8,25 +8,48 @@
 use crate::{
   core::{
     errors::ButtplugDeviceError,
-    message::{self, ActuatorType, ButtplugDeviceMessage, Endpoint, FeatureType, SensorReadingV4},
+    message::{
+      self,
+      ActuatorType,
+      ButtplugActuatorFeatureMessageType,
+      ButtplugDeviceMessage,
+      ButtplugServerDeviceMessage,
+      Endpoint,
+      FeatureType,
+      SensorReadingV4,
+    },
   },
   server::device::{
     configuration::{ProtocolCommunicationSpecifier, UserDeviceDefinition, UserDeviceIdentifier},
     hardware::{Hardware, HardwareCommand, HardwareEvent, HardwareSubscribeCmd, HardwareWriteCmd},
-    protocol::{ProtocolHandler, ProtocolIdentifier, ProtocolInitializer},
+    protocol::{
+      lovense_inputs::LovenseInputs,
+      ProtocolDiagnostics,
+      ProtocolHandler,
+      ProtocolIdentifier,
+      ProtocolInitializer,
+    },
   },
   util::{async_manager, sleep},
 };
 use async_trait::async_trait;
-use futures::{future::BoxFuture, FutureExt};
+use futures::{
+  future::{self, BoxFuture},
+  pin_mut,
+  FutureExt,
+  Stream,
+};
 use regex::Regex;
 use std::{
+  pin::Pin,
   sync::{
     atomic::{AtomicBool, AtomicU32, AtomicU8, Ordering},
     Arc,
+    Mutex,
   },
   time::Duration,
 };
+use tokio::sync::broadcast::error::RecvError;
 
 // Constants for dealing with the Lovense subscript/write race condition. The
 // timeout needs to be VERY long, otherwise this trips up old lovense serial
@@ -36,6 +59,17 @@ use std::{
 const LOVENSE_COMMAND_TIMEOUT_MS: u64 = 500;
 const LOVENSE_COMMAND_RETRY: u64 = 5;
 
+// Vibrating Lovense toys ship with 4 presets (Pulse, Wave, Fireworks, Earthquake), triggered by
+// "Preset:1;" through "Preset:4;". "Preset:0;" stops whichever one is running.
+const LOVENSE_PRESET_COUNT: u32 = 4;
+
+// Strokers that take both speed and position control (the Solace Pro) run one or the other, never
+// both at once. Whichever kind of command came in last is in charge, and switching stops whatever
+// the other mode was doing first, so the two don't fight over the motor.
+const STROKER_MODE_IDLE: u8 = 0;
+const STROKER_MODE_SPEED: u8 = 1;
+const STROKER_MODE_POSITION: u8 = 2;
+
 pub mod setup {
   use crate::server::device::protocol::{ProtocolIdentifier, ProtocolIdentifierFactory};
   #[derive(Default)]
@@ -78,6 +112,58 @@ fn lovense_model_resolver(type_response: String) -> String {
   identifier
 }
 
+/// Pull the firmware version out of a DeviceType reply, which looks like "Z:11:0082059AD3BD;".
+fn lovense_firmware_version(reply: &str) -> Option<String> {
+  let version = reply.split(':').nth(1)?;
+  (!version.is_empty() && version.chars().all(|c| c.is_ascii_digit())).then(|| version.to_owned())
+}
+
+/// Pull the level out of a Battery reply. Toys that are running prefix it with an "s", i.e. "s89;"
+/// instead of "89;".
+fn lovense_battery_level(reply: &str) -> Option<u32> {
+  reply
+    .trim_end_matches(';')
+    .trim_start_matches('s')
+    .parse::<u8>()
+    .ok()
+    .map(u32::from)
+}
+
+/// Send `query` to the toy and return the first reply `parse` accepts, or None if the toy doesn't
+/// send one within the command timeout. Keepalive replies and the like can show up while we wait,
+/// so anything `parse` doesn't accept is skipped.
+async fn query_lovense_device<T>(
+  device: &Hardware,
+  query: &[u8],
+  parse: impl Fn(&str) -> Option<T>,
+) -> Result<Option<T>, ButtplugDeviceError> {
+  let mut event_receiver = device.event_stream();
+  device
+    .write_value(&HardwareWriteCmd::new(Endpoint::Tx, query.to_vec(), false))
+    .await?;
+  let timeout = sleep(Duration::from_millis(LOVENSE_COMMAND_TIMEOUT_MS)).fuse();
+  pin_mut!(timeout);
+  loop {
+    select! {
+      event = event_receiver.recv().fuse() => match event {
+        Ok(HardwareEvent::Notification(_, _, data)) => {
+          if let Some(value) = std::str::from_utf8(&data).ok().and_then(&parse) {
+            return Ok(Some(value));
+          }
+        }
+        Ok(HardwareEvent::Rssi(..)) | Err(RecvError::Lagged(_)) => {}
+        Ok(HardwareEvent::Disconnected(_)) | Err(RecvError::Closed) => {
+          return Err(ButtplugDeviceError::ProtocolSpecificError(
+            "Lovense".to_owned(),
+            "Lovense Device disconnected while getting diagnostics.".to_owned(),
+          ))
+        }
+      },
+      _ = timeout => return Ok(None),
+    }
+  }
+}
+
 #[async_trait]
 impl ProtocolIdentifier for LovenseIdentifier {
   async fn identify(
@@ -100,8 +186,9 @@ impl ProtocolIdentifier for LovenseIdentifier {
           if let Ok(HardwareEvent::Notification(_, _, n)) = event {
             let type_response = std::str::from_utf8(&n).map_err(|_| ButtplugDeviceError::ProtocolSpecificError("lovense".to_owned(), "Lovense device init got back non-UTF8 string.".to_owned()))?.to_owned();
             debug!("Lovense Device Type Response: {}", type_response);
+            let firmware_version = lovense_firmware_version(&type_response);
             let ident = lovense_model_resolver(type_response);
-            return Ok((UserDeviceIdentifier::new(hardware.address(), "lovense", &Some(ident.clone())), Box::new(LovenseInitializer::new(ident))));
+            return Ok((UserDeviceIdentifier::new(hardware.address(), "lovense", &Some(ident.clone())), Box::new(LovenseInitializer::new(ident, firmware_version))));
           } else {
             return Err(
               ButtplugDeviceError::ProtocolSpecificError(
@@ -118,9 +205,9 @@ impl ProtocolIdentifier for LovenseIdentifier {
             let re = Regex::new(r"LVS-([A-Z]+)\d+").expect("Static regex shouldn't fail");
             if let Some(caps) = re.captures(hardware.name()) {
               info!("Lovense Device identified by BLE name");
-              return Ok((UserDeviceIdentifier::new(hardware.address(), "lovense", &Some(caps[1].to_string())), Box::new(LovenseInitializer::new(caps[1].to_string()))));
+              return Ok((UserDeviceIdentifier::new(hardware.address(), "lovense", &Some(caps[1].to_string())), Box::new(LovenseInitializer::new(caps[1].to_string(), None))));
             };
-            return Ok((UserDeviceIdentifier::new(hardware.address(), "lovense", &None), Box::new(LovenseInitializer::new("".to_string()))));
+            return Ok((UserDeviceIdentifier::new(hardware.address(), "lovense", &None), Box::new(LovenseInitializer::new("".to_string(), None))));
           }
         }
       }
@@ -129,11 +216,15 @@ impl ProtocolIdentifier for LovenseIdentifier {
 }
 pub struct LovenseInitializer {
   device_type: String,
+  firmware_version: Option<String>,
 }
 
 impl LovenseInitializer {
-  pub fn new(device_type: String) -> Self {
-    Self { device_type }
+  pub fn new(device_type: String, firmware_version: Option<String>) -> Self {
+    Self {
+      device_type,
+      firmware_version,
+    }
   }
 }
 
@@ -163,6 +254,14 @@ impl ProtocolInitializer for LovenseInitializer {
     let use_mply =
       (vibrator_count == 2 && actuator_count > 2) || vibrator_count > 2 || device_type == "H";
 
+    let has_linear = device_definition.features().iter().any(|x| {
+      x.actuator().as_ref().is_some_and(|actuator| {
+        actuator
+          .messages()
+          .contains(&ButtplugActuatorFeatureMessageType::LinearCmd)
+      })
+    });
+
     // New Lovense devices seem to be moving to the simplified LVS:<bytearray>; command format.
     // I'm not sure if there's a good way to detect this.
     let use_lvs = device_type == "OC";
@@ -180,6 +279,9 @@ impl ProtocolInitializer for LovenseInitializer {
       vibrator_count,
       use_mply,
       use_lvs,
+      has_linear,
+      self.firmware_version.clone(),
+      LovenseInputs::new(device_definition),
     )))
   }
 }
@@ -190,25 +292,45 @@ pub struct Lovense {
   use_mply: bool,
   use_lvs: bool,
   device_type: String,
-  // Pairing of position: u8, duration: u32
-  linear_info: Arc<(AtomicU8, AtomicU32)>,
+  firmware_version: Option<String>,
+  // Only set for devices with a position feature, which are moved by update_linear_movement.
+  linear_movement: Option<Arc<LinearMovement>>,
+  stroker_mode: AtomicU8,
+  // Only set for remotes, which have buttons to report instead of motors.
+  inputs: Option<LovenseInputs>,
+}
+
+#[derive(Default)]
+struct LinearMovement {
+  goal_position: AtomicU8,
+  duration: AtomicU32,
+  // Last position sent to the device.
+  current_position: AtomicU8,
+  // Commands stopping the speed side, written by the movement task before it moves so they can't
+  // end up queued behind position updates.
+  speed_stop: Mutex<Vec<HardwareCommand>>,
 }
 
 impl Lovense {
+  #[allow(clippy::too_many_arguments)]
   pub fn new(
     hardware: Arc<Hardware>,
     device_type: &str,
     vibrator_count: usize,
     use_mply: bool,
     use_lvs: bool,
+    has_linear: bool,
+    firmware_version: Option<String>,
+    inputs: Option<LovenseInputs>,
   ) -> Self {
-    let linear_info = Arc::new((AtomicU8::new(0), AtomicU32::new(0)));
-    if device_type == "BA" {
+    let linear_movement = has_linear.then(|| {
+      let linear_movement = Arc::new(LinearMovement::default());
       async_manager::spawn(update_linear_movement(
         hardware.clone(),
-        linear_info.clone(),
+        linear_movement.clone(),
       ));
-    }
+      linear_movement
+    });
 
     Self {
       rotation_direction: Arc::new(AtomicBool::new(false)),
@@ -216,22 +338,14 @@ impl Lovense {
       use_mply,
       use_lvs,
       device_type: device_type.to_owned(),
-      linear_info,
+      firmware_version,
+      linear_movement,
+      stroker_mode: AtomicU8::new(STROKER_MODE_IDLE),
+      inputs,
     }
   }
-}
-
-impl ProtocolHandler for Lovense {
-  fn keepalive_strategy(&self) -> super::ProtocolKeepaliveStrategy {
-    // For Lovense, we'll just repeat the device type packet and drop the result.
-    super::ProtocolKeepaliveStrategy::RepeatPacketStrategy(HardwareWriteCmd::new(
-      Endpoint::Tx,
-      b"DeviceType;".to_vec(),
-      false,
-    ))
-  }
 
-  fn handle_scalar_cmd(
+  fn scalar_commands(
     &self,
     cmds: &[Option<(ActuatorType, u32)>],
   ) -> Result<Vec<HardwareCommand>, ButtplugDeviceError> {
@@ -358,6 +472,77 @@ impl ProtocolHandler for Lovense {
 
     Ok(hardware_cmds)
   }
+}
+
+impl ProtocolHandler for Lovense {
+  fn needs_full_command_set(&self) -> bool {
+    // Position commands stop the speed side behind the command manager's back, so it can't skip
+    // resending a speed it thinks the device already has.
+    self.linear_movement.is_some()
+  }
+
+  fn timing_policy(&self) -> super::ProtocolTimingPolicy {
+    // Lovense firmware starts dropping commands past about 10 a second.
+    super::ProtocolTimingPolicy::new(100)
+  }
+
+  fn write_retry_policy(&self) -> super::ProtocolWriteRetryPolicy {
+    // Commands set absolute levels, so resending one that may have gone through is harmless.
+    super::ProtocolWriteRetryPolicy::new(2, 20)
+  }
+
+  fn keepalive_strategy(&self) -> super::ProtocolKeepaliveStrategy {
+    // For Lovense, we'll just repeat the device type packet and drop the result.
+    super::ProtocolKeepaliveStrategy::RepeatPacketStrategy(HardwareWriteCmd::new(
+      Endpoint::Tx,
+      b"DeviceType;".to_vec(),
+      false,
+    ))
+  }
+
+  fn firmware_version(&self) -> Option<String> {
+    self.firmware_version.clone()
+  }
+
+  fn firmware_pattern_count(&self) -> u32 {
+    // Presets only drive the vibrators, so toys without them (like the Solace) don't have any.
+    if self.vibrator_count > 0 {
+      LOVENSE_PRESET_COUNT
+    } else {
+      0
+    }
+  }
+
+  fn handle_firmware_pattern_cmd(
+    &self,
+    pattern: Option<u32>,
+  ) -> Result<Vec<HardwareCommand>, ButtplugDeviceError> {
+    let preset = pattern.map(|index| index + 1).unwrap_or(0);
+    let lovense_cmd = format!("Preset:{};", preset).as_bytes().to_vec();
+    Ok(vec![HardwareWriteCmd::new(
+      Endpoint::Tx,
+      lovense_cmd,
+      false,
+    )
+    .into()])
+  }
+
+  fn handle_scalar_cmd(
+    &self,
+    cmds: &[Option<(ActuatorType, u32)>],
+  ) -> Result<Vec<HardwareCommand>, ButtplugDeviceError> {
+    if let Some(linear_movement) = &self.linear_movement {
+      // Any speed command, including a stop, takes the stroker out of position mode by holding it
+      // wherever it got to.
+      if self.stroker_mode.swap(STROKER_MODE_SPEED, Ordering::SeqCst) == STROKER_MODE_POSITION {
+        linear_movement.goal_position.store(
+          linear_movement.current_position.load(Ordering::SeqCst),
+          Ordering::SeqCst,
+        );
+      }
+    }
+    self.scalar_commands(cmds)
+  }
 
   fn handle_rotate_cmd(
     &self,
@@ -420,6 +605,7 @@ impl ProtocolHandler for Lovense {
               }
             }
           }
+          HardwareEvent::Rssi(..) => {}
           HardwareEvent::Disconnected(_) => {
             return Err(ButtplugDeviceError::ProtocolSpecificError(
               "Lovense".to_owned(),
@@ -436,40 +622,129 @@ impl ProtocolHandler for Lovense {
     .boxed()
   }
 
+  fn event_stream(&self) -> Pin<Box<dyn Stream<Item = ButtplugServerDeviceMessage> + Send>> {
+    match &self.inputs {
+      Some(inputs) => inputs.event_stream(),
+      None => Box::pin(futures::stream::empty()),
+    }
+  }
+
+  fn handle_sensor_subscribe_cmd(
+    &self,
+    device: Arc<Hardware>,
+    message: &message::SensorSubscribeCmdV4,
+  ) -> BoxFuture<Result<(), ButtplugDeviceError>> {
+    let result = match &self.inputs {
+      Some(inputs) => inputs.subscribe(device, message),
+      None => Err(ButtplugDeviceError::ProtocolSensorNotSupported(
+        *message.sensor_type(),
+      )),
+    };
+    future::ready(result).boxed()
+  }
+
+  fn handle_sensor_unsubscribe_cmd(
+    &self,
+    _device: Arc<Hardware>,
+    message: &message::SensorUnsubscribeCmdV4,
+  ) -> BoxFuture<Result<(), ButtplugDeviceError>> {
+    let result = match &self.inputs {
+      Some(inputs) => {
+        inputs.unsubscribe(message);
+        Ok(())
+      }
+      None => Err(ButtplugDeviceError::ProtocolSensorNotSupported(
+        *message.sensor_type(),
+      )),
+    };
+    future::ready(result).boxed()
+  }
+
+  fn handle_diagnostics(
+    &self,
+    device: Arc<Hardware>,
+  ) -> BoxFuture<'static, Result<ProtocolDiagnostics, ButtplugDeviceError>> {
+    async move {
+      let firmware_version =
+        query_lovense_device(&device, b"DeviceType;", lovense_firmware_version).await?;
+      let battery_level = query_lovense_device(&device, b"Battery;", lovense_battery_level).await?;
+      Ok(ProtocolDiagnostics::new(
+        firmware_version,
+        battery_level,
+        vec![],
+      ))
+    }
+    .boxed()
+  }
+
   fn handle_linear_cmd(
     &self,
     message: message::LinearCmdV4,
   ) -> Result<Vec<HardwareCommand>, ButtplugDeviceError> {
+    let Some(linear_movement) = &self.linear_movement else {
+      return self.command_unimplemented("LinearCmd");
+    };
     let vector = message
       .vectors()
       .first()
       .expect("Already checked for vector subcommand");
-    self
-      .linear_info
-      .0
-      .store((vector.position() * 100f64) as u8, Ordering::SeqCst);
-    self
-      .linear_info
-      .1
+    if self
+      .stroker_mode
+      .swap(STROKER_MODE_POSITION, Ordering::SeqCst)
+      == STROKER_MODE_SPEED
+    {
+      let speed_stop = self.scalar_commands(&vec![
+        Some((ActuatorType::Oscillate, 0));
+        self.vibrator_count
+      ])?;
+      // Has to be in place before the goal changes, see update_linear_movement.
+      *linear_movement
+        .speed_stop
+        .lock()
+        .expect("Lock is never held across a panic") = speed_stop;
+    }
+    linear_movement
+      .duration
       .store(vector.duration(), Ordering::SeqCst);
+    linear_movement
+      .goal_position
+      .store((vector.position() * 100f64) as u8, Ordering::SeqCst);
     Ok(vec![])
   }
 }
 
-async fn update_linear_movement(device: Arc<Hardware>, linear_info: Arc<(AtomicU8, AtomicU32)>) {
+async fn update_linear_movement(device: Arc<Hardware>, linear_movement: Arc<LinearMovement>) {
   let mut last_goal_position = 0i32;
   let mut current_move_amount = 0i32;
   let mut current_position = 0i32;
   loop {
     // See if we've updated our goal position
-    let goal_position = linear_info.0.load(Ordering::Relaxed) as i32;
+    let goal_position = linear_movement.goal_position.load(Ordering::SeqCst) as i32;
+    // Speed stops are stored before the goal that comes with them, so having read the goal, we're
+    // guaranteed to stop the speed side before moving towards it.
+    let speed_stop = std::mem::take(
+      &mut *linear_movement
+        .speed_stop
+        .lock()
+        .expect("Lock is never held across a panic"),
+    );
+    for command in speed_stop {
+      if device.parse_message(&command).await.is_err() {
+        return;
+      }
+    }
     // If we have and it's not the same, recalculate based on current status.
     if last_goal_position != goal_position {
       last_goal_position = goal_position;
       // We move every 100ms, so divide the movement into that many chunks.
       // If we're moving so fast it'd be under our 100ms boundary, just move in 1 step.
-      let move_steps = (linear_info.1.load(Ordering::Relaxed) / 100).max(1);
-      current_move_amount = (goal_position as i32 - current_position) as i32 / move_steps as i32;
+      let move_steps = (linear_movement.duration.load(Ordering::Relaxed) / 100).max(1);
+      let distance = goal_position - current_position;
+      // Always move at least one step, or short slow moves would never get anywhere.
+      current_move_amount = distance / move_steps as i32;
+      if current_move_amount == 0 {
+        current_move_amount = distance.signum();
+      }
     }
 
     // If we aren't going anywhere, just pause then restart
@@ -496,6 +771,9 @@ async fn update_linear_movement(device: Arc<Hardware>, linear_info: Arc<(AtomicU
     if device.write_value(&hardware_cmd).await.is_err() {
       return;
     }
+    linear_movement
+      .current_position
+      .store(current_position as u8, Ordering::Relaxed);
     sleep(Duration::from_millis(100)).await;
   }
 }
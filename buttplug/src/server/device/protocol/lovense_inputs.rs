@@ -0,0 +1,250 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2024 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Button and dial input from Lovense remotes.
+//!
+//! Lovense's wireless remotes identify like any other Lovense device, but have no motors. Instead
+//! they report input over the same rx characteristic toys reply on, in the same semicolon
+//! terminated format:
+//!
+//! - "Key:<key>:<state>;" when a key (counting from 1) is pressed (state 1) or released (state 0).
+//! - "Dial:<level>;" when the dial is turned.
+//!
+//! Inputs show up as Button sensor features that can be subscribed to, so they can drive toys
+//! through device links. Keys are the button features with a 0-1 value range, in the order the
+//! config lists them, and the dial is the first button feature with a wider range.
+
+use crate::{
+  core::{
+    errors::ButtplugDeviceError,
+    message::{
+      ButtplugDeviceMessage,
+      ButtplugSensorFeatureMessageType,
+      ButtplugServerDeviceMessage,
+      FeatureType,
+      SensorReadingV4,
+      SensorSubscribeCmdV4,
+      SensorType,
+      SensorUnsubscribeCmdV4,
+    },
+  },
+  server::device::{
+    configuration::UserDeviceDefinition,
+    hardware::{Hardware, HardwareEvent},
+  },
+  util::{async_manager, stream::convert_broadcast_receiver_to_stream},
+};
+use dashmap::DashSet;
+use futures::{Stream, StreamExt};
+use std::{
+  pin::Pin,
+  sync::{
+    atomic::{AtomicBool, AtomicU32, Ordering},
+    Arc,
+  },
+};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LovenseInput {
+  Key { key: u32, pressed: bool },
+  Dial(i32),
+}
+
+fn parse_lovense_input(message: &str) -> Option<LovenseInput> {
+  let mut parts = message.trim().strip_suffix(';')?.split(':');
+  match (parts.next()?, parts.next(), parts.next(), parts.next()) {
+    ("Key", Some(key), Some(state), None) => Some(LovenseInput::Key {
+      key: key.parse().ok()?,
+      pressed: match state {
+        "0" => false,
+        "1" => true,
+        _ => return None,
+      },
+    }),
+    ("Dial", Some(level), None, None) => Some(LovenseInput::Dial(level.parse().ok()?)),
+    _ => None,
+  }
+}
+
+/// Which features a remote's inputs show up as.
+#[derive(Clone)]
+struct LovenseInputLayout {
+  /// Feature indexes of the keys, in key order.
+  key_features: Vec<u32>,
+  dial_feature: Option<u32>,
+}
+
+impl LovenseInputLayout {
+  fn contains(&self, feature_index: u32) -> bool {
+    self.key_features.contains(&feature_index) || self.dial_feature == Some(feature_index)
+  }
+
+  /// Feature index and reading value for an input, if the remote has a feature for it.
+  fn reading(&self, input: LovenseInput) -> Option<(u32, i32)> {
+    match input {
+      LovenseInput::Key { key, pressed } => {
+        let feature_index = *self.key_features.get(key.checked_sub(1)? as usize)?;
+        Some((feature_index, pressed as i32))
+      }
+      LovenseInput::Dial(level) => Some((self.dial_feature?, level)),
+    }
+  }
+}
+
+/// Turns input notifications from a Lovense remote into sensor readings for subscribed features.
+pub struct LovenseInputs {
+  layout: LovenseInputLayout,
+  subscribed_sensors: Arc<DashSet<u32>>,
+  // Readings go out under whatever index the last subscription used.
+  device_index: Arc<AtomicU32>,
+  listening: AtomicBool,
+  event_stream: broadcast::Sender<ButtplugServerDeviceMessage>,
+}
+
+impl LovenseInputs {
+  /// Set up input handling for the button features of `definition`, or None if it doesn't have
+  /// any that can be subscribed to.
+  pub(super) fn new(definition: &UserDeviceDefinition) -> Option<Self> {
+    let mut layout = LovenseInputLayout {
+      key_features: vec![],
+      dial_feature: None,
+    };
+    for (index, feature) in definition.features().iter().enumerate() {
+      if *feature.feature_type() != FeatureType::Button {
+        continue;
+      }
+      let Some(sensor) = feature.sensor().as_ref().filter(|sensor| {
+        sensor
+          .messages()
+          .contains(&ButtplugSensorFeatureMessageType::SensorSubscribeCmd)
+      }) else {
+        continue;
+      };
+      match sensor.value_range().first() {
+        Some(range) if *range.end() > 1 => {
+          layout.dial_feature.get_or_insert(index as u32);
+        }
+        _ => layout.key_features.push(index as u32),
+      }
+    }
+    if layout.key_features.is_empty() && layout.dial_feature.is_none() {
+      return None;
+    }
+    let (event_stream, _) = broadcast::channel(256);
+    Some(Self {
+      layout,
+      subscribed_sensors: Arc::new(DashSet::new()),
+      device_index: Arc::new(AtomicU32::new(0)),
+      listening: AtomicBool::new(false),
+      event_stream,
+    })
+  }
+
+  pub(super) fn event_stream(
+    &self,
+  ) -> Pin<Box<dyn Stream<Item = ButtplugServerDeviceMessage> + Send>> {
+    convert_broadcast_receiver_to_stream(self.event_stream.subscribe()).boxed()
+  }
+
+  pub(super) fn subscribe(
+    &self,
+    device: Arc<Hardware>,
+    message: &SensorSubscribeCmdV4,
+  ) -> Result<(), ButtplugDeviceError> {
+    let feature_index = *message.feature_index();
+    if !self.layout.contains(feature_index) {
+      return Err(ButtplugDeviceError::ProtocolSensorNotSupported(
+        *message.sensor_type(),
+      ));
+    }
+    self
+      .device_index
+      .store(message.device_index(), Ordering::SeqCst);
+    self.subscribed_sensors.insert(feature_index);
+    // The rx characteristic was subscribed to while identifying the device, so all that's left is
+    // to start listening to it.
+    if !self.listening.swap(true, Ordering::SeqCst) {
+      self.listen(device);
+    }
+    Ok(())
+  }
+
+  pub(super) fn unsubscribe(&self, message: &SensorUnsubscribeCmdV4) {
+    self.subscribed_sensors.remove(message.feature_index());
+  }
+
+  fn listen(&self, device: Arc<Hardware>) {
+    let mut hardware_stream = device.event_stream();
+    let layout = self.layout.clone();
+    let subscribed_sensors = self.subscribed_sensors.clone();
+    let device_index = self.device_index.clone();
+    let sender = self.event_stream.clone();
+    async_manager::spawn(async move {
+      loop {
+        let data = match hardware_stream.recv().await {
+          Ok(HardwareEvent::Notification(_, _, data)) => data,
+          Ok(HardwareEvent::Rssi(..)) | Err(RecvError::Lagged(_)) => continue,
+          Ok(HardwareEvent::Disconnected(_)) | Err(RecvError::Closed) => break,
+        };
+        // Replies to keepalives and battery queries come in here too, and get skipped.
+        let Some((feature_index, value)) = std::str::from_utf8(&data)
+          .ok()
+          .and_then(parse_lovense_input)
+          .and_then(|input| layout.reading(input))
+        else {
+          continue;
+        };
+        if subscribed_sensors.contains(&feature_index) {
+          // Having no one listening right now is fine, someone may subscribe again later.
+          let _ = sender.send(
+            SensorReadingV4::new(
+              device_index.load(Ordering::SeqCst),
+              feature_index,
+              SensorType::Button,
+              vec![value],
+            )
+            .into(),
+          );
+        }
+      }
+      debug!("Lovense device disconnected, no longer listening for input.");
+    });
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{parse_lovense_input, LovenseInput};
+
+  #[test]
+  fn test_parse_lovense_input() {
+    assert_eq!(
+      parse_lovense_input("Key:2:1;"),
+      Some(LovenseInput::Key {
+        key: 2,
+        pressed: true
+      })
+    );
+    assert_eq!(
+      parse_lovense_input("Key:1:0;"),
+      Some(LovenseInput::Key {
+        key: 1,
+        pressed: false
+      })
+    );
+    assert_eq!(
+      parse_lovense_input("Dial:13;"),
+      Some(LovenseInput::Dial(13))
+    );
+    // Regular replies aren't input.
+    assert_eq!(parse_lovense_input("RC:11:0082059AD3BD;"), None);
+    assert_eq!(parse_lovense_input("s89;"), None);
+    assert_eq!(parse_lovense_input("Key:1:2;"), None);
+    assert_eq!(parse_lovense_input("Dial:13"), None);
+  }
+}
@@ -0,0 +1,66 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2024 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Luvmazer, Funway and the other white-label BLE toys built on the same board.
+//!
+//! They all take a 6 byte packet on a single characteristic, differing only in the advertised name
+//! and which motors are fitted, so one protocol covers the lot. New names can be attached from a
+//! user config with just a `btle` `names` list, as the services are filled in from this protocol's
+//! base config.
+
+use crate::{
+  core::{errors::ButtplugDeviceError, message::Endpoint},
+  server::device::{
+    hardware::{HardwareCommand, HardwareWriteCmd},
+    protocol::{generic_protocol_setup, ProtocolHandler},
+  },
+};
+
+generic_protocol_setup!(Luvmazer, "luvmazer");
+
+const LUVMAZER_PACKET_HEADER: u8 = 0xa0;
+const LUVMAZER_VIBRATE_MOTOR: u8 = 0x01;
+const LUVMAZER_ROTATE_MOTOR: u8 = 0x0f;
+// Ceiling the level is scaled against, sent with every command.
+const LUVMAZER_LEVEL_MAX: u8 = 0x64;
+
+fn motor_command(motor: u8, level: u32) -> Vec<HardwareCommand> {
+  vec![HardwareWriteCmd::new(
+    Endpoint::Tx,
+    vec![
+      LUVMAZER_PACKET_HEADER,
+      motor,
+      0x00,
+      0x00,
+      LUVMAZER_LEVEL_MAX,
+      level as u8,
+    ],
+    false,
+  )
+  .into()]
+}
+
+#[derive(Default)]
+pub struct Luvmazer {}
+
+impl ProtocolHandler for Luvmazer {
+  fn handle_scalar_vibrate_cmd(
+    &self,
+    _index: u32,
+    scalar: u32,
+  ) -> Result<Vec<HardwareCommand>, ButtplugDeviceError> {
+    Ok(motor_command(LUVMAZER_VIBRATE_MOTOR, scalar))
+  }
+
+  fn handle_scalar_rotate_cmd(
+    &self,
+    _index: u32,
+    scalar: u32,
+  ) -> Result<Vec<HardwareCommand>, ButtplugDeviceError> {
+    Ok(motor_command(LUVMAZER_ROTATE_MOTOR, scalar))
+  }
+}
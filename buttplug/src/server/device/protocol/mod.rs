@@ -32,6 +32,7 @@ pub mod fredorch;
 pub mod fredorch_rotary;
 pub mod galaku;
 pub mod galaku_pump;
+pub mod gaming_input;
 pub mod hgod;
 pub mod hismith;
 pub mod hismith_mini;
@@ -63,7 +64,9 @@ pub mod lovedistance;
 pub mod lovehoney_desire;
 pub mod lovense;
 pub mod lovense_connect_service;
+pub mod lovense_inputs;
 pub mod lovenuts;
+pub mod luvmazer;
 pub mod magic_motion_v1;
 pub mod magic_motion_v2;
 pub mod magic_motion_v3;
@@ -86,9 +89,11 @@ pub mod mysteryvibe_v2;
 pub mod nextlevelracing;
 pub mod nintendo_joycon;
 pub mod nobra;
+pub mod osc_tracker;
 pub mod patoo;
 pub mod picobong;
 pub mod pink_punch;
+pub mod playstation;
 pub mod prettylove;
 pub mod raw_protocol;
 pub mod realov;
@@ -123,6 +128,7 @@ pub mod thehandy;
 pub mod tryfun;
 pub mod vibcrafter;
 pub mod vibratissimo;
+pub mod vorze_piston;
 pub mod vorze_sa;
 pub mod wetoy;
 pub mod wevibe;
@@ -130,6 +136,7 @@ pub mod wevibe8bit;
 pub mod wevibe_chorus;
 pub mod xibao;
 pub mod xinput;
+pub mod xinput_inputs;
 pub mod xiuxiuda;
 pub mod xuanhuan;
 pub mod youcups;
@@ -161,8 +168,9 @@ use futures::{
   future::{self, BoxFuture, FutureExt},
   StreamExt,
 };
+use serde::{Deserialize, Serialize};
 use std::pin::Pin;
-use std::{collections::HashMap, sync::Arc};
+use std::{collections::HashMap, sync::Arc, time::Duration};
 
 /// Strategy for situations where hardware needs to get updates every so often in order to keep
 /// things alive. Currently this only applies to iOS backgrounding with bluetooth devices, but since
@@ -192,13 +200,152 @@ pub enum ProtocolKeepaliveStrategy {
   NoStrategy,
   /// Repeat a specific packet, such as a ping or a no-op
   RepeatPacketStrategy(HardwareWriteCmd),
-  /// Repeat whatever the last packet sent was, and send Stop commands until first packet sent. This
-  /// will be useful for most devices that purely use scalar commands.
+  /// Repeat whatever the last packet (or write transaction) sent was, and send Stop commands until
+  /// first packet sent. This will be useful for most devices that purely use scalar commands.
   RepeatLastPacketStrategy,
   /// Call a specific method on the protocol implementation to generate keepalive packets.
   CustomStrategy,
 }
 
+/// How a device should respond when a user asks which physical device they're looking at.
+///
+/// The default pulse only touches vibration, oscillation, rotation and linear features, at a low
+/// level and for under a second, so it's safe for most toys. Protocols for devices that shouldn't
+/// be moved without explicit user input (strokers, e-stim, suction) override this.
+#[derive(Debug)]
+pub enum ProtocolIdentifyStrategy {
+  /// Briefly run the device's actuators at a low level, then stop the device.
+  ActuatorPulse,
+  /// Device has no safe way to identify itself.
+  Unsupported,
+}
+
+/// How a device should recover after a write to it fails.
+///
+/// A write failing partway through a command series can leave a device (or our idea of its state)
+/// out of sync, for protocols that split commands over several packets or track positions between
+/// commands.
+#[derive(Debug)]
+pub enum ProtocolWriteFailureStrategy {
+  /// Rerun the protocol's initialization sequence against the hardware.
+  Reinitialize,
+  /// Send a protocol specific set of commands to put the device back into a known state.
+  Commands(Vec<HardwareCommand>),
+  /// Nothing needs to be resynced.
+  Ignore,
+}
+
+/// How much hardware access for a device may overlap. Enforced by the device's [Hardware], so it
+/// covers everything that talks to the device: actuator commands, sensors, raw commands,
+/// keepalives and recovery.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ProtocolCommandConcurrency {
+  /// Reads, writes and subscriptions on the same endpoint run one at a time, in the order they
+  /// were issued, while different endpoints can be used at the same time.
+  #[default]
+  PerEndpoint,
+  /// Only one read, write or subscription is in flight at a time across the whole device, so each
+  /// finishes before the next one starts. For protocols that get confused when traffic on
+  /// different endpoints overlaps, like ones that set a mode on one endpoint before writing values
+  /// to another.
+  Serialized,
+}
+
+/// How fast a protocol's devices can take actuator updates. The device holds updates back to this
+/// rate, folding together ones that come in faster, and reports it to clients as the device's
+/// message timing gap so applications don't have to know it themselves.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ProtocolTimingPolicy {
+  min_command_interval_ms: Option<u32>,
+}
+
+impl ProtocolTimingPolicy {
+  /// Updates are sent no closer together than `min_command_interval_ms`.
+  pub fn new(min_command_interval_ms: u32) -> Self {
+    Self {
+      min_command_interval_ms: Some(min_command_interval_ms),
+    }
+  }
+
+  /// Shortest time between the starts of two actuator updates, or None if the device has no limit.
+  pub fn min_command_interval_ms(&self) -> Option<u32> {
+    self.min_command_interval_ms
+  }
+}
+
+/// How a protocol's writes are retried when the transport reports a failure that may only be
+/// transient, like the occasional failed GATT write on Bluetooth LE. A write that reported failure
+/// may still have made it to the device, so this should only be turned on for protocols whose
+/// writes are safe to send twice, like ones that set absolute levels.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ProtocolWriteRetryPolicy {
+  max_retries: u32,
+  backoff_ms: u32,
+}
+
+impl ProtocolWriteRetryPolicy {
+  /// Retry a failed write up to `max_retries` times, waiting `backoff_ms` before the first retry
+  /// and twice as long before each one after that.
+  pub fn new(max_retries: u32, backoff_ms: u32) -> Self {
+    Self {
+      max_retries,
+      backoff_ms,
+    }
+  }
+
+  pub fn max_retries(&self) -> u32 {
+    self.max_retries
+  }
+
+  pub fn backoff_ms(&self) -> u32 {
+    self.backoff_ms
+  }
+
+  /// Wait before retry number `retry`, counting from 1.
+  pub(crate) fn backoff(&self, retry: u32) -> Duration {
+    Duration::from_millis(self.backoff_ms as u64)
+      .saturating_mul(2u32.saturating_pow(retry.saturating_sub(1)))
+  }
+}
+
+/// What a device could tell us about itself when asked for diagnostics, for support tooling and
+/// bug reports. Anything the hardware can't report is left empty.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProtocolDiagnostics {
+  firmware_version: Option<String>,
+  battery_level: Option<u32>,
+  error_flags: Vec<String>,
+}
+
+impl ProtocolDiagnostics {
+  pub fn new(
+    firmware_version: Option<String>,
+    battery_level: Option<u32>,
+    error_flags: Vec<String>,
+  ) -> Self {
+    Self {
+      firmware_version,
+      battery_level,
+      error_flags,
+    }
+  }
+
+  /// Firmware version, as reported by the device.
+  pub fn firmware_version(&self) -> Option<&str> {
+    self.firmware_version.as_deref()
+  }
+
+  /// Battery level, as a percentage.
+  pub fn battery_level(&self) -> Option<u32> {
+    self.battery_level
+  }
+
+  /// Problems the device reports about itself, in whatever terms the protocol uses.
+  pub fn error_flags(&self) -> &[String] {
+    &self.error_flags
+  }
+}
+
 pub trait ProtocolIdentifierFactory: Send + Sync {
   fn identifier(&self) -> &str;
   fn create(&self) -> Box<dyn ProtocolIdentifier>;
@@ -296,6 +443,10 @@ pub fn get_default_protocol_map() -> HashMap<String, Arc<dyn ProtocolIdentifierF
   );
 
   add_to_protocol_map(&mut map, galaku::setup::GalakuIdentifierFactory::default());
+  add_to_protocol_map(
+    &mut map,
+    gaming_input::setup::GamingInputIdentifierFactory::default(),
+  );
 
   add_to_protocol_map(&mut map, itoys::setup::IToysIdentifierFactory::default());
   add_to_protocol_map(&mut map, jejoue::setup::JeJoueIdentifierFactory::default());
@@ -387,6 +538,10 @@ pub fn get_default_protocol_map() -> HashMap<String, Arc<dyn ProtocolIdentifierF
     &mut map,
     lovenuts::setup::LoveNutsIdentifierFactory::default(),
   );
+  add_to_protocol_map(
+    &mut map,
+    luvmazer::setup::LuvmazerIdentifierFactory::default(),
+  );
   add_to_protocol_map(
     &mut map,
     magic_motion_v1::setup::MagicMotionV1IdentifierFactory::default(),
@@ -463,6 +618,10 @@ pub fn get_default_protocol_map() -> HashMap<String, Arc<dyn ProtocolIdentifierF
     nintendo_joycon::setup::NintendoJoyconIdentifierFactory::default(),
   );
   add_to_protocol_map(&mut map, nobra::setup::NobraIdentifierFactory::default());
+  add_to_protocol_map(
+    &mut map,
+    osc_tracker::setup::OscTrackerIdentifierFactory::default(),
+  );
   add_to_protocol_map(&mut map, patoo::setup::PatooIdentifierFactory::default());
   add_to_protocol_map(
     &mut map,
@@ -472,6 +631,10 @@ pub fn get_default_protocol_map() -> HashMap<String, Arc<dyn ProtocolIdentifierF
     &mut map,
     pink_punch::setup::PinkPunchIdentifierFactory::default(),
   );
+  add_to_protocol_map(
+    &mut map,
+    playstation::setup::PlayStationIdentifierFactory::default(),
+  );
   add_to_protocol_map(
     &mut map,
     prettylove::setup::PrettyLoveIdentifierFactory::default(),
@@ -589,6 +752,10 @@ pub fn get_default_protocol_map() -> HashMap<String, Arc<dyn ProtocolIdentifierF
     &mut map,
     vibratissimo::setup::VibratissimoIdentifierFactory::default(),
   );
+  add_to_protocol_map(
+    &mut map,
+    vorze_piston::setup::VorzePistonIdentifierFactory::default(),
+  );
   add_to_protocol_map(
     &mut map,
     vorze_sa::setup::VorzeSAIdentifierFactory::default(),
@@ -664,6 +831,10 @@ pub trait ProtocolIdentifier: Sync + Send {
   ) -> Result<(UserDeviceIdentifier, Box<dyn ProtocolInitializer>), ButtplugDeviceError>;
 }
 
+/// Runs protocol setup on newly connected hardware and builds its handler.
+///
+/// With the default [ProtocolWriteFailureStrategy], this may be run again on an already connected
+/// device after a write failure. Handlers returned by those later runs are dropped.
 #[async_trait]
 pub trait ProtocolInitializer: Sync + Send {
   async fn initialize(
@@ -709,25 +880,25 @@ impl ProtocolIdentifier for GenericProtocolIdentifier {
 }
 
 pub struct GenericProtocolInitializer {
-  handler: Option<Arc<dyn ProtocolHandler>>,
+  handler: Arc<dyn ProtocolHandler>,
 }
 
 impl GenericProtocolInitializer {
   pub fn new(handler: Arc<dyn ProtocolHandler>) -> Self {
-    Self {
-      handler: Some(handler),
-    }
+    Self { handler }
   }
 }
 
 #[async_trait]
 impl ProtocolInitializer for GenericProtocolInitializer {
+  // Initializers can be rerun to recover from write failures, so hand back the same handler each
+  // time.
   async fn initialize(
     &mut self,
     _: Arc<Hardware>,
     _: &UserDeviceDefinition,
   ) -> Result<Arc<dyn ProtocolHandler>, ButtplugDeviceError> {
-    Ok(self.handler.take().unwrap())
+    Ok(self.handler.clone())
   }
 }
 
@@ -744,6 +915,55 @@ pub trait ProtocolHandler: Sync + Send {
     ProtocolKeepaliveStrategy::NoStrategy
   }
 
+  fn identify_strategy(&self) -> ProtocolIdentifyStrategy {
+    ProtocolIdentifyStrategy::ActuatorPulse
+  }
+
+  /// Called by the device after a write fails. Protocols that keep state about the device in
+  /// their handler should reset it here, as the handler stays in use after recovery.
+  fn on_write_failure(&self) -> ProtocolWriteFailureStrategy {
+    ProtocolWriteFailureStrategy::Reinitialize
+  }
+
+  fn command_concurrency(&self) -> ProtocolCommandConcurrency {
+    ProtocolCommandConcurrency::PerEndpoint
+  }
+
+  fn timing_policy(&self) -> ProtocolTimingPolicy {
+    ProtocolTimingPolicy::default()
+  }
+
+  /// Writes are never retried by default, as there's no telling whether sending one twice is safe.
+  fn write_retry_policy(&self) -> ProtocolWriteRetryPolicy {
+    ProtocolWriteRetryPolicy::default()
+  }
+
+  /// Firmware version the device reported while we were connecting to it, if the protocol knows
+  /// how to get one. Clients get this with the rest of the device info, so apps can work around
+  /// issues in specific firmware versions.
+  fn firmware_version(&self) -> Option<String> {
+    None
+  }
+
+  /// Number of patterns built into the device firmware. Firmware patterns run on the device itself,
+  /// so they keep going through short connection drops. Most protocols don't have these.
+  fn firmware_pattern_count(&self) -> u32 {
+    0
+  }
+
+  /// Start the firmware pattern at `pattern` (0 indexed), or stop the running pattern if `None`.
+  /// Only called for protocols that return a non-zero
+  /// [firmware_pattern_count](ProtocolHandler::firmware_pattern_count), with a pattern index below
+  /// that count.
+  fn handle_firmware_pattern_cmd(
+    &self,
+    _pattern: Option<u32>,
+  ) -> Result<Vec<HardwareCommand>, ButtplugDeviceError> {
+    Err(ButtplugDeviceError::UnhandledCommand(
+      "Protocol does not support firmware patterns".to_owned(),
+    ))
+  }
+
   fn handle_message(
     &self,
     message: &ButtplugDeviceCommandMessageUnion,
@@ -784,6 +1004,7 @@ pub trait ProtocolHandler: Sync + Send {
           ActuatorType::Rotate => self.handle_scalar_rotate_cmd(index as u32, *scalar)?,
           ActuatorType::Vibrate => self.handle_scalar_vibrate_cmd(index as u32, *scalar)?,
           ActuatorType::Position => self.handle_scalar_position_cmd(index as u32, *scalar)?,
+          ActuatorType::Estim => self.handle_scalar_estim_cmd(index as u32, *scalar)?,
           ActuatorType::Unknown => Err(ButtplugDeviceError::UnhandledCommand(
             "Unknown actuator types are not controllable.".to_owned(),
           ))?,
@@ -841,6 +1062,14 @@ pub trait ProtocolHandler: Sync + Send {
     self.command_unimplemented("ScalarCmd (Constrict Actuator)")
   }
 
+  fn handle_scalar_estim_cmd(
+    &self,
+    _index: u32,
+    _scalar: u32,
+  ) -> Result<Vec<HardwareCommand>, ButtplugDeviceError> {
+    self.command_unimplemented("ScalarCmd (Estim Actuator)")
+  }
+
   fn handle_vorze_a10_cyclone_cmd(
     &self,
     message: message::VorzeA10CycloneCmdV0,
@@ -876,6 +1105,15 @@ pub trait ProtocolHandler: Sync + Send {
     self.command_unimplemented(print_type_of(&message))
   }
 
+  /// Positions in `message` are fractions of each axis' step range, with the user's step limits
+  /// already applied.
+  fn handle_axis_cmd(
+    &self,
+    message: message::AxisCmdV4,
+  ) -> Result<Vec<HardwareCommand>, ButtplugDeviceError> {
+    self.command_unimplemented(print_type_of(&message))
+  }
+
   fn handle_sensor_subscribe_cmd(
     &self,
     _device: Arc<Hardware>,
@@ -957,6 +1195,18 @@ pub trait ProtocolHandler: Sync + Send {
     .boxed()
   }
 
+  /// Ask the device about itself (firmware version, battery, error flags, etc...), for support
+  /// tooling and bug reports. Protocols that can't query anything leave this unimplemented.
+  fn handle_diagnostics(
+    &self,
+    _device: Arc<Hardware>,
+  ) -> BoxFuture<'static, Result<ProtocolDiagnostics, ButtplugDeviceError>> {
+    future::ready(Err(ButtplugDeviceError::UnhandledCommand(
+      "Protocol does not support diagnostics".to_owned(),
+    )))
+    .boxed()
+  }
+
   fn event_stream(
     &self,
   ) -> Pin<Box<dyn tokio_stream::Stream<Item = ButtplugServerDeviceMessage> + Send>> {
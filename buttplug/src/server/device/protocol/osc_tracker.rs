@@ -0,0 +1,139 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2024 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+use crate::{
+  core::{
+    errors::ButtplugDeviceError,
+    message::{
+      self,
+      ButtplugDeviceMessage,
+      ButtplugServerDeviceMessage,
+      Endpoint,
+      SensorReadingV4,
+    },
+  },
+  server::device::{
+    hardware::{Hardware, HardwareEvent, HardwareSubscribeCmd, HardwareUnsubscribeCmd},
+    protocol::{generic_protocol_setup, ProtocolHandler},
+  },
+  util::{async_manager, stream::convert_broadcast_receiver_to_stream},
+};
+use dashmap::DashSet;
+use futures::{
+  future::{self, BoxFuture},
+  FutureExt,
+  StreamExt,
+};
+use std::{pin::Pin, sync::Arc};
+use tokio::sync::broadcast;
+
+generic_protocol_setup!(OscTracker, "osc-tracker");
+
+/// Motion trackers fed by the [OSC comm
+/// manager](crate::server::device::hardware::communication::osc). The hardware reports speed in
+/// meters per second, which we pass on as millimeters per second so it fits in a sensor reading.
+pub struct OscTracker {
+  subscribed_sensors: Arc<DashSet<u32>>,
+  event_stream: broadcast::Sender<ButtplugServerDeviceMessage>,
+}
+
+impl Default for OscTracker {
+  fn default() -> Self {
+    let (sender, _) = broadcast::channel(256);
+    Self {
+      subscribed_sensors: Arc::new(DashSet::new()),
+      event_stream: sender,
+    }
+  }
+}
+
+impl ProtocolHandler for OscTracker {
+  fn event_stream(
+    &self,
+  ) -> Pin<Box<dyn futures::Stream<Item = ButtplugServerDeviceMessage> + Send>> {
+    convert_broadcast_receiver_to_stream(self.event_stream.subscribe()).boxed()
+  }
+
+  fn handle_sensor_subscribe_cmd(
+    &self,
+    device: Arc<Hardware>,
+    message: &message::SensorSubscribeCmdV4,
+  ) -> BoxFuture<'_, Result<(), ButtplugDeviceError>> {
+    if self.subscribed_sensors.contains(message.feature_index()) {
+      return future::ready(Ok(())).boxed();
+    }
+    let message = message.clone();
+    let sensors = self.subscribed_sensors.clone();
+    async move {
+      if sensors.is_empty() {
+        device
+          .subscribe(&HardwareSubscribeCmd::new(Endpoint::Rx))
+          .await?;
+        // Trackers start streaming right away, so register the sensor before the listener task
+        // can see an empty set and exit.
+        sensors.insert(*message.feature_index());
+        let sender = self.event_stream.clone();
+        let mut hardware_stream = device.event_stream();
+        let stream_sensors = sensors.clone();
+        let device_index = message.device_index();
+        let sensor_type = *message.sensor_type();
+        async_manager::spawn(async move {
+          while let Ok(info) = hardware_stream.recv().await {
+            if sender.receiver_count() == 0 || stream_sensors.is_empty() {
+              return;
+            }
+            let HardwareEvent::Notification(_, Endpoint::Rx, data) = info else {
+              continue;
+            };
+            let Ok(bytes) = <[u8; 4]>::try_from(data.as_slice()) else {
+              continue;
+            };
+            let speed = (f32::from_be_bytes(bytes) * 1000.0).round() as i32;
+            for sensor_index in stream_sensors.iter() {
+              if sender
+                .send(
+                  SensorReadingV4::new(device_index, *sensor_index, sensor_type, vec![speed])
+                    .into(),
+                )
+                .is_err()
+              {
+                debug!("Hardware device listener for OSC tracker shut down, exiting task.");
+                return;
+              }
+            }
+          }
+        });
+      } else {
+        sensors.insert(*message.feature_index());
+      }
+      Ok(())
+    }
+    .boxed()
+  }
+
+  fn handle_sensor_unsubscribe_cmd(
+    &self,
+    device: Arc<Hardware>,
+    message: &message::SensorUnsubscribeCmdV4,
+  ) -> BoxFuture<'_, Result<(), ButtplugDeviceError>> {
+    if !self.subscribed_sensors.contains(message.feature_index()) {
+      return future::ready(Ok(())).boxed();
+    }
+    let message = message.clone();
+    let sensors = self.subscribed_sensors.clone();
+    async move {
+      sensors.remove(message.feature_index());
+      if sensors.is_empty() {
+        device
+          .unsubscribe(&HardwareUnsubscribeCmd::new(Endpoint::Rx))
+          .await?;
+      }
+      Ok(())
+    }
+    .boxed()
+  }
+}
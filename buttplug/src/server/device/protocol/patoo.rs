@@ -86,7 +86,6 @@ impl ProtocolHandler for Patoo {
     &self,
     cmds: &[Option<(ActuatorType, u32)>],
   ) -> Result<Vec<HardwareCommand>, ButtplugDeviceError> {
-    let mut msg_vec = vec![];
     // Default to vibes
     let mut mode: u8 = 4u8;
 
@@ -107,9 +106,10 @@ impl ProtocolHandler for Patoo {
       mode |= 0x80;
     }
 
-    msg_vec.push(HardwareWriteCmd::new(Endpoint::Tx, vec![speed], true).into());
-    msg_vec.push(HardwareWriteCmd::new(Endpoint::TxMode, vec![mode], true).into());
-
-    Ok(msg_vec)
+    // Speed and mode only make sense together, so don't let anything else get written between them.
+    Ok(vec![HardwareCommand::WriteTransaction(vec![
+      HardwareWriteCmd::new(Endpoint::Tx, vec![speed], true),
+      HardwareWriteCmd::new(Endpoint::TxMode, vec![mode], true),
+    ])])
   }
 }
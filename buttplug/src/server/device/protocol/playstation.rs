@@ -0,0 +1,108 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2024 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! DualShock 4 and DualSense controllers, through the
+//! [PlayStation](crate::server::device::hardware::communication::playstation) communication
+//! manager, which turns the levels sent here into output reports.
+
+use crate::{
+  core::{
+    errors::ButtplugDeviceError,
+    message::{ActuatorType, Endpoint},
+  },
+  server::device::{
+    configuration::{ProtocolCommunicationSpecifier, UserDeviceDefinition, UserDeviceIdentifier},
+    hardware::{Hardware, HardwareCommand, HardwareWriteCmd},
+    protocol::{
+      generic_protocol_initializer_setup,
+      ProtocolHandler,
+      ProtocolIdentifier,
+      ProtocolInitializer,
+      ProtocolTimingPolicy,
+    },
+  },
+};
+use async_trait::async_trait;
+use std::sync::Arc;
+
+generic_protocol_initializer_setup!(PlayStation, "playstation");
+
+#[derive(Default)]
+pub struct PlayStationInitializer {}
+
+#[async_trait]
+impl ProtocolInitializer for PlayStationInitializer {
+  async fn initialize(
+    &mut self,
+    _: Arc<Hardware>,
+    _: &UserDeviceDefinition,
+  ) -> Result<Arc<dyn ProtocolHandler>, ButtplugDeviceError> {
+    Ok(Arc::new(PlayStation::default()))
+  }
+}
+
+#[derive(Default)]
+pub struct PlayStation {}
+
+impl ProtocolHandler for PlayStation {
+  fn needs_full_command_set(&self) -> bool {
+    true
+  }
+
+  fn timing_policy(&self) -> ProtocolTimingPolicy {
+    // Controllers poll for output reports at a few hundred Hz at most, there's no use in going
+    // faster than that.
+    ProtocolTimingPolicy::new(10)
+  }
+
+  fn handle_scalar_cmd(
+    &self,
+    cmds: &[Option<(ActuatorType, u32)>],
+  ) -> Result<Vec<HardwareCommand>, ButtplugDeviceError> {
+    // One byte per actuator in feature order: left motor, right motor, then trigger resistance on
+    // controllers that have it.
+    let levels = cmds
+      .iter()
+      .map(|command| {
+        let step = command
+          .expect("Protocol uses full command set, we'll always get a value for every actuator")
+          .1;
+        step.min(u8::MAX as u32) as u8
+      })
+      .collect();
+    Ok(vec![
+      HardwareWriteCmd::new(Endpoint::Tx, levels, false).into()
+    ])
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::PlayStation;
+  use crate::{
+    core::message::{ActuatorType, Endpoint},
+    server::device::{
+      hardware::{HardwareCommand, HardwareWriteCmd},
+      protocol::ProtocolHandler,
+    },
+  };
+
+  #[test]
+  fn test_playstation_levels() {
+    let commands = PlayStation::default()
+      .handle_scalar_cmd(&[
+        Some((ActuatorType::Vibrate, 255)),
+        Some((ActuatorType::Vibrate, 10)),
+        Some((ActuatorType::Constrict, 0)),
+        Some((ActuatorType::Constrict, 128)),
+      ])
+      .unwrap();
+    let expected: HardwareCommand =
+      HardwareWriteCmd::new(Endpoint::Tx, vec![255, 10, 0, 128], false).into();
+    assert_eq!(commands, vec![expected]);
+  }
+}
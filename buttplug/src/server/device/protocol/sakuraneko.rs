@@ -15,10 +15,45 @@ use crate::{
 
 generic_protocol_setup!(Sakuraneko, "sakuraneko");
 
+const SAKURANEKO_VIBRATE_CHANNEL: u8 = 0xa1;
+const SAKURANEKO_ROTATE_CHANNEL: u8 = 0xa2;
+const SAKURANEKO_ESTIM_CHANNEL: u8 = 0xa3;
+// Hard ceilings per channel type. The estim channel is capped well below what the board accepts,
+// and this holds even if a user config widens the feature's step range.
+const SAKURANEKO_MOTOR_MAX: u32 = 100;
+const SAKURANEKO_ESTIM_MAX: u32 = 40;
+
+fn channel_command(channel: u8, level: u32, ceiling: u32, mode: u8) -> Vec<HardwareCommand> {
+  vec![HardwareWriteCmd::new(
+    Endpoint::Tx,
+    vec![
+      channel,
+      0x08,
+      0x01,
+      0x00,
+      0x00,
+      0x00,
+      0x64,
+      level.min(ceiling) as u8,
+      0x00,
+      mode,
+      0xdf,
+      0x55,
+    ],
+    false,
+  )
+  .into()]
+}
+
 #[derive(Default)]
 pub struct Sakuraneko {}
 
 impl ProtocolHandler for Sakuraneko {
+  fn identify_strategy(&self) -> super::ProtocolIdentifyStrategy {
+    // Shares its board with an e-stim channel, which should never fire without explicit user input.
+    super::ProtocolIdentifyStrategy::Unsupported
+  }
+
   fn keepalive_strategy(&self) -> super::ProtocolKeepaliveStrategy {
     super::ProtocolKeepaliveStrategy::RepeatLastPacketStrategy
   }
@@ -28,25 +63,12 @@ impl ProtocolHandler for Sakuraneko {
     _index: u32,
     scalar: u32,
   ) -> Result<Vec<HardwareCommand>, ButtplugDeviceError> {
-    Ok(vec![HardwareWriteCmd::new(
-      Endpoint::Tx,
-      vec![
-        0xa1,
-        0x08,
-        0x01,
-        0x00,
-        0x00,
-        0x00,
-        0x64,
-        scalar as u8,
-        0x00,
-        0x64,
-        0xdf,
-        0x55,
-      ],
-      false,
-    )
-    .into()])
+    Ok(channel_command(
+      SAKURANEKO_VIBRATE_CHANNEL,
+      scalar,
+      SAKURANEKO_MOTOR_MAX,
+      0x64,
+    ))
   }
 
   fn handle_scalar_rotate_cmd(
@@ -54,24 +76,24 @@ impl ProtocolHandler for Sakuraneko {
     _index: u32,
     scalar: u32,
   ) -> Result<Vec<HardwareCommand>, ButtplugDeviceError> {
-    Ok(vec![HardwareWriteCmd::new(
-      Endpoint::Tx,
-      vec![
-        0xa2,
-        0x08,
-        0x01,
-        0x00,
-        0x00,
-        0x00,
-        0x64,
-        scalar as u8,
-        0x00,
-        0x32,
-        0xdf,
-        0x55,
-      ],
-      false,
-    )
-    .into()])
+    Ok(channel_command(
+      SAKURANEKO_ROTATE_CHANNEL,
+      scalar,
+      SAKURANEKO_MOTOR_MAX,
+      0x32,
+    ))
+  }
+
+  fn handle_scalar_estim_cmd(
+    &self,
+    _index: u32,
+    scalar: u32,
+  ) -> Result<Vec<HardwareCommand>, ButtplugDeviceError> {
+    Ok(channel_command(
+      SAKURANEKO_ESTIM_CHANNEL,
+      scalar,
+      SAKURANEKO_ESTIM_MAX,
+      0x64,
+    ))
   }
 }
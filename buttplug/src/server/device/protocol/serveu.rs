@@ -25,6 +25,11 @@ pub struct ServeU {
 }
 
 impl ProtocolHandler for ServeU {
+  fn identify_strategy(&self) -> super::ProtocolIdentifyStrategy {
+    // Position only device, a stroke isn't a safe way to identify it.
+    super::ProtocolIdentifyStrategy::Unsupported
+  }
+
   fn handle_linear_cmd(
     &self,
     message: crate::core::message::LinearCmdV4,
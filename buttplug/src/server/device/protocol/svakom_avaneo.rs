@@ -11,58 +11,15 @@ use crate::{
     message::{ActuatorType, Endpoint},
   },
   server::device::{
-    configuration::{ProtocolCommunicationSpecifier, UserDeviceDefinition, UserDeviceIdentifier},
-    hardware::{Hardware, HardwareCommand, HardwareWriteCmd},
-    protocol::{
-      generic_protocol_initializer_setup,
-      ProtocolHandler,
-      ProtocolIdentifier,
-      ProtocolInitializer,
-    },
+    hardware::{HardwareCommand, HardwareWriteCmd},
+    protocol::{generic_protocol_setup, ProtocolHandler},
   },
-  util::{async_manager, sleep},
 };
-use async_trait::async_trait;
-use std::{sync::Arc, time::Duration};
 
-generic_protocol_initializer_setup!(SvakomAvaNeo, "svakom-avaneo");
+generic_protocol_setup!(SvakomAvaNeo, "svakom-avaneo");
 
 #[derive(Default)]
-pub struct SvakomAvaNeoInitializer {}
-
-#[async_trait]
-impl ProtocolInitializer for SvakomAvaNeoInitializer {
-  async fn initialize(
-    &mut self,
-    hardware: Arc<Hardware>,
-    _: &UserDeviceDefinition,
-  ) -> Result<Arc<dyn ProtocolHandler>, ButtplugDeviceError> {
-    Ok(Arc::new(SvakomAvaNeo::new(hardware)))
-  }
-}
-
-async fn delayed_update_handler(device: Arc<Hardware>, mode: u8, scalar: u8) {
-  sleep(Duration::from_millis(35)).await;
-  let res = device
-    .write_value(&HardwareWriteCmd::new(
-      Endpoint::Tx,
-      [0x55, mode, 0x00, 0x00, scalar as u8, 0xff].to_vec(),
-      false,
-    ))
-    .await;
-  if res.is_err() {
-    error!("Delayed Svakom Tara X command error: {:?}", res.err());
-  }
-}
-
-pub struct SvakomAvaNeo {
-  device: Arc<Hardware>,
-}
-impl SvakomAvaNeo {
-  fn new(device: Arc<Hardware>) -> Self {
-    Self { device }
-  }
-}
+pub struct SvakomAvaNeo {}
 
 impl ProtocolHandler for SvakomAvaNeo {
   fn handle_scalar_cmd(
@@ -74,6 +31,7 @@ impl ProtocolHandler for SvakomAvaNeo {
     }
 
     let mut hcmd = None;
+    let mut delayed_cmd = None;
     if let Some(cmd) = cmds[0] {
       let scalar = cmd.1;
       hcmd = Some(HardwareWriteCmd::new(
@@ -115,15 +73,23 @@ impl ProtocolHandler for SvakomAvaNeo {
         .into()]);
       } else {
         // Sending both commands in quick succession blots the earlier command
-        let dev = self.device.clone();
-        async_manager::spawn(async move { delayed_update_handler(dev, mode, scalar as u8).await });
+        delayed_cmd = Some(
+          HardwareWriteCmd::new(
+            Endpoint::Tx,
+            [0x55, mode, 0x00, 0x00, scalar as u8, 0xff].to_vec(),
+            false,
+          )
+          .with_send_after(35),
+        );
       }
     }
 
-    return if hcmd.is_some() {
-      Ok(vec![hcmd.unwrap().into()])
-    } else {
-      Ok(vec![])
-    };
+    Ok(
+      hcmd
+        .into_iter()
+        .chain(delayed_cmd)
+        .map(|cmd| cmd.into())
+        .collect(),
+    )
   }
 }
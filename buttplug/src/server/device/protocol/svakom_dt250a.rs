@@ -11,56 +11,22 @@ use crate::{
     message::{ActuatorType, Endpoint},
   },
   server::device::{
-    configuration::{ProtocolCommunicationSpecifier, UserDeviceDefinition, UserDeviceIdentifier},
-    hardware::{Hardware, HardwareCommand, HardwareWriteCmd},
-    protocol::{
-      generic_protocol_initializer_setup,
-      ProtocolHandler,
-      ProtocolIdentifier,
-      ProtocolInitializer,
-    },
+    hardware::{HardwareCommand, HardwareWriteCmd},
+    protocol::{generic_protocol_setup, ProtocolHandler},
   },
-  util::{async_manager, sleep},
 };
-use async_trait::async_trait;
-use std::{sync::Arc, time::Duration};
 
-generic_protocol_initializer_setup!(SvakomDT250A, "svakom-dt250a");
+generic_protocol_setup!(SvakomDT250A, "svakom-dt250a");
 
 #[derive(Default)]
-pub struct SvakomDT250AInitializer {}
+pub struct SvakomDT250A {}
 
-#[async_trait]
-impl ProtocolInitializer for SvakomDT250AInitializer {
-  async fn initialize(
-    &mut self,
-    hardware: Arc<Hardware>,
-    _: &UserDeviceDefinition,
-  ) -> Result<Arc<dyn ProtocolHandler>, ButtplugDeviceError> {
-    Ok(Arc::new(SvakomDT250A::new(hardware)))
-  }
-}
-
-async fn delayed_update_handler(device: Arc<Hardware>, cmd: Vec<u8>, delay: u64) {
-  sleep(Duration::from_millis(delay)).await;
-  let res = device
-    .write_value(&HardwareWriteCmd::new(Endpoint::Tx, cmd, false))
-    .await;
-  if res.is_err() {
-    error!("Delayed Svakom DT250A command error: {:?}", res.err());
+impl ProtocolHandler for SvakomDT250A {
+  fn identify_strategy(&self) -> super::ProtocolIdentifyStrategy {
+    // Suction device, don't actuate it unless the user asked for it.
+    super::ProtocolIdentifyStrategy::Unsupported
   }
-}
 
-pub struct SvakomDT250A {
-  device: Arc<Hardware>,
-}
-impl SvakomDT250A {
-  fn new(device: Arc<Hardware>) -> Self {
-    Self { device }
-  }
-}
-
-impl ProtocolHandler for SvakomDT250A {
   fn handle_scalar_cmd(
     &self,
     cmds: &[Option<(ActuatorType, u32)>],
@@ -71,6 +37,7 @@ impl ProtocolHandler for SvakomDT250A {
 
     let mut delay = 30;
     let mut hcmd = None;
+    let mut delayed_cmds = vec![];
     if let Some(cmd) = cmds[0] {
       let scalar = cmd.1;
 
@@ -113,20 +80,21 @@ impl ProtocolHandler for SvakomDT250A {
         return Ok(vec![HardwareWriteCmd::new(Endpoint::Tx, data, false).into()]);
       } else {
         // Sending both commands in quick succession blots the earlier command
-        let dev = self.device.clone();
-        async_manager::spawn(async move { delayed_update_handler(dev, data, delay).await });
+        delayed_cmds.push(HardwareWriteCmd::new(Endpoint::Tx, data, false).with_send_after(delay));
 
         // This is the minimum time between the 2nd and 3rd command that doesn't seem to just get dropped...
-        delay += 250;
+        delay = 250;
       }
     }
 
     if cmds.len() < 3 {
-      return if hcmd.is_some() {
-        Ok(vec![hcmd.unwrap().into()])
-      } else {
-        Ok(vec![])
-      };
+      return Ok(
+        hcmd
+          .into_iter()
+          .chain(delayed_cmds)
+          .map(|cmd| cmd.into())
+          .collect(),
+      );
     }
 
     if let Some(cmd) = cmds[2] {
@@ -137,15 +105,16 @@ impl ProtocolHandler for SvakomDT250A {
         return Ok(vec![HardwareWriteCmd::new(Endpoint::Tx, data, false).into()]);
       } else {
         // Sending both commands in quick succession blots the earlier command
-        let dev = self.device.clone();
-        async_manager::spawn(async move { delayed_update_handler(dev, data, delay).await });
+        delayed_cmds.push(HardwareWriteCmd::new(Endpoint::Tx, data, false).with_send_after(delay));
       }
     }
 
-    return if hcmd.is_some() {
-      Ok(vec![hcmd.unwrap().into()])
-    } else {
-      Ok(vec![])
-    };
+    Ok(
+      hcmd
+        .into_iter()
+        .chain(delayed_cmds)
+        .map(|cmd| cmd.into())
+        .collect(),
+    )
   }
 }
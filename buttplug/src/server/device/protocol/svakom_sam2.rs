@@ -19,6 +19,11 @@ generic_protocol_setup!(SvakomSam2, "svakom-sam2");
 pub struct SvakomSam2 {}
 
 impl ProtocolHandler for SvakomSam2 {
+  fn identify_strategy(&self) -> super::ProtocolIdentifyStrategy {
+    // Suction device, don't actuate it unless the user asked for it.
+    super::ProtocolIdentifyStrategy::Unsupported
+  }
+
   fn keepalive_strategy(&self) -> super::ProtocolKeepaliveStrategy {
     super::ProtocolKeepaliveStrategy::RepeatLastPacketStrategy
   }
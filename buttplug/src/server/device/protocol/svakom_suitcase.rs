@@ -11,58 +11,15 @@ use crate::{
     message::{ActuatorType, Endpoint},
   },
   server::device::{
-    configuration::{ProtocolCommunicationSpecifier, UserDeviceDefinition, UserDeviceIdentifier},
-    hardware::{Hardware, HardwareCommand, HardwareWriteCmd},
-    protocol::{
-      generic_protocol_initializer_setup,
-      ProtocolHandler,
-      ProtocolIdentifier,
-      ProtocolInitializer,
-    },
+    hardware::{HardwareCommand, HardwareWriteCmd},
+    protocol::{generic_protocol_setup, ProtocolHandler},
   },
-  util::{async_manager, sleep},
 };
-use async_trait::async_trait;
-use std::{sync::Arc, time::Duration};
 
-generic_protocol_initializer_setup!(SvakomSuitcase, "svakom-suitcase");
+generic_protocol_setup!(SvakomSuitcase, "svakom-suitcase");
 
 #[derive(Default)]
-pub struct SvakomSuitcaseInitializer {}
-
-#[async_trait]
-impl ProtocolInitializer for SvakomSuitcaseInitializer {
-  async fn initialize(
-    &mut self,
-    hardware: Arc<Hardware>,
-    _: &UserDeviceDefinition,
-  ) -> Result<Arc<dyn ProtocolHandler>, ButtplugDeviceError> {
-    Ok(Arc::new(SvakomSuitcase::new(hardware)))
-  }
-}
-
-async fn delayed_update_handler(device: Arc<Hardware>, scalar: u8) {
-  sleep(Duration::from_millis(50)).await;
-  let res = device
-    .write_value(&HardwareWriteCmd::new(
-      Endpoint::Tx,
-      [0x55, 0x09, 0x00, 0x00, scalar as u8, 0x00].to_vec(),
-      false,
-    ))
-    .await;
-  if res.is_err() {
-    error!("Delayed Svakom Suitcase command error: {:?}", res.err());
-  }
-}
-
-pub struct SvakomSuitcase {
-  device: Arc<Hardware>,
-}
-impl SvakomSuitcase {
-  fn new(device: Arc<Hardware>) -> Self {
-    Self { device }
-  }
-}
+pub struct SvakomSuitcase {}
 
 impl ProtocolHandler for SvakomSuitcase {
   fn handle_scalar_cmd(
@@ -74,6 +31,7 @@ impl ProtocolHandler for SvakomSuitcase {
     }
 
     let mut hcmd = None;
+    let mut delayed_cmd = None;
     if let Some(cmd) = cmds[0] {
       let scalar = cmd.1;
       let mut speed = (scalar % 10) as u8;
@@ -115,15 +73,23 @@ impl ProtocolHandler for SvakomSuitcase {
         .into()]);
       } else {
         // Sending both commands in quick succession blots the earlier command
-        let dev = self.device.clone();
-        async_manager::spawn(async move { delayed_update_handler(dev, scalar as u8).await });
+        delayed_cmd = Some(
+          HardwareWriteCmd::new(
+            Endpoint::Tx,
+            [0x55, 0x09, 0x00, 0x00, scalar as u8, 0x00].to_vec(),
+            false,
+          )
+          .with_send_after(50),
+        );
       }
     }
 
-    return if hcmd.is_some() {
-      Ok(vec![hcmd.unwrap().into()])
-    } else {
-      Ok(vec![])
-    };
+    Ok(
+      hcmd
+        .into_iter()
+        .chain(delayed_cmd)
+        .map(|cmd| cmd.into())
+        .collect(),
+    )
   }
 }
@@ -5,65 +5,21 @@
 // Licensed under the BSD 3-Clause license. See LICENSE file in the project root
 // for full license information.
 
-use crate::server::device::configuration::ProtocolCommunicationSpecifier;
 use crate::{
   core::{
     errors::ButtplugDeviceError,
     message::{ActuatorType, Endpoint},
   },
   server::device::{
-    configuration::{UserDeviceDefinition, UserDeviceIdentifier},
-    hardware::{Hardware, HardwareCommand, HardwareWriteCmd},
-    protocol::{
-      generic_protocol_initializer_setup,
-      ProtocolHandler,
-      ProtocolIdentifier,
-      ProtocolInitializer,
-    },
+    hardware::{HardwareCommand, HardwareWriteCmd},
+    protocol::{generic_protocol_setup, ProtocolHandler},
   },
-  util::{async_manager, sleep},
 };
-use async_trait::async_trait;
-use std::{sync::Arc, time::Duration};
 
-generic_protocol_initializer_setup!(SvakomTaraX, "svakom-tarax");
+generic_protocol_setup!(SvakomTaraX, "svakom-tarax");
 
 #[derive(Default)]
-pub struct SvakomTaraXInitializer {}
-
-#[async_trait]
-impl ProtocolInitializer for SvakomTaraXInitializer {
-  async fn initialize(
-    &mut self,
-    hardware: Arc<Hardware>,
-    _: &UserDeviceDefinition,
-  ) -> Result<Arc<dyn ProtocolHandler>, ButtplugDeviceError> {
-    Ok(Arc::new(SvakomTaraX::new(hardware)))
-  }
-}
-
-async fn delayed_update_handler(device: Arc<Hardware>, scalar: u8) {
-  sleep(Duration::from_millis(25)).await;
-  let res = device
-    .write_value(&HardwareWriteCmd::new(
-      Endpoint::Tx,
-      [0x55, 0x09, 0x00, 0x00, scalar as u8, 0x00].to_vec(),
-      false,
-    ))
-    .await;
-  if res.is_err() {
-    error!("Delayed Svakom Tara X command error: {:?}", res.err());
-  }
-}
-
-pub struct SvakomTaraX {
-  device: Arc<Hardware>,
-}
-impl SvakomTaraX {
-  fn new(device: Arc<Hardware>) -> Self {
-    Self { device }
-  }
-}
+pub struct SvakomTaraX {}
 
 impl ProtocolHandler for SvakomTaraX {
   fn handle_scalar_cmd(
@@ -75,6 +31,7 @@ impl ProtocolHandler for SvakomTaraX {
     }
 
     let mut hcmd = None;
+    let mut delayed_cmd = None;
     if let Some(cmd) = cmds[0] {
       let scalar = cmd.1;
       hcmd = Some(HardwareWriteCmd::new(
@@ -112,15 +69,23 @@ impl ProtocolHandler for SvakomTaraX {
         .into()]);
       } else {
         // Sending both commands in quick succession blots the earlier command
-        let dev = self.device.clone();
-        async_manager::spawn(async move { delayed_update_handler(dev, scalar as u8).await });
+        delayed_cmd = Some(
+          HardwareWriteCmd::new(
+            Endpoint::Tx,
+            [0x55, 0x09, 0x00, 0x00, scalar as u8, 0x00].to_vec(),
+            false,
+          )
+          .with_send_after(25),
+        );
       }
     }
 
-    return if hcmd.is_some() {
-      Ok(vec![hcmd.unwrap().into()])
-    } else {
-      Ok(vec![])
-    };
+    Ok(
+      hcmd
+        .into_iter()
+        .chain(delayed_cmd)
+        .map(|cmd| cmd.into())
+        .collect(),
+    )
   }
 }
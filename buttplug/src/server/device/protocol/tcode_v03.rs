@@ -8,7 +8,7 @@
 use crate::{
   core::{
     errors::ButtplugDeviceError,
-    message::{self, Endpoint},
+    message::{self, DeviceAxis, Endpoint},
   },
   server::device::{
     hardware::{HardwareCommand, HardwareWriteCmd},
@@ -18,10 +18,27 @@ use crate::{
 
 generic_protocol_setup!(TCodeV03, "tcode-v03");
 
+// TCode channel for each axis of a multi-axis (OSR2/SR6 style) device.
+fn axis_channel(axis: DeviceAxis) -> &'static str {
+  match axis {
+    DeviceAxis::Stroke => "L0",
+    DeviceAxis::Surge => "L1",
+    DeviceAxis::Sway => "L2",
+    DeviceAxis::Twist => "R0",
+    DeviceAxis::Roll => "R1",
+    DeviceAxis::Pitch => "R2",
+  }
+}
+
 #[derive(Default)]
 pub struct TCodeV03 {}
 
 impl ProtocolHandler for TCodeV03 {
+  fn identify_strategy(&self) -> super::ProtocolIdentifyStrategy {
+    // Position only device (usually a multi-axis stroker), a stroke isn't a safe way to identify it.
+    super::ProtocolIdentifyStrategy::Unsupported
+  }
+
   fn handle_linear_cmd(
     &self,
     msg: message::LinearCmdV4,
@@ -36,6 +53,36 @@ impl ProtocolHandler for TCodeV03 {
     Ok(msg_vec)
   }
 
+  fn handle_axis_cmd(
+    &self,
+    msg: message::AxisCmdV4,
+  ) -> Result<Vec<HardwareCommand>, ButtplugDeviceError> {
+    // Everything goes out on one line, so the device starts all of the moves at once. TCode
+    // magnitudes are decimal fractions, 4 digits gives us about as much resolution as the servos
+    // have.
+    let mut command = msg
+      .axes()
+      .iter()
+      .map(|axis| {
+        let position = (axis.position() * 9999f64).round() as u32;
+        format!(
+          "{}{:04}I{}",
+          axis_channel(axis.axis()),
+          position,
+          axis.duration()
+        )
+      })
+      .collect::<Vec<_>>()
+      .join(" ");
+    command.push('\n');
+    Ok(vec![HardwareWriteCmd::new(
+      Endpoint::Tx,
+      command.as_bytes().to_vec(),
+      false,
+    )
+    .into()])
+  }
+
   fn handle_scalar_vibrate_cmd(
     &self,
     index: u32,
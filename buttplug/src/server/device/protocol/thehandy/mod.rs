@@ -116,6 +116,11 @@ pub struct TheHandy {
 }
 
 impl ProtocolHandler for TheHandy {
+  fn identify_strategy(&self) -> super::ProtocolIdentifyStrategy {
+    // Position only device, a stroke isn't a safe way to identify it.
+    super::ProtocolIdentifyStrategy::Unsupported
+  }
+
   fn keepalive_strategy(&self) -> super::ProtocolKeepaliveStrategy {
     let ping_payload = handyplug::Payload {
       messages: vec![handyplug::Message {
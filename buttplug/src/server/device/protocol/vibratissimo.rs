@@ -14,7 +14,12 @@ use crate::{
   server::device::{
     configuration::{UserDeviceDefinition, UserDeviceIdentifier},
     hardware::{Hardware, HardwareCommand, HardwareReadCmd, HardwareWriteCmd},
-    protocol::{ProtocolHandler, ProtocolIdentifier, ProtocolInitializer},
+    protocol::{
+      ProtocolCommandConcurrency,
+      ProtocolHandler,
+      ProtocolIdentifier,
+      ProtocolInitializer,
+    },
   },
 };
 use async_trait::async_trait;
@@ -76,6 +81,13 @@ impl ProtocolInitializer for VibratissimoInitializer {
 pub struct Vibratissimo {}
 
 impl ProtocolHandler for Vibratissimo {
+  // Each update sets the mode before writing speeds, so the mode write has to have landed before
+  // the speed write goes out, and nothing else (raw writes, for instance) can be in flight
+  // alongside either of them.
+  fn command_concurrency(&self) -> ProtocolCommandConcurrency {
+    ProtocolCommandConcurrency::Serialized
+  }
+
   fn handle_scalar_cmd(
     &self,
     cmds: &[Option<(ActuatorType, u32)>],
@@ -0,0 +1,71 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2024 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+use super::fleshlight_launch_helper::StrokerSpeedCurve;
+use crate::{
+  core::{
+    errors::ButtplugDeviceError,
+    message::{self, Endpoint},
+  },
+  server::device::{
+    hardware::{HardwareCommand, HardwareWriteCmd},
+    protocol::{generic_protocol_setup, ProtocolHandler},
+  },
+};
+use std::sync::atomic::{AtomicU8, Ordering};
+
+generic_protocol_setup!(VorzePiston, "vorze-piston");
+
+// Same device byte the rest of the Vorze SA line puts at the front of every packet.
+const VORZE_PISTON_DEVICE_ID: u8 = 3;
+// The Piston takes positions in 0-200, and speeds as a percentage.
+const VORZE_PISTON_POSITION_MAX: f64 = 200f64;
+const VORZE_PISTON_SPEED_MAX: f64 = 100f64;
+// A full stroke takes about 148ms at speed 100, and about 6.7s at speed 1. The motor spends more of
+// a quick move getting up to speed, so faster moves need disproportionately higher settings, which
+// is what the exponent accounts for.
+const VORZE_PISTON_SPEED_CURVE: StrokerSpeedCurve = StrokerSpeedCurve {
+  fastest_stroke_ms: 148f64,
+  exponent: 1.21,
+};
+
+#[derive(Default)]
+pub struct VorzePiston {
+  previous_position: AtomicU8,
+}
+
+impl ProtocolHandler for VorzePiston {
+  fn identify_strategy(&self) -> super::ProtocolIdentifyStrategy {
+    // Position only device, a stroke isn't a safe way to identify it.
+    super::ProtocolIdentifyStrategy::Unsupported
+  }
+
+  fn handle_linear_cmd(
+    &self,
+    message: message::LinearCmdV4,
+  ) -> Result<Vec<HardwareCommand>, ButtplugDeviceError> {
+    let vector = message
+      .vectors()
+      .first()
+      .ok_or(ButtplugDeviceError::DeviceFeatureCountMismatch(1, 0))?;
+    let position = (vector.position() * VORZE_PISTON_POSITION_MAX).round() as u8;
+    let previous_position = self.previous_position.swap(position, Ordering::SeqCst);
+    let distance = position.abs_diff(previous_position) as f64 / VORZE_PISTON_POSITION_MAX;
+    let mut speed =
+      (VORZE_PISTON_SPEED_CURVE.speed(distance, vector.duration()) * VORZE_PISTON_SPEED_MAX) as u8;
+    // Speed 0 means "don't move", so a slow enough move would never get where it's going.
+    if distance > 0f64 {
+      speed = speed.max(1);
+    }
+    Ok(vec![HardwareWriteCmd::new(
+      Endpoint::Tx,
+      vec![VORZE_PISTON_DEVICE_ID, position, speed],
+      true,
+    )
+    .into()])
+  }
+}
@@ -23,10 +23,7 @@ use crate::{
   },
 };
 use async_trait::async_trait;
-use std::sync::{
-  atomic::{AtomicU8, Ordering},
-  Arc,
-};
+use std::sync::Arc;
 
 generic_protocol_initializer_setup!(VorzeSA, "vorze-sa");
 
@@ -51,8 +48,6 @@ impl ProtocolInitializer for VorzeSAInitializer {
       VorzeDevice::Bach
     } else if hwname.contains("rocket") {
       VorzeDevice::Rocket
-    } else if hwname.contains("piston") {
-      VorzeDevice::Piston
     } else {
       return Err(ButtplugDeviceError::ProtocolNotImplemented(format!(
         "No protocol implementation for Vorze Device {}",
@@ -64,16 +59,12 @@ impl ProtocolInitializer for VorzeSAInitializer {
 }
 
 pub struct VorzeSA {
-  previous_position: Arc<AtomicU8>,
   device_type: VorzeDevice,
 }
 
 impl VorzeSA {
   pub fn new(device_type: VorzeDevice) -> Self {
-    Self {
-      previous_position: Arc::new(AtomicU8::new(0)),
-      device_type,
-    }
+    Self { device_type }
   }
 }
 
@@ -81,7 +72,6 @@ impl VorzeSA {
 #[derive(PartialEq, Eq, Clone, Copy)]
 pub enum VorzeDevice {
   Bach = 6,
-  Piston = 3,
   Cyclone = 1,
   Rocket = 7,
   Ufo = 2,
@@ -94,31 +84,6 @@ enum VorzeActions {
   Vibrate = 3,
 }
 
-pub fn get_piston_speed(mut distance: f64, mut duration: f64) -> u8 {
-  if distance <= 0f64 {
-    return 100;
-  }
-
-  if distance > 200f64 {
-    distance = 200f64;
-  }
-
-  // Convert duration to max length
-  duration = 200f64 * duration / distance;
-
-  let mut speed = (duration / 6658f64).powf(-1.21);
-
-  if speed > 100f64 {
-    speed = 100f64;
-  }
-
-  if speed < 0f64 {
-    speed = 0f64;
-  }
-
-  speed as u8
-}
-
 impl ProtocolHandler for VorzeSA {
   fn needs_full_command_set(&self) -> bool {
     true
@@ -184,30 +149,6 @@ impl ProtocolHandler for VorzeSA {
     }
   }
 
-  fn handle_linear_cmd(
-    &self,
-    msg: message::LinearCmdV4,
-  ) -> Result<Vec<HardwareCommand>, ButtplugDeviceError> {
-    let v = msg.vectors()[0].clone();
-
-    let previous_position = self.previous_position.load(Ordering::SeqCst);
-    let position = v.position() * 200f64;
-    let distance = (previous_position as f64 - position).abs();
-
-    let speed = get_piston_speed(distance, v.duration() as f64);
-
-    self
-      .previous_position
-      .store(position as u8, Ordering::SeqCst);
-
-    Ok(vec![HardwareWriteCmd::new(
-      Endpoint::Tx,
-      vec![self.device_type as u8, position as u8, speed as u8],
-      true,
-    )
-    .into()])
-  }
-
   fn handle_vorze_a10_cyclone_cmd(
     &self,
     msg: message::VorzeA10CycloneCmdV0,
@@ -10,27 +10,139 @@ use byteorder::LittleEndian;
 use crate::{
   core::{
     errors::ButtplugDeviceError,
-    message::{self, ActuatorType, ButtplugDeviceMessage, Endpoint, SensorReadingV4},
+    message::{
+      self,
+      ActuatorType,
+      ButtplugDeviceMessage,
+      ButtplugServerDeviceMessage,
+      DeviceFeatureActuator,
+      Endpoint,
+      SensorReadingV4,
+    },
   },
   server::device::{
+    configuration::{ProtocolCommunicationSpecifier, UserDeviceDefinition, UserDeviceIdentifier},
     hardware::{Hardware, HardwareCommand, HardwareReadCmd, HardwareWriteCmd},
-    protocol::{generic_protocol_setup, ProtocolHandler},
+    protocol::{
+      generic_protocol_initializer_setup,
+      xinput_inputs::XInputInputs,
+      ProtocolHandler,
+      ProtocolIdentifier,
+      ProtocolInitializer,
+      ProtocolTimingPolicy,
+    },
   },
 };
+use async_trait::async_trait;
 use byteorder::WriteBytesExt;
-use futures::future::{BoxFuture, FutureExt};
-use std::sync::Arc;
+use futures::{
+  future::{self, BoxFuture, FutureExt},
+  Stream,
+};
+use std::{pin::Pin, sync::Arc};
 
-generic_protocol_setup!(XInput, "xinput");
+generic_protocol_initializer_setup!(XInput, "xinput");
 
 #[derive(Default)]
-pub struct XInput {}
+pub struct XInputInitializer {}
+
+#[async_trait]
+impl ProtocolInitializer for XInputInitializer {
+  async fn initialize(
+    &mut self,
+    _: Arc<Hardware>,
+    device_definition: &UserDeviceDefinition,
+  ) -> Result<Arc<dyn ProtocolHandler>, ButtplugDeviceError> {
+    let motors: Vec<XInputMotor> = device_definition
+      .features()
+      .iter()
+      .filter_map(|feature| feature.actuator().as_ref())
+      .map(XInputMotor::new)
+      .collect();
+    if motors.len() != 2 {
+      return Err(ButtplugDeviceError::DeviceFeatureCountMismatch(
+        2,
+        motors.len() as u32,
+      ));
+    }
+    Ok(Arc::new(XInput {
+      motors,
+      inputs: XInputInputs::new(device_definition),
+    }))
+  }
+}
+
+/// Maps a motor's steps, as set up in the device config, to the 0-65535 speed XInput takes. Pads
+/// differ a lot in how they respond, so the config can give the motor a coarser step range and a
+/// response curve, per controller index through user configs.
+pub(super) struct XInputMotor {
+  step_range: std::ops::RangeInclusive<u32>,
+  response_curve: Option<Vec<u32>>,
+}
+
+impl XInputMotor {
+  pub(super) fn new(actuator: &DeviceFeatureActuator) -> Self {
+    Self {
+      step_range: actuator.step_range().clone(),
+      response_curve: actuator.response_curve().clone(),
+    }
+  }
+
+  pub(super) fn speed(&self, step: u32) -> u16 {
+    let (start, end) = (*self.step_range.start(), *self.step_range.end());
+    let position = step.clamp(start, end).saturating_sub(start) as f64 / (end - start) as f64;
+    let speed = match &self.response_curve {
+      None => position * u16::MAX as f64,
+      Some(curve) => {
+        // Linear interpolation between the curve's evenly spaced points.
+        let scaled = position * (curve.len() - 1) as f64;
+        let index = (scaled.floor() as usize).min(curve.len() - 2);
+        let (low, high) = (curve[index] as f64, curve[index + 1] as f64);
+        low + (high - low) * (scaled - index as f64)
+      }
+    };
+    speed.round().clamp(0f64, u16::MAX as f64) as u16
+  }
+}
+
+pub struct XInput {
+  motors: Vec<XInputMotor>,
+  inputs: Option<XInputInputs>,
+}
+
+// Battery types and levels, as XInputGetBatteryInformation reports them.
+const BATTERY_TYPE_DISCONNECTED: u8 = 0x00;
+const BATTERY_TYPE_WIRED: u8 = 0x01;
+const BATTERY_LEVEL_FULL: u8 = 0x03;
+
+/// Turn the battery type and level read from the hardware into a 0-100 charge. XInput only reports
+/// four levels, so that's all the resolution there is. Wired pads have no battery to run down, so
+/// they always read as full.
+fn xinput_battery_level(data: &[u8]) -> Result<i32, ButtplugDeviceError> {
+  match *data {
+    [BATTERY_TYPE_DISCONNECTED, _] => Err(ButtplugDeviceError::DeviceNotConnected(
+      "XInput gamepad battery is not reporting, the gamepad may be disconnected.".to_owned(),
+    )),
+    [BATTERY_TYPE_WIRED, _] => Ok(100),
+    [_, level] if level <= BATTERY_LEVEL_FULL => Ok(level as i32 * 100 / BATTERY_LEVEL_FULL as i32),
+    _ => Err(ButtplugDeviceError::DeviceCommunicationError(format!(
+      "Unexpected XInput battery information: {:?}",
+      data
+    ))),
+  }
+}
 
 impl ProtocolHandler for XInput {
   fn needs_full_command_set(&self) -> bool {
     true
   }
 
+  fn timing_policy(&self) -> ProtocolTimingPolicy {
+    // XInputSetState is a blocking driver call, and controllers don't update their motors any
+    // faster than about 100hz anyways.
+    ProtocolTimingPolicy::new(10)
+  }
+
   fn handle_scalar_cmd(
     &self,
     cmds: &[Option<(ActuatorType, u32)>],
@@ -39,21 +151,18 @@ impl ProtocolHandler for XInput {
     // back by the manager and just form our own packet. This means
     // we'll just use the manager's return for command validity
     // checking.
-    let mut cmd = vec![];
-    if cmd
-      .write_u16::<LittleEndian>(
-        cmds[1]
+    let speed = |index: usize| {
+      self.motors[index].speed(
+        cmds[index]
           .expect("GCM uses match_all, we'll always get 2 values")
-          .1 as u16,
+          .1,
       )
-      .is_err()
-      || cmd
-        .write_u16::<LittleEndian>(
-          cmds[0]
-            .expect("GCM uses match_all, we'll always get 2 values")
-            .1 as u16,
-        )
-        .is_err()
+    };
+    // Feature 0 is the left (low frequency) motor and feature 1 the right (high frequency) one,
+    // which is also the order XInputSetState takes them in.
+    let mut cmd = vec![];
+    if cmd.write_u16::<LittleEndian>(speed(0)).is_err()
+      || cmd.write_u16::<LittleEndian>(speed(1)).is_err()
     {
       return Err(ButtplugDeviceError::ProtocolSpecificError(
         "XInput".to_owned(),
@@ -72,17 +181,7 @@ impl ProtocolHandler for XInput {
       let reading = device
         .read_value(&HardwareReadCmd::new(Endpoint::Rx, 0, 0))
         .await?;
-      let battery = match reading.data()[0] {
-        0 => 0i32,
-        1 => 33,
-        2 => 66,
-        3 => 100,
-        _ => {
-          return Err(ButtplugDeviceError::DeviceCommunicationError(
-            "something went wrong".to_string(),
-          ))
-        }
-      };
+      let battery = xinput_battery_level(reading.data())?;
       Ok(message::SensorReadingV4::new(
         msg.device_index(),
         *msg.feature_index(),
@@ -92,4 +191,122 @@ impl ProtocolHandler for XInput {
     }
     .boxed()
   }
+
+  fn event_stream(&self) -> Pin<Box<dyn Stream<Item = ButtplugServerDeviceMessage> + Send>> {
+    match &self.inputs {
+      Some(inputs) => inputs.event_stream(),
+      None => Box::pin(futures::stream::empty()),
+    }
+  }
+
+  fn handle_sensor_subscribe_cmd(
+    &self,
+    device: Arc<Hardware>,
+    message: &message::SensorSubscribeCmdV4,
+  ) -> BoxFuture<Result<(), ButtplugDeviceError>> {
+    match &self.inputs {
+      Some(inputs) => inputs.subscribe(device, message),
+      None => future::ready(Err(ButtplugDeviceError::ProtocolSensorNotSupported(
+        *message.sensor_type(),
+      )))
+      .boxed(),
+    }
+  }
+
+  fn handle_sensor_unsubscribe_cmd(
+    &self,
+    device: Arc<Hardware>,
+    message: &message::SensorUnsubscribeCmdV4,
+  ) -> BoxFuture<Result<(), ButtplugDeviceError>> {
+    match &self.inputs {
+      Some(inputs) => inputs.unsubscribe(device, message),
+      None => future::ready(Err(ButtplugDeviceError::ProtocolSensorNotSupported(
+        *message.sensor_type(),
+      )))
+      .boxed(),
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{xinput_battery_level, XInput, XInputMotor};
+  use crate::{
+    core::message::{
+      ActuatorType,
+      ButtplugActuatorFeatureMessageType,
+      DeviceFeatureActuator,
+      Endpoint,
+    },
+    server::device::{
+      hardware::{HardwareCommand, HardwareWriteCmd},
+      protocol::ProtocolHandler,
+    },
+  };
+  use std::collections::HashSet;
+
+  fn motor(steps: u32, response_curve: Option<Vec<u32>>) -> XInputMotor {
+    let mut actuator = DeviceFeatureActuator::new(
+      &(0..=steps),
+      &(0..=steps),
+      &HashSet::from([ButtplugActuatorFeatureMessageType::ScalarCmd]),
+    );
+    actuator.set_response_curve(response_curve);
+    XInputMotor::new(&actuator)
+  }
+
+  #[test]
+  fn test_xinput_motor_speed() {
+    // The default config passes speeds straight through.
+    let default = motor(65535, None);
+    assert_eq!(default.speed(0), 0);
+    assert_eq!(default.speed(12345), 12345);
+    assert_eq!(default.speed(65535), 65535);
+
+    // Coarser step ranges are scaled to the full motor range.
+    let coarse = motor(20, None);
+    assert_eq!(coarse.speed(10), 32768);
+    assert_eq!(coarse.speed(20), 65535);
+
+    // A pad that doesn't spin up until a quarter power, and saturates at 3/4.
+    let curved = motor(20, Some(vec![0, 16384, 49152, 65535, 65535]));
+    assert_eq!(curved.speed(0), 0);
+    assert_eq!(curved.speed(2), 6554);
+    assert_eq!(curved.speed(5), 16384);
+    assert_eq!(curved.speed(10), 49152);
+    assert_eq!(curved.speed(15), 65535);
+    assert_eq!(curved.speed(20), 65535);
+  }
+
+  #[test]
+  fn test_xinput_motor_order() {
+    let xinput = XInput {
+      motors: vec![motor(65535, None), motor(65535, None)],
+      inputs: None,
+    };
+    let commands = xinput
+      .handle_scalar_cmd(&[
+        Some((ActuatorType::Vibrate, 0x1234)),
+        Some((ActuatorType::Vibrate, 0xabcd)),
+      ])
+      .unwrap();
+    // Left motor first, then right, as XInputSetState takes them.
+    let expected: HardwareCommand =
+      HardwareWriteCmd::new(Endpoint::Tx, vec![0x34, 0x12, 0xcd, 0xab], false).into();
+    assert_eq!(commands, vec![expected]);
+  }
+
+  #[test]
+  fn test_xinput_battery_level() {
+    // NiMH pad at each level.
+    assert_eq!(xinput_battery_level(&[0x03, 0x00]).unwrap(), 0);
+    assert_eq!(xinput_battery_level(&[0x03, 0x01]).unwrap(), 33);
+    assert_eq!(xinput_battery_level(&[0x03, 0x02]).unwrap(), 66);
+    assert_eq!(xinput_battery_level(&[0x03, 0x03]).unwrap(), 100);
+    // Wired pads are always full, whatever level they claim.
+    assert_eq!(xinput_battery_level(&[0x01, 0x00]).unwrap(), 100);
+    assert!(xinput_battery_level(&[0x00, 0x00]).is_err());
+    assert!(xinput_battery_level(&[0x02, 0x04]).is_err());
+    assert!(xinput_battery_level(&[0x02]).is_err());
+  }
 }
@@ -0,0 +1,291 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2024 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Button, trigger and stick input from XInput gamepads.
+//!
+//! XInput has no input events, so while input is subscribed to, the hardware polls the pad and
+//! sends each new state over the rx endpoint, laid out like XINPUT_GAMEPAD: the button bits (u16),
+//! the left and right trigger (u8 each), then the left and right stick X and Y (i16 each), all
+//! little endian.
+//!
+//! Inputs are matched to sensor features by type, in the order the config lists them:
+//!
+//! - The first Button feature reads the button bits, using XInput's XINPUT_GAMEPAD_* values.
+//! - Pressure features read the left then the right trigger, 0-255.
+//! - Position features read the left then the right stick, as X and Y values from -32768 to 32767.
+//!
+//! A subscribed feature gets a reading whenever its input changes.
+
+use crate::{
+  core::{
+    errors::ButtplugDeviceError,
+    message::{
+      ButtplugDeviceMessage,
+      ButtplugSensorFeatureMessageType,
+      ButtplugServerDeviceMessage,
+      Endpoint,
+      FeatureType,
+      SensorReadingV4,
+      SensorSubscribeCmdV4,
+      SensorType,
+      SensorUnsubscribeCmdV4,
+    },
+  },
+  server::device::{
+    configuration::UserDeviceDefinition,
+    hardware::{Hardware, HardwareEvent, HardwareSubscribeCmd, HardwareUnsubscribeCmd},
+  },
+  util::{async_manager, stream::convert_broadcast_receiver_to_stream},
+};
+use dashmap::{DashMap, DashSet};
+use futures::{
+  future::{self, BoxFuture},
+  FutureExt,
+  Stream,
+  StreamExt,
+};
+use std::{
+  pin::Pin,
+  sync::{
+    atomic::{AtomicBool, AtomicU32, Ordering},
+    Arc,
+  },
+};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct XInputGamepadState {
+  buttons: u16,
+  left_trigger: u8,
+  right_trigger: u8,
+  left_stick: [i16; 2],
+  right_stick: [i16; 2],
+}
+
+fn parse_gamepad_state(data: &[u8]) -> Option<XInputGamepadState> {
+  let [b0, b1, left_trigger, right_trigger, lx0, lx1, ly0, ly1, rx0, rx1, ry0, ry1] = *data else {
+    return None;
+  };
+  Some(XInputGamepadState {
+    buttons: u16::from_le_bytes([b0, b1]),
+    left_trigger,
+    right_trigger,
+    left_stick: [
+      i16::from_le_bytes([lx0, lx1]),
+      i16::from_le_bytes([ly0, ly1]),
+    ],
+    right_stick: [
+      i16::from_le_bytes([rx0, rx1]),
+      i16::from_le_bytes([ry0, ry1]),
+    ],
+  })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum XInputInput {
+  Buttons,
+  LeftTrigger,
+  RightTrigger,
+  LeftStick,
+  RightStick,
+}
+
+impl XInputInput {
+  fn sensor_type(&self) -> SensorType {
+    match self {
+      Self::Buttons => SensorType::Button,
+      Self::LeftTrigger | Self::RightTrigger => SensorType::Pressure,
+      Self::LeftStick | Self::RightStick => SensorType::Position,
+    }
+  }
+
+  fn values(&self, state: &XInputGamepadState) -> Vec<i32> {
+    match self {
+      Self::Buttons => vec![state.buttons as i32],
+      Self::LeftTrigger => vec![state.left_trigger as i32],
+      Self::RightTrigger => vec![state.right_trigger as i32],
+      Self::LeftStick => state.left_stick.iter().map(|axis| *axis as i32).collect(),
+      Self::RightStick => state.right_stick.iter().map(|axis| *axis as i32).collect(),
+    }
+  }
+}
+
+/// Turns input polled from an XInput gamepad into sensor readings for subscribed features.
+pub struct XInputInputs {
+  /// Feature index of each input the pad's features cover.
+  inputs: Vec<(u32, XInputInput)>,
+  subscribed_sensors: Arc<DashSet<u32>>,
+  /// Last reading sent for each feature, so unchanged inputs aren't sent again.
+  last_readings: Arc<DashMap<u32, Vec<i32>>>,
+  // Readings go out under whatever index the last subscription used.
+  device_index: Arc<AtomicU32>,
+  listening: AtomicBool,
+  event_stream: broadcast::Sender<ButtplugServerDeviceMessage>,
+}
+
+impl XInputInputs {
+  /// Set up input handling for the sensor features of `definition`, or None if it doesn't have
+  /// any that can be subscribed to.
+  pub(super) fn new(definition: &UserDeviceDefinition) -> Option<Self> {
+    let mut buttons = Some(XInputInput::Buttons);
+    let mut triggers = [XInputInput::LeftTrigger, XInputInput::RightTrigger].into_iter();
+    let mut sticks = [XInputInput::LeftStick, XInputInput::RightStick].into_iter();
+    let mut inputs = vec![];
+    for (index, feature) in definition.features().iter().enumerate() {
+      let subscribable = feature.sensor().as_ref().is_some_and(|sensor| {
+        sensor
+          .messages()
+          .contains(&ButtplugSensorFeatureMessageType::SensorSubscribeCmd)
+      });
+      if !subscribable {
+        continue;
+      }
+      let input = match feature.feature_type() {
+        FeatureType::Button => buttons.take(),
+        FeatureType::Pressure => triggers.next(),
+        FeatureType::Position => sticks.next(),
+        _ => None,
+      };
+      if let Some(input) = input {
+        inputs.push((index as u32, input));
+      }
+    }
+    if inputs.is_empty() {
+      return None;
+    }
+    let (event_stream, _) = broadcast::channel(256);
+    Some(Self {
+      inputs,
+      subscribed_sensors: Arc::new(DashSet::new()),
+      last_readings: Arc::new(DashMap::new()),
+      device_index: Arc::new(AtomicU32::new(0)),
+      listening: AtomicBool::new(false),
+      event_stream,
+    })
+  }
+
+  pub(super) fn event_stream(
+    &self,
+  ) -> Pin<Box<dyn Stream<Item = ButtplugServerDeviceMessage> + Send>> {
+    convert_broadcast_receiver_to_stream(self.event_stream.subscribe()).boxed()
+  }
+
+  pub(super) fn subscribe(
+    &self,
+    device: Arc<Hardware>,
+    message: &SensorSubscribeCmdV4,
+  ) -> BoxFuture<'static, Result<(), ButtplugDeviceError>> {
+    let feature_index = *message.feature_index();
+    if !self.inputs.iter().any(|(index, _)| *index == feature_index) {
+      return future::ready(Err(ButtplugDeviceError::ProtocolSensorNotSupported(
+        *message.sensor_type(),
+      )))
+      .boxed();
+    }
+    self
+      .device_index
+      .store(message.device_index(), Ordering::SeqCst);
+    // Send the next reading for the feature even if it hasn't changed since the last one.
+    self.last_readings.remove(&feature_index);
+    self.subscribed_sensors.insert(feature_index);
+    if !self.listening.swap(true, Ordering::SeqCst) {
+      self.listen(device.clone());
+    }
+    // Polling is only started once, however many features are subscribed.
+    device.subscribe(&HardwareSubscribeCmd::new(Endpoint::Rx))
+  }
+
+  pub(super) fn unsubscribe(
+    &self,
+    device: Arc<Hardware>,
+    message: &SensorUnsubscribeCmdV4,
+  ) -> BoxFuture<'static, Result<(), ButtplugDeviceError>> {
+    self.subscribed_sensors.remove(message.feature_index());
+    if !self.subscribed_sensors.is_empty() {
+      return future::ready(Ok(())).boxed();
+    }
+    device.unsubscribe(&HardwareUnsubscribeCmd::new(Endpoint::Rx))
+  }
+
+  fn listen(&self, device: Arc<Hardware>) {
+    let mut hardware_stream = device.event_stream();
+    let inputs = self.inputs.clone();
+    let subscribed_sensors = self.subscribed_sensors.clone();
+    let last_readings = self.last_readings.clone();
+    let device_index = self.device_index.clone();
+    let sender = self.event_stream.clone();
+    async_manager::spawn(async move {
+      loop {
+        let data = match hardware_stream.recv().await {
+          Ok(HardwareEvent::Notification(_, Endpoint::Rx, data)) => data,
+          Ok(HardwareEvent::Notification(..) | HardwareEvent::Rssi(..))
+          | Err(RecvError::Lagged(_)) => continue,
+          Ok(HardwareEvent::Disconnected(_)) | Err(RecvError::Closed) => break,
+        };
+        let Some(state) = parse_gamepad_state(&data) else {
+          warn!("Unexpected XInput gamepad state: {:?}", data);
+          continue;
+        };
+        for (feature_index, input) in &inputs {
+          if !subscribed_sensors.contains(feature_index) {
+            continue;
+          }
+          let values = input.values(&state);
+          if last_readings
+            .get(feature_index)
+            .is_some_and(|last| *last == values)
+          {
+            continue;
+          }
+          last_readings.insert(*feature_index, values.clone());
+          // Having no one listening right now is fine, someone may subscribe again later.
+          let _ = sender.send(
+            SensorReadingV4::new(
+              device_index.load(Ordering::SeqCst),
+              *feature_index,
+              input.sensor_type(),
+              values,
+            )
+            .into(),
+          );
+        }
+      }
+      debug!("XInput gamepad disconnected, no longer listening for input.");
+    });
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{parse_gamepad_state, XInputGamepadState, XInputInput};
+
+  #[test]
+  fn test_parse_gamepad_state() {
+    // A held, left trigger halfway, left stick pushed up and right stick pushed fully left.
+    let state = parse_gamepad_state(&[
+      0x00, 0x10, 0x80, 0x00, 0x00, 0x00, 0xff, 0x7f, 0x00, 0x80, 0x00, 0x00,
+    ])
+    .unwrap();
+    assert_eq!(
+      state,
+      XInputGamepadState {
+        buttons: 0x1000,
+        left_trigger: 0x80,
+        right_trigger: 0,
+        left_stick: [0, i16::MAX],
+        right_stick: [i16::MIN, 0],
+      }
+    );
+    assert_eq!(XInputInput::Buttons.values(&state), vec![0x1000]);
+    assert_eq!(XInputInput::LeftTrigger.values(&state), vec![128]);
+    assert_eq!(XInputInput::RightTrigger.values(&state), vec![0]);
+    assert_eq!(XInputInput::LeftStick.values(&state), vec![0, 32767]);
+    assert_eq!(XInputInput::RightStick.values(&state), vec![-32768, 0]);
+    // Anything that isn't a full gamepad state is rejected.
+    assert_eq!(parse_gamepad_state(&[0x01, 0x03]), None);
+  }
+}
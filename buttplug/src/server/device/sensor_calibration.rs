@@ -0,0 +1,224 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2024 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Sensor calibration, run on readings before they're rate limited and sent on.
+//!
+//! Pressure and squeeze sensors cover very different raw ranges from one unit to the next, so the
+//! range a config file declares is rarely the range a sensor actually reports. A calibration
+//! session records the lowest and highest values seen while the user squeezes the device, and from
+//! then on readings are scaled so that range covers the whole declared one.
+
+use crate::{
+  core::{errors::ButtplugDeviceError, message::DeviceFeature},
+  server::device::configuration::SensorCalibration,
+};
+use dashmap::DashMap;
+use std::{collections::HashMap, ops::RangeInclusive};
+
+/// Lowest and highest values seen for each value in a sensor's readings.
+#[derive(Default)]
+struct CalibrationSession {
+  min: Vec<i32>,
+  max: Vec<i32>,
+}
+
+impl CalibrationSession {
+  fn record(&mut self, data: &[i32]) {
+    for (index, value) in data.iter().enumerate() {
+      if let Some(min) = self.min.get_mut(index) {
+        *min = (*min).min(*value);
+        self.max[index] = self.max[index].max(*value);
+      } else {
+        self.min.push(*value);
+        self.max.push(*value);
+      }
+    }
+  }
+
+  fn finish(self) -> Option<SensorCalibration> {
+    if self.min.is_empty() {
+      return None;
+    }
+    let value_range: Vec<_> = self
+      .min
+      .into_iter()
+      .zip(self.max)
+      .map(|(min, max)| min..=max)
+      .collect();
+    Some(SensorCalibration::new(&value_range))
+  }
+}
+
+pub(super) struct SensorCalibrator {
+  /// Value ranges the device's sensors declare, keyed by feature index.
+  value_ranges: HashMap<u32, Vec<RangeInclusive<i32>>>,
+  calibrations: DashMap<u32, SensorCalibration>,
+  sessions: DashMap<u32, CalibrationSession>,
+}
+
+impl SensorCalibrator {
+  pub(super) fn new(
+    features: &[DeviceFeature],
+    calibrations: &HashMap<u32, SensorCalibration>,
+  ) -> Self {
+    let value_ranges: HashMap<_, _> = features
+      .iter()
+      .enumerate()
+      .filter_map(|(index, feature)| {
+        feature
+          .sensor()
+          .as_ref()
+          .map(|sensor| (index as u32, sensor.value_range().clone()))
+      })
+      .collect();
+    let calibrations = calibrations
+      .iter()
+      .filter(|(index, _)| value_ranges.contains_key(index))
+      .map(|(index, calibration)| (*index, calibration.clone()))
+      .collect();
+    Self {
+      value_ranges,
+      calibrations,
+      sessions: DashMap::new(),
+    }
+  }
+
+  fn check_sensor(&self, feature_index: u32) -> Result<(), ButtplugDeviceError> {
+    if self.value_ranges.contains_key(&feature_index) {
+      Ok(())
+    } else {
+      Err(ButtplugDeviceError::SensorCalibrationError(
+        feature_index,
+        "Feature is not a sensor".to_owned(),
+      ))
+    }
+  }
+
+  /// Start recording readings for the sensor at `feature_index`, throwing away anything recorded
+  /// by a session that was already running for it.
+  pub(super) fn start_session(&self, feature_index: u32) -> Result<(), ButtplugDeviceError> {
+    self.check_sensor(feature_index)?;
+    self
+      .sessions
+      .insert(feature_index, CalibrationSession::default());
+    Ok(())
+  }
+
+  /// End the session for the sensor at `feature_index`, and calibrate the sensor to what it saw.
+  pub(super) fn finish_session(
+    &self,
+    feature_index: u32,
+  ) -> Result<SensorCalibration, ButtplugDeviceError> {
+    self.check_sensor(feature_index)?;
+    let Some((_, session)) = self.sessions.remove(&feature_index) else {
+      return Err(ButtplugDeviceError::SensorCalibrationError(
+        feature_index,
+        "No calibration session is running".to_owned(),
+      ));
+    };
+    let calibration = session.finish().ok_or_else(|| {
+      ButtplugDeviceError::SensorCalibrationError(
+        feature_index,
+        "No readings were taken during the calibration session".to_owned(),
+      )
+    })?;
+    self.calibrations.insert(feature_index, calibration.clone());
+    Ok(calibration)
+  }
+
+  pub(super) fn clear(&self, feature_index: u32) -> Result<(), ButtplugDeviceError> {
+    self.check_sensor(feature_index)?;
+    self.sessions.remove(&feature_index);
+    self.calibrations.remove(&feature_index);
+    Ok(())
+  }
+
+  /// Calibrate a raw reading. Readings taken while a session is running for the sensor are
+  /// recorded and passed on raw.
+  pub(super) fn process(&self, feature_index: u32, data: &[i32]) -> Vec<i32> {
+    if let Some(mut session) = self.sessions.get_mut(&feature_index) {
+      session.record(data);
+      return data.to_vec();
+    }
+    match (
+      self.calibrations.get(&feature_index),
+      self.value_ranges.get(&feature_index),
+    ) {
+      (Some(calibration), Some(value_range)) => calibration.scale_to(data, value_range),
+      _ => data.to_vec(),
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::SensorCalibrator;
+  use crate::{
+    core::message::{
+      ButtplugSensorFeatureMessageType,
+      DeviceFeature,
+      DeviceFeatureSensor,
+      FeatureType,
+    },
+    server::device::configuration::SensorCalibration,
+  };
+  use std::collections::{HashMap, HashSet};
+
+  fn pressure_feature() -> DeviceFeature {
+    DeviceFeature::new(
+      "Pressure",
+      FeatureType::Pressure,
+      &None,
+      &Some(DeviceFeatureSensor::new(
+        &vec![0..=1000],
+        &HashSet::from([ButtplugSensorFeatureMessageType::SensorReadCmd]),
+      )),
+    )
+  }
+
+  #[test]
+  fn test_sensor_calibration_session() {
+    let calibrator = SensorCalibrator::new(&[pressure_feature()], &HashMap::new());
+    assert_eq!(calibrator.process(0, &[300]), vec![300]);
+    assert!(calibrator.finish_session(0).is_err());
+    assert!(calibrator.start_session(1).is_err());
+
+    calibrator
+      .start_session(0)
+      .expect("Test, assuming infallible.");
+    assert!(calibrator.finish_session(0).is_err());
+    calibrator
+      .start_session(0)
+      .expect("Test, assuming infallible.");
+    // Readings during the session come through raw.
+    for value in [250, 100, 300] {
+      assert_eq!(calibrator.process(0, &[value]), vec![value]);
+    }
+    assert_eq!(
+      calibrator.finish_session(0),
+      Ok(SensorCalibration::new(&[100..=300]))
+    );
+    // 100 to 300 now covers the declared 0 to 1000, clamping anything outside of it.
+    assert_eq!(calibrator.process(0, &[200]), vec![500]);
+    assert_eq!(calibrator.process(0, &[50]), vec![0]);
+    assert_eq!(calibrator.process(0, &[400]), vec![1000]);
+
+    calibrator.clear(0).expect("Test, assuming infallible.");
+    assert_eq!(calibrator.process(0, &[200]), vec![200]);
+  }
+
+  #[test]
+  fn test_sensor_calibration_normalize() {
+    let calibration = SensorCalibration::new(&[100..=300, 5..=5]);
+    assert_eq!(calibration.normalize(&[150, 5]), vec![0.25, 0.0]);
+    // Values the calibration doesn't cover pass through scaling untouched.
+    assert_eq!(
+      calibration.scale_to(&[150, 5, 7], &[0..=100, 0..=100]),
+      vec![25, 0, 7]
+    );
+  }
+}
@@ -0,0 +1,109 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2024 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Rate limiting for sensor subscriptions.
+//!
+//! Some sensors report far faster than anyone needs (accelerometers at 100Hz, for instance), which
+//! can swamp slow remote connections. Clients can ask for a max rate when subscribing, and the
+//! [SensorRateLimiter] then decides which readings make it out. Readings are only ever sent when a
+//! new one arrives, so there's no timer involved, but that also means the last value of a burst is
+//! held until the sensor reports again.
+
+use crate::core::message::SensorDecimation;
+use instant::Instant;
+use std::time::Duration;
+
+pub(super) struct SensorRateLimiter {
+  min_interval: Duration,
+  decimation: SensorDecimation,
+  last_sent: Option<Instant>,
+  // Running totals for averaging, per reading value.
+  sums: Vec<i64>,
+  count: i64,
+}
+
+impl SensorRateLimiter {
+  pub(super) fn new(max_rate: u32, decimation: SensorDecimation) -> Self {
+    Self {
+      min_interval: Duration::from_secs(1) / max_rate.max(1),
+      decimation,
+      last_sent: None,
+      sums: vec![],
+      count: 0,
+    }
+  }
+
+  /// Take in a reading, returning the data to send if it's time for one to go out.
+  pub(super) fn process(&mut self, data: &[i32]) -> Option<Vec<i32>> {
+    self.process_at(data, Instant::now())
+  }
+
+  fn process_at(&mut self, data: &[i32], now: Instant) -> Option<Vec<i32>> {
+    if self.decimation == SensorDecimation::Average {
+      // If the shape of the reading changes, averaging across it makes no sense, so start over.
+      if self.sums.len() != data.len() {
+        self.sums = vec![0; data.len()];
+        self.count = 0;
+      }
+      for (sum, value) in self.sums.iter_mut().zip(data) {
+        *sum += *value as i64;
+      }
+      self.count += 1;
+    }
+    if self
+      .last_sent
+      .is_some_and(|last_sent| now.duration_since(last_sent) < self.min_interval)
+    {
+      return None;
+    }
+    self.last_sent = Some(now);
+    match self.decimation {
+      SensorDecimation::Latest => Some(data.to_vec()),
+      SensorDecimation::Average => {
+        let count = self.count;
+        self.count = 0;
+        Some(
+          self
+            .sums
+            .iter_mut()
+            .map(|sum| {
+              let average = (*sum as f64 / count as f64).round() as i32;
+              *sum = 0;
+              average
+            })
+            .collect(),
+        )
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::SensorRateLimiter;
+  use crate::core::message::SensorDecimation;
+  use instant::Instant;
+  use std::time::Duration;
+
+  #[test]
+  fn test_sensor_rate_limiter() {
+    let start = Instant::now();
+    let at = |ms| start + Duration::from_millis(ms);
+
+    // 10 readings per second means at most one every 100ms.
+    let mut latest = SensorRateLimiter::new(10, SensorDecimation::Latest);
+    assert_eq!(latest.process_at(&[1], at(0)), Some(vec![1]));
+    assert_eq!(latest.process_at(&[2], at(50)), None);
+    assert_eq!(latest.process_at(&[3], at(100)), Some(vec![3]));
+
+    let mut average = SensorRateLimiter::new(10, SensorDecimation::Average);
+    assert_eq!(average.process_at(&[10, 0], at(0)), Some(vec![10, 0]));
+    assert_eq!(average.process_at(&[1, 4], at(30)), None);
+    assert_eq!(average.process_at(&[2, 5], at(60)), None);
+    assert_eq!(average.process_at(&[3, 7], at(100)), Some(vec![2, 5]));
+  }
+}
@@ -47,23 +47,35 @@ use crate::{
     errors::{ButtplugDeviceError, ButtplugError},
     message::{
       self,
+      ActuatorType,
+      AxisCmdV4,
+      AxisSubcommandV4,
+      ButtplugActuatorFeatureMessageType,
       ButtplugDeviceCommandMessageUnion,
+      ButtplugDeviceMessage,
       ButtplugDeviceMessageType,
       ButtplugMessage,
       ButtplugServerDeviceMessage,
       ButtplugServerMessageV4,
+      DeviceTransport,
       Endpoint,
       FeatureType,
+      LinearCmdV4,
       RawReadingV2,
       RawSubscribeCmdV2,
+      RotateCmdV4,
+      RotationSubcommandV4,
       ScalarCmdV4,
+      ScalarSubcommandV4,
+      SensorReadingV4,
       SensorType,
+      VectorSubcommandV4,
     },
     ButtplugResultFuture,
   },
   server::{
     device::{
-      configuration::DeviceConfigurationManager,
+      configuration::{DeviceConfigurationManager, SensorCalibration},
       hardware::{Hardware, HardwareCommand, HardwareConnector, HardwareEvent},
       protocol::ProtocolHandler,
     },
@@ -72,22 +84,43 @@ use crate::{
   util::{self, async_manager, stream::convert_broadcast_receiver_to_stream},
 };
 use core::hash::{Hash, Hasher};
-use dashmap::DashSet;
+use dashmap::{DashMap, DashSet};
 use futures::future::{self, BoxFuture, FutureExt};
-use getset::Getters;
-use tokio::sync::RwLock;
+use getset::{CopyGetters, Getters};
+use tokio::sync::{broadcast, Mutex, RwLock};
 use tokio_stream::StreamExt;
 
 use super::{
+  adaptive_write_limiter::{AdaptiveWriteLimiter, CoalesceKey},
+  capability_emulator::{self, CapabilityEmulator, LinearCmdSender},
+  command_audit::{CommandAudit, CommandAuditEvent},
+  command_validation::{self, OutOfRangeValuePolicy},
   configuration::{UserDeviceDefinition, UserDeviceIdentifier},
-  hardware::HardwareWriteCmd,
+  energy_budget::EnergyThrottle,
   protocol::{
     actuator_command_manager::ActuatorCommandManager,
+    ProtocolDiagnostics,
+    ProtocolIdentifyStrategy,
+    ProtocolInitializer,
     ProtocolKeepaliveStrategy,
     ProtocolSpecializer,
+    ProtocolTimingPolicy,
+    ProtocolWriteFailureStrategy,
   },
+  intensity_meter::FeatureIntensity,
+  linear_splitter::LinearCmdSplitter,
+  sensor_calibration::SensorCalibrator,
+  sensor_rate_limiter::SensorRateLimiter,
 };
 
+// Identification pulses should be noticeable without being startling, so keep them low and short.
+const IDENTIFY_PULSE_LEVEL: f64 = 0.3;
+const IDENTIFY_PULSE_DURATION: Duration = Duration::from_millis(500);
+// Linear devices move between these two positions, as a short stroke around the middle of travel.
+const IDENTIFY_STROKE_POSITIONS: [f64; 2] = [0.4, 0.6];
+// Wait between connection attempts, multiplied by the number of attempts so far.
+const CONNECTION_RETRY_DELAY: Duration = Duration::from_millis(250);
+
 #[derive(Debug)]
 pub enum ServerDeviceEvent {
   Connected(Arc<ServerDevice>),
@@ -95,18 +128,37 @@ pub enum ServerDeviceEvent {
   Disconnected(UserDeviceIdentifier),
 }
 
-#[derive(Getters)]
+#[derive(Getters, CopyGetters)]
 pub struct ServerDevice {
   hardware: Arc<Hardware>,
   handler: Arc<dyn ProtocolHandler>,
+  /// Definition of the device as clients see it, including any emulated feature.
   #[getset(get = "pub")]
   definition: UserDeviceDefinition,
   actuator_command_manager: ActuatorCommandManager,
+  emulator: Option<CapabilityEmulator>,
+  /// Splits up linear moves longer than the user allows, if they set a limit.
+  linear_splitter: Option<LinearCmdSplitter>,
   /// Unique identifier for the device
   #[getset(get = "pub")]
   identifier: UserDeviceIdentifier,
+  /// How the device hardware is connected
+  #[getset(get_copy = "pub")]
+  transport: DeviceTransport,
   raw_subscribed_endpoints: Arc<DashSet<Endpoint>>,
-  keepalive_packet: Arc<RwLock<Option<HardwareWriteCmd>>>,
+  write_limiter: AdaptiveWriteLimiter,
+  /// Rate limits for sensor subscriptions that asked for one, keyed by feature index.
+  sensor_rate_limiters: Arc<DashMap<u32, SensorRateLimiter>>,
+  /// Calibrations for sensors whose raw range differs from what they declare.
+  sensor_calibrator: Arc<SensorCalibrator>,
+  /// Actuator commands the device has accepted, for anything watching device activity.
+  actuator_command_sender: broadcast::Sender<ButtplugDeviceCommandMessageUnion>,
+  /// Recent commands and hardware results, for working out what happened after the fact.
+  command_audit: Arc<CommandAudit>,
+  /// Throttling in effect after the device used up its energy budget, if any.
+  energy_throttle: EnergyThrottle,
+  /// What to do with actuator command values outside of 0.0 to 1.0.
+  value_policy: OutOfRangeValuePolicy,
 }
 impl Debug for ServerDevice {
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -132,11 +184,54 @@ impl PartialEq for ServerDevice {
   }
 }
 
+async fn recover_from_write_failure(
+  hardware: &Arc<Hardware>,
+  handler: &dyn ProtocolHandler,
+  initializer: &Mutex<Box<dyn ProtocolInitializer>>,
+  definition: &UserDeviceDefinition,
+) {
+  if !hardware.is_connected().await {
+    return;
+  }
+  let result = match handler.on_write_failure() {
+    ProtocolWriteFailureStrategy::Reinitialize => {
+      debug!("Reinitializing {} after write failure.", hardware.name());
+      initializer
+        .lock()
+        .await
+        .initialize(hardware.clone(), definition)
+        .await
+        .map(|_| ())
+    }
+    ProtocolWriteFailureStrategy::Commands(commands) => {
+      let mut result = Ok(());
+      for command in commands {
+        result = hardware.parse_message(&command).await;
+        if result.is_err() {
+          break;
+        }
+      }
+      result
+    }
+    ProtocolWriteFailureStrategy::Ignore => Ok(()),
+  };
+  if let Err(err) = result {
+    warn!(
+      "Could not recover {} after write failure: {:?}",
+      hardware.name(),
+      err
+    );
+  }
+}
+
 impl ServerDevice {
   pub(super) async fn build(
     device_config_manager: Arc<DeviceConfigurationManager>,
     mut hardware_connector: Box<dyn HardwareConnector>,
     protocol_specializers: Vec<ProtocolSpecializer>,
+    connection_attempts: u32,
+    command_audit_size: usize,
+    value_policy: OutOfRangeValuePolicy,
   ) -> Result<Self, ButtplugDeviceError> {
     // We've already checked to make sure we have specializers in the server device manager event
     // loop. That check used to be here for sake of continuity in building devices in this method, but
@@ -144,9 +239,28 @@ impl ServerDevice {
     // #462 for more info.)
 
     // At this point, we know we've got hardware that is waiting to connect, and enough protocol
-    // info to actually do something after we connect. So go ahead and connect.
-    trace!("Connecting to {:?}", hardware_connector);
-    let mut hardware_specializer = hardware_connector.connect().await?;
+    // info to actually do something after we connect. So go ahead and connect. Nothing has been
+    // sent to the device yet, so a failed connection can safely be tried again.
+    let mut attempt = 1;
+    let mut hardware_specializer = loop {
+      trace!(
+        "Connecting to {:?} (attempt {})",
+        hardware_connector,
+        attempt
+      );
+      match hardware_connector.connect().await {
+        Ok(specializer) => break specializer,
+        Err(err) if attempt < connection_attempts => {
+          warn!(
+            "Connection attempt {} of {} to {:?} failed, retrying: {}",
+            attempt, connection_attempts, hardware_connector, err
+          );
+          util::sleep(CONNECTION_RETRY_DELAY * attempt).await;
+          attempt += 1;
+        }
+        Err(err) => return Err(err),
+      }
+    };
 
     // We can't run these in parallel because we need to only accept one specializer.
     let mut protocol_identifier = None;
@@ -203,7 +317,17 @@ impl ServerDevice {
     let strategy = handler.keepalive_strategy();
 
     // We now have fully initialized hardware, return a server device.
-    let device = Self::new(identifier, handler, hardware, &attrs);
+    let transport = hardware_connector.specifier().transport();
+    let device = Self::new(
+      identifier,
+      transport,
+      handler,
+      protocol_initializer,
+      hardware,
+      &attrs,
+      command_audit_size,
+      value_policy,
+    );
 
     // If we need a keepalive with a packet replay, set this up via stopping the device on connect.
     if requires_keepalive
@@ -224,12 +348,21 @@ impl ServerDevice {
   }
 
   /// Given a protocol and a device impl, create a new ButtplugDevice instance
+  #[allow(clippy::too_many_arguments)]
   fn new(
     identifier: UserDeviceIdentifier,
+    transport: DeviceTransport,
     handler: Arc<dyn ProtocolHandler>,
+    initializer: Box<dyn ProtocolInitializer>,
     hardware: Arc<Hardware>,
     definition: &UserDeviceDefinition,
+    command_audit_size: usize,
+    value_policy: OutOfRangeValuePolicy,
   ) -> Self {
+    hardware.set_command_concurrency(handler.command_concurrency());
+    hardware.set_write_retry_policy(handler.write_retry_policy());
+    let command_audit = Arc::new(CommandAudit::new(command_audit_size));
+    command_audit.record(CommandAuditEvent::Connected);
     let keepalive_packet = Arc::new(RwLock::new(None));
     let acm = ActuatorCommandManager::new(definition.features());
     // If we've gotten here, we know our hardware is connected. This means we can start the keepalive if it's required.
@@ -256,7 +389,7 @@ impl ServerDevice {
               }
               ProtocolKeepaliveStrategy::RepeatLastPacketStrategy => {
                 if let Some(packet) = &*keepalive_packet.read().await {
-                  if let Err(e) = hardware.write_value(&packet).await {
+                  if let Err(e) = hardware.parse_message(packet).await {
                     warn!("Error writing keepalive packet: {:?}", e);
                     break;
                   }
@@ -277,17 +410,148 @@ impl ServerDevice {
       });
     }
 
+    let write_limiter = {
+      let hardware = hardware.clone();
+      let handler = handler.clone();
+      let initializer = Arc::new(Mutex::new(initializer));
+      let definition = definition.clone();
+      let command_audit = command_audit.clone();
+      let store_keepalive_packet = hardware.requires_keepalive()
+        && matches!(
+          handler.keepalive_strategy(),
+          ProtocolKeepaliveStrategy::RepeatLastPacketStrategy
+        );
+      let min_interval = handler
+        .timing_policy()
+        .min_command_interval_ms()
+        .map(|interval| Duration::from_millis(interval.into()));
+      AdaptiveWriteLimiter::new(
+        min_interval,
+        Arc::new(move |commands| {
+          let hardware = hardware.clone();
+          let keepalive_packet = keepalive_packet.clone();
+          let handler = handler.clone();
+          let initializer = initializer.clone();
+          let definition = definition.clone();
+          let command_audit = command_audit.clone();
+          async move {
+            // Run commands in order, otherwise we may end up sending out of order. This may take a
+            // while, but it's what 99% of protocols expect. If they want something else, they can
+            // implement it themselves. How these writes may overlap with everything else talking to
+            // the device is up to the protocol's command concurrency, which the hardware enforces.
+            //
+            // If anything errors out, just bail on the command series. This most likely means the
+            // device disconnected, but if it's still around, give the protocol a chance to resync
+            // first.
+            for command in commands {
+              let result = hardware.parse_message(&command).await;
+              if command_audit.enabled() {
+                command_audit.record(CommandAuditEvent::Hardware {
+                  command: command.clone(),
+                  result: result.clone(),
+                });
+              }
+              if let Err(err) = result {
+                if matches!(
+                  command,
+                  HardwareCommand::Write(_) | HardwareCommand::WriteTransaction(_)
+                ) {
+                  recover_from_write_failure(&hardware, &*handler, &initializer, &definition).await;
+                }
+                return Err(err);
+              }
+              if store_keepalive_packet
+                && matches!(
+                  command,
+                  HardwareCommand::Write(_) | HardwareCommand::WriteTransaction(_)
+                )
+              {
+                *keepalive_packet.write().await = Some(command);
+              }
+            }
+            Ok(())
+          }
+          .boxed()
+        }),
+      )
+    };
+
+    let sensor_calibrator = Arc::new(SensorCalibrator::new(
+      definition.features(),
+      definition.user_config().sensor_calibrations(),
+    ));
+
+    // Linear commands the device sends on its own, for emulation and split up moves.
+    let send_linear: LinearCmdSender = {
+      let handler = handler.clone();
+      let write_limiter = write_limiter.clone();
+      Arc::new(move |msg| {
+        let command_result = if handler.has_handle_message() {
+          handler.handle_message(&msg.into())
+        } else {
+          handler.handle_linear_cmd(msg)
+        };
+        let write_fut = command_result.map(|commands| write_limiter.write(None, commands));
+        async move { Ok(write_fut?.await?) }.boxed()
+      })
+    };
+    let linear_splitter = definition
+      .user_config()
+      .max_linear_duration_ms()
+      .map(|max_duration_ms| LinearCmdSplitter::new(max_duration_ms, send_linear.clone()));
+
+    // Emulated features are only known to clients and the emulator, so this has to come after
+    // everything that works with the device's real features has been set up.
+    let mut definition = definition.clone();
+    let emulator = definition.user_config().emulation().and_then(|emulation| {
+      let Some((feature, targets)) =
+        capability_emulator::emulated_feature(emulation, definition.features())
+      else {
+        warn!(
+          "{} has no actuators that can emulate {:?}, leaving emulation off.",
+          definition.name(),
+          emulation
+        );
+        return None;
+      };
+      let feature_index = definition.features().len() as u32;
+      definition.features_mut().push(feature);
+      Some(CapabilityEmulator::new(
+        emulation,
+        feature_index,
+        targets,
+        send_linear,
+      ))
+    });
+
     Self {
       identifier,
+      transport,
       actuator_command_manager: acm,
+      emulator,
+      linear_splitter,
       handler,
       hardware,
-      keepalive_packet,
-      definition: definition.clone(),
+      write_limiter,
+      definition,
       raw_subscribed_endpoints: Arc::new(DashSet::new()),
+      sensor_rate_limiters: Arc::new(DashMap::new()),
+      sensor_calibrator,
+      actuator_command_sender: broadcast::channel(256).0,
+      command_audit,
+      energy_throttle: EnergyThrottle::default(),
+      value_policy,
     }
   }
 
+  pub(super) fn command_audit(&self) -> &Arc<CommandAudit> {
+    &self.command_audit
+  }
+
+  pub(super) fn energy_throttle(&self) -> &EnergyThrottle {
+    &self.energy_throttle
+  }
+
   /// Get the name of the device as set in the Device Configuration File.
   ///
   /// This will also append "(Raw Messaged Allowed)" to the device name if raw mode is on, to warn
@@ -317,6 +581,11 @@ impl ServerDevice {
     async move { fut.await.map_err(|err| err.into()) }.boxed()
   }
 
+  /// Check whether the hardware connection for the device is still alive.
+  pub fn is_connected(&self) -> BoxFuture<'static, bool> {
+    self.hardware.is_connected()
+  }
+
   /// Retreive the event stream for the device.
   ///
   /// This will include connections, disconnections, and notification events from subscribed
@@ -324,6 +593,12 @@ impl ServerDevice {
   pub fn event_stream(&self) -> impl futures::Stream<Item = ServerDeviceEvent> + Send {
     let identifier = self.identifier.clone();
     let raw_endpoints = self.raw_subscribed_endpoints.clone();
+    // Signal strength is only passed on for devices that declare an RSSI sensor to report it on.
+    let rssi_feature = self
+      .definition
+      .features()
+      .iter()
+      .position(|feature| *feature.feature_type() == FeatureType::RSSI);
     let hardware_stream = convert_broadcast_receiver_to_stream(self.hardware.event_stream())
       .filter_map(move |hardware_event| {
         let id = identifier.clone();
@@ -340,17 +615,167 @@ impl ServerDevice {
               None
             }
           }
+          HardwareEvent::Rssi(_address, rssi) => rssi_feature.map(|feature_index| {
+            ServerDeviceEvent::Notification(
+              id,
+              ButtplugServerDeviceMessage::SensorReading(SensorReadingV4::new(
+                0,
+                feature_index as u32,
+                SensorType::RSSI,
+                vec![rssi],
+              )),
+            )
+          }),
         }
       });
 
     let identifier = self.identifier.clone();
-    let handler_mapped_stream = self.handler.event_stream().map(move |incoming_message| {
-      let id = identifier.clone();
-      ServerDeviceEvent::Notification(id, incoming_message)
-    });
+    let rate_limiters = self.sensor_rate_limiters.clone();
+    let calibrator = self.sensor_calibrator.clone();
+    let handler_mapped_stream = self
+      .handler
+      .event_stream()
+      .filter_map(move |incoming_message| {
+        let id = identifier.clone();
+        let ButtplugServerDeviceMessage::SensorReading(reading) = &incoming_message else {
+          return Some(ServerDeviceEvent::Notification(id, incoming_message));
+        };
+        let mut data = calibrator.process(reading.feature_index(), reading.data());
+        if let Some(mut limiter) = rate_limiters.get_mut(&reading.feature_index()) {
+          data = limiter.process(&data)?;
+        }
+        Some(ServerDeviceEvent::Notification(
+          id,
+          ButtplugServerDeviceMessage::SensorReading(SensorReadingV4::new(
+            reading.device_index(),
+            reading.feature_index(),
+            reading.sensor_type(),
+            data,
+          )),
+        ))
+      });
     hardware_stream.merge(handler_mapped_stream)
   }
 
+  /// Stream of the actuator commands the device accepts, from any source. Commands that don't
+  /// change anything are left out, and stopping the device shows up as the commands that zero its
+  /// actuators. Device indexes are whatever the sender used.
+  pub fn actuator_command_stream(
+    &self,
+  ) -> impl futures::Stream<Item = ButtplugDeviceCommandMessageUnion> + Send {
+    convert_broadcast_receiver_to_stream(self.actuator_command_sender.subscribe())
+  }
+
+  fn announce_actuator_command(&self, command: ButtplugDeviceCommandMessageUnion) {
+    // Having no one listening is the usual case.
+    let _ = self.actuator_command_sender.send(command);
+  }
+
+  /// Run a short, device appropriate action (usually a brief vibration or small stroke) so the user
+  /// can tell which physical device this is.
+  ///
+  /// The device is stopped once the action finishes, so this should not be run while the device is
+  /// being controlled by something else.
+  pub async fn identify(&self) -> Result<(), ButtplugError> {
+    match self.handler.identify_strategy() {
+      ProtocolIdentifyStrategy::ActuatorPulse => self.identify_with_actuator_pulse().await,
+      ProtocolIdentifyStrategy::Unsupported => Err(
+        ButtplugDeviceError::UnhandledCommand(format!(
+          "{} does not support identification",
+          self.name()
+        ))
+        .into(),
+      ),
+    }
+  }
+
+  /// Ask the device about itself (firmware version, battery, error flags, etc...). Fails if the
+  /// device's protocol can't query any of it.
+  pub fn diagnostics(&self) -> ButtplugResultFuture<ProtocolDiagnostics> {
+    let fut = self.handler.handle_diagnostics(self.hardware.clone());
+    async move { Ok(fut.await?) }.boxed()
+  }
+
+  async fn identify_with_actuator_pulse(&self) -> Result<(), ButtplugError> {
+    let mut scalars = vec![];
+    let mut rotations = vec![];
+    let mut linear_features = vec![];
+    for (index, feature) in self.definition.features().iter().enumerate() {
+      let Some(actuator) = feature.actuator() else {
+        continue;
+      };
+      let index = index as u32;
+      let supports = |msg_type| actuator.messages().contains(&msg_type);
+      match feature.feature_type() {
+        FeatureType::Vibrate if supports(ButtplugActuatorFeatureMessageType::ScalarCmd) => scalars
+          .push(ScalarSubcommandV4::new(
+            index,
+            IDENTIFY_PULSE_LEVEL,
+            ActuatorType::Vibrate,
+          )),
+        FeatureType::Oscillate if supports(ButtplugActuatorFeatureMessageType::ScalarCmd) => {
+          scalars.push(ScalarSubcommandV4::new(
+            index,
+            IDENTIFY_PULSE_LEVEL,
+            ActuatorType::Oscillate,
+          ))
+        }
+        FeatureType::Rotate if supports(ButtplugActuatorFeatureMessageType::RotateCmd) => {
+          rotations.push(RotationSubcommandV4::new(index, IDENTIFY_PULSE_LEVEL, true))
+        }
+        FeatureType::Rotate if supports(ButtplugActuatorFeatureMessageType::ScalarCmd) => scalars
+          .push(ScalarSubcommandV4::new(
+            index,
+            IDENTIFY_PULSE_LEVEL,
+            ActuatorType::Rotate,
+          )),
+        FeatureType::Position if supports(ButtplugActuatorFeatureMessageType::LinearCmd) => {
+          linear_features.push(index)
+        }
+        // Constriction and inflation can't be undone quickly, so leave them alone.
+        _ => {}
+      }
+    }
+
+    if scalars.is_empty() && rotations.is_empty() && linear_features.is_empty() {
+      return Err(
+        ButtplugDeviceError::UnhandledCommand(format!(
+          "{} has no actuators that can be used for identification",
+          self.name()
+        ))
+        .into(),
+      );
+    }
+
+    if !scalars.is_empty() {
+      self
+        .parse_message(ScalarCmdV4::new(0, scalars).into())
+        .await?;
+    }
+    if !rotations.is_empty() {
+      self
+        .parse_message(RotateCmdV4::new(0, rotations).into())
+        .await?;
+    }
+    if !linear_features.is_empty() {
+      let duration = IDENTIFY_PULSE_DURATION.as_millis() as u32;
+      for position in IDENTIFY_STROKE_POSITIONS {
+        let vectors = linear_features
+          .iter()
+          .map(|index| VectorSubcommandV4::new(*index, duration, position))
+          .collect();
+        self
+          .parse_message(LinearCmdV4::new(0, vectors).into())
+          .await?;
+        util::sleep(IDENTIFY_PULSE_DURATION).await;
+      }
+    } else {
+      util::sleep(IDENTIFY_PULSE_DURATION).await;
+    }
+    self.handle_stop_device_cmd().await?;
+    Ok(())
+  }
+
   pub fn supports_message(
     &self,
     message: &ButtplugDeviceCommandMessageUnion,
@@ -386,6 +811,9 @@ impl ServerDevice {
       ButtplugDeviceCommandMessageUnion::ScalarCmd(_) => {
         check_msg(ButtplugDeviceMessageType::ScalarCmd)
       }
+      ButtplugDeviceCommandMessageUnion::AxisCmd(_) => {
+        check_msg(ButtplugDeviceMessageType::AxisCmd)
+      }
       ButtplugDeviceCommandMessageUnion::StopDeviceCmd(_) => {
         //check_msg(ButtplugDeviceMessageType::StopDeviceCmd)
         Ok(())
@@ -409,15 +837,76 @@ impl ServerDevice {
   pub fn parse_message(
     &self,
     command_message: ButtplugDeviceCommandMessageUnion,
+  ) -> ButtplugServerResultFuture {
+    if !self.command_audit.enabled() {
+      return self.route_message(command_message);
+    }
+    let fut = self.route_message(command_message.clone());
+    let command_audit = self.command_audit.clone();
+    async move {
+      let result = fut.await;
+      command_audit.record(CommandAuditEvent::Command {
+        message: command_message,
+        result: result.as_ref().map(|_| ()).map_err(|err| err.clone()),
+      });
+      result
+    }
+    .boxed()
+  }
+
+  fn route_message(
+    &self,
+    command_message: ButtplugDeviceCommandMessageUnion,
   ) -> ButtplugServerResultFuture {
     if let Err(err) = self.supports_message(&command_message) {
       return future::ready(Err(err)).boxed();
     }
+    let command_message =
+      command_validation::apply_value_policy(command_message, self.value_policy);
+    if let Err(err) =
+      command_validation::validate_command(self.definition.features(), &command_message)
+    {
+      return future::ready(Err(err.into())).boxed();
+    }
+    let command_message = match self.energy_throttle.apply(command_message) {
+      Ok(command_message) => command_message,
+      Err(err) => return future::ready(Err(err)).boxed(),
+    };
+
+    let Some(emulator) = &self.emulator else {
+      return self.dispatch_message(command_message);
+    };
+    // Whatever was sent to the emulated feature comes back as messages for the device's real
+    // features, if the emulator doesn't take care of it on its own.
+    let mut messages = match emulator.translate(command_message) {
+      Ok(messages) => messages,
+      Err(err) => return future::ready(Err(err.into())).boxed(),
+    };
+    if messages.len() == 1 {
+      return self.dispatch_message(messages.remove(0));
+    }
+    let fut_vec: Vec<_> = messages
+      .into_iter()
+      .map(|msg| self.dispatch_message(msg))
+      .collect();
+    async move {
+      for fut in fut_vec {
+        fut.await?;
+      }
+      Ok(message::OkV0::default().into())
+    }
+    .boxed()
+  }
 
+  fn dispatch_message(
+    &self,
+    command_message: ButtplugDeviceCommandMessageUnion,
+  ) -> ButtplugServerResultFuture {
     // If a handler implements handle message, bypass all of our parsing and let it do its own
     // thing. This should be a very rare thing.
     if self.handler.has_handle_message() {
-      let fut = self.handle_generic_command_result(self.handler.handle_message(&command_message));
+      let fut =
+        self.handle_generic_command_result(None, self.handler.handle_message(&command_message));
       return async move { fut.await }.boxed();
     }
 
@@ -440,63 +929,58 @@ impl ServerDevice {
       // Actuator messages
       ButtplugDeviceCommandMessageUnion::ScalarCmd(msg) => self.handle_scalarcmd_v4(&msg),
       ButtplugDeviceCommandMessageUnion::RotateCmd(msg) => {
+        let full_command_set = self.needs_full_command_set();
         let commands = match self
           .actuator_command_manager
-          .update_rotation(&msg, self.handler.needs_full_command_set())
+          .update_rotation(&msg, full_command_set)
         {
           Ok(values) => values,
           Err(err) => return future::ready(Err(err)).boxed(),
         };
-        self.handle_generic_command_result(self.handler.handle_rotate_cmd(&commands))
+        let command_result = self.handler.handle_rotate_cmd(&commands);
+        if command_result.is_ok() {
+          self.announce_actuator_command(msg.into());
+        }
+        self.handle_generic_command_result(
+          full_command_set.then_some(CoalesceKey::Rotate),
+          command_result,
+        )
       }
       ButtplugDeviceCommandMessageUnion::LinearCmd(msg) => {
-        self.handle_generic_command_result(self.handler.handle_linear_cmd(msg))
+        let (first_segments, remaining) = match &self.linear_splitter {
+          Some(splitter) => {
+            let (first_segments, remaining) = splitter.split(&msg);
+            (first_segments, Some(remaining))
+          }
+          None => (msg.clone(), None),
+        };
+        let command_result = self.handler.handle_linear_cmd(first_segments);
+        if command_result.is_ok() {
+          self.announce_actuator_command(msg.into());
+        }
+        let result_fut = self.handle_generic_command_result(None, command_result);
+        match remaining {
+          // The rest of a split move only goes out once the first segment has.
+          Some(remaining) => async move {
+            let result = result_fut.await?;
+            remaining.start();
+            Ok(result)
+          }
+          .boxed(),
+          None => result_fut,
+        }
       }
+      ButtplugDeviceCommandMessageUnion::AxisCmd(msg) => self.handle_axiscmd_v4(&msg),
       // Other generic messages
       ButtplugDeviceCommandMessageUnion::StopDeviceCmd(_) => self.handle_stop_device_cmd(),
     }
   }
 
   fn handle_scalarcmd_v4(&self, msg: &ScalarCmdV4) -> ButtplugServerResultFuture {
-    if msg.scalars().is_empty() {
-      return future::ready(Err(
-        ButtplugDeviceError::ProtocolRequirementError(
-          "ScalarCmd with no subcommands is not valid.".to_owned(),
-        )
-        .into(),
-      ))
-      .boxed();
-    }
-
-    for command in msg.scalars() {
-      if command.feature_index() > self.definition.features().len() as u32 {
-        return future::ready(Err(
-          ButtplugDeviceError::DeviceFeatureIndexError(
-            self.definition.features().len() as u32,
-            command.feature_index(),
-          )
-          .into(),
-        ))
-        .boxed();
-      }
-      let feature_type =
-        self.definition.features()[command.feature_index() as usize].feature_type();
-      if *feature_type != command.actuator_type().into() {
-        return future::ready(Err(
-          ButtplugDeviceError::DeviceActuatorTypeMismatch(
-            self.name(),
-            command.actuator_type(),
-            *feature_type,
-          )
-          .into(),
-        ))
-        .boxed();
-      }
-    }
-
+    let full_command_set = self.needs_full_command_set();
     let commands = match self
       .actuator_command_manager
-      .update_scalar(&msg, self.handler.needs_full_command_set())
+      .update_scalar(msg, full_command_set)
     {
       Ok(values) => values,
       Err(err) => return future::ready(Err(err)).boxed(),
@@ -506,33 +990,121 @@ impl ServerDevice {
       trace!("No commands generated for incoming device packet, skipping and returning success.");
       return future::ready(Ok(message::OkV0::default().into())).boxed();
     }
-    self.handle_generic_command_result(self.handler.handle_scalar_cmd(&commands))
+    let command_result = self.handler.handle_scalar_cmd(&commands);
+    if command_result.is_ok() {
+      self.announce_actuator_command(msg.clone().into());
+    }
+    self.handle_generic_command_result(
+      full_command_set.then_some(CoalesceKey::Scalar),
+      command_result,
+    )
+  }
+
+  fn handle_axiscmd_v4(&self, msg: &AxisCmdV4) -> ButtplugServerResultFuture {
+    // Protocols get positions as a fraction of the axis' full step range, with the position the
+    // client asked for already squeezed into whatever step limit the user set.
+    let mut axes = vec![];
+    for command in msg.axes() {
+      let Some(actuator) = self
+        .definition
+        .features()
+        .iter()
+        .filter_map(|feature| feature.actuator().as_ref())
+        .find(|actuator| {
+          *actuator.axis() == Some(command.axis())
+            && actuator
+              .messages()
+              .contains(&ButtplugActuatorFeatureMessageType::AxisCmd)
+        })
+      else {
+        return future::ready(Err(
+          ButtplugDeviceError::DeviceAxisNotFound(self.name(), command.axis()).into(),
+        ))
+        .boxed();
+      };
+      let limit = actuator.step_limit();
+      let step = *limit.start() as f64 + command.position() * (limit.end() - limit.start()) as f64;
+      let range_end = (*actuator.step_range().end()).max(1) as f64;
+      axes.push(AxisSubcommandV4::new(
+        command.axis(),
+        command.duration(),
+        step / range_end,
+      ));
+    }
+
+    let command_result = self
+      .handler
+      .handle_axis_cmd(AxisCmdV4::new(msg.device_index(), axes));
+    if command_result.is_ok() {
+      self.announce_actuator_command(msg.clone().into());
+    }
+    self.handle_generic_command_result(None, command_result)
+  }
+
+  /// Whether actuator updates should be sent as full command sets. Protocols may require this, but
+  /// we also switch to it while the link is congested or the protocol's rate limit is holding
+  /// updates back, as full sets are what allow queued updates to be coalesced.
+  fn needs_full_command_set(&self) -> bool {
+    self.handler.needs_full_command_set()
+      || self.write_limiter.is_congested()
+      || self.write_limiter.is_throttled()
   }
 
-  fn handle_hardware_commands(&self, commands: Vec<HardwareCommand>) -> ButtplugServerResultFuture {
-    let hardware = self.hardware.clone();
-    let keepalive_type = self.handler.keepalive_strategy();
-    let keepalive_packet = self.keepalive_packet.clone();
+  /// How fast the device can take actuator updates, as declared by its protocol.
+  pub fn timing_policy(&self) -> ProtocolTimingPolicy {
+    self.handler.timing_policy()
+  }
+
+  /// Levels the device's scalar and rotation features were last set to.
+  pub fn actuator_intensities(&self) -> Vec<FeatureIntensity> {
+    self.actuator_command_manager.intensities()
+  }
+
+  /// Start a calibration session for the sensor at `feature_index`. Until the session is finished,
+  /// the sensor's readings are passed on raw and its lowest and highest values are recorded, so
+  /// something needs to be subscribed to or reading the sensor in the meantime.
+  pub fn start_sensor_calibration(&self, feature_index: u32) -> Result<(), ButtplugDeviceError> {
+    self.sensor_calibrator.start_session(feature_index)
+  }
+
+  /// Finish the calibration session for the sensor at `feature_index`, calibrating it to the range
+  /// of values it reported during the session.
+  pub fn finish_sensor_calibration(
+    &self,
+    feature_index: u32,
+  ) -> Result<SensorCalibration, ButtplugDeviceError> {
+    self.sensor_calibrator.finish_session(feature_index)
+  }
+
+  /// Drop any calibration (or running calibration session) for the sensor at `feature_index`.
+  pub fn clear_sensor_calibration(&self, feature_index: u32) -> Result<(), ButtplugDeviceError> {
+    self.sensor_calibrator.clear(feature_index)
+  }
+
+  /// Number of failed writes that have been retried, see
+  /// [ProtocolWriteRetryPolicy](super::protocol::ProtocolWriteRetryPolicy).
+  pub fn write_retry_count(&self) -> u64 {
+    self.hardware.write_retry_count()
+  }
+
+  /// Turn dry run mode on or off for this device, see [Hardware::set_dry_run].
+  pub fn set_dry_run(&self, dry_run: bool) {
+    self.hardware.set_dry_run(dry_run);
+  }
+
+  /// Whether commands to this device are being held back from the hardware.
+  pub fn dry_run(&self) -> bool {
+    self.hardware.dry_run()
+  }
+
+  fn handle_hardware_commands(
+    &self,
+    coalesce_key: Option<CoalesceKey>,
+    commands: Vec<HardwareCommand>,
+  ) -> ButtplugServerResultFuture {
+    let fut = self.write_limiter.write(coalesce_key, commands);
     async move {
-      // Run commands in order, otherwise we may end up sending out of order. This may take a while,
-      // but it's what 99% of protocols expect. If they want something else, they can implement it
-      // themselves.
-      //
-      // If anything errors out, just bail on the command series. This most likely means the device
-      // disconnected.
-      for command in commands {
-        hardware.parse_message(&command).await?;
-        if hardware.requires_keepalive()
-          && matches!(
-            keepalive_type,
-            ProtocolKeepaliveStrategy::RepeatLastPacketStrategy
-          )
-        {
-          if let HardwareCommand::Write(command) = command {
-            *keepalive_packet.write().await = Some(command);
-          }
-        }
-      }
+      fut.await?;
       Ok(message::OkV0::default().into())
     }
     .boxed()
@@ -540,6 +1112,7 @@ impl ServerDevice {
 
   fn handle_generic_command_result(
     &self,
+    coalesce_key: Option<CoalesceKey>,
     command_result: Result<Vec<HardwareCommand>, ButtplugDeviceError>,
   ) -> ButtplugServerResultFuture {
     let hardware_commands = match command_result {
@@ -547,10 +1120,16 @@ impl ServerDevice {
       Err(err) => return future::ready(Err(err.into())).boxed(),
     };
 
-    self.handle_hardware_commands(hardware_commands)
+    self.handle_hardware_commands(coalesce_key, hardware_commands)
   }
 
   fn handle_stop_device_cmd(&self) -> ButtplugServerResultFuture {
+    if let Some(emulator) = &self.emulator {
+      emulator.stop();
+    }
+    if let Some(splitter) = &self.linear_splitter {
+      splitter.stop();
+    }
     let commands = self.actuator_command_manager.stop_commands();
     let mut fut_vec = vec![];
     commands
@@ -565,6 +1144,99 @@ impl ServerDevice {
     .boxed()
   }
 
+  /// Firmware version the device reported while connecting, if its protocol knows how to ask.
+  pub fn firmware_version(&self) -> Option<String> {
+    self.handler.firmware_version()
+  }
+
+  /// Number of patterns built into the device firmware, or 0 if the device doesn't have any.
+  pub fn firmware_pattern_count(&self) -> u32 {
+    self.handler.firmware_pattern_count()
+  }
+
+  /// Start a pattern built into the device firmware, replacing any firmware pattern that's already
+  /// running. `pattern` must be less than [ServerDevice::firmware_pattern_count].
+  pub fn start_firmware_pattern(&self, pattern: u32) -> ButtplugServerResultFuture {
+    let pattern_count = self.firmware_pattern_count();
+    if pattern >= pattern_count {
+      return future::ready(Err(
+        ButtplugDeviceError::ProtocolRequirementError(format!(
+          "Device has {} firmware patterns, but pattern {} was requested.",
+          pattern_count, pattern
+        ))
+        .into(),
+      ))
+      .boxed();
+    }
+    self.handle_generic_command_result(
+      None,
+      self.handler.handle_firmware_pattern_cmd(Some(pattern)),
+    )
+  }
+
+  /// Stop the firmware pattern running on the device, if any.
+  pub fn stop_firmware_pattern(&self) -> ButtplugServerResultFuture {
+    if self.firmware_pattern_count() == 0 {
+      return future::ready(Err(
+        ButtplugDeviceError::UnhandledCommand(format!(
+          "{} does not have firmware patterns",
+          self.name()
+        ))
+        .into(),
+      ))
+      .boxed();
+    }
+    self.handle_generic_command_result(None, self.handler.handle_firmware_pattern_cmd(None))
+  }
+
+  /// Commands that will restore this device's current actuator values on a new connection to the
+  /// same hardware. See [ActuatorCommandManager::replay_commands].
+  pub(crate) fn replay_commands(&self) -> Vec<ButtplugDeviceCommandMessageUnion> {
+    self.actuator_command_manager.replay_commands()
+  }
+
+  /// Stop the given features, leaving the rest of the device running. Stops are sent as zero value
+  /// actuator commands, so they go through the protocol the same way any other speed change would.
+  /// Features configured to hold on stop are left as they are.
+  pub fn stop_features(&self, feature_indexes: &[u32]) -> ButtplugServerResultFuture {
+    let feature_count = self.definition.features().len() as u32;
+    if let Some(index) = feature_indexes
+      .iter()
+      .find(|index| **index >= feature_count)
+    {
+      return future::ready(Err(
+        ButtplugDeviceError::DeviceFeatureIndexError(feature_count, *index).into(),
+      ))
+      .boxed();
+    }
+    let mut feature_indexes = feature_indexes.to_vec();
+    if let Some(emulator) = &self.emulator {
+      let emulated_count = feature_indexes.len();
+      feature_indexes.retain(|index| *index != emulator.feature_index());
+      if feature_indexes.len() != emulated_count {
+        emulator.stop();
+      }
+    }
+    let commands = match self
+      .actuator_command_manager
+      .stop_feature_commands(&feature_indexes)
+    {
+      Ok(commands) => commands,
+      Err(err) => return future::ready(Err(err.into())).boxed(),
+    };
+    let fut_vec: Vec<_> = commands
+      .into_iter()
+      .map(|msg| self.parse_message(msg))
+      .collect();
+    async move {
+      for fut in fut_vec {
+        fut.await?;
+      }
+      Ok(message::OkV0::default().into())
+    }
+    .boxed()
+  }
+
   fn check_sensor_command(
     &self,
     feature_index: &u32,
@@ -595,13 +1267,19 @@ impl ServerDevice {
     let result = self.check_sensor_command(message.feature_index(), message.sensor_type());
     let device = self.hardware.clone();
     let handler = self.handler.clone();
+    let calibrator = self.sensor_calibrator.clone();
     async move {
       result?;
-      handler
-        .handle_sensor_read_cmd(device, &message)
-        .await
-        .map_err(|e| e.into())
-        .map(|e| e.into())
+      let reading = handler.handle_sensor_read_cmd(device, &message).await?;
+      Ok(
+        SensorReadingV4::new(
+          reading.device_index(),
+          reading.feature_index(),
+          reading.sensor_type(),
+          calibrator.process(reading.feature_index(), reading.data()),
+        )
+        .into(),
+      )
     }
     .boxed()
   }
@@ -613,13 +1291,23 @@ impl ServerDevice {
     let result = self.check_sensor_command(message.feature_index(), message.sensor_type());
     let device = self.hardware.clone();
     let handler = self.handler.clone();
+    let rate_limiters = self.sensor_rate_limiters.clone();
     async move {
       result?;
       handler
         .handle_sensor_subscribe_cmd(device, &message)
-        .await
-        .map(|_| message::OkV0::new(message.id()).into())
-        .map_err(|e| e.into())
+        .await?;
+      // Resubscribing replaces any earlier rate limit.
+      let feature_index = *message.feature_index();
+      if let Some(max_rate) = message.max_rate() {
+        rate_limiters.insert(
+          feature_index,
+          SensorRateLimiter::new(max_rate, message.decimation()),
+        );
+      } else {
+        rate_limiters.remove(&feature_index);
+      }
+      Ok(message::OkV0::new(message.id()).into())
     }
     .boxed()
   }
@@ -631,13 +1319,14 @@ impl ServerDevice {
     let result = self.check_sensor_command(message.feature_index(), message.sensor_type());
     let device = self.hardware.clone();
     let handler = self.handler.clone();
+    let rate_limiters = self.sensor_rate_limiters.clone();
     async move {
       result?;
       handler
         .handle_sensor_unsubscribe_cmd(device, &message)
-        .await
-        .map(|_| message::OkV0::new(message.id()).into())
-        .map_err(|e| e.into())
+        .await?;
+      rate_limiters.remove(message.feature_index());
+      Ok(message::OkV0::new(message.id()).into())
     }
     .boxed()
   }
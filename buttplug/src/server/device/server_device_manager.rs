@@ -10,7 +10,7 @@
 
 use crate::{
   core::{
-    errors::{ButtplugDeviceError, ButtplugMessageError, ButtplugUnknownError},
+    errors::{ButtplugDeviceError, ButtplugError, ButtplugMessageError, ButtplugUnknownError},
     message::{
       self,
       ButtplugClientMessageV4,
@@ -19,65 +19,158 @@ use crate::{
       ButtplugDeviceMessage,
       ButtplugMessage,
       ButtplugServerMessageV4,
+      DeviceInfoV4,
       DeviceListV4,
       DeviceMessageInfoV4,
+      PatternSessionStartedV4,
+      ScanningProgressV4,
     },
+    ButtplugResultFuture,
   },
   server::{
     device::{
-      configuration::{DeviceConfigurationManager, UserDeviceIdentifier},
+      command_audit::{CommandAudit, CommandAuditEntry},
+      command_validation::OutOfRangeValuePolicy,
+      configuration::{DeviceConfigurationManager, SensorCalibration, UserDeviceIdentifier},
+      device_link::{start_device_link, DeviceLink},
+      device_list_history::DeviceListHistory,
+      energy_budget::{EnergyBudgetEvent, EnergyBudgetPolicy},
+      event_bus::{DeviceManagerEvent, DeviceManagerEventBus},
       hardware::communication::{
         HardwareCommunicationManager,
         HardwareCommunicationManagerBuilder,
+        HardwareCommunicationManagerStatus,
+        HardwareFirmwareVersion,
+      },
+      intensity_meter::IntensityMeterReading,
+      pattern_session::{start_pattern_session, PatternSession, PatternSessionEvent},
+      protocol::ProtocolDiagnostics,
+      server_device_manager_event_loop::{
+        build_comm_manager,
+        CommManagerSlot,
+        ServerDeviceManagerEventLoop,
       },
-      server_device_manager_event_loop::ServerDeviceManagerEventLoop,
       ServerDevice,
     },
     ButtplugServerError,
     ButtplugServerResultFuture,
   },
-  util::{async_manager, stream::convert_broadcast_receiver_to_stream},
+  util::{
+    async_manager,
+    system_resume::{
+      system_resume_events,
+      DEFAULT_RESUME_CHECK_INTERVAL,
+      DEFAULT_RESUME_THRESHOLD,
+    },
+  },
 };
 use dashmap::DashMap;
 use futures::{
   future::{self, FutureExt},
   Stream,
 };
-use getset::Getters;
+use getset::{CopyGetters, Getters};
 use std::{
+  collections::HashMap,
   convert::TryFrom,
   sync::{
-    atomic::{AtomicBool, Ordering},
+    atomic::{AtomicBool, AtomicU32, Ordering},
     Arc,
+    Mutex,
   },
+  time::Duration,
 };
-use tokio::sync::{broadcast, mpsc};
+use tokio::sync::mpsc;
 use tokio_util::sync::CancellationToken;
 
+/// Default amount of time a communication manager gets to report that scanning has started before
+/// we stop waiting on it.
+const DEFAULT_SCANNING_START_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Default time between [ScanningProgress] updates while a scan is running.
+const DEFAULT_SCANNING_PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Default number of times a failed communication manager is rebuilt before it's disabled.
+const DEFAULT_MAX_COMM_MANAGER_RESTARTS: u32 = 3;
+
+/// Default number of events kept in each device's command audit.
+const DEFAULT_COMMAND_AUDIT_SIZE: usize = 50;
+
 #[derive(Debug)]
 pub(super) enum DeviceManagerCommand {
   StartScanning,
   StopScanning,
 }
 
-#[derive(Debug, Getters)]
-#[getset(get = "pub")]
+#[derive(Debug, Getters, CopyGetters)]
 pub struct ServerDeviceInfo {
+  #[getset(get = "pub")]
   identifier: UserDeviceIdentifier,
+  #[getset(get = "pub")]
+  name: String,
+  #[getset(get = "pub")]
   display_name: Option<String>,
+  /// Number of patterns built into the device firmware, see
+  /// [ServerDeviceManager::start_device_firmware_pattern].
+  #[getset(get_copy = "pub")]
+  firmware_pattern_count: u32,
+}
+
+/// Periodic update sent while scanning is running, so applications can show that a scan is alive
+/// and making progress instead of stuck. See [ServerDeviceManager::scanning_progress_stream].
+#[derive(Debug, Clone, PartialEq, Eq, Getters, CopyGetters)]
+pub struct ScanningProgress {
+  /// Number of distinct devices each communication manager has found since scanning started, keyed
+  /// by manager name. Every manager is listed, including ones that haven't found anything yet.
+  #[getset(get = "pub")]
+  devices_found: HashMap<&'static str, usize>,
+  /// Time since scanning started.
+  #[getset(get_copy = "pub")]
+  elapsed: Duration,
+}
+
+impl ScanningProgress {
+  pub(super) fn new(devices_found: HashMap<&'static str, usize>, elapsed: Duration) -> Self {
+    Self {
+      devices_found,
+      elapsed,
+    }
+  }
+}
+
+impl From<ScanningProgress> for ScanningProgressV4 {
+  fn from(progress: ScanningProgress) -> Self {
+    ScanningProgressV4::new(
+      progress
+        .devices_found
+        .into_iter()
+        .map(|(name, count)| (name.to_owned(), count as u32))
+        .collect(),
+      progress.elapsed.as_millis() as u32,
+    )
+  }
 }
 
 pub struct ServerDeviceManagerBuilder {
   device_configuration_manager: Arc<DeviceConfigurationManager>,
   comm_managers: Vec<Box<dyn HardwareCommunicationManagerBuilder>>,
+  scanning_start_timeout: Duration,
+  manager_scanning_start_timeouts: HashMap<String, Duration>,
+  detect_system_resume: bool,
+  restart_scanning_on_resume: bool,
+  replay_state_on_reconnect: bool,
+  scanning_progress_interval: Duration,
+  intensity_metering_interval: Option<Duration>,
+  energy_budget: Option<EnergyBudgetPolicy>,
+  connection_attempts: u32,
+  command_audit_size: usize,
+  value_policy: OutOfRangeValuePolicy,
+  max_comm_manager_restarts: u32,
 }
 
 impl ServerDeviceManagerBuilder {
   pub fn new(device_configuration_manager: DeviceConfigurationManager) -> Self {
-    Self {
-      device_configuration_manager: Arc::new(device_configuration_manager),
-      comm_managers: vec![],
-    }
+    Self::new_with_arc(Arc::new(device_configuration_manager))
   }
 
   /// Use a prebuilt device configuration manager that needs to be shared with the outside world
@@ -86,6 +179,18 @@ impl ServerDeviceManagerBuilder {
     Self {
       device_configuration_manager,
       comm_managers: vec![],
+      scanning_start_timeout: DEFAULT_SCANNING_START_TIMEOUT,
+      manager_scanning_start_timeouts: HashMap::new(),
+      detect_system_resume: false,
+      restart_scanning_on_resume: false,
+      replay_state_on_reconnect: false,
+      scanning_progress_interval: DEFAULT_SCANNING_PROGRESS_INTERVAL,
+      intensity_metering_interval: None,
+      energy_budget: None,
+      connection_attempts: 1,
+      command_audit_size: DEFAULT_COMMAND_AUDIT_SIZE,
+      value_policy: OutOfRangeValuePolicy::default(),
+      max_comm_manager_restarts: DEFAULT_MAX_COMM_MANAGER_RESTARTS,
     }
   }
 
@@ -97,12 +202,177 @@ impl ServerDeviceManagerBuilder {
     self
   }
 
+  /// Add every communication manager for hardware attached to this machine (Bluetooth, serial,
+  /// Lovense dongles, XInput, PlayStation controllers) that the library was built with and that
+  /// works on the current platform. Managers that talk to the network, like the websocket device
+  /// server or Lovense Connect, are left out.
+  pub fn local_comm_managers(&mut self) -> &mut Self {
+    #[cfg(all(
+      feature = "btleplug-manager",
+      any(
+        target_os = "windows",
+        target_os = "macos",
+        target_os = "linux",
+        target_os = "ios",
+        target_os = "android"
+      )
+    ))]
+    {
+      use crate::server::device::hardware::communication::btleplug::BtlePlugCommunicationManagerBuilder;
+      self.comm_manager(BtlePlugCommunicationManagerBuilder::default());
+    }
+    #[cfg(all(
+      feature = "serial-manager",
+      any(target_os = "windows", target_os = "macos", target_os = "linux")
+    ))]
+    {
+      use crate::server::device::hardware::communication::serialport::SerialPortCommunicationManagerBuilder;
+      self.comm_manager(SerialPortCommunicationManagerBuilder::default());
+    }
+    #[cfg(all(
+      feature = "lovense-dongle-manager",
+      any(target_os = "windows", target_os = "macos", target_os = "linux")
+    ))]
+    {
+      use crate::server::device::hardware::communication::lovense_dongle::{
+        LovenseHIDDongleCommunicationManagerBuilder,
+        LovenseSerialDongleCommunicationManagerBuilder,
+      };
+      self.comm_manager(LovenseHIDDongleCommunicationManagerBuilder::default());
+      self.comm_manager(LovenseSerialDongleCommunicationManagerBuilder::default());
+    }
+    #[cfg(all(feature = "xinput-manager", target_os = "windows"))]
+    {
+      use crate::server::device::hardware::communication::xinput::XInputDeviceCommunicationManagerBuilder;
+      self.comm_manager(XInputDeviceCommunicationManagerBuilder::default());
+    }
+    #[cfg(all(
+      feature = "playstation-manager",
+      any(target_os = "windows", target_os = "macos", target_os = "linux")
+    ))]
+    {
+      use crate::server::device::hardware::communication::playstation::PlayStationCommunicationManagerBuilder;
+      self.comm_manager(PlayStationCommunicationManagerBuilder::default());
+    }
+    self
+  }
+
+  /// Set how long each communication manager has to bring up scanning after a StartScanning call.
+  /// Managers are started concurrently, and a manager that runs past its timeout is logged and
+  /// skipped so it doesn't hold up scanning status for everything else.
+  pub fn scanning_start_timeout(&mut self, timeout: Duration) -> &mut Self {
+    self.scanning_start_timeout = timeout;
+    self
+  }
+
+  /// Override the scanning start timeout for a single communication manager, identified by the name
+  /// it reports via [HardwareCommunicationManager::name].
+  pub fn manager_scanning_start_timeout(
+    &mut self,
+    manager_name: &str,
+    timeout: Duration,
+  ) -> &mut Self {
+    self
+      .manager_scanning_start_timeouts
+      .insert(manager_name.to_owned(), timeout);
+    self
+  }
+
+  /// Set whether to watch for the host system waking up from sleep (off by default). On resume, all
+  /// device connections are checked, and devices whose connections didn't survive are removed.
+  ///
+  /// Resumes are spotted by the wall clock jumping ahead, so large clock changes (NTP steps, the
+  /// user setting the time) are taken for resumes too. Only turn this on where that's acceptable.
+  pub fn detect_system_resume(&mut self, detect_system_resume: bool) -> &mut Self {
+    self.detect_system_resume = detect_system_resume;
+    self
+  }
+
+  /// Set whether to start a fresh scan after the host system wakes up from sleep, so devices that
+  /// were dropped while asleep can reconnect. Off by default. Only used if system resume detection
+  /// is on.
+  pub fn restart_scanning_on_resume(&mut self, restart_scanning_on_resume: bool) -> &mut Self {
+    self.restart_scanning_on_resume = restart_scanning_on_resume;
+    self
+  }
+
+  /// Set whether a device that drops and reconnects should have its last scalar and rotation values
+  /// sent again once it's back. Off by default, in which case the device comes back idle, and
+  /// clients can tell its state was reset by the DeviceRemoved/DeviceAdded pair sent around the
+  /// reconnect. Saved values are dropped on StopAllDevices, so devices that reconnect after a stop
+  /// stay stopped.
+  pub fn replay_state_on_reconnect(&mut self, replay_state_on_reconnect: bool) -> &mut Self {
+    self.replay_state_on_reconnect = replay_state_on_reconnect;
+    self
+  }
+
+  /// Set how often [ScanningProgress] updates are sent while scanning. Defaults to once a second.
+  pub fn scanning_progress_interval(&mut self, interval: Duration) -> &mut Self {
+    self.scanning_progress_interval = interval;
+    self
+  }
+
+  /// Turn on intensity metering, sending an [IntensityMeterReading] for each device whose actuator
+  /// levels change, no more often than once per `interval`. Off by default.
+  pub fn intensity_metering_interval(&mut self, interval: Duration) -> &mut Self {
+    self.intensity_metering_interval = Some(interval);
+    self
+  }
+
+  /// Limit how long each device can run at high intensity, throttling devices that go over as set
+  /// out in `policy`. Throttling changes are sent out on
+  /// [ServerDeviceManager::energy_budget_event_stream]. Off by default.
+  pub fn energy_budget(&mut self, policy: EnergyBudgetPolicy) -> &mut Self {
+    self.energy_budget = Some(policy);
+    self
+  }
+
+  /// Set how many times to try connecting to a device before giving up on it. Defaults to 1, so a
+  /// failed connection is only retried if the device is found again by a later scan. Only the
+  /// connection itself is retried, as once protocol identification has talked to the device it may
+  /// be in a state a second attempt can't recover from. Values under 1 are treated as 1.
+  pub fn connection_attempts(&mut self, attempts: u32) -> &mut Self {
+    self.connection_attempts = attempts.max(1);
+    self
+  }
+
+  /// Set how many recent events each device keeps in its command audit, see
+  /// [ServerDeviceManager::device_command_audit]. Defaults to 50. 0 turns auditing off.
+  pub fn command_audit_size(&mut self, size: usize) -> &mut Self {
+    self.command_audit_size = size;
+    self
+  }
+
+  /// Set what devices do with actuator command values outside of 0.0 to 1.0. Defaults to
+  /// [OutOfRangeValuePolicy::Reject], failing the command. With [OutOfRangeValuePolicy::Clamp],
+  /// values are moved to the nearest end of the range instead, for apps that expect levels to
+  /// saturate.
+  pub fn out_of_range_value_policy(&mut self, policy: OutOfRangeValuePolicy) -> &mut Self {
+    self.value_policy = policy;
+    self
+  }
+
+  /// Set how many times a communication manager that fails (usually from a panic in one of its
+  /// tasks) is rebuilt before it's disabled for the rest of the session. Defaults to 3. A disabled
+  /// manager reports [HardwareCommunicationManagerStatus::Failed].
+  pub fn max_comm_manager_restarts(&mut self, restarts: u32) -> &mut Self {
+    self.max_comm_manager_restarts = restarts;
+    self
+  }
+
+  /// Build the device manager. Communication manager builders are handed over to the device
+  /// manager, which needs them to rebuild failed managers, so this can only be called once.
   pub fn finish(&mut self) -> Result<ServerDeviceManager, ButtplugServerError> {
     let (device_command_sender, device_command_receiver) = mpsc::channel(256);
     let (device_event_sender, device_event_receiver) = mpsc::channel(256);
+    // Root of the cancellation tree for everything the device manager (and the server that owns
+    // it) spawns. Cancelled on shutdown or drop.
+    let loop_cancellation_token = CancellationToken::new();
     let mut comm_managers: Vec<Box<dyn HardwareCommunicationManager>> = Vec::new();
-    for builder in &mut self.comm_managers {
-      let comm_mgr = builder.finish(device_event_sender.clone());
+    let mut comm_manager_slots = Vec::new();
+    for mut builder in self.comm_managers.drain(..) {
+      let token = loop_cancellation_token.child_token();
+      let comm_mgr = build_comm_manager(builder.as_mut(), device_event_sender.clone(), &token);
 
       if comm_managers
         .iter()
@@ -116,6 +386,7 @@ impl ServerDeviceManagerBuilder {
       }
 
       comm_managers.push(comm_mgr);
+      comm_manager_slots.push(CommManagerSlot::new(comm_manager_slots.len(), builder, token));
     }
 
     let mut colliding_dcms = vec![];
@@ -123,7 +394,8 @@ impl ServerDeviceManagerBuilder {
       info!("{}: {}", mgr.name(), mgr.can_scan());
       // Hack: Lovense and Bluetooth dongles will fight with each other over devices, possibly
       // interrupting each other connecting and causing very weird issues for users. Print a
-      // warning message to logs if more than one is active and available to scan.
+      // warning message to logs if both kinds are active and available to scan. Serial and HID
+      // Lovense dongles each run their own state machines, so those are fine together.
       if [
         "BtlePlugCommunicationManager",
         "LovenseSerialDongleCommunicationManager",
@@ -136,21 +408,88 @@ impl ServerDeviceManagerBuilder {
         colliding_dcms.push(mgr.name().to_owned());
       }
     }
-    if colliding_dcms.len() > 1 {
+    if colliding_dcms.len() > 1
+      && colliding_dcms
+        .iter()
+        .any(|name| name == "BtlePlugCommunicationManager")
+    {
       warn!("The following device connection methods may collide: {}. This may mean you have lovense dongles and bluetooth dongles connected at the same time. Please disconnect the lovense dongles or turn off the Lovense HID/Serial Dongle support in Intiface/Buttplug. Lovense devices will work with the Bluetooth dongle.", colliding_dcms.join(", "));
     }
 
+    // Each entry was already logged when the configuration manager was built, repeat the count here
+    // so it shows up next to the rest of the server bringup output.
+    let invalid_definitions = self.device_configuration_manager.invalid_definitions();
+    if !invalid_definitions.is_empty() {
+      warn!(
+        "{} device configuration entries are invalid and were skipped, see DeviceConfigurationManager::invalid_definitions.",
+        invalid_definitions.len()
+      );
+    }
+
     let devices = Arc::new(DashMap::new());
-    let loop_cancellation_token = CancellationToken::new();
+    let device_list_history = Arc::new(Mutex::new(DeviceListHistory::default()));
+
+    let event_bus = DeviceManagerEventBus::default();
+
+    let scanning_start_timeouts = comm_managers
+      .iter()
+      .map(|mgr| {
+        self
+          .manager_scanning_start_timeouts
+          .get(mgr.name())
+          .copied()
+          .unwrap_or(self.scanning_start_timeout)
+      })
+      .collect();
+
+    let comm_manager_status: Arc<DashMap<_, _>> = Arc::new(
+      comm_managers
+        .iter()
+        .map(|mgr| (mgr.name(), mgr.status()))
+        .collect(),
+    );
 
-    let output_sender = broadcast::channel(255).0;
+    let comm_manager_firmware = Arc::new(DashMap::new());
 
+    let system_resume_receiver = if self.detect_system_resume {
+      system_resume_events(
+        DEFAULT_RESUME_CHECK_INTERVAL,
+        DEFAULT_RESUME_THRESHOLD,
+        loop_cancellation_token.child_token(),
+      )
+    } else {
+      // Receiver with no sender, so the event loop never sees a resume.
+      mpsc::channel(1).1
+    };
+
+    let reconnect_state = self
+      .replay_state_on_reconnect
+      .then(|| Arc::new(DashMap::new()));
+
+    let command_audits = Arc::new(DashMap::new());
     let mut event_loop = ServerDeviceManagerEventLoop::new(
       comm_managers,
+      scanning_start_timeouts,
+      comm_manager_slots,
+      self.max_comm_manager_restarts,
+      system_resume_receiver,
+      self.restart_scanning_on_resume,
+      reconnect_state.clone(),
+      self.scanning_progress_interval,
+      self.intensity_metering_interval,
+      self.energy_budget,
+      self.connection_attempts,
+      self.command_audit_size,
+      self.value_policy,
+      command_audits.clone(),
+      comm_manager_status.clone(),
+      comm_manager_firmware.clone(),
+      event_bus.clone(),
       self.device_configuration_manager.clone(),
       devices.clone(),
+      device_list_history.clone(),
       loop_cancellation_token.child_token(),
-      output_sender.clone(),
+      device_event_sender,
       device_event_receiver,
       device_command_receiver,
     );
@@ -160,10 +499,19 @@ impl ServerDeviceManagerBuilder {
     Ok(ServerDeviceManager {
       device_configuration_manager: self.device_configuration_manager.clone(),
       devices,
+      device_list_history,
       device_command_sender,
       loop_cancellation_token,
       running: Arc::new(AtomicBool::new(true)),
-      output_sender,
+      event_bus,
+      comm_manager_status,
+      comm_manager_firmware,
+      reconnect_state,
+      command_audits,
+      device_links: Arc::new(DashMap::new()),
+      next_device_link_id: Arc::new(AtomicU32::new(0)),
+      pattern_sessions: Arc::new(DashMap::new()),
+      next_pattern_session_id: Arc::new(AtomicU32::new(0)),
     })
   }
 }
@@ -174,20 +522,98 @@ pub struct ServerDeviceManager {
   device_configuration_manager: Arc<DeviceConfigurationManager>,
   #[getset(get = "pub(crate)")]
   devices: Arc<DashMap<u32, Arc<ServerDevice>>>,
+  /// Record of device additions and removals, for incremental device lists.
+  device_list_history: Arc<Mutex<DeviceListHistory>>,
   device_command_sender: mpsc::Sender<DeviceManagerCommand>,
   loop_cancellation_token: CancellationToken,
   running: Arc<AtomicBool>,
-  output_sender: broadcast::Sender<ButtplugServerMessageV4>,
+  /// Everything the device manager reports goes out through here, see [DeviceManagerEventBus].
+  #[getset(get = "pub")]
+  event_bus: DeviceManagerEventBus,
+  comm_manager_status: Arc<DashMap<&'static str, HardwareCommunicationManagerStatus>>,
+  comm_manager_firmware: Arc<DashMap<&'static str, Vec<HardwareFirmwareVersion>>>,
+  /// Actuator commands to replay on devices that reconnect, keyed by device index. Only set if
+  /// state replay is turned on.
+  reconnect_state: Option<Arc<DashMap<u32, Vec<ButtplugDeviceCommandMessageUnion>>>>,
+  /// Command audits of every device seen this session, keyed by device index.
+  command_audits: Arc<DashMap<u32, Arc<CommandAudit>>>,
+  /// Running device links, keyed by link id, with the token that stops each one.
+  device_links: Arc<DashMap<u32, (DeviceLink, CancellationToken)>>,
+  next_device_link_id: Arc<AtomicU32>,
+  /// Running pattern sessions, keyed by session id, with the token that stops each one.
+  pattern_sessions: Arc<DashMap<u32, (PatternSession, CancellationToken)>>,
+  next_pattern_session_id: Arc<AtomicU32>,
 }
 
 impl ServerDeviceManager {
   pub fn event_stream(&self) -> impl Stream<Item = ButtplugServerMessageV4> {
     // Unlike the client API, we can expect anyone using the server to pin this
     // themselves.
-    convert_broadcast_receiver_to_stream(self.output_sender.subscribe())
+    self.event_bus.subscribe_map(|event| match event {
+      DeviceManagerEvent::ServerMessage(message) => Some(message),
+      DeviceManagerEvent::PatternSession(event) => Some(event.into()),
+      DeviceManagerEvent::ScanningProgress(progress) => Some(
+        ButtplugServerMessageV4::ScanningProgress(progress.into()),
+      ),
+      _ => None,
+    })
+  }
+
+  /// Stream of [IntensityMeterReading]s, if metering was turned on with
+  /// [ServerDeviceManagerBuilder::intensity_metering_interval]. Otherwise this never yields.
+  pub fn intensity_meter_stream(&self) -> impl Stream<Item = IntensityMeterReading> {
+    self.event_bus.subscribe_map(|event| match event {
+      DeviceManagerEvent::IntensityMeter(reading) => Some(reading),
+      _ => None,
+    })
+  }
+
+  /// Stream of [EnergyBudgetEvent]s, if a policy was set with
+  /// [ServerDeviceManagerBuilder::energy_budget]. Otherwise this never yields.
+  pub fn energy_budget_event_stream(&self) -> impl Stream<Item = EnergyBudgetEvent> {
+    self.event_bus.subscribe_map(|event| match event {
+      DeviceManagerEvent::EnergyBudget(event) => Some(event),
+      _ => None,
+    })
+  }
+
+  /// Stream of [ScanningProgress] updates, sent periodically for as long as scanning is running.
+  pub fn scanning_progress_stream(&self) -> impl Stream<Item = ScanningProgress> {
+    self.event_bus.subscribe_map(|event| match event {
+      DeviceManagerEvent::ScanningProgress(progress) => Some(progress),
+      _ => None,
+    })
+  }
+
+  /// Current hardware availability for each communication manager, keyed by manager name. Lets
+  /// applications tell users why nothing is showing up, e.g. when Bluetooth has been switched off.
+  pub fn comm_manager_status(&self) -> HashMap<&'static str, HardwareCommunicationManagerStatus> {
+    self
+      .comm_manager_status
+      .iter()
+      .map(|entry| (*entry.key(), entry.value().clone()))
+      .collect()
+  }
+
+  /// Firmware versions reported by hardware each communication manager uses (dongles, etc...),
+  /// keyed by manager name. Lets users tell whether their hardware needs a firmware update when
+  /// devices fail to pair. Managers only show up here once their hardware has reported a version.
+  pub fn comm_manager_firmware_versions(
+    &self,
+  ) -> HashMap<&'static str, Vec<HardwareFirmwareVersion>> {
+    self
+      .comm_manager_firmware
+      .iter()
+      .map(|entry| (*entry.key(), entry.value().clone()))
+      .collect()
   }
 
-  fn start_scanning(&self) -> ButtplugServerResultFuture {
+  /// Start scanning for devices on all communication managers. Found devices are announced as
+  /// [DeviceAdded](crate::core::message::DeviceAddedV4) messages on [Self::event_stream].
+  pub fn start_scanning(&self) -> ButtplugServerResultFuture {
+    if !self.running.load(Ordering::SeqCst) {
+      return future::ready(Err(ButtplugUnknownError::DeviceManagerNotRunning.into())).boxed();
+    }
     let command_sender = self.device_command_sender.clone();
     async move {
       if command_sender
@@ -202,7 +628,8 @@ impl ServerDeviceManager {
     .boxed()
   }
 
-  fn stop_scanning(&self) -> ButtplugServerResultFuture {
+  /// Stop scanning for devices on all communication managers.
+  pub fn stop_scanning(&self) -> ButtplugServerResultFuture {
     let command_sender = self.device_command_sender.clone();
     async move {
       if command_sender
@@ -218,6 +645,13 @@ impl ServerDeviceManager {
   }
 
   pub(crate) fn stop_all_devices(&self) -> ButtplugServerResultFuture {
+    // Links would start their targets right back up on the next sensor reading.
+    self.remove_all_device_links();
+    self.cancel_all_pattern_sessions();
+    // Anything currently disconnected should also come back stopped.
+    if let Some(reconnect_state) = &self.reconnect_state {
+      reconnect_state.clear();
+    }
     let device_map = self.devices.clone();
     // TODO This could use some error reporting.
     async move {
@@ -238,6 +672,32 @@ impl ServerDeviceManager {
     &self,
     device_msg: ButtplugDeviceCommandMessageUnion,
   ) -> ButtplugServerResultFuture {
+    let device_index = device_msg.device_index();
+    match device_msg {
+      // Stopping a device always works, and takes it back from any session using it.
+      ButtplugDeviceCommandMessageUnion::StopDeviceCmd(_) => {
+        self.pattern_sessions.retain(|_, (session, token)| {
+          if session.uses_device(device_index) {
+            token.cancel();
+            false
+          } else {
+            true
+          }
+        });
+      }
+      ButtplugDeviceCommandMessageUnion::ScalarCmd(_)
+      | ButtplugDeviceCommandMessageUnion::LinearCmd(_)
+      | ButtplugDeviceCommandMessageUnion::RotateCmd(_)
+      | ButtplugDeviceCommandMessageUnion::AxisCmd(_)
+        if self
+          .pattern_sessions
+          .iter()
+          .any(|entry| entry.value().0.uses_device(device_index)) =>
+      {
+        return ButtplugDeviceError::DeviceInPatternSession(device_index).into();
+      }
+      _ => {}
+    }
     match self.devices.get(&device_msg.device_index()) {
       Some(device) => {
         let fut = device.parse_message(device_msg);
@@ -248,30 +708,227 @@ impl ServerDeviceManager {
     }
   }
 
+  /// Run a command on a device, going through the same checks as device messages from clients.
+  ///
+  /// Meant for applications embedding a server without a client attached, which can skip building
+  /// full client messages. The command's `id` doesn't matter, and is not copied to the reply.
+  pub fn send_device_command(
+    &self,
+    msg: ButtplugDeviceCommandMessageUnion,
+  ) -> ButtplugServerResultFuture {
+    if !self.running.load(Ordering::SeqCst) {
+      return future::ready(Err(ButtplugUnknownError::DeviceManagerNotRunning.into())).boxed();
+    }
+    self.parse_device_message(msg)
+  }
+
+  /// Information for all currently connected devices, ordered by device index, in the same form
+  /// clients get it in device lists.
+  pub fn device_list(&self) -> Vec<DeviceMessageInfoV4> {
+    let mut devices: Vec<DeviceMessageInfoV4> = self
+      .devices
+      .iter()
+      .map(|device| self.device_message_info(*device.key(), device.value()))
+      .collect();
+    devices.sort_by_key(|info| info.device_index());
+    devices
+  }
+
+  /// Recent commands and hardware results for the device at `device_index`, oldest first. Devices
+  /// that have disconnected keep their audit, so this works for anything seen this session, and
+  /// returns None for indexes that have never had a device.
+  pub fn device_command_audit(&self, device_index: u32) -> Option<Vec<CommandAuditEntry>> {
+    self
+      .command_audits
+      .get(&device_index)
+      .map(|audit| audit.entries())
+  }
+
+  fn connected_device(&self, device_index: u32) -> Result<Arc<ServerDevice>, ButtplugDeviceError> {
+    self
+      .devices
+      .get(&device_index)
+      .map(|device| device.value().clone())
+      .ok_or(ButtplugDeviceError::DeviceNotAvailable(device_index))
+  }
+
+  /// Start calibrating the sensor at `feature_index` on the device at `device_index`. While the
+  /// session runs, the sensor's readings go out raw and its range is recorded, so the user should
+  /// take the sensor through its whole range (squeezing as lightly and as hard as they will in use)
+  /// while it's subscribed to or read.
+  pub fn start_sensor_calibration(
+    &self,
+    device_index: u32,
+    feature_index: u32,
+  ) -> Result<(), ButtplugDeviceError> {
+    self
+      .connected_device(device_index)?
+      .start_sensor_calibration(feature_index)
+  }
+
+  /// Finish calibrating a sensor. From then on its readings are scaled so the recorded range
+  /// covers the range the sensor declares. The calibration is stored in the device's user
+  /// configuration, so it's kept for future connections once the user configuration is saved.
+  pub fn finish_sensor_calibration(
+    &self,
+    device_index: u32,
+    feature_index: u32,
+  ) -> Result<SensorCalibration, ButtplugDeviceError> {
+    let device = self.connected_device(device_index)?;
+    let calibration = device.finish_sensor_calibration(feature_index)?;
+    self
+      .device_configuration_manager
+      .set_user_sensor_calibration(
+        device.identifier(),
+        feature_index,
+        Some(calibration.clone()),
+      );
+    Ok(calibration)
+  }
+
+  /// Remove a sensor's calibration, both from the device and its user configuration.
+  pub fn clear_sensor_calibration(
+    &self,
+    device_index: u32,
+    feature_index: u32,
+  ) -> Result<(), ButtplugDeviceError> {
+    let device = self.connected_device(device_index)?;
+    device.clear_sensor_calibration(feature_index)?;
+    self
+      .device_configuration_manager
+      .set_user_sensor_calibration(device.identifier(), feature_index, None);
+    Ok(())
+  }
+
+  /// Number of times writes to the device at `device_index` have been retried after transient
+  /// failures since it connected, or None if no device has that index.
+  pub fn device_write_retry_count(&self, device_index: u32) -> Option<u64> {
+    self
+      .devices
+      .get(&device_index)
+      .map(|device| device.write_retry_count())
+  }
+
+  /// Turn dry run mode on or off for the device at `device_index`. Commands to a device in dry run
+  /// mode are validated and turned into hardware commands as usual, which are then logged instead of
+  /// being written, so apps can be tested against real devices without running them. Returns false
+  /// if no device has that index.
+  pub fn set_device_dry_run(&self, device_index: u32, dry_run: bool) -> bool {
+    if let Some(device) = self.devices.get(&device_index) {
+      info!(
+        "Dry run mode {} for device {}",
+        if dry_run { "enabled" } else { "disabled" },
+        device_index
+      );
+      device.set_dry_run(dry_run);
+      true
+    } else {
+      false
+    }
+  }
+
+  /// Whether the device at `device_index` is in dry run mode, or None if no device has that index.
+  pub fn device_dry_run(&self, device_index: u32) -> Option<bool> {
+    self
+      .devices
+      .get(&device_index)
+      .map(|device| device.dry_run())
+  }
+
+  fn device_message_info(&self, index: u32, device: &ServerDevice) -> DeviceMessageInfoV4 {
+    let mut info = DeviceMessageInfoV4::new(
+      index,
+      &device.name(),
+      device.definition().user_config().display_name(),
+      &device.timing_policy().min_command_interval_ms(),
+      device.definition().features().clone(),
+    );
+    info.set_device_protocol(Some(device.identifier().protocol().clone()));
+    info.set_device_transport(Some(device.transport()));
+    info.set_device_address(Some(device.identifier().address().clone()));
+    info.set_device_firmware_version(device.firmware_version());
+    info
+  }
+
   fn parse_device_manager_message(
     &self,
     manager_msg: ButtplugDeviceManagerMessageUnion,
   ) -> ButtplugServerResultFuture {
     match manager_msg {
       ButtplugDeviceManagerMessageUnion::RequestDeviceList(msg) => {
-        let devices = self
-          .devices
-          .iter()
-          .map(|device| {
-            let dev = device.value();
-            DeviceMessageInfoV4::new(
-              *device.key(),
-              &dev.name(),
-              &dev.definition().user_config().display_name(),
-              &None,
-              dev.definition().features().clone(),
-            )
-          })
-          .collect();
-        let mut device_list = DeviceListV4::new(devices);
+        // The event loop updates the device map before the history, so as long as we grab the
+        // generation first, the list we build can only be ahead of it. Clients then see a few
+        // changes twice, which is harmless, instead of missing some.
+        let (generation, delta) = {
+          let history = self
+            .device_list_history
+            .lock()
+            .expect("Device list history lock should never be poisoned.");
+          let delta = msg
+            .since_generation()
+            .and_then(|since| history.changes_since(since));
+          (history.generation(), delta)
+        };
+        let mut device_list = match delta {
+          Some(delta) => DeviceListV4::new_incremental(
+            self
+              .devices
+              .iter()
+              .filter(|device| delta.added.contains(device.key()))
+              .map(|device| self.device_message_info(*device.key(), device.value()))
+              .collect(),
+            delta.removed,
+            generation,
+          ),
+          None => DeviceListV4::new(
+            self
+              .devices
+              .iter()
+              .map(|device| self.device_message_info(*device.key(), device.value()))
+              .collect(),
+            generation,
+          ),
+        };
         device_list.set_id(msg.id());
         future::ready(Ok(device_list.into())).boxed()
       }
+      ButtplugDeviceManagerMessageUnion::RequestDeviceInfo(msg) => {
+        match self.devices.get(&msg.device_index()) {
+          Some(device) => {
+            let mut device_info =
+              DeviceInfoV4::from(&self.device_message_info(*device.key(), device.value()));
+            device_info.set_id(msg.id());
+            future::ready(Ok(device_info.into())).boxed()
+          }
+          None => ButtplugDeviceError::DeviceNotAvailable(msg.device_index()).into(),
+        }
+      }
+      ButtplugDeviceManagerMessageUnion::IdentifyDevice(msg) => {
+        let id = msg.id();
+        let fut = self.identify_device(msg.device_index());
+        async move {
+          fut.await?;
+          Ok(message::OkV0::new(id).into())
+        }
+        .boxed()
+      }
+      ButtplugDeviceManagerMessageUnion::StartPatternSession(msg) => {
+        let result = self
+          .start_pattern_session(PatternSession::from(&msg))
+          .map(|session_id| {
+            let mut started = PatternSessionStartedV4::new(session_id);
+            started.set_id(msg.id());
+            started.into()
+          });
+        future::ready(result).boxed()
+      }
+      ButtplugDeviceManagerMessageUnion::StopPatternSession(msg) => {
+        if self.cancel_pattern_session(msg.session_id()) {
+          future::ready(Ok(message::OkV0::new(msg.id()).into())).boxed()
+        } else {
+          ButtplugDeviceError::PatternSessionNotFound(msg.session_id()).into()
+        }
+      }
       ButtplugDeviceManagerMessageUnion::StopAllDevices(_) => self.stop_all_devices(),
       ButtplugDeviceManagerMessageUnion::StartScanning(_) => self.start_scanning(),
       ButtplugDeviceManagerMessageUnion::StopScanning(_) => self.stop_scanning(),
@@ -293,15 +950,279 @@ impl ServerDeviceManager {
     }
   }
 
+  /// Stop some features of the device at `index`, leaving the others running. See
+  /// [ServerDevice::stop_features] for details.
+  pub fn stop_device_features(
+    &self,
+    index: u32,
+    feature_indexes: &[u32],
+  ) -> ButtplugServerResultFuture {
+    if !self.running.load(Ordering::SeqCst) {
+      return future::ready(Err(ButtplugUnknownError::DeviceManagerNotRunning.into())).boxed();
+    }
+    match self.devices.get(&index) {
+      Some(device) => device.stop_features(feature_indexes),
+      None => ButtplugDeviceError::DeviceNotAvailable(index).into(),
+    }
+  }
+
+  /// Start a pattern built into the firmware of the device at `index`. See
+  /// [ServerDevice::start_firmware_pattern] for details.
+  pub fn start_device_firmware_pattern(
+    &self,
+    index: u32,
+    pattern: u32,
+  ) -> ButtplugServerResultFuture {
+    if !self.running.load(Ordering::SeqCst) {
+      return future::ready(Err(ButtplugUnknownError::DeviceManagerNotRunning.into())).boxed();
+    }
+    match self.devices.get(&index) {
+      Some(device) => device.start_firmware_pattern(pattern),
+      None => ButtplugDeviceError::DeviceNotAvailable(index).into(),
+    }
+  }
+
+  /// Stop the firmware pattern running on the device at `index`.
+  pub fn stop_device_firmware_pattern(&self, index: u32) -> ButtplugServerResultFuture {
+    if !self.running.load(Ordering::SeqCst) {
+      return future::ready(Err(ButtplugUnknownError::DeviceManagerNotRunning.into())).boxed();
+    }
+    match self.devices.get(&index) {
+      Some(device) => device.stop_firmware_pattern(),
+      None => ButtplugDeviceError::DeviceNotAvailable(index).into(),
+    }
+  }
+
+  /// Briefly actuate the device at `index` so the user can tell which physical device it is. See
+  /// [ServerDevice::identify] for details.
+  pub fn identify_device(&self, index: u32) -> ButtplugResultFuture {
+    if !self.running.load(Ordering::SeqCst) {
+      return future::ready(Err(ButtplugUnknownError::DeviceManagerNotRunning.into())).boxed();
+    }
+    let device = self
+      .devices
+      .get(&index)
+      .map(|device| device.value().clone());
+    async move {
+      let device = device.ok_or(ButtplugDeviceError::DeviceNotAvailable(index))?;
+      device.identify().await
+    }
+    .boxed()
+  }
+
+  /// Ask the device at `index` about itself, for support tooling and bug reports. See
+  /// [ServerDevice::diagnostics] for details.
+  pub fn device_diagnostics(&self, index: u32) -> ButtplugResultFuture<ProtocolDiagnostics> {
+    if !self.running.load(Ordering::SeqCst) {
+      return future::ready(Err(ButtplugUnknownError::DeviceManagerNotRunning.into())).boxed();
+    }
+    match self.devices.get(&index) {
+      Some(device) => device.diagnostics(),
+      None => future::ready(Err(ButtplugDeviceError::DeviceNotAvailable(index).into())).boxed(),
+    }
+  }
+
+  /// Forget the device at `index`: disconnect it if it's connected, remove everything stored for it
+  /// in the user configuration (see [DeviceConfigurationManager::forget_device]), and keep it from
+  /// reconnecting for the rest of the session. Devices that aren't connected but have an index in
+  /// the user configuration can be forgotten too.
+  ///
+  /// Device links and pattern sessions using the device are stopped, as its index may go to a
+  /// different device later.
+  pub fn forget_device(&self, index: u32) -> ButtplugResultFuture {
+    if !self.running.load(Ordering::SeqCst) {
+      return future::ready(Err(ButtplugUnknownError::DeviceManagerNotRunning.into())).boxed();
+    }
+    let device = self
+      .devices
+      .get(&index)
+      .map(|device| device.value().clone());
+    let identifier = match &device {
+      Some(device) => Some(device.identifier().clone()),
+      None => self
+        .device_configuration_manager
+        .user_device_definitions()
+        .iter()
+        .find(|entry| entry.value().user_config().index() == index)
+        .map(|entry| entry.key().clone()),
+    };
+    let Some(identifier) = identifier else {
+      return ButtplugDeviceError::DeviceNotAvailable(index).into();
+    };
+    // Forget before disconnecting, otherwise a running scan could pick the device right back up.
+    self.device_configuration_manager.forget_device(&identifier);
+    self.device_links.retain(|_, (link, token)| {
+      if link.source_device_index() == index || link.target_device_index() == index {
+        token.cancel();
+        false
+      } else {
+        true
+      }
+    });
+    self.pattern_sessions.retain(|_, (session, token)| {
+      if session.uses_device(index) {
+        token.cancel();
+        false
+      } else {
+        true
+      }
+    });
+    if let Some(reconnect_state) = &self.reconnect_state {
+      reconnect_state.remove(&index);
+    }
+    self.command_audits.remove(&index);
+    async move {
+      if let Some(device) = device {
+        device.disconnect().await?;
+      }
+      Ok(())
+    }
+    .boxed()
+  }
+
+  /// Start driving an actuator from a sensor, as described by `link`. Resolves to an id that can be
+  /// used to remove the link. The source sensor is subscribed to as part of setting up the link.
+  ///
+  /// Links are removed, and their targets stopped, on StopAllDevices.
+  pub fn add_device_link(&self, link: DeviceLink) -> ButtplugResultFuture<u32> {
+    if !self.running.load(Ordering::SeqCst) {
+      return future::ready(Err(ButtplugUnknownError::DeviceManagerNotRunning.into())).boxed();
+    }
+    let token = self.loop_cancellation_token.child_token();
+    let start = start_device_link(
+      link.clone(),
+      self.devices.clone(),
+      self.event_bus.receiver(),
+      token.clone(),
+    );
+    let device_links = self.device_links.clone();
+    let link_id = self.next_device_link_id.fetch_add(1, Ordering::SeqCst);
+    async move {
+      start.await?;
+      device_links.insert(link_id, (link, token));
+      Ok(link_id)
+    }
+    .boxed()
+  }
+
+  /// Stop and remove the device link with the given id. Its target actuator is stopped. Returns
+  /// false if there was no such link.
+  pub fn remove_device_link(&self, link_id: u32) -> bool {
+    match self.device_links.remove(&link_id) {
+      Some((_, (_, token))) => {
+        token.cancel();
+        true
+      }
+      None => false,
+    }
+  }
+
+  fn remove_all_device_links(&self) {
+    self.device_links.retain(|_, (_, token)| {
+      token.cancel();
+      false
+    });
+  }
+
+  /// Currently running device links, keyed by link id.
+  pub fn device_links(&self) -> HashMap<u32, DeviceLink> {
+    self
+      .device_links
+      .iter()
+      .map(|entry| (*entry.key(), entry.value().0.clone()))
+      .collect()
+  }
+
+  /// Start running `session` on the server, returning an id that can be used to cancel it. Fails if
+  /// the session is malformed, refers to features that aren't scalar actuators, or uses a device
+  /// that's already in another session.
+  ///
+  /// Sessions are cancelled on StopAllDevices, and by a StopDeviceCmd for any device they use.
+  pub fn start_pattern_session(&self, session: PatternSession) -> Result<u32, ButtplugError> {
+    if !self.running.load(Ordering::SeqCst) {
+      return Err(ButtplugUnknownError::DeviceManagerNotRunning.into());
+    }
+    if let Some(busy) = session.tracks().iter().find(|track| {
+      self
+        .pattern_sessions
+        .iter()
+        .any(|entry| entry.value().0.uses_device(track.device_index()))
+    }) {
+      return Err(ButtplugDeviceError::DeviceInPatternSession(busy.device_index()).into());
+    }
+    let token = self.loop_cancellation_token.child_token();
+    let session_id = self.next_pattern_session_id.fetch_add(1, Ordering::SeqCst);
+    // Register the session before it starts, so it can't end before it's been added.
+    self
+      .pattern_sessions
+      .insert(session_id, (session.clone(), token.clone()));
+    if let Err(err) = start_pattern_session(
+      session_id,
+      session,
+      self.devices.clone(),
+      self.pattern_sessions.clone(),
+      self.event_bus.clone(),
+      token,
+    ) {
+      self.pattern_sessions.remove(&session_id);
+      return Err(err);
+    }
+    Ok(session_id)
+  }
+
+  /// Cancel the pattern session with the given id, stopping the features it was running. Returns
+  /// false if there was no such session.
+  pub fn cancel_pattern_session(&self, session_id: u32) -> bool {
+    match self.pattern_sessions.remove(&session_id) {
+      Some((_, (_, token))) => {
+        token.cancel();
+        true
+      }
+      None => false,
+    }
+  }
+
+  fn cancel_all_pattern_sessions(&self) {
+    self.pattern_sessions.retain(|_, (_, token)| {
+      token.cancel();
+      false
+    });
+  }
+
+  /// Stream of actuator commands accepted by devices, whether they came from clients, device links,
+  /// pattern sessions or anything else. Commands that wouldn't change anything are left out, and
+  /// stopping a device shows up as the commands that zero its actuators.
+  pub fn actuator_command_stream(&self) -> impl Stream<Item = ButtplugDeviceCommandMessageUnion> {
+    self.event_bus.subscribe_map(|event| match event {
+      DeviceManagerEvent::ActuatorCommand(command) => Some(command),
+      _ => None,
+    })
+  }
+
+  /// Stream of progress and end events for pattern sessions.
+  pub fn pattern_session_event_stream(&self) -> impl Stream<Item = PatternSessionEvent> {
+    self.event_bus.subscribe_map(|event| match event {
+      DeviceManagerEvent::PatternSession(event) => Some(event),
+      _ => None,
+    })
+  }
+
+  /// Token for tasks that should stop when the device manager shuts down.
+  pub(crate) fn child_cancellation_token(&self) -> CancellationToken {
+    self.loop_cancellation_token.child_token()
+  }
+
   pub fn device_info(&self, index: u32) -> Option<ServerDeviceInfo> {
     self.devices.get(&index).map(|device| ServerDeviceInfo {
       identifier: device.value().identifier().clone(),
+      name: device.value().name(),
       display_name: device
         .value()
         .definition()
         .user_config()
         .display_name()
         .clone(),
+      firmware_pattern_count: device.value().firmware_pattern_count(),
     })
   }
 
@@ -323,11 +1244,15 @@ impl ServerDeviceManager {
       // cleaning up if we're still scanning.
       let _ = stop_scanning.await;
       let _ = stop_devices.await;
+      let mut result = Ok(message::OkV0::default().into());
       for device in devices.iter() {
-        device.value().disconnect().await?;
+        if let Err(err) = device.value().disconnect().await {
+          result = Err(err);
+        }
       }
+      // Cancel even if a device wouldn't disconnect, so nothing we spawned outlives us.
       token.cancel();
-      Ok(message::OkV0::default().into())
+      result
     }
     .boxed()
   }
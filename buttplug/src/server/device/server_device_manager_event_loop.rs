@@ -6,75 +6,264 @@
 // for full license information.
 
 use crate::{
-  core::message::{ButtplugServerMessageV4, DeviceAddedV4, DeviceRemovedV0, ScanningFinishedV0},
+  core::{
+    errors::ButtplugError,
+    message::{
+      ButtplugDeviceCommandMessageUnion,
+      ButtplugDeviceMessage,
+      DeviceAddedV4,
+      DeviceRemovedV4,
+      ScanningFinishedV0,
+    },
+  },
   server::device::{
+    command_audit::{CommandAudit, CommandAuditEvent},
+    command_validation::OutOfRangeValuePolicy,
     configuration::DeviceConfigurationManager,
-    hardware::communication::{HardwareCommunicationManager, HardwareCommunicationManagerEvent},
+    device_list_history::DeviceListHistory,
+    energy_budget::{start_energy_budget, EnergyBudgetPolicy},
+    event_bus::{DeviceManagerEvent, DeviceManagerEventBus},
+    hardware::communication::{
+      HardwareCommunicationManager,
+      HardwareCommunicationManagerBuilder,
+      HardwareCommunicationManagerEvent,
+      HardwareCommunicationManagerStatus,
+      HardwareFirmwareVersion,
+    },
+    intensity_meter::start_intensity_meter,
     ServerDevice,
     ServerDeviceEvent,
   },
-  util::async_manager,
+  util::{self, async_manager},
 };
 use dashmap::{DashMap, DashSet};
-use futures::{future, FutureExt, StreamExt};
-use std::sync::Arc;
-use tokio::sync::{broadcast, mpsc};
+use futures::{future, select, FutureExt, StreamExt};
+use instant::Instant;
+use std::{
+  collections::{HashMap, HashSet},
+  sync::{Arc, Mutex, MutexGuard},
+  time::Duration,
+};
+use tokio::sync::mpsc;
 use tokio_util::sync::CancellationToken;
 use tracing;
 use tracing_futures::Instrument;
 
-use super::server_device_manager::DeviceManagerCommand;
+use super::server_device_manager::{DeviceManagerCommand, ScanningProgress};
+
+/// How long a device gets to confirm its connection is alive after a system resume before it's
+/// considered dead.
+const RESUME_CONNECTION_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Outcome of a single communication manager's attempt to start scanning.
+#[derive(Debug)]
+enum ScanningBringupResult {
+  Started,
+  Failed(ButtplugError),
+  TimedOut,
+  /// Bringup panicked, which is treated like the manager failing.
+  Panicked(String),
+}
+
+/// What's needed to rebuild a communication manager if it fails.
+pub(super) struct CommManagerSlot {
+  /// Where the manager was added in the device manager builder. Unlike its position in the event
+  /// loop's lists, this stays the same when other managers are disabled.
+  index: usize,
+  builder: Box<dyn HardwareCommunicationManagerBuilder>,
+  /// Cancelled when the manager is replaced or disabled, stopping its tasks.
+  cancellation_token: CancellationToken,
+  restarts: u32,
+}
+
+impl CommManagerSlot {
+  pub(super) fn new(
+    index: usize,
+    builder: Box<dyn HardwareCommunicationManagerBuilder>,
+    cancellation_token: CancellationToken,
+  ) -> Self {
+    Self {
+      index,
+      builder,
+      cancellation_token,
+      restarts: 0,
+    }
+  }
+}
+
+/// Build a communication manager, forwarding its events to the event loop tagged with its name.
+/// Each manager gets its own channel for this, and forwarding stops when `cancellation_token` is
+/// cancelled, so a replaced manager's leftover tasks can't confuse its replacement.
+pub(super) fn build_comm_manager(
+  builder: &mut dyn HardwareCommunicationManagerBuilder,
+  event_sender: mpsc::Sender<(&'static str, HardwareCommunicationManagerEvent)>,
+  cancellation_token: &CancellationToken,
+) -> Box<dyn HardwareCommunicationManager> {
+  let (manager_event_sender, mut manager_event_receiver) = mpsc::channel(256);
+  let comm_mgr = builder.finish(manager_event_sender, cancellation_token.clone());
+  let name = comm_mgr.name();
+  let token = cancellation_token.clone();
+  async_manager::spawn(async move {
+    loop {
+      let event = tokio::select! {
+        event = manager_event_receiver.recv() => event,
+        _ = token.cancelled() => break,
+      };
+      let Some(event) = event else {
+        break;
+      };
+      if event_sender.send((name, event)).await.is_err() {
+        break;
+      }
+    }
+  });
+  comm_mgr
+}
 
 pub(super) struct ServerDeviceManagerEventLoop {
   comm_managers: Vec<Box<dyn HardwareCommunicationManager>>,
+  /// Scanning start timeouts, one per entry in comm_managers.
+  scanning_start_timeouts: Vec<Duration>,
+  /// Builders and restart counts, one per entry in comm_managers.
+  comm_manager_slots: Vec<CommManagerSlot>,
+  max_comm_manager_restarts: u32,
   device_config_manager: Arc<DeviceConfigurationManager>,
   device_command_receiver: mpsc::Receiver<DeviceManagerCommand>,
   /// Maps device index (exposed to the outside world) to actual device objects held by the server.
   device_map: Arc<DashMap<u32, Arc<ServerDevice>>>,
-  /// Broadcaster that relays device events in the form of Buttplug Messages to
-  /// whoever owns the Buttplug Server.
-  server_sender: broadcast::Sender<ButtplugServerMessageV4>,
+  /// Record of device_map additions and removals, kept in step with device_map.
+  device_list_history: Arc<Mutex<DeviceListHistory>>,
+  /// Bus that relays device events, in the form of Buttplug Messages among others, to whoever owns
+  /// the Buttplug Server.
+  event_bus: DeviceManagerEventBus,
   /// As the device manager owns the Device Communication Managers, it will have
-  /// a receiver that the comm managers all send thru.
-  device_comm_receiver: mpsc::Receiver<HardwareCommunicationManagerEvent>,
+  /// a receiver that the comm managers all send thru, tagged with the sending manager's name.
+  device_comm_receiver: mpsc::Receiver<(&'static str, HardwareCommunicationManagerEvent)>,
+  /// Sender side of device_comm_receiver, for hooking up rebuilt comm managers.
+  device_comm_sender: mpsc::Sender<(&'static str, HardwareCommunicationManagerEvent)>,
   /// Sender for device events, passed to new devices when they are created.
   device_event_sender: mpsc::Sender<ServerDeviceEvent>,
   /// Receiver for device events, which the event loops to handle events.
   device_event_receiver: mpsc::Receiver<ServerDeviceEvent>,
-  /// True if StartScanning has been called but no ScanningFinished has been
-  /// emitted yet.
-  scanning_bringup_in_progress: bool,
+  /// Comm managers (by [CommManagerSlot] index) that have been told to start scanning but haven't
+  /// reported back (or timed out) yet. While this is non-empty, scanning bringup is still in
+  /// progress.
+  scanning_bringup_pending: HashSet<usize>,
+  /// Comm managers report scanning bringup results through this channel, tagged with their slot
+  /// index and name, so a slow manager doesn't block the event loop (and therefore device discovery
+  /// from every other manager).
+  scanning_bringup_sender: mpsc::Sender<(usize, &'static str, ScanningBringupResult)>,
+  scanning_bringup_receiver: mpsc::Receiver<(usize, &'static str, ScanningBringupResult)>,
   /// Denote whether scanning has been started since we last sent a ScanningFinished message.
   scanning_started: bool,
+  /// When the current scan started, for progress reporting.
+  scanning_start_time: Instant,
+  /// Addresses of devices found by each comm manager during the current scan.
+  scanning_devices_found: HashMap<&'static str, HashSet<String>>,
+  scanning_progress_interval: Duration,
+  /// If set, how often each device's actuator levels can be reported.
+  intensity_metering_interval: Option<Duration>,
+  /// If set, limits on how long devices can run at high intensity.
+  energy_budget: Option<EnergyBudgetPolicy>,
+  /// Receives a tick every scanning_progress_interval while a scan is running.
+  scanning_progress_tick_sender: mpsc::Sender<()>,
+  scanning_progress_tick_receiver: mpsc::Receiver<()>,
+  /// Stops the progress ticker for the current scan.
+  scanning_progress_token: Option<CancellationToken>,
+  /// Number of times to try connecting to a newly found device.
+  connection_attempts: u32,
+  /// Number of entries each device keeps in its command audit.
+  command_audit_size: usize,
+  /// What devices do with actuator command values outside of 0.0 to 1.0.
+  value_policy: OutOfRangeValuePolicy,
+  /// Command audits of every device seen this session, keyed by device index. Kept after devices
+  /// disconnect, and shared with the device manager frontend.
+  command_audits: Arc<DashMap<u32, Arc<CommandAudit>>>,
   /// Devices currently trying to connect.
   connecting_devices: Arc<DashSet<String>>,
+  /// Receives approximate sleep durations whenever the host system wakes up.
+  system_resume_receiver: mpsc::Receiver<Duration>,
+  /// If true, start scanning again after a system resume.
+  restart_scanning_on_resume: bool,
+  /// If set, actuator commands saved when a device disconnects, keyed by device index, to be
+  /// replayed when the device reconnects.
+  reconnect_state: Option<Arc<DashMap<u32, Vec<ButtplugDeviceCommandMessageUnion>>>>,
+  /// Hardware availability for each comm manager, shared with the device manager frontend.
+  comm_manager_status: Arc<DashMap<&'static str, HardwareCommunicationManagerStatus>>,
+  /// Firmware versions reported by each comm manager's hardware, shared with the device manager
+  /// frontend.
+  comm_manager_firmware: Arc<DashMap<&'static str, Vec<HardwareFirmwareVersion>>>,
   /// Cancellation token for the event loop
   loop_cancellation_token: CancellationToken,
 }
 
 impl ServerDeviceManagerEventLoop {
+  #[allow(clippy::too_many_arguments)]
   pub fn new(
     comm_managers: Vec<Box<dyn HardwareCommunicationManager>>,
+    scanning_start_timeouts: Vec<Duration>,
+    comm_manager_slots: Vec<CommManagerSlot>,
+    max_comm_manager_restarts: u32,
+    system_resume_receiver: mpsc::Receiver<Duration>,
+    restart_scanning_on_resume: bool,
+    reconnect_state: Option<Arc<DashMap<u32, Vec<ButtplugDeviceCommandMessageUnion>>>>,
+    scanning_progress_interval: Duration,
+    intensity_metering_interval: Option<Duration>,
+    energy_budget: Option<EnergyBudgetPolicy>,
+    connection_attempts: u32,
+    command_audit_size: usize,
+    value_policy: OutOfRangeValuePolicy,
+    command_audits: Arc<DashMap<u32, Arc<CommandAudit>>>,
+    comm_manager_status: Arc<DashMap<&'static str, HardwareCommunicationManagerStatus>>,
+    comm_manager_firmware: Arc<DashMap<&'static str, Vec<HardwareFirmwareVersion>>>,
+    event_bus: DeviceManagerEventBus,
     device_config_manager: Arc<DeviceConfigurationManager>,
     device_map: Arc<DashMap<u32, Arc<ServerDevice>>>,
+    device_list_history: Arc<Mutex<DeviceListHistory>>,
     loop_cancellation_token: CancellationToken,
-    server_sender: broadcast::Sender<ButtplugServerMessageV4>,
-    device_comm_receiver: mpsc::Receiver<HardwareCommunicationManagerEvent>,
+    device_comm_sender: mpsc::Sender<(&'static str, HardwareCommunicationManagerEvent)>,
+    device_comm_receiver: mpsc::Receiver<(&'static str, HardwareCommunicationManagerEvent)>,
     device_command_receiver: mpsc::Receiver<DeviceManagerCommand>,
   ) -> Self {
     let (device_event_sender, device_event_receiver) = mpsc::channel(256);
+    let (scanning_bringup_sender, scanning_bringup_receiver) = mpsc::channel(256);
+    let (scanning_progress_tick_sender, scanning_progress_tick_receiver) = mpsc::channel(1);
     Self {
       comm_managers,
+      scanning_start_timeouts,
+      comm_manager_slots,
+      max_comm_manager_restarts,
       device_config_manager: device_config_manager,
-      server_sender,
+      event_bus,
       device_map,
+      device_list_history,
       device_comm_receiver,
+      device_comm_sender,
       device_event_sender,
       device_event_receiver,
       device_command_receiver,
-      scanning_bringup_in_progress: false,
+      scanning_bringup_pending: HashSet::new(),
+      scanning_bringup_sender,
+      scanning_bringup_receiver,
       scanning_started: false,
+      scanning_start_time: Instant::now(),
+      scanning_devices_found: HashMap::new(),
+      scanning_progress_interval,
+      intensity_metering_interval,
+      energy_budget,
+      scanning_progress_tick_sender,
+      scanning_progress_tick_receiver,
+      scanning_progress_token: None,
+      connection_attempts,
+      command_audit_size,
+      value_policy,
+      command_audits,
       connecting_devices: Arc::new(DashSet::new()),
+      system_resume_receiver,
+      restart_scanning_on_resume,
+      reconnect_state,
+      comm_manager_status,
+      comm_manager_firmware,
       loop_cancellation_token,
     }
   }
@@ -87,24 +276,138 @@ impl ServerDeviceManagerEventLoop {
     false
   }
 
-  async fn handle_start_scanning(&mut self) {
-    if self.scanning_status() || self.scanning_bringup_in_progress {
+  fn handle_start_scanning(&mut self) {
+    if self.scanning_status() || !self.scanning_bringup_pending.is_empty() {
       debug!("System already scanning, ignoring new scanning request");
       return;
     }
 
     info!("No scan currently in progress, starting new scan.");
-    self.scanning_bringup_in_progress = true;
     self.scanning_started = true;
-    let fut_vec: Vec<_> = self
+    self.start_scanning_progress();
+    // Kick off every manager at once, and let each one report back on its own time. Some managers
+    // (serial port probing, for instance) can take a while to come up, and we don't want them
+    // holding up everyone else.
+    for index in 0..self.comm_managers.len() {
+      self.start_manager_scanning(index);
+    }
+    if self.scanning_bringup_pending.is_empty() {
+      self.maybe_emit_scanning_finished();
+    }
+  }
+
+  fn start_manager_scanning(&mut self, index: usize) {
+    let mgr = &mut self.comm_managers[index];
+    let timeout = self.scanning_start_timeouts[index];
+    let slot_index = self.comm_manager_slots[index].index;
+    let name = mgr.name();
+    let fut = mgr.start_scanning();
+    let sender = self.scanning_bringup_sender.clone();
+    let panic_sender = sender.clone();
+    self.scanning_bringup_pending.insert(slot_index);
+    async_manager::spawn_catching_panics(
+      async move {
+        let result = select! {
+          result = fut.fuse() => match result {
+            Ok(()) => ScanningBringupResult::Started,
+            Err(err) => ScanningBringupResult::Failed(err),
+          },
+          _ = util::sleep(timeout).fuse() => ScanningBringupResult::TimedOut,
+        };
+        if sender.send((slot_index, name, result)).await.is_err() {
+          debug!("Device manager event loop exited before {} started scanning.", name);
+        }
+      },
+      move |reason| {
+        let _ = panic_sender.try_send((slot_index, name, ScanningBringupResult::Panicked(reason)));
+      },
+    );
+  }
+
+  fn handle_scanning_bringup(
+    &mut self,
+    slot_index: usize,
+    name: &'static str,
+    result: ScanningBringupResult,
+  ) {
+    self.scanning_bringup_pending.remove(&slot_index);
+    match result {
+      ScanningBringupResult::Started => debug!("{} started scanning.", name),
+      ScanningBringupResult::Failed(err) => warn!("{} failed to start scanning: {}", name, err),
+      ScanningBringupResult::TimedOut => warn!(
+        "{} did not start scanning before its timeout, no longer waiting on it.",
+        name
+      ),
+      ScanningBringupResult::Panicked(reason) => self.handle_comm_manager_failure(name, reason),
+    }
+    if self.scanning_bringup_pending.is_empty() {
+      debug!("Scanning bringup finished for all hardware comm managers.");
+      // Fast managers may have already finished while slower ones were still coming up. Their
+      // ScanningFinished events were held while bringup was in progress, so check again now.
+      self.maybe_emit_scanning_finished();
+    }
+  }
+
+  fn maybe_emit_scanning_finished(&mut self) {
+    if !self.scanning_status() && self.scanning_started {
+      debug!("All managers finished, emitting ScanningFinished");
+      self.scanning_started = false;
+      if let Some(token) = self.scanning_progress_token.take() {
+        token.cancel();
+      }
+      if !self.event_bus.publish(DeviceManagerEvent::ServerMessage(
+        ScanningFinishedV0::default().into(),
+      )) {
+        info!("Server disappeared, exiting loop.");
+      }
+    }
+  }
+
+  fn start_scanning_progress(&mut self) {
+    self.scanning_start_time = Instant::now();
+    self.scanning_devices_found.clear();
+    // Drop any tick that was left over from the last scan.
+    while self.scanning_progress_tick_receiver.try_recv().is_ok() {}
+    let token = self.loop_cancellation_token.child_token();
+    let ticker_token = token.clone();
+    let interval = self.scanning_progress_interval;
+    let tick_sender = self.scanning_progress_tick_sender.clone();
+    async_manager::spawn(async move {
+      loop {
+        tokio::select! {
+          _ = util::sleep(interval) => {
+            if tick_sender.send(()).await.is_err() {
+              break;
+            }
+          }
+          _ = ticker_token.cancelled() => break,
+        }
+      }
+    });
+    self.scanning_progress_token = Some(token);
+  }
+
+  fn emit_scanning_progress(&self) {
+    if !self.scanning_started {
+      return;
+    }
+    let devices_found = self
       .comm_managers
-      .iter_mut()
-      .map(|guard| guard.start_scanning())
+      .iter()
+      .map(|mgr| {
+        let found = self
+          .scanning_devices_found
+          .get(mgr.name())
+          .map_or(0, |addresses| addresses.len());
+        (mgr.name(), found)
+      })
       .collect();
-    // TODO If start_scanning fails anywhere, this will ignore it. We should maybe at least log?
-    future::join_all(fut_vec).await;
-    debug!("Scanning started for all hardware comm managers.");
-    self.scanning_bringup_in_progress = false;
+    let progress = ScanningProgress::new(devices_found, self.scanning_start_time.elapsed());
+    trace!("Scanning progress: {:?}", progress);
+    // No one listening for progress is fine, so ignore send errors.
+    self
+      .event_bus
+      .publish(DeviceManagerEvent::ScanningProgress(progress));
   }
 
   async fn handle_stop_scanning(&mut self) {
@@ -117,27 +420,159 @@ impl ServerDeviceManagerEventLoop {
     future::join_all(fut_vec).await;
   }
 
-  async fn handle_device_communication(&mut self, event: HardwareCommunicationManagerEvent) {
+  /// Connections (BLE ones especially) can be left dead without ever being reported as
+  /// disconnected when the host goes to sleep. Check on every device and push disconnects through
+  /// the usual device event path for anything that didn't make it.
+  async fn handle_system_resume(&mut self, slept: Duration) {
+    info!(
+      "System resumed after sleeping for roughly {:?}, checking device connections.",
+      slept
+    );
+    for device_pair in self.device_map.iter() {
+      let device = device_pair.value().clone();
+      let event_sender = self.device_event_sender.clone();
+      async_manager::spawn(async move {
+        let connected = select! {
+          connected = device.is_connected().fuse() => connected,
+          _ = util::sleep(RESUME_CONNECTION_CHECK_TIMEOUT).fuse() => false,
+        };
+        if connected {
+          return;
+        }
+        info!(
+          "Device {} did not survive system sleep, removing.",
+          device.name()
+        );
+        if event_sender
+          .send(ServerDeviceEvent::Disconnected(device.identifier().clone()))
+          .await
+          .is_err()
+        {
+          return;
+        }
+        // Let the hardware clean up whatever it can. If it already knows it's disconnected, this is
+        // a no-op.
+        if let Err(err) = device.disconnect().await {
+          debug!("Error disconnecting device after system resume: {:?}", err);
+        }
+      });
+    }
+    if self.restart_scanning_on_resume {
+      // Scanning may have died along with everything else, so stop whatever's left of it before
+      // starting fresh.
+      if self.scanning_started || self.scanning_status() {
+        self.handle_stop_scanning().await;
+      }
+      info!("Restarting scanning after system resume.");
+      self.handle_start_scanning();
+    }
+  }
+
+  fn set_comm_manager_status(
+    &self,
+    name: &'static str,
+    status: HardwareCommunicationManagerStatus,
+  ) {
+    self.comm_manager_status.insert(name, status.clone());
+    self
+      .event_bus
+      .publish(DeviceManagerEvent::CommManagerStatusChanged {
+        manager: name,
+        status,
+      });
+  }
+
+  /// Rebuild a comm manager whose tasks have panicked, picking up scanning where it left off, or
+  /// disable it if it has already used up its restarts.
+  fn handle_comm_manager_failure(&mut self, name: &'static str, reason: String) {
+    let Some(index) = self.comm_managers.iter().position(|mgr| mgr.name() == name) else {
+      debug!("{} failed again after being disabled, ignoring.", name);
+      return;
+    };
+    // Whatever the old manager still has running goes away along with it.
+    self.comm_manager_slots[index].cancellation_token.cancel();
+    self
+      .scanning_bringup_pending
+      .remove(&self.comm_manager_slots[index].index);
+    if self.comm_manager_slots[index].restarts < self.max_comm_manager_restarts {
+      let slot = &mut self.comm_manager_slots[index];
+      slot.restarts += 1;
+      warn!(
+        "{} failed ({}), restarting it ({} of {} restarts).",
+        name, reason, slot.restarts, self.max_comm_manager_restarts
+      );
+      slot.cancellation_token = self.loop_cancellation_token.child_token();
+      let comm_mgr = build_comm_manager(
+        slot.builder.as_mut(),
+        self.device_comm_sender.clone(),
+        &slot.cancellation_token,
+      );
+      let status = comm_mgr.status();
+      self.comm_managers[index] = comm_mgr;
+      self.set_comm_manager_status(name, status);
+      if self.scanning_started {
+        self.start_manager_scanning(index);
+      }
+    } else {
+      error!(
+        "{} failed ({}) and is out of restarts, disabling it.",
+        name, reason
+      );
+      self.comm_managers.remove(index);
+      self.scanning_start_timeouts.remove(index);
+      self.comm_manager_slots.remove(index);
+      self.set_comm_manager_status(name, HardwareCommunicationManagerStatus::Failed(reason));
+      // It won't be reporting back, so don't leave a scan waiting on it.
+      if self.scanning_bringup_pending.is_empty() {
+        self.maybe_emit_scanning_finished();
+      }
+    }
+  }
+
+  async fn handle_device_communication(
+    &mut self,
+    manager_name: &'static str,
+    event: HardwareCommunicationManagerEvent,
+  ) {
     match event {
       HardwareCommunicationManagerEvent::ScanningFinished => {
         debug!(
           "System signaled that scanning was finished, check to see if all managers are finished."
         );
-        if self.scanning_bringup_in_progress {
+        if !self.scanning_bringup_pending.is_empty() {
           debug!("Hardware Comm Manager finished before scanning was fully started, continuing event loop.");
           return;
         }
-        if !self.scanning_status() && self.scanning_started {
-          debug!("All managers finished, emitting ScanningFinished");
-          self.scanning_started = false;
-          if self
-            .server_sender
-            .send(ScanningFinishedV0::default().into())
-            .is_err()
-          {
-            info!("Server disappeared, exiting loop.");
-          }
+        self.maybe_emit_scanning_finished();
+      }
+      HardwareCommunicationManagerEvent::StatusChanged { name, status } => {
+        if status == HardwareCommunicationManagerStatus::Available {
+          info!("{} hardware is available.", name);
+        } else {
+          warn!("{} hardware status changed to {:?}.", name, status);
         }
+        self.set_comm_manager_status(name, status);
+      }
+      HardwareCommunicationManagerEvent::FirmwareVersion { hardware, version } => {
+        info!(
+          "{} hardware {} has firmware version {}.",
+          manager_name, hardware, version
+        );
+        let mut versions = self.comm_manager_firmware.entry(manager_name).or_default();
+        versions.retain(|firmware| *firmware.hardware() != hardware);
+        versions.push(HardwareFirmwareVersion::new(&hardware, &version));
+      }
+      HardwareCommunicationManagerEvent::Failed { reason } => {
+        self.handle_comm_manager_failure(manager_name, reason);
+      }
+      HardwareCommunicationManagerEvent::Error { reason } => {
+        warn!("{} reported an error: {}", manager_name, reason);
+        self
+          .event_bus
+          .publish(DeviceManagerEvent::CommManagerError {
+            manager: manager_name,
+            reason,
+          });
       }
       HardwareCommunicationManagerEvent::DeviceFound {
         name,
@@ -145,6 +580,11 @@ impl ServerDeviceManagerEventLoop {
         creator,
       } => {
         info!("Device {} ({}) found.", name, address);
+        self
+          .scanning_devices_found
+          .entry(manager_name)
+          .or_default()
+          .insert(address.clone());
         // Make sure the device isn't on the deny list, or is on the allow list if anything is on it.
         if !self.device_config_manager.address_allowed(&address) {
           return;
@@ -207,6 +647,9 @@ impl ServerDeviceManagerEventLoop {
 
         let device_config_manager = self.device_config_manager.clone();
         let connecting_devices = self.connecting_devices.clone();
+        let connection_attempts = self.connection_attempts;
+        let command_audit_size = self.command_audit_size;
+        let value_policy = self.value_policy;
         let span = info_span!(
           "device creation",
           name = tracing::field::display(name),
@@ -214,7 +657,16 @@ impl ServerDeviceManagerEventLoop {
         );
 
         async_manager::spawn(async move {
-          match ServerDevice::build(device_config_manager, creator, protocol_specializers).await {
+          match ServerDevice::build(
+            device_config_manager,
+            creator,
+            protocol_specializers,
+            connection_attempts,
+            command_audit_size,
+            value_policy,
+          )
+          .await
+          {
             Ok(device) => {
               if device_event_sender_clone
                 .send(ServerDeviceEvent::Connected(Arc::new(device)))
@@ -255,6 +707,18 @@ impl ServerDeviceManagerEventLoop {
         // message goes out, so timing matters here.
         if let Some((_, old_device)) = self.device_map.remove(&device_index) {
           info!("Device map contains key {}.", device_index);
+          let generation = self.device_list_history().device_removed(device_index);
+          // The old device's own disconnect may not make it back to us before the new device is
+          // in the map, so announce the removal here to keep it ahead of the new DeviceAdded.
+          if !self.event_bus.publish(DeviceManagerEvent::ServerMessage(
+            DeviceRemovedV4::new(device_index, generation).into(),
+          )) {
+            debug!("Server not currently available, dropping Device Removed event.");
+          }
+          self.save_reconnect_state(device_index, &old_device);
+          old_device
+            .command_audit()
+            .record(CommandAuditEvent::Disconnected);
           // After removing the device from the array, manually disconnect it to
           // make sure the event is thrown.
           if let Err(err) = old_device.disconnect().await {
@@ -266,8 +730,20 @@ impl ServerDeviceManagerEventLoop {
           info!("Device map does not contain key {}.", device_index);
         }
 
-        // Create event loop for forwarding device events into our selector.
-        let event_listener = device.event_stream();
+        // A device coming back under the same index picks up where its audit left off.
+        let command_audit = device.command_audit().clone();
+        if let Some(previous) = self
+          .command_audits
+          .insert(device_index, command_audit.clone())
+        {
+          command_audit.carry_over(&previous);
+        }
+
+        // Create event loop for forwarding device events into our selector. Both forwarders stop
+        // on shutdown, even if the device is still hanging around.
+        let event_listener = device
+          .event_stream()
+          .take_until(self.loop_cancellation_token.child_token().cancelled_owned());
         let event_sender = self.device_event_sender.clone();
         async_manager::spawn(async move {
           pin_mut!(event_listener);
@@ -280,24 +756,63 @@ impl ServerDeviceManagerEventLoop {
           }
         });
 
+        // Commands may come in with whatever index the sender used (identification pulses, for
+        // instance), so stamp them with the one the device actually has.
+        let command_listener = device
+          .actuator_command_stream()
+          .take_until(self.loop_cancellation_token.child_token().cancelled_owned());
+        let event_bus = self.event_bus.clone();
+        async_manager::spawn(async move {
+          pin_mut!(command_listener);
+          // Ends when the device goes away and drops its sender.
+          while let Some(mut command) = command_listener.next().await {
+            command.set_device_index(device_index);
+            event_bus.publish(DeviceManagerEvent::ActuatorCommand(command));
+          }
+        });
+
+        if let Some(interval) = self.intensity_metering_interval {
+          start_intensity_meter(
+            device_index,
+            &device,
+            interval,
+            self.event_bus.clone(),
+            self.loop_cancellation_token.child_token(),
+          );
+        }
+
+        if let Some(policy) = self.energy_budget {
+          start_energy_budget(
+            device_index,
+            &device,
+            policy,
+            self.event_bus.clone(),
+            self.loop_cancellation_token.child_token(),
+          );
+        }
+
         info!("Assigning index {} to {}", device_index, device.name());
-        let device_added_message = DeviceAddedV4::new(
+        let mut device_added_message = DeviceAddedV4::new(
           device_index,
           &device.name(),
           &device.definition().user_config().display_name(),
-          &None,
+          &device.timing_policy().min_command_interval_ms(),
           &device.definition().features().clone(),
         );
-        self.device_map.insert(device_index, device);
+        device_added_message.set_device_protocol(Some(device.identifier().protocol().clone()));
+        device_added_message.set_device_transport(Some(device.transport()));
+        device_added_message.set_device_address(Some(device.identifier().address().clone()));
+        device_added_message.set_device_firmware_version(device.firmware_version());
+        self.device_map.insert(device_index, device.clone());
+        device_added_message.set_generation(self.device_list_history().device_added(device_index));
         // After that, we can send out to the server's event listeners to let
         // them know a device has been added.
-        if self
-          .server_sender
-          .send(device_added_message.into())
-          .is_err()
-        {
+        if !self.event_bus.publish(DeviceManagerEvent::ServerMessage(
+          device_added_message.into(),
+        )) {
           debug!("Server not currently available, dropping Device Added event.");
         }
+        self.replay_reconnect_state(device_index, device);
       }
       ServerDeviceEvent::Disconnected(identifier) => {
         let mut device_index = None;
@@ -308,35 +823,93 @@ impl ServerDeviceManagerEventLoop {
           }
         }
         if let Some(device_index) = device_index {
-          self
+          let (_, device) = self
             .device_map
             .remove(&device_index)
             .expect("Remove will always work.");
-          if self
-            .server_sender
-            .send(DeviceRemovedV0::new(device_index).into())
-            .is_err()
-          {
+          let generation = self.device_list_history().device_removed(device_index);
+          self.save_reconnect_state(device_index, &device);
+          device
+            .command_audit()
+            .record(CommandAuditEvent::Disconnected);
+          if !self.event_bus.publish(DeviceManagerEvent::ServerMessage(
+            DeviceRemovedV4::new(device_index, generation).into(),
+          )) {
             debug!("Server not currently available, dropping Device Removed event.");
           }
         }
       }
       ServerDeviceEvent::Notification(_, message) => {
-        if self.server_sender.send(message.into()).is_err() {
+        if !self
+          .event_bus
+          .publish(DeviceManagerEvent::ServerMessage(message.into()))
+        {
           debug!("Server not currently available, dropping Device Added event.");
         }
       }
     }
   }
 
+  fn device_list_history(&self) -> MutexGuard<'_, DeviceListHistory> {
+    self
+      .device_list_history
+      .lock()
+      .expect("Device list history lock should never be poisoned.")
+  }
+
+  fn save_reconnect_state(&self, device_index: u32, device: &ServerDevice) {
+    let Some(reconnect_state) = &self.reconnect_state else {
+      return;
+    };
+    // Forgotten devices shouldn't come back doing what they were doing, should they be remembered.
+    if self
+      .device_config_manager
+      .address_forgotten(device.identifier().address())
+    {
+      reconnect_state.remove(&device_index);
+      return;
+    }
+    let commands = device.replay_commands();
+    if commands.is_empty() {
+      reconnect_state.remove(&device_index);
+    } else {
+      reconnect_state.insert(device_index, commands);
+    }
+  }
+
+  fn replay_reconnect_state(&self, device_index: u32, device: Arc<ServerDevice>) {
+    let Some((_, commands)) = self
+      .reconnect_state
+      .as_ref()
+      .and_then(|reconnect_state| reconnect_state.remove(&device_index))
+    else {
+      return;
+    };
+    info!(
+      "Replaying last actuator state to reconnected device {}.",
+      device.name()
+    );
+    async_manager::spawn(async move {
+      for command in commands {
+        if let Err(err) = device.parse_message(command).await {
+          warn!(
+            "Could not replay state to reconnected device {}: {:?}",
+            device.name(),
+            err
+          );
+        }
+      }
+    });
+  }
+
   pub async fn run(&mut self) {
     debug!("Starting Device Manager Loop");
     loop {
       tokio::select! {
         device_comm_msg = self.device_comm_receiver.recv() => {
-          if let Some(msg) = device_comm_msg {
-            trace!("Got device communication message {:?}", msg);
-            self.handle_device_communication(msg).await;
+          if let Some((manager_name, msg)) = device_comm_msg {
+            trace!("Got device communication message {:?} from {}", msg, manager_name);
+            self.handle_device_communication(manager_name, msg).await;
           } else {
             break;
           }
@@ -350,11 +923,25 @@ impl ServerDeviceManagerEventLoop {
             break;
           }
         },
+        bringup_msg = self.scanning_bringup_receiver.recv() => {
+          if let Some((slot_index, name, result)) = bringup_msg {
+            self.handle_scanning_bringup(slot_index, name, result);
+          } else {
+            error!("We shouldn't be able to get here since we also own the sender.");
+            break;
+          }
+        }
+        Some(_) = self.scanning_progress_tick_receiver.recv() => {
+          self.emit_scanning_progress();
+        }
+        Some(slept) = self.system_resume_receiver.recv() => {
+          self.handle_system_resume(slept).await;
+        }
         device_command_msg = self.device_command_receiver.recv() => {
           if let Some(msg) = device_command_msg {
             trace!("Got device command message {:?}", msg);
             match msg {
-              DeviceManagerCommand::StartScanning => self.handle_start_scanning().await,
+              DeviceManagerCommand::StartScanning => self.handle_start_scanning(),
               DeviceManagerCommand::StopScanning => self.handle_stop_scanning().await,
             }
           } else {
@@ -49,12 +49,20 @@ pub mod device;
 mod ping_timer;
 mod server;
 mod server_builder;
+pub mod server_config;
 mod server_downgrade_wrapper;
 mod server_message_conversion;
+mod server_statistics;
+pub mod server_storage;
+#[cfg(feature = "event-webhook")]
+pub mod webhook;
 
 pub use server::ButtplugServer;
 pub use server_builder::ButtplugServerBuilder;
+pub use server_config::ButtplugServerConfig;
 pub use server_downgrade_wrapper::ButtplugServerDowngradeWrapper;
+pub use server_statistics::{MessageStatistics, ServerStatistics};
+pub use server_storage::{JsonFileServerStorage, ServerStorage, ServerStorageError};
 
 use futures::future::BoxFuture;
 use thiserror::Error;
@@ -88,4 +96,13 @@ pub enum ButtplugServerError {
   /// Requested protocol has not been registered with the system.
   #[error("Buttplug Protocol of type {0} does not exist in the system and cannot be removed.")]
   ProtocolDoesNotExist(String),
+  /// Webhook endpoint is not a URL that can be sent to.
+  #[error("Webhook endpoint {0} is not an http(s) or ws(s) URL.")]
+  InvalidWebhookEndpoint(String),
+  /// State could not be loaded from the server's storage.
+  #[error("Server storage error: {0}")]
+  StorageError(#[from] ServerStorageError),
+  /// Server configuration could not be loaded.
+  #[error("Invalid server configuration: {0}")]
+  InvalidConfiguration(String),
 }
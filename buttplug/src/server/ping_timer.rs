@@ -15,6 +15,7 @@ use std::{
   time::Duration,
 };
 use tokio::sync::{mpsc, Notify};
+use tokio_util::sync::CancellationToken;
 
 pub enum PingMessage {
   Ping,
@@ -28,6 +29,7 @@ async fn ping_timer(
   mut ping_msg_receiver: mpsc::Receiver<PingMessage>,
   notifier: Arc<Notify>,
   pinged_out_status: Arc<AtomicBool>,
+  cancellation_token: CancellationToken,
 ) {
   let mut started = false;
   let mut pinged = false;
@@ -43,6 +45,7 @@ async fn ping_timer(
           pinged = false;
         }
       }
+      _ = cancellation_token.cancelled().fuse() => return,
       msg = ping_msg_receiver.recv().fuse() => {
         if msg.is_none() {
           return;
@@ -79,7 +82,7 @@ impl Drop for PingTimer {
 }
 
 impl PingTimer {
-  pub fn new(max_ping_time: u32) -> Self {
+  pub fn new(max_ping_time: u32, cancellation_token: CancellationToken) -> Self {
     let ping_timeout_notifier = Arc::new(Notify::new());
     let (sender, receiver) = mpsc::channel(256);
     let pinged_out = Arc::new(AtomicBool::new(false));
@@ -89,6 +92,7 @@ impl PingTimer {
         receiver,
         ping_timeout_notifier.clone(),
         pinged_out.clone(),
+        cancellation_token,
       );
       async_manager::spawn(async move { fut.await });
     }
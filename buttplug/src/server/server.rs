@@ -5,7 +5,12 @@
 // Licensed under the BSD 3-Clause license. See LICENSE file in the project root
 // for full license information.
 
-use super::{device::ServerDeviceManager, ping_timer::PingTimer, ButtplugServerResultFuture};
+use super::{
+  device::ServerDeviceManager,
+  ping_timer::PingTimer,
+  server_statistics::{ServerStatistics, StatisticsRegistry},
+  ButtplugServerResultFuture,
+};
 use crate::{
   core::{
     errors::*,
@@ -14,6 +19,7 @@ use crate::{
       ButtplugClientMessageV4,
       ButtplugDeviceCommandMessageUnion,
       ButtplugDeviceManagerMessageUnion,
+      ButtplugDeviceMessage,
       ButtplugMessage,
       ButtplugServerMessageV4,
       StopAllDevicesV0,
@@ -23,10 +29,12 @@ use crate::{
   },
   util::stream::convert_broadcast_receiver_to_stream,
 };
+use dashmap::DashSet;
 use futures::{
   future::{self, BoxFuture, FutureExt},
   Stream,
 };
+use instant::Instant;
 use std::{
   fmt,
   sync::{
@@ -63,6 +71,14 @@ pub struct ButtplugServer {
   output_sender: broadcast::Sender<ButtplugServerMessageV4>,
   /// Name of the connected client, assuming there is one.
   client_name: Arc<RwLock<Option<String>>>,
+  /// If true, the connected client is an observer, and can only watch.
+  observer: Arc<AtomicBool>,
+  /// Message counters, see [ButtplugServer::statistics()].
+  statistics: Arc<StatisticsRegistry>,
+  /// Pattern sessions the connected client started. Only their progress and end messages go out
+  /// through [ButtplugServer::event_stream()], so clients aren't told about sessions started
+  /// through the device manager.
+  client_pattern_sessions: Arc<DashSet<u32>>,
 }
 
 impl std::fmt::Debug for ButtplugServer {
@@ -92,6 +108,9 @@ impl ButtplugServer {
       connected,
       output_sender,
       client_name: Arc::new(RwLock::new(None)),
+      observer: Arc::new(AtomicBool::new(false)),
+      statistics: Arc::new(StatisticsRegistry::default()),
+      client_pattern_sessions: Arc::new(DashSet::new()),
     }
   }
 
@@ -103,6 +122,12 @@ impl ButtplugServer {
       .clone()
   }
 
+  /// If true, the connected client asked to be an observer during the handshake. Observers get all
+  /// server events and can list devices and read sensors, but can't scan or control devices.
+  pub fn client_is_observer(&self) -> bool {
+    self.observer.load(Ordering::SeqCst)
+  }
+
   /// Retreive an async stream of ButtplugServerMessages. This is how the server sends out
   /// non-query-related updates to the system, including information on devices being added/removed,
   /// client disconnection, etc...
@@ -110,11 +135,32 @@ impl ButtplugServer {
     // Unlike the client API, we can expect anyone using the server to pin this
     // themselves.
     let server_receiver = convert_broadcast_receiver_to_stream(self.output_sender.subscribe());
-    let device_receiver = self.device_manager.event_stream();
+    let client_pattern_sessions = self.client_pattern_sessions.clone();
+    let device_receiver = self
+      .device_manager
+      .event_stream()
+      .filter(move |msg| match msg {
+        ButtplugServerMessageV4::PatternSessionProgress(m) => {
+          client_pattern_sessions.contains(&m.session_id())
+        }
+        ButtplugServerMessageV4::PatternSessionEnded(m) => {
+          client_pattern_sessions.contains(&m.session_id())
+        }
+        _ => true,
+      });
     device_receiver.merge(server_receiver)
   }
 
-  /// Returns a references to the internal device manager, for handling configuration.
+  /// Message counts and timings for the server and current client session, for diagnostics.
+  pub fn statistics(&self) -> ServerStatistics {
+    self.statistics.snapshot()
+  }
+
+  /// Returns a reference to the internal device manager, for handling configuration.
+  ///
+  /// Applications that embed a server without ever connecting a client (installations, kiosks,
+  /// etc...) can also use it to scan for, list, and command devices directly. Shutting the device
+  /// manager down is left to the server.
   pub fn device_manager(&self) -> Arc<ServerDeviceManager> {
     self.device_manager.clone()
   }
@@ -136,6 +182,11 @@ impl ButtplugServer {
     let stop_fut = self.parse_message(ButtplugClientMessageV4::StopAllDevices(
       StopAllDevicesV0::default(),
     ));
+    // Observers can't have started anything, so whatever is running belongs to someone else and
+    // is left alone. The stop messages above will just have been rejected.
+    self.observer.store(false, Ordering::SeqCst);
+    self.statistics.end_session();
+    self.client_pattern_sessions.clear();
     let connected = self.connected.clone();
     let mut name = self
       .client_name
@@ -171,6 +222,9 @@ impl ButtplugServer {
       msg
     );
     let id = msg.id();
+    let started = Instant::now();
+    let device_command = ButtplugDeviceCommandMessageUnion::try_from(msg.clone()).ok();
+    let device_index = device_command.as_ref().map(|cmd| cmd.device_index());
     if !self.connected() {
       // Check for ping timeout first! There's no way we should've pinged out if
       // we haven't received RequestServerInfo first, but we do want to know if
@@ -188,9 +242,21 @@ impl ButtplugServer {
       };
       if let Some(mut return_error) = error {
         return_error.set_id(msg.id());
+        self
+          .statistics
+          .record(started.elapsed(), true, device_index);
         return future::ready(Err(return_error)).boxed();
       }
       // If we haven't pinged out and we got an RSI message, fall thru.
+    } else if self.client_is_observer() && !Self::allowed_for_observer(&msg) {
+      let mut error = message::ErrorV0::from(ButtplugError::from(
+        ButtplugMessageError::ObserverNotAllowed(format!("{:?}", msg)),
+      ));
+      error.set_id(id);
+      self
+        .statistics
+        .record(started.elapsed(), true, device_index);
+      return future::ready(Err(error)).boxed();
     }
     // Produce whatever future is needed to reply to the message, this may be a
     // device command future, or something the server handles. All futures will
@@ -198,7 +264,7 @@ impl ButtplugServer {
     // tagging the result with the message id in the future we put out as the
     // return value from this method.
     let out_fut = if ButtplugDeviceManagerMessageUnion::try_from(msg.clone()).is_ok()
-      || ButtplugDeviceCommandMessageUnion::try_from(msg.clone()).is_ok()
+      || device_command.is_some()
     {
       self.device_manager.parse_message(msg.clone())
     } else {
@@ -208,11 +274,17 @@ impl ButtplugServer {
         _ => ButtplugMessageError::UnexpectedMessageType(format!("{:?}", msg)).into(),
       }
     };
+    let statistics = self.statistics.clone();
+    let client_pattern_sessions = self.client_pattern_sessions.clone();
     // Simple way to set the ID on the way out. Just rewrap
     // the returned future to make sure it happens.
     async move {
-      out_fut
-        .await
+      let result = out_fut.await;
+      statistics.record(started.elapsed(), result.is_err(), device_index);
+      if let Ok(ButtplugServerMessageV4::PatternSessionStarted(m)) = &result {
+        client_pattern_sessions.insert(m.session_id());
+      }
+      result
         .map(|mut ok_msg| {
           ok_msg.set_id(id);
           ok_msg
@@ -227,12 +299,27 @@ impl ButtplugServer {
     .boxed()
   }
 
+  /// Observers can ask about devices and read their sensors, but nothing that changes device or
+  /// scanning state.
+  fn allowed_for_observer(msg: &ButtplugClientMessageV4) -> bool {
+    matches!(
+      msg,
+      ButtplugClientMessageV4::RequestServerInfo(_)
+        | ButtplugClientMessageV4::Ping(_)
+        | ButtplugClientMessageV4::RequestDeviceList(_)
+        | ButtplugClientMessageV4::RequestDeviceInfo(_)
+        | ButtplugClientMessageV4::SensorReadCmd(_)
+        | ButtplugClientMessageV4::SensorSubscribeCmd(_)
+        | ButtplugClientMessageV4::SensorUnsubscribeCmd(_)
+    )
+  }
+
   /// Performs the [RequestServerInfo]([ServerInfo](crate::core::message::RequestServerInfo) /
   /// [ServerInfo](crate::core::message::ServerInfo) handshake, as specified in the [Buttplug
   /// Protocol Spec](https://buttplug-spec.docs.buttplug.io). This is the first thing that must
   /// happens upon connection to the server, in order to make sure the server can speak the same
   /// protocol version as the client.
-  fn perform_handshake(&self, msg: message::RequestServerInfoV1) -> ButtplugServerResultFuture {
+  fn perform_handshake(&self, msg: message::RequestServerInfoV4) -> ButtplugServerResultFuture {
     if self.connected() {
       return ButtplugHandshakeError::HandshakeAlreadyHappened.into();
     }
@@ -260,6 +347,11 @@ impl ButtplugServer {
       .try_write()
       .expect("We should never conflict on name access");
     *name = Some(msg.client_name().clone());
+    if msg.observer() {
+      info!("Client {} connected as an observer.", msg.client_name());
+    }
+    self.observer.store(msg.observer(), Ordering::SeqCst);
+    self.statistics.start_session();
     async move {
       ping_timer.start_ping_timer().await;
       connected.store(true, Ordering::SeqCst);
@@ -13,6 +13,7 @@ use super::{
   },
   ping_timer::PingTimer,
   server::ButtplugServer,
+  server_storage::{ServerStorage, USER_DEVICE_CONFIG_STORAGE_KEY},
   ButtplugServerError,
 };
 use crate::{
@@ -20,7 +21,10 @@ use crate::{
     errors::*,
     message::{self, ButtplugServerMessageV4},
   },
-  util::async_manager,
+  util::{
+    async_manager,
+    device_configuration::{apply_user_config, save_user_config},
+  },
 };
 use std::sync::{
   atomic::{AtomicBool, Ordering},
@@ -39,6 +43,8 @@ pub struct ButtplugServerBuilder {
   max_ping_time: Option<u32>,
   /// Device manager builder for the server
   device_manager: Arc<ServerDeviceManager>,
+  /// Where state that should outlive the server is kept, if anywhere.
+  storage: Option<Arc<dyn ServerStorage>>,
 }
 
 impl Default for ButtplugServerBuilder {
@@ -46,6 +52,7 @@ impl Default for ButtplugServerBuilder {
     Self {
       name: "Buttplug Server".to_owned(),
       max_ping_time: None,
+      storage: None,
       device_manager: Arc::new(
         ServerDeviceManagerBuilder::new(
           DeviceConfigurationManagerBuilder::default()
@@ -64,6 +71,7 @@ impl ButtplugServerBuilder {
     Self {
       name: "Buttplug Server".to_owned(),
       max_ping_time: None,
+      storage: None,
       device_manager: Arc::new(device_manager),
     }
   }
@@ -72,6 +80,7 @@ impl ButtplugServerBuilder {
     Self {
       name: "Buttplug Server".to_owned(),
       max_ping_time: None,
+      storage: None,
       device_manager: device_manager,
     }
   }
@@ -95,12 +104,24 @@ impl ButtplugServerBuilder {
     self
   }
 
+  /// Set where the server keeps state between runs, see [ServerStorage]. The user device
+  /// configuration (device indexes, display names, calibrations, limits...) is loaded from it when
+  /// the server is built, and stored back to it every time it changes.
+  pub fn storage(&mut self, storage: Arc<dyn ServerStorage>) -> &mut Self {
+    self.storage = Some(storage);
+    self
+  }
+
   /// Try to build a [ButtplugServer] using the parameters given.
   pub fn finish(&self) -> Result<ButtplugServer, ButtplugServerError> {
     // Create the server
     debug!("Creating server '{}'", self.name);
     info!("Buttplug Server Operating System Info: {}", os_info::get());
 
+    if let Some(storage) = &self.storage {
+      self.start_storage(storage.clone())?;
+    }
+
     // Set up our channels to different parts of the system.
     let (output_sender, _) = broadcast::channel(256);
     let output_sender_clone = output_sender.clone();
@@ -108,9 +129,10 @@ impl ButtplugServerBuilder {
     let connected = Arc::new(AtomicBool::new(false));
     let connected_clone = connected.clone();
 
-    // TODO this should use a cancellation token instead of passing around the timer itself.
+    // Server tasks hang off the device manager's token, so shutting it down stops them too.
+    let token = self.device_manager.child_cancellation_token();
     let ping_time = self.max_ping_time.unwrap_or(0);
-    let ping_timer = Arc::new(PingTimer::new(ping_time));
+    let ping_timer = Arc::new(PingTimer::new(ping_time, token.child_token()));
     let ping_timeout_notifier = ping_timer.ping_timeout_waiter();
 
     // Spawn the ping timer task, assuming the ping time is > 0.
@@ -118,8 +140,11 @@ impl ButtplugServerBuilder {
       let device_manager_clone = self.device_manager.clone();
       async_manager::spawn(
         async move {
-          // This will only exit if we've pinged out.
-          ping_timeout_notifier.await;
+          // This will only get past here if we've pinged out.
+          tokio::select! {
+            _ = ping_timeout_notifier => {}
+            _ = token.cancelled() => return,
+          }
           error!("Ping out signal received, stopping server");
           connected_clone.store(false, Ordering::SeqCst);
           async_manager::spawn(async move {
@@ -151,4 +176,37 @@ impl ButtplugServerBuilder {
       output_sender,
     ))
   }
+
+  /// Load the user device configuration from storage, then keep storage up to date with it for as
+  /// long as the device manager runs.
+  fn start_storage(&self, storage: Arc<dyn ServerStorage>) -> Result<(), ButtplugServerError> {
+    let dcm = self.device_manager.device_configuration_manager().clone();
+    if let Some(user_config) = storage.load(USER_DEVICE_CONFIG_STORAGE_KEY)? {
+      apply_user_config(&dcm, &user_config, false)
+        .map_err(ButtplugServerError::DeviceConfigurationManagerError)?;
+    }
+    let token = self.device_manager.child_cancellation_token();
+    async_manager::spawn(
+      async move {
+        loop {
+          tokio::select! {
+            _ = dcm.user_config_changed() => {}
+            _ = token.cancelled() => break,
+          }
+          let result = save_user_config(&dcm)
+            .map_err(|e| e.to_string())
+            .and_then(|config| {
+              storage
+                .store(USER_DEVICE_CONFIG_STORAGE_KEY, &config)
+                .map_err(|e| e.to_string())
+            });
+          if let Err(e) = result {
+            error!("Could not store user device configuration: {}", e);
+          }
+        }
+      }
+      .instrument(tracing::info_span!("Buttplug Server Storage Task")),
+    );
+    Ok(())
+  }
 }
@@ -0,0 +1,531 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2024 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Configuration for a whole server, in one struct that can be loaded from a file.
+//!
+//! Front-ends that keep their settings in a file can load a [ButtplugServerConfig] from it and turn
+//! it into a [ButtplugServerBuilder] with [ButtplugServerConfig::server_builder], instead of
+//! calling every builder method themselves. The struct is plain serde, so besides JSON
+//! ([ButtplugServerConfig::from_json]) it can be loaded from TOML, YAML or anything else serde
+//! supports. Fields use kebab-case names, and anything left out keeps the library default.
+//!
+//! ```json
+//! {
+//!   "name": "My Server",
+//!   "max-ping-time-ms": 1000,
+//!   "storage-directory": "/home/user/.config/my-app",
+//!   "comm-managers": {
+//!     "websocket-server": { "port": 54817 }
+//!   },
+//!   "device-manager": {
+//!     "replay-state-on-reconnect": true
+//!   },
+//!   "energy-budget": {
+//!     "intensity-threshold": 0.8,
+//!     "budget-ms": 600000,
+//!     "action": { "cooldown": { "duration-ms": 60000 } }
+//!   }
+//! }
+//! ```
+
+use super::{
+  device::{EnergyBudgetAction, EnergyBudgetPolicy, ServerDeviceManagerBuilder},
+  ButtplugServerBuilder,
+  ButtplugServerError,
+  JsonFileServerStorage,
+};
+use crate::util::device_configuration::load_protocol_configs;
+use serde::{Deserialize, Serialize};
+use std::{fs, path::PathBuf, sync::Arc, time::Duration};
+
+/// Everything needed to set up a server. See the [module docs](self) for an example.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ButtplugServerConfig {
+  /// Name the server reports to clients.
+  pub name: String,
+  /// Maximum time between client pings before the server disconnects, or None for no ping timer.
+  pub max_ping_time_ms: Option<u32>,
+  /// Directory to keep state between runs in, see
+  /// [ServerStorage](super::server_storage::ServerStorage). Nothing is kept if this isn't set.
+  pub storage_directory: Option<PathBuf>,
+  pub device_config: DeviceConfigSettings,
+  pub comm_managers: CommManagerSettings,
+  pub device_manager: DeviceManagerSettings,
+  /// Limit on how long devices can run at high intensity. Off if not set.
+  pub energy_budget: Option<EnergyBudgetSettings>,
+}
+
+impl Default for ButtplugServerConfig {
+  fn default() -> Self {
+    Self {
+      name: "Buttplug Server".to_owned(),
+      max_ping_time_ms: None,
+      storage_directory: None,
+      device_config: DeviceConfigSettings::default(),
+      comm_managers: CommManagerSettings::default(),
+      device_manager: DeviceManagerSettings::default(),
+      energy_budget: None,
+    }
+  }
+}
+
+/// Where device configuration comes from, and which protocols and messages are allowed.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct DeviceConfigSettings {
+  /// Device configuration file to use instead of the one built into the library.
+  pub main_config_path: Option<PathBuf>,
+  /// User device configuration file to load on top of the main one.
+  pub user_config_path: Option<PathBuf>,
+  pub allow_raw_messages: bool,
+  pub allow_experimental_protocols: bool,
+  /// Protocols to leave out, by name.
+  pub exclude_protocols: Vec<String>,
+}
+
+/// Which communication managers to run. Managers for hardware attached to this machine are on by
+/// default, managers that listen on the network are off until configured. Managers the library
+/// wasn't built with, or that don't run on this platform, are skipped with a warning.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct CommManagerSettings {
+  pub bluetooth: bool,
+  pub serial: bool,
+  pub hid: bool,
+  pub lovense_dongle: bool,
+  pub xinput: bool,
+  /// Windows.Gaming.Input gamepads. These also show up through XInput, so turn that off when
+  /// turning this on.
+  pub gaming_input: bool,
+  /// DualShock 4 and DualSense controllers, over USB or bluetooth.
+  pub playstation: bool,
+  pub lovense_connect: bool,
+  pub websocket_server: Option<NetworkCommManagerSettings>,
+  pub osc: Option<OscCommManagerSettings>,
+}
+
+impl Default for CommManagerSettings {
+  fn default() -> Self {
+    Self {
+      bluetooth: true,
+      serial: true,
+      hid: false,
+      lovense_dongle: true,
+      xinput: true,
+      gaming_input: false,
+      playstation: true,
+      lovense_connect: false,
+      websocket_server: None,
+      osc: None,
+    }
+  }
+}
+
+/// Settings for managers that listen for devices on the network.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct NetworkCommManagerSettings {
+  /// Port to listen on, or None for the manager's default.
+  pub port: Option<u16>,
+  /// Listen on all interfaces instead of just localhost.
+  pub listen_on_all_interfaces: bool,
+}
+
+/// Settings for the OSC motion tracker manager.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct OscCommManagerSettings {
+  #[serde(flatten)]
+  pub network: NetworkCommManagerSettings,
+  /// OSC address prefix trackers send to, or None for the manager's default.
+  pub address_prefix: Option<String>,
+}
+
+/// Device manager options. Anything not set keeps the [ServerDeviceManagerBuilder] default.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct DeviceManagerSettings {
+  pub scanning_start_timeout_ms: Option<u64>,
+  pub detect_system_resume: Option<bool>,
+  pub restart_scanning_on_resume: Option<bool>,
+  pub replay_state_on_reconnect: Option<bool>,
+  pub scanning_progress_interval_ms: Option<u64>,
+  /// Turns intensity metering on, at this interval.
+  pub intensity_metering_interval_ms: Option<u64>,
+  pub connection_attempts: Option<u32>,
+  pub command_audit_size: Option<usize>,
+  pub max_comm_manager_restarts: Option<u32>,
+}
+
+/// Serde form of [EnergyBudgetPolicy].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct EnergyBudgetSettings {
+  pub intensity_threshold: f64,
+  pub budget_ms: u64,
+  pub action: EnergyBudgetActionSettings,
+}
+
+/// Serde form of [EnergyBudgetAction].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub enum EnergyBudgetActionSettings {
+  #[serde(rename_all = "kebab-case")]
+  Cooldown { duration_ms: u64 },
+  #[serde(rename_all = "kebab-case")]
+  Reduce {
+    max_intensity: f64,
+    duration_ms: u64,
+  },
+}
+
+impl From<&EnergyBudgetSettings> for EnergyBudgetPolicy {
+  fn from(settings: &EnergyBudgetSettings) -> Self {
+    let action = match settings.action {
+      EnergyBudgetActionSettings::Cooldown { duration_ms } => {
+        EnergyBudgetAction::Cooldown(Duration::from_millis(duration_ms))
+      }
+      EnergyBudgetActionSettings::Reduce {
+        max_intensity,
+        duration_ms,
+      } => EnergyBudgetAction::Reduce {
+        max_intensity,
+        duration: Duration::from_millis(duration_ms),
+      },
+    };
+    EnergyBudgetPolicy::new(
+      settings.intensity_threshold,
+      Duration::from_millis(settings.budget_ms),
+      action,
+    )
+  }
+}
+
+fn read_config_file(path: &Option<PathBuf>) -> Result<Option<String>, ButtplugServerError> {
+  path
+    .as_ref()
+    .map(|path| {
+      fs::read_to_string(path).map_err(|e| {
+        ButtplugServerError::InvalidConfiguration(format!("Cannot read {}: {}", path.display(), e))
+      })
+    })
+    .transpose()
+}
+
+impl ButtplugServerConfig {
+  /// Load a configuration from JSON.
+  pub fn from_json(json: &str) -> Result<Self, ButtplugServerError> {
+    serde_json::from_str(json).map_err(|e| ButtplugServerError::InvalidConfiguration(e.to_string()))
+  }
+
+  /// Build the device configuration and device managers this configuration describes, and return
+  /// a server builder set up to use them. Device configuration files are read here.
+  pub fn server_builder(&self) -> Result<ButtplugServerBuilder, ButtplugServerError> {
+    let mut dcm_builder = load_protocol_configs(
+      &read_config_file(&self.device_config.main_config_path)?,
+      &read_config_file(&self.device_config.user_config_path)?,
+      false,
+    )
+    .map_err(ButtplugServerError::DeviceConfigurationManagerError)?;
+    dcm_builder
+      .allow_raw_messages(self.device_config.allow_raw_messages)
+      .allow_experimental_protocols(self.device_config.allow_experimental_protocols);
+    for protocol in &self.device_config.exclude_protocols {
+      dcm_builder.exclude_protocol(protocol);
+    }
+    let dcm = dcm_builder
+      .finish()
+      .map_err(ButtplugServerError::DeviceConfigurationManagerError)?;
+
+    let mut dm_builder = ServerDeviceManagerBuilder::new(dcm);
+    self.add_comm_managers(&mut dm_builder);
+    let settings = &self.device_manager;
+    if let Some(timeout) = settings.scanning_start_timeout_ms {
+      dm_builder.scanning_start_timeout(Duration::from_millis(timeout));
+    }
+    if let Some(detect) = settings.detect_system_resume {
+      dm_builder.detect_system_resume(detect);
+    }
+    if let Some(restart) = settings.restart_scanning_on_resume {
+      dm_builder.restart_scanning_on_resume(restart);
+    }
+    if let Some(replay) = settings.replay_state_on_reconnect {
+      dm_builder.replay_state_on_reconnect(replay);
+    }
+    if let Some(interval) = settings.scanning_progress_interval_ms {
+      dm_builder.scanning_progress_interval(Duration::from_millis(interval));
+    }
+    if let Some(interval) = settings.intensity_metering_interval_ms {
+      dm_builder.intensity_metering_interval(Duration::from_millis(interval));
+    }
+    if let Some(attempts) = settings.connection_attempts {
+      dm_builder.connection_attempts(attempts);
+    }
+    if let Some(size) = settings.command_audit_size {
+      dm_builder.command_audit_size(size);
+    }
+    if let Some(restarts) = settings.max_comm_manager_restarts {
+      dm_builder.max_comm_manager_restarts(restarts);
+    }
+    if let Some(energy_budget) = &self.energy_budget {
+      dm_builder.energy_budget(energy_budget.into());
+    }
+
+    let mut server_builder = ButtplugServerBuilder::new(dm_builder.finish()?);
+    server_builder.name(&self.name);
+    if let Some(ping_time) = self.max_ping_time_ms {
+      server_builder.max_ping_time(ping_time);
+    }
+    if let Some(directory) = &self.storage_directory {
+      server_builder.storage(Arc::new(JsonFileServerStorage::new(directory)));
+    }
+    Ok(server_builder)
+  }
+
+  #[allow(unused_variables)]
+  fn add_comm_managers(&self, dm_builder: &mut ServerDeviceManagerBuilder) {
+    let managers = &self.comm_managers;
+    let unavailable = |name: &str| {
+      warn!(
+        "{} communication manager is turned on, but isn't available in this build, skipping.",
+        name
+      )
+    };
+    if managers.bluetooth {
+      #[cfg(all(
+        feature = "btleplug-manager",
+        any(
+          target_os = "windows",
+          target_os = "macos",
+          target_os = "linux",
+          target_os = "ios",
+          target_os = "android"
+        )
+      ))]
+      {
+        use crate::server::device::hardware::communication::btleplug::BtlePlugCommunicationManagerBuilder;
+        dm_builder.comm_manager(BtlePlugCommunicationManagerBuilder::default());
+      }
+      #[cfg(not(all(
+        feature = "btleplug-manager",
+        any(
+          target_os = "windows",
+          target_os = "macos",
+          target_os = "linux",
+          target_os = "ios",
+          target_os = "android"
+        )
+      )))]
+      unavailable("Bluetooth");
+    }
+    if managers.serial {
+      #[cfg(all(
+        feature = "serial-manager",
+        any(target_os = "windows", target_os = "macos", target_os = "linux")
+      ))]
+      {
+        use crate::server::device::hardware::communication::serialport::SerialPortCommunicationManagerBuilder;
+        dm_builder.comm_manager(SerialPortCommunicationManagerBuilder::default());
+      }
+      #[cfg(not(all(
+        feature = "serial-manager",
+        any(target_os = "windows", target_os = "macos", target_os = "linux")
+      )))]
+      unavailable("Serial");
+    }
+    if managers.hid {
+      #[cfg(all(
+        feature = "hid-manager",
+        any(target_os = "windows", target_os = "macos", target_os = "linux")
+      ))]
+      {
+        use crate::server::device::hardware::communication::hid::HidCommunicationManagerBuilder;
+        dm_builder.comm_manager(HidCommunicationManagerBuilder::default());
+      }
+      #[cfg(not(all(
+        feature = "hid-manager",
+        any(target_os = "windows", target_os = "macos", target_os = "linux")
+      )))]
+      unavailable("HID");
+    }
+    if managers.lovense_dongle {
+      #[cfg(all(
+        feature = "lovense-dongle-manager",
+        any(target_os = "windows", target_os = "macos", target_os = "linux")
+      ))]
+      {
+        use crate::server::device::hardware::communication::lovense_dongle::{
+          LovenseHIDDongleCommunicationManagerBuilder,
+          LovenseSerialDongleCommunicationManagerBuilder,
+        };
+        dm_builder.comm_manager(LovenseHIDDongleCommunicationManagerBuilder::default());
+        dm_builder.comm_manager(LovenseSerialDongleCommunicationManagerBuilder::default());
+      }
+      #[cfg(not(all(
+        feature = "lovense-dongle-manager",
+        any(target_os = "windows", target_os = "macos", target_os = "linux")
+      )))]
+      unavailable("Lovense dongle");
+    }
+    if managers.xinput {
+      #[cfg(all(feature = "xinput-manager", target_os = "windows"))]
+      {
+        use crate::server::device::hardware::communication::xinput::XInputDeviceCommunicationManagerBuilder;
+        dm_builder.comm_manager(XInputDeviceCommunicationManagerBuilder::default());
+      }
+      // XInput is on by default, so don't warn about it everywhere but windows.
+      #[cfg(all(not(feature = "xinput-manager"), target_os = "windows"))]
+      unavailable("XInput");
+    }
+    if managers.gaming_input {
+      #[cfg(all(feature = "gaming-input-manager", target_os = "windows"))]
+      {
+        use crate::server::device::hardware::communication::gaming_input::GamingInputCommunicationManagerBuilder;
+        dm_builder.comm_manager(GamingInputCommunicationManagerBuilder::default());
+      }
+      #[cfg(not(all(feature = "gaming-input-manager", target_os = "windows")))]
+      unavailable("Windows.Gaming.Input");
+    }
+    if managers.playstation {
+      #[cfg(all(
+        feature = "playstation-manager",
+        any(target_os = "windows", target_os = "macos", target_os = "linux")
+      ))]
+      {
+        use crate::server::device::hardware::communication::playstation::PlayStationCommunicationManagerBuilder;
+        dm_builder.comm_manager(PlayStationCommunicationManagerBuilder::default());
+      }
+      #[cfg(not(all(
+        feature = "playstation-manager",
+        any(target_os = "windows", target_os = "macos", target_os = "linux")
+      )))]
+      unavailable("PlayStation controller");
+    }
+    if managers.lovense_connect {
+      #[cfg(feature = "lovense-connect-service-manager")]
+      {
+        use crate::server::device::hardware::communication::lovense_connect_service::LovenseConnectServiceCommunicationManagerBuilder;
+        dm_builder.comm_manager(LovenseConnectServiceCommunicationManagerBuilder::default());
+      }
+      #[cfg(not(feature = "lovense-connect-service-manager"))]
+      unavailable("Lovense Connect");
+    }
+    if let Some(settings) = &managers.websocket_server {
+      #[cfg(feature = "websocket-server-manager")]
+      {
+        use crate::server::device::hardware::communication::websocket_server::websocket_server_comm_manager::WebsocketServerDeviceCommunicationManagerBuilder;
+        let mut builder = WebsocketServerDeviceCommunicationManagerBuilder::default()
+          .listen_on_all_interfaces(settings.listen_on_all_interfaces);
+        if let Some(port) = settings.port {
+          builder = builder.server_port(port);
+        }
+        dm_builder.comm_manager(builder);
+      }
+      #[cfg(not(feature = "websocket-server-manager"))]
+      unavailable("Websocket server");
+    }
+    if let Some(settings) = &managers.osc {
+      #[cfg(feature = "osc-manager")]
+      {
+        use crate::server::device::hardware::communication::osc::OscCommunicationManagerBuilder;
+        let mut builder = OscCommunicationManagerBuilder::default()
+          .listen_on_all_interfaces(settings.network.listen_on_all_interfaces);
+        if let Some(port) = settings.network.port {
+          builder = builder.server_port(port);
+        }
+        if let Some(prefix) = &settings.address_prefix {
+          builder = builder.address_prefix(prefix);
+        }
+        dm_builder.comm_manager(builder);
+      }
+      #[cfg(not(feature = "osc-manager"))]
+      unavailable("OSC");
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn test_server_config_defaults() {
+    assert_eq!(
+      ButtplugServerConfig::from_json("{}").unwrap(),
+      ButtplugServerConfig::default()
+    );
+  }
+
+  #[test]
+  fn test_server_config_from_json() {
+    let config = ButtplugServerConfig::from_json(
+      r#"{
+        "name": "Config Test",
+        "max-ping-time-ms": 1000,
+        "comm-managers": {
+          "bluetooth": false,
+          "osc": { "port": 9000, "address-prefix": "/trackers/" }
+        },
+        "device-manager": { "connection-attempts": 3 },
+        "energy-budget": {
+          "intensity-threshold": 0.8,
+          "budget-ms": 600000,
+          "action": { "reduce": { "max-intensity": 0.5, "duration-ms": 60000 } }
+        }
+      }"#,
+    )
+    .unwrap();
+    assert_eq!(config.name, "Config Test");
+    assert_eq!(config.max_ping_time_ms, Some(1000));
+    assert!(!config.comm_managers.bluetooth);
+    // Left out, so still on.
+    assert!(config.comm_managers.serial);
+    let osc = config.comm_managers.osc.as_ref().unwrap();
+    assert_eq!(osc.network.port, Some(9000));
+    assert_eq!(osc.address_prefix.as_deref(), Some("/trackers/"));
+    assert_eq!(config.device_manager.connection_attempts, Some(3));
+    assert_eq!(config.device_manager.command_audit_size, None);
+    let policy: EnergyBudgetPolicy = config.energy_budget.as_ref().unwrap().into();
+    assert_eq!(
+      policy,
+      EnergyBudgetPolicy::new(
+        0.8,
+        Duration::from_secs(600),
+        EnergyBudgetAction::Reduce {
+          max_intensity: 0.5,
+          duration: Duration::from_secs(60)
+        }
+      )
+    );
+    // Typos are errors rather than silently ignored settings.
+    assert!(ButtplugServerConfig::from_json(r#"{ "max-ping-tme-ms": 1000 }"#).is_err());
+  }
+
+  #[tokio::test]
+  async fn test_server_config_builds_server() {
+    let config = ButtplugServerConfig::from_json(
+      r#"{
+        "name": "Config Test",
+        "comm-managers": {
+          "bluetooth": false,
+          "serial": false,
+          "hid": false,
+          "lovense-dongle": false,
+          "xinput": false,
+          "gaming-input": false,
+          "playstation": false,
+          "lovense-connect": false
+        },
+        "device-config": { "exclude-protocols": ["lovense"] }
+      }"#,
+    )
+    .unwrap();
+    let server = config.server_builder().unwrap().finish().unwrap();
+    assert!(server.device_manager().comm_manager_status().is_empty());
+  }
+}
@@ -78,17 +78,21 @@ impl ButtplugServerDowngradeWrapper {
 
   pub fn client_version_event_stream(&self) -> impl Stream<Item = ButtplugServerMessageVariant> {
     let spec_version = self.spec_version.clone();
-    self.server.event_stream().map(move |m| {
+    self.server.event_stream().filter_map(move |m| {
       let converter = ButtplugServerMessageConverter::new(None);
       // If we get an event and don't have a spec version yet, just throw out the latest.
-      converter
-        .convert_outgoing(
-          &m,
-          spec_version
-            .get()
-            .unwrap_or(&ButtplugMessageSpecVersion::Version4),
-        )
-        .unwrap()
+      let version = spec_version
+        .get()
+        .unwrap_or(&ButtplugMessageSpecVersion::Version4);
+      // Events that only exist in newer specs (ScanningProgress, etc...) have nothing to convert
+      // to, so older clients just never see them.
+      match converter.convert_outgoing(&m, version) {
+        Ok(msg) => Some(msg),
+        Err(err) => {
+          debug!("Skipping event that can't be sent to a {version:?} client: {err:?}");
+          None
+        }
+      }
     })
   }
 
@@ -190,7 +194,7 @@ mod test {
     core::message::{
       ButtplugClientMessageV4,
       ButtplugClientMessageVariant,
-      RequestServerInfoV1,
+      RequestServerInfoV4,
       BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION,
     },
     server::{ButtplugServerBuilder, ButtplugServerDowngradeWrapper},
@@ -203,9 +207,10 @@ mod test {
       ButtplugServerDowngradeWrapper::new(ButtplugServerBuilder::default().finish().unwrap());
     assert!(wrapper
       .parse_message(ButtplugClientMessageVariant::V4(
-        ButtplugClientMessageV4::RequestServerInfo(RequestServerInfoV1::new(
+        ButtplugClientMessageV4::RequestServerInfo(RequestServerInfoV4::new(
           "TestClient",
-          BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION
+          BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION,
+          false
         ))
       ))
       .await
@@ -219,9 +224,10 @@ mod test {
       ButtplugServerDowngradeWrapper::new(ButtplugServerBuilder::default().finish().unwrap());
     let result = wrapper
       .parse_message(ButtplugClientMessageVariant::V4(
-        ButtplugClientMessageV4::RequestServerInfo(RequestServerInfoV1::new(
+        ButtplugClientMessageV4::RequestServerInfo(RequestServerInfoV4::new(
           "TestClient",
           BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION,
+          false,
         )),
       ))
       .await;
@@ -46,6 +46,7 @@ use crate::core::{
     FeatureType,
     LinearCmdV1,
     LinearCmdV4,
+    PatternTrackV4,
     RSSILevelCmdV2,
     RSSILevelReadingV2,
     RotateCmdV1,
@@ -62,6 +63,7 @@ use crate::core::{
     SensorType,
     SensorUnsubscribeCmdV3,
     SensorUnsubscribeCmdV4,
+    StartPatternSessionV4,
     VectorSubcommandV4,
     VibrateCmdV1,
     VorzeA10CycloneCmdV0,
@@ -85,7 +87,7 @@ impl TryFrom<ButtplugClientMessageV3> for ButtplugClientMessageV4 {
     match value {
       ButtplugClientMessageV3::Ping(m) => Ok(ButtplugClientMessageV4::Ping(m.clone())),
       ButtplugClientMessageV3::RequestServerInfo(m) => {
-        Ok(ButtplugClientMessageV4::RequestServerInfo(m.clone()))
+        Ok(ButtplugClientMessageV4::RequestServerInfo(m.clone().into()))
       }
       ButtplugClientMessageV3::StartScanning(m) => {
         Ok(ButtplugClientMessageV4::StartScanning(m.clone()))
@@ -94,7 +96,7 @@ impl TryFrom<ButtplugClientMessageV3> for ButtplugClientMessageV4 {
         Ok(ButtplugClientMessageV4::StopScanning(m.clone()))
       }
       ButtplugClientMessageV3::RequestDeviceList(m) => {
-        Ok(ButtplugClientMessageV4::RequestDeviceList(m.clone()))
+        Ok(ButtplugClientMessageV4::RequestDeviceList(m.clone().into()))
       }
       ButtplugClientMessageV3::StopAllDevices(m) => {
         Ok(ButtplugClientMessageV4::StopAllDevices(m.clone()))
@@ -110,6 +112,13 @@ impl TryFrom<ButtplugClientMessageV3> for ButtplugClientMessageV4 {
       ButtplugClientMessageV3::RawUnsubscribeCmd(m) => {
         Ok(ButtplugClientMessageV4::RawUnsubscribeCmd(m))
       }
+      ButtplugClientMessageV3::RequestDeviceInfo(m) => {
+        Ok(ButtplugClientMessageV4::RequestDeviceInfo(m))
+      }
+      ButtplugClientMessageV3::IdentifyDevice(m) => Ok(ButtplugClientMessageV4::IdentifyDevice(m)),
+      ButtplugClientMessageV3::StopPatternSession(m) => {
+        Ok(ButtplugClientMessageV4::StopPatternSession(m))
+      }
       _ => Err(ButtplugMessageError::MessageConversionError(format!(
         "Cannot convert message {:?} to V4 message spec while lacking state.",
         value
@@ -269,13 +278,25 @@ impl TryFrom<ButtplugServerMessageV4> for ButtplugServerMessageV3 {
       ButtplugServerMessageV4::Ok(m) => Ok(ButtplugServerMessageV3::Ok(m)),
       ButtplugServerMessageV4::Error(m) => Ok(ButtplugServerMessageV3::Error(m)),
       ButtplugServerMessageV4::ServerInfo(m) => Ok(ButtplugServerMessageV3::ServerInfo(m)),
-      ButtplugServerMessageV4::DeviceRemoved(m) => Ok(ButtplugServerMessageV3::DeviceRemoved(m)),
+      ButtplugServerMessageV4::DeviceRemoved(m) => {
+        Ok(ButtplugServerMessageV3::DeviceRemoved(m.into()))
+      }
       ButtplugServerMessageV4::ScanningFinished(m) => {
         Ok(ButtplugServerMessageV3::ScanningFinished(m))
       }
       ButtplugServerMessageV4::RawReading(m) => Ok(ButtplugServerMessageV3::RawReading(m)),
       ButtplugServerMessageV4::DeviceList(m) => Ok(ButtplugServerMessageV3::DeviceList(m.into())),
       ButtplugServerMessageV4::DeviceAdded(m) => Ok(ButtplugServerMessageV3::DeviceAdded(m.into())),
+      ButtplugServerMessageV4::DeviceInfo(m) => Ok(ButtplugServerMessageV3::DeviceInfo(m)),
+      ButtplugServerMessageV4::PatternSessionStarted(m) => {
+        Ok(ButtplugServerMessageV3::PatternSessionStarted(m))
+      }
+      ButtplugServerMessageV4::PatternSessionProgress(m) => {
+        Ok(ButtplugServerMessageV3::PatternSessionProgress(m))
+      }
+      ButtplugServerMessageV4::PatternSessionEnded(m) => {
+        Ok(ButtplugServerMessageV3::PatternSessionEnded(m))
+      }
       // All other messages (SensorReading) requires device manager context.
       _ => Err(ButtplugMessageError::MessageConversionError(format!(
         "Cannot convert message {:?} to current message spec while lacking state.",
@@ -301,6 +322,17 @@ impl From<ButtplugServerMessageV3> for ButtplugServerMessageV2 {
           "SensorReading cannot be converted to Buttplug Message Spec V2".to_owned(),
         )),
       )),
+      ButtplugServerMessageV3::DeviceInfo(_)
+      | ButtplugServerMessageV3::PatternSessionStarted(_)
+      | ButtplugServerMessageV3::PatternSessionProgress(_)
+      | ButtplugServerMessageV3::PatternSessionEnded(_) => {
+        ButtplugServerMessageV2::Error(ErrorV0::from(ButtplugError::from(
+          ButtplugMessageError::MessageConversionError(format!(
+            "{:?} cannot be converted to Buttplug Message Spec V2",
+            value
+          )),
+        )))
+      }
     }
   }
 }
@@ -471,6 +503,9 @@ impl ButtplugServerMessageConverter {
       ButtplugClientMessageV3::SensorUnsubscribeCmd(m) => {
         self.convert_sensorunsubscribev3_to_sensorunsubcribe4(m, device_manager)
       }
+      ButtplugClientMessageV3::StartPatternSession(m) => {
+        self.convert_startpatternsessionv3_to_startpatternsessionv4(m, device_manager)
+      }
       _ => msg_v3
         .clone()
         .try_into()
@@ -673,6 +708,54 @@ impl ButtplugServerMessageConverter {
     Ok(ScalarCmdV4::new(message.device_index(), scalars_v4).into())
   }
 
+  fn convert_startpatternsessionv3_to_startpatternsessionv4(
+    &self,
+    message: &StartPatternSessionV4,
+    device_manager: &ServerDeviceManager,
+  ) -> Result<ButtplugClientMessageV4, ButtplugError> {
+    // v3 clients address actuators the way ScalarCmd does, by their position among the device's
+    // scalar features.
+    let mut tracks = vec![];
+    for track in message.tracks() {
+      let device = device_manager
+        .devices()
+        .get(&track.device_index())
+        .ok_or(ButtplugDeviceError::DeviceNotAvailable(track.device_index()))?;
+      let scalar_features: Vec<usize> = device
+        .definition()
+        .features()
+        .iter()
+        .enumerate()
+        .filter(|(_, x)| {
+          x.actuator().as_ref().is_some_and(|y| {
+            y.messages()
+              .contains(&message::ButtplugActuatorFeatureMessageType::ScalarCmd)
+          })
+        })
+        .map(|(index, _)| index)
+        .collect();
+      let feature_index = scalar_features
+        .get(track.feature_index() as usize)
+        .ok_or(ButtplugDeviceError::DeviceFeatureIndexError(
+          scalar_features.len() as u32,
+          track.feature_index(),
+        ))?;
+      tracks.push(PatternTrackV4::new(
+        track.device_index(),
+        *feature_index as u32,
+        track.steps().clone(),
+      ));
+    }
+    let mut converted = StartPatternSessionV4::new(
+      tracks,
+      message.time_limit(),
+      message.repeat(),
+      message.progress_interval(),
+    );
+    converted.set_id(message.id());
+    Ok(converted.into())
+  }
+
   fn convert_rotatecmdv1_to_scalarcmdv4(
     &self,
     message: &RotateCmdV1,
@@ -0,0 +1,165 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2024 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Message counters, for diagnostics pages and the like.
+//!
+//! Everything here is a relaxed atomic bump per message, so it stays on all the time. Counts cover
+//! messages that come in through [ButtplugServer::parse_message](super::ButtplugServer), so
+//! commands sent from device links, pattern sessions or directly to the device manager aren't
+//! included.
+
+use dashmap::DashMap;
+use getset::CopyGetters;
+use instant::Instant;
+use std::{
+  collections::HashMap,
+  sync::{
+    atomic::{AtomicU64, Ordering},
+    RwLock,
+  },
+  time::Duration,
+};
+
+/// Counts for a set of messages.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, CopyGetters)]
+#[getset(get_copy = "pub")]
+pub struct MessageStatistics {
+  /// Number of messages handled, including ones that failed.
+  messages_processed: u64,
+  /// Number of messages that got an error back.
+  errors: u64,
+  /// Average time from receiving a message to having its reply ready.
+  average_latency: Duration,
+}
+
+/// Snapshot of server statistics, from [ButtplugServer::statistics](super::ButtplugServer).
+#[derive(Debug, Clone, CopyGetters)]
+pub struct ServerStatistics {
+  /// Time since the server was built.
+  #[getset(get_copy = "pub")]
+  uptime: Duration,
+  /// Time since the current client finished its handshake, or None if no client is connected.
+  #[getset(get_copy = "pub")]
+  session_uptime: Option<Duration>,
+  /// Messages handled since the server was built.
+  #[getset(get_copy = "pub")]
+  server: MessageStatistics,
+  /// Messages handled for the current (or last) client.
+  #[getset(get_copy = "pub")]
+  session: MessageStatistics,
+  /// Number of device command messages handled, keyed by device index. Covers the whole server
+  /// lifetime, so devices that have since disconnected are still listed.
+  device_command_counts: HashMap<u32, u64>,
+}
+
+impl ServerStatistics {
+  pub fn device_command_counts(&self) -> &HashMap<u32, u64> {
+    &self.device_command_counts
+  }
+}
+
+#[derive(Default)]
+struct MessageCounters {
+  processed: AtomicU64,
+  errors: AtomicU64,
+  total_latency_us: AtomicU64,
+}
+
+impl MessageCounters {
+  fn record(&self, latency: Duration, is_error: bool) {
+    self.processed.fetch_add(1, Ordering::Relaxed);
+    if is_error {
+      self.errors.fetch_add(1, Ordering::Relaxed);
+    }
+    self
+      .total_latency_us
+      .fetch_add(latency.as_micros() as u64, Ordering::Relaxed);
+  }
+
+  fn reset(&self) {
+    self.processed.store(0, Ordering::Relaxed);
+    self.errors.store(0, Ordering::Relaxed);
+    self.total_latency_us.store(0, Ordering::Relaxed);
+  }
+
+  fn snapshot(&self) -> MessageStatistics {
+    let messages_processed = self.processed.load(Ordering::Relaxed);
+    MessageStatistics {
+      messages_processed,
+      errors: self.errors.load(Ordering::Relaxed),
+      average_latency: Duration::from_micros(
+        self.total_latency_us.load(Ordering::Relaxed) / messages_processed.max(1),
+      ),
+    }
+  }
+}
+
+pub(super) struct StatisticsRegistry {
+  started: Instant,
+  session_started: RwLock<Option<Instant>>,
+  server: MessageCounters,
+  session: MessageCounters,
+  device_commands: DashMap<u32, u64>,
+}
+
+impl Default for StatisticsRegistry {
+  fn default() -> Self {
+    Self {
+      started: Instant::now(),
+      session_started: RwLock::new(None),
+      server: MessageCounters::default(),
+      session: MessageCounters::default(),
+      device_commands: DashMap::new(),
+    }
+  }
+}
+
+impl StatisticsRegistry {
+  /// Start counting for a new client. The last session's counts are kept until this is called, so
+  /// they can still be looked at after a disconnect.
+  pub(super) fn start_session(&self) {
+    self.session.reset();
+    *self
+      .session_started
+      .write()
+      .expect("Statistics lock is never held across a panic") = Some(Instant::now());
+  }
+
+  pub(super) fn end_session(&self) {
+    *self
+      .session_started
+      .write()
+      .expect("Statistics lock is never held across a panic") = None;
+  }
+
+  /// Count a handled message. `device_index` is set for device commands.
+  pub(super) fn record(&self, latency: Duration, is_error: bool, device_index: Option<u32>) {
+    self.server.record(latency, is_error);
+    self.session.record(latency, is_error);
+    if let Some(index) = device_index {
+      *self.device_commands.entry(index).or_default() += 1;
+    }
+  }
+
+  pub(super) fn snapshot(&self) -> ServerStatistics {
+    ServerStatistics {
+      uptime: self.started.elapsed(),
+      session_uptime: self
+        .session_started
+        .read()
+        .expect("Statistics lock is never held across a panic")
+        .map(|started| started.elapsed()),
+      server: self.server.snapshot(),
+      session: self.session.snapshot(),
+      device_command_counts: self
+        .device_commands
+        .iter()
+        .map(|entry| (*entry.key(), *entry.value()))
+        .collect(),
+    }
+  }
+}
@@ -0,0 +1,111 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2024 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Storage for server state that should outlive the server.
+//!
+//! Everything the server learns about devices over time (stable indexes, display names, sensor
+//! calibrations, limits and so on) lives in the user device configuration. When a [ServerStorage] is
+//! set on the [ButtplugServerBuilder](super::ButtplugServerBuilder), that configuration is loaded from
+//! it when the server is built, and stored back to it whenever it changes.
+//!
+//! [JsonFileServerStorage] keeps each entry in a JSON file in a directory, and covers most apps.
+//! Anything else (sled, sqlite, app specific settings stores...) can be plugged in by implementing
+//! [ServerStorage].
+
+use std::{
+  fs,
+  io,
+  path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+/// Key the user device configuration is stored under.
+pub const USER_DEVICE_CONFIG_STORAGE_KEY: &str = "user-device-config";
+
+/// Errors from [ServerStorage] implementations.
+#[derive(Error, Debug)]
+pub enum ServerStorageError {
+  /// The entry could not be read from storage.
+  #[error("Could not load {0} from storage: {1}")]
+  LoadFailed(String, String),
+  /// The entry could not be written to storage.
+  #[error("Could not store {0} to storage: {1}")]
+  StoreFailed(String, String),
+}
+
+/// A place for the server to keep state between runs.
+///
+/// Entries are strings of JSON, keyed by name. Calls are made from async tasks, so implementations
+/// should be quick about it, but don't need to be async themselves.
+pub trait ServerStorage: Send + Sync {
+  /// Load the entry stored under `key`, or None if nothing has been stored there yet.
+  fn load(&self, key: &str) -> Result<Option<String>, ServerStorageError>;
+  /// Store `value` under `key`, replacing whatever was stored there.
+  fn store(&self, key: &str, value: &str) -> Result<(), ServerStorageError>;
+}
+
+/// Default [ServerStorage], which keeps each entry as a `<key>.json` file in a directory.
+#[derive(Debug, Clone)]
+pub struct JsonFileServerStorage {
+  directory: PathBuf,
+}
+
+impl JsonFileServerStorage {
+  /// Store entries in `directory`, which is created on the first store if it doesn't exist.
+  pub fn new(directory: impl AsRef<Path>) -> Self {
+    Self {
+      directory: directory.as_ref().to_owned(),
+    }
+  }
+
+  fn path(&self, key: &str) -> PathBuf {
+    self.directory.join(format!("{}.json", key))
+  }
+}
+
+impl ServerStorage for JsonFileServerStorage {
+  fn load(&self, key: &str) -> Result<Option<String>, ServerStorageError> {
+    match fs::read_to_string(self.path(key)) {
+      Ok(value) => Ok(Some(value)),
+      Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+      Err(e) => Err(ServerStorageError::LoadFailed(
+        key.to_owned(),
+        e.to_string(),
+      )),
+    }
+  }
+
+  fn store(&self, key: &str, value: &str) -> Result<(), ServerStorageError> {
+    let store_failed =
+      |e: io::Error| ServerStorageError::StoreFailed(key.to_owned(), e.to_string());
+    fs::create_dir_all(&self.directory).map_err(store_failed)?;
+    // Write next to the real file and move it into place, so a crash mid write can't leave a
+    // truncated file behind.
+    let path = self.path(key);
+    let temp_path = path.with_extension("json.tmp");
+    fs::write(&temp_path, value).map_err(store_failed)?;
+    fs::rename(&temp_path, &path).map_err(store_failed)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn test_json_file_storage_roundtrip() {
+    let directory =
+      std::env::temp_dir().join(format!("buttplug-storage-test-{}", std::process::id()));
+    let storage = JsonFileServerStorage::new(&directory);
+    assert_eq!(storage.load("test").unwrap(), None);
+    storage.store("test", r#"{"a":1}"#).unwrap();
+    storage.store("test", r#"{"a":2}"#).unwrap();
+    assert_eq!(storage.load("test").unwrap().as_deref(), Some(r#"{"a":2}"#));
+    assert_eq!(storage.load("other").unwrap(), None);
+    fs::remove_dir_all(directory).unwrap();
+  }
+}
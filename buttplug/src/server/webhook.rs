@@ -0,0 +1,426 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2024 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Event webhooks, for driving things like stream overlays from server activity.
+//!
+//! A [WebhookEmitter] watches a [ServerDeviceManager] and sends a payload to an endpoint whenever a
+//! device connects or disconnects, or an actuator's intensity changes. `http://` and `https://`
+//! endpoints get a POST per event. `ws://` and `wss://` endpoints get a text message per event, over
+//! a connection that is opened on the first event and reopened after failures.
+//!
+//! Delivery is best effort. Events that can't be sent are logged and dropped, and if the endpoint
+//! is slow enough for the event queue to back up, the oldest events are skipped.
+//!
+//! Payloads are built from templates, with `{{placeholder}}` values filled in per event:
+//!
+//! - `{{event}}`: Event type, `DeviceAdded`, `DeviceRemoved` or `IntensityChanged`
+//! - `{{device_index}}`: Index of the device
+//! - `{{device_name}}`: Display name of the device if the user set one, otherwise the device name.
+//!   Escaped for use inside a JSON string.
+//! - `{{timestamp}}`: Milliseconds since the unix epoch
+//! - `{{feature_index}}`, `{{actuator_type}}`, `{{intensity}}`: Actuator that changed and its new
+//!   intensity, from 0.0 to 1.0. Empty for device events.
+//!
+//! Rotation speeds count as intensity. Linear commands are positions rather than intensities, and
+//! don't produce events.
+
+use super::{
+  device::{
+    DeviceManagerEvent,
+    DeviceManagerEventFilter,
+    DeviceManagerEventKind,
+    ServerDeviceManager,
+  },
+  ButtplugServerError,
+};
+use crate::{
+  core::message::{
+    ButtplugDeviceCommandMessageUnion,
+    ButtplugDeviceMessage,
+    ButtplugServerMessageV4,
+  },
+  util::async_manager,
+};
+use futures::{SinkExt, StreamExt};
+use std::{
+  collections::HashMap,
+  sync::{Arc, Weak},
+  time::{SystemTime, UNIX_EPOCH},
+};
+use tokio_tungstenite::{tungstenite::Message, MaybeTlsStream, WebSocketStream};
+use tokio_util::sync::CancellationToken;
+
+const DEFAULT_DEVICE_TEMPLATE: &str = r#"{"event":"{{event}}","device_index":{{device_index}},"device_name":"{{device_name}}","timestamp":{{timestamp}}}"#;
+const DEFAULT_INTENSITY_TEMPLATE: &str = r#"{"event":"{{event}}","device_index":{{device_index}},"device_name":"{{device_name}}","feature_index":{{feature_index}},"actuator_type":"{{actuator_type}}","intensity":{{intensity}},"timestamp":{{timestamp}}}"#;
+
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WebhookEventType {
+  DeviceAdded,
+  DeviceRemoved,
+  IntensityChanged,
+}
+
+/// A single event, with everything its template might need.
+#[derive(Debug, Clone, PartialEq)]
+struct WebhookEvent {
+  event_type: WebhookEventType,
+  device_index: u32,
+  device_name: String,
+  actuator: Option<(u32, String, f64)>,
+  timestamp: u128,
+}
+
+impl WebhookEvent {
+  fn render(&self, template: &str) -> String {
+    // serde_json gives us the escaped string with its quotes, the template supplies its own.
+    let escaped_name = serde_json::to_string(&self.device_name).expect("Strings always serialize");
+    let (feature_index, actuator_type, intensity) = match &self.actuator {
+      Some((feature_index, actuator_type, intensity)) => (
+        feature_index.to_string(),
+        actuator_type.clone(),
+        intensity.to_string(),
+      ),
+      None => (String::new(), String::new(), String::new()),
+    };
+    [
+      ("event", self.event_type.to_string()),
+      ("device_index", self.device_index.to_string()),
+      (
+        "device_name",
+        escaped_name[1..escaped_name.len() - 1].to_owned(),
+      ),
+      ("feature_index", feature_index),
+      ("actuator_type", actuator_type),
+      ("intensity", intensity),
+      ("timestamp", self.timestamp.to_string()),
+    ]
+    .iter()
+    .fold(template.to_owned(), |payload, (key, value)| {
+      payload.replace(&format!("{{{{{}}}}}", key), value)
+    })
+  }
+}
+
+/// Turns server activity into [WebhookEvent]s, keeping track of device names and the last
+/// intensity sent for each actuator so repeated commands don't flood the endpoint.
+struct WebhookEventTracker {
+  device_manager: Weak<ServerDeviceManager>,
+  device_names: HashMap<u32, String>,
+  intensities: HashMap<(u32, u32), f64>,
+}
+
+impl WebhookEventTracker {
+  fn new(device_manager: Weak<ServerDeviceManager>) -> Self {
+    Self {
+      device_manager,
+      device_names: HashMap::new(),
+      intensities: HashMap::new(),
+    }
+  }
+
+  fn device_name(&mut self, device_index: u32) -> String {
+    if let Some(name) = self.device_names.get(&device_index) {
+      return name.clone();
+    }
+    // Devices that connected before the emitter started have to be looked up.
+    let name = self
+      .device_manager
+      .upgrade()
+      .and_then(|manager| manager.device_info(device_index))
+      .map(|info| info.display_name().clone().unwrap_or(info.name().clone()))
+      .unwrap_or_default();
+    self.device_names.insert(device_index, name.clone());
+    name
+  }
+
+  fn server_event(&mut self, message: ButtplugServerMessageV4) -> Vec<WebhookEvent> {
+    match message {
+      ButtplugServerMessageV4::DeviceAdded(added) => {
+        let index = added.device_index();
+        let name = added
+          .device_display_name()
+          .clone()
+          .unwrap_or(added.device_name().clone());
+        self.device_names.insert(index, name.clone());
+        self.intensities.retain(|(device, _), _| *device != index);
+        vec![event(WebhookEventType::DeviceAdded, index, name, None)]
+      }
+      ButtplugServerMessageV4::DeviceRemoved(removed) => {
+        let index = removed.device_index();
+        let name = self.device_name(index);
+        self.device_names.remove(&index);
+        self.intensities.retain(|(device, _), _| *device != index);
+        vec![event(WebhookEventType::DeviceRemoved, index, name, None)]
+      }
+      _ => vec![],
+    }
+  }
+
+  fn command(&mut self, command: ButtplugDeviceCommandMessageUnion) -> Vec<WebhookEvent> {
+    let (device_index, changes): (u32, Vec<(u32, String, f64)>) = match command {
+      ButtplugDeviceCommandMessageUnion::ScalarCmd(msg) => (
+        msg.device_index(),
+        msg
+          .scalars()
+          .iter()
+          .map(|s| (s.feature_index(), s.actuator_type().to_string(), s.scalar()))
+          .collect(),
+      ),
+      ButtplugDeviceCommandMessageUnion::RotateCmd(msg) => (
+        msg.device_index(),
+        msg
+          .rotations()
+          .iter()
+          .map(|r| (r.feature_index(), "Rotate".to_owned(), r.speed()))
+          .collect(),
+      ),
+      _ => return vec![],
+    };
+    let mut events = vec![];
+    for (feature_index, actuator_type, intensity) in changes {
+      if self
+        .intensities
+        .insert((device_index, feature_index), intensity)
+        == Some(intensity)
+      {
+        continue;
+      }
+      let name = self.device_name(device_index);
+      events.push(event(
+        WebhookEventType::IntensityChanged,
+        device_index,
+        name,
+        Some((feature_index, actuator_type, intensity)),
+      ));
+    }
+    events
+  }
+}
+
+fn event(
+  event_type: WebhookEventType,
+  device_index: u32,
+  device_name: String,
+  actuator: Option<(u32, String, f64)>,
+) -> WebhookEvent {
+  WebhookEvent {
+    event_type,
+    device_index,
+    device_name,
+    actuator,
+    timestamp: SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .map(|time| time.as_millis())
+      .unwrap_or_default(),
+  }
+}
+
+type WebhookWebSocket = WebSocketStream<MaybeTlsStream<tokio::net::TcpStream>>;
+
+enum WebhookTransport {
+  Http(reqwest::Client),
+  WebSocket(Option<Box<WebhookWebSocket>>),
+}
+
+struct WebhookSender {
+  endpoint: String,
+  content_type: String,
+  transport: WebhookTransport,
+}
+
+impl WebhookSender {
+  async fn send(&mut self, payload: String) {
+    match &mut self.transport {
+      WebhookTransport::Http(client) => {
+        match client
+          .post(&self.endpoint)
+          .header(reqwest::header::CONTENT_TYPE, &self.content_type)
+          .body(payload)
+          .send()
+          .await
+        {
+          Ok(response) if !response.status().is_success() => warn!(
+            "Webhook endpoint {} rejected event: {}",
+            self.endpoint,
+            response.status()
+          ),
+          Ok(_) => {}
+          Err(err) => warn!(
+            "Cannot send event to webhook endpoint {}: {:?}",
+            self.endpoint, err
+          ),
+        }
+      }
+      WebhookTransport::WebSocket(socket) => {
+        if socket.is_none() {
+          match tokio_tungstenite::connect_async(&self.endpoint).await {
+            Ok((stream, _)) => *socket = Some(Box::new(stream)),
+            Err(err) => {
+              warn!(
+                "Cannot connect to webhook endpoint {}: {:?}",
+                self.endpoint, err
+              );
+              return;
+            }
+          }
+        }
+        if let Some(stream) = socket {
+          if let Err(err) = stream.send(Message::Text(payload.into())).await {
+            warn!(
+              "Cannot send event to webhook endpoint {}: {:?}",
+              self.endpoint, err
+            );
+            // Try a new connection on the next event.
+            *socket = None;
+          }
+        }
+      }
+    }
+  }
+}
+
+pub struct WebhookEmitterBuilder {
+  endpoint: String,
+  templates: HashMap<WebhookEventType, String>,
+  content_type: String,
+}
+
+impl WebhookEmitterBuilder {
+  pub fn new(endpoint: &str) -> Self {
+    Self {
+      endpoint: endpoint.to_owned(),
+      templates: HashMap::new(),
+      content_type: "application/json".to_owned(),
+    }
+  }
+
+  /// Payload template for one type of event, replacing the default JSON object. See the
+  /// [module docs](self) for the placeholders available.
+  pub fn template(&mut self, event_type: WebhookEventType, template: &str) -> &mut Self {
+    self.templates.insert(event_type, template.to_owned());
+    self
+  }
+
+  /// Content type sent with HTTP requests. Defaults to `application/json`, change it along with
+  /// the templates if they don't produce JSON.
+  pub fn content_type(&mut self, content_type: &str) -> &mut Self {
+    self.content_type = content_type.to_owned();
+    self
+  }
+
+  /// Start sending events for `device_manager`. Events keep going out until the returned emitter
+  /// is dropped or the device manager shuts down.
+  pub fn finish(
+    &self,
+    device_manager: &Arc<ServerDeviceManager>,
+  ) -> Result<WebhookEmitter, ButtplugServerError> {
+    let transport = match url::Url::parse(&self.endpoint).map(|url| url.scheme().to_owned()) {
+      Ok(scheme) if scheme == "http" || scheme == "https" => {
+        WebhookTransport::Http(reqwest::Client::new())
+      }
+      Ok(scheme) if scheme == "ws" || scheme == "wss" => WebhookTransport::WebSocket(None),
+      _ => {
+        return Err(ButtplugServerError::InvalidWebhookEndpoint(
+          self.endpoint.clone(),
+        ))
+      }
+    };
+    let mut sender = WebhookSender {
+      endpoint: self.endpoint.clone(),
+      content_type: self.content_type.clone(),
+      transport,
+    };
+    let mut templates = self.templates.clone();
+    for event_type in [
+      WebhookEventType::DeviceAdded,
+      WebhookEventType::DeviceRemoved,
+    ] {
+      templates
+        .entry(event_type)
+        .or_insert(DEFAULT_DEVICE_TEMPLATE.to_owned());
+    }
+    templates
+      .entry(WebhookEventType::IntensityChanged)
+      .or_insert(DEFAULT_INTENSITY_TEMPLATE.to_owned());
+
+    let mut tracker = WebhookEventTracker::new(Arc::downgrade(device_manager));
+    // One subscription for both kinds, so commands can't overtake the DeviceAdded before them.
+    let bus_events = device_manager.event_bus().subscribe(
+      DeviceManagerEventFilter::new()
+        .kind(DeviceManagerEventKind::ServerMessage)
+        .kind(DeviceManagerEventKind::ActuatorCommand),
+    );
+    let token = device_manager.child_cancellation_token();
+    let child_token = token.child_token();
+    async_manager::spawn(async move {
+      futures::pin_mut!(bus_events);
+      loop {
+        let events = tokio::select! {
+          _ = child_token.cancelled() => break,
+          event = bus_events.next() => match event {
+            Some(DeviceManagerEvent::ServerMessage(message)) => tracker.server_event(message),
+            Some(DeviceManagerEvent::ActuatorCommand(command)) => tracker.command(command),
+            Some(_) => continue,
+            None => break,
+          },
+        };
+        for event in events {
+          sender
+            .send(event.render(&templates[&event.event_type]))
+            .await;
+        }
+      }
+      debug!("Webhook emitter for {} exiting", sender.endpoint);
+    });
+    Ok(WebhookEmitter { token })
+  }
+}
+
+/// Sends server events to a webhook endpoint, see the [module docs](self). Built with a
+/// [WebhookEmitterBuilder]. Stops sending when dropped.
+pub struct WebhookEmitter {
+  token: CancellationToken,
+}
+
+impl Drop for WebhookEmitter {
+  fn drop(&mut self) {
+    self.token.cancel();
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::core::message::{ActuatorType, ScalarCmdV4, ScalarSubcommandV4};
+
+  #[test]
+  fn test_webhook_template_rendering() {
+    let mut tracker = WebhookEventTracker::new(Weak::new());
+    tracker.device_names.insert(0, "Lush \"2\"".to_owned());
+    let command = |intensity| {
+      ButtplugDeviceCommandMessageUnion::ScalarCmd(ScalarCmdV4::new(
+        0,
+        vec![ScalarSubcommandV4::new(1, intensity, ActuatorType::Vibrate)],
+      ))
+    };
+    let events = tracker.command(command(0.5));
+    assert_eq!(events.len(), 1);
+    let payload: serde_json::Value =
+      serde_json::from_str(&events[0].render(DEFAULT_INTENSITY_TEMPLATE)).unwrap();
+    assert_eq!(payload["event"], "IntensityChanged");
+    assert_eq!(payload["device_name"], "Lush \"2\"");
+    assert_eq!(payload["feature_index"], 1);
+    assert_eq!(payload["actuator_type"], "Vibrate");
+    assert_eq!(payload["intensity"], 0.5);
+    // Repeating the same intensity isn't a change.
+    assert!(tracker.command(command(0.5)).is_empty());
+    assert_eq!(
+      tracker.command(command(0.0))[0].render("{{device_index}}:{{intensity}} {{unknown}}"),
+      "0:0 {{unknown}}"
+    );
+  }
+}
@@ -20,3 +20,33 @@ cfg_if::cfg_if! {
     std::compile_error!("Please choose a runtime feature: tokio-runtime, wasm-bindgen-runtime, dummy-runtime");
   }
 }
+
+use futures::{future::Future, FutureExt};
+use std::{any::Any, panic::AssertUnwindSafe};
+
+/// Spawn a task, calling `on_panic` with the panic message if it panics. Without this, a panic
+/// only ends the task, and whatever was depending on it (a comm manager's scanning loop, for
+/// instance) stops working without anyone being told.
+// The dummy runtime's spawn returns a result, which it's fine to drop here.
+#[allow(unused_must_use)]
+pub fn spawn_catching_panics<Fut, F>(future: Fut, on_panic: F)
+where
+  Fut: Future<Output = ()> + Send + 'static,
+  F: FnOnce(String) + Send + 'static,
+{
+  spawn(async move {
+    if let Err(payload) = AssertUnwindSafe(future).catch_unwind().await {
+      on_panic(panic_message(payload.as_ref()));
+    }
+  });
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+  if let Some(message) = payload.downcast_ref::<&str>() {
+    message.to_string()
+  } else if let Some(message) = payload.downcast_ref::<String>() {
+    message.clone()
+  } else {
+    "Task panicked with a non-string payload".to_owned()
+  }
+}
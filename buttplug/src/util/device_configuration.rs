@@ -23,8 +23,9 @@ use crate::{
 };
 use dashmap::DashMap;
 use getset::{CopyGetters, Getters, MutGetters, Setters};
+use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, fmt::Display};
+use std::{collections::HashMap, env, fmt::Display};
 
 pub static DEVICE_CONFIGURATION_JSON: &str =
   include_str!("../../buttplug-device-config/build-config/buttplug-device-config-v3.json");
@@ -32,6 +33,18 @@ static DEVICE_CONFIGURATION_JSON_SCHEMA: &str = include_str!(
   "../../buttplug-device-config/device-config-v3/buttplug-device-config-schema-v3.json"
 );
 
+// Validating and parsing the internal configuration is one of the slower parts of bringing up a
+// server, and the result never changes, so we only do it once per process. Apps that spin up
+// multiple embedded servers get a clone of the cached builder instead of reparsing every time.
+// Version checks still run against the cached config on every load, since whether to skip them is
+// up to each caller.
+static INTERNAL_BASE_CONFIG: Lazy<BaseConfigFile> = Lazy::new(|| {
+  parse_protocol_config_json::<BaseConfigFile>(DEVICE_CONFIGURATION_JSON)
+    .expect("If this fails, the whole library goes with it.")
+});
+static INTERNAL_DCM_BUILDER: Lazy<DeviceConfigurationManagerBuilder> =
+  Lazy::new(|| dcm_builder_from_main_config(INTERNAL_BASE_CONFIG.clone()));
+
 /// The top level configuration for a protocol. Contains all data about devices that can use the
 /// protocol, as well as names, message attributes, etc... for different devices.
 ///
@@ -71,6 +84,19 @@ struct ProtocolAttributes {
   features: Option<Vec<DeviceFeature>>,
 }
 
+/// Parts of a protocol definition to swap in when running on a specific OS, for devices that need
+/// different endpoints or features depending on the platform's bluetooth/USB stack.
+#[derive(Deserialize, Serialize, Debug, Clone, Default, Getters, Setters, MutGetters)]
+#[getset(get = "pub", set = "pub", get_mut = "pub(crate)")]
+struct ProtocolPlatformOverride {
+  #[serde(skip_serializing_if = "Option::is_none")]
+  communication: Option<Vec<ProtocolCommunicationSpecifier>>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  defaults: Option<ProtocolAttributes>,
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  configurations: Vec<ProtocolAttributes>,
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone, Default, Getters, Setters, MutGetters)]
 #[getset(get = "pub", set = "pub", get_mut = "pub(crate)")]
 struct ProtocolDefinition {
@@ -80,6 +106,39 @@ struct ProtocolDefinition {
   pub defaults: Option<ProtocolAttributes>,
   #[serde(default)]
   pub configurations: Vec<ProtocolAttributes>,
+  /// Overrides keyed by OS name, as reported by [std::env::consts::OS].
+  #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+  pub platforms: HashMap<String, ProtocolPlatformOverride>,
+}
+
+impl ProtocolDefinition {
+  /// Resolve the definition for the given OS. Communication and defaults sections in a matching
+  /// override replace the base sections outright, while override configurations replace any base
+  /// configurations that share an identifier with them.
+  fn for_platform(mut self, platform: &str) -> Self {
+    let Some(platform_override) = self.platforms.remove(platform) else {
+      self.platforms.clear();
+      return self;
+    };
+    self.platforms.clear();
+    if platform_override.communication.is_some() {
+      self.communication = platform_override.communication;
+    }
+    if platform_override.defaults.is_some() {
+      self.defaults = platform_override.defaults;
+    }
+    for config in platform_override.configurations {
+      let identifiers = config.identifier.clone().unwrap_or_default();
+      self.configurations.retain(|existing| {
+        !existing
+          .identifier
+          .as_ref()
+          .is_some_and(|existing_ids| existing_ids.iter().any(|id| identifiers.contains(id)))
+      });
+      self.configurations.push(config);
+    }
+    self
+  }
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone, Getters, Setters, MutGetters)]
@@ -158,7 +217,7 @@ trait ConfigVersionGetter {
   fn version(&self) -> ConfigVersion;
 }
 
-#[derive(Deserialize, Serialize, Debug, Getters)]
+#[derive(Deserialize, Serialize, Debug, Clone, Getters)]
 #[getset(get = "pub", get_mut = "pub", set = "pub")]
 pub struct BaseConfigFile {
   version: ConfigVersion,
@@ -235,38 +294,17 @@ impl UserConfigFile {
 }
 
 fn get_internal_config_version() -> ConfigVersion {
-  let config: BaseConfigFile = serde_json::from_str(DEVICE_CONFIGURATION_JSON)
-    .expect("If this fails, the whole library goes with it.");
-  config.version
+  INTERNAL_BASE_CONFIG.version
 }
 
-fn load_protocol_config_from_json<'a, T>(
-  config_str: &'a str,
-  skip_version_check: bool,
-) -> Result<T, ButtplugDeviceError>
+fn parse_protocol_config_json<'a, T>(config_str: &'a str) -> Result<T, ButtplugDeviceError>
 where
-  T: ConfigVersionGetter + Deserialize<'a>,
+  T: Deserialize<'a>,
 {
   let config_validator = JSONValidator::new(DEVICE_CONFIGURATION_JSON_SCHEMA);
   match config_validator.validate(config_str) {
-    Ok(_) => match serde_json::from_str::<T>(config_str) {
-      Ok(protocol_config) => {
-        let internal_config_version = get_internal_config_version();
-        if !skip_version_check && protocol_config.version().major != internal_config_version.major {
-          Err(ButtplugDeviceError::DeviceConfigurationError(format!(
-            "Device configuration file major version {} is different than internal major version {}. Cannot load external files that do not have matching major version numbers.",
-            protocol_config.version(),
-            internal_config_version
-          )))
-        } else {
-          Ok(protocol_config)
-        }
-      }
-      Err(err) => Err(ButtplugDeviceError::DeviceConfigurationError(format!(
-        "{}",
-        err
-      ))),
-    },
+    Ok(_) => serde_json::from_str::<T>(config_str)
+      .map_err(|err| ButtplugDeviceError::DeviceConfigurationError(format!("{}", err))),
     Err(err) => Err(ButtplugDeviceError::DeviceConfigurationError(format!(
       "{}",
       err
@@ -274,23 +312,50 @@ where
   }
 }
 
+fn check_config_version<T>(config: &T, skip_version_check: bool) -> Result<(), ButtplugDeviceError>
+where
+  T: ConfigVersionGetter,
+{
+  let internal_config_version = get_internal_config_version();
+  if !skip_version_check && config.version().major != internal_config_version.major {
+    Err(ButtplugDeviceError::DeviceConfigurationError(format!(
+      "Device configuration file major version {} is different than internal major version {}. Cannot load external files that do not have matching major version numbers.",
+      config.version(),
+      internal_config_version
+    )))
+  } else {
+    Ok(())
+  }
+}
+
+fn load_protocol_config_from_json<'a, T>(
+  config_str: &'a str,
+  skip_version_check: bool,
+) -> Result<T, ButtplugDeviceError>
+where
+  T: ConfigVersionGetter + Deserialize<'a>,
+{
+  let protocol_config = parse_protocol_config_json::<T>(config_str)?;
+  check_config_version(&protocol_config, skip_version_check)?;
+  Ok(protocol_config)
+}
+
 fn load_main_config(
   main_config_str: &Option<String>,
   skip_version_check: bool,
 ) -> Result<DeviceConfigurationManagerBuilder, ButtplugDeviceError> {
-  if main_config_str.is_some() {
-    info!("Loading from custom base device configuration...")
-  } else {
-    info!("Loading from internal base device configuration...")
-  }
-  // Start by loading the main config
-  let main_config = load_protocol_config_from_json::<BaseConfigFile>(
-    &main_config_str
-      .as_ref()
-      .unwrap_or(&DEVICE_CONFIGURATION_JSON.to_owned()),
-    skip_version_check,
-  )?;
+  let Some(main_config_str) = main_config_str else {
+    info!("Loading from internal base device configuration...");
+    check_config_version(&*INTERNAL_BASE_CONFIG, skip_version_check)?;
+    return Ok(INTERNAL_DCM_BUILDER.clone());
+  };
+  info!("Loading from custom base device configuration...");
+  let main_config =
+    load_protocol_config_from_json::<BaseConfigFile>(main_config_str, skip_version_check)?;
+  Ok(dcm_builder_from_main_config(main_config))
+}
 
+fn dcm_builder_from_main_config(main_config: BaseConfigFile) -> DeviceConfigurationManagerBuilder {
   info!("Loaded config version {:?}", main_config.version);
 
   let mut dcm_builder = DeviceConfigurationManagerBuilder::default();
@@ -307,7 +372,8 @@ fn load_main_config(
   // Iterate through all of the protocols in the main config first and build up a map of protocol
   // name to ProtocolDeviceConfiguration structs.
   for (protocol_name, protocol_def) in main_config.protocols.unwrap_or_default() {
-    let protocol_device_config: ProtocolDeviceConfiguration = protocol_def.into();
+    let protocol_device_config: ProtocolDeviceConfiguration =
+      protocol_def.for_platform(env::consts::OS).into();
     protocol_specifiers.insert(
       protocol_name.clone(),
       protocol_device_config.specifiers().clone(),
@@ -326,7 +392,7 @@ fn load_main_config(
     dcm_builder.protocol_features(&ident, &features);
   }
 
-  Ok(dcm_builder)
+  dcm_builder
 }
 
 fn load_user_config(
@@ -348,6 +414,7 @@ fn load_user_config(
     .expect("Just checked validity");
 
   for (protocol, specifier) in user_config.protocols.unwrap_or_default() {
+    let specifier = specifier.for_platform(env::consts::OS);
     if let Some(comm_specifiers) = specifier.communication() {
       dcm_builder.user_communication_specifier(&protocol, comm_specifiers);
     }
@@ -379,6 +446,43 @@ pub fn load_protocol_configs(
   Ok(dcm_builder)
 }
 
+/// Load a user configuration into an already running [DeviceConfigurationManager], on top of what
+/// it already has. Device definitions that don't fit the manager's protocols are logged and skipped,
+/// so one bad entry doesn't lose the rest.
+pub fn apply_user_config(
+  dcm: &DeviceConfigurationManager,
+  user_config_str: &str,
+  skip_version_check: bool,
+) -> Result<(), ButtplugDeviceError> {
+  let user_config_file =
+    load_protocol_config_from_json::<UserConfigFile>(user_config_str, skip_version_check)?;
+  let Some(user_config) = user_config_file.user_configs else {
+    return Ok(());
+  };
+  for (protocol, definition) in user_config.protocols.unwrap_or_default() {
+    let definition = definition.for_platform(env::consts::OS);
+    for specifier in definition.communication().iter().flatten() {
+      let known = dcm
+        .user_communication_specifiers()
+        .get(&protocol)
+        .is_some_and(|specifiers| specifiers.contains(specifier));
+      if !known {
+        dcm.add_user_communication_specifier(&protocol, specifier)?;
+      }
+    }
+  }
+  for pair in user_config.user_device_configs.unwrap_or_default() {
+    if let Err(e) = dcm.add_user_device_definition(pair.identifier(), pair.config()) {
+      warn!(
+        "Skipping user device definition for {:?}: {}",
+        pair.identifier(),
+        e
+      );
+    }
+  }
+  Ok(())
+}
+
 pub fn save_user_config(dcm: &DeviceConfigurationManager) -> Result<String, ButtplugError> {
   let user_specifiers = dcm.user_communication_specifiers();
   let user_definitions_vec = dcm
@@ -12,9 +12,11 @@ pub mod async_manager;
 #[cfg(feature = "server")]
 pub mod device_configuration;
 pub mod future;
+#[cfg(feature = "serialize-json")]
 pub mod json;
 pub mod logging;
 pub mod stream;
+pub mod system_resume;
 
 #[cfg(not(feature = "wasm"))]
 pub use tokio::time::sleep;
@@ -71,20 +73,7 @@ pub async fn in_process_client(client_name: &str, allow_raw_messages: bool) -> B
     .unwrap();
 
   let mut device_manager_builder = ServerDeviceManagerBuilder::new(dcm);
-  #[cfg(all(
-    feature = "btleplug-manager",
-    any(
-      target_os = "windows",
-      target_os = "macos",
-      target_os = "linux",
-      target_os = "ios",
-      target_os = "android"
-    )
-  ))]
-  {
-    use crate::server::device::hardware::communication::btleplug::BtlePlugCommunicationManagerBuilder;
-    device_manager_builder.comm_manager(BtlePlugCommunicationManagerBuilder::default());
-  }
+  device_manager_builder.local_comm_managers();
   #[cfg(feature = "websocket-server-manager")]
   {
     use crate::server::device::hardware::communication::websocket_server::websocket_server_comm_manager::WebsocketServerDeviceCommunicationManagerBuilder;
@@ -92,37 +81,12 @@ pub async fn in_process_client(client_name: &str, allow_raw_messages: bool) -> B
       WebsocketServerDeviceCommunicationManagerBuilder::default().listen_on_all_interfaces(true),
     );
   }
-  #[cfg(all(
-    feature = "serial-manager",
-    any(target_os = "windows", target_os = "macos", target_os = "linux")
-  ))]
-  {
-    use crate::server::device::hardware::communication::serialport::SerialPortCommunicationManagerBuilder;
-    device_manager_builder.comm_manager(SerialPortCommunicationManagerBuilder::default());
-  }
   #[cfg(feature = "lovense-connect-service-manager")]
   {
     use crate::server::device::hardware::communication::lovense_connect_service::LovenseConnectServiceCommunicationManagerBuilder;
     device_manager_builder
       .comm_manager(LovenseConnectServiceCommunicationManagerBuilder::default());
   }
-  #[cfg(all(
-    feature = "lovense-dongle-manager",
-    any(target_os = "windows", target_os = "macos", target_os = "linux")
-  ))]
-  {
-    use crate::server::device::hardware::communication::lovense_dongle::{
-      LovenseHIDDongleCommunicationManagerBuilder,
-      LovenseSerialDongleCommunicationManagerBuilder,
-    };
-    device_manager_builder.comm_manager(LovenseHIDDongleCommunicationManagerBuilder::default());
-    device_manager_builder.comm_manager(LovenseSerialDongleCommunicationManagerBuilder::default());
-  }
-  #[cfg(all(feature = "xinput-manager", target_os = "windows"))]
-  {
-    use crate::server::device::hardware::communication::xinput::XInputDeviceCommunicationManagerBuilder;
-    device_manager_builder.comm_manager(XInputDeviceCommunicationManagerBuilder::default());
-  }
   let server_builder = ButtplugServerBuilder::new(device_manager_builder.finish().unwrap());
   let server = server_builder.finish().unwrap();
   let connector = ButtplugInProcessClientConnectorBuilder::default()
@@ -0,0 +1,109 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2024 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Detection of the host system waking up from sleep.
+//!
+//! There's no portable API for getting sleep/resume notifications, and the platform specific ones
+//! (power broadcast messages on Windows, IOKit on macOS, logind over DBus on linux) would each need
+//! their own window/session plumbing. Instead, we watch for the wall clock jumping ahead of our
+//! timers. Monotonic clocks (which tokio timers run on) stop while the system is suspended, but the
+//! wall clock keeps going, so if a short timer wakes up to find far more wall time has passed than
+//! it waited for, the system was asleep in between.
+//!
+//! Large wall clock adjustments (NTP corrections, manual clock changes) will also register as a
+//! resume. Anything acting on resume events should be safe to run spuriously.
+
+use crate::util::{async_manager, sleep};
+use instant::SystemTime;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+/// How often the wall clock is checked.
+pub const DEFAULT_RESUME_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+/// How far the wall clock has to get ahead of a check interval to be considered a resume.
+pub const DEFAULT_RESUME_THRESHOLD: Duration = Duration::from_secs(10);
+
+/// Tracks wall clock time between checks, reporting when it has jumped far enough to indicate the
+/// system was asleep.
+#[derive(Debug)]
+pub struct SystemResumeDetector {
+  check_interval: Duration,
+  threshold: Duration,
+  last_check: SystemTime,
+}
+
+impl SystemResumeDetector {
+  pub fn new(check_interval: Duration, threshold: Duration) -> Self {
+    Self {
+      check_interval,
+      threshold,
+      last_check: SystemTime::now(),
+    }
+  }
+
+  /// Record a check at `now`, which should happen once per check interval. Returns roughly how long
+  /// the system was asleep if a resume was detected since the last check.
+  pub fn check(&mut self, now: SystemTime) -> Option<Duration> {
+    // If the clock went backwards, there's nothing to report, just start counting again from here.
+    let elapsed = now.duration_since(self.last_check).unwrap_or_default();
+    self.last_check = now;
+    let overrun = elapsed.saturating_sub(self.check_interval);
+    (overrun > self.threshold).then_some(overrun)
+  }
+}
+
+/// Spawn a task that checks for system resume, sending the approximate sleep duration over the
+/// returned channel whenever one is detected. The task exits once the receiver is dropped or the
+/// token is cancelled.
+pub fn system_resume_events(
+  check_interval: Duration,
+  threshold: Duration,
+  cancellation_token: CancellationToken,
+) -> mpsc::Receiver<Duration> {
+  let (sender, receiver) = mpsc::channel(1);
+  async_manager::spawn(async move {
+    let mut detector = SystemResumeDetector::new(check_interval, threshold);
+    while !sender.is_closed() {
+      tokio::select! {
+        _ = sleep(check_interval) => {}
+        _ = cancellation_token.cancelled() => break,
+      }
+      if let Some(slept) = detector.check(SystemTime::now()) {
+        info!("System resume detected, slept for roughly {:?}.", slept);
+        // If an earlier resume is still waiting to be handled, this one can be dropped.
+        let _ = sender.try_send(slept);
+      }
+    }
+  });
+  receiver
+}
+
+#[cfg(test)]
+mod test {
+  use super::SystemResumeDetector;
+  use instant::SystemTime;
+  use std::time::Duration;
+
+  #[test]
+  fn test_resume_detection() {
+    let interval = Duration::from_secs(5);
+    let mut detector = SystemResumeDetector::new(interval, Duration::from_secs(10));
+    let start = SystemTime::now();
+    // Regular ticks (with some scheduling slop) aren't resumes.
+    assert_eq!(detector.check(start + interval), None);
+    assert_eq!(
+      detector.check(start + interval * 2 + Duration::from_secs(2)),
+      None
+    );
+    // A jump well past the interval is.
+    let resumed = start + interval * 3 + Duration::from_secs(2) + Duration::from_secs(600);
+    assert_eq!(detector.check(resumed), Some(Duration::from_secs(600)));
+    // Clock going backwards resets rather than reporting.
+    assert_eq!(detector.check(resumed - Duration::from_secs(60)), None);
+  }
+}
@@ -0,0 +1,405 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2024 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Conformance run against real hardware.
+//!
+//! Walks a connected device through everything it advertises (scalar sweeps, linear strokes,
+//! rotation, battery reads, stop) and prints a pass/fail report with command timings, so people
+//! with the hardware can check that a device really works the way its config says it does. Only
+//! built with the `hardware-conformance` feature:
+//!
+//! ```text
+//! cargo test --features hardware-conformance --test hardware_conformance
+//! ```
+//!
+//! Settings come from environment variables:
+//!
+//! - `BUTTPLUG_CONFORMANCE_DEVICE`: Only test a device whose name contains this string. Otherwise
+//!   the first device found is used.
+//! - `BUTTPLUG_CONFORMANCE_SCAN_TIMEOUT`: Seconds to scan for a device before giving up (default
+//!   30).
+//! - `BUTTPLUG_CONFORMANCE_STEP_MS`: How long to hold each step of a sweep (default 500).
+//! - `BUTTPLUG_CONFORMANCE_INTERACTIVE`: If set, ask after each check whether the device actually
+//!   did what it was told, and fail the check on a "no". Without this, checks only confirm that
+//!   the device accepted every command.
+
+use buttplug::{
+  client::{
+    ButtplugClient, ButtplugClientDevice, ButtplugClientEvent, LinearCommand, RotateCommand,
+    ScalarCommand,
+  },
+  core::connector::ButtplugInProcessClientConnectorBuilder,
+  server::{
+    device::{hardware::communication, ServerDeviceManagerBuilder},
+    ButtplugServerBuilder,
+  },
+  util::device_configuration::load_protocol_configs,
+};
+use futures::{Future, StreamExt};
+use std::{
+  collections::HashMap,
+  env,
+  io::{self, Write},
+  process::ExitCode,
+  sync::Arc,
+  time::{Duration, Instant},
+};
+
+/// Levels to step through when sweeping a scalar actuator.
+const SCALAR_SWEEP: [f64; 5] = [0.25, 0.5, 0.75, 1.0, 0.0];
+/// Stroke durations (in milliseconds) to run linear actuators at, slowest first.
+const LINEAR_STROKE_DURATIONS: [u32; 3] = [1000, 500, 250];
+
+struct Settings {
+  device_filter: Option<String>,
+  scan_timeout: Duration,
+  step_duration: Duration,
+  interactive: bool,
+}
+
+impl Settings {
+  fn from_env() -> Self {
+    let number = |name: &str, default: u64| {
+      env::var(name)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+    };
+    Self {
+      device_filter: env::var("BUTTPLUG_CONFORMANCE_DEVICE").ok(),
+      scan_timeout: Duration::from_secs(number("BUTTPLUG_CONFORMANCE_SCAN_TIMEOUT", 30)),
+      step_duration: Duration::from_millis(number("BUTTPLUG_CONFORMANCE_STEP_MS", 500)),
+      interactive: env::var("BUTTPLUG_CONFORMANCE_INTERACTIVE").is_ok(),
+    }
+  }
+}
+
+/// Outcome of one check, with how long each command took to be acknowledged.
+struct CheckResult {
+  name: String,
+  error: Option<String>,
+  command_times: Vec<Duration>,
+}
+
+impl CheckResult {
+  fn print(&self) {
+    let status = if self.error.is_none() { "PASS" } else { "FAIL" };
+    let timing = match self.command_times.iter().max() {
+      Some(max) => {
+        let total: Duration = self.command_times.iter().sum();
+        format!(
+          "{} commands, avg {:?}, max {:?}",
+          self.command_times.len(),
+          total / self.command_times.len() as u32,
+          max
+        )
+      }
+      None => "no commands".to_owned(),
+    };
+    println!("{} {} ({})", status, self.name, timing);
+    if let Some(error) = &self.error {
+      println!("     {}", error);
+    }
+  }
+}
+
+/// Runs checks, keeping track of their results.
+struct Checker {
+  settings: Settings,
+  results: Vec<CheckResult>,
+}
+
+impl Checker {
+  async fn command(
+    result: &mut CheckResult,
+    command: impl Future<Output = Result<(), impl std::fmt::Debug>>,
+  ) {
+    if result.error.is_some() {
+      return;
+    }
+    let start = Instant::now();
+    match command.await {
+      Ok(()) => result.command_times.push(start.elapsed()),
+      Err(err) => result.error = Some(format!("Command failed: {:?}", err)),
+    }
+  }
+
+  /// Ask the user whether the device did what was expected. Always true when not interactive.
+  async fn confirm(&self, expected: &str) -> bool {
+    if !self.settings.interactive {
+      return true;
+    }
+    let question = format!("Did the device {}? [y/n] ", expected);
+    tokio::task::spawn_blocking(move || loop {
+      print!("{}", question);
+      io::stdout().flush().expect("Can't prompt without stdout.");
+      let mut answer = String::new();
+      if io::stdin().read_line(&mut answer).is_err() {
+        return false;
+      }
+      match answer.trim().to_lowercase().as_str() {
+        "y" | "yes" => return true,
+        "n" | "no" => return false,
+        _ => continue,
+      }
+    })
+    .await
+    .unwrap_or(false)
+  }
+
+  async fn finish(
+    &mut self,
+    mut result: CheckResult,
+    device: &ButtplugClientDevice,
+    expected: &str,
+  ) {
+    // Always leave the device stopped, even if the check failed partway through.
+    if let Err(err) = device.stop().await {
+      result
+        .error
+        .get_or_insert(format!("Stop failed: {:?}", err));
+    }
+    if result.error.is_none() && !self.confirm(expected).await {
+      result.error = Some(format!("Device did not {}.", expected));
+    }
+    result.print();
+    self.results.push(result);
+  }
+
+  async fn check_scalars(&mut self, device: &ButtplugClientDevice) {
+    let Some(attributes) = device.message_attributes().scalar_cmd().clone() else {
+      return;
+    };
+    for (index, attribute) in attributes.iter().enumerate() {
+      let mut result = CheckResult {
+        name: format!(
+          "scalar {} ({}, {} steps) sweep",
+          index,
+          attribute.actuator_type(),
+          attribute.step_count()
+        ),
+        error: None,
+        command_times: vec![],
+      };
+      for level in SCALAR_SWEEP {
+        let command = ScalarCommand::ScalarMap(HashMap::from([(
+          index as u32,
+          (level, *attribute.actuator_type()),
+        )]));
+        Self::command(&mut result, device.scalar(&command)).await;
+        tokio::time::sleep(self.settings.step_duration).await;
+      }
+      let expected = format!(
+        "run {} in {} increasing steps, then stop",
+        attribute.actuator_type(),
+        SCALAR_SWEEP.len() - 1
+      );
+      self.finish(result, device, &expected).await;
+    }
+  }
+
+  async fn check_linears(&mut self, device: &ButtplugClientDevice) {
+    let Some(attributes) = device.message_attributes().linear_cmd().clone() else {
+      return;
+    };
+    for index in 0..attributes.len() as u32 {
+      let mut result = CheckResult {
+        name: format!("linear {} stroke sweep", index),
+        error: None,
+        command_times: vec![],
+      };
+      for duration in LINEAR_STROKE_DURATIONS {
+        for position in [1.0, 0.0] {
+          let command = LinearCommand::LinearMap(HashMap::from([(index, (duration, position))]));
+          Self::command(&mut result, device.linear(&command)).await;
+          tokio::time::sleep(Duration::from_millis(duration.into())).await;
+        }
+      }
+      let expected = format!(
+        "make {} full strokes, each faster than the last",
+        LINEAR_STROKE_DURATIONS.len()
+      );
+      self.finish(result, device, &expected).await;
+    }
+  }
+
+  async fn check_rotators(&mut self, device: &ButtplugClientDevice) {
+    let Some(attributes) = device.message_attributes().rotate_cmd().clone() else {
+      return;
+    };
+    for index in 0..attributes.len() as u32 {
+      let mut result = CheckResult {
+        name: format!("rotate {} direction sweep", index),
+        error: None,
+        command_times: vec![],
+      };
+      for (speed, clockwise) in [(0.5, true), (1.0, true), (0.5, false), (1.0, false)] {
+        let command = RotateCommand::RotateMap(HashMap::from([(index, (speed, clockwise))]));
+        Self::command(&mut result, device.rotate(&command)).await;
+        tokio::time::sleep(self.settings.step_duration).await;
+      }
+      self
+        .finish(
+          result,
+          device,
+          "rotate at two speeds one way, then two speeds the other way",
+        )
+        .await;
+    }
+  }
+
+  async fn check_battery(&mut self, device: &ButtplugClientDevice) {
+    if !device.has_battery_level() {
+      return;
+    }
+    let mut result = CheckResult {
+      name: "battery level read".to_owned(),
+      error: None,
+      command_times: vec![],
+    };
+    let start = Instant::now();
+    match device.battery_level().await {
+      Ok(level) if (0.0..=1.0).contains(&level) => {
+        result.command_times.push(start.elapsed());
+        println!("     Battery level: {:.0}%", level * 100.0);
+      }
+      Ok(level) => result.error = Some(format!("Battery level {} out of range.", level)),
+      Err(err) => result.error = Some(format!("Battery read failed: {:?}", err)),
+    }
+    result.print();
+    self.results.push(result);
+  }
+}
+
+async fn find_device(
+  client: &ButtplugClient,
+  settings: &Settings,
+) -> Option<Arc<ButtplugClientDevice>> {
+  let matches = |device: &ButtplugClientDevice| {
+    settings
+      .device_filter
+      .as_ref()
+      .is_none_or(|filter| device.name().contains(filter.as_str()))
+  };
+  let mut events = client.event_stream();
+  client
+    .start_scanning()
+    .await
+    .expect("Scanning should start if any comm managers are available.");
+  let found = tokio::time::timeout(settings.scan_timeout, async {
+    while let Some(event) = events.next().await {
+      if let ButtplugClientEvent::DeviceAdded(device) = event {
+        println!("Found {}", device.name());
+        if matches(&device) {
+          return Some(device);
+        }
+      }
+    }
+    None
+  })
+  .await
+  .ok()
+  .flatten();
+  let _ = client.stop_scanning().await;
+  found
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> ExitCode {
+  tracing_subscriber::fmt::init();
+  let settings = Settings::from_env();
+
+  let dcm = load_protocol_configs(&None, &None, false)
+    .expect("Built in device config should always load.")
+    .finish()
+    .expect("Built in device config should always load.");
+  let mut device_manager_builder = ServerDeviceManagerBuilder::new(dcm);
+  add_comm_managers(&mut device_manager_builder);
+  let server = ButtplugServerBuilder::new(
+    device_manager_builder
+      .finish()
+      .expect("Comm managers should all be distinct."),
+  )
+  .name("Buttplug Conformance")
+  .finish()
+  .expect("Server should build with default settings.");
+  let connector = ButtplugInProcessClientConnectorBuilder::default()
+    .server(server)
+    .finish();
+  let client = ButtplugClient::new("Buttplug Conformance");
+  client
+    .connect(connector)
+    .await
+    .expect("In process connection should always work.");
+
+  println!("Scanning for devices...");
+  let Some(device) = find_device(&client, &settings).await else {
+    println!("No matching device found.");
+    return ExitCode::FAILURE;
+  };
+  println!(
+    "Testing {} (protocol {}, transport {:?})",
+    device.name(),
+    device.protocol().as_deref().unwrap_or("unknown"),
+    device.transport()
+  );
+
+  let mut checker = Checker {
+    settings,
+    results: vec![],
+  };
+  checker.check_scalars(&device).await;
+  checker.check_linears(&device).await;
+  checker.check_rotators(&device).await;
+  checker.check_battery(&device).await;
+  let mut stop = CheckResult {
+    name: "stop all devices".to_owned(),
+    error: None,
+    command_times: vec![],
+  };
+  Checker::command(&mut stop, client.stop_all_devices()).await;
+  stop.print();
+  checker.results.push(stop);
+  let _ = client.disconnect().await;
+
+  let failed = checker
+    .results
+    .iter()
+    .filter(|result| result.error.is_some())
+    .count();
+  println!(
+    "{}: {} checks, {} failed",
+    device.name(),
+    checker.results.len(),
+    failed
+  );
+  if failed == 0 {
+    ExitCode::SUCCESS
+  } else {
+    ExitCode::FAILURE
+  }
+}
+
+/// Add every real hardware comm manager this build and platform supports.
+fn add_comm_managers(builder: &mut ServerDeviceManagerBuilder) {
+  builder.comm_manager(communication::btleplug::BtlePlugCommunicationManagerBuilder::default());
+  #[cfg(feature = "serial-manager")]
+  builder.comm_manager(communication::serialport::SerialPortCommunicationManagerBuilder::default());
+  #[cfg(feature = "hid-manager")]
+  builder.comm_manager(communication::hid::HidCommunicationManagerBuilder::default());
+  #[cfg(feature = "lovense-dongle-manager")]
+  {
+    builder.comm_manager(
+      communication::lovense_dongle::LovenseHIDDongleCommunicationManagerBuilder::default(),
+    );
+    builder.comm_manager(
+      communication::lovense_dongle::LovenseSerialDongleCommunicationManagerBuilder::default(),
+    );
+  }
+  #[cfg(all(feature = "xinput-manager", target_os = "windows"))]
+  builder.comm_manager(communication::xinput::XInputDeviceCommunicationManagerBuilder::default());
+}
@@ -6,26 +6,52 @@
 // for full license information.
 
 mod util;
-use util::{test_client, test_client_with_delayed_device_manager, test_client_with_device};
+use util::{
+  channel_transport::ChannelClientTestHelper,
+  test_client,
+  test_client_with_delayed_device_manager,
+  test_client_with_device,
+  test_device_manager::TestDeviceIdentifier,
+  test_server_with_comm_manager,
+  test_server_with_device,
+  TestDeviceCommunicationManagerBuilder,
+};
 extern crate buttplug;
 extern crate tracing;
 
 use buttplug::{
-  client::{ButtplugClient, ButtplugClientError, ButtplugClientEvent},
+  client::{ButtplugClient, ButtplugClientError, ButtplugClientEvent, ScalarValueCommand},
   core::{
     connector::{
       ButtplugConnector,
       ButtplugConnectorError,
       ButtplugConnectorResultFuture,
       ButtplugInProcessClientConnectorBuilder,
+      ButtplugMessageTrace,
+      ButtplugTraceDirection,
+      ButtplugTracingConnector,
+    },
+    errors::{ButtplugDeviceError, ButtplugError, ButtplugHandshakeError},
+    message::{
+      self,
+      ButtplugClientMessageCurrent,
+      ButtplugClientMessageV3,
+      ButtplugClientMessageVariant,
+      ButtplugMessage,
+      ButtplugMessageSpecVersion,
+      ButtplugServerMessageCurrent,
+      ButtplugServerMessageV3,
+      ButtplugServerMessageVariant,
     },
-    errors::{ButtplugDeviceError, ButtplugError},
-    message::{ButtplugClientMessageCurrent, ButtplugServerMessageCurrent},
   },
-  server::ButtplugServerBuilder,
+  server::{device::hardware::HardwareCommand, ButtplugServerBuilder},
 };
 
-use futures::{future::BoxFuture, StreamExt};
+use futures::{
+  future::{self, BoxFuture},
+  FutureExt,
+  StreamExt,
+};
 use std::time::Duration;
 use tokio::{sync::mpsc::Sender, time::sleep};
 
@@ -51,6 +77,29 @@ impl ButtplugConnector<ButtplugClientMessageCurrent, ButtplugServerMessageCurren
   }
 }
 
+// Connects, then never answers anything, like a server that's hung or isn't a Buttplug server.
+#[derive(Default)]
+struct ButtplugSilentConnector {}
+
+impl ButtplugConnector<ButtplugClientMessageCurrent, ButtplugServerMessageCurrent>
+  for ButtplugSilentConnector
+{
+  fn connect(
+    &mut self,
+    _: Sender<ButtplugServerMessageCurrent>,
+  ) -> BoxFuture<'static, Result<(), ButtplugConnectorError>> {
+    future::ready(Ok(())).boxed()
+  }
+
+  fn disconnect(&self) -> ButtplugConnectorResultFuture {
+    future::ready(Ok(())).boxed()
+  }
+
+  fn send(&self, _msg: ButtplugClientMessageCurrent) -> ButtplugConnectorResultFuture {
+    future::ready(Ok(())).boxed()
+  }
+}
+
 #[cfg(feature = "server")]
 #[tokio::test]
 async fn test_failing_connection() {
@@ -168,6 +217,148 @@ async fn test_client_ping() {
   // TODO Watch for ping events
   assert!(client.ping().await.is_err());
 }
+
+#[tokio::test]
+async fn test_handshake_timeout() {
+  let client =
+    ButtplugClient::new("Test Client").with_handshake_timeout(Duration::from_millis(100));
+  let err = client
+    .connect(ButtplugSilentConnector::default())
+    .await
+    .unwrap_err();
+  let ButtplugClientError::ButtplugConnectorError(err) = err else {
+    panic!("Expected a connector error, got {:?}", err);
+  };
+  assert!(matches!(err, ButtplugConnectorError::HandshakeTimeout(_)));
+  assert!(err.remediation_hint().is_some());
+  assert!(!client.connected());
+}
+
+#[tokio::test]
+async fn test_handshake_spec_version_mismatch() {
+  let helper = ChannelClientTestHelper::new();
+  let server = async {
+    assert!(matches!(
+      helper.next_client_message().await,
+      ButtplugClientMessageVariant::V3(ButtplugClientMessageV3::RequestServerInfo(..))
+    ));
+    let mut error_msg = ButtplugServerMessageV3::Error(message::ErrorV0::from(
+      ButtplugError::from(ButtplugHandshakeError::MessageSpecVersionMismatch(
+        ButtplugMessageSpecVersion::Version2,
+        ButtplugMessageSpecVersion::Version3,
+      )),
+    ));
+    error_msg.set_id(1);
+    helper
+      .send_client_incoming(ButtplugServerMessageVariant::V3(error_msg))
+      .await;
+  };
+  let (result, _) = tokio::join!(helper.connect_without_reply(), server);
+  assert!(matches!(
+    result.unwrap_err(),
+    ButtplugClientError::ButtplugConnectorError(ButtplugConnectorError::SpecVersionMismatch(
+      ButtplugMessageSpecVersion::Version2,
+      ButtplugMessageSpecVersion::Version3
+    ))
+  ));
+  assert!(!helper.client().connected());
+}
+
+#[cfg(feature = "websockets")]
+#[tokio::test]
+async fn test_connect_to_http_server() {
+  use buttplug::core::connector::{
+    ButtplugRemoteClientConnector,
+    ButtplugWebsocketClientTransport,
+  };
+  use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+  let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+    .await
+    .expect("Test, assuming infallible.");
+  let address = format!(
+    "ws://{}",
+    listener.local_addr().expect("Test, assuming infallible.")
+  );
+  tokio::spawn(async move {
+    let (mut stream, _) = listener.accept().await.expect("Test, assuming infallible.");
+    // Read the upgrade request, then answer like a plain web server would.
+    let mut request = [0u8; 1024];
+    let _ = stream.read(&mut request).await;
+    let _ = stream
+      .write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n")
+      .await;
+  });
+  let connector = ButtplugRemoteClientConnector::<ButtplugWebsocketClientTransport>::new(
+    ButtplugWebsocketClientTransport::new_insecure_connector(&address),
+  );
+  let client = ButtplugClient::new("Test Client");
+  assert!(matches!(
+    client.connect(connector).await.unwrap_err(),
+    ButtplugClientError::ButtplugConnectorError(ButtplugConnectorError::NotAButtplugServer(_))
+  ));
+}
+#[tokio::test]
+async fn test_client_trace_replay() {
+  let mut builder = TestDeviceCommunicationManagerBuilder::default();
+  let _device = builder.add_test_device(&TestDeviceIdentifier::new("Massage Demo", None));
+  let connector = ButtplugTracingConnector::new(
+    ButtplugInProcessClientConnectorBuilder::default()
+      .server(test_server_with_comm_manager(builder, false))
+      .finish(),
+  );
+  let recorder = connector.recorder();
+  let client = ButtplugClient::new("Test Client");
+  client
+    .connect(connector)
+    .await
+    .expect("Test, assuming infallible.");
+  let mut event_stream = client.event_stream();
+  client
+    .start_scanning()
+    .await
+    .expect("Test, assuming infallible.");
+  while let Some(event) = event_stream.next().await {
+    if let ButtplugClientEvent::DeviceAdded(dev) = event {
+      dev
+        .vibrate(&ScalarValueCommand::ScalarValue(0.5))
+        .await
+        .expect("Test, assuming infallible.");
+      break;
+    }
+  }
+  client
+    .disconnect()
+    .await
+    .expect("Test, assuming infallible.");
+
+  // Traces go through JSON on their way to and from bug reports.
+  let trace = ButtplugMessageTrace::from_json(&recorder.trace().to_json())
+    .expect("Test, assuming infallible.");
+  assert!(trace
+    .entries()
+    .iter()
+    .any(|entry| entry.message().contains("DeviceAdded")));
+
+  let (server, mut device) = test_server_with_device("Massage Demo", false);
+  let replayed = trace
+    .replay(&server)
+    .await
+    .expect("Test, assuming infallible.");
+  let replies: Vec<_> = replayed
+    .entries()
+    .iter()
+    .filter(|entry| entry.direction() == ButtplugTraceDirection::ServerToClient)
+    .collect();
+  assert!(!replies.is_empty());
+  assert!(replies
+    .iter()
+    .all(|entry| !entry.message().contains("\"Error\"")));
+  let command = tokio::time::timeout(Duration::from_secs(1), device.receiver.recv())
+    .await
+    .expect("Test, assuming infallible.");
+  assert!(matches!(command, Some(HardwareCommand::Write(_))));
+}
 /*
 // Tests both the stop all devices functionality, as well as both ends of the
 // command range for is_in_command_range message validation.
@@ -15,14 +15,25 @@ use buttplug::{
   },
   core::{
     errors::{ButtplugDeviceError, ButtplugError, ButtplugMessageError},
-    message::{self, ClientDeviceMessageAttributesV3},
+    message::{
+      self,
+      ClientDeviceMessageAttributesV3,
+      PatternSessionEndReason,
+      PatternStepV4,
+      PatternTrackV4,
+      StartPatternSessionV4,
+    },
   },
   util::async_manager,
 };
 use futures::StreamExt;
 use std::{sync::Arc, time::Duration};
 use tokio::time::sleep;
-use util::{test_client_with_device, test_device_manager::TestHardwareEvent};
+use util::{
+  test_client_with_device,
+  test_client_with_named_device,
+  test_device_manager::TestHardwareEvent,
+};
 
 #[cfg(feature = "server")]
 #[tokio::test]
@@ -62,6 +73,173 @@ async fn test_client_device_connected_status() {
   assert!(!client.connected());
 }
 
+#[cfg(feature = "server")]
+#[tokio::test]
+async fn test_client_device_identify() {
+  use buttplug::server::device::hardware::{HardwareCommand, HardwareWriteCmd};
+  use util::test_device_manager::check_test_recv_value;
+
+  let (client, mut device) = test_client_with_device().await;
+
+  let mut event_stream = client.event_stream();
+  client
+    .start_scanning()
+    .await
+    .expect("Test, assuming infallible.");
+  let mut client_device = None;
+  while let Some(msg) = event_stream.next().await {
+    if let ButtplugClientEvent::DeviceAdded(da) = msg {
+      client_device = Some(da);
+      break;
+    }
+  }
+  let test_device = client_device.expect("Test, assuming infallible.");
+  test_device
+    .identify()
+    .await
+    .expect("Test, assuming infallible.");
+  // Both vibrators pulse, then stop.
+  for command in [[0xF1, 39], [0xF2, 39], [0xF1, 0], [0xF2, 0]] {
+    check_test_recv_value(
+      &mut device,
+      HardwareCommand::Write(HardwareWriteCmd::new(
+        message::Endpoint::Tx,
+        command.to_vec(),
+        false,
+      )),
+    );
+  }
+}
+
+#[cfg(feature = "server")]
+#[tokio::test]
+async fn test_client_device_transport_info() {
+  let (client, _) = test_client_with_device().await;
+
+  let mut event_stream = client.event_stream();
+  client
+    .start_scanning()
+    .await
+    .expect("Test, assuming infallible.");
+  let mut client_device = None;
+  while let Some(msg) = event_stream.next().await {
+    if let ButtplugClientEvent::DeviceAdded(da) = msg {
+      client_device = Some(da);
+      break;
+    }
+  }
+  let test_device = client_device.expect("Test, assuming infallible.");
+  assert!(test_device.protocol().is_none());
+  test_device
+    .request_device_info()
+    .await
+    .expect("Test, assuming infallible.");
+  assert_eq!(test_device.protocol().as_deref(), Some("aneros"));
+  assert_eq!(
+    test_device.transport(),
+    Some(message::DeviceTransport::BluetoothLE)
+  );
+  assert!(test_device.address().is_some());
+}
+
+#[cfg(feature = "server")]
+#[tokio::test]
+async fn test_client_device_firmware_version() {
+  use buttplug::server::device::hardware::HardwareCommand;
+
+  let (client, mut device) = test_client_with_named_device("LVS-Test").await;
+
+  let mut event_stream = client.event_stream();
+  client
+    .start_scanning()
+    .await
+    .expect("Test, assuming infallible.");
+  // Answer the firmware request Lovense devices get on connect.
+  while let Some(command) = device.receiver.recv().await {
+    if matches!(command, HardwareCommand::Write(_)) {
+      break;
+    }
+  }
+  device
+    .sender
+    .send(TestHardwareEvent::notification(
+      message::Endpoint::Rx,
+      b"Z:11:0082059AD3BD;",
+    ))
+    .await
+    .expect("Test, assuming infallible.");
+  let mut client_device = None;
+  while let Some(msg) = event_stream.next().await {
+    if let ButtplugClientEvent::DeviceAdded(da) = msg {
+      client_device = Some(da);
+      break;
+    }
+  }
+  let test_device = client_device.expect("Test, assuming infallible.");
+  assert!(test_device.firmware_version().is_none());
+  test_device
+    .request_device_info()
+    .await
+    .expect("Test, assuming infallible.");
+  assert_eq!(test_device.firmware_version().as_deref(), Some("11"));
+}
+
+#[cfg(feature = "server")]
+#[tokio::test]
+async fn test_client_pattern_session() {
+  let (client, _device) = test_client_with_device().await;
+
+  let mut event_stream = client.event_stream();
+  client
+    .start_scanning()
+    .await
+    .expect("Test, assuming infallible.");
+  let mut client_device = None;
+  while let Some(msg) = event_stream.next().await {
+    if let ButtplugClientEvent::DeviceAdded(da) = msg {
+      client_device = Some(da);
+      break;
+    }
+  }
+  let test_device = client_device.expect("Test, assuming infallible.");
+  let session_id = client
+    .start_pattern_session(StartPatternSessionV4::new(
+      vec![PatternTrackV4::new(
+        test_device.index(),
+        0,
+        vec![PatternStepV4::new(0.5, 10000)],
+      )],
+      10000,
+      false,
+      Some(50),
+    ))
+    .await
+    .expect("Test, assuming infallible.");
+  // The device is reserved for the session.
+  assert!(test_device
+    .vibrate(&ScalarValueCommand::ScalarValue(0.5))
+    .await
+    .is_err());
+  while let Some(msg) = event_stream.next().await {
+    if let ButtplugClientEvent::PatternSessionProgress(progress) = msg {
+      assert_eq!(progress.session_id(), session_id);
+      break;
+    }
+  }
+  client
+    .stop_pattern_session(session_id)
+    .await
+    .expect("Test, assuming infallible.");
+  while let Some(msg) = event_stream.next().await {
+    if let ButtplugClientEvent::PatternSessionEnded(ended) = msg {
+      assert_eq!(ended.session_id(), session_id);
+      assert_eq!(ended.reason(), PatternSessionEndReason::Cancelled);
+      break;
+    }
+  }
+  assert!(client.stop_pattern_session(session_id).await.is_err());
+}
+
 #[cfg(feature = "server")]
 #[tokio::test]
 async fn test_client_device_client_disconnected_status() {
@@ -303,9 +481,330 @@ async fn test_client_repeated_deviceremoved_message() {
   ));
 }
 
+#[cfg(feature = "server")]
+#[tokio::test]
+async fn test_client_device_pipelined_commands() {
+  use buttplug::core::message::{
+    ButtplugClientMessageV3,
+    ButtplugClientMessageVariant,
+    ButtplugMessage,
+    ButtplugServerMessageVariant,
+  };
+
+  let helper = Arc::new(util::channel_transport::ChannelClientTestHelper::new());
+  helper.simulate_successful_connect().await;
+  let mut event_stream = helper.client().event_stream();
+  helper
+    .send_client_incoming(ButtplugServerMessageVariant::V3(
+      message::DeviceAddedV3::new(
+        1,
+        "Test Device",
+        &None,
+        &None,
+        &ClientDeviceMessageAttributesV3::default(),
+      )
+      .into(),
+    ))
+    .await;
+  let device = match event_stream
+    .next()
+    .await
+    .expect("Test, assuming infallible.")
+  {
+    ButtplugClientEvent::DeviceAdded(device) => device,
+    _ => panic!("Expected DeviceAdded event"),
+  };
+  device.set_pipelined(true);
+  // Commands go out without their futures being polled or awaited, in order.
+  for _ in 0..3 {
+    drop(device.stop());
+  }
+  let mut ids = vec![];
+  for _ in 0..3 {
+    match helper.next_client_message().await {
+      ButtplugClientMessageVariant::V3(ButtplugClientMessageV3::StopDeviceCmd(msg)) => {
+        ids.push(msg.id())
+      }
+      msg => panic!("Expected StopDeviceCmd, got {:?}", msg),
+    }
+  }
+  assert!(ids.windows(2).all(|pair| pair[0] < pair[1]));
+
+  let mut acknowledged = device.acknowledged();
+  for id in &ids[0..2] {
+    helper
+      .send_client_incoming(ButtplugServerMessageVariant::V3(
+        message::OkV0::new(*id).into(),
+      ))
+      .await;
+  }
+  // Still waiting on the last command.
+  assert!(
+    tokio::time::timeout(Duration::from_millis(50), &mut acknowledged)
+      .await
+      .is_err()
+  );
+  let mut error = message::ErrorV0::from(ButtplugError::from(
+    ButtplugDeviceError::DeviceNotConnected("Test".to_owned()),
+  ));
+  error.set_id(ids[2]);
+  helper
+    .send_client_incoming(ButtplugServerMessageVariant::V3(error.into()))
+    .await;
+  assert!(matches!(
+    acknowledged.await.unwrap_err(),
+    ButtplugClientError::ButtplugError(ButtplugError::ButtplugDeviceError(
+      ButtplugDeviceError::DeviceNotConnected(..)
+    ))
+  ));
+  // Errors are only reported once, and with nothing outstanding we resolve immediately.
+  assert!(device.acknowledged().await.is_ok());
+}
+
+#[cfg(feature = "server")]
+#[tokio::test]
+async fn test_client_device_oscillate_linear() {
+  use buttplug::core::message::{
+    ActuatorType,
+    ButtplugClientMessageV3,
+    ButtplugClientMessageVariant,
+    ButtplugMessage,
+    ButtplugServerMessageVariant,
+    ClientDeviceMessageAttributesV3Builder,
+    ClientGenericDeviceMessageAttributesV3,
+  };
+
+  let helper = Arc::new(util::channel_transport::ChannelClientTestHelper::new());
+  helper.simulate_successful_connect().await;
+  let mut event_stream = helper.client().event_stream();
+  let mut builder = ClientDeviceMessageAttributesV3Builder::default();
+  builder.linear_cmd(&[ClientGenericDeviceMessageAttributesV3::new(
+    "Stroker",
+    100,
+    ActuatorType::Position,
+  )]);
+  let attrs = builder.finish();
+  helper
+    .send_client_incoming(ButtplugServerMessageVariant::V3(
+      message::DeviceAddedV3::new(1, "Test Device", &None, &None, &attrs).into(),
+    ))
+    .await;
+  let device = match event_stream
+    .next()
+    .await
+    .expect("Test, assuming infallible.")
+  {
+    ButtplugClientEvent::DeviceAdded(device) => device,
+    _ => panic!("Expected DeviceAdded event"),
+  };
+  assert!(device.oscillate_linear(0.8..=0.2, 1.0).is_err());
+  assert!(device.oscillate_linear(0.2..=0.8, 0.0).is_err());
+
+  // 5 strokes a second, so 100ms each way.
+  let oscillation = device
+    .oscillate_linear(0.2..=0.8, 5.0)
+    .expect("Test, assuming infallible.");
+  let mut positions = vec![];
+  for _ in 0..3 {
+    match helper.next_client_message().await {
+      ButtplugClientMessageVariant::V3(ButtplugClientMessageV3::LinearCmd(msg)) => {
+        let vector = msg.vectors()[0].clone();
+        assert!(vector.duration() <= 100);
+        positions.push(vector.position());
+        helper
+          .send_client_incoming(ButtplugServerMessageVariant::V3(
+            message::OkV0::new(msg.id()).into(),
+          ))
+          .await;
+      }
+      msg => panic!("Expected LinearCmd, got {:?}", msg),
+    }
+  }
+  assert_eq!(positions, vec![0.8, 0.2, 0.8]);
+  assert!(oscillation.is_running());
+
+  oscillation.stop();
+  assert!(!oscillation.is_running());
+  assert!(
+    tokio::time::timeout(Duration::from_millis(300), helper.next_client_message())
+      .await
+      .is_err()
+  );
+}
+
+#[cfg(feature = "server")]
+#[tokio::test]
+async fn test_client_device_removed_with_command_in_flight() {
+  use buttplug::core::message::{
+    ButtplugClientMessageV3,
+    ButtplugClientMessageVariant,
+    ButtplugMessage,
+    ButtplugServerMessageVariant,
+  };
+
+  let helper = Arc::new(util::channel_transport::ChannelClientTestHelper::new());
+  helper.simulate_successful_connect().await;
+  let mut event_stream = helper.client().event_stream();
+  helper
+    .send_client_incoming(ButtplugServerMessageVariant::V3(
+      message::DeviceAddedV3::new(
+        1,
+        "Test Device",
+        &None,
+        &None,
+        &ClientDeviceMessageAttributesV3::default(),
+      )
+      .into(),
+    ))
+    .await;
+  let device = match event_stream
+    .next()
+    .await
+    .expect("Test, assuming infallible.")
+  {
+    ButtplugClientEvent::DeviceAdded(device) => device,
+    _ => panic!("Expected DeviceAdded event"),
+  };
+  let in_flight = tokio::spawn(device.stop());
+  let id = match helper.next_client_message().await {
+    ButtplugClientMessageVariant::V3(ButtplugClientMessageV3::StopDeviceCmd(msg)) => msg.id(),
+    msg => panic!("Expected StopDeviceCmd, got {:?}", msg),
+  };
+  // The device goes away before the server gets around to answering the command.
+  helper
+    .send_client_incoming(ButtplugServerMessageVariant::V3(
+      message::DeviceRemovedV0::new(1).into(),
+    ))
+    .await;
+  assert!(matches!(
+    in_flight.await.expect("Test, assuming infallible."),
+    Err(ButtplugClientError::ButtplugError(
+      ButtplugError::ButtplugDeviceError(ButtplugDeviceError::DeviceDisconnected(1))
+    ))
+  ));
+  assert!(matches!(
+    event_stream
+      .next()
+      .await
+      .expect("Test, assuming infallible."),
+    ButtplugClientEvent::DeviceRemoved(..)
+  ));
+  assert!(!device.connected());
+
+  // A late reply to the drained command is dropped quietly, instead of showing up as an error.
+  let mut error = message::ErrorV0::from(ButtplugError::from(
+    ButtplugDeviceError::DeviceNotAvailable(1),
+  ));
+  error.set_id(id);
+  helper
+    .send_client_incoming(ButtplugServerMessageVariant::V3(error.into()))
+    .await;
+  assert!(
+    tokio::time::timeout(Duration::from_millis(50), event_stream.next())
+      .await
+      .is_err()
+  );
+
+  // New commands fail without going out to the server.
+  assert!(matches!(
+    device.stop().await,
+    Err(ButtplugClientError::ButtplugError(
+      ButtplugError::ButtplugDeviceError(ButtplugDeviceError::DeviceDisconnected(1))
+    ))
+  ));
+  assert!(
+    tokio::time::timeout(Duration::from_millis(50), helper.next_client_message())
+      .await
+      .is_err()
+  );
+}
+
 // TODO Test invalid messages to device
 // TODO Test invalid parameters in message
 // TODO Test device invalidation across client connections (i.e. a device shouldn't be allowed to reconnect even if index is the same)
 // TODO Test DeviceList being sent followed by repeat DeviceAdded
 // TODO Test DeviceList being sent multiple times
 // TODO Test sending device return for device that doesn't exist (in client)
+
+#[cfg(feature = "server")]
+#[tokio::test]
+async fn test_client_device_handle_survives_reconnect() {
+  use buttplug::{
+    client::ButtplugClient,
+    core::connector::ButtplugInProcessClientConnectorBuilder,
+    server::{device::ServerDeviceManagerBuilder, ButtplugServerBuilder},
+  };
+  use util::{
+    create_test_dcm,
+    test_device_manager::TestDeviceIdentifier,
+    TestDeviceCommunicationManagerBuilder,
+  };
+
+  let mut builder = TestDeviceCommunicationManagerBuilder::default();
+  let identifier = TestDeviceIdentifier::new("Massage Demo", Some("HandleAddress".to_owned()));
+  let device = builder.add_test_device(&identifier);
+  let _reconnected_device = builder.add_test_device_on_rescan(&identifier);
+  let mut dm_builder = ServerDeviceManagerBuilder::new(create_test_dcm(false));
+  dm_builder.comm_manager(builder);
+  let connector = ButtplugInProcessClientConnectorBuilder::default()
+    .server(
+      ButtplugServerBuilder::new(dm_builder.finish().unwrap())
+        .finish()
+        .unwrap(),
+    )
+    .finish();
+  let client = ButtplugClient::new("Test Client");
+  client
+    .connect(connector)
+    .await
+    .expect("Test, assuming infallible.");
+
+  let mut event_stream = client.event_stream();
+  client
+    .start_scanning()
+    .await
+    .expect("Test, assuming infallible.");
+  let mut client_device = None;
+  while let Some(msg) = event_stream.next().await {
+    if let ButtplugClientEvent::DeviceAdded(da) = msg {
+      client_device = Some(da);
+      break;
+    }
+  }
+  let test_device = client_device.expect("Test, assuming infallible.");
+  let mut device_event_stream = test_device.event_stream();
+  device
+    .sender
+    .send(TestHardwareEvent::Disconnect)
+    .await
+    .expect("Test, assuming infallible.");
+  while let Some(msg) = device_event_stream.next().await {
+    if let ButtplugClientDeviceEvent::DeviceRemoved = msg {
+      break;
+    }
+  }
+  assert!(!test_device.is_connected());
+  assert!(test_device
+    .vibrate(&ScalarValueCommand::ScalarValue(0.5))
+    .await
+    .is_err());
+
+  client
+    .start_scanning()
+    .await
+    .expect("Test, assuming infallible.");
+  while let Some(msg) = device_event_stream.next().await {
+    if let ButtplugClientDeviceEvent::DeviceReconnected = msg {
+      break;
+    }
+  }
+  assert!(test_device.is_connected());
+  // The client hands out the same handle it did the first time.
+  let devices = client.devices();
+  assert_eq!(devices.len(), 1);
+  assert!(Arc::ptr_eq(&devices[0], &test_device));
+  test_device
+    .vibrate(&ScalarValueCommand::ScalarValue(0.5))
+    .await
+    .expect("Test, assuming infallible.");
+}
@@ -8,7 +8,15 @@
 mod util;
 extern crate buttplug;
 
-use buttplug::util::device_configuration::load_protocol_configs;
+use buttplug::{
+  server::device::configuration::{
+    BluetoothLEConnectionParameters,
+    BluetoothLESpecifier,
+    ProtocolCommunicationSpecifier,
+  },
+  util::device_configuration::load_protocol_configs,
+};
+use std::{collections::HashMap, env};
 use tokio_test::assert_ok;
 
 const BASE_CONFIG_JSON: &str = r#"
@@ -195,6 +203,239 @@ async fn test_valid_user_config() {
   .is_err());
 }
 
+#[cfg(feature = "server")]
+#[tokio::test]
+async fn test_internal_config_builders_are_independent() {
+  // The internal config is parsed once and shared, so make sure changes to one builder don't leak
+  // into the next.
+  let mut first_builder = load_protocol_configs(&None, &None, false).unwrap();
+  first_builder.user_communication_specifier(
+    "lovense",
+    &[ProtocolCommunicationSpecifier::BluetoothLE(
+      BluetoothLESpecifier::new_from_device("LVS-Test", &HashMap::new(), &[]),
+    )],
+  );
+  let first_dcm = first_builder.finish().unwrap();
+  assert_eq!(first_dcm.user_communication_specifiers().len(), 1);
+
+  let second_dcm = load_protocol_configs(&None, &None, false)
+    .unwrap()
+    .finish()
+    .unwrap();
+  assert!(second_dcm.user_communication_specifiers().is_empty());
+}
+
+#[cfg(feature = "server")]
+#[tokio::test]
+async fn test_internal_config_has_no_invalid_definitions() {
+  let dcm = load_protocol_configs(&None, &None, false)
+    .unwrap()
+    .finish()
+    .unwrap();
+  let invalid: Vec<String> = dcm
+    .invalid_definitions()
+    .iter()
+    .map(|invalid| invalid.to_string())
+    .collect();
+  assert!(invalid.is_empty(), "Invalid definitions: {:?}", invalid);
+}
+
+#[cfg(feature = "server")]
+#[tokio::test]
+async fn test_cached_internal_config_version_check() {
+  // The internal config is parsed once and cached, so loading it with and without version checks
+  // in any order should give the same result each time.
+  for skip_version_check in [true, false, true] {
+    let dcm = load_protocol_configs(&None, &None, skip_version_check)
+      .unwrap()
+      .finish()
+      .unwrap();
+    assert!(!dcm.protocol_device_configurations().is_empty());
+  }
+}
+
+#[cfg(feature = "server")]
+#[tokio::test]
+async fn test_platform_override_device_config() {
+  let other_platform = if env::consts::OS == "windows" {
+    "linux"
+  } else {
+    "windows"
+  };
+  let btle_json = |name: &str| {
+    format!(
+      r#"[{{
+        "btle": {{
+          "names": ["{name}"],
+          "services": {{
+            "0000fff0-0000-1000-8000-00805f9b34fb": {{
+              "tx": "0000fff2-0000-1000-8000-00805f9b34fb"
+            }}
+          }}
+        }}
+      }}]"#
+    )
+  };
+  let user_config_json = format!(
+    r#"{{
+      "version": {{
+        "major": 3,
+        "minor": 0
+      }},
+      "user-configs": {{
+        "protocols": {{
+          "lovense": {{
+            "communication": {},
+            "platforms": {{
+              "{}": {{ "communication": {} }},
+              "{}": {{ "communication": {} }}
+            }}
+          }}
+        }}
+      }}
+    }}"#,
+    btle_json("LVS-Base"),
+    env::consts::OS,
+    btle_json("LVS-ThisPlatform"),
+    other_platform,
+    btle_json("LVS-OtherPlatform"),
+  );
+  let dcm = load_protocol_configs(&None, &Some(user_config_json), false)
+    .unwrap()
+    .finish()
+    .unwrap();
+  let specifiers = dcm.user_communication_specifiers();
+  let lovense_specifiers = specifiers.get("lovense").unwrap();
+  assert_eq!(lovense_specifiers.len(), 1);
+  let ProtocolCommunicationSpecifier::BluetoothLE(btle) = &lovense_specifiers[0] else {
+    panic!("Expected a bluetooth specifier");
+  };
+  assert!(btle.names().contains("LVS-ThisPlatform"));
+}
+
+#[cfg(feature = "server")]
+#[tokio::test]
+async fn test_btle_connection_parameters_device_config() {
+  let user_config_json = r#"{
+    "version": {
+      "major": 3,
+      "minor": 0
+    },
+    "user-configs": {
+      "protocols": {
+        "lovense": {
+          "communication": [{
+            "btle": {
+              "names": ["LVS-Fast"],
+              "services": {
+                "0000fff0-0000-1000-8000-00805f9b34fb": {
+                  "tx": "0000fff2-0000-1000-8000-00805f9b34fb"
+                }
+              },
+              "connection-parameters": {
+                "interval": 15
+              }
+            }
+          }]
+        }
+      }
+    }
+  }"#;
+  let dcm = load_protocol_configs(&None, &Some(user_config_json.to_owned()), false)
+    .unwrap()
+    .finish()
+    .unwrap();
+  let specifiers = dcm.user_communication_specifiers();
+  let ProtocolCommunicationSpecifier::BluetoothLE(btle) = &specifiers.get("lovense").unwrap()[0]
+  else {
+    panic!("Expected a bluetooth specifier");
+  };
+  assert_eq!(
+    *btle.connection_parameters(),
+    Some(BluetoothLEConnectionParameters::new(Some(15), None))
+  );
+
+  // Intervals below what BLE allows are rejected by the schema.
+  let invalid_json = user_config_json.replace("\"interval\": 15", "\"interval\": 1");
+  assert!(load_protocol_configs(&None, &Some(invalid_json), false).is_err());
+}
+
+#[cfg(feature = "server")]
+#[tokio::test]
+async fn test_btle_write_weight_device_config() {
+  let user_config_json = r#"{
+    "version": {
+      "major": 3,
+      "minor": 0
+    },
+    "user-configs": {
+      "protocols": {
+        "lovense": {
+          "communication": [{
+            "btle": {
+              "names": ["LVS-Chatty"],
+              "write-weight": 4
+            }
+          }]
+        }
+      }
+    }
+  }"#;
+  let dcm = load_protocol_configs(&None, &Some(user_config_json.to_owned()), false)
+    .unwrap()
+    .finish()
+    .unwrap();
+  let specifiers = dcm.user_communication_specifiers();
+  let ProtocolCommunicationSpecifier::BluetoothLE(btle) = &specifiers.get("lovense").unwrap()[0]
+  else {
+    panic!("Expected a bluetooth specifier");
+  };
+  assert_eq!(*btle.write_weight(), Some(4));
+
+  // Devices always get at least one write per turn.
+  let invalid_json = user_config_json.replace("\"write-weight\": 4", "\"write-weight\": 0");
+  assert!(load_protocol_configs(&None, &Some(invalid_json), false).is_err());
+}
+
+#[cfg(feature = "server")]
+#[tokio::test]
+async fn test_user_btle_names_inherit_protocol_services() {
+  let user_config_json = r#"{
+    "version": {
+      "major": 3,
+      "minor": 0
+    },
+    "user-configs": {
+      "protocols": {
+        "luvmazer": {
+          "communication": [{
+            "btle": {
+              "names": ["NoName Bullet"]
+            }
+          }]
+        }
+      }
+    }
+  }"#;
+  let dcm = load_protocol_configs(&None, &Some(user_config_json.to_owned()), false)
+    .unwrap()
+    .finish()
+    .unwrap();
+  let specializers = dcm.protocol_specializers(&ProtocolCommunicationSpecifier::BluetoothLE(
+    BluetoothLESpecifier::new_from_device("NoName Bullet", &HashMap::new(), &[]),
+  ));
+  assert_eq!(specializers.len(), 1);
+  let Some(ProtocolCommunicationSpecifier::BluetoothLE(btle)) = specializers[0]
+    .specifiers()
+    .iter()
+    .find(|specifier| matches!(specifier, ProtocolCommunicationSpecifier::BluetoothLE(_)))
+  else {
+    panic!("Expected a bluetooth specifier");
+  };
+  assert!(btle.names().contains("NoName Bullet"));
+  assert!(!btle.services().is_empty());
+}
+
 #[cfg(feature = "server")]
 #[tokio::test]
 async fn test_invalid_step_range_device_config_wrong_range_length() {
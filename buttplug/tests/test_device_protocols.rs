@@ -39,6 +39,8 @@ async fn load_test_case(test_file: &str) -> DeviceTestCase {
 #[test_case("test_lovense_flexer_fw3.yaml" ; "Lovense Protocol - Flexer FW3")]
 #[test_case("test_lovense_edge.yaml" ; "Lovense Protocol - Edge")]
 #[test_case("test_lovense_osci3.yaml" ; "Lovense Protocol - Osci3")]
+#[test_case("test_lovense_solace_pro.yaml" ; "Lovense Protocol - Solace Pro (Oscillate/Position)")]
+#[test_case("test_luvmazer_protocol.yaml" ; "Luvmazer Protocol")]
 #[test_case("test_user_config_display_name.yaml" ; "User Config Display Name")]
 #[test_case("test_satisfyer_single_vibrator.yaml" ; "Satisfyer Protocol - Single Vibrator")]
 #[test_case("test_satisfyer_dual_vibrator.yaml" ; "Satisfyer Protocol - Dual Vibrator")]
@@ -50,6 +52,7 @@ async fn load_test_case(test_file: &str) -> DeviceTestCase {
 #[test_case("test_vorze_ufo.yaml" ; "Vorze Protocol - UFO")]
 #[test_case("test_vorze_ufo_tw.yaml" ; "Vorze Protocol - UFO TW")]
 #[test_case("test_vorze_cyclone.yaml" ; "Vorze Protocol - Cyclone")]
+#[test_case("test_vorze_piston.yaml" ; "Vorze Protocol - Piston")]
 #[test_case("test_wevibe_4plus.yaml" ; "WeVibe Protocol (Legacy) - 4 Plus")]
 #[test_case("test_wevibe_pivot.yaml" ; "WeVibe Protocol (Legacy) - Pivot")]
 #[test_case("test_wevibe_vector.yaml" ; "WeVibe Protocol (8bit) - Vector")]
@@ -96,6 +99,7 @@ async fn load_test_case(test_file: &str) -> DeviceTestCase {
 #[test_case("test_svakom_mora_neo.yaml" ; "Svakom Mora Neo")]
 #[test_case("test_fox_protocol.yaml" ; "Fox Protocol")]
 #[test_case("test_sakuraneko_koikoi.yaml" ; "Sakuraneko Protocol - Koikoi")]
+#[test_case("test_sakuraneko_nexus.yaml" ; "Sakuraneko Protocol - Nexus (Vibrate/Estim)")]
 #[test_case("test_xiuxiuda_protocol.yaml" ; "Xiuxiuda Protocol")]
 #[test_case("test_longlosttouch_protocol.yaml" ; "LongLostTouch Protocol")]
 #[test_case("test_adrienlastic_protocol.yaml" ; "Adrien Lastic Protocol")]
@@ -116,6 +120,8 @@ async fn load_test_case(test_file: &str) -> DeviceTestCase {
 #[test_case("test_deepsire.yaml" ; "DeepSire Protocol")]
 #[test_case("test_xuanhuan_protocol.yaml" ; "Xuanhuan Protocol")]
 #[test_case("test_tcode_linear_and_vibrate.yaml" ; "TCode (Linear + Vibrate)")]
+#[test_case("test_tcode_emulated_vibrate.yaml" ; "TCode (Linear, Emulated Vibrate)")]
+#[test_case("test_tcode_emulated_linear.yaml" ; "TCode (Vibrate, Emulated Linear)")]
 #[test_case("test_serveu_protocol.yaml" ; "ServeU")]
 #[test_case("test_kiiroo_prowand.yaml" ; "Kiiroo ProWand Protocol")]
 #[test_case("test_fleshy_thrust_protocol.yaml" ; "Fleshy Thrust Sync Protocol")]
@@ -148,6 +154,8 @@ async fn test_device_protocols_embedded_v3(test_file: &str) {
 #[test_case("test_lovense_flexer_fw3.yaml" ; "Lovense Protocol - Flexer FW3")]
 #[test_case("test_lovense_edge.yaml" ; "Lovense Protocol - Edge")]
 #[test_case("test_lovense_osci3.yaml" ; "Lovense Protocol - Osci3")]
+#[test_case("test_lovense_solace_pro.yaml" ; "Lovense Protocol - Solace Pro (Oscillate/Position)")]
+#[test_case("test_luvmazer_protocol.yaml" ; "Luvmazer Protocol")]
 #[test_case("test_user_config_display_name.yaml" ; "User Config Display Name")]
 #[test_case("test_satisfyer_single_vibrator.yaml" ; "Satisfyer Protocol - Single Vibrator")]
 #[test_case("test_satisfyer_dual_vibrator.yaml" ; "Satisfyer Protocol - Dual Vibrator")]
@@ -160,6 +168,7 @@ async fn test_device_protocols_embedded_v3(test_file: &str) {
 #[test_case("test_vorze_ufo.yaml" ; "Vorze Protocol - UFO")]
 #[test_case("test_vorze_ufo_tw.yaml" ; "Vorze Protocol - UFO TW")]
 #[test_case("test_vorze_cyclone.yaml" ; "Vorze Protocol - Cyclone")]
+#[test_case("test_vorze_piston.yaml" ; "Vorze Protocol - Piston")]
 #[test_case("test_wevibe_4plus.yaml" ; "WeVibe Protocol (Legacy) - 4 Plus")]
 #[test_case("test_wevibe_pivot.yaml" ; "WeVibe Protocol (Legacy) - Pivot")]
 #[test_case("test_wevibe_vector.yaml" ; "WeVibe Protocol (8bit) - Vector")]
@@ -206,6 +215,7 @@ async fn test_device_protocols_embedded_v3(test_file: &str) {
 #[test_case("test_svakom_iker.yaml" ; "Svakom Iker")]
 #[test_case("test_fox_protocol.yaml" ; "Fox Protocol")]
 #[test_case("test_sakuraneko_koikoi.yaml" ; "Sakuraneko Protocol - Koikoi")]
+#[test_case("test_sakuraneko_nexus.yaml" ; "Sakuraneko Protocol - Nexus (Vibrate/Estim)")]
 #[test_case("test_xiuxiuda_protocol.yaml" ; "Xiuxiuda Protocol")]
 #[test_case("test_adrienlastic_protocol.yaml" ; "Adrien Lastic Protocol")]
 #[test_case("test_foreo_protocol.yaml" ; "Foreo Protocol")]
@@ -225,6 +235,8 @@ async fn test_device_protocols_embedded_v3(test_file: &str) {
 #[test_case("test_deepsire.yaml" ; "DeepSire Protocol")]
 #[test_case("test_xuanhuan_protocol.yaml" ; "Xuanhuan Protocol")]
 #[test_case("test_tcode_linear_and_vibrate.yaml" ; "TCode (Linear + Vibrate)")]
+#[test_case("test_tcode_emulated_vibrate.yaml" ; "TCode (Linear, Emulated Vibrate)")]
+#[test_case("test_tcode_emulated_linear.yaml" ; "TCode (Vibrate, Emulated Linear)")]
 #[test_case("test_serveu_protocol.yaml" ; "ServeU")]
 #[test_case("test_kiiroo_prowand.yaml" ; "Kiiroo ProWand Protocol")]
 #[test_case("test_fleshy_thrust_protocol.yaml" ; "Fleshy Thrust Sync Protocol")]
@@ -247,6 +259,7 @@ async fn test_device_protocols_json_v3(test_file: &str) {
 #[test_case("test_lovense_flexer_fw2.yaml" ; "Lovense Protocol - Flexer FW2")]
 #[test_case("test_lovense_edge.yaml" ; "Lovense Protocol - Edge")]
 #[test_case("test_lovense_osci3.yaml" ; "Lovense Protocol - Osci3")]
+#[test_case("test_luvmazer_protocol.yaml" ; "Luvmazer Protocol")]
 #[test_case("test_satisfyer_single_vibrator.yaml" ; "Satisfyer Protocol - Single Vibrator")]
 #[test_case("test_satisfyer_dual_vibrator.yaml" ; "Satisfyer Protocol - Dual Vibrator")]
 #[test_case("test_satisfyer_triple_vibrator.yaml" ; "Satisfyer Protocol - Triple Vibrator")]
@@ -258,6 +271,7 @@ async fn test_device_protocols_json_v3(test_file: &str) {
 #[test_case("test_vorze_ufo.yaml" ; "Vorze Protocol - UFO")]
 #[test_case("test_vorze_ufo_tw.yaml" ; "Vorze Protocol - UFO TW")]
 #[test_case("test_vorze_cyclone.yaml" ; "Vorze Protocol - Cyclone")]
+#[test_case("test_vorze_piston.yaml" ; "Vorze Protocol - Piston")]
 #[test_case("test_wevibe_4plus.yaml" ; "WeVibe Protocol (Legacy) - 4 Plus")]
 #[test_case("test_wevibe_pivot.yaml" ; "WeVibe Protocol (Legacy) - Pivot")]
 #[test_case("test_wevibe_vector.yaml" ; "WeVibe Protocol (8bit) - Vector")]
@@ -304,6 +318,8 @@ async fn test_device_protocols_json_v3(test_file: &str) {
 #[test_case("test_deepsire.yaml" ; "DeepSire Protocol")]
 #[test_case("test_xuanhuan_protocol.yaml" ; "Xuanhuan Protocol")]
 #[test_case("test_tcode_linear_and_vibrate.yaml" ; "TCode (Linear + Vibrate)")]
+#[test_case("test_tcode_emulated_vibrate.yaml" ; "TCode (Linear, Emulated Vibrate)")]
+#[test_case("test_tcode_emulated_linear.yaml" ; "TCode (Vibrate, Emulated Linear)")]
 #[test_case("test_serveu_protocol.yaml" ; "ServeU")]
 #[test_case("test_kiiroo_prowand.yaml" ; "Kiiroo ProWand Protocol")]
 #[test_case("test_fleshy_thrust_protocol.yaml" ; "Fleshy Thrust Sync Protocol")]
@@ -325,6 +341,7 @@ async fn test_device_protocols_embedded_v2(test_file: &str) {
 #[test_case("test_lovense_battery_non_default.yaml" ; "Lovense Protocol - Lovense Battery (Non-Default Devices)")]
 #[test_case("test_lovense_edge.yaml" ; "Lovense Protocol - Edge")]
 #[test_case("test_lovense_osci3.yaml" ; "Lovense Protocol - Osci3")]
+#[test_case("test_luvmazer_protocol.yaml" ; "Luvmazer Protocol")]
 #[test_case("test_satisfyer_single_vibrator.yaml" ; "Satisfyer Protocol - Single Vibrator")]
 #[test_case("test_satisfyer_dual_vibrator.yaml" ; "Satisfyer Protocol - Dual Vibrator")]
 #[test_case("test_satisfyer_triple_vibrator.yaml" ; "Satisfyer Protocol - Triple Vibrator")]
@@ -336,6 +353,7 @@ async fn test_device_protocols_embedded_v2(test_file: &str) {
 #[test_case("test_vorze_ufo.yaml" ; "Vorze Protocol - UFO")]
 #[test_case("test_vorze_ufo_tw.yaml" ; "Vorze Protocol - UFO TW")]
 #[test_case("test_vorze_cyclone.yaml" ; "Vorze Protocol - Cyclone")]
+#[test_case("test_vorze_piston.yaml" ; "Vorze Protocol - Piston")]
 #[test_case("test_wevibe_4plus.yaml" ; "WeVibe Protocol (Legacy) - 4 Plus")]
 #[test_case("test_wevibe_pivot.yaml" ; "WeVibe Protocol (Legacy) - Pivot")]
 #[test_case("test_wevibe_vector.yaml" ; "WeVibe Protocol (8bit) - Vector")]
@@ -382,6 +400,8 @@ async fn test_device_protocols_embedded_v2(test_file: &str) {
 #[test_case("test_deepsire.yaml" ; "DeepSire Protocol")]
 #[test_case("test_xuanhuan_protocol.yaml" ; "Xuanhuan Protocol")]
 #[test_case("test_tcode_linear_and_vibrate.yaml" ; "TCode (Linear + Vibrate)")]
+#[test_case("test_tcode_emulated_vibrate.yaml" ; "TCode (Linear, Emulated Vibrate)")]
+#[test_case("test_tcode_emulated_linear.yaml" ; "TCode (Vibrate, Emulated Linear)")]
 #[test_case("test_serveu_protocol.yaml" ; "ServeU")]
 #[test_case("test_kiiroo_prowand.yaml" ; "Kiiroo ProWand Protocol")]
 #[test_case("test_fleshy_thrust_protocol.yaml" ; "Fleshy Thrust Sync Protocol")]
@@ -0,0 +1,110 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2024 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+mod util;
+
+#[cfg(feature = "direct-device")]
+mod test {
+  use crate::util::{
+    create_test_dcm,
+    test_device_manager::{
+      check_test_recv_value,
+      TestDeviceCommunicationManagerBuilder,
+      TestDeviceIdentifier,
+      TestHardwareEvent,
+    },
+  };
+  use buttplug::{
+    core::{
+      errors::{ButtplugDeviceError, ButtplugError},
+      message::{ButtplugDeviceMessageType, Endpoint},
+    },
+    direct_device::{DirectDeviceEvent, DirectDeviceManagerBuilder},
+    server::device::hardware::{HardwareCommand, HardwareWriteCmd},
+  };
+  use futures::{pin_mut, StreamExt};
+
+  #[tokio::test]
+  async fn test_direct_device_control() {
+    let mut builder = TestDeviceCommunicationManagerBuilder::default();
+    let mut device = builder.add_test_device(&TestDeviceIdentifier::new("Massage Demo", None));
+    let manager = DirectDeviceManagerBuilder::new(create_test_dcm(false))
+      .comm_manager(builder)
+      .finish()
+      .expect("Test, assuming infallible.");
+    let events = manager.event_stream();
+    pin_mut!(events);
+    manager
+      .start_scanning()
+      .await
+      .expect("Test, assuming infallible.");
+    let direct_device = loop {
+      match events.next().await.expect("Test, assuming infallible.") {
+        DirectDeviceEvent::DeviceAdded(device) => break device,
+        DirectDeviceEvent::ScanningFinished => continue,
+        DirectDeviceEvent::DeviceRemoved(_) => panic!("Device removed before it was added"),
+      }
+    };
+    assert_eq!(direct_device.name(), "Aneros Vivi");
+    assert!(direct_device.connected());
+    assert_eq!(manager.devices().len(), 1);
+
+    // Both of the device's vibrators get the level.
+    direct_device
+      .vibrate(0.5)
+      .await
+      .expect("Test, assuming infallible.");
+    check_test_recv_value(
+      &mut device,
+      HardwareCommand::Write(HardwareWriteCmd::new(Endpoint::Tx, vec![0xF1, 64], false)),
+    );
+    check_test_recv_value(
+      &mut device,
+      HardwareCommand::Write(HardwareWriteCmd::new(Endpoint::Tx, vec![0xF2, 64], false)),
+    );
+    assert!(matches!(
+      direct_device.rotate(0.5, true).await,
+      Err(ButtplugError::ButtplugDeviceError(
+        ButtplugDeviceError::MessageNotSupported(ButtplugDeviceMessageType::RotateCmd)
+      ))
+    ));
+    direct_device
+      .stop()
+      .await
+      .expect("Test, assuming infallible.");
+    check_test_recv_value(
+      &mut device,
+      HardwareCommand::Write(HardwareWriteCmd::new(Endpoint::Tx, vec![0xF1, 0], false)),
+    );
+    check_test_recv_value(
+      &mut device,
+      HardwareCommand::Write(HardwareWriteCmd::new(Endpoint::Tx, vec![0xF2, 0], false)),
+    );
+
+    device
+      .sender
+      .send(TestHardwareEvent::Disconnect)
+      .await
+      .expect("Test, assuming infallible.");
+    loop {
+      if let DirectDeviceEvent::DeviceRemoved(index) =
+        events.next().await.expect("Test, assuming infallible.")
+      {
+        assert_eq!(index, direct_device.index());
+        break;
+      }
+    }
+    assert!(!direct_device.connected());
+    assert!(matches!(
+      direct_device.vibrate(0.5).await,
+      Err(ButtplugError::ButtplugDeviceError(
+        ButtplugDeviceError::DeviceNotAvailable(_)
+      ))
+    ));
+    assert!(manager.shutdown().await.is_ok());
+  }
+}
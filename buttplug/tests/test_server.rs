@@ -13,6 +13,7 @@ pub use util::{
     check_test_recv_value,
     TestDeviceCommunicationManagerBuilder,
     TestDeviceIdentifier,
+    TestHardwareEvent,
   },
   test_server_with_comm_manager,
   test_server_with_device,
@@ -20,7 +21,8 @@ pub use util::{
 
 use buttplug::{
   core::{
-    errors::{ButtplugDeviceError, ButtplugError, ButtplugHandshakeError},
+    errors::{ButtplugDeviceError, ButtplugError, ButtplugHandshakeError, ButtplugMessageError},
+    ButtplugResultFuture,
     message::{
       self,
       ButtplugMessageSpecVersion,
@@ -34,16 +36,43 @@ use buttplug::{
   },
   server::{
     device::{
-      hardware::{HardwareCommand, HardwareWriteCmd},
+      hardware::{
+        communication::{
+          spawn_manager_task,
+          HardwareCommunicationManager,
+          HardwareCommunicationManagerBuilder,
+          HardwareCommunicationManagerEvent,
+          HardwareCommunicationManagerStatus,
+        },
+        HardwareCommand,
+        HardwareWriteCmd,
+      },
+      CommandAuditEvent,
+      EnergyBudgetAction,
+      EnergyBudgetEvent,
+      EnergyBudgetPolicy,
       ServerDeviceManagerBuilder,
     },
+    server_storage::USER_DEVICE_CONFIG_STORAGE_KEY,
+    ButtplugServer,
     ButtplugServerBuilder,
     ButtplugServerDowngradeWrapper,
+    ServerStorage,
+    ServerStorageError,
   },
 };
-use futures::{pin_mut, Stream, StreamExt};
-use std::time::Duration;
-use tokio::time::sleep;
+use futures::{future, pin_mut, FutureExt, Stream, StreamExt};
+use std::{
+  collections::HashMap,
+  sync::{
+    atomic::{AtomicU32, Ordering},
+    Arc,
+    Mutex,
+  },
+  time::Duration,
+};
+use tokio::{sync::mpsc::Sender, time::sleep};
+use tokio_util::sync::CancellationToken;
 
 async fn setup_test_server(
   msg_union: message::ButtplugClientMessageV3,
@@ -152,7 +181,9 @@ async fn test_ping_timeout() {
   let msg = message::RequestServerInfoV1::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION);
   sleep(Duration::from_millis(150)).await;
   let reply = server
-    .parse_message(message::ButtplugClientMessageV4::RequestServerInfo(msg))
+    .parse_message(message::ButtplugClientMessageV4::RequestServerInfo(
+      msg.into(),
+    ))
     .await;
   assert!(
     reply.is_ok(),
@@ -297,6 +328,512 @@ async fn test_invalid_device_index() {
   }
 }
 
+#[tokio::test]
+async fn test_observer_client() {
+  let mut builder = TestDeviceCommunicationManagerBuilder::default();
+  let mut _device = builder.add_test_device(&TestDeviceIdentifier::new("Massage Demo", None));
+  let server = test_server_with_comm_manager(builder, false);
+  let recv = server.event_stream();
+  pin_mut!(recv);
+  assert!(server
+    .parse_message(
+      message::RequestServerInfoV4::new("Overlay", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION, true)
+        .into()
+    )
+    .await
+    .is_ok());
+  assert!(server.client_is_observer());
+
+  let is_observer_error = |err: message::ErrorV0| {
+    matches!(
+      err.original_error(),
+      ButtplugError::ButtplugMessageError(ButtplugMessageError::ObserverNotAllowed(_))
+    )
+  };
+  let err = server
+    .parse_message(message::StartScanningV0::default().into())
+    .await
+    .unwrap_err();
+  assert!(is_observer_error(err));
+
+  // Devices found by something other than the observer still show up for it.
+  assert!(server
+    .device_manager()
+    .parse_message(message::StartScanningV0::default().into())
+    .await
+    .is_ok());
+  loop {
+    match recv.next().await.expect("Test, assuming infallible") {
+      ButtplugServerMessageV4::DeviceAdded(_) => break,
+      ButtplugServerMessageV4::ScanningFinished(_) => continue,
+      msg => panic!("Unexpected event {:?}", msg),
+    }
+  }
+  assert!(server
+    .parse_message(message::RequestDeviceListV4::default().into())
+    .await
+    .is_ok());
+  let err = server
+    .parse_message(
+      message::ScalarCmdV4::new(
+        0,
+        vec![message::ScalarSubcommandV4::new(
+          0,
+          0.5,
+          message::ActuatorType::Vibrate,
+        )],
+      )
+      .into(),
+    )
+    .await
+    .unwrap_err();
+  assert!(is_observer_error(err));
+  let err = server
+    .parse_message(message::StopAllDevicesV0::default().into())
+    .await
+    .unwrap_err();
+  assert!(is_observer_error(err));
+
+  // The role goes away with the connection.
+  assert!(server.disconnect().await.is_ok());
+  assert!(!server.client_is_observer());
+}
+
+#[tokio::test]
+async fn test_server_statistics() {
+  let mut builder = TestDeviceCommunicationManagerBuilder::default();
+  let mut _device = builder.add_test_device(&TestDeviceIdentifier::new("Massage Demo", None));
+  let server = test_server_with_comm_manager(builder, false);
+  let recv = server.event_stream();
+  pin_mut!(recv);
+  // Messages before the handshake are rejected, but still count for the server.
+  assert!(server
+    .parse_message(message::StartScanningV0::default().into())
+    .await
+    .is_err());
+  assert!(server.statistics().session_uptime().is_none());
+  assert!(server
+    .parse_message(
+      message::RequestServerInfoV1::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION)
+        .into()
+    )
+    .await
+    .is_ok());
+  assert!(server
+    .parse_message(message::StartScanningV0::default().into())
+    .await
+    .is_ok());
+  while !matches!(
+    recv.next().await.expect("Test, assuming infallible"),
+    ButtplugServerMessageV4::DeviceAdded(_)
+  ) {}
+  let vibrate = |device_index| {
+    message::ScalarCmdV4::new(
+      device_index,
+      vec![message::ScalarSubcommandV4::new(
+        0,
+        0.5,
+        message::ActuatorType::Vibrate,
+      )],
+    )
+    .into()
+  };
+  assert!(server.parse_message(vibrate(0)).await.is_ok());
+  assert!(server.parse_message(vibrate(5)).await.is_err());
+
+  let statistics = server.statistics();
+  assert!(statistics.session_uptime().is_some());
+  assert!(statistics.uptime() >= statistics.session_uptime().unwrap());
+  assert_eq!(statistics.server().messages_processed(), 5);
+  assert_eq!(statistics.server().errors(), 2);
+  assert_eq!(statistics.session().messages_processed(), 4);
+  assert_eq!(statistics.session().errors(), 1);
+  assert_eq!(
+    *statistics.device_command_counts(),
+    HashMap::from([(0, 1), (5, 1)])
+  );
+
+  // Session counts stick around after disconnect, until the next client shows up. Disconnecting
+  // sends StopScanning and StopAllDevices, which count too.
+  assert!(server.disconnect().await.is_ok());
+  let statistics = server.statistics();
+  assert!(statistics.session_uptime().is_none());
+  assert_eq!(statistics.session().messages_processed(), 6);
+}
+
+#[tokio::test]
+async fn test_device_manager_without_client() {
+  let mut builder = TestDeviceCommunicationManagerBuilder::default();
+  let mut device = builder.add_test_device(&TestDeviceIdentifier::new("Massage Demo", None));
+  let server = test_server_with_comm_manager(builder, false);
+  let device_manager = server.device_manager();
+  let recv = device_manager.event_stream();
+  pin_mut!(recv);
+  // No handshake, nobody's connected, but the device manager is still usable on its own.
+  assert!(device_manager.start_scanning().await.is_ok());
+  while !matches!(
+    recv.next().await.expect("Test, assuming infallible"),
+    ButtplugServerMessageV4::DeviceAdded(_)
+  ) {}
+  assert!(device_manager.stop_scanning().await.is_ok());
+  let devices = device_manager.device_list();
+  assert_eq!(devices.len(), 1);
+  assert_eq!(devices[0].device_name(), "Aneros Vivi");
+
+  let vibrate = |device_index, feature_index| {
+    message::ScalarCmdV4::new(
+      device_index,
+      vec![message::ScalarSubcommandV4::new(
+        feature_index,
+        0.5,
+        message::ActuatorType::Vibrate,
+      )],
+    )
+    .into()
+  };
+  assert!(device_manager
+    .send_device_command(vibrate(devices[0].device_index(), 0))
+    .await
+    .is_ok());
+  check_test_recv_value(
+    &mut device,
+    HardwareCommand::Write(HardwareWriteCmd::new(Endpoint::Tx, vec![0xF1, 64], false)),
+  );
+  // Commands get the same checks they would coming from a client.
+  assert!(matches!(
+    device_manager
+      .send_device_command(vibrate(devices[0].device_index(), 5))
+      .await,
+    Err(ButtplugError::ButtplugDeviceError(
+      ButtplugDeviceError::DeviceSubcommandFeatureIndexError(..)
+    ))
+  ));
+  // Values are checked too, since these don't go through client message validation.
+  assert!(matches!(
+    device_manager
+      .send_device_command(
+        message::ScalarCmdV4::new(
+          devices[0].device_index(),
+          vec![message::ScalarSubcommandV4::new(
+            0,
+            1.5,
+            message::ActuatorType::Vibrate,
+          )],
+        )
+        .into()
+      )
+      .await,
+    Err(ButtplugError::ButtplugDeviceError(
+      ButtplugDeviceError::DeviceSubcommandValueOutOfRange(
+        message::ButtplugDeviceMessageType::ScalarCmd,
+        0,
+        _
+      )
+    ))
+  ));
+  assert!(matches!(
+    device_manager.send_device_command(vibrate(5, 0)).await,
+    Err(ButtplugError::ButtplugDeviceError(
+      ButtplugDeviceError::DeviceNotAvailable(5)
+    ))
+  ));
+
+  assert!(server.shutdown().await.is_ok());
+  assert!(device_manager.start_scanning().await.is_err());
+  assert!(device_manager
+    .send_device_command(vibrate(devices[0].device_index(), 0))
+    .await
+    .is_err());
+}
+
+#[tokio::test]
+async fn test_device_command_audit() {
+  let mut builder = TestDeviceCommunicationManagerBuilder::default();
+  let mut device = builder.add_test_device(&TestDeviceIdentifier::new("Massage Demo", None));
+  let server = test_server_with_comm_manager(builder, false);
+  let device_manager = server.device_manager();
+  let recv = device_manager.event_stream();
+  pin_mut!(recv);
+  assert!(device_manager.start_scanning().await.is_ok());
+  let device_index = loop {
+    if let ButtplugServerMessageV4::DeviceAdded(added) =
+      recv.next().await.expect("Test, assuming infallible")
+    {
+      break added.device_index();
+    }
+  };
+  assert!(device_manager
+    .device_command_audit(device_index + 1)
+    .is_none());
+
+  let vibrate = message::ScalarCmdV4::new(
+    device_index,
+    vec![message::ScalarSubcommandV4::new(
+      0,
+      0.5,
+      message::ActuatorType::Vibrate,
+    )],
+  );
+  assert!(device_manager
+    .send_device_command(vibrate.into())
+    .await
+    .is_ok());
+  check_test_recv_value(
+    &mut device,
+    HardwareCommand::Write(HardwareWriteCmd::new(Endpoint::Tx, vec![0xF1, 64], false)),
+  );
+  let audit = device_manager
+    .device_command_audit(device_index)
+    .expect("Test, assuming infallible");
+  assert!(matches!(audit[0].event(), CommandAuditEvent::Connected));
+  // The hardware write comes before the command that caused it, which is recorded once done.
+  let [.., hardware, command] = &audit[..] else {
+    panic!("Audit is missing entries: {:?}", audit);
+  };
+  assert!(matches!(
+    hardware.event(),
+    CommandAuditEvent::Hardware { command, result: Ok(()) }
+      if *command == HardwareWriteCmd::new(Endpoint::Tx, vec![0xF1, 64], false).into()
+  ));
+  assert!(matches!(
+    command.event(),
+    CommandAuditEvent::Command { result: Ok(()), .. }
+  ));
+
+  // The audit is still around after the device goes away.
+  device
+    .sender
+    .send(TestHardwareEvent::Disconnect)
+    .await
+    .expect("Test, assuming infallible");
+  while !matches!(
+    recv.next().await.expect("Test, assuming infallible"),
+    ButtplugServerMessageV4::DeviceRemoved(_)
+  ) {}
+  let audit = device_manager
+    .device_command_audit(device_index)
+    .expect("Test, assuming infallible");
+  assert!(matches!(
+    audit.last().expect("Test, assuming infallible").event(),
+    CommandAuditEvent::Disconnected
+  ));
+  assert!(server.shutdown().await.is_ok());
+}
+
+#[tokio::test]
+async fn test_intensity_metering() {
+  let mut builder = TestDeviceCommunicationManagerBuilder::default();
+  let _device = builder.add_test_device(&TestDeviceIdentifier::new("Massage Demo", None));
+  let mut dm_builder = ServerDeviceManagerBuilder::new(create_test_dcm(false));
+  dm_builder
+    .comm_manager(builder)
+    .intensity_metering_interval(Duration::from_millis(100));
+  let server = ButtplugServerBuilder::new(dm_builder.finish().unwrap())
+    .finish()
+    .unwrap();
+  let device_manager = server.device_manager();
+  let recv = device_manager.event_stream();
+  pin_mut!(recv);
+  let meter = device_manager.intensity_meter_stream();
+  pin_mut!(meter);
+  assert!(device_manager.start_scanning().await.is_ok());
+  let device_index = loop {
+    if let ButtplugServerMessageV4::DeviceAdded(added) =
+      recv.next().await.expect("Test, assuming infallible")
+    {
+      break added.device_index();
+    }
+  };
+  let vibrate = |level| {
+    device_manager.send_device_command(
+      message::ScalarCmdV4::new(
+        device_index,
+        vec![message::ScalarSubcommandV4::new(
+          0,
+          level,
+          message::ActuatorType::Vibrate,
+        )],
+      )
+      .into(),
+    )
+  };
+
+  assert!(vibrate(0.5).await.is_ok());
+  let reading = meter.next().await.expect("Test, assuming infallible");
+  assert_eq!(reading.device_index(), device_index);
+  let features = reading.features();
+  assert_eq!(features.len(), 2);
+  assert_eq!(features[0].step(), 64);
+  assert!(features[0].intensity() > 0.5 && features[0].intensity() < 0.51);
+  assert_eq!(features[1].step(), 0);
+
+  // Changes that come in faster than the metering interval end up in a single reading.
+  assert!(vibrate(0.25).await.is_ok());
+  assert!(vibrate(1.0).await.is_ok());
+  let reading = meter.next().await.expect("Test, assuming infallible");
+  assert_eq!(reading.features()[0].intensity(), 1.0);
+  assert!(
+    tokio::time::timeout(Duration::from_millis(200), meter.next())
+      .await
+      .is_err()
+  );
+  assert!(server.shutdown().await.is_ok());
+}
+
+#[tokio::test]
+async fn test_energy_budget_cooldown() {
+  let mut builder = TestDeviceCommunicationManagerBuilder::default();
+  let _device = builder.add_test_device(&TestDeviceIdentifier::new("Massage Demo", None));
+  let mut dm_builder = ServerDeviceManagerBuilder::new(create_test_dcm(false));
+  dm_builder.comm_manager(builder).energy_budget(EnergyBudgetPolicy::new(
+    0.8,
+    Duration::from_millis(100),
+    EnergyBudgetAction::Cooldown(Duration::from_millis(200)),
+  ));
+  let server = ButtplugServerBuilder::new(dm_builder.finish().unwrap())
+    .finish()
+    .unwrap();
+  let device_manager = server.device_manager();
+  let recv = device_manager.event_stream();
+  pin_mut!(recv);
+  let budget_events = device_manager.energy_budget_event_stream();
+  pin_mut!(budget_events);
+  assert!(device_manager.start_scanning().await.is_ok());
+  let device_index = loop {
+    if let ButtplugServerMessageV4::DeviceAdded(added) =
+      recv.next().await.expect("Test, assuming infallible")
+    {
+      break added.device_index();
+    }
+  };
+  let vibrate = |level| {
+    device_manager.send_device_command(
+      message::ScalarCmdV4::new(
+        device_index,
+        vec![message::ScalarSubcommandV4::new(
+          0,
+          level,
+          message::ActuatorType::Vibrate,
+        )],
+      )
+      .into(),
+    )
+  };
+
+  // Time below the threshold doesn't count against the budget.
+  assert!(vibrate(0.5).await.is_ok());
+  assert!(
+    tokio::time::timeout(Duration::from_millis(150), budget_events.next())
+      .await
+      .is_err()
+  );
+
+  assert!(vibrate(1.0).await.is_ok());
+  assert_eq!(
+    budget_events.next().await.expect("Test, assuming infallible"),
+    EnergyBudgetEvent::Engaged {
+      device_index,
+      action: EnergyBudgetAction::Cooldown(Duration::from_millis(200)),
+    }
+  );
+  assert!(matches!(
+    vibrate(0.5).await,
+    Err(ButtplugError::ButtplugDeviceError(
+      ButtplugDeviceError::DeviceCoolingDown(index)
+    )) if index == device_index
+  ));
+
+  assert_eq!(
+    budget_events.next().await.expect("Test, assuming infallible"),
+    EnergyBudgetEvent::Released { device_index }
+  );
+  assert!(vibrate(0.5).await.is_ok());
+  assert!(server.shutdown().await.is_ok());
+}
+
+#[tokio::test]
+async fn test_forget_device() {
+  let mut builder = TestDeviceCommunicationManagerBuilder::default();
+  let _device = builder.add_test_device(&TestDeviceIdentifier::new("Massage Demo", None));
+  let server = test_server_with_comm_manager(builder, false);
+  let device_manager = server.device_manager();
+  let recv = device_manager.event_stream();
+  pin_mut!(recv);
+  assert!(device_manager.start_scanning().await.is_ok());
+  let device_index = loop {
+    if let ButtplugServerMessageV4::DeviceAdded(added) =
+      recv.next().await.expect("Test, assuming infallible")
+    {
+      break added.device_index();
+    }
+  };
+  let identifier = device_manager
+    .device_info(device_index)
+    .expect("Test, assuming infallible")
+    .identifier()
+    .clone();
+  let dcm = device_manager.device_configuration_manager();
+  assert!(dcm.user_device_definitions().contains_key(&identifier));
+
+  assert!(device_manager.forget_device(device_index).await.is_ok());
+  while !matches!(
+    recv.next().await.expect("Test, assuming infallible"),
+    ButtplugServerMessageV4::DeviceRemoved(removed) if removed.device_index() == device_index
+  ) {}
+  assert!(!dcm.user_device_definitions().contains_key(&identifier));
+  assert!(dcm.address_forgotten(identifier.address()));
+  assert!(device_manager.device_command_audit(device_index).is_none());
+  // Nothing left to forget.
+  assert!(matches!(
+    device_manager.forget_device(device_index).await,
+    Err(ButtplugError::ButtplugDeviceError(
+      ButtplugDeviceError::DeviceNotAvailable(_)
+    ))
+  ));
+  assert!(server.shutdown().await.is_ok());
+}
+
+#[tokio::test]
+async fn test_shutdown_stops_spawned_tasks() {
+  let metrics = tokio::runtime::Handle::current().metrics();
+  let baseline = metrics.num_alive_tasks();
+  let mut builder = TestDeviceCommunicationManagerBuilder::default();
+  let _device = builder.add_test_device(&TestDeviceIdentifier::new("Massage Demo", None));
+  let mut dm_builder = ServerDeviceManagerBuilder::new(create_test_dcm(false));
+  dm_builder.comm_manager(builder);
+  let server = ButtplugServerBuilder::new(dm_builder.finish().unwrap())
+    .max_ping_time(60000)
+    .finish()
+    .unwrap();
+  let recv = server.event_stream();
+  pin_mut!(recv);
+  assert!(server
+    .parse_message(
+      message::RequestServerInfoV1::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION)
+        .into()
+    )
+    .await
+    .is_ok());
+  assert!(server
+    .parse_message(message::StartScanningV0::default().into())
+    .await
+    .is_ok());
+  while !matches!(
+    recv.next().await.expect("Test, assuming infallible"),
+    ButtplugServerMessageV4::DeviceAdded(_)
+  ) {}
+  assert!(metrics.num_alive_tasks() > baseline);
+
+  assert!(server.shutdown().await.is_ok());
+  for _ in 0..50 {
+    if metrics.num_alive_tasks() == baseline {
+      break;
+    }
+    sleep(Duration::from_millis(10)).await;
+  }
+  assert_eq!(metrics.num_alive_tasks(), baseline);
+}
+
 #[tokio::test]
 async fn test_device_index_generation() {
   let mut builder = TestDeviceCommunicationManagerBuilder::default();
@@ -343,6 +880,88 @@ async fn test_device_index_generation() {
   }
 }
 
+#[derive(Default)]
+struct MemoryStorage {
+  entries: Mutex<HashMap<String, String>>,
+}
+
+impl ServerStorage for MemoryStorage {
+  fn load(&self, key: &str) -> Result<Option<String>, ServerStorageError> {
+    Ok(self.entries.lock().unwrap().get(key).cloned())
+  }
+
+  fn store(&self, key: &str, value: &str) -> Result<(), ServerStorageError> {
+    self
+      .entries
+      .lock()
+      .unwrap()
+      .insert(key.to_owned(), value.to_owned());
+    Ok(())
+  }
+}
+
+#[tokio::test]
+async fn test_server_storage_keeps_device_indexes() {
+  let storage = Arc::new(MemoryStorage::default());
+  let storage_server = |addresses: &[&str]| {
+    let mut builder = TestDeviceCommunicationManagerBuilder::default();
+    for address in addresses {
+      builder.add_test_device(&TestDeviceIdentifier::new(
+        "Massage Demo",
+        Some(address.to_string()),
+      ));
+    }
+    let mut dm_builder = ServerDeviceManagerBuilder::new(create_test_dcm(false));
+    dm_builder.comm_manager(builder);
+    ButtplugServerBuilder::new(dm_builder.finish().unwrap())
+      .storage(storage.clone())
+      .finish()
+      .unwrap()
+  };
+  let device_indexes = |server: &ButtplugServer, count: usize| {
+    let device_manager = server.device_manager();
+    async move {
+      let recv = device_manager.event_stream();
+      pin_mut!(recv);
+      assert!(device_manager.start_scanning().await.is_ok());
+      let mut indexes = HashMap::new();
+      while indexes.len() < count {
+        if let ButtplugServerMessageV4::DeviceAdded(added) =
+          recv.next().await.expect("Test, assuming infallible")
+        {
+          let address = device_manager
+            .device_info(added.device_index())
+            .expect("Test, assuming infallible")
+            .identifier()
+            .address()
+            .clone();
+          indexes.insert(address, added.device_index());
+        }
+      }
+      indexes
+    }
+  };
+
+  // The first run sees one device, which gets index 0, and that gets stored.
+  let server = storage_server(&["StorageTest"]);
+  assert_eq!(device_indexes(&server, 1).await["StorageTest"], 0);
+  let stored = loop {
+    if let Ok(Some(config)) = storage.load(USER_DEVICE_CONFIG_STORAGE_KEY) {
+      break config;
+    }
+    sleep(Duration::from_millis(10)).await;
+  };
+  assert!(stored.contains("StorageTest"));
+  assert!(server.shutdown().await.is_ok());
+
+  // The next run loads the stored config, so the device keeps its index however discovery goes.
+  let server = storage_server(&["StorageOther", "StorageTest"]);
+  let indexes = device_indexes(&server, 2).await;
+  assert_eq!(indexes["StorageTest"], 0);
+  assert_eq!(indexes["StorageOther"], 1);
+  assert!(server.shutdown().await.is_ok());
+}
+
 #[tokio::test]
 async fn test_server_scanning_finished() {
   let mut builder = TestDeviceCommunicationManagerBuilder::default();
@@ -381,6 +1000,446 @@ async fn test_server_scanning_finished() {
   assert!(finish_received);
 }
 
+/// Comm manager whose scanning bringup never completes.
+#[derive(Default)]
+struct StalledDeviceCommunicationManagerBuilder {}
+
+impl HardwareCommunicationManagerBuilder for StalledDeviceCommunicationManagerBuilder {
+  fn finish(
+    &mut self,
+    _: Sender<HardwareCommunicationManagerEvent>,
+    _: CancellationToken,
+  ) -> Box<dyn HardwareCommunicationManager> {
+    Box::new(StalledDeviceCommunicationManager {})
+  }
+}
+
+struct StalledDeviceCommunicationManager {}
+
+impl HardwareCommunicationManager for StalledDeviceCommunicationManager {
+  fn name(&self) -> &'static str {
+    "StalledDeviceCommunicationManager"
+  }
+
+  fn start_scanning(&mut self) -> ButtplugResultFuture {
+    future::pending().boxed()
+  }
+
+  fn stop_scanning(&mut self) -> ButtplugResultFuture {
+    future::ready(Ok(())).boxed()
+  }
+
+  fn scanning_status(&self) -> bool {
+    false
+  }
+
+  fn can_scan(&self) -> bool {
+    true
+  }
+}
+
+async fn start_scanning_with_stalled_manager(
+  scanning_start_timeout: Duration,
+) -> (
+  ButtplugServer,
+  impl Stream<Item = ButtplugServerMessageV4>,
+) {
+  let mut builder = TestDeviceCommunicationManagerBuilder::default();
+  let mut _device = builder.add_test_device(&TestDeviceIdentifier::new("Massage Demo", None));
+
+  let mut dm_builder = ServerDeviceManagerBuilder::new(create_test_dcm(false));
+  dm_builder
+    .comm_manager(StalledDeviceCommunicationManagerBuilder::default())
+    .comm_manager(builder)
+    .manager_scanning_start_timeout("StalledDeviceCommunicationManager", scanning_start_timeout);
+  let server = ButtplugServerBuilder::new(dm_builder.finish().unwrap())
+    .finish()
+    .unwrap();
+  let recv = server.event_stream();
+  assert!(server
+    .parse_message(
+      message::RequestServerInfoV1::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION)
+        .into()
+    )
+    .await
+    .is_ok());
+  assert!(server
+    .parse_message(message::StartScanningV0::default().into())
+    .await
+    .is_ok());
+  (server, recv)
+}
+
+#[tokio::test]
+async fn test_stalled_manager_does_not_block_device_discovery() {
+  let (_server, recv) = start_scanning_with_stalled_manager(Duration::from_secs(60)).await;
+  pin_mut!(recv);
+  let device_added = tokio::time::timeout(Duration::from_secs(5), async {
+    while let Some(msg) = recv.next().await {
+      if matches!(msg, ButtplugServerMessageV4::DeviceAdded(_)) {
+        return true;
+      }
+    }
+    false
+  })
+  .await;
+  assert!(matches!(device_added, Ok(true)));
+}
+
+#[tokio::test]
+async fn test_stalled_manager_scanning_start_timeout() {
+  let (_server, recv) = start_scanning_with_stalled_manager(Duration::from_millis(100)).await;
+  pin_mut!(recv);
+  let finish_received = tokio::time::timeout(Duration::from_secs(5), async {
+    while let Some(msg) = recv.next().await {
+      if matches!(msg, ButtplugServerMessageV4::ScanningFinished(_)) {
+        return true;
+      }
+    }
+    false
+  })
+  .await;
+  assert!(matches!(finish_received, Ok(true)));
+}
+
+#[tokio::test]
+async fn test_scanning_progress() {
+  let mut builder = TestDeviceCommunicationManagerBuilder::default();
+  let mut _device = builder.add_test_device(&TestDeviceIdentifier::new("Massage Demo", None));
+
+  // The stalled manager never finishes bringup, which keeps the scan (and progress updates) going.
+  let mut dm_builder = ServerDeviceManagerBuilder::new(create_test_dcm(false));
+  dm_builder
+    .comm_manager(StalledDeviceCommunicationManagerBuilder::default())
+    .comm_manager(builder)
+    .scanning_progress_interval(Duration::from_millis(50));
+  let server = ButtplugServerBuilder::new(dm_builder.finish().unwrap())
+    .finish()
+    .unwrap();
+  let progress_stream = server.device_manager().scanning_progress_stream();
+  pin_mut!(progress_stream);
+  assert!(server
+    .parse_message(
+      message::RequestServerInfoV1::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION)
+        .into()
+    )
+    .await
+    .is_ok());
+  assert!(server
+    .parse_message(message::StartScanningV0::default().into())
+    .await
+    .is_ok());
+  let progress = tokio::time::timeout(Duration::from_secs(5), async {
+    while let Some(progress) = progress_stream.next().await {
+      if progress
+        .devices_found()
+        .get("TestDeviceCommunicationManager")
+        == Some(&1)
+      {
+        return Some(progress);
+      }
+    }
+    None
+  })
+  .await
+  .expect("Test, assuming infallible.")
+  .expect("Test, assuming infallible.");
+  assert_eq!(
+    progress
+      .devices_found()
+      .get("StalledDeviceCommunicationManager"),
+    Some(&0)
+  );
+  assert!(progress.elapsed() > Duration::ZERO);
+}
+
+#[tokio::test]
+async fn test_scanning_progress_message() {
+  let mut builder = TestDeviceCommunicationManagerBuilder::default();
+  let mut _device = builder.add_test_device(&TestDeviceIdentifier::new("Massage Demo", None));
+
+  let mut dm_builder = ServerDeviceManagerBuilder::new(create_test_dcm(false));
+  dm_builder
+    .comm_manager(StalledDeviceCommunicationManagerBuilder::default())
+    .comm_manager(builder)
+    .scanning_progress_interval(Duration::from_millis(50));
+  let server = ButtplugServerBuilder::new(dm_builder.finish().unwrap())
+    .finish()
+    .unwrap();
+  let recv = server.event_stream();
+  pin_mut!(recv);
+  assert!(server
+    .parse_message(
+      message::RequestServerInfoV1::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION)
+        .into()
+    )
+    .await
+    .is_ok());
+  assert!(server
+    .parse_message(message::StartScanningV0::default().into())
+    .await
+    .is_ok());
+  let progress = tokio::time::timeout(Duration::from_secs(5), async {
+    while let Some(msg) = recv.next().await {
+      if let ButtplugServerMessageV4::ScanningProgress(progress) = msg {
+        if progress
+          .devices_found()
+          .get("TestDeviceCommunicationManager")
+          == Some(&1)
+        {
+          return Some(progress);
+        }
+      }
+    }
+    None
+  })
+  .await
+  .expect("Test, assuming infallible.")
+  .expect("Test, assuming infallible.");
+  assert_eq!(
+    progress
+      .devices_found()
+      .get("StalledDeviceCommunicationManager"),
+    Some(&0)
+  );
+  // Progress only exists in spec v4, so it has nothing to downgrade to.
+  assert!(ButtplugServerMessageV3::try_from(ButtplugServerMessageV4::ScanningProgress(progress))
+    .is_err());
+}
+
+#[derive(Default)]
+struct PoweredOffCommunicationManagerBuilder {}
+
+impl HardwareCommunicationManagerBuilder for PoweredOffCommunicationManagerBuilder {
+  fn finish(
+    &mut self,
+    sender: Sender<HardwareCommunicationManagerEvent>,
+    _: CancellationToken,
+  ) -> Box<dyn HardwareCommunicationManager> {
+    // Pretend the radio was found and then switched off.
+    tokio::spawn(async move {
+      sender
+        .send(HardwareCommunicationManagerEvent::StatusChanged {
+          name: "PoweredOffCommunicationManager",
+          status: HardwareCommunicationManagerStatus::PoweredOff,
+        })
+        .await
+        .expect("Test, assuming infallible.");
+    });
+    Box::new(PoweredOffCommunicationManager {})
+  }
+}
+
+struct PoweredOffCommunicationManager {}
+
+impl HardwareCommunicationManager for PoweredOffCommunicationManager {
+  fn name(&self) -> &'static str {
+    "PoweredOffCommunicationManager"
+  }
+
+  fn start_scanning(&mut self) -> ButtplugResultFuture {
+    future::ready(Ok(())).boxed()
+  }
+
+  fn stop_scanning(&mut self) -> ButtplugResultFuture {
+    future::ready(Ok(())).boxed()
+  }
+
+  fn can_scan(&self) -> bool {
+    false
+  }
+}
+
+#[tokio::test]
+async fn test_comm_manager_status_changes() {
+  let mut dm_builder = ServerDeviceManagerBuilder::new(create_test_dcm(false));
+  dm_builder.comm_manager(PoweredOffCommunicationManagerBuilder::default());
+  let device_manager = dm_builder.finish().unwrap();
+  assert_eq!(
+    device_manager
+      .comm_manager_status()
+      .get("PoweredOffCommunicationManager"),
+    Some(&HardwareCommunicationManagerStatus::Unavailable)
+  );
+  let status_updated = tokio::time::timeout(Duration::from_secs(5), async {
+    loop {
+      if device_manager
+        .comm_manager_status()
+        .get("PoweredOffCommunicationManager")
+        == Some(&HardwareCommunicationManagerStatus::PoweredOff)
+      {
+        return;
+      }
+      sleep(Duration::from_millis(10)).await;
+    }
+  })
+  .await;
+  assert!(status_updated.is_ok());
+}
+
+#[derive(Default)]
+struct DongleCommunicationManagerBuilder {}
+
+impl HardwareCommunicationManagerBuilder for DongleCommunicationManagerBuilder {
+  fn finish(
+    &mut self,
+    sender: Sender<HardwareCommunicationManagerEvent>,
+    _: CancellationToken,
+  ) -> Box<dyn HardwareCommunicationManager> {
+    // Pretend two dongles reported their firmware, with one of them reporting twice.
+    tokio::spawn(async move {
+      for (hardware, version) in [("COM3", "1.2.0"), ("COM4", "1.5.1"), ("COM3", "1.3.0")] {
+        sender
+          .send(HardwareCommunicationManagerEvent::FirmwareVersion {
+            hardware: hardware.to_owned(),
+            version: version.to_owned(),
+          })
+          .await
+          .expect("Test, assuming infallible.");
+      }
+    });
+    Box::new(DongleCommunicationManager {})
+  }
+}
+
+struct DongleCommunicationManager {}
+
+impl HardwareCommunicationManager for DongleCommunicationManager {
+  fn name(&self) -> &'static str {
+    "DongleCommunicationManager"
+  }
+
+  fn start_scanning(&mut self) -> ButtplugResultFuture {
+    future::ready(Ok(())).boxed()
+  }
+
+  fn stop_scanning(&mut self) -> ButtplugResultFuture {
+    future::ready(Ok(())).boxed()
+  }
+
+  fn can_scan(&self) -> bool {
+    true
+  }
+}
+
+#[tokio::test]
+async fn test_comm_manager_firmware_versions() {
+  let mut dm_builder = ServerDeviceManagerBuilder::new(create_test_dcm(false));
+  dm_builder.comm_manager(DongleCommunicationManagerBuilder::default());
+  let device_manager = dm_builder.finish().unwrap();
+  let versions = tokio::time::timeout(Duration::from_secs(5), async {
+    loop {
+      if let Some(versions) = device_manager
+        .comm_manager_firmware_versions()
+        .get("DongleCommunicationManager")
+        .filter(|versions| versions.iter().any(|firmware| firmware.version() == "1.3.0"))
+      {
+        return versions.clone();
+      }
+      sleep(Duration::from_millis(10)).await;
+    }
+  })
+  .await
+  .expect("Firmware versions should show up");
+  let mut versions: Vec<(String, String)> = versions
+    .iter()
+    .map(|firmware| (firmware.hardware().clone(), firmware.version().clone()))
+    .collect();
+  versions.sort();
+  assert_eq!(
+    versions,
+    vec![
+      ("COM3".to_owned(), "1.3.0".to_owned()),
+      ("COM4".to_owned(), "1.5.1".to_owned())
+    ]
+  );
+}
+
+#[derive(Default)]
+struct PanickingCommunicationManagerBuilder {
+  builds: Arc<AtomicU32>,
+}
+
+impl HardwareCommunicationManagerBuilder for PanickingCommunicationManagerBuilder {
+  fn finish(
+    &mut self,
+    sender: Sender<HardwareCommunicationManagerEvent>,
+    _: CancellationToken,
+  ) -> Box<dyn HardwareCommunicationManager> {
+    self.builds.fetch_add(1, Ordering::SeqCst);
+    Box::new(PanickingCommunicationManager {
+      sender,
+      scanning: false,
+    })
+  }
+}
+
+struct PanickingCommunicationManager {
+  sender: Sender<HardwareCommunicationManagerEvent>,
+  scanning: bool,
+}
+
+impl HardwareCommunicationManager for PanickingCommunicationManager {
+  fn name(&self) -> &'static str {
+    "PanickingCommunicationManager"
+  }
+
+  fn start_scanning(&mut self) -> ButtplugResultFuture {
+    self.scanning = true;
+    spawn_manager_task(self.sender.clone(), async {
+      panic!("Scanning task blew up");
+    });
+    future::ready(Ok(())).boxed()
+  }
+
+  fn stop_scanning(&mut self) -> ButtplugResultFuture {
+    self.scanning = false;
+    future::ready(Ok(())).boxed()
+  }
+
+  fn scanning_status(&self) -> bool {
+    self.scanning
+  }
+
+  fn can_scan(&self) -> bool {
+    true
+  }
+}
+
+#[tokio::test]
+async fn test_comm_manager_failure_restarts_then_disables() {
+  let comm_builder = PanickingCommunicationManagerBuilder::default();
+  let builds = comm_builder.builds.clone();
+  let mut dm_builder = ServerDeviceManagerBuilder::new(create_test_dcm(false));
+  dm_builder
+    .comm_manager(comm_builder)
+    .max_comm_manager_restarts(1);
+  let device_manager = dm_builder.finish().unwrap();
+  let recv = device_manager.event_stream();
+  pin_mut!(recv);
+  assert!(device_manager
+    .parse_message(message::StartScanningV0::default().into())
+    .await
+    .is_ok());
+  // Scanning panics once, the manager is rebuilt and scanning again, panics again, and is then
+  // disabled. Scanning shouldn't be left hanging on it.
+  tokio::time::timeout(Duration::from_secs(5), async {
+    while !matches!(
+      recv.next().await.expect("Test, assuming infallible."),
+      ButtplugServerMessageV4::ScanningFinished(_)
+    ) {}
+  })
+  .await
+  .expect("Test, assuming infallible.");
+  assert_eq!(builds.load(Ordering::SeqCst), 2);
+  assert!(matches!(
+    device_manager
+      .comm_manager_status()
+      .get("PanickingCommunicationManager"),
+    Some(HardwareCommunicationManagerStatus::Failed(_))
+  ));
+}
+
 // TODO Test sending system message (Id 0)
 // TODO Test sending system message (Ok but Id > 0)
 // TODO Test scan with no comm managers
@@ -6,23 +6,54 @@
 // for full license information.
 
 mod util;
-use buttplug::core::{
-  errors::{ButtplugDeviceError, ButtplugError},
-  message::{
-    self,
-    ButtplugClientMessageV4,
-    ButtplugClientMessageVariant,
-    ButtplugServerMessageV3,
-    ButtplugServerMessageV4,
-    ButtplugServerMessageVariant,
-    Endpoint,
-    BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION,
+use buttplug::{
+  core::{
+    errors::{ButtplugDeviceError, ButtplugError},
+    message::{
+      self,
+      ButtplugClientMessageV4,
+      ButtplugClientMessageVariant,
+      ButtplugDeviceMessage,
+      ButtplugServerMessageV3,
+      ButtplugServerMessageV4,
+      ButtplugServerMessageVariant,
+      DeviceAxis,
+      Endpoint,
+      BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION,
+    },
   },
+  server::{
+    device::{
+      hardware::{HardwareCommand, HardwareSubscribeCmd, HardwareWriteCmd},
+      DeviceLink,
+      DeviceLinkTransfer,
+      OutOfRangeValuePolicy,
+      PatternSession,
+      PatternSessionEnd,
+      PatternSessionEvent,
+      PatternStep,
+      PatternTrack,
+      ServerDeviceManagerBuilder,
+    },
+    ButtplugServerBuilder,
+  },
+  util::device_configuration::{load_protocol_configs, save_user_config},
 };
-use futures::{pin_mut, StreamExt};
-use std::matches;
+use futures::{pin_mut, Stream, StreamExt};
+use std::{matches, time::Duration};
 pub use util::test_device_manager::TestDeviceCommunicationManagerBuilder;
-use util::{test_server_v4_with_device, test_server_with_device};
+use util::test_device_manager::{
+  check_test_recv_value,
+  TestDeviceChannelHost,
+  TestDeviceIdentifier,
+  TestHardwareEvent,
+};
+use util::{
+  create_test_dcm,
+  test_server_v4_with_device,
+  test_server_with_comm_manager,
+  test_server_with_device,
+};
 
 // Test devices that have protocols that support movements not all devices do.
 // For instance, the Onyx+ is part of a protocol that supports vibration, but
@@ -210,6 +241,1511 @@ async fn test_reject_on_no_raw_message() {
   }
 }
 
+#[tokio::test]
+async fn test_identify_device() {
+  let (server, mut device) = test_server_v4_with_device("Massage Demo", false);
+  let recv = server.event_stream();
+  pin_mut!(recv);
+  assert!(server
+    .parse_message(ButtplugClientMessageV4::from(
+      message::RequestServerInfoV1::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION)
+    ))
+    .await
+    .is_ok());
+  assert!(server
+    .parse_message(ButtplugClientMessageV4::from(
+      message::StartScanningV0::default()
+    ))
+    .await
+    .is_ok());
+  while let Some(msg) = recv.next().await {
+    if let ButtplugServerMessageV4::DeviceAdded(da) = msg {
+      let device_manager = server.device_manager();
+      assert!(matches!(
+        device_manager.identify_device(da.device_index() + 1).await,
+        Err(ButtplugError::ButtplugDeviceError(
+          ButtplugDeviceError::DeviceNotAvailable(_)
+        ))
+      ));
+      device_manager
+        .identify_device(da.device_index())
+        .await
+        .expect("Test, assuming infallible.");
+      // Both vibrators should pulse at a low level, then stop.
+      for command in [[0xF1, 39], [0xF2, 39], [0xF1, 0], [0xF2, 0]] {
+        check_test_recv_value(
+          &mut device,
+          HardwareCommand::Write(HardwareWriteCmd::new(Endpoint::Tx, command.to_vec(), false)),
+        );
+      }
+      return;
+    }
+  }
+  panic!("Did not get DeviceAdded message");
+}
+
+#[tokio::test]
+async fn test_identify_position_only_device_unsupported() {
+  let (server, _device) = test_server_v4_with_device("Onyx+", false);
+  let recv = server.event_stream();
+  pin_mut!(recv);
+  assert!(server
+    .parse_message(ButtplugClientMessageV4::from(
+      message::RequestServerInfoV1::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION)
+    ))
+    .await
+    .is_ok());
+  assert!(server
+    .parse_message(ButtplugClientMessageV4::from(
+      message::StartScanningV0::default()
+    ))
+    .await
+    .is_ok());
+  while let Some(msg) = recv.next().await {
+    if let ButtplugServerMessageV4::DeviceAdded(da) = msg {
+      let result = server
+        .parse_message(ButtplugClientMessageV4::from(
+          message::IdentifyDeviceV4::new(da.device_index()),
+        ))
+        .await;
+      assert!(matches!(
+        result,
+        Err(err) if err.error_code() == message::ErrorCode::ErrorDevice
+      ));
+      return;
+    }
+  }
+  panic!("Did not get DeviceAdded message");
+}
+
+#[tokio::test]
+async fn test_stop_device_features() {
+  let (server, mut device) = test_server_v4_with_device("Massage Demo", false);
+  let recv = server.event_stream();
+  pin_mut!(recv);
+  assert!(server
+    .parse_message(ButtplugClientMessageV4::from(
+      message::RequestServerInfoV1::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION)
+    ))
+    .await
+    .is_ok());
+  assert!(server
+    .parse_message(ButtplugClientMessageV4::from(
+      message::StartScanningV0::default()
+    ))
+    .await
+    .is_ok());
+  while let Some(msg) = recv.next().await {
+    if let ButtplugServerMessageV4::DeviceAdded(da) = msg {
+      server
+        .parse_message(ButtplugClientMessageV4::from(message::ScalarCmdV4::new(
+          da.device_index(),
+          vec![
+            message::ScalarSubcommandV4::new(0, 0.5, message::ActuatorType::Vibrate),
+            message::ScalarSubcommandV4::new(1, 0.5, message::ActuatorType::Vibrate),
+          ],
+        )))
+        .await
+        .expect("Test, assuming infallible.");
+      for command in [[0xF1, 64], [0xF2, 64]] {
+        check_test_recv_value(
+          &mut device,
+          HardwareCommand::Write(HardwareWriteCmd::new(Endpoint::Tx, command.to_vec(), false)),
+        );
+      }
+      let device_manager = server.device_manager();
+      // Only the second vibrator should stop.
+      device_manager
+        .stop_device_features(da.device_index(), &[1])
+        .await
+        .expect("Test, assuming infallible.");
+      check_test_recv_value(
+        &mut device,
+        HardwareCommand::Write(HardwareWriteCmd::new(Endpoint::Tx, vec![0xF2, 0], false)),
+      );
+      assert!(device.receiver.try_recv().is_err());
+      assert!(matches!(
+        device_manager
+          .stop_device_features(da.device_index(), &[2])
+          .await,
+        Err(ButtplugError::ButtplugDeviceError(
+          ButtplugDeviceError::DeviceFeatureIndexError(2, 2)
+        ))
+      ));
+      return;
+    }
+  }
+  panic!("Did not get DeviceAdded message");
+}
+
+#[tokio::test]
+async fn test_lovense_firmware_patterns() {
+  let (server, mut device) = test_server_v4_with_device("LVS-Test", false);
+  let recv = server.event_stream();
+  pin_mut!(recv);
+  assert!(server
+    .parse_message(ButtplugClientMessageV4::from(
+      message::RequestServerInfoV1::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION)
+    ))
+    .await
+    .is_ok());
+  assert!(server
+    .parse_message(ButtplugClientMessageV4::from(
+      message::StartScanningV0::default()
+    ))
+    .await
+    .is_ok());
+  // Answer the DeviceType query so the device identifies as a Lovense Hush.
+  while let Some(command) = device.receiver.recv().await {
+    if matches!(command, HardwareCommand::Write(_)) {
+      break;
+    }
+  }
+  device
+    .sender
+    .send(TestHardwareEvent::notification(
+      Endpoint::Rx,
+      b"Z:11:0082059AD3BD;",
+    ))
+    .await
+    .expect("Test, assuming infallible.");
+  while let Some(msg) = recv.next().await {
+    if let ButtplugServerMessageV4::DeviceAdded(da) = msg {
+      let device_manager = server.device_manager();
+      let info = device_manager
+        .device_info(da.device_index())
+        .expect("Test, assuming infallible.");
+      assert_eq!(info.firmware_pattern_count(), 4);
+      device_manager
+        .start_device_firmware_pattern(da.device_index(), 1)
+        .await
+        .expect("Test, assuming infallible.");
+      check_test_recv_value(
+        &mut device,
+        HardwareCommand::Write(HardwareWriteCmd::new(
+          Endpoint::Tx,
+          b"Preset:2;".to_vec(),
+          false,
+        )),
+      );
+      device_manager
+        .stop_device_firmware_pattern(da.device_index())
+        .await
+        .expect("Test, assuming infallible.");
+      check_test_recv_value(
+        &mut device,
+        HardwareCommand::Write(HardwareWriteCmd::new(
+          Endpoint::Tx,
+          b"Preset:0;".to_vec(),
+          false,
+        )),
+      );
+      assert!(device_manager
+        .start_device_firmware_pattern(da.device_index(), 4)
+        .await
+        .is_err());
+      return;
+    }
+  }
+  panic!("Did not get DeviceAdded message");
+}
+
+#[tokio::test]
+async fn test_lovense_diagnostics() {
+  let (server, mut device) = test_server_v4_with_device("LVS-Test", false);
+  let recv = server.event_stream();
+  pin_mut!(recv);
+  assert!(server
+    .parse_message(ButtplugClientMessageV4::from(
+      message::RequestServerInfoV1::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION)
+    ))
+    .await
+    .is_ok());
+  assert!(server
+    .parse_message(ButtplugClientMessageV4::from(
+      message::StartScanningV0::default()
+    ))
+    .await
+    .is_ok());
+  while let Some(command) = device.receiver.recv().await {
+    if matches!(command, HardwareCommand::Write(_)) {
+      break;
+    }
+  }
+  device
+    .sender
+    .send(TestHardwareEvent::notification(
+      Endpoint::Rx,
+      b"Z:11:0082059AD3BD;",
+    ))
+    .await
+    .expect("Test, assuming infallible.");
+  let device_index = loop {
+    match recv.next().await {
+      Some(ButtplugServerMessageV4::DeviceAdded(da)) => break da.device_index(),
+      Some(_) => continue,
+      None => panic!("Did not get DeviceAdded message"),
+    }
+  };
+  let device_manager = server.device_manager();
+  assert!(device_manager
+    .device_diagnostics(device_index + 1)
+    .await
+    .is_err());
+
+  let diagnostics = tokio::spawn(device_manager.device_diagnostics(device_index));
+  // Answer the queries like a running Hush would.
+  for (query, reply) in [
+    (b"DeviceType;".as_slice(), b"Z:11:0082059AD3BD;".as_slice()),
+    (b"Battery;".as_slice(), b"s64;".as_slice()),
+  ] {
+    loop {
+      match device.receiver.recv().await {
+        Some(HardwareCommand::Write(cmd)) if cmd.data() == query => break,
+        Some(_) => continue,
+        None => panic!("Device went away"),
+      }
+    }
+    device
+      .sender
+      .send(TestHardwareEvent::notification(Endpoint::Rx, reply))
+      .await
+      .expect("Test, assuming infallible.");
+  }
+  let diagnostics = diagnostics
+    .await
+    .expect("Test, assuming infallible.")
+    .expect("Test, assuming infallible.");
+  assert_eq!(diagnostics.firmware_version(), Some("11"));
+  assert_eq!(diagnostics.battery_level(), Some(64));
+  assert!(diagnostics.error_flags().is_empty());
+}
+
+#[tokio::test]
+async fn test_lovense_firmware_version_in_device_info() {
+  let (server, mut device) = test_server_v4_with_device("LVS-Test", false);
+  let recv = server.event_stream();
+  pin_mut!(recv);
+  assert!(server
+    .parse_message(ButtplugClientMessageV4::from(
+      message::RequestServerInfoV1::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION)
+    ))
+    .await
+    .is_ok());
+  assert!(server
+    .parse_message(ButtplugClientMessageV4::from(
+      message::StartScanningV0::default()
+    ))
+    .await
+    .is_ok());
+  while let Some(command) = device.receiver.recv().await {
+    if matches!(command, HardwareCommand::Write(_)) {
+      break;
+    }
+  }
+  device
+    .sender
+    .send(TestHardwareEvent::notification(
+      Endpoint::Rx,
+      b"Z:11:0082059AD3BD;",
+    ))
+    .await
+    .expect("Test, assuming infallible.");
+  let device_added = loop {
+    match recv.next().await {
+      Some(ButtplugServerMessageV4::DeviceAdded(da)) => break da,
+      Some(_) => continue,
+      None => panic!("Did not get DeviceAdded message"),
+    }
+  };
+  assert_eq!(
+    device_added.device_firmware_version().as_deref(),
+    Some("11")
+  );
+
+  let ButtplugServerMessageV4::DeviceList(list) = server
+    .parse_message(message::RequestDeviceListV4::default().into())
+    .await
+    .expect("Test, assuming infallible.")
+  else {
+    panic!("Expected DeviceList");
+  };
+  assert_eq!(
+    list.devices()[0].device_firmware_version().as_deref(),
+    Some("11")
+  );
+}
+
+#[tokio::test]
+async fn test_replay_state_on_reconnect() {
+  let mut builder = TestDeviceCommunicationManagerBuilder::default();
+  let identifier = TestDeviceIdentifier::new("Massage Demo", Some("ReplayAddress".to_owned()));
+  let mut device = builder.add_test_device(&identifier);
+  let mut reconnected_device = builder.add_test_device_on_rescan(&identifier);
+  let mut dm_builder = ServerDeviceManagerBuilder::new(create_test_dcm(false));
+  dm_builder
+    .comm_manager(builder)
+    .replay_state_on_reconnect(true);
+  let server = ButtplugServerBuilder::new(dm_builder.finish().unwrap())
+    .finish()
+    .unwrap();
+  let recv = server.event_stream();
+  pin_mut!(recv);
+  assert!(server
+    .parse_message(ButtplugClientMessageV4::from(
+      message::RequestServerInfoV1::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION)
+    ))
+    .await
+    .is_ok());
+  assert!(server
+    .parse_message(ButtplugClientMessageV4::from(
+      message::StartScanningV0::default()
+    ))
+    .await
+    .is_ok());
+  let mut device_index = None;
+  while let Some(msg) = recv.next().await {
+    match msg {
+      ButtplugServerMessageV4::DeviceAdded(da) if device_index.is_none() => {
+        device_index = Some(da.device_index());
+        server
+          .parse_message(ButtplugClientMessageV4::from(message::ScalarCmdV4::new(
+            da.device_index(),
+            vec![message::ScalarSubcommandV4::new(
+              0,
+              0.5,
+              message::ActuatorType::Vibrate,
+            )],
+          )))
+          .await
+          .expect("Test, assuming infallible.");
+        check_test_recv_value(
+          &mut device,
+          HardwareCommand::Write(HardwareWriteCmd::new(Endpoint::Tx, vec![0xF1, 64], false)),
+        );
+        device
+          .sender
+          .send(TestHardwareEvent::Disconnect)
+          .await
+          .expect("Test, assuming infallible.");
+      }
+      ButtplugServerMessageV4::DeviceRemoved(dr) => {
+        assert_eq!(Some(dr.device_index()), device_index);
+        assert!(server
+          .parse_message(ButtplugClientMessageV4::from(
+            message::StartScanningV0::default()
+          ))
+          .await
+          .is_ok());
+      }
+      ButtplugServerMessageV4::DeviceAdded(da) => {
+        assert_eq!(Some(da.device_index()), device_index);
+        let command =
+          tokio::time::timeout(Duration::from_secs(1), reconnected_device.receiver.recv())
+            .await
+            .expect("Replayed command not sent.");
+        assert_eq!(
+          command,
+          Some(HardwareCommand::Write(HardwareWriteCmd::new(
+            Endpoint::Tx,
+            vec![0xF1, 64],
+            false
+          )))
+        );
+        return;
+      }
+      _ => continue,
+    }
+  }
+  panic!("Did not get reconnected DeviceAdded message");
+}
+
+#[tokio::test]
+async fn test_connection_retries() {
+  let mut builder = TestDeviceCommunicationManagerBuilder::default();
+  let _device = builder
+    .add_test_device(&TestDeviceIdentifier::new("Massage Demo", None).with_failed_connections(2));
+  let mut dm_builder = ServerDeviceManagerBuilder::new(create_test_dcm(false));
+  dm_builder.comm_manager(builder).connection_attempts(3);
+  let server = ButtplugServerBuilder::new(dm_builder.finish().unwrap())
+    .finish()
+    .unwrap();
+  let recv = server.event_stream();
+  pin_mut!(recv);
+  assert!(server
+    .parse_message(ButtplugClientMessageV4::from(
+      message::RequestServerInfoV1::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION)
+    ))
+    .await
+    .is_ok());
+  assert!(server
+    .parse_message(ButtplugClientMessageV4::from(
+      message::StartScanningV0::default()
+    ))
+    .await
+    .is_ok());
+  let device_added = tokio::time::timeout(Duration::from_secs(5), async {
+    while let Some(msg) = recv.next().await {
+      if matches!(msg, ButtplugServerMessageV4::DeviceAdded(_)) {
+        return true;
+      }
+    }
+    false
+  })
+  .await;
+  assert!(matches!(device_added, Ok(true)));
+}
+
+#[tokio::test]
+async fn test_out_of_range_value_clamping() {
+  let mut builder = TestDeviceCommunicationManagerBuilder::default();
+  let mut device = builder.add_test_device(&TestDeviceIdentifier::new("Massage Demo", None));
+  let mut dm_builder = ServerDeviceManagerBuilder::new(create_test_dcm(false));
+  dm_builder
+    .comm_manager(builder)
+    .out_of_range_value_policy(OutOfRangeValuePolicy::Clamp);
+  let server = ButtplugServerBuilder::new(dm_builder.finish().unwrap())
+    .finish()
+    .unwrap();
+  let recv = server.event_stream();
+  pin_mut!(recv);
+  assert!(server
+    .parse_message(ButtplugClientMessageV4::from(
+      message::RequestServerInfoV1::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION)
+    ))
+    .await
+    .is_ok());
+  assert!(server
+    .parse_message(ButtplugClientMessageV4::from(
+      message::StartScanningV0::default()
+    ))
+    .await
+    .is_ok());
+  let device_index = loop {
+    if let Some(ButtplugServerMessageV4::DeviceAdded(da)) = recv.next().await {
+      break da.device_index();
+    }
+  };
+  let scalar = |value| {
+    ButtplugClientMessageV4::from(message::ScalarCmdV4::new(
+      device_index,
+      vec![message::ScalarSubcommandV4::new(
+        0,
+        value,
+        message::ActuatorType::Vibrate,
+      )],
+    ))
+  };
+  assert!(server.parse_message(scalar(1.5)).await.is_ok());
+  check_test_recv_value(
+    &mut device,
+    HardwareCommand::Write(HardwareWriteCmd::new(Endpoint::Tx, vec![0xF1, 127], false)),
+  );
+  assert!(server.parse_message(scalar(-0.5)).await.is_ok());
+  check_test_recv_value(
+    &mut device,
+    HardwareCommand::Write(HardwareWriteCmd::new(Endpoint::Tx, vec![0xF1, 0], false)),
+  );
+  // Clamping only covers values, everything else is still checked.
+  assert!(server.parse_message(scalar(f64::NAN)).await.is_err());
+}
+
+#[tokio::test]
+async fn test_kiiroo_pearl2_touch_sensor() {
+  let (server, mut device) = test_server_v4_with_device("Pearl2", false);
+  let recv = server.event_stream();
+  pin_mut!(recv);
+  assert!(server
+    .parse_message(ButtplugClientMessageV4::from(
+      message::RequestServerInfoV1::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION)
+    ))
+    .await
+    .is_ok());
+  assert!(server
+    .parse_message(ButtplugClientMessageV4::from(
+      message::StartScanningV0::default()
+    ))
+    .await
+    .is_ok());
+  let mut device_index = None;
+  while let Some(msg) = recv.next().await {
+    match msg {
+      ButtplugServerMessageV4::DeviceAdded(da) => {
+        device_index = Some(da.device_index());
+        server
+          .parse_message(ButtplugClientMessageV4::from(
+            message::SensorSubscribeCmdV4::new(da.device_index(), 1, message::SensorType::Pressure),
+          ))
+          .await
+          .expect("Test, assuming infallible.");
+        check_test_recv_value(
+          &mut device,
+          HardwareCommand::Subscribe(HardwareSubscribeCmd::new(Endpoint::RxTouch)),
+        );
+        device
+          .sender
+          .send(TestHardwareEvent::notification(
+            Endpoint::RxTouch,
+            &[0x00, 0x80, 0xFF],
+          ))
+          .await
+          .expect("Test, assuming infallible.");
+      }
+      ButtplugServerMessageV4::SensorReading(reading) => {
+        assert_eq!(Some(reading.device_index()), device_index);
+        assert_eq!(reading.feature_index(), 1);
+        assert_eq!(reading.sensor_type(), message::SensorType::Pressure);
+        assert_eq!(reading.data(), &vec![0x00, 0x80, 0xFF]);
+        return;
+      }
+      _ => continue,
+    }
+  }
+  panic!("Did not get SensorReading message");
+}
+
+async fn check_test_recv_write_soon(device: &mut TestDeviceChannelHost, data: &[u8]) {
+  let received = tokio::time::timeout(Duration::from_secs(1), device.receiver.recv())
+    .await
+    .expect("Device not written to.");
+  assert_eq!(
+    received,
+    Some(HardwareCommand::Write(HardwareWriteCmd::new(
+      Endpoint::Tx,
+      data.to_vec(),
+      false
+    )))
+  );
+}
+
+#[tokio::test]
+async fn test_device_link() {
+  let mut builder = TestDeviceCommunicationManagerBuilder::default();
+  let mut source = builder.add_test_device(&TestDeviceIdentifier::new("Pearl2", None));
+  let mut target = builder.add_test_device(&TestDeviceIdentifier::new("Massage Demo", None));
+  let server = test_server_with_comm_manager(builder, false);
+  let recv = server.event_stream();
+  pin_mut!(recv);
+  assert!(server
+    .parse_message(ButtplugClientMessageV4::from(
+      message::RequestServerInfoV1::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION)
+    ))
+    .await
+    .is_ok());
+  assert!(server
+    .parse_message(ButtplugClientMessageV4::from(
+      message::StartScanningV0::default()
+    ))
+    .await
+    .is_ok());
+  let mut source_index = None;
+  let mut target_index = None;
+  while let Some(msg) = recv.next().await {
+    if let ButtplugServerMessageV4::DeviceAdded(da) = msg {
+      if da.device_name() == "Kiiroo Pearl 2" {
+        source_index = Some(da.device_index());
+      } else {
+        target_index = Some(da.device_index());
+      }
+      if source_index.is_some() && target_index.is_some() {
+        break;
+      }
+    }
+  }
+  let device_manager = server.device_manager();
+  let link_id = device_manager
+    .add_device_link(DeviceLink::new(
+      source_index.unwrap(),
+      1,
+      target_index.unwrap(),
+      0,
+      DeviceLinkTransfer::Linear { min: 0.0, max: 1.0 },
+    ))
+    .await
+    .expect("Test, assuming infallible.");
+  check_test_recv_value(
+    &mut source,
+    HardwareCommand::Subscribe(HardwareSubscribeCmd::new(Endpoint::RxTouch)),
+  );
+  source
+    .sender
+    .send(TestHardwareEvent::notification(Endpoint::RxTouch, &[0xFF]))
+    .await
+    .expect("Test, assuming infallible.");
+  // Full touch level drives the target at full speed.
+  check_test_recv_write_soon(&mut target, &[0xF1, 127]).await;
+  // Removing the link stops the target.
+  assert!(device_manager.remove_device_link(link_id));
+  check_test_recv_write_soon(&mut target, &[0xF1, 0]).await;
+  assert!(device_manager.device_links().is_empty());
+  // Links must point from a subscribable sensor to a scalar actuator.
+  assert!(device_manager
+    .add_device_link(DeviceLink::new(
+      target_index.unwrap(),
+      0,
+      source_index.unwrap(),
+      0,
+      DeviceLinkTransfer::Linear { min: 0.0, max: 1.0 },
+    ))
+    .await
+    .is_err());
+}
+
+#[tokio::test]
+async fn test_lovense_remote_input() {
+  let mut builder = TestDeviceCommunicationManagerBuilder::default();
+  let mut remote = builder.add_test_device(&TestDeviceIdentifier::new("LVS-Remote", None));
+  let mut target = builder.add_test_device(&TestDeviceIdentifier::new("Massage Demo", None));
+  let server = test_server_with_comm_manager(builder, false);
+  let recv = server.event_stream();
+  pin_mut!(recv);
+  assert!(server
+    .parse_message(ButtplugClientMessageV4::from(
+      message::RequestServerInfoV1::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION)
+    ))
+    .await
+    .is_ok());
+  assert!(server
+    .parse_message(ButtplugClientMessageV4::from(
+      message::StartScanningV0::default()
+    ))
+    .await
+    .is_ok());
+  // Answer the DeviceType query so the device identifies as a remote.
+  while let Some(command) = remote.receiver.recv().await {
+    if matches!(command, HardwareCommand::Write(_)) {
+      break;
+    }
+  }
+  remote
+    .sender
+    .send(TestHardwareEvent::notification(
+      Endpoint::Rx,
+      b"RC:1:0082059AD3BD;",
+    ))
+    .await
+    .expect("Test, assuming infallible.");
+  let mut remote_index = None;
+  let mut target_index = None;
+  while let Some(msg) = recv.next().await {
+    if let ButtplugServerMessageV4::DeviceAdded(da) = msg {
+      if da.device_name() == "Lovense Remote" {
+        remote_index = Some(da.device_index());
+      } else {
+        target_index = Some(da.device_index());
+      }
+      if remote_index.is_some() && target_index.is_some() {
+        break;
+      }
+    }
+  }
+  let (remote_index, target_index) = (remote_index.unwrap(), target_index.unwrap());
+
+  // Keys show up as button sensors.
+  server
+    .parse_message(ButtplugClientMessageV4::from(
+      message::SensorSubscribeCmdV4::new(remote_index, 1, message::SensorType::Button),
+    ))
+    .await
+    .expect("Test, assuming infallible.");
+  remote
+    .sender
+    .send(TestHardwareEvent::notification(Endpoint::Rx, b"Key:2:1;"))
+    .await
+    .expect("Test, assuming infallible.");
+  loop {
+    match recv.next().await {
+      Some(ButtplugServerMessageV4::SensorReading(reading)) => {
+        assert_eq!(reading.device_index(), remote_index);
+        assert_eq!(reading.feature_index(), 1);
+        assert_eq!(reading.sensor_type(), message::SensorType::Button);
+        assert_eq!(reading.data(), &vec![1]);
+        break;
+      }
+      Some(_) => continue,
+      None => panic!("Did not get SensorReading message"),
+    }
+  }
+
+  // The dial can drive another toy through a device link.
+  server
+    .device_manager()
+    .add_device_link(DeviceLink::new(
+      remote_index,
+      2,
+      target_index,
+      0,
+      DeviceLinkTransfer::Linear { min: 0.0, max: 1.0 },
+    ))
+    .await
+    .expect("Test, assuming infallible.");
+  remote
+    .sender
+    .send(TestHardwareEvent::notification(Endpoint::Rx, b"Dial:20;"))
+    .await
+    .expect("Test, assuming infallible.");
+  check_test_recv_write_soon(&mut target, &[0xF1, 127]).await;
+}
+
+#[cfg(feature = "osc-manager")]
+#[tokio::test]
+async fn test_osc_tracker_sensor() {
+  use buttplug::server::device::hardware::communication::osc::OscCommunicationManagerBuilder;
+
+  fn osc_velocity_packet(tracker: &str, velocity: [f32; 3]) -> Vec<u8> {
+    fn padded(string: &str) -> Vec<u8> {
+      let mut bytes = string.as_bytes().to_vec();
+      bytes.resize((bytes.len() + 4) & !3, 0);
+      bytes
+    }
+    let mut packet = padded(&format!("/buttplug/tracker/{}", tracker));
+    packet.extend(padded(",fff"));
+    for component in velocity {
+      packet.extend(component.to_be_bytes());
+    }
+    packet
+  }
+
+  const OSC_PORT: u16 = 54891;
+  let server = test_server_with_comm_manager(
+    OscCommunicationManagerBuilder::default().server_port(OSC_PORT),
+    false,
+  );
+  let recv = server.event_stream();
+  pin_mut!(recv);
+  assert!(server
+    .parse_message(ButtplugClientMessageV4::from(
+      message::RequestServerInfoV1::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION)
+    ))
+    .await
+    .is_ok());
+  let socket = std::net::UdpSocket::bind("127.0.0.1:0").expect("Test, assuming infallible.");
+  // Keep sending until the comm manager is listening and the tracker shows up as a device, then
+  // until the readings make it through the subscription.
+  let mut device_index = None;
+  let reading = tokio::time::timeout(Duration::from_secs(5), async {
+    loop {
+      socket
+        .send_to(
+          &osc_velocity_packet("left_hand", [0.3, 0.4, 0.0]),
+          ("127.0.0.1", OSC_PORT),
+        )
+        .expect("Test, assuming infallible.");
+      let msg = tokio::time::timeout(Duration::from_millis(50), recv.next()).await;
+      match msg {
+        Ok(Some(ButtplugServerMessageV4::DeviceAdded(da))) => {
+          assert_eq!(da.device_name(), "OSC Motion Tracker");
+          device_index = Some(da.device_index());
+          assert!(server
+            .parse_message(ButtplugClientMessageV4::from(
+              message::SensorSubscribeCmdV4::new(
+                da.device_index(),
+                0,
+                message::SensorType::Velocity
+              )
+            ))
+            .await
+            .is_ok());
+        }
+        Ok(Some(ButtplugServerMessageV4::SensorReading(reading))) => return reading,
+        _ => continue,
+      }
+    }
+  })
+  .await
+  .expect("Tracker readings should arrive.");
+  assert_eq!(Some(reading.device_index()), device_index);
+  assert_eq!(reading.sensor_type(), message::SensorType::Velocity);
+  // 0.5m/s, reported in mm/s.
+  assert_eq!(reading.data(), &vec![500]);
+}
+
+#[tokio::test]
+async fn test_reinitialize_after_write_failure() {
+  let (server, mut device) = test_server_v4_with_device("Onyx+", false);
+  let recv = server.event_stream();
+  pin_mut!(recv);
+  assert!(server
+    .parse_message(ButtplugClientMessageV4::from(
+      message::RequestServerInfoV1::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION)
+    ))
+    .await
+    .is_ok());
+  assert!(server
+    .parse_message(ButtplugClientMessageV4::from(
+      message::StartScanningV0::default()
+    ))
+    .await
+    .is_ok());
+  let init_commands = [
+    HardwareWriteCmd::new(Endpoint::Tx, vec![0x03, 0x00, 0x64, 0x19], true),
+    HardwareWriteCmd::new(Endpoint::Tx, vec![0x03, 0x00, 0x64, 0x00], true),
+  ];
+  while let Some(msg) = recv.next().await {
+    if let ButtplugServerMessageV4::DeviceAdded(da) = msg {
+      for command in &init_commands {
+        check_test_recv_value(&mut device, HardwareCommand::Write(command.clone()));
+      }
+      device
+        .sender
+        .send(TestHardwareEvent::FailWrites(1))
+        .await
+        .expect("Test, assuming infallible.");
+      // Give the test device a moment to pick up the failure.
+      tokio::time::sleep(Duration::from_millis(50)).await;
+      assert!(server
+        .parse_message(ButtplugClientMessageV4::from(message::LinearCmdV4::new(
+          da.device_index(),
+          vec![message::VectorSubcommandV4::new(0, 500, 0.5)]
+        )))
+        .await
+        .is_err());
+      // The device is still connected, so the protocol init sequence runs again to resync it.
+      for command in &init_commands {
+        check_test_recv_value(&mut device, HardwareCommand::Write(command.clone()));
+      }
+      return;
+    }
+  }
+  panic!("Should've gotten a device added message.");
+}
+
+#[tokio::test]
+async fn test_write_retry_after_transient_failure() {
+  // Aneros writes are absolute levels, so its protocol retries them.
+  let (server, mut device) = test_server_v4_with_device("Massage Demo", false);
+  let recv = server.event_stream();
+  pin_mut!(recv);
+  assert!(server
+    .parse_message(ButtplugClientMessageV4::from(
+      message::RequestServerInfoV1::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION)
+    ))
+    .await
+    .is_ok());
+  assert!(server
+    .parse_message(ButtplugClientMessageV4::from(
+      message::StartScanningV0::default()
+    ))
+    .await
+    .is_ok());
+  let device_index = loop {
+    if let Some(ButtplugServerMessageV4::DeviceAdded(da)) = recv.next().await {
+      break da.device_index();
+    }
+  };
+  let vibrate = |level| {
+    ButtplugClientMessageV4::from(message::ScalarCmdV4::new(
+      device_index,
+      vec![message::ScalarSubcommandV4::new(
+        0,
+        level,
+        message::ActuatorType::Vibrate,
+      )],
+    ))
+  };
+  let device_manager = server.device_manager();
+  assert_eq!(
+    device_manager.device_write_retry_count(device_index),
+    Some(0)
+  );
+
+  device
+    .sender
+    .send(TestHardwareEvent::FailWrites(2))
+    .await
+    .expect("Test, assuming infallible.");
+  // Give the test device a moment to pick up the failures.
+  tokio::time::sleep(Duration::from_millis(50)).await;
+  assert!(server.parse_message(vibrate(0.5)).await.is_ok());
+  check_test_recv_value(
+    &mut device,
+    HardwareCommand::Write(HardwareWriteCmd::new(Endpoint::Tx, vec![0xF1, 64], false)),
+  );
+  assert_eq!(
+    device_manager.device_write_retry_count(device_index),
+    Some(2)
+  );
+
+  // Retries are bounded, so a write that keeps failing still fails the command.
+  device
+    .sender
+    .send(TestHardwareEvent::FailWrites(3))
+    .await
+    .expect("Test, assuming infallible.");
+  tokio::time::sleep(Duration::from_millis(50)).await;
+  assert!(server.parse_message(vibrate(1.0)).await.is_err());
+  assert_eq!(
+    device_manager.device_write_retry_count(device_index),
+    Some(4)
+  );
+}
+
+#[tokio::test]
+async fn test_device_dry_run() {
+  let (server, mut device) = test_server_v4_with_device("Massage Demo", false);
+  let recv = server.event_stream();
+  pin_mut!(recv);
+  assert!(server
+    .parse_message(ButtplugClientMessageV4::from(
+      message::RequestServerInfoV1::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION)
+    ))
+    .await
+    .is_ok());
+  assert!(server
+    .parse_message(ButtplugClientMessageV4::from(
+      message::StartScanningV0::default()
+    ))
+    .await
+    .is_ok());
+  let device_index = loop {
+    if let Some(ButtplugServerMessageV4::DeviceAdded(da)) = recv.next().await {
+      break da.device_index();
+    }
+  };
+  let vibrate = |level| {
+    ButtplugClientMessageV4::from(message::ScalarCmdV4::new(
+      device_index,
+      vec![message::ScalarSubcommandV4::new(
+        0,
+        level,
+        message::ActuatorType::Vibrate,
+      )],
+    ))
+  };
+  let device_manager = server.device_manager();
+  assert_eq!(device_manager.device_dry_run(device_index), Some(false));
+  assert!(!device_manager.set_device_dry_run(device_index + 1, true));
+  assert!(device_manager.set_device_dry_run(device_index, true));
+  assert_eq!(device_manager.device_dry_run(device_index), Some(true));
+
+  // Commands still succeed, but nothing reaches the hardware.
+  assert!(server.parse_message(vibrate(1.0)).await.is_ok());
+  tokio::time::sleep(Duration::from_millis(50)).await;
+  assert!(device.receiver.try_recv().is_err());
+
+  assert!(device_manager.set_device_dry_run(device_index, false));
+  assert!(server.parse_message(vibrate(0.5)).await.is_ok());
+  check_test_recv_value(
+    &mut device,
+    HardwareCommand::Write(HardwareWriteCmd::new(Endpoint::Tx, vec![0xF1, 64], false)),
+  );
+}
+
+#[tokio::test]
+async fn test_sensor_subscription_max_rate() {
+  let (server, mut device) = test_server_v4_with_device("Pearl2", false);
+  let recv = server.event_stream();
+  pin_mut!(recv);
+  assert!(server
+    .parse_message(ButtplugClientMessageV4::from(
+      message::RequestServerInfoV1::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION)
+    ))
+    .await
+    .is_ok());
+  assert!(server
+    .parse_message(ButtplugClientMessageV4::from(
+      message::StartScanningV0::default()
+    ))
+    .await
+    .is_ok());
+  let device_index = loop {
+    if let Some(ButtplugServerMessageV4::DeviceAdded(da)) = recv.next().await {
+      break da.device_index();
+    }
+  };
+  server
+    .parse_message(ButtplugClientMessageV4::from(
+      message::SensorSubscribeCmdV4::new(device_index, 1, message::SensorType::Pressure)
+        .with_max_rate(1, message::SensorDecimation::Latest),
+    ))
+    .await
+    .expect("Test, assuming infallible.");
+  check_test_recv_value(
+    &mut device,
+    HardwareCommand::Subscribe(HardwareSubscribeCmd::new(Endpoint::RxTouch)),
+  );
+  for value in [0x10, 0x20, 0x30] {
+    device
+      .sender
+      .send(TestHardwareEvent::notification(Endpoint::RxTouch, &[value]))
+      .await
+      .expect("Test, assuming infallible.");
+  }
+  // At one reading per second, only the first of a quick burst makes it out.
+  let mut readings = vec![];
+  while let Ok(Some(msg)) = tokio::time::timeout(Duration::from_millis(200), recv.next()).await {
+    if let ButtplugServerMessageV4::SensorReading(reading) = msg {
+      readings.push(reading.data().clone());
+    }
+  }
+  assert_eq!(readings, vec![vec![0x10]]);
+}
+
+async fn next_sensor_data(
+  recv: &mut (impl Stream<Item = ButtplugServerMessageV4> + Unpin),
+) -> Vec<i32> {
+  loop {
+    if let Some(ButtplugServerMessageV4::SensorReading(reading)) = recv.next().await {
+      return reading.data().clone();
+    }
+  }
+}
+
+#[tokio::test]
+async fn test_sensor_calibration() {
+  let (server, mut device) = test_server_v4_with_device("Pearl2", false);
+  let recv = server.event_stream();
+  pin_mut!(recv);
+  assert!(server
+    .parse_message(ButtplugClientMessageV4::from(
+      message::RequestServerInfoV1::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION)
+    ))
+    .await
+    .is_ok());
+  assert!(server
+    .parse_message(ButtplugClientMessageV4::from(
+      message::StartScanningV0::default()
+    ))
+    .await
+    .is_ok());
+  let device_index = loop {
+    if let Some(ButtplugServerMessageV4::DeviceAdded(da)) = recv.next().await {
+      break da.device_index();
+    }
+  };
+  server
+    .parse_message(ButtplugClientMessageV4::from(
+      message::SensorSubscribeCmdV4::new(device_index, 1, message::SensorType::Pressure),
+    ))
+    .await
+    .expect("Test, assuming infallible.");
+  check_test_recv_value(
+    &mut device,
+    HardwareCommand::Subscribe(HardwareSubscribeCmd::new(Endpoint::RxTouch)),
+  );
+  let device_manager = server.device_manager();
+  // Feature 0 is the vibrator, which has nothing to calibrate.
+  assert!(matches!(
+    device_manager.start_sensor_calibration(device_index, 0),
+    Err(ButtplugDeviceError::SensorCalibrationError(0, _))
+  ));
+  device_manager
+    .start_sensor_calibration(device_index, 1)
+    .expect("Test, assuming infallible.");
+
+  let send_reading = |value: u8| {
+    let sender = device.sender.clone();
+    async move {
+      sender
+        .send(TestHardwareEvent::notification(Endpoint::RxTouch, &[value]))
+        .await
+        .expect("Test, assuming infallible.");
+    }
+  };
+  // Readings during calibration come through as they are.
+  for value in [0x60, 0x40, 0xC0] {
+    send_reading(value).await;
+    assert_eq!(next_sensor_data(&mut recv).await, vec![value as i32]);
+  }
+  let calibration = device_manager
+    .finish_sensor_calibration(device_index, 1)
+    .expect("Test, assuming infallible.");
+  assert_eq!(calibration.value_range(), &vec![0x40..=0xC0]);
+  // Halfway through the calibrated range is halfway through the declared 0-255.
+  send_reading(0x80).await;
+  assert_eq!(next_sensor_data(&mut recv).await, vec![128]);
+  send_reading(0x20).await;
+  assert_eq!(next_sensor_data(&mut recv).await, vec![0]);
+
+  // The calibration is kept in the user config, so it's there the next time the device connects.
+  let user_config = save_user_config(device_manager.device_configuration_manager())
+    .expect("Test, assuming infallible.");
+  assert!(user_config.contains("sensor-calibrations"));
+  device_manager
+    .clear_sensor_calibration(device_index, 1)
+    .expect("Test, assuming infallible.");
+  let user_config = save_user_config(device_manager.device_configuration_manager())
+    .expect("Test, assuming infallible.");
+  assert!(!user_config.contains("sensor-calibrations"));
+  send_reading(0x80).await;
+  assert_eq!(next_sensor_data(&mut recv).await, vec![0x80]);
+}
+
+#[tokio::test]
+async fn test_incremental_device_list() {
+  let (server, device) = test_server_v4_with_device("Massage Demo", false);
+  let recv = server.event_stream();
+  pin_mut!(recv);
+  assert!(server
+    .parse_message(ButtplugClientMessageV4::from(
+      message::RequestServerInfoV1::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION)
+    ))
+    .await
+    .is_ok());
+  assert!(server
+    .parse_message(ButtplugClientMessageV4::from(
+      message::StartScanningV0::default()
+    ))
+    .await
+    .is_ok());
+  let mut added = None;
+  while let Some(msg) = recv.next().await {
+    if let ButtplugServerMessageV4::DeviceAdded(da) = msg {
+      added = Some(da);
+      break;
+    }
+  }
+  let added = added.expect("Test, assuming infallible.");
+  let device_index = added.device_index();
+
+  let request_list = |since_generation| {
+    let fut = server.parse_message(ButtplugClientMessageV4::from(
+      message::RequestDeviceListV4::new(since_generation),
+    ));
+    async move {
+      match fut.await.expect("Test, assuming infallible.") {
+        ButtplugServerMessageV4::DeviceList(list) => list,
+        msg => panic!("Expected DeviceList, got {:?}", msg),
+      }
+    }
+  };
+
+  let full_list = request_list(None).await;
+  assert!(!full_list.incremental());
+  assert_eq!(full_list.devices().len(), 1);
+  let generation = full_list.generation();
+  // Notifications carry the generation they brought the list to.
+  assert_eq!(added.generation(), generation);
+  let no_changes = request_list(Some(generation)).await;
+  assert!(no_changes.incremental());
+  assert!(no_changes.devices().is_empty());
+  assert!(no_changes.removed_devices().is_empty());
+  assert_eq!(no_changes.generation(), generation);
+
+  device
+    .sender
+    .send(TestHardwareEvent::Disconnect)
+    .await
+    .expect("Test, assuming infallible.");
+  let mut removed_generation = None;
+  while let Some(msg) = recv.next().await {
+    if let ButtplugServerMessageV4::DeviceRemoved(dr) = msg {
+      removed_generation = Some(dr.generation());
+      break;
+    }
+  }
+  // Each change moves the generation along by one, so clients can spot a missed notification.
+  assert_eq!(removed_generation, Some(generation + 1));
+  let removed = request_list(Some(generation)).await;
+  assert!(removed.incremental());
+  assert!(removed.devices().is_empty());
+  assert_eq!(removed.removed_devices(), &vec![device_index]);
+  assert_eq!(removed.generation(), generation + 1);
+
+  // A generation from some other server run can't be diffed, so it gets a full list.
+  let other_server = request_list(Some(generation ^ (1 << 32))).await;
+  assert!(!other_server.incremental());
+  assert!(other_server.devices().is_empty());
+}
+
+#[tokio::test]
+async fn test_pattern_session() {
+  let (server, mut device) = test_server_v4_with_device("Massage Demo", false);
+  let recv = server.event_stream();
+  pin_mut!(recv);
+  assert!(server
+    .parse_message(ButtplugClientMessageV4::from(
+      message::RequestServerInfoV1::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION)
+    ))
+    .await
+    .is_ok());
+  assert!(server
+    .parse_message(ButtplugClientMessageV4::from(
+      message::StartScanningV0::default()
+    ))
+    .await
+    .is_ok());
+  let mut device_index = None;
+  while let Some(msg) = recv.next().await {
+    if let ButtplugServerMessageV4::DeviceAdded(da) = msg {
+      device_index = Some(da.device_index());
+      break;
+    }
+  }
+  let device_index = device_index.expect("Test, assuming infallible.");
+  let device_manager = server.device_manager();
+  let session_events = device_manager.pattern_session_event_stream();
+  pin_mut!(session_events);
+  async fn next_write(device: &mut TestDeviceChannelHost) -> HardwareCommand {
+    tokio::time::timeout(Duration::from_secs(1), device.receiver.recv())
+      .await
+      .expect("Pattern session command not sent.")
+      .expect("Test, assuming infallible.")
+  }
+
+  let steps = vec![
+    PatternStep::new(0.5, Duration::from_millis(50)),
+    PatternStep::new(1.0, Duration::from_millis(50)),
+  ];
+  let session_id = device_manager
+    .start_pattern_session(PatternSession::new(
+      vec![PatternTrack::new(device_index, 0, steps.clone())],
+      Duration::from_secs(1),
+    ))
+    .expect("Test, assuming infallible.");
+  assert_eq!(
+    next_write(&mut device).await,
+    HardwareCommand::Write(HardwareWriteCmd::new(Endpoint::Tx, vec![0xF1, 64], false))
+  );
+  // The session owns the device until it's done.
+  assert!(server
+    .parse_message(ButtplugClientMessageV4::from(message::ScalarCmdV4::new(
+      device_index,
+      vec![message::ScalarSubcommandV4::new(
+        0,
+        0.1,
+        message::ActuatorType::Vibrate,
+      )],
+    )))
+    .await
+    .is_err());
+  assert_eq!(
+    next_write(&mut device).await,
+    HardwareCommand::Write(HardwareWriteCmd::new(Endpoint::Tx, vec![0xF1, 127], false))
+  );
+  assert_eq!(
+    next_write(&mut device).await,
+    HardwareCommand::Write(HardwareWriteCmd::new(Endpoint::Tx, vec![0xF1, 0], false))
+  );
+  while let Some(event) = session_events.next().await {
+    if let PatternSessionEvent::Ended {
+      session_id: id,
+      reason,
+    } = event
+    {
+      assert_eq!(id, session_id);
+      assert_eq!(reason, PatternSessionEnd::Completed);
+      break;
+    }
+  }
+
+  // Repeating sessions run until their time limit, unless the device is stopped first.
+  let mut session = PatternSession::new(
+    vec![PatternTrack::new(device_index, 0, steps)],
+    Duration::from_secs(10),
+  );
+  session.set_repeat(true);
+  let session_id = device_manager
+    .start_pattern_session(session)
+    .expect("Test, assuming infallible.");
+  assert_eq!(
+    next_write(&mut device).await,
+    HardwareCommand::Write(HardwareWriteCmd::new(Endpoint::Tx, vec![0xF1, 64], false))
+  );
+  assert!(server
+    .parse_message(ButtplugClientMessageV4::from(
+      message::StopDeviceCmdV0::new(device_index)
+    ))
+    .await
+    .is_ok());
+  while let Some(event) = session_events.next().await {
+    if let PatternSessionEvent::Ended {
+      session_id: id,
+      reason,
+    } = event
+    {
+      assert_eq!(id, session_id);
+      assert_eq!(reason, PatternSessionEnd::Cancelled);
+      break;
+    }
+  }
+}
+
+#[tokio::test]
+async fn test_multi_axis_device() {
+  let config_file_path =
+    std::path::Path::new(&std::env::var("CARGO_MANIFEST_DIR").expect("Should have manifest path"))
+      .join("tests/util/device_test/device_test_case/config/tcode_sr6_user_config.json");
+  let user_cfg = std::fs::read_to_string(config_file_path).expect("Should be able to load config");
+  let dcm = load_protocol_configs(&None, &Some(user_cfg), false)
+    .expect("Test, assuming infallible.")
+    .finish()
+    .expect("Test, assuming infallible.");
+  let mut builder = TestDeviceCommunicationManagerBuilder::default();
+  let mut device = builder.add_test_device(&TestDeviceIdentifier::new(
+    "tcode-v03",
+    Some("COM8".to_owned()),
+  ));
+  let mut dm_builder = ServerDeviceManagerBuilder::new(dcm);
+  dm_builder.comm_manager(builder);
+  let server = ButtplugServerBuilder::new(dm_builder.finish().expect("Test, assuming infallible."))
+    .finish()
+    .expect("Test, assuming infallible.");
+  let recv = server.event_stream();
+  pin_mut!(recv);
+  assert!(server
+    .parse_message(ButtplugClientMessageV4::from(
+      message::RequestServerInfoV1::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION)
+    ))
+    .await
+    .is_ok());
+  assert!(server
+    .parse_message(ButtplugClientMessageV4::from(
+      message::StartScanningV0::default()
+    ))
+    .await
+    .is_ok());
+  while let Some(msg) = recv.next().await {
+    if let ButtplugServerMessageV4::DeviceAdded(da) = msg {
+      assert_eq!(
+        da.device_features()[4]
+          .actuator()
+          .as_ref()
+          .expect("Test, assuming infallible.")
+          .axis(),
+        &Some(DeviceAxis::Roll)
+      );
+      // Both axes move in a single TCode line. Roll has a step limit of 2500-7500, so its full
+      // position ends up at 7500.
+      assert!(server
+        .parse_message(ButtplugClientMessageV4::from(message::AxisCmdV4::new(
+          da.device_index(),
+          vec![
+            message::AxisSubcommandV4::new(DeviceAxis::Stroke, 500, 0.5),
+            message::AxisSubcommandV4::new(DeviceAxis::Roll, 500, 1.0),
+          ]
+        )))
+        .await
+        .is_ok());
+      check_test_recv_value(
+        &mut device,
+        HardwareCommand::Write(HardwareWriteCmd::new(
+          Endpoint::Tx,
+          b"L05000I500 R17500I500\n".to_vec(),
+          false,
+        )),
+      );
+      return;
+    }
+  }
+  panic!("Should've gotten a device added message.");
+}
+
+#[tokio::test]
+async fn test_axis_cmd_on_single_axis_device() {
+  let (server, _device) = test_server_v4_with_device("Onyx+", false);
+  let recv = server.event_stream();
+  pin_mut!(recv);
+  assert!(server
+    .parse_message(ButtplugClientMessageV4::from(
+      message::RequestServerInfoV1::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION)
+    ))
+    .await
+    .is_ok());
+  assert!(server
+    .parse_message(ButtplugClientMessageV4::from(
+      message::StartScanningV0::default()
+    ))
+    .await
+    .is_ok());
+  while let Some(msg) = recv.next().await {
+    if let ButtplugServerMessageV4::DeviceAdded(da) = msg {
+      // The Onyx+ takes LinearCmd, but has no named axes.
+      assert!(server
+        .parse_message(ButtplugClientMessageV4::from(message::AxisCmdV4::new(
+          da.device_index(),
+          vec![message::AxisSubcommandV4::new(DeviceAxis::Stroke, 500, 0.5)],
+        )))
+        .await
+        .is_err());
+      return;
+    }
+  }
+  panic!("Should've gotten a device added message.");
+}
+
+#[tokio::test]
+async fn test_linear_cmd_splitting() {
+  let config_file_path =
+    std::path::Path::new(&std::env::var("CARGO_MANIFEST_DIR").expect("Should have manifest path"))
+      .join("tests/util/device_test/device_test_case/config/tcode_sr6_user_config.json");
+  let user_cfg = std::fs::read_to_string(config_file_path)
+    .expect("Should be able to load config")
+    .replace(
+      "\"index\": 0",
+      "\"index\": 0, \"max-linear-duration-ms\": 100",
+    );
+  let dcm = load_protocol_configs(&None, &Some(user_cfg), false)
+    .expect("Test, assuming infallible.")
+    .finish()
+    .expect("Test, assuming infallible.");
+  let mut builder = TestDeviceCommunicationManagerBuilder::default();
+  let mut device = builder.add_test_device(&TestDeviceIdentifier::new(
+    "tcode-v03",
+    Some("COM8".to_owned()),
+  ));
+  let mut dm_builder = ServerDeviceManagerBuilder::new(dcm);
+  dm_builder.comm_manager(builder);
+  let server = ButtplugServerBuilder::new(dm_builder.finish().expect("Test, assuming infallible."))
+    .finish()
+    .expect("Test, assuming infallible.");
+  let recv = server.event_stream();
+  pin_mut!(recv);
+  assert!(server
+    .parse_message(ButtplugClientMessageV4::from(
+      message::RequestServerInfoV1::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION)
+    ))
+    .await
+    .is_ok());
+  assert!(server
+    .parse_message(ButtplugClientMessageV4::from(
+      message::StartScanningV0::default()
+    ))
+    .await
+    .is_ok());
+  let device_index = loop {
+    if let Some(ButtplugServerMessageV4::DeviceAdded(da)) = recv.next().await {
+      break da.device_index();
+    }
+  };
+  let linear = |duration, position| {
+    ButtplugClientMessageV4::from(message::LinearCmdV4::new(
+      device_index,
+      vec![message::VectorSubcommandV4::new(0, duration, position)],
+    ))
+  };
+  let expect_write = |device: &mut TestDeviceChannelHost, command: &str| {
+    check_test_recv_value(
+      device,
+      HardwareCommand::Write(HardwareWriteCmd::new(
+        Endpoint::Tx,
+        command.as_bytes().to_vec(),
+        false,
+      )),
+    )
+  };
+  // Where the stroker starts out isn't known, so the first long move is only split on time.
+  assert!(server.parse_message(linear(200, 1.0)).await.is_ok());
+  expect_write(&mut device, "L099I100\n");
+  assert!(device.receiver.try_recv().is_err());
+  tokio::time::sleep(Duration::from_millis(150)).await;
+  expect_write(&mut device, "L099I100\n");
+  // Twice the limit goes out as two halves, the second once the first is done.
+  assert!(server.parse_message(linear(200, 0.0)).await.is_ok());
+  expect_write(&mut device, "L049I100\n");
+  assert!(device.receiver.try_recv().is_err());
+  tokio::time::sleep(Duration::from_millis(150)).await;
+  expect_write(&mut device, "L000I100\n");
+
+  // A new command drops what's left of the last one.
+  assert!(server.parse_message(linear(300, 0.99)).await.is_ok());
+  expect_write(&mut device, "L032I100\n");
+  assert!(server.parse_message(linear(50, 0.0)).await.is_ok());
+  expect_write(&mut device, "L000I50\n");
+  tokio::time::sleep(Duration::from_millis(250)).await;
+  assert!(device.receiver.try_recv().is_err());
+}
+
 /*
 #[cfg(target_os = "windows")]
 #[ignore = "Has weird timeout issues"]
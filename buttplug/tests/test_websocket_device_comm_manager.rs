@@ -43,3 +43,141 @@ mod test {
     assert!(client.connected());
   }
 }
+
+#[cfg(all(feature = "websocket-server-manager", feature = "device-emulation"))]
+mod emulation_test {
+  use buttplug::{
+    core::message::{
+      self,
+      ActuatorType,
+      ButtplugActuatorFeatureMessageType,
+      ButtplugClientMessageV4,
+      ButtplugDeviceMessage,
+      ButtplugSensorFeatureMessageType,
+      ButtplugServerMessageV4,
+      DeviceFeature,
+      DeviceFeatureActuator,
+      DeviceFeatureSensor,
+      FeatureType,
+      ScalarSubcommandV4,
+      SensorType,
+      BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION,
+    },
+    device_emulation::EmulatedDeviceBuilder,
+    server::{
+      device::{
+        hardware::communication::websocket_server::websocket_server_comm_manager::WebsocketServerDeviceCommunicationManagerBuilder,
+        ServerDeviceManagerBuilder,
+      },
+      ButtplugServerBuilder,
+    },
+    util::device_configuration::load_protocol_configs,
+  };
+  use futures::{pin_mut, StreamExt};
+  use std::{collections::HashSet, time::Duration};
+  use tokio::sync::mpsc;
+
+  #[tokio::test]
+  async fn test_emulated_device() {
+    let (scalar_sender, mut scalar_receiver) = mpsc::unbounded_channel();
+    let mut builder = EmulatedDeviceBuilder::new("diy-vibe", "diy-vibe-0001");
+    builder
+      .name("DIY Vibe")
+      .feature(DeviceFeature::new(
+        "Motor",
+        FeatureType::Vibrate,
+        &Some(DeviceFeatureActuator::new(
+          &(0..=100),
+          &(0..=100),
+          &HashSet::from([ButtplugActuatorFeatureMessageType::ScalarCmd]),
+        )),
+        &None,
+      ))
+      .feature(DeviceFeature::new(
+        "Squeeze",
+        FeatureType::Pressure,
+        &None,
+        &Some(DeviceFeatureSensor::new(
+          &vec![0..=1023],
+          &HashSet::from([ButtplugSensorFeatureMessageType::SensorSubscribeCmd]),
+        )),
+      ))
+      .on_scalar(move |scalar| {
+        let _ = scalar_sender.send(scalar.clone());
+      });
+
+    let dcm = load_protocol_configs(&None, &Some(builder.user_config(1)), false)
+      .expect("Test, assuming infallible.")
+      .finish()
+      .expect("Test, assuming infallible.");
+    let mut dm_builder = ServerDeviceManagerBuilder::new(dcm);
+    dm_builder
+      .comm_manager(WebsocketServerDeviceCommunicationManagerBuilder::default().server_port(51284));
+    let server = ButtplugServerBuilder::new(dm_builder.finish().unwrap())
+      .finish()
+      .unwrap();
+    let recv = server.event_stream();
+    pin_mut!(recv);
+    server
+      .parse_message(ButtplugClientMessageV4::from(
+        message::RequestServerInfoV1::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION),
+      ))
+      .await
+      .expect("Test, assuming infallible.");
+    server
+      .parse_message(ButtplugClientMessageV4::from(
+        message::StartScanningV0::default(),
+      ))
+      .await
+      .expect("Test, assuming infallible.");
+
+    // The device manager binds its port in the background, so keep trying until it's up.
+    let device = loop {
+      if let Ok(device) = builder.connect("ws://127.0.0.1:51284").await {
+        break device;
+      }
+      tokio::time::sleep(Duration::from_millis(50)).await;
+    };
+    let device_added = loop {
+      if let Some(ButtplugServerMessageV4::DeviceAdded(da)) = recv.next().await {
+        break da;
+      }
+    };
+    assert_eq!(device_added.device_index(), 1);
+    assert_eq!(device_added.device_name(), "DIY Vibe");
+
+    server
+      .parse_message(ButtplugClientMessageV4::from(message::ScalarCmdV4::new(
+        1,
+        vec![ScalarSubcommandV4::new(0, 0.5, ActuatorType::Vibrate)],
+      )))
+      .await
+      .expect("Test, assuming infallible.");
+    assert_eq!(
+      scalar_receiver.recv().await,
+      Some(ScalarSubcommandV4::new(0, 0.5, ActuatorType::Vibrate))
+    );
+
+    server
+      .parse_message(ButtplugClientMessageV4::from(
+        message::SensorSubscribeCmdV4::new(1, 1, SensorType::Pressure),
+      ))
+      .await
+      .expect("Test, assuming infallible.");
+    while !device.sensor_subscribed(1) {
+      tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    device
+      .send_sensor_reading(1, SensorType::Pressure, vec![512])
+      .await
+      .expect("Test, assuming infallible.");
+    let reading = loop {
+      if let Some(ButtplugServerMessageV4::SensorReading(reading)) = recv.next().await {
+        break reading;
+      }
+    };
+    assert_eq!(reading.device_index(), 1);
+    assert_eq!(reading.feature_index(), 1);
+    assert_eq!(reading.data(), &vec![512]);
+  }
+}
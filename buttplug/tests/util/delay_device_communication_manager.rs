@@ -19,6 +19,7 @@ use std::sync::{
   Arc,
 };
 use tokio::sync::mpsc::Sender;
+use tokio_util::sync::CancellationToken;
 
 #[derive(Default)]
 pub struct DelayDeviceCommunicationManagerBuilder {}
@@ -27,6 +28,7 @@ impl HardwareCommunicationManagerBuilder for DelayDeviceCommunicationManagerBuil
   fn finish(
     &mut self,
     sender: Sender<HardwareCommunicationManagerEvent>,
+    _: CancellationToken,
   ) -> Box<dyn HardwareCommunicationManager> {
     Box::new(DelayDeviceCommunicationManager::new(sender))
   }
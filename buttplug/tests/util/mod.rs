@@ -77,8 +77,15 @@ pub async fn test_client() -> ButtplugClient {
 
 #[allow(dead_code)]
 pub async fn test_client_with_device() -> (ButtplugClient, TestDeviceChannelHost) {
+  test_client_with_named_device("Massage Demo").await
+}
+
+#[allow(dead_code)]
+pub async fn test_client_with_named_device(
+  device_type: &str,
+) -> (ButtplugClient, TestDeviceChannelHost) {
   let mut builder = TestDeviceCommunicationManagerBuilder::default();
-  let device = builder.add_test_device(&TestDeviceIdentifier::new("Massage Demo", None));
+  let device = builder.add_test_device(&TestDeviceIdentifier::new(device_type, None));
 
   let mut dm_builder = ServerDeviceManagerBuilder::new(create_test_dcm(false));
   dm_builder.comm_manager(builder);
@@ -33,9 +33,13 @@ use serde::{Deserialize, Serialize};
 use std::{
   collections::{HashSet, VecDeque},
   fmt::{self, Debug},
-  sync::Arc,
+  sync::{
+    atomic::{AtomicU32, Ordering},
+    Arc,
+  },
 };
 use tokio::sync::{broadcast, mpsc, Mutex};
+use tokio_util::sync::CancellationToken;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct TestHardwareNotification {
@@ -43,18 +47,33 @@ pub struct TestHardwareNotification {
   data: Vec<u8>,
 }
 
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum TestHardwareEvent {
   // Values to be emitted from subscriptions
   Notifications(Vec<TestHardwareNotification>),
   // Values to be emitted when calls to ReadValue happen
   Reads(Vec<TestHardwareNotification>),
+  // Number of upcoming writes that should fail
+  FailWrites(u32),
   Disconnect,
 }
 
+impl TestHardwareEvent {
+  #[allow(dead_code)]
+  pub fn notification(endpoint: Endpoint, data: &[u8]) -> Self {
+    Self::Notifications(vec![TestHardwareNotification {
+      endpoint,
+      data: data.to_vec(),
+    }])
+  }
+}
+
 pub struct TestHardwareConnector {
   specifier: ProtocolCommunicationSpecifier,
   hardware: Option<TestDevice>,
+  // Number of upcoming connection attempts that should fail
+  failing_connections: u32,
 }
 
 impl TestHardwareConnector {
@@ -63,8 +82,14 @@ impl TestHardwareConnector {
     Self {
       specifier,
       hardware: Some(hardware),
+      failing_connections: 0,
     }
   }
+
+  #[allow(dead_code)]
+  pub fn fail_connections(&mut self, count: u32) {
+    self.failing_connections = count;
+  }
 }
 
 impl Debug for TestHardwareConnector {
@@ -82,6 +107,12 @@ impl HardwareConnector for TestHardwareConnector {
   }
 
   async fn connect(&mut self) -> Result<Box<dyn HardwareSpecializer>, ButtplugDeviceError> {
+    if self.failing_connections > 0 {
+      self.failing_connections -= 1;
+      return Err(ButtplugDeviceError::DeviceConnectionError(
+        "Test device connection failure".to_owned(),
+      ));
+    }
     Ok(Box::new(TestHardwareSpecializer::new(
       self.hardware.take().expect("Test"),
     )))
@@ -162,11 +193,17 @@ pub struct TestDevice {
   event_sender: broadcast::Sender<HardwareEvent>,
   subscribed_endpoints: Arc<DashSet<Endpoint>>,
   read_data: Arc<Mutex<VecDeque<HardwareReading>>>,
+  failing_writes: Arc<AtomicU32>,
 }
 
 impl TestDevice {
   #[allow(dead_code)]
-  pub fn new(name: &str, address: &str, test_device_channel: TestDeviceChannelDevice) -> Self {
+  pub fn new(
+    name: &str,
+    address: &str,
+    test_device_channel: TestDeviceChannelDevice,
+    cancellation_token: CancellationToken,
+  ) -> Self {
     let (event_sender, _) = broadcast::channel(256);
 
     let event_sender_clone = event_sender.clone();
@@ -176,8 +213,17 @@ impl TestDevice {
     let subscribed_endpoints_clone = subscribed_endpoints.clone();
     let read_data = Arc::new(Mutex::new(VecDeque::new()));
     let read_data_clone = read_data.clone();
+    let failing_writes = Arc::new(AtomicU32::new(0));
+    let failing_writes_clone = failing_writes.clone();
     async_manager::spawn(async move {
-      while let Some(event) = receiver.recv().await {
+      loop {
+        let event = tokio::select! {
+          event = receiver.recv() => event,
+          _ = cancellation_token.cancelled() => break,
+        };
+        let Some(event) = event else {
+          break;
+        };
         match event {
           TestHardwareEvent::Disconnect => {
             event_sender_clone
@@ -203,6 +249,9 @@ impl TestDevice {
               guard.push_front(HardwareReading::new(read.endpoint, &read.data));
             }
           }
+          TestHardwareEvent::FailWrites(count) => {
+            failing_writes_clone.store(count, Ordering::SeqCst);
+          }
         }
       }
     });
@@ -215,6 +264,7 @@ impl TestDevice {
       event_sender,
       subscribed_endpoints,
       read_data,
+      failing_writes,
     }
   }
 
@@ -301,6 +351,18 @@ impl HardwareInternal for TestDevice {
     if !self.endpoints.contains(&msg.endpoint()) {
       return future::ready(Err(ButtplugDeviceError::InvalidEndpoint(msg.endpoint()))).boxed();
     }
+    if self
+      .failing_writes
+      .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |count| {
+        count.checked_sub(1)
+      })
+      .is_ok()
+    {
+      return future::ready(Err(ButtplugDeviceError::DeviceCommunicationError(
+        "Test write failure".to_owned(),
+      )))
+      .boxed();
+    }
     self.send_command(msg.clone().into())
   }
 
@@ -34,6 +34,7 @@ use std::{
   time::{SystemTime, UNIX_EPOCH},
 };
 use tokio::sync::mpsc::Sender;
+use tokio_util::sync::CancellationToken;
 use tracing::*;
 
 pub fn generate_address() -> String {
@@ -52,6 +53,9 @@ pub struct TestDeviceIdentifier {
   name: String,
   #[serde(default = "generate_address")]
   address: String,
+  /// Number of connection attempts that fail before the device connects.
+  #[serde(default)]
+  failed_connections: u32,
 }
 
 impl TestDeviceIdentifier {
@@ -62,18 +66,27 @@ impl TestDeviceIdentifier {
     Self {
       name: name.to_owned(),
       address,
+      failed_connections: 0,
     }
   }
+
+  #[allow(dead_code)]
+  pub fn with_failed_connections(mut self, failed_connections: u32) -> Self {
+    self.failed_connections = failed_connections;
+    self
+  }
 }
 
 pub struct TestDeviceCommunicationManagerBuilder {
   devices: Option<Vec<(TestDeviceIdentifier, TestDeviceChannelDevice)>>,
+  rescan_devices: Option<Vec<(TestDeviceIdentifier, TestDeviceChannelDevice)>>,
 }
 
 impl Default for TestDeviceCommunicationManagerBuilder {
   fn default() -> Self {
     Self {
       devices: Some(vec![]),
+      rescan_devices: Some(vec![]),
     }
   }
 }
@@ -88,12 +101,29 @@ impl TestDeviceCommunicationManagerBuilder {
       .push((device.clone(), device_channel));
     host_channel
   }
+
+  /// Add a device that will only be found by the second scan, e.g. to simulate a device coming
+  /// back after a disconnect.
+  #[allow(dead_code)]
+  pub fn add_test_device_on_rescan(
+    &mut self,
+    device: &TestDeviceIdentifier,
+  ) -> TestDeviceChannelHost {
+    let (host_channel, device_channel) = new_device_channel();
+    self
+      .rescan_devices
+      .as_mut()
+      .expect("Devices vec does not exist, is this running twice?")
+      .push((device.clone(), device_channel));
+    host_channel
+  }
 }
 
 impl HardwareCommunicationManagerBuilder for TestDeviceCommunicationManagerBuilder {
   fn finish(
     &mut self,
     sender: Sender<HardwareCommunicationManagerEvent>,
+    cancellation_token: CancellationToken,
   ) -> Box<dyn HardwareCommunicationManager> {
     Box::new(TestDeviceCommunicationManager::new(
       sender,
@@ -101,6 +131,11 @@ impl HardwareCommunicationManagerBuilder for TestDeviceCommunicationManagerBuild
         .devices
         .take()
         .expect("Devices vec does not exist, is this running twice?"),
+      self
+        .rescan_devices
+        .take()
+        .expect("Devices vec does not exist, is this running twice?"),
+      cancellation_token,
     ))
   }
 }
@@ -108,30 +143,44 @@ impl HardwareCommunicationManagerBuilder for TestDeviceCommunicationManagerBuild
 fn new_uninitialized_ble_test_device(
   identifier: &TestDeviceIdentifier,
   device_channel: TestDeviceChannelDevice,
+  cancellation_token: CancellationToken,
 ) -> TestHardwareConnector {
   let address = identifier.address.clone();
   let specifier = ProtocolCommunicationSpecifier::BluetoothLE(
     BluetoothLESpecifier::new_from_device(&identifier.name, &HashMap::new(), &[]),
   );
-  let hardware = TestDevice::new(&identifier.name, &address, device_channel);
-  TestHardwareConnector::new(specifier, hardware)
+  let hardware = TestDevice::new(
+    &identifier.name,
+    &address,
+    device_channel,
+    cancellation_token,
+  );
+  let mut connector = TestHardwareConnector::new(specifier, hardware);
+  connector.fail_connections(identifier.failed_connections);
+  connector
 }
 
 pub struct TestDeviceCommunicationManager {
   device_sender: Sender<HardwareCommunicationManagerEvent>,
   devices: Vec<(TestDeviceIdentifier, TestDeviceChannelDevice)>,
+  rescan_devices: Vec<(TestDeviceIdentifier, TestDeviceChannelDevice)>,
   is_scanning: Arc<AtomicBool>,
+  cancellation_token: CancellationToken,
 }
 
 impl TestDeviceCommunicationManager {
   pub fn new(
     device_sender: Sender<HardwareCommunicationManagerEvent>,
     devices: Vec<(TestDeviceIdentifier, TestDeviceChannelDevice)>,
+    rescan_devices: Vec<(TestDeviceIdentifier, TestDeviceChannelDevice)>,
+    cancellation_token: CancellationToken,
   ) -> Self {
     Self {
       device_sender,
       devices,
+      rescan_devices,
       is_scanning: Arc::new(AtomicBool::new(false)),
+      cancellation_token,
     }
   }
 }
@@ -149,7 +198,11 @@ impl HardwareCommunicationManager for TestDeviceCommunicationManager {
     let mut events = vec![];
 
     while let Some((device, test_channel)) = self.devices.pop() {
-      let device_creator = new_uninitialized_ble_test_device(&device, test_channel);
+      let device_creator = new_uninitialized_ble_test_device(
+        &device,
+        test_channel,
+        self.cancellation_token.child_token(),
+      );
 
       events.push(HardwareCommunicationManagerEvent::DeviceFound {
         name: device.name.clone(),
@@ -157,6 +210,7 @@ impl HardwareCommunicationManager for TestDeviceCommunicationManager {
         creator: Box::new(device_creator),
       });
     }
+    self.devices = std::mem::take(&mut self.rescan_devices);
     let device_sender = self.device_sender.clone();
     let is_scanning = self.is_scanning.clone();
     async move {